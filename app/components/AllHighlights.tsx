@@ -4,6 +4,7 @@ import React, { useEffect, useState, useCallback } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import Image from "next/image";
 import { cleanBookTitle } from "../utils/book";
+import NoteHistory from "./NoteHistory";
 import type { HighlightItem } from "./HighlightsSidebar";
 import type { BookMetadata } from "./Library";
 
@@ -117,6 +118,11 @@ export default function AllHighlights({ onJumpToHighlight }: AllHighlightsProps)
     }
   };
 
+  const handleNoteRestored = (id: number, notes: string) => {
+    setHighlights((prev) => prev.map((h) => (h.id === id ? { ...h, notes } : h)));
+    setCollectionHighlights((prev) => prev.map((h) => (h.id === id ? { ...h, notes } : h)));
+  };
+
   const handleCreateCollection = async () => {
     if (!newCollectionName.trim()) return;
     try {
@@ -297,6 +303,7 @@ export default function AllHighlights({ onJumpToHighlight }: AllHighlightsProps)
                   onJump={onJumpToHighlight}
                   onDelete={handleDelete}
                   onUpdateNote={handleUpdateNote}
+                  onNoteRestored={handleNoteRestored}
                   collections={collections}
                   assigningHighlightId={assigningHighlightId}
                   onToggleAssignMenu={(id) => {
@@ -380,6 +387,7 @@ export default function AllHighlights({ onJumpToHighlight }: AllHighlightsProps)
                       onJump={onJumpToHighlight}
                       onDelete={handleDelete}
                       onUpdateNote={handleUpdateNote}
+                      onNoteRestored={handleNoteRestored}
                       collections={collections}
                       assigningHighlightId={assigningHighlightId}
                       onToggleAssignMenu={(id) => {
@@ -440,6 +448,7 @@ function HighlightCard({
   onJump,
   onDelete,
   onUpdateNote,
+  onNoteRestored,
   collections,
   assigningHighlightId,
   onToggleAssignMenu,
@@ -452,6 +461,7 @@ function HighlightCard({
   onJump: (t: string, c: string) => void;
   onDelete: (id: number) => void;
   onUpdateNote: (id: number, notes: string) => void;
+  onNoteRestored: (id: number, notes: string) => void;
   collections: Collection[];
   assigningHighlightId: number | null;
   onToggleAssignMenu: (id: number) => void;
@@ -485,6 +495,7 @@ function HighlightCard({
           <div className="highlight-item-note-global">
             <label className="global-note-label">MY ANNOTATION</label>
             <textarea
+              key={hl.notes}
               className="global-note-input"
               placeholder="Add your thoughts..."
               defaultValue={hl.notes}
@@ -507,6 +518,8 @@ function HighlightCard({
             >
               Delete
             </button>
+
+            <NoteHistory highlightId={hl.id} onRestored={(notes) => onNoteRestored(hl.id, notes)} />
             
             {/* Collection Assignment Button */}
             <div className="collection-assign-wrapper">
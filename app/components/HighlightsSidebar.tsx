@@ -8,6 +8,19 @@ export interface HighlightItem {
   color: string;
   notes: string;
   created_at: string;
+  unanchored?: boolean;
+  favorite?: boolean;
+  color_id?: number | null;
+  style?: "highlight" | "underline" | "squiggly" | "strikethrough";
+  annotation_type?: "text" | "image" | "region";
+  geometry?: {
+    page: number | null;
+    src: string | null;
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+  } | null;
 }
 
 export interface BookmarkItem {
@@ -9,11 +9,50 @@ export interface BookMetadata {
   id: number;
   title: string;
   filename: string;
-  last_cfi?: string;
+  last_position?: string;
   cover?: string;
   locations_data?: string;
   last_percentage: number;
+  format: string;
+  page_count?: number;
+  finished_at?: string;
   created_at: string;
+  indexed_at?: string;
+  summary?: string;
+  summarized_at?: string;
+  archived: boolean;
+  favorite: boolean;
+  rating: number;
+  review?: string;
+  publisher?: string;
+  year?: number;
+  isbn?: string;
+  word_count?: number;
+  reading_minutes?: number;
+  source_url?: string;
+  last_opened_at?: string;
+  description?: string;
+  language?: string;
+  highlight_count?: number;
+  bookmark_count?: number;
+}
+
+interface ImportFinished {
+  task_id: number;
+  book: BookMetadata | null;
+  error: string | null;
+  cancelled: boolean;
+}
+
+interface InboxEntry {
+  id: number;
+  feed_id: number;
+  feed_title: string;
+  title: string;
+  url?: string;
+  published_at?: string;
+  read_at?: string;
+  book: BookMetadata;
 }
 
 interface LibraryProps {
@@ -23,6 +62,7 @@ interface LibraryProps {
 export default function Library({ onSelectBook }: LibraryProps) {
   const [books, setBooks] = useState<BookMetadata[]>([]);
   const [isLoading, setIsLoading] = useState(true);
+  const [inbox, setInbox] = useState<InboxEntry[]>([]);
 
   const fetchBooks = useCallback(async () => {
     try {
@@ -40,52 +80,114 @@ export default function Library({ onSelectBook }: LibraryProps) {
     fetchBooks();
   }, [fetchBooks]);
 
+  useEffect(() => {
+    invoke<InboxEntry[]>("get_inbox")
+      .then(setInbox)
+      .catch((err) => console.error("Failed to fetch inbox:", err));
+    let unlisten: (() => void) | undefined;
+    let cancelled = false;
+    import("@tauri-apps/api/event").then(async ({ listen }) => {
+      const stop = await listen<InboxEntry>("feeds://inbox-entry-updated", (event) => {
+        const entry = event.payload;
+        setInbox((prev) => {
+          const rest = prev.filter((e) => e.id !== entry.id);
+          return entry.read_at ? rest : [entry, ...rest];
+        });
+      });
+      if (cancelled) stop();
+      else unlisten = stop;
+    });
+    return () => {
+      cancelled = true;
+      unlisten?.();
+    };
+  }, []);
+
+  const handleImportReadLater = async () => {
+    try {
+      const { open } = await import("@tauri-apps/plugin-dialog");
+      const path = await open({
+        multiple: false,
+        filters: [{ name: "Pocket or Instapaper export", extensions: ["zip", "html", "csv"] }],
+      });
+      if (!path || typeof path !== "string") return;
+      const report = await invoke<{
+        articles_added: number;
+        articles_existing: number;
+        highlights_added: number;
+        highlights_unmatched: number;
+        failures: string[];
+      }>("import_read_later", { path });
+      await fetchBooks();
+      alert(
+        `Saved ${report.articles_added} articles (${report.articles_existing} already saved) ` +
+          `and ${report.highlights_added} highlights.` +
+          (report.failures.length ? `\n${report.failures.length} articles could not be fetched.` : ""),
+      );
+    } catch (err) {
+      console.error("Failed to import reading list:", err);
+      alert(`Failed to import reading list: ${err}`);
+    }
+  };
+
+  const handleSubscribe = async () => {
+    const url = prompt("Address of the feed or site to follow:");
+    if (!url?.trim()) return;
+    try {
+      await invoke("subscribe_feed", { url });
+    } catch (err) {
+      console.error("Failed to subscribe:", err);
+      alert(`Failed to subscribe: ${err}`);
+    }
+  };
+
+  const handleMarkRead = async (id: number) => {
+    try {
+      await invoke("mark_inbox_entry_read", { id, read: true });
+    } catch (err) {
+      console.error("Failed to mark entry read:", err);
+    }
+  };
+
   const handleAddBook = async () => {
     try {
       const { open } = await import("@tauri-apps/plugin-dialog");
       const result = await open({
         multiple: false,
-        filters: [{ name: "EPUB", extensions: ["epub"] }],
+        filters: [{ name: "Books", extensions: ["epub", "pdf", "fb2", "mobi", "azw", "prc"] }],
       });
 
       if (result && typeof result === "string") {
-        const path = result;
-        const { readFile } = await import("@tauri-apps/plugin-fs");
-        const bytes = await readFile(path);
-        const name = path.split(/[\\/]/).pop() || "book.epub";
-        const title = name.replace(/\.epub$/i, "");
-
-        // Extract cover
-        let coverBase64: string | undefined = undefined;
+        // The import runs in the background; wait for its finished event.
+        const { listen } = await import("@tauri-apps/api/event");
+        let taskId: number | undefined;
+        const early: ImportFinished[] = [];
+        let resolveFinished: (finished: ImportFinished) => void = () => {};
+        const finishedPromise = new Promise<ImportFinished>((resolve) => {
+          resolveFinished = resolve;
+        });
+        const unlisten = await listen<ImportFinished>("import://finished", (event) => {
+          if (taskId === undefined) early.push(event.payload);
+          else if (event.payload.task_id === taskId) resolveFinished(event.payload);
+        });
         try {
-          const { default: ePub } = await import("epubjs");
-          const book = ePub(bytes.buffer);
-          const coverUrl = await book.coverUrl();
-          if (coverUrl) {
-            const resp = await fetch(coverUrl);
-            const blob = await resp.blob();
-            coverBase64 = await new Promise((resolve) => {
-              const reader = new FileReader();
-              reader.onloadend = () => resolve(reader.result as string);
-              reader.readAsDataURL(blob);
-            });
+          taskId = await invoke<number>("start_import", { path: result });
+          const done = early.find((f) => f.task_id === taskId);
+          if (done) resolveFinished(done);
+          const finished = await finishedPromise;
+          if (!finished.book) {
+            if (finished.cancelled) return;
+            throw new Error(finished.error ?? "Import failed");
           }
-        } catch (e) {
-          console.warn("Failed to extract cover:", e);
+          const newBook = finished.book;
+          setBooks((prev) => {
+            const filtered = prev.filter((b) => b.id !== newBook.id);
+            return [newBook, ...filtered];
+          });
+          onSelectBook(newBook);
+        } finally {
+          unlisten();
         }
-
-        const newBook = await invoke<BookMetadata>("add_book", {
-          title,
-          filename: name,
-          cover: coverBase64,
-          data: Array.from(bytes),
-        });
-
-        setBooks((prev) => {
-          const filtered = prev.filter((b) => b.id !== newBook.id);
-          return [newBook, ...filtered];
-        });
-        onSelectBook(newBook);
       }
     } catch (err) {
       console.error("Failed to add book:", err);
@@ -93,6 +195,19 @@ export default function Library({ onSelectBook }: LibraryProps) {
     }
   };
 
+  const handleSaveArticle = async () => {
+    const url = prompt("Address of the article to save:");
+    if (!url?.trim()) return;
+    try {
+      const article = await invoke<BookMetadata>("save_article", { url });
+      setBooks((prev) => [article, ...prev.filter((b) => b.id !== article.id)]);
+      onSelectBook(article);
+    } catch (err) {
+      console.error("Failed to save article:", err);
+      alert(`Failed to save article: ${err}`);
+    }
+  };
+
   const handleDeleteBook = async (e: React.MouseEvent, title: string) => {
     e.stopPropagation();
     const displayTitle = cleanBookTitle(title);
@@ -118,8 +233,36 @@ export default function Library({ onSelectBook }: LibraryProps) {
           </svg>
           Add Book
         </button>
+        <button className="library-add-btn" onClick={handleSaveArticle}>
+          Save Article
+        </button>
+        <button className="library-add-btn" onClick={handleSubscribe}>
+          Follow Feed
+        </button>
+        <button className="library-add-btn" onClick={handleImportReadLater}>
+          Import Pocket / Instapaper
+        </button>
       </div>
 
+      {inbox.length > 0 && (
+        <div className="inbox">
+          <h2>Inbox</h2>
+          <ul className="inbox-list">
+            {inbox.map((entry) => (
+              <li key={entry.id} className="inbox-entry">
+                <button className="inbox-entry-title" onClick={() => onSelectBook(entry.book)}>
+                  {entry.title}
+                </button>
+                <span className="inbox-entry-feed">{entry.feed_title}</span>
+                <button className="inbox-entry-read" onClick={() => handleMarkRead(entry.id)}>
+                  Mark read
+                </button>
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+
       {isLoading ? (
         <div className="library-loading">Loading your collection...</div>
       ) : books.length === 0 ? (
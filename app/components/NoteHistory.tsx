@@ -0,0 +1,75 @@
+"use client";
+
+import React, { useState } from "react";
+
+interface NoteRevision {
+  id: number;
+  highlight_id: number;
+  notes: string;
+  replaced_at: string;
+}
+
+interface NoteHistoryProps {
+  highlightId: number;
+  onRestored: (notes: string) => void;
+}
+
+// Earlier versions of a highlight's note, any of which can be put back
+export default function NoteHistory({ highlightId, onRestored }: NoteHistoryProps) {
+  const [revisions, setRevisions] = useState<NoteRevision[] | null>(null);
+
+  const toggle = async (e: React.MouseEvent) => {
+    e.stopPropagation();
+    if (revisions) return setRevisions(null);
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      setRevisions(await invoke<NoteRevision[]>("get_note_history", { highlightId }));
+    } catch (err) {
+      console.error("Failed to load note history:", err);
+    }
+  };
+
+  const restore = async (revision: NoteRevision) => {
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      const hl = await invoke<{ notes: string }>("restore_note_revision", { revisionId: revision.id });
+      setRevisions(null);
+      onRestored(hl.notes);
+    } catch (err) {
+      console.error("Failed to restore note:", err);
+    }
+  };
+
+  return (
+    <div className="collection-assign-wrapper">
+      <button className="collection-assign-btn" onClick={toggle} title="Note history">
+        <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2" strokeLinecap="round" strokeLinejoin="round">
+          <circle cx="12" cy="12" r="10" />
+          <polyline points="12 6 12 12 16 14" />
+        </svg>
+      </button>
+
+      {revisions && (
+        <div className="collection-assign-dropdown note-history" onClick={(e) => e.stopPropagation()}>
+          {revisions.length === 0 ? (
+            <div className="collection-assign-empty">No earlier versions.</div>
+          ) : (
+            revisions.map((revision) => (
+              <button
+                key={revision.id}
+                className="collection-assign-option"
+                onClick={() => restore(revision)}
+                title="Restore this version"
+              >
+                <span className="note-history-text">{revision.notes}</span>
+                <span className="note-history-date">
+                  {new Date(revision.replaced_at + "Z").toLocaleString()}
+                </span>
+              </button>
+            ))
+          )}
+        </div>
+      )}
+    </div>
+  );
+}
@@ -0,0 +1,57 @@
+"use client";
+
+import React, { useState } from "react";
+
+interface UnlockScreenProps {
+  // "library" asks for the encryption passphrase, "app" for the app lock PIN.
+  lock: "library" | "app";
+  onUnlocked: () => void;
+}
+
+export default function UnlockScreen({ lock, onUnlocked }: UnlockScreenProps) {
+  const [passphrase, setPassphrase] = useState("");
+  const [error, setError] = useState<string | null>(null);
+  const [isUnlocking, setIsUnlocking] = useState(false);
+
+  const unlock = async (e: React.FormEvent) => {
+    e.preventDefault();
+    setIsUnlocking(true);
+    setError(null);
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      if (lock === "library") {
+        await invoke("unlock_library", { passphrase });
+      } else {
+        await invoke("unlock_app", { pin: passphrase });
+      }
+      setPassphrase("");
+      onUnlocked();
+    } catch (err) {
+      setError(String(err));
+    } finally {
+      setIsUnlocking(false);
+    }
+  };
+
+  return (
+    <form className="unlock-screen" onSubmit={unlock}>
+      <span className="logo-icon">🔒</span>
+      <p>
+        {lock === "library"
+          ? "This library is encrypted. Enter its passphrase to open it."
+          : "Enter your PIN to unlock."}
+      </p>
+      <input
+        type="password"
+        value={passphrase}
+        onChange={(e) => setPassphrase(e.target.value)}
+        placeholder={lock === "library" ? "Passphrase" : "PIN"}
+        autoFocus
+      />
+      {error && <p className="unlock-error">{error}</p>}
+      <button type="submit" className="drop-zone-button" disabled={isUnlocking || !passphrase}>
+        {isUnlocking ? "Unlocking..." : "Unlock"}
+      </button>
+    </form>
+  );
+}
@@ -7,6 +7,7 @@ import HighlightsSidebar from "./components/HighlightsSidebar";
 import type { HighlightItem } from "./components/HighlightsSidebar";
 import Navbar from "./components/Navbar";
 import AllHighlights from "./components/AllHighlights";
+import UnlockScreen from "./components/UnlockScreen";
 import { saveProgress, getProgress, clearDatabase } from "./utils/db";
 
 export interface BookmarkItem {
@@ -17,6 +18,18 @@ export interface BookmarkItem {
   created_at: string;
 }
 
+interface OpenRequest {
+  book: BookMetadata;
+  cfi: string | null;
+}
+
+interface ProgressJump {
+  book_id: number;
+  title: string;
+  from_percentage: number;
+  to_percentage: number;
+}
+
 export default function Home() {
   const [view, setView] = useState<"library" | "reader" | "highlights">("library");
   const [currentBook, setCurrentBook] = useState<BookMetadata | null>(null);
@@ -30,9 +43,41 @@ export default function Home() {
   const [bookmarks, setBookmarks] = useState<BookmarkItem[]>([]);
   const syncTimeoutRef = useRef<NodeJS.Timeout | null>(null);
 
+  // Record a reading session for goals and stats whenever a book is closed
+  const currentTitle = currentBook?.title;
+  useEffect(() => {
+    if (view !== "reader" || !currentTitle) return;
+    const startedAt = Date.now();
+    const startPercentage = currentBookRef.current?.last_percentage ?? null;
+    return () => {
+      const seconds = Math.round((Date.now() - startedAt) / 1000);
+      if (seconds < 10) return;
+      import("@tauri-apps/api/core")
+        .then(({ invoke }) => invoke("log_reading_session", { bookTitle: currentTitle, seconds, startPercentage }))
+        .catch((err) => console.warn("Failed to log reading session:", err));
+    };
+  }, [view, currentTitle]);
+
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
 
+  // An encrypted library stays locked until its passphrase is entered, and
+  // the app until its PIN is, if one is set
+  const [locked, setLocked] = useState<"library" | "app" | false | null>(null);
+  const checkLocks = useCallback(async () => {
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      const encryption = await invoke<{ locked: boolean }>("get_encryption_status");
+      if (encryption.locked) return setLocked("library");
+      const appLock = await invoke<{ locked: boolean }>("get_app_lock");
+      setLocked(appLock.locked ? "app" : false);
+    } catch (err) {
+      console.warn("Failed to read lock status:", err);
+      setLocked(false);
+    }
+  }, []);
+  useEffect(() => { checkLocks(); }, [checkLocks]);
+
   // Jump to specific book and CFI
   const onJumpToHighlight = useCallback(async (bookTitle: string, cfi: string) => {
     setIsLoading(true);
@@ -76,7 +121,7 @@ export default function Home() {
         console.warn("Failed to reconcile progress:", e);
       }
 
-      const bookToOpen = { ...book, last_cfi: (typeof finalCfi === 'string' && finalCfi) ? finalCfi : undefined };
+      const bookToOpen = { ...book, last_position: (typeof finalCfi === 'string' && finalCfi) ? finalCfi : undefined };
       setCurrentBook(bookToOpen);
 
       // Fetch saved highlights for this book
@@ -100,8 +145,8 @@ export default function Home() {
     }
   }, []);
 
-  // Load book from library
-  const onSelectBook = useCallback(async (book: BookMetadata) => {
+  // Load book from library, at `cfi` instead of the saved position if given
+  const onSelectBook = useCallback(async (book: BookMetadata, cfi?: string) => {
     setIsLoading(true);
     setError(null);
     try {
@@ -126,9 +171,9 @@ export default function Home() {
       setBookmarks(bks);
 
       // Reconcile IndexedDB vs Database
-      let finalCfi = book.last_cfi;
+      let finalCfi = cfi ?? book.last_position;
       console.log(`[Progress] SQLite CFI for "${book.title}":`, finalCfi || "(empty)");
-      try {
+      if (!cfi) try {
         const localProgress = await getProgress(book.title);
         if (localProgress && localProgress.cfi) {
           console.log(`[Progress] IndexedDB CFI:`, localProgress.cfi.substring(0, 50));
@@ -150,7 +195,7 @@ export default function Home() {
       }
 
       console.log(`[Progress] Final CFI:`, finalCfi ? finalCfi.substring(0, 50) : "(none)");
-      setCurrentBook({ ...book, last_cfi: (typeof finalCfi === 'string' && finalCfi) ? finalCfi : undefined });
+      setCurrentBook({ ...book, last_position: (typeof finalCfi === 'string' && finalCfi) ? finalCfi : undefined });
       setView("reader");
     } catch (err) {
       console.error("Failed to load book content:", err);
@@ -160,6 +205,97 @@ export default function Home() {
     }
   }, []);
 
+  // Open books handed to the app by the OS (file associations, a second
+  // launch, tumelog:// links)
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    let cancelled = false;
+    (async () => {
+      const { invoke } = await import("@tauri-apps/api/core");
+      const { listen } = await import("@tauri-apps/api/event");
+      const stop = await listen<OpenRequest>("library://navigate", (event) => {
+        onSelectBook(event.payload.book, event.payload.cfi ?? undefined);
+      });
+      if (cancelled) {
+        stop();
+        return;
+      }
+      unlisten = stop;
+      const pending = await invoke<OpenRequest[]>("take_opened_books");
+      const last = pending[pending.length - 1];
+      if (last) onSelectBook(last.book, last.cfi ?? undefined);
+    })().catch((err) => console.warn("Failed to listen for opened books:", err));
+    return () => {
+      cancelled = true;
+      unlisten?.();
+    };
+  }, [onSelectBook]);
+
+  // Offer to undo large progress jumps (e.g. an accidental "go to end")
+  const [progressJump, setProgressJump] = useState<ProgressJump | null>(null);
+  useEffect(() => {
+    let unlisten: (() => void) | undefined;
+    let cancelled = false;
+    (async () => {
+      const { listen } = await import("@tauri-apps/api/event");
+      const stop = await listen<ProgressJump>("library://progress-jumped", (event) => {
+        if (event.payload.title === currentBookRef.current?.title) setProgressJump(event.payload);
+      });
+      if (cancelled) stop();
+      else unlisten = stop;
+    })().catch((err) => console.warn("Failed to listen for progress jumps:", err));
+    return () => {
+      cancelled = true;
+      unlisten?.();
+    };
+  }, []);
+
+  useEffect(() => {
+    if (!progressJump) return;
+    const timeout = setTimeout(() => setProgressJump(null), 10000);
+    return () => clearTimeout(timeout);
+  }, [progressJump]);
+
+  const undoProgressJump = useCallback(async () => {
+    if (!progressJump) return;
+    setProgressJump(null);
+    try {
+      const { invoke } = await import("@tauri-apps/api/core");
+      const book = await invoke<BookMetadata>("revert_progress", { bookId: progressJump.book_id });
+      await onSelectBook(book, book.last_position);
+    } catch (err) {
+      console.warn("Failed to revert progress:", err);
+    }
+  }, [progressJump, onSelectBook]);
+
+  // Ctrl+Z / Ctrl+Shift+Z (Cmd on macOS) undo and redo highlight, bookmark
+  // and collection changes in the backend, then reload the open book's
+  useEffect(() => {
+    const handler = async (e: KeyboardEvent) => {
+      const target = e.target as HTMLElement;
+      if (target.tagName === "INPUT" || target.tagName === "TEXTAREA" || target.isContentEditable) return;
+      if (!(e.ctrlKey || e.metaKey) || e.altKey) return;
+      const key = e.key.toLowerCase();
+      const command = key === "z" && !e.shiftKey ? "undo_last_operation"
+        : (key === "z" && e.shiftKey) || key === "y" ? "redo"
+        : null;
+      if (!command) return;
+      e.preventDefault();
+      try {
+        const { invoke } = await import("@tauri-apps/api/core");
+        const done = await invoke<string | null>(command);
+        const book = currentBookRef.current;
+        if (!done || !book) return;
+        setHighlights(await invoke<HighlightItem[]>("get_highlights", { bookTitle: book.title }));
+        setBookmarks(await invoke<BookmarkItem[]>("get_bookmarks", { bookTitle: book.title }));
+      } catch (err) {
+        console.warn(`Failed to ${command === "redo" ? "redo" : "undo"}:`, err);
+      }
+    };
+    window.addEventListener("keydown", handler);
+    return () => window.removeEventListener("keydown", handler);
+  }, []);
+
   // Update reading progress
   const onLocationChange = useCallback(
     async (cfi: string, percentage: number) => {
@@ -182,7 +318,7 @@ export default function Home() {
 
       // Update local state without triggering a full re-render of Reader if possible
       // (However, since Reader uses currentBook.title for key, it won't re-mount if title is same)
-      setCurrentBook(prev => prev ? { ...prev, last_cfi: cfi, last_percentage: percentage } : null);
+      setCurrentBook(prev => prev ? { ...prev, last_position: cfi, last_percentage: percentage } : null);
 
       // 2. Debounced Backend Sync
       if (syncTimeoutRef.current) clearTimeout(syncTimeoutRef.current);
@@ -192,7 +328,7 @@ export default function Home() {
           const { invoke } = await import("@tauri-apps/api/core");
           await invoke("update_book_progress", {
             title: book.title,
-            cfi,
+            position: cfi,
             percentage,
           });
         } catch (err) {
@@ -234,7 +370,8 @@ export default function Home() {
           color: highlightColor,
           notes: payload.notes || "",
         });
-        setHighlights((prev) => [hl, ...prev]);
+        // A repeated highlight comes back as the existing one.
+        setHighlights((prev) => [hl, ...prev.filter((h) => h.id !== hl.id)]);
         console.log("Highlight saved successfully");
       } catch (err) {
         console.error("Failed to save highlight:", err);
@@ -334,6 +471,14 @@ export default function Home() {
     }
   }, []);
 
+  if (locked === null) {
+    return null;
+  }
+
+  if (locked) {
+    return <UnlockScreen key={locked} lock={locked} onUnlocked={checkLocks} />;
+  }
+
   if (view !== "reader") {
     return (
       <div className="home-layout">
@@ -379,11 +524,20 @@ export default function Home() {
   return (
     <div className="app-layout">
       {isLoading && <div className="loading-overlay">Loading...</div>}
+      {progressJump && (
+        <div className="progress-undo">
+          <span>
+            Jumped from {Math.round(progressJump.from_percentage)}% to{" "}
+            {Math.round(progressJump.to_percentage)}%
+          </span>
+          <button onClick={undoProgressJump}>Undo</button>
+        </div>
+      )}
       <Reader
         key={currentBook.title}
         bookData={bookData}
         bookTitle={currentBook.title}
-        initialCfi={currentBook.last_cfi}
+        initialCfi={currentBook.last_position}
         onHighlight={onHighlight}
         onLocationChange={onLocationChange}
         highlightColor={highlightColor}
@@ -0,0 +1,69 @@
+//! A PIN or passphrase that has to be entered before the app shows any data.
+//!
+//! Unlike [`crate::encryption`], nothing on disk changes: the lock keeps
+//! someone at the keyboard out, not someone with the files. The secret is
+//! stored in the profile's settings as an Argon2id hash, and a profile with
+//! one opens locked. While locked, every command that needs the database
+//! fails with [`LOCKED`].
+
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString};
+use argon2::{Argon2, PasswordVerifier};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// Settings key holding the hashed secret.
+pub const SETTING: &str = "app_lock.pin";
+
+/// Error returned by data commands until the app is unlocked.
+pub const LOCKED: &str = "App is locked";
+
+/// Shortest PIN accepted.
+pub const MIN_PIN_LEN: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppLockStatus {
+    pub enabled: bool,
+    pub locked: bool,
+}
+
+/// The stored hash, if the profile has a lock.
+pub fn stored(conn: &Connection) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![SETTING],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Sets the lock to `pin`, or removes it if `pin` is empty.
+pub fn set(conn: &Connection, pin: &str) -> Result<(), String> {
+    if pin.is_empty() {
+        conn.execute("DELETE FROM settings WHERE key = ?1", params![SETTING])
+            .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    if pin.chars().count() < MIN_PIN_LEN {
+        return Err(format!("The PIN must be at least {MIN_PIN_LEN} characters"));
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map_err(|e| e.to_string())?
+        .to_string();
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![SETTING, hash],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Whether `pin` matches the stored `hash`.
+pub fn verify(pin: &str, hash: &str) -> Result<bool, String> {
+    let hash = PasswordHash::new(hash).map_err(|e| e.to_string())?;
+    Ok(Argon2::default()
+        .verify_password(pin.as_bytes(), &hash)
+        .is_ok())
+}
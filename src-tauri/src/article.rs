@@ -0,0 +1,751 @@
+//! Saving web articles to the library as single-chapter EPUBs, so they are
+//! read and highlighted like books.
+//!
+//! The readable part of a page is found with a simplified version of the
+//! Readability heuristics: paragraphs score their parent and grandparent by
+//! length and commas, the scores are damped by how much of each candidate is
+//! link text, and the best candidate is kept along with siblings that look
+//! like part of the same text. The result is rewritten as XHTML with only
+//! basic formatting, links and images; images are downloaded into the EPUB.
+
+use crate::convert::{image_extension, image_mime, EpubBuilder};
+use crate::db::{book_from_row, BOOK_COLUMNS};
+use crate::import::{self, NewBook};
+use crate::models::BookMetadata;
+use kuchikiki::traits::TendrilSink;
+use kuchikiki::NodeRef;
+use quick_xml::escape::escape;
+use reqwest::Url;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::time::Duration;
+
+/// Format of books saved from the web; stored as EPUB.
+pub const FORMAT: &str = "article";
+
+/// Images beyond this many are left out.
+const MAX_IMAGES: usize = 40;
+/// Images larger than this are left out.
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Pages larger than this aren't articles.
+const MAX_PAGE_BYTES: usize = 10 * 1024 * 1024;
+
+const STYLESHEET: &str = "body { margin: 0 5%; line-height: 1.5; }
+p { margin: 0 0 1em; }
+h1 { margin-bottom: 0.2em; }
+p.byline { color: #666; font-style: italic; margin-bottom: 0.2em; }
+p.source { color: #666; font-size: 0.85em; margin-bottom: 2em; }
+blockquote { margin: 1em 2em; font-style: italic; }
+pre { white-space: pre-wrap; font-size: 0.9em; }
+figure { margin: 1em 0; text-align: center; }
+figcaption { font-size: 0.85em; color: #666; }
+img { max-width: 100%; }
+";
+
+/// Elements that never hold article text.
+const REMOVED_TAGS: &[&str] = &[
+    "script", "style", "noscript", "iframe", "form", "nav", "footer", "aside", "svg", "button",
+    "input", "select", "textarea", "object", "embed", "link", "meta", "template", "canvas",
+];
+
+/// Class or id fragments of page furniture.
+const UNLIKELY: &[&str] = &[
+    "comment",
+    "sidebar",
+    "footer",
+    "footnote-ref",
+    "menu",
+    "nav",
+    "share",
+    "social",
+    "promo",
+    "related",
+    "subscribe",
+    "newsletter",
+    "cookie",
+    "banner",
+    "popup",
+    "modal",
+    "sponsor",
+    "ad-",
+    "advert",
+    "breadcrumb",
+    "masthead",
+    "pagination",
+    "skip",
+];
+
+/// Class or id fragments of article text.
+const LIKELY: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "story", "text", "blog",
+];
+
+/// Elements kept in the saved text, as themselves.
+const KEPT_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "em",
+    "strong",
+    "i",
+    "b",
+    "u",
+    "s",
+    "sub",
+    "sup",
+    "br",
+    "hr",
+    "figure",
+    "figcaption",
+    "table",
+    "thead",
+    "tbody",
+    "tr",
+    "th",
+    "td",
+    "dl",
+    "dt",
+    "dd",
+    "a",
+    "img",
+    "small",
+    "mark",
+    "cite",
+    "q",
+    "abbr",
+    "del",
+    "ins",
+];
+
+/// Elements whose start or end also ends a line of text.
+const BLOCK_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "figure",
+    "figcaption",
+    "table",
+    "tr",
+    "dl",
+    "dt",
+    "dd",
+    "div",
+    "section",
+    "article",
+    "hr",
+];
+
+/// A page reduced to its readable text.
+pub struct Article {
+    pub url: String,
+    pub title: String,
+    pub byline: Option<String>,
+    pub site_name: Option<String>,
+    pub lang: String,
+    /// XHTML for inside `<body>`; images point at `images/<name>`.
+    pub body: String,
+    pub images: Vec<ArticleImage>,
+}
+
+/// An image referenced by [`Article::body`].
+pub struct ArticleImage {
+    pub url: String,
+    pub name: String,
+    /// The `<img>` element exactly as it appears in the body, so it can be
+    /// dropped if the download fails.
+    tag: String,
+}
+
+/// A downloaded image, as `(name, mime type, bytes)`.
+pub type ImageData = (String, String, Vec<u8>);
+
+pub fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .user_agent(concat!(
+            "Mozilla/5.0 (compatible; tumelog/",
+            env!("CARGO_PKG_VERSION"),
+            ")"
+        ))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Downloads `url` and returns the page's HTML along with the URL it ended
+/// up at after redirects.
+pub async fn fetch_page(client: &reqwest::Client, url: &str) -> Result<(String, Url), String> {
+    let url = parse_url(url)?;
+    let (html, content_type, final_url) = fetch_text(client, url).await?;
+    if !content_type.contains("html") {
+        return Err(format!("{final_url} is not a web page ({content_type})"));
+    }
+    Ok((html, final_url))
+}
+
+/// `url` if it's a valid web address.
+pub fn parse_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url.trim()).map_err(|e| format!("Invalid URL {url:?}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Only web pages can be saved, not {}", url.scheme()));
+    }
+    Ok(url)
+}
+
+/// Downloads `url` as text, returning it with its lowercased content type
+/// and the URL it ended up at after redirects.
+pub async fn fetch_text(
+    client: &reqwest::Client,
+    url: Url,
+) -> Result<(String, String, Url), String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let final_url = response.url().clone();
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("text/html")
+        .to_lowercase();
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    if bytes.len() > MAX_PAGE_BYTES {
+        return Err(format!("{final_url} is too large to save"));
+    }
+    Ok((decode(&bytes, &content_type), content_type, final_url))
+}
+
+/// Page bytes as text, in the charset named by the `Content-Type` header, a
+/// `<meta>` tag or XML declaration near the start, or else UTF-8.
+fn decode(bytes: &[u8], content_type: &str) -> String {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(2048)]).to_lowercase();
+    let label = charset_after(content_type, "charset=")
+        .or_else(|| charset_after(&head, "charset="))
+        .or_else(|| charset_after(&head, "encoding="));
+    let encoding = label
+        .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+fn charset_after(text: &str, key: &str) -> Option<String> {
+    let start = text.find(key)? + key.len();
+    let label: String = text[start..]
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        .collect();
+    (!label.is_empty()).then_some(label)
+}
+
+/// Downloads the article's images. Ones that fail, are too large or aren't
+/// JPEG, PNG, GIF or WebP are dropped from the body.
+pub async fn fetch_images(client: &reqwest::Client, article: &mut Article) -> Vec<ImageData> {
+    let mut fetched = Vec::new();
+    for image in std::mem::take(&mut article.images) {
+        let result = async {
+            let response = client
+                .get(&image.url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?;
+            let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+            if bytes.len() > MAX_IMAGE_BYTES {
+                return Err("too large".to_string());
+            }
+            let mime = image_mime(&bytes).ok_or("not a supported image")?;
+            Ok::<_, String>((mime, bytes.to_vec()))
+        }
+        .await;
+        match result {
+            Ok((mime, bytes)) => fetched.push((image.name, mime.to_string(), bytes)),
+            Err(e) => {
+                log::warn!("Leaving out image {}: {e}", image.url);
+                article.body = article.body.replace(&image.tag, "");
+            }
+        }
+    }
+    fetched
+}
+
+/// The readable part of `html`, the page at `url`.
+pub fn extract(html: &str, url: &Url) -> Result<Article, String> {
+    let document = kuchikiki::parse_html().one(html).document_node;
+
+    let meta = |selector: &str| {
+        document
+            .select_first(selector)
+            .ok()
+            .and_then(|m| m.attributes.borrow().get("content").map(clean_text))
+            .filter(|s| !s.is_empty())
+    };
+    let title = meta("meta[property='og:title']")
+        .or_else(|| {
+            document
+                .select_first("title")
+                .ok()
+                .map(|t| clean_text(&t.text_contents()))
+        })
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| url.host_str().unwrap_or("Untitled article").to_string());
+    let byline = meta("meta[name='author']").or_else(|| meta("meta[property='article:author']"));
+    let site_name = meta("meta[property='og:site_name']");
+    let lang = document
+        .select_first("html")
+        .ok()
+        .and_then(|h| h.attributes.borrow().get("lang").map(str::to_string))
+        .filter(|l| !l.trim().is_empty())
+        .unwrap_or_else(|| "en".to_string());
+
+    let body = document
+        .select_first("body")
+        .map_err(|_| "The page has no content".to_string())?
+        .as_node()
+        .clone();
+    strip_furniture(&body);
+    let content = best_candidate(&body);
+
+    let mut writer = XhtmlWriter {
+        base: url,
+        out: String::new(),
+        images: Vec::new(),
+    };
+    for node in &content {
+        writer.write(node);
+    }
+    if clean_text(
+        &content
+            .iter()
+            .map(|n| n.text_contents())
+            .collect::<String>(),
+    )
+    .len()
+        < 100
+    {
+        return Err(format!("Couldn't find an article on {url}"));
+    }
+    Ok(Article {
+        url: url.to_string(),
+        title,
+        byline,
+        site_name,
+        lang,
+        body: writer.out,
+        images: writer.images,
+    })
+}
+
+fn tag(node: &NodeRef) -> Option<String> {
+    node.as_element().map(|e| e.name.local.to_string())
+}
+
+fn class_and_id(node: &NodeRef) -> String {
+    node.as_element()
+        .map(|e| {
+            let attributes = e.attributes.borrow();
+            format!(
+                "{} {}",
+                attributes.get("class").unwrap_or_default(),
+                attributes.get("id").unwrap_or_default()
+            )
+            .to_lowercase()
+        })
+        .unwrap_or_default()
+}
+
+/// Removes scripts, navigation and elements named like page furniture.
+fn strip_furniture(root: &NodeRef) {
+    let doomed: Vec<NodeRef> = root
+        .descendants()
+        .filter(|node| {
+            let Some(tag) = tag(node) else {
+                return node.as_comment().is_some();
+            };
+            if REMOVED_TAGS.contains(&tag.as_str()) {
+                return true;
+            }
+            if matches!(tag.as_str(), "article" | "main" | "body") {
+                return false;
+            }
+            let names = class_and_id(node);
+            UNLIKELY.iter().any(|u| names.contains(u)) && !LIKELY.iter().any(|l| names.contains(l))
+        })
+        .collect();
+    for node in doomed {
+        node.detach();
+    }
+}
+
+/// Share of `node`'s text that is inside links.
+fn link_density(node: &NodeRef) -> f64 {
+    let total = clean_text(&node.text_contents()).len();
+    if total == 0 {
+        return 0.0;
+    }
+    let linked: usize = node
+        .select("a")
+        .map(|links| links.map(|a| clean_text(&a.text_contents()).len()).sum())
+        .unwrap_or(0);
+    linked as f64 / total as f64
+}
+
+fn class_weight(node: &NodeRef) -> f64 {
+    let names = class_and_id(node);
+    let mut weight = 0.0;
+    if LIKELY.iter().any(|l| names.contains(l)) {
+        weight += 25.0;
+    }
+    if UNLIKELY.iter().any(|u| names.contains(u)) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+fn initial_score(node: &NodeRef) -> f64 {
+    let base = match tag(node).as_deref() {
+        Some("article") => 10.0,
+        Some("div") | Some("main") | Some("section") => 5.0,
+        Some("pre") | Some("td") | Some("blockquote") => 3.0,
+        Some("form") | Some("ol") | Some("ul") | Some("dl") | Some("li") => -3.0,
+        Some("th") | Some("h1") | Some("h2") | Some("h3") | Some("h4") | Some("h5")
+        | Some("h6") => -5.0,
+        _ => 0.0,
+    };
+    base + class_weight(node)
+}
+
+/// The nodes making up the article: the best-scoring candidate and the
+/// siblings that belong with it, or the whole body if nothing scores.
+fn best_candidate(body: &NodeRef) -> Vec<NodeRef> {
+    let mut scores: Vec<(NodeRef, f64)> = Vec::new();
+    let mut add = |node: NodeRef, score: f64| match scores.iter_mut().find(|(n, _)| *n == node) {
+        Some((_, total)) => *total += score,
+        None => {
+            let initial = initial_score(&node);
+            scores.push((node, initial + score));
+        }
+    };
+    for paragraph in body.select("p, pre, td, blockquote").into_iter().flatten() {
+        let text = clean_text(&paragraph.text_contents());
+        if text.len() < 25 {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (text.len() as f64 / 100.0).min(3.0);
+        let node = paragraph.as_node();
+        if let Some(parent) = node.parent().filter(|p| p.as_element().is_some()) {
+            if let Some(grandparent) = parent.parent().filter(|g| g.as_element().is_some()) {
+                add(grandparent, score / 2.0);
+            }
+            add(parent, score);
+        }
+    }
+    for (node, score) in &mut scores {
+        *score *= 1.0 - link_density(node);
+    }
+    let Some((top, top_score)) = scores
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(n, s)| (n.clone(), *s))
+    else {
+        return vec![body.clone()];
+    };
+    let Some(parent) = top.parent() else {
+        return vec![top];
+    };
+
+    let threshold = (top_score * 0.2).max(10.0);
+    parent
+        .children()
+        .filter(|sibling| {
+            if *sibling == top {
+                return true;
+            }
+            if scores
+                .iter()
+                .any(|(n, s)| n == sibling && *s + class_weight(sibling) >= threshold)
+            {
+                return true;
+            }
+            if tag(sibling).as_deref() != Some("p") {
+                return false;
+            }
+            let text = clean_text(&sibling.text_contents());
+            let density = link_density(sibling);
+            (text.len() > 80 && density < 0.25)
+                || (!text.is_empty() && density == 0.0 && text.ends_with('.'))
+        })
+        .collect()
+}
+
+/// Collapses runs of whitespace to single spaces and trims.
+fn clean_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Serializes kept elements as XHTML, unwrapping everything else.
+struct XhtmlWriter<'a> {
+    base: &'a Url,
+    out: String,
+    images: Vec<ArticleImage>,
+}
+
+impl XhtmlWriter<'_> {
+    fn write(&mut self, node: &NodeRef) {
+        if let Some(text) = node.as_text() {
+            self.out.push_str(&escape(text.borrow().as_str()));
+            return;
+        }
+        let Some(tag) = tag(node) else {
+            for child in node.children() {
+                self.write(&child);
+            }
+            return;
+        };
+        match tag.as_str() {
+            "img" => self.image(node),
+            "br" | "hr" => self.out.push_str(&format!("<{tag}/>")),
+            "a" => {
+                let href = self
+                    .attribute(node, "href")
+                    .and_then(|href| self.absolute(&href));
+                match href.filter(|h| h.starts_with("http")) {
+                    Some(href) => {
+                        self.out
+                            .push_str(&format!("<a href=\"{}\">", escape(href.as_str())));
+                        self.children(node);
+                        self.out.push_str("</a>");
+                    }
+                    None => self.children(node),
+                }
+            }
+            tag if KEPT_TAGS.contains(&tag) => {
+                self.out.push_str(&format!("<{tag}>"));
+                self.children(node);
+                self.out.push_str(&format!("</{tag}>"));
+            }
+            // Unknown blocks keep their line breaks as paragraphs of loose
+            // text would otherwise run together.
+            tag if BLOCK_TAGS.contains(&tag) && !has_block_children(node) => {
+                if !clean_text(&node.text_contents()).is_empty() {
+                    self.out.push_str("<p>");
+                    self.children(node);
+                    self.out.push_str("</p>");
+                }
+            }
+            _ => self.children(node),
+        }
+    }
+
+    fn children(&mut self, node: &NodeRef) {
+        for child in node.children() {
+            self.write(&child);
+        }
+    }
+
+    fn attribute(&self, node: &NodeRef, name: &str) -> Option<String> {
+        node.as_element()
+            .and_then(|e| e.attributes.borrow().get(name).map(str::to_string))
+    }
+
+    fn absolute(&self, href: &str) -> Option<String> {
+        self.base.join(href.trim()).ok().map(String::from)
+    }
+
+    /// Lazy-loading pages keep the real source in a data attribute.
+    fn image(&mut self, node: &NodeRef) {
+        if self.images.len() >= MAX_IMAGES {
+            return;
+        }
+        let Some(url) = ["data-src", "data-original", "src"]
+            .iter()
+            .filter_map(|name| self.attribute(node, name))
+            .find(|src| !src.trim().is_empty() && !src.starts_with("data:"))
+            .and_then(|src| self.absolute(&src))
+        else {
+            return;
+        };
+        if let Some(image) = self.images.iter().find(|i| i.url == url) {
+            let tag = image.tag.clone();
+            self.out.push_str(&tag);
+            return;
+        }
+        let extension = Path::new(url.split(['?', '#']).next().unwrap_or_default())
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .filter(|e| ["jpg", "jpeg", "png", "gif", "webp"].contains(&e.as_str()))
+            .map_or_else(
+                || image_extension("image/jpeg").to_string(),
+                |e| format!(".{e}"),
+            );
+        let name = format!("image{:03}{extension}", self.images.len() + 1);
+        let alt = self.attribute(node, "alt").unwrap_or_default();
+        let tag = format!(
+            "<img src=\"images/{name}\" alt=\"{}\"/>",
+            escape(clean_text(&alt).as_str())
+        );
+        self.out.push_str(&tag);
+        self.images.push(ArticleImage { url, name, tag });
+    }
+}
+
+fn has_block_children(node: &NodeRef) -> bool {
+    node.children()
+        .any(|child| tag(&child).is_some_and(|t| BLOCK_TAGS.contains(&t.as_str())))
+}
+
+/// Packages the article and its downloaded images as an EPUB.
+pub fn to_epub(article: &Article, images: Vec<ImageData>) -> Result<Vec<u8>, String> {
+    let mut builder = EpubBuilder::new(article.title.clone(), article.lang.clone());
+    builder.stylesheet = STYLESHEET;
+    builder.author = article.byline.clone();
+    let mut header = format!("<h1>{}</h1>\n", escape(article.title.as_str()));
+    if let Some(byline) = &article.byline {
+        header.push_str(&format!(
+            "<p class=\"byline\">{}</p>\n",
+            escape(byline.as_str())
+        ));
+    }
+    let source = article.site_name.as_deref().unwrap_or(&article.url);
+    header.push_str(&format!(
+        "<p class=\"source\"><a href=\"{}\">{}</a></p>\n",
+        escape(article.url.as_str()),
+        escape(source)
+    ));
+    builder.add_chapter(article.title.clone(), header + &article.body);
+    for (name, mime, bytes) in images {
+        builder.add_image(name, mime, bytes);
+    }
+    builder.finish()
+}
+
+/// The book saved from `url`, if any.
+pub fn saved(conn: &Connection, url: &str) -> rusqlite::Result<Option<BookMetadata>> {
+    conn.query_row(
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.source_url = ?1"),
+        params![url],
+        book_from_row,
+    )
+    .optional()
+}
+
+/// A file name for `title` that no book uses yet.
+pub fn unused_filename(
+    conn: &Connection,
+    books_dir: &Path,
+    title: &str,
+) -> rusqlite::Result<String> {
+    let stem: String = title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(80)
+        .collect();
+    let stem = stem.trim();
+    let stem = if stem.is_empty() { "article" } else { stem };
+    for n in 1.. {
+        let filename = match n {
+            1 => format!("{stem}.epub"),
+            n => format!("{stem} ({n}).epub"),
+        };
+        let taken: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM books WHERE filename = ?1)",
+            params![filename],
+            |row| row.get(0),
+        )?;
+        if !taken && !books_dir.join(&filename).exists() {
+            return Ok(filename);
+        }
+    }
+    unreachable!()
+}
+
+/// A title no book has yet: the article's own, else with the site name, else
+/// numbered. Titles identify books, so two articles can't share one.
+fn unused_title(conn: &Connection, article: &Article) -> rusqlite::Result<String> {
+    let taken = |title: &str| -> rusqlite::Result<bool> {
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM books WHERE title = ?1 COLLATE fold)",
+            params![title],
+            |row| row.get(0),
+        )
+    };
+    if !taken(&article.title)? {
+        return Ok(article.title.clone());
+    }
+    let site = article
+        .site_name
+        .clone()
+        .or_else(|| {
+            Url::parse(&article.url)
+                .ok()?
+                .host_str()
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "web".to_string());
+    let with_site = format!("{} ({site})", article.title);
+    for n in 1.. {
+        let title = match n {
+            1 => with_site.clone(),
+            n => format!("{with_site} {n}"),
+        };
+        if !taken(&title)? {
+            return Ok(title);
+        }
+    }
+    unreachable!()
+}
+
+/// Adds the article to the library as a book of format [`FORMAT`]
+/// remembering where it came from.
+pub fn save(
+    conn: &Connection,
+    books_dir: &Path,
+    mut article: Article,
+    images: Vec<ImageData>,
+) -> Result<BookMetadata, String> {
+    article.title = unused_title(conn, &article).map_err(|e| e.to_string())?;
+    let book = NewBook {
+        filename: unused_filename(conn, books_dir, &article.title).map_err(|e| e.to_string())?,
+        title: article.title.clone(),
+        cover: None,
+        data: to_epub(&article, images)?,
+    };
+    let book = import::import_book(conn, books_dir, book, &mut |_, _| Ok(()), &mut |_, _| {})?;
+    conn.execute(
+        "UPDATE books SET format = ?1, source_url = ?2 WHERE id = ?3",
+        params![FORMAT, article.url, book.id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(BookMetadata {
+        format: FORMAT.to_string(),
+        source_url: Some(article.url),
+        ..book
+    })
+}
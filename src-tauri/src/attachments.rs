@@ -0,0 +1,329 @@
+//! Files attached to highlights: voice notes (e.g. a recorded pronunciation),
+//! screenshots, or any other file. They're stored as files under the library
+//! folder, named after their row and encrypted like book files in an encrypted
+//! library (see [`crate::storage`]), and served to the webview over the
+//! `tumelog-media` URI scheme so `<audio>` and `<img>` elements can load them.
+//!
+//! Deleting a highlight leaves its attachments in place while the deletion
+//! can still be undone, so undo brings the highlight back with them; they're
+//! pruned once the deletion drops off the undo stack (see [`crate::undo`]).
+
+use crate::models::{Attachment, AttachmentKind};
+use crate::storage;
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
+
+/// URI scheme the files are served under.
+pub const SCHEME: &str = "tumelog-media";
+
+/// Folder of new attachments within the library folder.
+const DIR: &str = "attachments";
+
+/// Folders attachments may be in; voice notes attached before attachments
+/// were generalized stay in `audio`.
+const DIRS: &[&str] = &[DIR, "audio"];
+
+/// Largest file that can be attached.
+const MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Known file types by extension, with their MIME types. Other files are
+/// attached as `application/octet-stream`.
+const TYPES: &[(&str, &str)] = &[
+    ("webm", "audio/webm"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("opus", "audio/ogg"),
+    ("m4a", "audio/mp4"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("pdf", "application/pdf"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+];
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Where a file to attach comes from.
+pub enum AttachmentSource {
+    /// Made in the app, e.g. recorded or pasted; `mime` as reported by the
+    /// browser.
+    Bytes {
+        data: Vec<u8>,
+        mime: String,
+        name: Option<String>,
+    },
+    /// An existing file, which is copied.
+    File(PathBuf),
+}
+
+/// URL the webview loads the attachment at `path` from. Windows and Android
+/// webviews only allow custom schemes as `http://<scheme>.localhost`.
+pub fn url(path: &str) -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{SCHEME}.localhost/{path}")
+    } else {
+        format!("{SCHEME}://localhost/{path}")
+    }
+}
+
+fn mime_for_extension(extension: &str) -> &'static str {
+    let extension = extension.to_lowercase();
+    TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map_or(OCTET_STREAM, |(_, mime)| *mime)
+}
+
+fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    // Recorders add codecs, as in `audio/webm;codecs=opus`.
+    let mime = mime.split(';').next().unwrap_or_default().trim();
+    TYPES
+        .iter()
+        .find(|(_, m)| m.eq_ignore_ascii_case(mime))
+        .map(|(ext, _)| *ext)
+}
+
+fn extension_of(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .filter(|e| !e.is_empty() && e.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+fn attachment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Attachment> {
+    let path: String = row.get(3)?;
+    Ok(Attachment {
+        id: row.get(0)?,
+        highlight_id: row.get(1)?,
+        kind: AttachmentKind::parse(&row.get::<_, String>(2)?),
+        url: url(&path),
+        path,
+        mime: row.get(4)?,
+        name: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+const ATTACHMENT_COLUMNS: &str = "id, highlight_id, kind, path, mime, name, created_at";
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<Attachment> {
+    conn.query_row(
+        &format!("SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE id = ?1"),
+        params![id],
+        attachment_from_row,
+    )
+}
+
+/// A highlight's attachments, oldest first.
+pub fn list(conn: &Connection, highlight_id: i64) -> rusqlite::Result<Vec<Attachment>> {
+    conn.prepare(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments
+         WHERE highlight_id = ?1 ORDER BY created_at, id"
+    ))?
+    .query_map(params![highlight_id], attachment_from_row)?
+    .collect()
+}
+
+/// Stores the file and attaches it to the highlight.
+pub fn attach(
+    conn: &Connection,
+    library_dir: &Path,
+    highlight_id: i64,
+    source: AttachmentSource,
+) -> Result<Attachment, String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM highlights WHERE id = ?1)",
+            params![highlight_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !exists {
+        return Err("Highlight not found".to_string());
+    }
+    let (extension, mime, name) = match &source {
+        AttachmentSource::Bytes { data, mime, name } => {
+            if data.len() as u64 > MAX_SIZE {
+                return Err("The file is too large to attach".to_string());
+            }
+            let extension = extension_for_mime(mime)
+                .map(str::to_string)
+                .or_else(|| name.as_deref().and_then(extension_of));
+            let mime = match &extension {
+                Some(extension) => mime_for_extension(extension),
+                None => OCTET_STREAM,
+            };
+            (extension, mime, name.clone())
+        }
+        AttachmentSource::File(path) => {
+            let size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+            if size > MAX_SIZE {
+                return Err("The file is too large to attach".to_string());
+            }
+            let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+            let extension = name.as_deref().and_then(extension_of);
+            let mime = extension
+                .as_deref()
+                .map_or(OCTET_STREAM, mime_for_extension);
+            (extension, mime, name)
+        }
+    };
+    let kind = AttachmentKind::for_mime(mime);
+
+    conn.execute(
+        "INSERT INTO attachments (highlight_id, kind, path, mime, name)
+         VALUES (?1, ?2, '', ?3, ?4)",
+        params![highlight_id, kind.as_str(), mime, name],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    let path = match extension {
+        Some(extension) => format!("{DIR}/{id}.{extension}"),
+        None => format!("{DIR}/{id}"),
+    };
+    // The row names the file before it's written, so [`prune`] running
+    // meanwhile doesn't take it for a stray.
+    conn.execute(
+        "UPDATE attachments SET path = ?1 WHERE id = ?2",
+        params![path, id],
+    )
+    .map_err(|e| e.to_string())?;
+    let target = library_dir.join(&path);
+    let written = std::fs::create_dir_all(library_dir.join(DIR)).and_then(|()| {
+        let data = match source {
+            AttachmentSource::Bytes { data, .. } => data,
+            AttachmentSource::File(source) => std::fs::read(source)?,
+        };
+        storage::write(&target, &data, false)
+    });
+    if let Err(e) = written {
+        conn.execute("DELETE FROM attachments WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        remove_file(library_dir, &path);
+        return Err(e.to_string());
+    }
+    get(conn, id).map_err(|e| e.to_string())
+}
+
+fn remove_file(library_dir: &Path, path: &str) {
+    let path = library_dir.join(path);
+    if let Err(e) = std::fs::remove_file(&path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Could not delete {}: {e}", path.display());
+        }
+    }
+}
+
+pub fn delete(conn: &Connection, library_dir: &Path, id: i64) -> Result<(), String> {
+    let attachment = get(conn, id).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM attachments WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    remove_file(library_dir, &attachment.path);
+    Ok(())
+}
+
+/// Deletes the attachments of highlights that are gone for good, and files
+/// no attachment refers to, e.g. left by a crash while attaching. A highlight
+/// that an operation on the undo stack could bring back keeps its
+/// attachments. Returns how many attachments were deleted.
+pub fn prune(conn: &Connection, library_dir: &Path) -> Result<usize, String> {
+    let orphans: Vec<(i64, String)> = conn
+        .prepare(
+            "SELECT a.id, a.path FROM attachments a
+             WHERE a.highlight_id NOT IN (SELECT id FROM highlights)
+               AND NOT EXISTS (
+                   SELECT 1 FROM undo_stack u
+                   JOIN oplog o ON o.id BETWEEN u.first_change AND u.last_change
+                   WHERE o.table_name = 'highlights' AND o.row_id = a.highlight_id
+               )",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    for (id, path) in &orphans {
+        conn.execute("DELETE FROM attachments WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        remove_file(library_dir, path);
+    }
+
+    let mut referenced = conn
+        .prepare("SELECT EXISTS(SELECT 1 FROM attachments WHERE path = ?1)")
+        .map_err(|e| e.to_string())?;
+    for dir in DIRS {
+        let Ok(entries) = std::fs::read_dir(library_dir.join(dir)) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = format!("{dir}/{}", entry.file_name().to_string_lossy());
+            let used: bool = referenced
+                .query_row(params![path], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            if !used {
+                remove_file(library_dir, &path);
+            }
+        }
+    }
+    Ok(orphans.len())
+}
+
+/// Every attachment file in the library folder, for converting them when
+/// encryption is turned on or off.
+pub fn stored_files(library_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for dir in DIRS {
+        let entries = match std::fs::read_dir(library_dir.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.to_string()),
+        };
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Response to a request for `path` over [`SCHEME`]: the file's bytes, or
+/// 404 for anything that isn't an attachment, or while the app is locked
+/// (`library_dir` is `None`).
+pub fn respond(library_dir: Option<&Path>, path: &str) -> tauri::http::Response<Vec<u8>> {
+    let path = path.trim_start_matches('/');
+    let attachment = path.split_once('/').filter(|(dir, file)| {
+        DIRS.contains(dir)
+            && !file.is_empty()
+            && !file.contains(['/', '\\'])
+            && !file.starts_with('.')
+    });
+    let data = match (library_dir, attachment) {
+        (Some(library_dir), Some(_)) => storage::read(&library_dir.join(path)).ok(),
+        _ => None,
+    };
+    let builder = tauri::http::Response::builder();
+    match data {
+        Some(data) => builder
+            .header(
+                tauri::http::header::CONTENT_TYPE,
+                extension_of(path).map_or(OCTET_STREAM, |e| mime_for_extension(&e)),
+            )
+            .body(data),
+        None => builder
+            .status(tauri::http::StatusCode::NOT_FOUND)
+            .body(Vec::new()),
+    }
+    .expect("static response parts")
+}
@@ -0,0 +1,120 @@
+//! Authors and series as library entities, so books can be grouped by who
+//! wrote them and multi-volume works listed in order.
+//!
+//! `books.author` and `books.series` stay as the display values shown on each
+//! book; the `authors`/`series` tables and their join tables are what grouping
+//! queries use. Names are matched case-insensitively, so "Ursula K. Le Guin"
+//! from two different files ends up as one author.
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Author {
+    pub id: i64,
+    pub name: String,
+    pub book_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Series {
+    pub id: i64,
+    pub name: String,
+    pub book_count: i64,
+}
+
+/// Replaces the authors of `book_id` with `names`, in order.
+pub fn set_authors(conn: &Connection, book_id: i64, names: &[String]) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM book_authors WHERE book_id = ?1",
+        params![book_id],
+    )?;
+    for (position, name) in names.iter().enumerate() {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO authors (name) VALUES (?1)",
+            params![name],
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO book_authors (book_id, author_id, position)
+             SELECT ?1, id, ?2 FROM authors WHERE name = ?3 COLLATE fold",
+            params![book_id, position as i64, name],
+        )?;
+    }
+    remove_orphans(conn)
+}
+
+/// Puts `book_id` in the series called `name` at `index`, or takes it out of
+/// any series when `name` is `None`.
+pub fn set_series(
+    conn: &Connection,
+    book_id: i64,
+    name: Option<&str>,
+    index: Option<f64>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM book_series WHERE book_id = ?1",
+        params![book_id],
+    )?;
+    if let Some(name) = name.map(str::trim).filter(|n| !n.is_empty()) {
+        conn.execute(
+            "INSERT OR IGNORE INTO series (name) VALUES (?1)",
+            params![name],
+        )?;
+        conn.execute(
+            "INSERT INTO book_series (book_id, series_id, series_index)
+             SELECT ?1, id, ?2 FROM series WHERE name = ?3 COLLATE fold",
+            params![book_id, index, name],
+        )?;
+    }
+    remove_orphans(conn)
+}
+
+/// Drops authors and series no book refers to any more.
+pub fn remove_orphans(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "DELETE FROM book_authors WHERE book_id NOT IN (SELECT id FROM books);
+         DELETE FROM book_series WHERE book_id NOT IN (SELECT id FROM books);
+         DELETE FROM authors WHERE id NOT IN (SELECT author_id FROM book_authors);
+         DELETE FROM series WHERE id NOT IN (SELECT series_id FROM book_series);",
+    )
+}
+
+/// Every author with at least one book, by name.
+pub fn list_authors(conn: &Connection) -> rusqlite::Result<Vec<Author>> {
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.name, COUNT(ba.book_id) FROM authors a
+         INNER JOIN book_authors ba ON ba.author_id = a.id
+         GROUP BY a.id
+         ORDER BY a.name COLLATE fold",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Author {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            book_count: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Every series with at least one book, by name.
+pub fn list_series(conn: &Connection) -> rusqlite::Result<Vec<Series>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.name, COUNT(bs.book_id) FROM series s
+         INNER JOIN book_series bs ON bs.series_id = s.id
+         GROUP BY s.id
+         ORDER BY s.name COLLATE fold",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Series {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            book_count: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
@@ -0,0 +1,281 @@
+//! Exports that run by themselves: an Obsidian vault export, a JSON backup of
+//! the highlights, and a Readwise sync, each off, at app start, or daily.
+//!
+//! Each task's schedule and folder are stored in the settings table as
+//! `automation.<task>` (JSON), and the outcome of its last run as
+//! `automation.<task>.status`.
+
+use crate::models::ExportScope;
+use crate::{export, obsidian, readwise, secrets};
+use crate::{run_blocking, DbState};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationTask {
+    /// Incremental export into the Obsidian vault at the task's path.
+    Obsidian,
+    /// `highlights-<date>.json` written to the task's path.
+    JsonBackup,
+    ReadwiseSync,
+}
+
+impl AutomationTask {
+    pub const ALL: [AutomationTask; 3] = [
+        AutomationTask::Obsidian,
+        AutomationTask::JsonBackup,
+        AutomationTask::ReadwiseSync,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            AutomationTask::Obsidian => "obsidian",
+            AutomationTask::JsonBackup => "json_backup",
+            AutomationTask::ReadwiseSync => "readwise_sync",
+        }
+    }
+
+    fn needs_path(self) -> bool {
+        self != AutomationTask::ReadwiseSync
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Schedule {
+    #[default]
+    Off,
+    /// Every time the app starts.
+    Startup,
+    /// At start and then every 24 hours while the app runs.
+    Daily,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct TaskSettings {
+    schedule: Schedule,
+    path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct LastRun {
+    at: String,
+    /// What the run did, or why it failed.
+    message: String,
+    ok: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutomationStatus {
+    pub task: AutomationTask,
+    pub schedule: Schedule,
+    pub path: Option<String>,
+    pub last_run_at: Option<String>,
+    pub last_run_ok: Option<bool>,
+    pub last_run_message: Option<String>,
+}
+
+fn read_json<T: for<'de> Deserialize<'de> + Default>(
+    conn: &Connection,
+    key: &str,
+) -> Result<Option<T>, String> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    // A value this version can't read counts as unset.
+    Ok(value.map(|v| serde_json::from_str(&v).unwrap_or_default()))
+}
+
+fn write_json<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<(), String> {
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn settings(conn: &Connection, task: AutomationTask) -> Result<TaskSettings, String> {
+    Ok(read_json(conn, &format!("automation.{}", task.key()))?.unwrap_or_default())
+}
+
+fn last_run(conn: &Connection, task: AutomationTask) -> Result<Option<LastRun>, String> {
+    read_json(conn, &format!("automation.{}.status", task.key()))
+}
+
+pub fn status(conn: &Connection, task: AutomationTask) -> Result<AutomationStatus, String> {
+    let settings = settings(conn, task)?;
+    let last_run = last_run(conn, task)?;
+    Ok(AutomationStatus {
+        task,
+        schedule: settings.schedule,
+        path: settings.path,
+        last_run_at: last_run.as_ref().map(|r| r.at.clone()),
+        last_run_ok: last_run.as_ref().map(|r| r.ok),
+        last_run_message: last_run.map(|r| r.message),
+    })
+}
+
+pub fn statuses(conn: &Connection) -> Result<Vec<AutomationStatus>, String> {
+    AutomationTask::ALL
+        .into_iter()
+        .map(|task| status(conn, task))
+        .collect()
+}
+
+/// Sets when `task` runs. Exports need an existing folder in `path`.
+pub fn configure(
+    conn: &Connection,
+    task: AutomationTask,
+    schedule: Schedule,
+    path: Option<String>,
+) -> Result<AutomationStatus, String> {
+    let path = path.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    if task.needs_path() && schedule != Schedule::Off {
+        match &path {
+            Some(p) if Path::new(p).is_dir() => {}
+            Some(p) => return Err(format!("{p} is not a folder")),
+            None => return Err("Choose a folder to export to".to_string()),
+        }
+    }
+    write_json(
+        conn,
+        &format!("automation.{}", task.key()),
+        &TaskSettings { schedule, path },
+    )?;
+    status(conn, task)
+}
+
+/// Tasks to run now: at startup every scheduled task, later only daily ones
+/// whose last run was a day ago or more.
+fn due(conn: &Connection, startup: bool) -> Result<Vec<AutomationTask>, String> {
+    let mut due = Vec::new();
+    for task in AutomationTask::ALL {
+        let scheduled = match settings(conn, task)?.schedule {
+            Schedule::Off => false,
+            Schedule::Startup => startup,
+            Schedule::Daily if startup => true,
+            Schedule::Daily => {
+                let last = last_run(conn, task)?.map(|r| r.at);
+                conn.query_row(
+                    "SELECT ?1 IS NULL OR datetime(?1, '+1 day') <= datetime('now')",
+                    params![last],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?
+            }
+        };
+        if scheduled {
+            due.push(task);
+        }
+    }
+    Ok(due)
+}
+
+fn export_json(conn: &Connection, dir: &Path) -> Result<String, String> {
+    let document = export::highlights_document(conn, &ExportScope::All)?;
+    let date = document.exported_at.get(..10).unwrap_or_default();
+    let name = format!("highlights-{date}.json");
+    let json = serde_json::to_string_pretty(&document).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(&name), json).map_err(|e| e.to_string())?;
+    Ok(format!(
+        "Saved {} highlights to {name}",
+        document.items.len()
+    ))
+}
+
+async fn execute(db: &DbState, task: AutomationTask) -> Result<String, String> {
+    let state = db.clone();
+    let path = run_blocking(move || {
+        let conn = state.conn()?;
+        Ok(settings(&conn, task)?.path)
+    })
+    .await?;
+    let folder = match (task.needs_path(), path) {
+        (true, Some(path)) => path,
+        (true, None) => return Err("No folder configured".to_string()),
+        (false, _) => String::new(),
+    };
+    let state = db.clone();
+    match task {
+        AutomationTask::Obsidian => {
+            run_blocking(move || {
+                let conn = state.conn()?;
+                let report = obsidian::export(&conn, Path::new(&folder), true)?;
+                Ok(format!(
+                    "Wrote {} notes, {} unchanged",
+                    report.written, report.unchanged
+                ))
+            })
+            .await
+        }
+        AutomationTask::JsonBackup => {
+            run_blocking(move || {
+                let conn = state.conn()?;
+                export_json(&conn, Path::new(&folder))
+            })
+            .await
+        }
+        AutomationTask::ReadwiseSync => {
+            let token = run_blocking(|| {
+                secrets::get("readwise", "token")?
+                    .filter(|t| !t.trim().is_empty())
+                    .ok_or_else(|| "No Readwise token configured".to_string())
+            })
+            .await?;
+            let report = readwise::sync(state, token).await?;
+            Ok(format!("Pushed {} highlights", report.pushed))
+        }
+    }
+}
+
+/// Runs `task` now and records the outcome as its last run.
+pub async fn run(db: &DbState, task: AutomationTask) -> Result<AutomationStatus, String> {
+    let outcome = execute(db, task).await;
+    if let Err(e) = &outcome {
+        log::warn!("Automatic {} failed: {e}", task.key());
+    }
+    let state = db.clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let at: String = conn
+            .query_row("SELECT datetime('now')", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let (ok, message) = match outcome {
+            Ok(message) => (true, message),
+            Err(e) => (false, e),
+        };
+        write_json(
+            &conn,
+            &format!("automation.{}.status", task.key()),
+            &LastRun { at, message, ok },
+        )?;
+        status(&conn, task)
+    })
+    .await
+}
+
+/// Runs the tasks that are due, for the scheduler; see [`due`]. Returns their
+/// statuses after the run.
+pub async fn run_due(db: &DbState, startup: bool) -> Result<Vec<AutomationStatus>, String> {
+    let state = db.clone();
+    let due = run_blocking(move || {
+        let conn = state.conn()?;
+        due(&conn, startup)
+    })
+    .await?;
+    let mut statuses = Vec::new();
+    for task in due {
+        statuses.push(run(db, task).await?);
+    }
+    Ok(statuses)
+}
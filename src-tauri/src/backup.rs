@@ -0,0 +1,195 @@
+//! Rotating snapshots of the highlights database in `<profile>/backups`.
+//!
+//! Snapshots are written with `VACUUM INTO`, which produces a consistent,
+//! compacted copy without blocking readers. Only the database is backed up;
+//! book files can be re-imported, annotations can't. Snapshots of an
+//! encrypted database are encrypted with the same key.
+
+use crate::db;
+use crate::encryption::{self, LibraryKey};
+use crate::migrations;
+use rusqlite::backup::Backup;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const PREFIX: &str = "highlights-";
+const SUFFIX: &str = ".db";
+
+/// How many snapshots to keep; older ones are deleted after each backup.
+pub const KEEP: usize = 7;
+
+/// Minimum age of the newest snapshot before the scheduler takes another.
+pub const INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    pub name: String,
+    pub size: u64,
+    pub created_at: String,
+}
+
+pub fn backups_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join("backups")
+}
+
+/// Snapshots the database into `dir`. Callers prune afterwards with [`prune`].
+pub fn create(conn: &Connection, dir: &Path) -> Result<BackupInfo, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let stamp: String = conn
+        .query_row("SELECT strftime('%Y%m%d-%H%M%S', 'now')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| e.to_string())?;
+    let name = format!("{PREFIX}{stamp}{SUFFIX}");
+    let path = dir.join(&name);
+
+    // Two backups within the same second are the same backup.
+    if !path.exists() {
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map_err(|e| e.to_string())?;
+        log::info!("Backed up database to {}", path.display());
+    }
+
+    info(&path).ok_or_else(|| format!("Backup {name} was not written"))
+}
+
+/// Takes a snapshot if the newest one is older than [`INTERVAL`].
+pub fn run_scheduled(conn: &Connection, dir: &Path) -> Result<Option<BackupInfo>, String> {
+    let newest = list(dir)?
+        .first()
+        .and_then(|b| std::fs::metadata(dir.join(&b.name)).ok())
+        .and_then(|m| m.modified().ok());
+    let due = match newest {
+        Some(modified) => SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= INTERVAL),
+        None => true,
+    };
+    if !due {
+        return Ok(None);
+    }
+    let backup = create(conn, dir)?;
+    prune(dir, KEEP)?;
+    Ok(Some(backup))
+}
+
+/// Snapshots in `dir`, newest first.
+pub fn list(dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<BackupInfo> = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .filter_map(|entry| info(&entry.path()))
+        .collect();
+    // The timestamp in the name sorts lexicographically.
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Deletes all but the `keep` newest snapshots.
+pub fn prune(dir: &Path, keep: usize) -> Result<(), String> {
+    for backup in list(dir)?.into_iter().skip(keep) {
+        std::fs::remove_file(dir.join(&backup.name)).map_err(|e| e.to_string())?;
+        log::info!("Removed old backup {}", backup.name);
+    }
+    Ok(())
+}
+
+/// Rewrites every snapshot in `dir` from the key `from` to `to` (`None`
+/// meaning unencrypted), when encryption is turned on or off. Returns the
+/// names of snapshots that couldn't be converted; they are left as they were.
+pub fn rekey(dir: &Path, from: Option<&LibraryKey>, to: Option<&LibraryKey>) -> Vec<String> {
+    let mut failed = Vec::new();
+    for backup in list(dir).unwrap_or_default() {
+        let path = dir.join(&backup.name);
+        let temp = dir.join(format!("{}.tmp", backup.name));
+        let result = (|| {
+            let source = Connection::open(&path).map_err(|e| e.to_string())?;
+            if let Some(key) = from {
+                encryption::apply(&source, key).map_err(|e| e.to_string())?;
+            }
+            db::register_functions(&source).map_err(|e| e.to_string())?;
+            encryption::export(&source, &temp, to)?;
+            drop(source);
+            std::fs::rename(&temp, &path).map_err(|e| e.to_string())
+        })();
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp);
+            log::warn!("Could not convert backup {}: {e}", backup.name);
+            failed.push(backup.name);
+        }
+    }
+    failed
+}
+
+fn info(path: &Path) -> Option<BackupInfo> {
+    let name = path.file_name()?.to_str()?.to_string();
+    let stamp = name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?;
+    // "20261017-025537" -> "2026-10-17 02:55:37", matching datetime('now').
+    if stamp.len() != 15 || !stamp.bytes().all(|b| b.is_ascii_digit() || b == b'-') {
+        return None;
+    }
+    let created_at = format!(
+        "{}-{}-{} {}:{}:{}",
+        &stamp[0..4],
+        &stamp[4..6],
+        &stamp[6..8],
+        &stamp[9..11],
+        &stamp[11..13],
+        &stamp[13..15]
+    );
+    let size = std::fs::metadata(path).ok()?.len();
+    Some(BackupInfo {
+        name,
+        size,
+        created_at,
+    })
+}
+
+/// Resolves a backup name to its path, rejecting anything that isn't a
+/// snapshot in `dir`.
+pub fn resolve(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let path = dir.join(name);
+    if name.contains(['/', '\\']) || info(&path).is_none() {
+        return Err(format!("No backup named {name}"));
+    }
+    Ok(path)
+}
+
+/// Replaces the contents of `conn`'s database with the snapshot at `path`.
+/// The snapshot is integrity-checked first so a damaged backup can't replace
+/// a working database, and snapshots from a newer app version are refused.
+/// Older snapshots are migrated forward after restoring. Snapshots of an
+/// encrypted library need its `key`.
+pub fn restore(conn: &mut Connection, path: &Path, key: Option<&LibraryKey>) -> Result<(), String> {
+    let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    if let Some(key) = key {
+        encryption::apply(&source, key).map_err(|e| e.to_string())?;
+    }
+    db::register_functions(&source).map_err(|e| e.to_string())?;
+    let check: String = source
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if check != "ok" {
+        return Err(format!("Backup failed its integrity check: {check}"));
+    }
+    let version = migrations::schema_version(&source).map_err(|e| e.to_string())?;
+    if version > migrations::latest_version() {
+        return Err(format!(
+            "Backup schema version {version} is newer than this app supports ({})",
+            migrations::latest_version()
+        ));
+    }
+
+    Backup::new(&source, conn)
+        .and_then(|backup| backup.run_to_completion(100, Duration::from_millis(10), None))
+        .map_err(|e| e.to_string())?;
+    drop(source);
+    log::info!("Restored database from {}", path.display());
+    migrations::run(conn)
+}
@@ -0,0 +1,234 @@
+//! Export to blog posts for static site generators: Markdown with YAML
+//! frontmatter (`title`, `date`, `tags`), one post per book or per
+//! collection, named the way Hugo or Jekyll expect.
+//!
+//! A post is rewritten on every export. Jekyll names carry the post's date,
+//! so one whose date moved replaces the file with the old date.
+
+use crate::export::{self, ExportItem};
+use crate::markdown::{book_tags, yaml};
+use crate::models::ExportScope;
+use crate::site::slug;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlogGenerator {
+    /// `<slug>.md`, for a section such as `content/posts`.
+    #[default]
+    Hugo,
+    /// `<date>-<slug>.md` with `layout: post`, for `_posts`.
+    Jekyll,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BlogPostPer {
+    #[default]
+    Book,
+    Collection,
+}
+
+struct Post {
+    title: String,
+    /// SQLite timestamp.
+    date: String,
+    tags: Vec<String>,
+    book_author: Option<String>,
+    body: String,
+}
+
+fn quote(text: &str, notes: &str) -> String {
+    let mut block: String = text
+        .trim()
+        .lines()
+        .map(|line| format!("{}\n", format!("> {line}").trim_end()))
+        .collect();
+    if !notes.trim().is_empty() {
+        block.push_str(&format!("\n{}\n", notes.trim()));
+    }
+    block
+}
+
+fn frontmatter(generator: BlogGenerator, post: &Post) -> String {
+    let mut lines = vec!["---".to_string()];
+    if generator == BlogGenerator::Jekyll {
+        lines.push("layout: post".to_string());
+    }
+    lines.push(format!("title: {}", yaml(&post.title)));
+    let date = match generator {
+        BlogGenerator::Hugo => crate::readwise::iso8601(&post.date),
+        BlogGenerator::Jekyll => format!("{} +0000", post.date),
+    };
+    lines.push(format!("date: {}", yaml(&date)));
+    if !post.tags.is_empty() {
+        lines.push("tags:".to_string());
+        lines.extend(post.tags.iter().map(|tag| format!("  - {}", yaml(tag))));
+    }
+    if let Some(author) = &post.book_author {
+        lines.push(format!("book_author: {}", yaml(author)));
+    }
+    lines.push("---".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// A post per book with text highlights, in reading order, dated when the
+/// book was finished or else its first highlight, and tagged with the
+/// book's collections.
+fn book_posts(conn: &Connection) -> rusqlite::Result<Vec<Post>> {
+    let books: Vec<(String, Option<i64>, Option<String>, String)> = conn
+        .prepare(
+            "SELECT h.book_title, b.id, b.author, COALESCE(b.finished_at, MIN(h.created_at))
+             FROM highlights h
+             LEFT JOIN books b ON b.title = h.book_title
+             WHERE h.annotation_type = 'text'
+             GROUP BY h.book_title
+             ORDER BY h.book_title COLLATE fold",
+        )?
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut highlights = conn.prepare(
+        "SELECT text, notes FROM highlights
+         WHERE book_title = ?1 AND annotation_type = 'text'
+         ORDER BY cfi COLLATE cfi_order",
+    )?;
+
+    let mut posts = Vec::new();
+    for (title, book_id, author, date) in books {
+        let body = highlights
+            .query_map(params![title], |row| {
+                Ok(quote(&row.get::<_, String>(0)?, &row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?
+            .join("\n");
+        let tags = match book_id {
+            Some(id) => book_tags(conn, id)?,
+            None => Vec::new(),
+        };
+        posts.push(Post {
+            title,
+            date,
+            tags,
+            book_author: author,
+            body,
+        });
+    }
+    Ok(posts)
+}
+
+fn collection_body(items: &[ExportItem]) -> String {
+    let mut body = String::new();
+    for book in export::by_book(items) {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!("## {}\n", book[0].book_title));
+        if let Some(author) = &book[0].author {
+            body.push_str(&format!("\n*{author}*\n"));
+        }
+        for item in book {
+            body.push('\n');
+            body.push_str(&quote(&item.text, &item.notes));
+        }
+    }
+    body
+}
+
+/// A post per collection with highlights, grouped by book, dated by its
+/// first highlight and tagged with the collection's name.
+fn collection_posts(conn: &Connection) -> rusqlite::Result<Vec<Post>> {
+    let collections: Vec<(i64, String)> = conn
+        .prepare(
+            "SELECT id, name FROM collections
+             WHERE id IN (SELECT collection_id FROM highlight_collections)
+             ORDER BY name COLLATE fold",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut posts = Vec::new();
+    for (collection_id, name) in collections {
+        let scope = ExportScope::Collection { collection_id };
+        let items = export::items(conn, &scope)?;
+        let Some(date) = items.iter().map(|item| &item.created_at).min().cloned() else {
+            continue;
+        };
+        posts.push(Post {
+            title: export::scope_title(conn, &scope)?,
+            date,
+            tags: vec![name],
+            book_author: None,
+            body: collection_body(&items),
+        });
+    }
+    Ok(posts)
+}
+
+/// Whether `name` is a Jekyll post `<YYYY-MM-DD>-<slug>.md`.
+fn is_jekyll_post(name: &str, slug: &str) -> bool {
+    let stem = name.strip_suffix(".md").unwrap_or_default();
+    match (stem.get(..10), stem.get(10..)) {
+        (Some(date), Some(rest)) => {
+            rest.strip_prefix('-') == Some(slug)
+                && date.bytes().enumerate().all(|(i, b)| match i {
+                    4 | 7 => b == b'-',
+                    _ => b.is_ascii_digit(),
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Writes a post per book or collection into `dir`. Returns how many were
+/// written.
+pub fn export(
+    conn: &Connection,
+    dir: &Path,
+    generator: BlogGenerator,
+    per: BlogPostPer,
+) -> Result<usize, String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a folder", dir.display()));
+    }
+    let posts = match per {
+        BlogPostPer::Book => book_posts(conn),
+        BlogPostPer::Collection => collection_posts(conn),
+    }
+    .map_err(|e| e.to_string())?;
+    let existing: Vec<String> = std::fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+
+    let mut slugs = HashSet::new();
+    for post in &posts {
+        let base = slug(&post.title);
+        let mut slug = base.clone();
+        let mut n = 2;
+        while !slugs.insert(slug.clone()) {
+            slug = format!("{base}-{n}");
+            n += 1;
+        }
+        let name = match generator {
+            BlogGenerator::Hugo => format!("{slug}.md"),
+            BlogGenerator::Jekyll => {
+                let date = export::date(&post.date);
+                for old in existing.iter().filter(|name| is_jekyll_post(name, &slug)) {
+                    std::fs::remove_file(dir.join(old)).map_err(|e| e.to_string())?;
+                }
+                format!("{date}-{slug}.md")
+            }
+        };
+        std::fs::write(
+            dir.join(name),
+            format!("{}\n{}", frontmatter(generator, post), post.body),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(posts.len())
+}
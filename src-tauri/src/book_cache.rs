@@ -0,0 +1,478 @@
+//! EPUBs unpacked once into a cache folder and served to the reader over the
+//! `tumelog-book` URI scheme, so opening a large book doesn't mean unzipping
+//! all of it in the webview every time.
+//!
+//! Each book is unpacked into `cache/books/<book id>` under the library
+//! folder. A `.stamp` file written last records the size and modification
+//! time of the book file it came from: a folder without one is incomplete,
+//! and one whose stamp no longer matches (the file was replaced or
+//! compressed) is unpacked again. The stamp's own modification time is when
+//! the book was last opened, and the least recently opened books are removed
+//! once the cache grows past its limit.
+//!
+//! The reader can also have the chapters it's about to show read ahead into
+//! memory with [`prefetch`], so turning into them doesn't wait on the disk.
+//!
+//! The scheme also serves book files whole under `file/<book id>`, for PDFs
+//! and anything else too large to load into the webview at once. Both kinds
+//! of file are served with range requests (see [`crate::streaming`]).
+//!
+//! Books in an encrypted library are never unpacked, as that would leave
+//! their contents on disk in the clear; the reader loads those from
+//! `get_book_content` as before.
+
+use crate::db::{self, DbState};
+use crate::epub::percent_decode;
+use crate::storage;
+use crate::streaming::{self, Contents};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use zip::ZipArchive;
+
+/// URI scheme the unpacked books are served under.
+pub const SCHEME: &str = "tumelog-book";
+
+/// Settings key: the cache's size limit in megabytes.
+pub const LIMIT_SETTING: &str = "book_cache.limit_mb";
+
+const DEFAULT_LIMIT_MB: u64 = 512;
+
+const STAMP: &str = ".stamp";
+
+/// Most bytes of prefetched files kept in memory.
+const PREFETCH_BYTES: usize = 32 * 1024 * 1024;
+
+/// Prefetched files by path, oldest first.
+static PREFETCHED: Mutex<Vec<(PathBuf, Vec<u8>)>> = Mutex::new(Vec::new());
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Media types of the files found in EPUBs, by extension.
+const TYPES: &[(&str, &str)] = &[
+    ("xhtml", "application/xhtml+xml"),
+    ("xht", "application/xhtml+xml"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("xml", "application/xml"),
+    ("opf", "application/oebps-package+xml"),
+    ("ncx", "application/x-dtbncx+xml"),
+    ("smil", "application/smil+xml"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("mp4", "video/mp4"),
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedBook {
+    pub book_id: i64,
+    /// URL of the unpacked book's root folder (holding `META-INF`), ending
+    /// in `/`.
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookCacheInfo {
+    pub books: usize,
+    pub bytes: u64,
+    pub limit_mb: u64,
+}
+
+/// An unpacked book in the cache.
+struct Entry {
+    book_id: i64,
+    path: PathBuf,
+    bytes: u64,
+    last_opened: SystemTime,
+}
+
+fn root(library_dir: &Path) -> PathBuf {
+    library_dir.join("cache").join("books")
+}
+
+/// Windows and Android webviews only allow custom schemes as
+/// `http://<scheme>.localhost`.
+fn base_url() -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{SCHEME}.localhost")
+    } else {
+        format!("{SCHEME}://localhost")
+    }
+}
+
+/// URL the webview loads `book_id`'s unpacked files from.
+pub fn url(book_id: i64) -> String {
+    format!("{}/{book_id}/", base_url())
+}
+
+/// URL of `book_id`'s file.
+pub fn file_url(book_id: i64) -> String {
+    format!("{}/file/{book_id}", base_url())
+}
+
+pub fn limit_mb(conn: &Connection) -> Result<u64, String> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![LIMIT_SETTING],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_LIMIT_MB))
+}
+
+/// What identifies the version of the book file at `source`.
+fn stamp_of(source: &Path) -> Result<String, String> {
+    let meta = std::fs::metadata(source).map_err(|e| e.to_string())?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    Ok(format!("{} {modified}", meta.len()))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// The complete books in the cache, least recently opened first.
+fn entries(root: &Path) -> Vec<Entry> {
+    let Ok(dirs) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<Entry> = dirs
+        .filter_map(|dir| {
+            let path = dir.ok()?.path();
+            let book_id = path.file_name()?.to_str()?.parse().ok()?;
+            let last_opened = std::fs::metadata(path.join(STAMP)).ok()?.modified().ok()?;
+            Some(Entry {
+                book_id,
+                bytes: dir_size(&path),
+                path,
+                last_opened,
+            })
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.last_opened);
+    entries
+}
+
+/// Extracts the archive at `source` into `dir`, leaving out entries whose
+/// names would land outside it.
+fn unpack(source: &Path, dir: &Path) -> Result<(), String> {
+    let reader = storage::open(source).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|e| e.to_string())?;
+        let Some(name) = file.enclosed_name() else {
+            log::warn!("Skipping {} outside the book", file.name());
+            continue;
+        };
+        let target = dir.join(name);
+        if file.is_dir() {
+            std::fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut out = File::create(&target).map_err(|e| e.to_string())?;
+        std::io::copy(&mut file, &mut out).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Removes the least recently opened books until the cache fits in
+/// `limit_mb`, sparing `keep`.
+fn evict(library_dir: &Path, limit_mb: u64, keep: Option<i64>) -> Result<(), String> {
+    let entries = entries(&root(library_dir));
+    let limit = limit_mb * 1024 * 1024;
+    let mut total: u64 = entries.iter().map(|entry| entry.bytes).sum();
+    for entry in entries {
+        if total <= limit {
+            break;
+        }
+        if Some(entry.book_id) == keep {
+            continue;
+        }
+        std::fs::remove_dir_all(&entry.path).map_err(|e| e.to_string())?;
+        forget(&entry.path);
+        total -= entry.bytes;
+    }
+    Ok(())
+}
+
+/// Unpacks `book_id`'s EPUB at `source` unless the cache already holds this
+/// version of it, and marks it as just opened.
+pub fn open(
+    conn: &Connection,
+    library_dir: &Path,
+    book_id: i64,
+    source: &Path,
+) -> Result<CachedBook, String> {
+    if storage::layout(source)
+        .map_err(|e| e.to_string())?
+        .encrypted
+    {
+        return Err("Books in an encrypted library aren't unpacked to disk".to_string());
+    }
+    let root = root(library_dir);
+    let dir = root.join(book_id.to_string());
+    let stamp = stamp_of(source)?;
+    let stamp_path = dir.join(STAMP);
+
+    if std::fs::read_to_string(&stamp_path).ok().as_deref() == Some(stamp.as_str()) {
+        File::options()
+            .write(true)
+            .open(&stamp_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .map_err(|e| e.to_string())?;
+    } else {
+        // Unpacked beside the cache and moved in once complete, so the
+        // reader never sees half a book.
+        let partial = root.join(format!("{book_id}.partial"));
+        forget(&dir);
+        for old in [&partial, &dir] {
+            if old.exists() {
+                std::fs::remove_dir_all(old).map_err(|e| e.to_string())?;
+            }
+        }
+        std::fs::create_dir_all(&partial).map_err(|e| e.to_string())?;
+        if let Err(e) = unpack(source, &partial) {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Err(e);
+        }
+        std::fs::write(partial.join(STAMP), &stamp).map_err(|e| e.to_string())?;
+        std::fs::rename(&partial, &dir).map_err(|e| e.to_string())?;
+    }
+
+    evict(library_dir, limit_mb(conn)?, Some(book_id))?;
+    Ok(CachedBook {
+        book_id,
+        url: url(book_id),
+    })
+}
+
+pub fn info(conn: &Connection, library_dir: &Path) -> Result<BookCacheInfo, String> {
+    let entries = entries(&root(library_dir));
+    Ok(BookCacheInfo {
+        books: entries.len(),
+        bytes: entries.iter().map(|entry| entry.bytes).sum(),
+        limit_mb: limit_mb(conn)?,
+    })
+}
+
+/// Sets the size limit, evicting books right away if the cache is over it.
+pub fn set_limit(
+    conn: &Connection,
+    library_dir: &Path,
+    limit_mb: u64,
+) -> Result<BookCacheInfo, String> {
+    if limit_mb == 0 {
+        return Err("The book cache needs at least 1 MB".to_string());
+    }
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![LIMIT_SETTING, limit_mb.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    evict(library_dir, limit_mb, None)?;
+    info(conn, library_dir)
+}
+
+/// Removes every unpacked book.
+pub fn clear(library_dir: &Path) -> Result<(), String> {
+    let root = root(library_dir);
+    forget(&root);
+    if root.exists() {
+        std::fs::remove_dir_all(&root).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Removes `book_id`'s unpacked copy, if there is one.
+pub fn remove(library_dir: &Path, book_id: i64) -> Result<(), String> {
+    let dir = root(library_dir).join(book_id.to_string());
+    forget(&dir);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Removes the unpacked copies of books that were deleted.
+pub fn prune(conn: &Connection, library_dir: &Path) -> Result<usize, String> {
+    let mut exists = conn
+        .prepare("SELECT 1 FROM books WHERE id = ?1")
+        .map_err(|e| e.to_string())?;
+    let mut removed = 0;
+    for entry in entries(&root(library_dir)) {
+        if !exists
+            .exists(params![entry.book_id])
+            .map_err(|e| e.to_string())?
+        {
+            std::fs::remove_dir_all(&entry.path).map_err(|e| e.to_string())?;
+            forget(&entry.path);
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn prefetched() -> std::sync::MutexGuard<'static, Vec<(PathBuf, Vec<u8>)>> {
+    PREFETCHED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Drops the prefetched files under `dir`.
+fn forget(dir: &Path) {
+    prefetched().retain(|(path, _)| !path.starts_with(dir));
+}
+
+/// Path of `href`, a path inside `book_id`'s EPUB, in the cache. `None` for
+/// paths leading outside the book, or if it isn't completely unpacked.
+fn cached_path(library_dir: &Path, book_id: i64, href: &str) -> Option<PathBuf> {
+    let safe = href
+        .split('/')
+        .all(|part| !matches!(part, "" | "." | "..") && !part.contains(['\\', ':']));
+    let dir = root(library_dir).join(book_id.to_string());
+    (safe && dir.join(STAMP).is_file()).then(|| dir.join(href))
+}
+
+/// The file at `path`, from memory if it was prefetched.
+fn read(path: &Path) -> Option<Contents> {
+    let memory = prefetched()
+        .iter()
+        .find(|(prefetched, _)| prefetched == path)
+        .map(|(_, data)| Contents::Memory(data.clone()));
+    memory.or_else(|| streaming::map(path).ok())
+}
+
+/// `book_id`'s file and its media type. Compressed and encrypted files are
+/// decoded into memory; plain ones are mapped.
+fn book_file(state: &DbState, book_id: i64) -> Result<(Contents, &'static str), String> {
+    let conn = state.conn()?;
+    let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+    if book.archived {
+        return Err(format!("{} is archived", book.title));
+    }
+    let path = db::books_dir(&conn, &state.dir()?)?.join(&book.filename);
+    let layout = storage::layout(&path).map_err(|e| e.to_string())?;
+    let contents = if layout.encrypted || layout.compressed {
+        Contents::Memory(storage::read(&path).map_err(|e| e.to_string())?)
+    } else {
+        streaming::map(&path).map_err(|e| e.to_string())?
+    };
+    let mime = match book.format.as_str() {
+        "pdf" => "application/pdf",
+        format if crate::import::is_epub(format) => "application/epub+zip",
+        _ => OCTET_STREAM,
+    };
+    Ok((contents, mime))
+}
+
+/// Reads `hrefs`, paths inside `book_id`'s unpacked EPUB (fragments are
+/// ignored), into memory ahead of the reader asking for them. The oldest
+/// prefetched files are dropped to make room.
+pub fn prefetch(library_dir: &Path, book_id: i64, hrefs: &[String]) {
+    for href in hrefs {
+        let href = href.split('#').next().unwrap_or_default();
+        let href = percent_decode(href.trim_start_matches('/'));
+        let Some(path) = cached_path(library_dir, book_id, &href) else {
+            continue;
+        };
+        if prefetched()
+            .iter()
+            .any(|(prefetched, _)| *prefetched == path)
+        {
+            continue;
+        }
+        let data = match std::fs::read(&path) {
+            Ok(data) if data.len() <= PREFETCH_BYTES => data,
+            Ok(_) => continue,
+            Err(e) => {
+                log::warn!("Could not prefetch {href} of book {book_id}: {e}");
+                continue;
+            }
+        };
+        let mut files = prefetched();
+        let mut total: usize = files.iter().map(|(_, data)| data.len()).sum::<usize>() + data.len();
+        while total > PREFETCH_BYTES && !files.is_empty() {
+            total -= files.remove(0).1.len();
+        }
+        files.push((path, data));
+    }
+}
+
+fn mime_for(path: &str) -> &'static str {
+    let extension = path.rsplit_once('.').map(|(_, e)| e.to_lowercase());
+    TYPES
+        .iter()
+        .find(|(ext, _)| Some(*ext) == extension.as_deref())
+        .map_or(OCTET_STREAM, |(_, mime)| *mime)
+}
+
+/// Response to a request for `path` over [`SCHEME`], with the request's
+/// `Range` header: `<book id>/<path in the EPUB>` for an unpacked file, or
+/// `file/<book id>` for the book file. 404 for anything else, including
+/// books that haven't finished unpacking and while the app is locked.
+pub fn respond(state: &DbState, path: &str, range: Option<&str>) -> tauri::http::Response<Vec<u8>> {
+    if !matches!(state.is_app_locked(), Ok(false)) {
+        return streaming::not_found();
+    }
+    let path = percent_decode(path.trim_start_matches('/'));
+    let Some((first, rest)) = path.split_once('/') else {
+        return streaming::not_found();
+    };
+    if first == "file" {
+        let Ok(book_id) = rest.parse::<i64>() else {
+            return streaming::not_found();
+        };
+        return match book_file(state, book_id) {
+            Ok((contents, mime)) => streaming::respond(&contents, range, mime),
+            Err(e) => {
+                log::warn!("Could not serve book {book_id}: {e}");
+                streaming::not_found()
+            }
+        };
+    }
+    let contents = match (state.dir(), first.parse::<i64>()) {
+        (Ok(library_dir), Ok(book_id)) => {
+            cached_path(&library_dir, book_id, rest).and_then(|path| read(&path))
+        }
+        _ => None,
+    };
+    match contents {
+        Some(contents) => streaming::respond(&contents, range, mime_for(&path)),
+        None => streaming::not_found(),
+    }
+}
@@ -0,0 +1,327 @@
+//! Ordering of EPUB CFIs by position in the book.
+//!
+//! CFIs are compared by their numeric path: every `/step`, in order across
+//! `!` indirections, and then by the character `:offset` if any, so an offset
+//! into a node sorts before the node's children. ID assertions
+//! (`[chap01]`) and temporal/spatial offsets don't affect position and are
+//! ignored. Range CFIs (`epubcfi(parent,start,end)`) sort by their start.
+//! Strings that aren't CFIs sort after all CFIs.
+//!
+//! Ranges can also be tested for overlap and joined into one range.
+//!
+//! The ordering is available in SQL as the collation `cfi_order`:
+//! `ORDER BY h.cfi COLLATE cfi_order` lists highlights in reading order.
+
+use rusqlite::Connection;
+use std::cmp::Ordering;
+
+/// The parts of a CFI: its path alone, or for a range CFI
+/// (`epubcfi(parent,start,end)`) the parent and the two local paths.
+/// Assertions are kept. `None` if `cfi` isn't a CFI.
+fn parts(cfi: &str) -> Option<Vec<&str>> {
+    let inner = cfi.trim().strip_prefix("epubcfi(")?.strip_suffix(')')?;
+    // Assertions may contain any character (escaped with `^`), including the
+    // `,` that separates range parts.
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '^' => escaped = true,
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&inner[part_start..i]);
+                part_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[part_start..]);
+    matches!(parts.len(), 1 | 3).then_some(parts)
+}
+
+/// Start and end of a CFI as full locations (parent joined with the local
+/// path). Both are the same for a CFI that isn't a range.
+fn bounds(cfi: &str) -> Option<(String, String)> {
+    match parts(cfi)?.as_slice() {
+        [location] => Some((location.to_string(), location.to_string())),
+        [parent, start, end] => Some((format!("{parent}{start}"), format!("{parent}{end}"))),
+        _ => None,
+    }
+}
+
+/// Where a location points: its steps, then the character offset into the
+/// last one. Ordered as positions in the book.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Position {
+    steps: Vec<u32>,
+    offset: Option<u32>,
+}
+
+/// Numeric path of a location, ignoring assertions.
+fn position(location: &str) -> Option<Position> {
+    let mut plain = String::with_capacity(location.len());
+    let mut chars = location.chars();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '^' => {
+                if let Some(escaped) = chars.next() {
+                    if depth == 0 {
+                        plain.push(escaped);
+                    }
+                }
+            }
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => plain.push(c),
+            _ => {}
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut offset = None;
+    let mut rest = plain.as_str();
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '/' | ':' => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let number = rest[..end].parse().ok()?;
+                rest = &rest[end..];
+                if c == ':' {
+                    offset = Some(number);
+                    break;
+                }
+                steps.push(number);
+            }
+            '!' => {}
+            _ => break,
+        }
+    }
+    (!steps.is_empty()).then_some(Position { steps, offset })
+}
+
+/// Position of a CFI (the start, for ranges), or `None` if `cfi` isn't one.
+fn path(cfi: &str) -> Option<Position> {
+    position(&bounds(cfi)?.0)
+}
+
+/// Positions of the start and end of a CFI.
+fn range(cfi: &str) -> Option<(Position, Position)> {
+    let (start, end) = bounds(cfi)?;
+    Some((position(&start)?, position(&end)?))
+}
+
+/// Compares two CFIs by where they point in the book.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (path(a), path(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Adds the `cfi_order` collation, which sorts by [`compare`].
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_collation("cfi_order", compare)
+}
+
+/// Index in the spine of the document `cfi` points into.
+pub fn spine_index(cfi: &str) -> Option<usize> {
+    match path(cfi)?.steps.as_slice() {
+        [6, step, ..] if *step >= 2 => Some(*step as usize / 2 - 1),
+        _ => None,
+    }
+}
+
+/// Whether two range CFIs share any text. Ranges that only touch don't.
+pub fn overlaps(a: &str, b: &str) -> bool {
+    match (range(a), range(b)) {
+        (Some((a_start, a_end)), Some((b_start, b_end))) => a_start < b_end && b_start < a_end,
+        _ => false,
+    }
+}
+
+/// A range CFI covering both `a` and `b` and everything between them, or
+/// `None` if they aren't CFIs or lie in different documents.
+pub fn span(a: &str, b: &str) -> Option<String> {
+    let (a_start, a_end) = bounds(a)?;
+    let (b_start, b_end) = bounds(b)?;
+    let start = if position(&a_start)? <= position(&b_start)? {
+        a_start
+    } else {
+        b_start
+    };
+    let end = if position(&a_end)? >= position(&b_end)? {
+        a_end
+    } else {
+        b_end
+    };
+
+    // The new parent is the longest shared prefix that ends at a step within
+    // the same document (after the last `!`).
+    let mut split = None;
+    let mut in_document = false;
+    let mut depth = 0;
+    let mut escaped = false;
+    for ((i, c), d) in start.char_indices().zip(end.chars()) {
+        if c != d {
+            break;
+        }
+        match c {
+            _ if escaped => escaped = false,
+            '^' => escaped = true,
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            '!' if depth == 0 => {
+                in_document = true;
+                split = None;
+            }
+            '/' if depth == 0 && in_document => split = Some(i),
+            _ => {}
+        }
+    }
+    let split = split?;
+    Some(format!(
+        "epubcfi({},{},{})",
+        &start[..split],
+        &start[split..],
+        &end[split..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_follows_reading_order() {
+        let ordered = [
+            "epubcfi(/6/2!/4/2/1:0)",
+            "epubcfi(/6/4!/4/2:5)",
+            "epubcfi(/6/4!/4/2/1)",
+            "epubcfi(/6/4!/4/2/1:3)",
+            "epubcfi(/6/4!/4/2/1:12)",
+            "epubcfi(/6/4!/4/10/1:0)",
+            "epubcfi(/6/12!/4/2/1:0)",
+        ];
+        for (i, a) in ordered.iter().enumerate() {
+            for (j, b) in ordered.iter().enumerate() {
+                assert_eq!(compare(a, b), i.cmp(&j), "{a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_are_not_compared_with_steps() {
+        // An offset into /4/2 comes before its children, however large.
+        assert_eq!(
+            compare("epubcfi(/6/4!/4/2:50)", "epubcfi(/6/4!/4/2/1:0)"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare("epubcfi(/6/4!/4/2/3:0)", "epubcfi(/6/4!/4/2:3)"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn compare_ignores_assertions() {
+        assert_eq!(
+            compare(
+                "epubcfi(/6/4[chap01ref]!/4[body01]/10[para05]/3:10)",
+                "epubcfi(/6/4!/4/10/3:10)"
+            ),
+            Ordering::Equal
+        );
+        // Escaped brackets and commas inside an assertion.
+        assert_eq!(
+            compare("epubcfi(/6/4[a^]b^,c]!/4/2/1:0)", "epubcfi(/6/4!/4/2/1:0)"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn ranges_sort_by_their_start() {
+        assert_eq!(
+            compare("epubcfi(/6/4!/4/2,/1:5,/1:40)", "epubcfi(/6/4!/4/2/1:10)"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare("epubcfi(/6/4!/4/2,/1:5,/1:40)", "epubcfi(/6/4!/4/2/1:5)"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn non_cfis_sort_after_cfis() {
+        assert_eq!(
+            compare("page-12", "epubcfi(/6/4!/4/2/1:0)"),
+            Ordering::Greater
+        );
+        assert_eq!(compare("epubcfi(/6/4!/4/2/1:0)", "page-12"), Ordering::Less);
+        assert_eq!(compare("page-12", "page-3"), "page-12".cmp("page-3"));
+        assert_eq!(compare("epubcfi()", "epubcfi(/6/2!/4)"), Ordering::Greater);
+        assert_eq!(
+            compare("epubcfi(/6/4!/4,/2,/4,/6)", "epubcfi(/6/2!/4)"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn spine_index_reads_the_package_step() {
+        assert_eq!(spine_index("epubcfi(/6/2!/4/2/1:0)"), Some(0));
+        assert_eq!(spine_index("epubcfi(/6/14[chap07]!/4/2/1:0)"), Some(6));
+        assert_eq!(spine_index("epubcfi(/6/4!/4/2,/1:0,/1:9)"), Some(1));
+        assert_eq!(spine_index("epubcfi(/4/2!/4)"), None);
+        assert_eq!(spine_index("epubcfi(/6/0!/4)"), None);
+        assert_eq!(spine_index("page-12"), None);
+    }
+
+    #[test]
+    fn overlapping_ranges_share_text() {
+        let a = "epubcfi(/6/4!/4/2,/1:0,/1:10)";
+        assert!(overlaps(a, "epubcfi(/6/4!/4/2,/1:5,/1:20)"));
+        assert!(overlaps("epubcfi(/6/4!/4/2,/1:5,/1:20)", a));
+        assert!(overlaps(a, "epubcfi(/6/4!/4,/2/1:2,/2/1:3)"));
+        // Touching isn't overlapping.
+        assert!(!overlaps(a, "epubcfi(/6/4!/4/2,/1:10,/1:20)"));
+        assert!(!overlaps(a, "epubcfi(/6/6!/4/2,/1:0,/1:10)"));
+        assert!(!overlaps(a, "page-12"));
+    }
+
+    #[test]
+    fn span_covers_both_ranges() {
+        assert_eq!(
+            span(
+                "epubcfi(/6/4!/4/2,/1:0,/1:10)",
+                "epubcfi(/6/4!/4/2,/1:5,/1:20)"
+            )
+            .as_deref(),
+            Some("epubcfi(/6/4!/4/2,/1:0,/1:20)")
+        );
+        assert_eq!(
+            span(
+                "epubcfi(/6/4!/4/4,/1:3,/1:8)",
+                "epubcfi(/6/4!/4/2,/1:0,/1:10)"
+            )
+            .as_deref(),
+            Some("epubcfi(/6/4!/4,/2/1:0,/4/1:8)")
+        );
+        assert_eq!(
+            span(
+                "epubcfi(/6/4!/4/2,/1:0,/1:10)",
+                "epubcfi(/6/6!/4/2,/1:0,/1:10)"
+            ),
+            None
+        );
+        assert_eq!(span("epubcfi(/6/4!/4/2,/1:0,/1:10)", "page-12"), None);
+    }
+}
@@ -0,0 +1,261 @@
+//! Bibliography entries for books, in the common citation styles.
+//!
+//! Citations are plain text built from whatever metadata the library has;
+//! missing parts are left out (or shown as `n.d.` where a style requires a
+//! year), so the result may need touching up for books imported without a
+//! publisher or date. Names are given either as "First Last" or already
+//! inverted as "Last, First".
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CitationStyle {
+    Apa,
+    Mla,
+    Chicago,
+    Bibtex,
+}
+
+pub struct Source {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    pub year: Option<i64>,
+    pub isbn: Option<String>,
+}
+
+/// Lowercase words that belong to a surname ("van", "de", ...).
+const PARTICLES: &[&str] = &["da", "de", "del", "der", "di", "du", "la", "van", "von"];
+
+/// `name` split into given names and surname.
+fn split_name(name: &str) -> (String, String) {
+    if let Some((last, first)) = name.split_once(',') {
+        return (first.trim().to_string(), last.trim().to_string());
+    }
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let Some(mut start) = words.len().checked_sub(1) else {
+        return (String::new(), String::new());
+    };
+    while start > 1 && PARTICLES.contains(&words[start - 1]) {
+        start -= 1;
+    }
+    (words[..start].join(" "), words[start..].join(" "))
+}
+
+/// "Last, First". Only the last word and any particles before it are taken
+/// as the surname, so a name like "Le Guin" has to be stored inverted.
+fn inverted(name: &str) -> String {
+    match split_name(name) {
+        (first, last) if first.is_empty() => last,
+        (first, last) => format!("{last}, {first}"),
+    }
+}
+
+fn direct(name: &str) -> String {
+    match split_name(name) {
+        (first, last) if first.is_empty() => last,
+        (first, last) => format!("{first} {last}"),
+    }
+}
+
+/// "Ursula Kroeber" → "U. K."; hyphenated names keep the hyphen.
+fn initials(given: &str) -> String {
+    given
+        .split_whitespace()
+        .map(|word| {
+            word.split('-')
+                .filter_map(|part| part.chars().next())
+                .map(|c| format!("{}.", c.to_uppercase()))
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Ends `text` with a period unless it already ends a sentence.
+fn sentence(text: &str) -> String {
+    let text = text.trim();
+    if text.ends_with(['.', '?', '!']) {
+        text.to_string()
+    } else {
+        format!("{text}.")
+    }
+}
+
+/// Joins `parts` as a list: "A", "A, and B", "A, B, and C". The comma
+/// before the last name is needed when the first one is inverted.
+fn list(parts: &[String], and: &str) -> String {
+    match parts {
+        [] => String::new(),
+        [only] => only.clone(),
+        [first, second] => format!("{first}, {and} {second}"),
+        [rest @ .., last] => format!("{}, {and} {last}", rest.join(", ")),
+    }
+}
+
+/// Citation of `source` in `style`.
+pub fn format(style: CitationStyle, source: &Source) -> String {
+    match style {
+        CitationStyle::Apa => apa(source),
+        CitationStyle::Mla => mla(source),
+        CitationStyle::Chicago => chicago(source),
+        CitationStyle::Bibtex => bibtex(source, &key(source)),
+    }
+}
+
+/// Last, F. M., & Last, F. (Year). Title. Publisher.
+fn apa(source: &Source) -> String {
+    let names: Vec<String> = source
+        .authors
+        .iter()
+        .map(|name| match split_name(name) {
+            (first, last) if first.is_empty() => last,
+            (first, last) => format!("{last}, {}", initials(&first)),
+        })
+        .collect();
+    let year = source
+        .year
+        .map_or_else(|| "n.d.".to_string(), |y| y.to_string());
+    let mut parts = Vec::new();
+    if !names.is_empty() {
+        // APA keeps the comma before "&" even with two authors.
+        let authors = match names.as_slice() {
+            [only] => only.clone(),
+            [rest @ .., last] => format!("{}, & {last}", rest.join(", ")),
+            [] => unreachable!(),
+        };
+        parts.push(sentence(&authors));
+    }
+    parts.push(format!("({year})."));
+    parts.push(sentence(&source.title));
+    parts.extend(source.publisher.as_deref().map(sentence));
+    parts.join(" ")
+}
+
+/// Last, First, and First Last. Title. Publisher, Year.
+fn mla(source: &Source) -> String {
+    let authors = match source.authors.as_slice() {
+        [] => None,
+        [only] => Some(inverted(only)),
+        [first, second] => Some(format!("{}, and {}", inverted(first), direct(second))),
+        [first, ..] => Some(format!("{}, et al", inverted(first))),
+    };
+    let mut parts: Vec<String> = authors.as_deref().map(sentence).into_iter().collect();
+    parts.push(sentence(&source.title));
+    parts.extend(publication(source));
+    parts.join(" ")
+}
+
+/// Last, First, First Last, and First Last. Title. Publisher, Year.
+fn chicago(source: &Source) -> String {
+    let names: Vec<String> = source
+        .authors
+        .iter()
+        .enumerate()
+        .map(|(i, name)| if i == 0 { inverted(name) } else { direct(name) })
+        .collect();
+    let mut parts = Vec::new();
+    if !names.is_empty() {
+        parts.push(sentence(&list(&names, "and")));
+    }
+    parts.push(sentence(&source.title));
+    parts.extend(publication(source));
+    parts.join(" ")
+}
+
+/// "Publisher, Year." with whichever of the two is known.
+fn publication(source: &Source) -> Option<String> {
+    let parts: Vec<String> = source
+        .publisher
+        .iter()
+        .cloned()
+        .chain(source.year.map(|y| y.to_string()))
+        .collect();
+    (!parts.is_empty()).then(|| sentence(&parts.join(", ")))
+}
+
+/// Citation key like `leguin1969left`: first author's surname, year and first
+/// word of the title, ASCII letters and digits only.
+fn key(source: &Source) -> String {
+    let clean = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let surname = source
+        .authors
+        .first()
+        .map(|name| clean(&split_name(name).1))
+        .unwrap_or_default();
+    let year = source.year.map(|y| y.to_string()).unwrap_or_default();
+    let word = source
+        .title
+        .split_whitespace()
+        .map(clean)
+        .find(|w| !w.is_empty() && !matches!(w.as_str(), "a" | "an" | "the"))
+        .unwrap_or_default();
+    let key = format!("{surname}{year}{word}");
+    if key.is_empty() {
+        "book".to_string()
+    } else {
+        key
+    }
+}
+
+/// Escapes the characters LaTeX treats specially in a field value.
+fn latex(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// A `@book` entry for `source` under `key`.
+fn bibtex(source: &Source, key: &str) -> String {
+    let mut fields = Vec::new();
+    if !source.authors.is_empty() {
+        let authors: Vec<String> = source.authors.iter().map(|a| inverted(a)).collect();
+        fields.push(("author", latex(&authors.join(" and "))));
+    }
+    // Double braces keep BibTeX from changing the title's capitalization.
+    fields.push(("title", format!("{{{}}}", latex(&source.title))));
+    fields.extend(source.publisher.as_deref().map(|p| ("publisher", latex(p))));
+    fields.extend(source.year.map(|y| ("year", y.to_string())));
+    fields.extend(source.isbn.clone().map(|isbn| ("isbn", isbn)));
+
+    let body: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| format!("  {name} = {{{value}}}"))
+        .collect();
+    format!("@book{{{key},\n{}\n}}\n", body.join(",\n"))
+}
+
+/// A BibTeX file with an entry for each of `sources`. Keys that would clash
+/// get a letter suffix (`smith2001`, `smith2001a`, ...).
+pub fn bibliography(sources: &[Source]) -> String {
+    let mut used = HashSet::new();
+    let mut entries = Vec::new();
+    for source in sources {
+        let base = key(source);
+        let mut key = base.clone();
+        let mut suffixes = ('a'..='z')
+            .map(String::from)
+            .chain((2..).map(|n| n.to_string()));
+        while !used.insert(key.clone()) {
+            key = format!("{base}{}", suffixes.next().unwrap_or_default());
+        }
+        entries.push(bibtex(source, &key));
+    }
+    entries.join("\n")
+}
@@ -0,0 +1,310 @@
+//! `tumelog-cli`: exports, imports, statistics and backups against the app's
+//! database without starting the window, for scripts and cron jobs.
+//!
+//! The CLI opens the same profile the app would (or the one given with
+//! `--profile`). A running app doesn't see the CLI's changes until it reloads
+//! the library.
+
+use crate::db::{self, DbState, Library};
+use crate::export::ExportFormat;
+use crate::models::ExportScope;
+use crate::stats::StatsRange;
+use crate::{backup, encryption, export, import, profiles, stats, storage};
+use crate::{APP_IDENTIFIER, LEGACY_IDENTIFIER};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+/// Environment variable holding the passphrase of an encrypted library.
+const PASSPHRASE_ENV: &str = "TUMELOG_PASSPHRASE";
+
+const USAGE: &str = "Usage: tumelog-cli [--data-dir DIR] [--profile NAME] <command> [options]
+
+Commands:
+  export [--format json|markdown|html] [--book TITLE | --collection ID] [--output FILE]
+      Write highlights to FILE, or to standard output. JSON exports can be
+      imported again.
+  import FILE...
+      Add book files to the library, or merge highlights from JSON exports.
+  stats [--json] [--csv DIR [--from YYYY-MM-DD] [--to YYYY-MM-DD]]
+      Print library totals, or write reading statistics as CSV files to DIR.
+  backup [--dir DIR] [--keep N]
+      Snapshot the database into the profile's backups folder, or DIR, and
+      keep the newest N snapshots there.
+
+An encrypted library is unlocked with the passphrase in TUMELOG_PASSPHRASE.";
+
+/// Options that don't take a value.
+const FLAGS: &[&str] = &["json", "help"];
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(raw: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut raw = raw.into_iter();
+        let mut args = Args {
+            positional: Vec::new(),
+            options: HashMap::new(),
+        };
+        while let Some(arg) = raw.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                args.positional.push(arg);
+                continue;
+            };
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None if FLAGS.contains(&name) => (name.to_string(), String::new()),
+                None => (
+                    name.to_string(),
+                    raw.next().ok_or(format!("--{name} needs a value"))?,
+                ),
+            };
+            args.options.insert(name, value);
+        }
+        Ok(args)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+}
+
+/// Entry point of the `tumelog-cli` binary.
+pub fn main() -> ExitCode {
+    match run(std::env::args().skip(1)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("tumelog-cli: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(raw: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let args = Args::parse(raw)?;
+    let Some(command) = args.positional.first().filter(|_| !args.flag("help")) else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+    let library = open(&args)?;
+    match command.as_str() {
+        "export" => export(&library, &args),
+        "import" => import(&library, &args.positional[1..]),
+        "stats" => stats(&library, &args),
+        "backup" => backup(&library, &args),
+        other => Err(format!("Unknown command {other:?}\n\n{USAGE}")),
+    }
+}
+
+/// The app data folder, as Tauri resolves it.
+fn app_dir(args: &Args) -> Result<PathBuf, String> {
+    if let Some(dir) = args.option("data-dir") {
+        return Ok(PathBuf::from(dir));
+    }
+    let dir = dirs::data_dir()
+        .map(|dir| dir.join(APP_IDENTIFIER))
+        .ok_or_else(|| "Could not find the app data folder; pass --data-dir".to_string())?;
+    profiles::move_legacy_app_dir(&dir, LEGACY_IDENTIFIER).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+fn open(args: &Args) -> Result<DbState, String> {
+    let app_dir = app_dir(args)?;
+    if !app_dir.is_dir() {
+        return Err(format!("No library at {}", app_dir.display()));
+    }
+    let profile = match args.option("profile") {
+        Some(name) if profiles::exists(&app_dir, name) => name.to_string(),
+        Some(name) => return Err(format!("No profile named {name:?}")),
+        None => profiles::current(&app_dir),
+    };
+    let dir = profiles::dir(&app_dir, &profile);
+    let library = if encryption::is_enabled(&dir) {
+        let passphrase = std::env::var(PASSPHRASE_ENV)
+            .map_err(|_| format!("The library is encrypted; set {PASSPHRASE_ENV}"))?;
+        let key = Arc::new(encryption::unlock(&dir, &passphrase)?);
+        storage::set_key(Some(key.clone()));
+        Library::open_with_key(&profile, dir, Some(key))?
+    } else {
+        Library::open(&app_dir, &profile)?
+    };
+    Ok(DbState::new(library))
+}
+
+/// A connection regardless of the app lock, which guards the window: anyone
+/// who can run this can read an unencrypted database file directly, and an
+/// encrypted one was unlocked with its passphrase.
+fn conn(library: &DbState) -> Result<db::PooledConnection, String> {
+    library.unchecked_conn()
+}
+
+fn export(library: &DbState, args: &Args) -> Result<(), String> {
+    let scope = match (args.option("book"), args.option("collection")) {
+        (Some(_), Some(_)) => return Err("Pass either --book or --collection".to_string()),
+        (Some(title), None) => ExportScope::Book {
+            title: title.to_string(),
+        },
+        (None, Some(id)) => ExportScope::Collection {
+            collection_id: id
+                .parse()
+                .map_err(|_| format!("Invalid collection id {id:?}"))?,
+        },
+        (None, None) => ExportScope::All,
+    };
+    let conn = conn(library)?;
+    let output = match args.option("format").unwrap_or("json") {
+        "json" => {
+            let document = export::highlights_document(&conn, &scope)?;
+            serde_json::to_string_pretty(&document).map_err(|e| e.to_string())?
+        }
+        format => {
+            let format = match format {
+                "markdown" | "md" => ExportFormat::Markdown,
+                "html" => ExportFormat::Html,
+                other => return Err(format!("Unknown format {other:?}")),
+            };
+            let title = export::scope_title(&conn, &scope).map_err(|e| e.to_string())?;
+            let items = export::items(&conn, &scope).map_err(|e| e.to_string())?;
+            export::render(format, &title, &items)
+        }
+    };
+    match args.option("output") {
+        Some(path) => std::fs::write(path, output).map_err(|e| e.to_string()),
+        None => {
+            println!("{output}");
+            Ok(())
+        }
+    }
+}
+
+/// Imports each file, reporting failures without stopping; fails at the end
+/// if any did.
+fn import(library: &DbState, files: &[String]) -> Result<(), String> {
+    if files.is_empty() {
+        return Err("Nothing to import".to_string());
+    }
+    let mut conn = conn(library)?;
+    let books_dir = db::books_dir(&conn, &library.dir()?)?;
+    let mut failed = 0;
+    for file in files {
+        let path = Path::new(file);
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let result = if is_json {
+            std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|json| export::import_document(&mut conn, &json))
+                .map(|report| {
+                    format!(
+                        "{} added, {} updated, {} unchanged, {} conflicts",
+                        report.added, report.updated, report.unchanged, report.conflicts
+                    )
+                })
+        } else {
+            import::import_file(&conn, &books_dir, path, &mut |_, _| Ok(()), &mut |_, _| {}).map(
+                |(book, added)| {
+                    if added {
+                        format!("added {:?}", book.title)
+                    } else {
+                        format!("already in the library as {:?}", book.title)
+                    }
+                },
+            )
+        };
+        match result {
+            Ok(message) => println!("{file}: {message}"),
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                failed += 1;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!(
+            "{n} of {} files could not be imported",
+            files.len()
+        )),
+    }
+}
+
+fn stats(library: &DbState, args: &Args) -> Result<(), String> {
+    let conn = conn(library)?;
+    if let Some(dir) = args.option("csv") {
+        let range = StatsRange {
+            from: args.option("from").map(str::to_string),
+            to: args.option("to").map(str::to_string),
+        };
+        let written = stats::export(&conn, Path::new(dir), &range)?;
+        println!(
+            "Wrote {} sessions, {} days and {} books to {dir}",
+            written.sessions, written.days, written.books
+        );
+        return Ok(());
+    }
+    let summary = stats::summary(&conn).map_err(|e| e.to_string())?;
+    if args.flag("json") {
+        let json = serde_json::to_string_pretty(&summary).map_err(|e| e.to_string())?;
+        println!("{json}");
+        return Ok(());
+    }
+    println!(
+        "Books:            {} ({} finished)",
+        summary.books, summary.finished_books
+    );
+    println!("Highlights:       {}", summary.highlights);
+    println!("Bookmarks:        {}", summary.bookmarks);
+    println!(
+        "Reading:          {} minutes in {} sessions",
+        summary.minutes_read, summary.reading_sessions
+    );
+    println!(
+        "Streak:           {} days (longest {})",
+        summary.current_streak, summary.longest_streak
+    );
+    if !summary.languages.is_empty() {
+        println!("Languages:");
+    }
+    for language in &summary.languages {
+        println!(
+            "  {:<16}{} books ({} finished), {} minutes",
+            language.language.as_deref().unwrap_or("unknown"),
+            language.books,
+            language.finished_books,
+            language.minutes_read
+        );
+    }
+    Ok(())
+}
+
+fn backup(library: &DbState, args: &Args) -> Result<(), String> {
+    let keep = args
+        .option("keep")
+        .map(|n| n.parse().map_err(|_| format!("Invalid --keep {n:?}")))
+        .transpose()?;
+    // The app's own folder is pruned like the daily backups; a folder of the
+    // caller's only when asked to.
+    let (dir, keep) = match args.option("dir") {
+        Some(dir) => (PathBuf::from(dir), keep),
+        None => (
+            backup::backups_dir(&library.dir()?),
+            Some(keep.unwrap_or(backup::KEEP)),
+        ),
+    };
+    let conn = conn(library)?;
+    let created = backup::create(&conn, &dir)?;
+    if let Some(keep) = keep {
+        backup::prune(&dir, keep)?;
+    }
+    println!("{}", dir.join(created.name).display());
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! Bookmarks.
+
+use crate::db::{self, DbState};
+use crate::events::DataEvent;
+use crate::models::{Bookmark, BookmarkSort};
+use crate::{events, undo};
+
+#[tauri::command]
+pub fn add_bookmark(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_title: String,
+    cfi: String,
+    label: String,
+) -> Result<Bookmark, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let bookmark =
+        db::bookmarks::add(&conn, &book_title, &cfi, &label).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Add bookmark", since)?;
+    events::emit(&app, DataEvent::BookmarkAdded(bookmark.clone()));
+    Ok(bookmark)
+}
+
+/// Bookmarks in `book_title`, in reading order unless `sort` says otherwise.
+#[tauri::command]
+pub fn get_bookmarks(
+    state: tauri::State<DbState>,
+    book_title: String,
+    sort: Option<BookmarkSort>,
+) -> Result<Vec<Bookmark>, String> {
+    let conn = state.conn()?;
+    db::bookmarks::list(&conn, &book_title, sort.unwrap_or_default()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn update_bookmark(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    label: String,
+) -> Result<Bookmark, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let bookmark = db::bookmarks::rename(&conn, id, &label).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Rename bookmark", since)?;
+    events::emit(&app, DataEvent::BookmarkUpdated(bookmark.clone()));
+    Ok(bookmark)
+}
+
+#[tauri::command]
+pub fn delete_bookmark(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    db::bookmarks::delete(&conn, id).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Delete bookmark", since)?;
+    events::emit(&app, DataEvent::BookmarkDeleted(events::RecordId { id }));
+    Ok(())
+}
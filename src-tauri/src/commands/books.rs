@@ -0,0 +1,1025 @@
+//! Books: adding, reading progress, files, ratings, citations, authors and
+//! series, and book-level notes.
+
+use super::{books_dir, run_blocking};
+use super::{conversion_progress, import_book};
+use crate::db::{self, compress_books, store_chapters, DbState};
+use crate::events::DataEvent;
+use crate::import::{book_format, convert_on_import, NewBook};
+use crate::models::{
+    BookMetadata, BookNote, BookQuery, BookSortBy, BookWithCounts, Drawing, LibrarySort,
+    ProgressEntry, ReadingStatus, SortDirection, Stroke, Tag,
+};
+use crate::{
+    article, authors, book_cache, citation, covers, enrich, epub, events, extract, integrity,
+    language, reanchor, search, storage,
+};
+use rusqlite::{params, Connection};
+
+#[tauri::command]
+pub async fn add_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    title: String,
+    filename: String,
+    cover: Option<String>,
+    data: Vec<u8>,
+) -> Result<BookMetadata, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = NewBook {
+            title,
+            filename,
+            cover,
+            data,
+        };
+        import_book(&app, &conn, book, &mut |_, _| Ok(()))
+    })
+    .await
+}
+
+/// Every book, ordered by `sort`. With `min_rating`, only books rated at
+/// least that many stars.
+#[tauri::command]
+pub async fn get_all_books(
+    state: tauri::State<'_, DbState>,
+    sort: Option<LibrarySort>,
+    min_rating: Option<i64>,
+) -> Result<Vec<BookWithCounts>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        db::books::list(&conn, sort.unwrap_or_default(), min_rating).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Library view with sorting, a reading status, collection or tag filter, and a
+/// search over titles and authors that ignores case and accents.
+#[tauri::command]
+pub async fn query_books(
+    state: tauri::State<'_, DbState>,
+    sort_by: Option<BookSortBy>,
+    direction: Option<SortDirection>,
+    search: Option<String>,
+    status: Option<ReadingStatus>,
+    collection_id: Option<i64>,
+    tag: Option<String>,
+) -> Result<Vec<BookWithCounts>, String> {
+    let query = BookQuery {
+        sort_by: sort_by.unwrap_or_default(),
+        direction,
+        search,
+        status,
+        collection_id,
+        tag,
+    };
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        db::books::query_books(&conn, &query).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn update_book_progress(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    title: String,
+    position: String,
+    percentage: f64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let update = db::books::update_progress(&conn, &title, &position, percentage)
+        .map_err(|e| e.to_string())?;
+    if let Some(update) = update {
+        events::emit(
+            &app,
+            DataEvent::BookProgress(events::BookProgress {
+                title,
+                last_position: position,
+                last_percentage: percentage,
+                finished_at: update.finished_at,
+            }),
+        );
+        if let Some(jump) = update.jump {
+            events::emit(&app, DataEvent::ProgressJumped(jump));
+        }
+    }
+    Ok(())
+}
+
+/// Moves a book back to the position before its latest progress history
+/// entry; see [`db::books::revert_progress`].
+#[tauri::command]
+pub fn revert_progress(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+) -> Result<BookMetadata, String> {
+    let mut conn = state.conn()?;
+    let book = db::books::revert_progress(&mut conn, book_id)?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Reading positions of a book over time, oldest first.
+#[tauri::command]
+pub fn get_progress_history(
+    state: tauri::State<DbState>,
+    book_id: i64,
+) -> Result<Vec<ProgressEntry>, String> {
+    let conn = state.conn()?;
+    db::books::progress_history(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Emits `library://book-updated` with the current row for `title`, if any.
+pub fn emit_book_updated(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    title: &str,
+) -> Result<(), String> {
+    if let Some(book) = db::books::by_title(conn, title).map_err(|e| e.to_string())? {
+        events::emit(app, DataEvent::BookUpdated(book));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn update_book_locations(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    title: String,
+    locations_data: String,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    db::books::set_locations(&conn, &title, &locations_data).map_err(|e| e.to_string())?;
+    emit_book_updated(&app, &conn, &title)
+}
+
+/// The book file's bytes, for the reader. Also records the book as opened.
+#[tauri::command]
+pub async fn get_book_content(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    filename: String,
+) -> Result<Vec<u8>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let file_path = books_dir(&app, &conn)?.join(&filename);
+        let data = storage::read(&file_path).map_err(|e| e.to_string())?;
+        if let Some(book) = db::books::mark_opened(&conn, &filename).map_err(|e| e.to_string())? {
+            events::emit(&app, DataEvent::BookUpdated(book));
+        }
+        Ok(data)
+    })
+    .await
+}
+
+/// The book's plain text, or one chapter's (pages for PDFs, counted from 0).
+#[tauri::command]
+pub async fn extract_book_text(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    chapter: Option<usize>,
+) -> Result<extract::BookText, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        if book.archived {
+            return Err(format!("{} is archived", book.title));
+        }
+        let path = books_dir(&app, &conn)?.join(&book.filename);
+        extract::book_text(&path, &book.format, chapter)
+    })
+    .await
+}
+
+/// Detects the book's language again from its text and stores it.
+#[tauri::command]
+pub async fn detect_book_language(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<BookMetadata, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        if book.archived {
+            return Err(format!("{} is archived", book.title));
+        }
+        let path = books_dir(&app, &conn)?.join(&book.filename);
+        let detected = language::book_language(&path, &book.format)?;
+        language::store(&conn, book_id, detected.as_deref()).map_err(|e| e.to_string())?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        events::emit(&app, DataEvent::BookUpdated(book.clone()));
+        Ok(book)
+    })
+    .await
+}
+
+/// Detects the language of every book that doesn't have one yet, such as
+/// books added before languages were detected. Returns how many were found.
+#[tauri::command]
+pub async fn detect_missing_languages(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let dir = books_dir(&app, &conn)?;
+        let mut found = 0;
+        for book in db::books::without_language(&conn).map_err(|e| e.to_string())? {
+            let detected = match language::book_language(&dir.join(&book.filename), &book.format) {
+                Ok(Some(detected)) => detected,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::warn!("Could not detect the language of {}: {e}", book.title);
+                    continue;
+                }
+            };
+            language::store(&conn, book.id, Some(&detected)).map_err(|e| e.to_string())?;
+            events::emit(
+                &app,
+                DataEvent::BookUpdated(BookMetadata {
+                    language: Some(detected),
+                    ..book
+                }),
+            );
+            found += 1;
+        }
+        Ok(found)
+    })
+    .await
+}
+
+/// Sets the book's language by hand, as an ISO 639-1 code or a tag like
+/// "pt-BR". `None` or a blank value clears it.
+#[tauri::command]
+pub fn set_book_language(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+    language: Option<String>,
+) -> Result<BookMetadata, String> {
+    let language = match language.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(code) => Some(
+            language::normalize(code).ok_or_else(|| format!("{code:?} is not a language code"))?,
+        ),
+    };
+    let conn = state.conn()?;
+    language::store(&conn, book_id, language.as_deref()).map_err(|e| e.to_string())?;
+    let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Unpacks the EPUB into the book cache, if it isn't there already, for the
+/// reader to load from the returned URL.
+#[tauri::command]
+pub async fn open_cached_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<book_cache::CachedBook, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        if !crate::import::is_epub(&book.format) {
+            return Err(format!("{} is not an EPUB", book.title));
+        }
+        if book.archived {
+            return Err(format!("{} is archived", book.title));
+        }
+        let path = books_dir(&app, &conn)?.join(&book.filename);
+        book_cache::open(&conn, &state.dir()?, book_id, &path)
+    })
+    .await
+}
+
+/// URL the viewer can stream the book's file from, seeking with range
+/// requests rather than loading it whole. Also records the book as opened.
+#[tauri::command]
+pub async fn get_book_file_url(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        if book.archived {
+            return Err(format!("{} is archived", book.title));
+        }
+        if let Some(book) =
+            db::books::mark_opened(&conn, &book.filename).map_err(|e| e.to_string())?
+        {
+            events::emit(&app, DataEvent::BookUpdated(book));
+        }
+        Ok(book_cache::file_url(book_id))
+    })
+    .await
+}
+
+/// Reads `hrefs` of a book opened with `open_cached_book` into memory in the
+/// background, for the chapters the reader is about to turn into.
+#[tauri::command]
+pub fn prefetch_resources(
+    state: tauri::State<DbState>,
+    book_id: i64,
+    hrefs: Vec<String>,
+) -> Result<(), String> {
+    let dir = state.dir()?;
+    tauri::async_runtime::spawn_blocking(move || book_cache::prefetch(&dir, book_id, &hrefs));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_book_cache(state: tauri::State<DbState>) -> Result<book_cache::BookCacheInfo, String> {
+    let conn = state.conn()?;
+    book_cache::info(&conn, &state.dir()?)
+}
+
+#[tauri::command]
+pub fn set_book_cache_limit(
+    state: tauri::State<DbState>,
+    limit_mb: u64,
+) -> Result<book_cache::BookCacheInfo, String> {
+    let conn = state.conn()?;
+    book_cache::set_limit(&conn, &state.dir()?, limit_mb)
+}
+
+#[tauri::command]
+pub fn clear_book_cache(state: tauri::State<DbState>) -> Result<(), String> {
+    book_cache::clear(&state.dir()?)
+}
+
+/// Books opened most recently, newest first (10 unless `limit` says
+/// otherwise). With `unfinished_only`, finished books are left out, as for a
+/// "Continue reading" row.
+#[tauri::command]
+pub fn get_recent_books(
+    state: tauri::State<DbState>,
+    limit: Option<i64>,
+    unfinished_only: Option<bool>,
+) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::books::recent(&conn, limit.unwrap_or(10), unfinished_only.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    title: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let filename = db::books::delete(&mut conn, &title).map_err(|e| e.to_string())?;
+        super::highlights::prune_attachments(&state, &conn);
+        if let Err(e) = book_cache::prune(&conn, &state.dir()?) {
+            log::warn!("Could not remove the deleted book's cached files: {e}");
+        }
+
+        // Delete the file only once the rows are gone for good
+        let file_path = books_dir(&app, &conn)?.join(filename);
+        if file_path.exists() {
+            std::fs::remove_file(file_path).map_err(|e| e.to_string())?;
+        }
+
+        events::emit(&app, DataEvent::BookDeleted(events::BookRef { title }));
+        Ok(())
+    })
+    .await
+}
+
+/// Stores the file at `path` as `book_id`'s file, replacing (or, for an
+/// archived book, restoring) the previous one. The new file must be of the
+/// same kind as the book: a PDF for PDFs, an EPUB (or a format converted to
+/// one) otherwise. EPUB highlights are re-anchored by their text.
+fn attach_book_file(
+    app: &tauri::AppHandle,
+    conn: &mut rusqlite::Connection,
+    book_id: i64,
+    path: &str,
+) -> Result<reanchor::ReanchorReport, String> {
+    let (title, old_filename, old_format, indexed): (String, String, String, bool) = conn
+        .query_row(
+            "SELECT title, filename, format, indexed_at IS NOT NULL FROM books WHERE id = ?1",
+            params![book_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let source = std::path::Path::new(path);
+    let source_name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut data = std::fs::read(source).map_err(|e| e.to_string())?;
+    let mut format = book_format(&source_name);
+    if let Some(converted) = convert_on_import(
+        &source_name,
+        &data,
+        &mut conversion_progress(app, &source_name),
+    )? {
+        data = converted.epub;
+        format = "epub";
+    }
+    let expected = if old_format == "pdf" { "pdf" } else { "epub" };
+    if format != expected {
+        return Err(format!(
+            "{title} needs {} file",
+            if expected == "pdf" {
+                "a PDF"
+            } else {
+                "an EPUB, FB2 or MOBI"
+            }
+        ));
+    }
+    let mut epub = None;
+    let mut page_count = None;
+    if format == "pdf" {
+        let meta = lopdf::Document::load_metadata_mem(&data).map_err(|e| e.to_string())?;
+        page_count = Some(meta.page_count as i64);
+    } else {
+        epub = Some(epub::Epub::from_reader(std::io::Cursor::new(
+            data.as_slice(),
+        ))?);
+    }
+
+    let filename = std::path::Path::new(&old_filename)
+        .with_extension(format)
+        .to_string_lossy()
+        .into_owned();
+
+    // Write next to the old file first, so a failure below leaves the book as
+    // it was.
+    let books_dir = books_dir(app, conn)?;
+    std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
+    let file_path = books_dir.join(&filename);
+    let temp_path = books_dir.join(format!("{filename}.tmp"));
+    storage::write(&temp_path, &data, compress_books(conn)?).map_err(|e| e.to_string())?;
+
+    let result = (|| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let report = match epub.as_mut() {
+            Some(epub) => reanchor::reanchor(&tx, &title, epub)?,
+            None => reanchor::ReanchorReport::default(),
+        };
+        tx.execute(
+            "UPDATE books SET filename = ?1, format = ?2, page_count = ?3,
+                 locations_data = NULL, archived = 0, content_hash = ?4
+             WHERE id = ?5",
+            params![
+                filename,
+                format,
+                page_count,
+                integrity::hash_bytes(&data),
+                book_id
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(report)
+    })();
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+    std::fs::rename(&temp_path, &file_path).map_err(|e| e.to_string())?;
+    if old_filename != filename {
+        let _ = std::fs::remove_file(books_dir.join(&old_filename));
+    }
+
+    if format == "epub" {
+        if let Err(e) = store_chapters(conn, book_id, &file_path) {
+            log::warn!("Could not read chapters of {title}: {e}");
+        }
+        if indexed {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            search::index_book(&tx, book_id, &file_path)?;
+            tx.commit().map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(report)
+}
+
+/// Swaps a book's file for another edition of it, keeping its metadata,
+/// progress and annotations. Highlights are re-anchored by their text; those
+/// that can't be found are flagged `unanchored` rather than removed.
+#[tauri::command]
+pub async fn replace_book_file(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    path: String,
+) -> Result<reanchor::ReanchorReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let report = attach_book_file(&app, &mut conn, book_id, &path)?;
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(report)
+    })
+    .await
+}
+
+/// Deletes a book's file, and its unpacked copy in the book cache, to free
+/// space while keeping everything else about it: metadata, progress,
+/// highlights and bookmarks. The book stays in the library, flagged
+/// `archived`, until a file is attached with [`unarchive_book`].
+#[tauri::command]
+pub async fn archive_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    id: i64,
+) -> Result<BookMetadata, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let (title, filename): (String, String) = conn
+            .query_row(
+                "SELECT title, filename FROM books WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+
+        // The flag is only set once the files are gone, so a failed delete
+        // doesn't leave a book marked archived that still has its file.
+        book_cache::remove(&state.dir()?, id)?;
+        let file_path = books_dir(&app, &conn)?.join(filename);
+        if file_path.exists() {
+            std::fs::remove_file(file_path).map_err(|e| e.to_string())?;
+        }
+        conn.execute("UPDATE books SET archived = 1 WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        emit_book_updated(&app, &conn, &title)?;
+        db::books::get(&conn, id).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Re-attaches a file to an archived book. If it's a different edition from
+/// the one archived, highlights are re-anchored as in [`replace_book_file`].
+#[tauri::command]
+pub async fn unarchive_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    id: i64,
+    path: String,
+) -> Result<reanchor::ReanchorReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let archived: bool = conn
+            .query_row(
+                "SELECT archived FROM books WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !archived {
+            return Err("This book isn't archived".to_string());
+        }
+        let report = attach_book_file(&app, &mut conn, id, &path)?;
+        let title: String = conn
+            .query_row(
+                "SELECT title FROM books WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        emit_book_updated(&app, &conn, &title)?;
+        Ok(report)
+    })
+    .await
+}
+
+/// Points a book whose file went missing (a moved library, a sync hiccup) at
+/// `path`. The file is copied into the library; annotations are kept and, if
+/// it's a different edition, re-anchored as in [`replace_book_file`].
+#[tauri::command]
+pub async fn relink_book_file(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    path: String,
+) -> Result<reanchor::ReanchorReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let report = attach_book_file(&app, &mut conn, book_id, &path)?;
+        let title: String = conn
+            .query_row(
+                "SELECT title FROM books WHERE id = ?1",
+                params![book_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        emit_book_updated(&app, &conn, &title)?;
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn toggle_favorite_book(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<BookMetadata, String> {
+    let conn = state.conn()?;
+    let book = db::books::toggle_favorite(&conn, id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Rates a book from 1 to 5 stars, or clears the rating with 0.
+#[tauri::command]
+pub fn set_book_rating(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    rating: i64,
+) -> Result<BookMetadata, String> {
+    let conn = state.conn()?;
+    let book = db::books::set_rating(&conn, id, rating)?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Sets a book's review; a blank review clears it.
+#[tauri::command]
+pub fn set_book_review(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    review: String,
+) -> Result<BookMetadata, String> {
+    let conn = state.conn()?;
+    let book = db::books::set_review(&conn, id, &review).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Sets the publication details used in citations. Blank values clear them.
+#[tauri::command]
+pub fn set_book_publication(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    publisher: Option<String>,
+    year: Option<i64>,
+    isbn: Option<String>,
+) -> Result<BookMetadata, String> {
+    let conn = state.conn()?;
+    let book = db::books::set_publication(&conn, id, publisher.as_deref(), year, isbn.as_deref())
+        .map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Covers found online for the book, by its ISBN or else its title and
+/// author, for the user to pick one with `set_book_cover_from_url`.
+#[tauri::command]
+pub async fn fetch_cover_online(
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<Vec<covers::CoverCandidate>, String> {
+    let state = state.inner().clone();
+    let book = run_blocking(move || {
+        let conn = state.conn()?;
+        db::books::get(&conn, book_id).map_err(|e| e.to_string())
+    })
+    .await?;
+    let query = covers::BookQuery {
+        isbn: book.isbn.as_deref(),
+        title: &book.title,
+        author: book.author.as_deref(),
+    };
+    Ok(covers::candidates(&article::http_client()?, &query).await)
+}
+
+/// Downloads the cover at `url`, one of `fetch_cover_online`'s, and makes it
+/// the book's cover.
+#[tauri::command]
+pub async fn set_book_cover_from_url(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    url: String,
+) -> Result<BookMetadata, String> {
+    let cover = covers::download(&article::http_client()?, &url).await?;
+    let state = state.inner().clone();
+    let book = run_blocking(move || {
+        let conn = state.conn()?;
+        db::books::set_cover(&conn, book_id, Some(&cover)).map_err(|e| e.to_string())
+    })
+    .await?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// Details missing from the book that Open Library has, for the user to
+/// review before `apply_metadata`. `None` if the book can't be found there.
+#[tauri::command]
+pub async fn enrich_metadata(
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<Option<enrich::MetadataProposal>, String> {
+    let state = state.inner().clone();
+    let (book, tags) = run_blocking(move || {
+        let conn = state.conn()?;
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        let tags = db::book_tags::for_book(&conn, book_id).map_err(|e| e.to_string())?;
+        Ok((book, tags))
+    })
+    .await?;
+    enrich::lookup(&article::http_client()?, &book, &tags).await
+}
+
+/// Fills the book's empty fields from a reviewed `enrich_metadata` proposal.
+#[tauri::command]
+pub fn apply_metadata(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    proposal: enrich::MetadataProposal,
+) -> Result<BookMetadata, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let book = enrich::apply(&tx, &proposal)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book.clone()));
+    Ok(book)
+}
+
+/// A citation of the book in APA, MLA, Chicago or BibTeX style.
+#[tauri::command]
+pub fn generate_citation(
+    state: tauri::State<DbState>,
+    book_id: i64,
+    style: citation::CitationStyle,
+) -> Result<String, String> {
+    let conn = state.conn()?;
+    let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+    let source = db::books::citation_source(&conn, &book).map_err(|e| e.to_string())?;
+    Ok(citation::format(style, &source))
+}
+
+/// Writes a BibTeX file with every book in the library to `path`. Returns
+/// the number of entries.
+#[tauri::command]
+pub async fn export_bibtex(
+    state: tauri::State<'_, DbState>,
+    path: String,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let sources = db::books::all(&conn)
+            .and_then(|books| {
+                books
+                    .iter()
+                    .map(|book| db::books::citation_source(&conn, book))
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(|e| e.to_string())?;
+        std::fs::write(&path, citation::bibliography(&sources)).map_err(|e| e.to_string())?;
+        Ok(sources.len())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_favorite_books(state: tauri::State<DbState>) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::books::favorites(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_authors(state: tauri::State<DbState>) -> Result<Vec<authors::Author>, String> {
+    let conn = state.conn()?;
+    authors::list_authors(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_series(state: tauri::State<DbState>) -> Result<Vec<authors::Series>, String> {
+    let conn = state.conn()?;
+    authors::list_series(&conn).map_err(|e| e.to_string())
+}
+
+/// Books by `author_id`, grouped by series and in series order.
+#[tauri::command]
+pub fn get_books_by_author(
+    state: tauri::State<DbState>,
+    author_id: i64,
+) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::books::by_author(&conn, author_id).map_err(|e| e.to_string())
+}
+
+/// Books in `series_id` by their position in the series. Books without a
+/// position come last.
+#[tauri::command]
+pub fn get_books_by_series(
+    state: tauri::State<DbState>,
+    series_id: i64,
+) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::books::by_series(&conn, series_id).map_err(|e| e.to_string())
+}
+
+/// Books tagged `tag`, ignoring case and accents, by title.
+#[tauri::command]
+pub fn get_books_by_tag(
+    state: tauri::State<DbState>,
+    tag: String,
+) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::books::by_tag(&conn, &tag).map_err(|e| e.to_string())
+}
+
+/// Every tag in the library with its number of books.
+#[tauri::command]
+pub fn list_book_tags(state: tauri::State<DbState>) -> Result<Vec<Tag>, String> {
+    let conn = state.conn()?;
+    db::book_tags::all(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_book_tags(state: tauri::State<DbState>, book_id: i64) -> Result<Vec<String>, String> {
+    let conn = state.conn()?;
+    db::book_tags::for_book(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Tags the book with `tag` and returns its tags.
+#[tauri::command]
+pub fn add_book_tag(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+    tag: String,
+) -> Result<Vec<String>, String> {
+    if tag.trim().is_empty() {
+        return Err("Tags can't be blank".to_string());
+    }
+    let conn = state.conn()?;
+    let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+    db::book_tags::add(&conn, book_id, &[tag]).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookUpdated(book));
+    db::book_tags::for_book(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Removes `tag` from the book and returns its remaining tags.
+#[tauri::command]
+pub fn remove_book_tag(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+    tag: String,
+) -> Result<Vec<String>, String> {
+    let conn = state.conn()?;
+    if db::book_tags::remove(&conn, book_id, &tag).map_err(|e| e.to_string())? {
+        let book = db::books::get(&conn, book_id).map_err(|e| e.to_string())?;
+        events::emit(&app, DataEvent::BookUpdated(book));
+    }
+    db::book_tags::for_book(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Renames a tag across the library, merging it into `new_name` if that tag
+/// exists too. Returns how many books were changed.
+#[tauri::command]
+pub fn rename_book_tag(
+    state: tauri::State<DbState>,
+    name: String,
+    new_name: String,
+) -> Result<usize, String> {
+    if new_name.trim().is_empty() {
+        return Err("Tags can't be blank".to_string());
+    }
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let changed = db::book_tags::rename(&tx, &name, &new_name).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(changed)
+}
+
+/// Removes a tag from every book. Returns how many books had it.
+#[tauri::command]
+pub fn delete_book_tag(state: tauri::State<DbState>, name: String) -> Result<usize, String> {
+    let conn = state.conn()?;
+    db::book_tags::delete(&conn, &name).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn add_book_note(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+    content: String,
+) -> Result<BookNote, String> {
+    let conn = state.conn()?;
+    let note = db::book_notes::add(&conn, book_id, &content).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookNoteAdded(note.clone()));
+    Ok(note)
+}
+
+/// A book's journal, oldest entry first.
+#[tauri::command]
+pub fn get_book_notes(state: tauri::State<DbState>, book_id: i64) -> Result<Vec<BookNote>, String> {
+    let conn = state.conn()?;
+    db::book_notes::list(&conn, book_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn update_book_note(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    content: String,
+) -> Result<BookNote, String> {
+    let conn = state.conn()?;
+    let note = db::book_notes::update(&conn, id, &content).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookNoteUpdated(note.clone()));
+    Ok(note)
+}
+
+#[tauri::command]
+pub fn delete_book_note(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    db::book_notes::delete(&conn, id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::BookNoteDeleted(events::RecordId { id }));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn add_drawing(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_id: i64,
+    cfi: String,
+    strokes: Vec<Stroke>,
+) -> Result<Drawing, String> {
+    let conn = state.conn()?;
+    let drawing = db::drawings::add(&conn, book_id, &cfi, &strokes).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::DrawingAdded(drawing.clone()));
+    Ok(drawing)
+}
+
+/// A book's drawings, or with `cfi` only those on that page.
+#[tauri::command]
+pub fn get_drawings(
+    state: tauri::State<DbState>,
+    book_id: i64,
+    cfi: Option<String>,
+) -> Result<Vec<Drawing>, String> {
+    let conn = state.conn()?;
+    db::drawings::list(&conn, book_id, cfi.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn update_drawing(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    strokes: Vec<Stroke>,
+) -> Result<Drawing, String> {
+    let conn = state.conn()?;
+    let drawing = db::drawings::update(&conn, id, &strokes).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::DrawingUpdated(drawing.clone()));
+    Ok(drawing)
+}
+
+#[tauri::command]
+pub fn delete_drawing(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    db::drawings::delete(&conn, id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::DrawingDeleted(events::RecordId { id }));
+    Ok(())
+}
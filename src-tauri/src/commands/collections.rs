@@ -0,0 +1,159 @@
+//! Collections and smart collections.
+
+use crate::db::{self, DbState};
+use crate::events::DataEvent;
+use crate::models::{BookMetadata, Collection, Highlight, SmartCollection};
+use crate::smart_collections::SmartFilter;
+use crate::{events, undo};
+
+#[tauri::command]
+pub fn create_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    name: String,
+    emoji: String,
+) -> Result<Collection, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let collection = db::collections::create(&conn, &name, &emoji).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Create collection", since)?;
+    events::emit(&app, DataEvent::CollectionCreated(collection.clone()));
+    Ok(collection)
+}
+
+#[tauri::command]
+pub fn get_all_collections(state: tauri::State<DbState>) -> Result<Vec<Collection>, String> {
+    let conn = state.conn()?;
+    db::collections::all(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    db::collections::delete(&conn, id).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Delete collection", since)?;
+    events::emit(&app, DataEvent::CollectionDeleted(events::RecordId { id }));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn add_highlight_to_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    highlight_id: i64,
+    collection_id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    db::collections::add_highlight(&conn, collection_id, highlight_id)
+        .map_err(|e| e.to_string())?;
+    undo::record(&conn, "Add to collection", since)?;
+    events::emit(
+        &app,
+        DataEvent::HighlightAddedToCollection(events::CollectionLink {
+            highlight_id,
+            collection_id,
+        }),
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn remove_highlight_from_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    highlight_id: i64,
+    collection_id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    db::collections::remove_highlight(&conn, collection_id, highlight_id)
+        .map_err(|e| e.to_string())?;
+    undo::record(&conn, "Remove from collection", since)?;
+    events::emit(
+        &app,
+        DataEvent::HighlightRemovedFromCollection(events::CollectionLink {
+            highlight_id,
+            collection_id,
+        }),
+    );
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_highlights_by_collection(
+    state: tauri::State<DbState>,
+    collection_id: i64,
+) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::collections::highlights(&conn, collection_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_highlight_collections(
+    state: tauri::State<DbState>,
+    highlight_id: i64,
+) -> Result<Vec<Collection>, String> {
+    let conn = state.conn()?;
+    db::collections::for_highlight(&conn, highlight_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_books_by_collection(
+    state: tauri::State<DbState>,
+    collection_id: i64,
+) -> Result<Vec<BookMetadata>, String> {
+    let conn = state.conn()?;
+    db::collections::books(&conn, collection_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn create_smart_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    name: String,
+    emoji: String,
+    filter: SmartFilter,
+) -> Result<SmartCollection, String> {
+    let conn = state.conn()?;
+    let collection = db::collections::create_smart(&conn, &name, &emoji, &filter)?;
+    events::emit(&app, DataEvent::SmartCollectionCreated(collection.clone()));
+    Ok(collection)
+}
+
+#[tauri::command]
+pub fn get_all_smart_collections(
+    state: tauri::State<DbState>,
+) -> Result<Vec<SmartCollection>, String> {
+    let conn = state.conn()?;
+    db::collections::all_smart(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn evaluate_smart_collection(
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::collections::evaluate_smart(&conn, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_smart_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    db::collections::delete_smart(&conn, id).map_err(|e| e.to_string())?;
+    events::emit(
+        &app,
+        DataEvent::SmartCollectionDeleted(events::RecordId { id }),
+    );
+    Ok(())
+}
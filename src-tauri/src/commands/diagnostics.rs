@@ -0,0 +1,374 @@
+//! Schema version, database and file checks, logs and the diagnostics bundle.
+
+use super::{books_dir, run_blocking};
+use crate::db::{book_from_row, DbState, BOOK_COLUMNS};
+use crate::events::DataEvent;
+use crate::import::{is_epub, OPENABLE_EXTENSIONS};
+use crate::models::{
+    BookFileStatus, BookIntegrity, DatabaseCheckReport, FileStatus, IntegrityStatus,
+    LibraryCleanReport, OrphanCounts, SchemaVersion,
+};
+use crate::{diagnostics, epub, events, integrity, migrations, storage};
+use rusqlite::{params, Connection};
+use tauri::Manager;
+
+/// The last `lines` lines of the app's log, oldest first.
+#[tauri::command]
+pub fn get_recent_logs(
+    app: tauri::AppHandle,
+    lines: Option<usize>,
+) -> Result<Vec<diagnostics::LogEntry>, String> {
+    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    diagnostics::recent(&app_dir, lines.unwrap_or(200))
+}
+
+/// Writes a zip for bug reports to `path`: the log files, schema version,
+/// database pragmas and record counts (see [`diagnostics`]).
+#[tauri::command]
+pub async fn export_diagnostics(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    path: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        let version = app.package_info().version.to_string();
+        let conn = state.conn();
+        diagnostics::export(
+            &app_dir,
+            &version,
+            conn.as_deref().map_err(Clone::clone),
+            std::path::Path::new(&path),
+        )?;
+        log::info!("Exported diagnostics");
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_schema_version(state: tauri::State<DbState>) -> Result<SchemaVersion, String> {
+    let conn = state.conn()?;
+    Ok(SchemaVersion {
+        current: migrations::schema_version(&conn).map_err(|e| e.to_string())?,
+        latest: migrations::latest_version(),
+    })
+}
+
+/// `WHERE` clauses selecting orphaned rows, by table.
+const ORPHAN_HIGHLIGHTS: &str = "book_title NOT IN (SELECT title FROM books)";
+const ORPHAN_BOOKMARKS: &str = "book_title NOT IN (SELECT title FROM books)";
+const ORPHAN_VOCABULARY: &str =
+    "book_title IS NOT NULL AND book_title NOT IN (SELECT title FROM books)";
+const ORPHAN_HIGHLIGHT_LINKS: &str = "highlight_id NOT IN (SELECT id FROM highlights)
+     OR collection_id NOT IN (SELECT id FROM collections)";
+const ORPHAN_HIGHLIGHT_TO_HIGHLIGHT_LINKS: &str = "from_id NOT IN (SELECT id FROM highlights)
+     OR to_id NOT IN (SELECT id FROM highlights)";
+const ORPHAN_BOOK_LINKS: &str = "book_id NOT IN (SELECT id FROM books)
+     OR collection_id NOT IN (SELECT id FROM collections)";
+
+fn count_orphans(conn: &Connection) -> rusqlite::Result<OrphanCounts> {
+    let count = |table: &str, filter: &str| {
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM {table} WHERE {filter}"),
+            [],
+            |row| row.get::<_, usize>(0),
+        )
+    };
+    Ok(OrphanCounts {
+        highlights: count("highlights", ORPHAN_HIGHLIGHTS)?,
+        bookmarks: count("bookmarks", ORPHAN_BOOKMARKS)?,
+        vocabulary: count("vocabulary", ORPHAN_VOCABULARY)?,
+        highlight_collection_links: count("highlight_collections", ORPHAN_HIGHLIGHT_LINKS)?,
+        book_collection_links: count("book_collections", ORPHAN_BOOK_LINKS)?,
+        highlight_links: count("highlight_links", ORPHAN_HIGHLIGHT_TO_HIGHLIGHT_LINKS)?,
+    })
+}
+
+/// Runs `PRAGMA integrity_check` and looks for orphaned rows. With `repair`,
+/// orphaned highlights and bookmarks are re-linked to a book whose title
+/// matches ignoring case and surrounding whitespace, or deleted if there is
+/// no unambiguous match; orphaned collection links and links between
+/// highlights are deleted and vocabulary words keep their text but lose the
+/// dangling book reference. Repair is skipped when the integrity check itself
+/// fails.
+#[tauri::command]
+pub async fn check_database(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    repair: bool,
+) -> Result<DatabaseCheckReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let mut stmt = conn
+            .prepare("PRAGMA integrity_check")
+            .map_err(|e| e.to_string())?;
+        let integrity = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        drop(stmt);
+
+        let orphans = count_orphans(&conn).map_err(|e| e.to_string())?;
+        let mut report = DatabaseCheckReport {
+            integrity,
+            orphans,
+            repaired: false,
+            relinked: 0,
+            deleted: 0,
+        };
+        if !repair || report.integrity != ["ok"] {
+            return Ok(report);
+        }
+
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for (table, filter) in [
+            ("highlights", ORPHAN_HIGHLIGHTS),
+            ("bookmarks", ORPHAN_BOOKMARKS),
+        ] {
+            report.relinked += tx
+                .execute(
+                    &format!(
+                        "UPDATE {table} SET book_title =
+                            (SELECT b.title FROM books b
+                             WHERE trim(b.title) = trim({table}.book_title) COLLATE fold)
+                         WHERE ({filter})
+                           AND (SELECT COUNT(*) FROM books b
+                                WHERE trim(b.title) = trim({table}.book_title) COLLATE fold) = 1"
+                    ),
+                    [],
+                )
+                .map_err(|e| e.to_string())?;
+            report.deleted += tx
+                .execute(&format!("DELETE FROM {table} WHERE {filter}"), [])
+                .map_err(|e| e.to_string())?;
+        }
+        // Links are cleaned up after highlights so links to highlights that
+        // were just deleted go too.
+        for (table, filter) in [
+            ("highlight_collections", ORPHAN_HIGHLIGHT_LINKS),
+            ("book_collections", ORPHAN_BOOK_LINKS),
+            ("highlight_links", ORPHAN_HIGHLIGHT_TO_HIGHLIGHT_LINKS),
+        ] {
+            report.deleted += tx
+                .execute(&format!("DELETE FROM {table} WHERE {filter}"), [])
+                .map_err(|e| e.to_string())?;
+        }
+        tx.execute(
+            &format!(
+                "UPDATE vocabulary SET book_title = NULL, cfi = NULL WHERE {ORPHAN_VOCABULARY}"
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+
+        report.repaired = true;
+        if report.relinked + report.deleted > 0 {
+            events::emit(&app, DataEvent::LibraryReloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Cross-checks the books folder against the database: finds files no book
+/// refers to (left behind by failed imports or edits to the database) and
+/// books whose file is missing. With `remove`, the orphaned files are
+/// deleted; books with missing files are only reported.
+#[tauri::command]
+pub async fn clean_library(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    remove: bool,
+) -> Result<LibraryCleanReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let books_dir = books_dir(&app, &conn)?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {BOOK_COLUMNS} FROM books b ORDER BY b.title"
+            ))
+            .map_err(|e| e.to_string())?;
+        let books = stmt
+            .query_map([], book_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        let known: std::collections::HashSet<&str> =
+            books.iter().map(|b| b.filename.as_str()).collect();
+        let mut report = LibraryCleanReport {
+            orphaned_files: Vec::new(),
+            orphaned_bytes: 0,
+            removed: false,
+            missing_files: Vec::new(),
+        };
+        // A user-chosen library folder may hold unrelated files, so only
+        // files the app could have written count as orphans.
+        if books_dir.is_dir() {
+            for entry in std::fs::read_dir(&books_dir).map_err(|e| e.to_string())? {
+                let entry = entry.map_err(|e| e.to_string())?;
+                let metadata = entry.metadata().map_err(|e| e.to_string())?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let extension = std::path::Path::new(&name)
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                let ours = extension == "tmp" || OPENABLE_EXTENSIONS.contains(&extension.as_str());
+                if metadata.is_file() && ours && !known.contains(name.as_str()) {
+                    report.orphaned_bytes += metadata.len();
+                    report.orphaned_files.push(name);
+                }
+            }
+        }
+        report.orphaned_files.sort();
+
+        report.missing_files = books
+            .iter()
+            .filter(|b| !b.archived && !books_dir.join(&b.filename).is_file())
+            .cloned()
+            .collect();
+
+        if remove {
+            for name in &report.orphaned_files {
+                std::fs::remove_file(books_dir.join(name)).map_err(|e| format!("{name}: {e}"))?;
+            }
+            report.removed = true;
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Checks that every book's file is present and opens as its format. Books
+/// with a problem can be fixed with `relink_book_file`.
+#[tauri::command]
+pub async fn verify_library(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<BookFileStatus>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let books_dir = books_dir(&app, &conn)?;
+        let mut stmt = conn
+            .prepare("SELECT id, title, filename, format, archived FROM books ORDER BY title")
+            .map_err(|e| e.to_string())?;
+        let books = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, bool>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(books
+            .into_iter()
+            .map(|(book_id, title, filename, format, archived)| {
+                let path = books_dir.join(&filename);
+                let status = if archived {
+                    FileStatus::Archived
+                } else if !path.is_file() {
+                    FileStatus::Missing
+                } else {
+                    let opened = match format.as_str() {
+                        "pdf" => storage::read(&path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|data| {
+                                lopdf::Document::load_metadata_mem(&data)
+                                    .map(|_| ())
+                                    .map_err(|e| e.to_string())
+                            }),
+                        format if is_epub(format) => epub::Epub::open(&path).map(|_| ()),
+                        // MOBI files that couldn't be converted are stored
+                        // as-is and only read by the frontend.
+                        _ => Ok(()),
+                    };
+                    match opened {
+                        Ok(()) => FileStatus::Ok,
+                        Err(error) => FileStatus::Unreadable { error },
+                    }
+                };
+                BookFileStatus {
+                    book_id,
+                    title,
+                    filename,
+                    status,
+                }
+            })
+            .collect())
+    })
+    .await
+}
+
+/// Re-hashes every book file and compares it with the hash recorded at
+/// import, to catch files that were corrupted or changed outside the app.
+#[tauri::command]
+pub async fn verify_book_files(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<BookIntegrity>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let books_dir = books_dir(&app, &conn)?;
+        let books = conn
+            .prepare("SELECT id, title, filename, archived, content_hash FROM books ORDER BY title")
+            .map_err(|e| e.to_string())?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        let mut report = Vec::new();
+        for (book_id, title, filename, archived, expected) in books {
+            let path = books_dir.join(&filename);
+            let status = if archived {
+                IntegrityStatus::Archived
+            } else if !path.is_file() {
+                IntegrityStatus::Missing
+            } else {
+                let actual = integrity::hash_file(&path).map_err(|e| e.to_string())?;
+                match expected {
+                    Some(expected) if expected == actual => IntegrityStatus::Ok,
+                    Some(expected) => IntegrityStatus::Modified { expected, actual },
+                    None => {
+                        conn.execute(
+                            "UPDATE books SET content_hash = ?1 WHERE id = ?2",
+                            params![actual, book_id],
+                        )
+                        .map_err(|e| e.to_string())?;
+                        IntegrityStatus::Recorded
+                    }
+                }
+            };
+            report.push(BookIntegrity {
+                book_id,
+                title,
+                filename,
+                status,
+            });
+        }
+        Ok(report)
+    })
+    .await
+}
@@ -0,0 +1,489 @@
+//! Highlight export and import, sending highlights to other apps, and
+//! mailing books to reading devices.
+
+use super::{books_dir, run_blocking};
+use crate::db::DbState;
+use crate::email::{self, Delivery, EmailStatus};
+use crate::events::DataEvent;
+use crate::models::{ExportScope, SiteOptions};
+use crate::{
+    automation, blog, digest, drawings, events, export, hypothesis, markdown, merge, notion,
+    obsidian, quote_image, readwise, secrets, site, templates,
+};
+use rusqlite::params;
+use tauri::Emitter;
+
+#[tauri::command]
+pub async fn export_highlights_json(
+    state: tauri::State<'_, DbState>,
+    scope: ExportScope,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let document = export::highlights_document(&conn, &scope)?;
+        serde_json::to_string_pretty(&document).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn import_highlights_json(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    json: String,
+) -> Result<merge::MergeReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let report = export::import_document(&mut conn, &json)?;
+        events::emit(&app, DataEvent::HighlightsImported(report.clone()));
+        Ok(report)
+    })
+    .await
+}
+
+/// Writes a static website of the highlights chosen by `options` into the
+/// folder at `path`: an index of books and a page per book; see [`site`].
+#[tauri::command]
+pub async fn publish_highlights_site(
+    state: tauri::State<'_, DbState>,
+    path: String,
+    options: SiteOptions,
+) -> Result<site::SiteReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let report = site::publish(&conn, &options, std::path::Path::new(&path))?;
+        log::info!(
+            "Published {} highlights from {} books to {path}",
+            report.highlights,
+            report.books
+        );
+        Ok(report)
+    })
+    .await
+}
+
+/// Writes a collection's highlights, grouped by book with notes and dates, to
+/// `path` as Markdown or a standalone HTML page. Returns how many highlights
+/// were written.
+#[tauri::command]
+pub async fn export_collection(
+    state: tauri::State<'_, DbState>,
+    collection_id: i64,
+    format: export::ExportFormat,
+    path: String,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let scope = ExportScope::Collection { collection_id };
+        let title = export::scope_title(&conn, &scope).map_err(|e| e.to_string())?;
+        let items = export::items(&conn, &scope).map_err(|e| e.to_string())?;
+        std::fs::write(&path, export::render(format, &title, &items)).map_err(|e| e.to_string())?;
+        Ok(items.len())
+    })
+    .await
+}
+
+/// The Obsidian note template in use: the user's, or the default.
+#[tauri::command]
+pub fn get_obsidian_template(state: tauri::State<DbState>) -> Result<String, String> {
+    let conn = state.conn()?;
+    obsidian::template(&conn).map_err(|e| e.to_string())
+}
+
+/// Stores a custom Obsidian note template after checking that it compiles. An
+/// empty template restores the default.
+#[tauri::command]
+pub fn set_obsidian_template(state: tauri::State<DbState>, template: String) -> Result<(), String> {
+    let conn = state.conn()?;
+    if template.trim().is_empty() {
+        conn.execute(
+            "DELETE FROM settings WHERE key = ?1",
+            params![obsidian::TEMPLATE_SETTING],
+        )
+        .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    obsidian::compile(&template)?;
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![obsidian::TEMPLATE_SETTING, template],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Writes one note per book into the Obsidian vault folder `vault_path`. With
+/// `incremental`, only books that changed since the last export are written.
+#[tauri::command]
+pub async fn export_to_obsidian(
+    state: tauri::State<'_, DbState>,
+    vault_path: String,
+    incremental: Option<bool>,
+) -> Result<obsidian::ObsidianExportReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        obsidian::export(
+            &conn,
+            std::path::Path::new(&vault_path),
+            incremental.unwrap_or(false),
+        )
+    })
+    .await
+}
+
+/// The built-in export templates and the user's own.
+#[tauri::command]
+pub fn get_export_templates(
+    state: tauri::State<DbState>,
+) -> Result<Vec<templates::ExportTemplate>, String> {
+    templates::list(&templates::templates_dir(&state.dir()?))
+}
+
+/// Stores an export template after checking that it compiles. Naming it after
+/// a built-in template replaces that one.
+#[tauri::command]
+pub fn save_export_template(
+    state: tauri::State<DbState>,
+    name: String,
+    content: String,
+) -> Result<templates::ExportTemplate, String> {
+    templates::save(&templates::templates_dir(&state.dir()?), &name, &content)
+}
+
+/// Deletes the user's template, or restores a built-in one.
+#[tauri::command]
+pub fn delete_export_template(state: tauri::State<DbState>, name: String) -> Result<(), String> {
+    templates::delete(&templates::templates_dir(&state.dir()?), &name)
+}
+
+/// Renders unsaved template text for one book, so it can be checked while
+/// it's edited. `format` is the extension it would be saved with (`md` by
+/// default); `html` escapes values.
+#[tauri::command]
+pub async fn preview_export_template(
+    state: tauri::State<'_, DbState>,
+    template: String,
+    book_id: i64,
+    format: Option<String>,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let name = format!("preview.{}", format.as_deref().unwrap_or("md"));
+        templates::validate_name(&name)?;
+        let conn = state.conn()?;
+        templates::render(&conn, &name, &template, book_id)
+    })
+    .await
+}
+
+/// Renders the stored template `name` for the book and writes it to `path`.
+#[tauri::command]
+pub async fn export_with_template(
+    state: tauri::State<'_, DbState>,
+    name: String,
+    book_id: i64,
+    path: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let template = templates::load(&templates::templates_dir(&state.dir()?), &name)?;
+        let conn = state.conn()?;
+        let output = templates::render(&conn, &template.name, &template.content, book_id)?;
+        std::fs::write(&path, output).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Writes one Markdown file with YAML frontmatter per book into `dir`, for
+/// Joplin, Logseq and other Markdown apps. `existing` decides what happens to
+/// files already there; they're overwritten by default.
+#[tauri::command]
+pub async fn export_markdown(
+    state: tauri::State<'_, DbState>,
+    dir: String,
+    existing: Option<markdown::ExistingFiles>,
+) -> Result<markdown::MarkdownExportReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        markdown::export(
+            &conn,
+            std::path::Path::new(&dir),
+            existing.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Writes blog posts for Hugo or Jekyll into `dir`, one per book or per
+/// collection, with `title`, `date` and `tags` frontmatter. Returns how many
+/// were written.
+#[tauri::command]
+pub async fn export_blog_posts(
+    state: tauri::State<'_, DbState>,
+    dir: String,
+    generator: blog::BlogGenerator,
+    per: Option<blog::BlogPostPer>,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        blog::export(
+            &conn,
+            std::path::Path::new(&dir),
+            generator,
+            per.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Writes the book's drawings into `dir` as SVG files. Returns how many were
+/// written.
+#[tauri::command]
+pub async fn export_drawings(
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    dir: String,
+) -> Result<usize, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        drawings::export(&conn, book_id, std::path::Path::new(&dir))
+    })
+    .await
+}
+
+/// Renders a highlight and its book's title onto a square PNG for sharing.
+/// Returns the image bytes.
+#[tauri::command]
+pub async fn render_quote_image(
+    state: tauri::State<'_, DbState>,
+    highlight_id: i64,
+    style: Option<quote_image::QuoteStyle>,
+) -> Result<Vec<u8>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let (text, book_title, author, color): (String, String, Option<String>, String) = conn
+            .query_row(
+                "SELECT h.text, h.book_title, b.author, h.color FROM highlights h
+                 LEFT JOIN books b ON b.title = h.book_title
+                 WHERE h.id = ?1",
+                params![highlight_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        quote_image::render(
+            &text,
+            &book_title,
+            author.as_deref(),
+            &color,
+            &style.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Writes every book's highlights to its page in the configured Notion
+/// database (see [`notion`] for the keychain settings).
+#[tauri::command]
+pub async fn sync_to_notion(
+    state: tauri::State<'_, DbState>,
+) -> Result<notion::NotionSyncReport, String> {
+    let db = state.inner().clone();
+    let (token, database_id) = run_blocking(|| {
+        let token = secrets::get("notion", "token")?
+            .filter(|t| !t.trim().is_empty())
+            .ok_or("No Notion token configured")?;
+        let database_id = secrets::get("notion", "database_id")?
+            .map(|id| id.trim().replace('-', ""))
+            .filter(|id| !id.is_empty())
+            .ok_or("No Notion database configured")?;
+        Ok((token, database_id))
+    })
+    .await?;
+    notion::sync(db, token, database_id).await
+}
+
+/// Pushes new and edited highlights to Readwise and emits the result as a
+/// `readwise://synced` event.
+#[tauri::command]
+pub async fn sync_readwise(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<readwise::ReadwiseSyncReport, String> {
+    let db = state.inner().clone();
+    let token = run_blocking(|| {
+        secrets::get("readwise", "token")?
+            .filter(|t| !t.trim().is_empty())
+            .ok_or_else(|| "No Readwise token configured".to_string())
+    })
+    .await?;
+    let report = readwise::sync(db, token).await?;
+    if let Err(e) = app.emit("readwise://synced", report.clone()) {
+        log::warn!("Failed to emit Readwise sync result: {e}");
+    }
+    Ok(report)
+}
+
+/// Creates, updates and deletes Hypothes.is annotations to match the
+/// highlights, and emits the result as a `hypothesis://synced` event.
+#[tauri::command]
+pub async fn sync_to_hypothesis(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<hypothesis::HypothesisSyncReport, String> {
+    let db = state.inner().clone();
+    let (token, group) = run_blocking(|| {
+        let token = secrets::get("hypothesis", "token")?
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .ok_or("No Hypothes.is token configured")?;
+        let group = secrets::get("hypothesis", "group")?
+            .map(|g| g.trim().to_string())
+            .filter(|g| !g.is_empty());
+        Ok((token, group))
+    })
+    .await?;
+    let report = hypothesis::sync(db, token, group).await?;
+    if let Err(e) = app.emit("hypothesis://synced", report.clone()) {
+        log::warn!("Failed to emit Hypothes.is sync result: {e}");
+    }
+    Ok(report)
+}
+
+/// Each automatic export's schedule, folder and last run.
+#[tauri::command]
+pub async fn get_automation_status(
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<automation::AutomationStatus>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        automation::statuses(&conn)
+    })
+    .await
+}
+
+/// Sets when an automatic export runs, and the folder it exports to.
+#[tauri::command]
+pub async fn set_automation(
+    state: tauri::State<'_, DbState>,
+    task: automation::AutomationTask,
+    schedule: automation::Schedule,
+    path: Option<String>,
+) -> Result<automation::AutomationStatus, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        automation::configure(&conn, task, schedule, path)
+    })
+    .await
+}
+
+/// Runs an automatic export now, whatever its schedule. Its status, failed
+/// or not, is returned and emitted as an `automation://ran` event.
+#[tauri::command]
+pub async fn run_automation(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    task: automation::AutomationTask,
+) -> Result<automation::AutomationStatus, String> {
+    let status = automation::run(state.inner(), task).await?;
+    if let Err(e) = app.emit("automation://ran", status.clone()) {
+        log::warn!("Failed to emit automation status: {e}");
+    }
+    Ok(status)
+}
+
+/// Mails the book's file to the device address set up in [`email`], turning
+/// MOBI files into EPUB unless `convert` is `false`. Progress arrives as
+/// `email://status` events: `sending`, then `sent` or `failed`.
+#[tauri::command]
+pub async fn send_book_via_email(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    convert: Option<bool>,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    let handle = app.clone();
+    let (config, attachment) = run_blocking(move || {
+        let config = email::load_config()?;
+        let conn = state.conn()?;
+        let books_dir = books_dir(&handle, &conn)?;
+        let attachment = email::attachment(&conn, &books_dir, book_id, convert.unwrap_or(true))?;
+        Ok((config, attachment))
+    })
+    .await?;
+
+    let title = attachment.book.title.clone();
+    let emit = |status: Delivery, error: Option<String>| {
+        let event = EmailStatus {
+            book_id,
+            title: title.clone(),
+            status,
+            error,
+        };
+        if let Err(e) = app.emit("email://status", event) {
+            log::warn!("Failed to emit email://status: {e}");
+        }
+    };
+    emit(Delivery::Sending, None);
+    match email::send(&config, attachment).await {
+        Ok(()) => {
+            log::info!("Mailed {title}");
+            emit(Delivery::Sent, None);
+            Ok(())
+        }
+        Err(e) => {
+            log::warn!("Could not mail {title}: {e}");
+            emit(Delivery::Failed, Some(e.clone()));
+            Err(e)
+        }
+    }
+}
+
+/// The digest of the past `period` (a week by default): new highlights and
+/// reading stats as an HTML email body. With `send`, it's also mailed to the
+/// digest address set up in [`email`].
+#[tauri::command]
+pub async fn generate_digest(
+    state: tauri::State<'_, DbState>,
+    period: Option<digest::DigestPeriod>,
+    send: Option<bool>,
+) -> Result<digest::Digest, String> {
+    let state = state.inner().clone();
+    let send = send.unwrap_or(false);
+    let (mut digest, config) = run_blocking(move || {
+        let config = if send {
+            Some(email::load_config()?)
+        } else {
+            None
+        };
+        let conn = state.conn()?;
+        let digest =
+            digest::generate(&conn, period.unwrap_or_default()).map_err(|e| e.to_string())?;
+        Ok((digest, config))
+    })
+    .await?;
+    if let Some(config) = config {
+        email::send_html(
+            &config,
+            &digest.subject,
+            digest.plain.clone(),
+            digest.html.clone(),
+        )
+        .await?;
+        log::info!("Mailed the reading digest");
+        digest.sent = true;
+    }
+    Ok(digest)
+}
@@ -0,0 +1,279 @@
+//! Feed subscriptions and the read-it-later inbox of articles saved from
+//! them.
+
+use super::{books_dir, run_blocking};
+use crate::db::{self, DbState};
+use crate::events::{self, DataEvent};
+use crate::feeds::ParsedEntry;
+use crate::models::{Feed, FeedRefreshReport, InboxEntry};
+use crate::{article, feeds};
+use reqwest::Url;
+use std::sync::Arc;
+use tauri::Manager;
+
+/// Held while feeds refresh, so the scheduled refresh and one asked for
+/// don't save the same entries twice.
+#[derive(Clone, Default)]
+pub struct FeedRefresh(Arc<tokio::sync::Mutex<()>>);
+
+/// Subscribes to the feed at `url`, or the one the web page at `url` links
+/// to. Its newest entries are saved to the inbox in the background.
+#[tauri::command]
+pub async fn subscribe_feed(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    url: String,
+) -> Result<Feed, String> {
+    let state = state.inner().clone();
+    let client = article::http_client()?;
+    let (parsed, feed_url) = feeds::fetch(&client, article::parse_url(&url)?).await?;
+    let feed = run_blocking(move || {
+        let conn = state.conn()?;
+        if let Some(feed) =
+            db::feeds::by_url(&conn, feed_url.as_str()).map_err(|e| e.to_string())?
+        {
+            return Ok(feed);
+        }
+        let title = match parsed.title.as_str() {
+            "" => feed_url.host_str().unwrap_or(feed_url.as_str()).to_string(),
+            title => title.to_string(),
+        };
+        db::feeds::subscribe(&conn, feed_url.as_str(), &title, parsed.site_url.as_deref())
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+    events::emit(&app, DataEvent::FeedUpdated(feed.clone()));
+
+    let handle = app.clone();
+    let subscribed = feed.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = refresh(&handle, vec![subscribed]).await {
+            log::error!("Feed refresh failed: {e}");
+        }
+    });
+    Ok(feed)
+}
+
+/// Stops following the feed. Articles already saved from it stay in the
+/// library.
+#[tauri::command]
+pub fn unsubscribe_feed(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let mut conn = state.conn()?;
+    db::feeds::delete(&mut conn, id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::FeedDeleted(events::RecordId { id }));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_feeds(state: tauri::State<DbState>) -> Result<Vec<Feed>, String> {
+    let conn = state.conn()?;
+    db::feeds::all(&conn).map_err(|e| e.to_string())
+}
+
+/// Checks every feed for new entries now, rather than waiting for the
+/// scheduled refresh.
+#[tauri::command]
+pub async fn refresh_feeds(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<FeedRefreshReport, String> {
+    let state = state.inner().clone();
+    let all = run_blocking(move || {
+        let conn = state.conn()?;
+        db::feeds::all(&conn).map_err(|e| e.to_string())
+    })
+    .await?;
+    refresh(&app, all).await
+}
+
+/// Articles saved from feeds, newest first; read ones only with
+/// `include_read`.
+#[tauri::command]
+pub fn get_inbox(
+    state: tauri::State<DbState>,
+    include_read: Option<bool>,
+) -> Result<Vec<InboxEntry>, String> {
+    let conn = state.conn()?;
+    db::feeds::inbox(&conn, include_read.unwrap_or(false)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn mark_inbox_entry_read(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    read: bool,
+) -> Result<InboxEntry, String> {
+    let conn = state.conn()?;
+    let entry = db::feeds::set_read(&conn, id, read)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No inbox entry with id {id}"))?;
+    events::emit(&app, DataEvent::InboxEntryUpdated(entry.clone()));
+    if let Ok(feed) = db::feeds::get(&conn, entry.feed_id) {
+        events::emit(&app, DataEvent::FeedUpdated(feed));
+    }
+    Ok(entry)
+}
+
+/// Refreshes the feeds that are due, for the scheduled refresh.
+pub async fn refresh_due(app: &tauri::AppHandle) -> Result<FeedRefreshReport, String> {
+    let state = app.state::<DbState>().inner().clone();
+    let due = run_blocking(move || {
+        let conn = state.conn()?;
+        db::feeds::due(&conn, feeds::REFRESH_MINUTES).map_err(|e| e.to_string())
+    })
+    .await?;
+    if due.is_empty() {
+        return Ok(FeedRefreshReport::default());
+    }
+    refresh(app, due).await
+}
+
+/// Fetches each feed and saves its new entries as articles. A feed or entry
+/// that fails is recorded and skipped; entries are retried on later
+/// refreshes up to [`feeds::MAX_ATTEMPTS`] times.
+pub async fn refresh(
+    app: &tauri::AppHandle,
+    to_refresh: Vec<Feed>,
+) -> Result<FeedRefreshReport, String> {
+    let lock = app.state::<FeedRefresh>().inner().clone();
+    let _refreshing = lock.0.lock().await;
+    let state = app.state::<DbState>().inner().clone();
+    let client = article::http_client()?;
+    let mut report = FeedRefreshReport::default();
+    for feed in to_refresh {
+        report.feeds += 1;
+        let fetched = match article::parse_url(&feed.url) {
+            Ok(url) => feeds::fetch(&client, url).await,
+            Err(e) => Err(e),
+        };
+        let (feed_id, db) = (feed.id, state.clone());
+        let (parsed, feed_url, pending) = match fetched {
+            Ok((parsed, feed_url)) => {
+                let entries = parsed.entries.clone();
+                let title = parsed.title.clone();
+                let pending = run_blocking(move || {
+                    let conn = db.conn()?;
+                    db::feeds::record_fetch(&conn, feed_id, Some(&title), None)
+                        .and_then(|_| db::feeds::add_entries(&conn, feed_id, &entries))
+                        .and_then(|_| db::feeds::pending(&conn, feed_id))
+                        .map_err(|e| e.to_string())
+                })
+                .await?;
+                (parsed, feed_url, pending)
+            }
+            Err(e) => {
+                log::warn!("Could not refresh the feed {}: {e}", feed.url);
+                run_blocking(move || {
+                    let conn = db.conn()?;
+                    db::feeds::record_fetch(&conn, feed_id, None, Some(&e))
+                        .map_err(|e| e.to_string())
+                })
+                .await?;
+                emit_feed(app, &state, feed_id).await;
+                continue;
+            }
+        };
+
+        for (entry_id, guid) in pending {
+            // Entries no longer in the feed can't be fetched again.
+            let Some(entry) = parsed.entries.iter().find(|e| e.guid == guid) else {
+                continue;
+            };
+            match save_entry(app, &state, &client, entry_id, entry, &feed_url).await {
+                Ok(()) => report.saved += 1,
+                Err(e) => {
+                    log::warn!("Could not save {:?} from {}: {e}", entry.title, feed.url);
+                    report.failed += 1;
+                    let db = state.clone();
+                    run_blocking(move || {
+                        let conn = db.conn()?;
+                        db::feeds::entry_failed(&conn, entry_id, &e).map_err(|e| e.to_string())
+                    })
+                    .await?;
+                }
+            }
+        }
+        emit_feed(app, &state, feed_id).await;
+    }
+    Ok(report)
+}
+
+async fn emit_feed(app: &tauri::AppHandle, state: &DbState, feed_id: i64) {
+    let state = state.clone();
+    let feed = run_blocking(move || {
+        let conn = state.conn()?;
+        db::feeds::get(&conn, feed_id).map_err(|e| e.to_string())
+    })
+    .await;
+    match feed {
+        Ok(feed) => events::emit(app, DataEvent::FeedUpdated(feed)),
+        Err(e) => log::warn!("Could not reload feed {feed_id}: {e}"),
+    }
+}
+
+/// Saves the entry as an article, or links it to the book already saved from
+/// the same page.
+async fn save_entry(
+    app: &tauri::AppHandle,
+    state: &DbState,
+    client: &reqwest::Client,
+    entry_id: i64,
+    entry: &ParsedEntry,
+    feed_url: &Url,
+) -> Result<(), String> {
+    let link = entry
+        .url
+        .as_deref()
+        .and_then(|url| feed_url.join(url).ok())
+        .map(String::from);
+    let db = state.clone();
+    let existing = run_blocking(move || {
+        let conn = db.conn()?;
+        let book = match link {
+            Some(link) => article::saved(&conn, &link).map_err(|e| e.to_string())?,
+            None => None,
+        };
+        if let Some(book) = &book {
+            db::feeds::entry_saved(&conn, entry_id, book.id).map_err(|e| e.to_string())?;
+        }
+        Ok(book)
+    })
+    .await?;
+
+    if existing.is_none() {
+        let (page, images) = feeds::entry_article(client, entry, feed_url).await?;
+        let (db, handle) = (state.clone(), app.clone());
+        let added = run_blocking(move || {
+            let conn = db.conn()?;
+            let (book, added) = match article::saved(&conn, &page.url).map_err(|e| e.to_string())? {
+                Some(book) => (book, false),
+                None => {
+                    let books_dir = books_dir(&handle, &conn)?;
+                    (article::save(&conn, &books_dir, page, images)?, true)
+                }
+            };
+            db::feeds::entry_saved(&conn, entry_id, book.id).map_err(|e| e.to_string())?;
+            Ok(added.then_some(book))
+        })
+        .await?;
+        if let Some(book) = added {
+            events::emit(app, DataEvent::BookAdded(book));
+        }
+    }
+
+    let db = state.clone();
+    let entry = run_blocking(move || {
+        let conn = db.conn()?;
+        db::feeds::inbox_entry(&conn, entry_id).map_err(|e| e.to_string())
+    })
+    .await?;
+    if let Some(entry) = entry {
+        events::emit(app, DataEvent::InboxEntryUpdated(entry));
+    }
+    Ok(())
+}
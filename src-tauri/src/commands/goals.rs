@@ -0,0 +1,430 @@
+//! Reading goals, sessions and statistics.
+
+use super::{books_dir, run_blocking};
+use crate::commands::books::emit_book_updated;
+use crate::db::{book_from_row, streaks, DbState, BOOK_COLUMNS};
+use crate::events::DataEvent;
+use crate::models::{
+    BooksGoalProgress, GoalKind, GoalProgress, MinutesGoalProgress, ReadingHeatmap, ReadingSession,
+};
+use crate::reminders::{self, ReminderKind, ReminderSchedule};
+use crate::{events, reading_time, stats, year_review};
+use rusqlite::{params, OptionalExtension};
+use tauri_plugin_notification::{NotificationExt, PermissionState};
+
+#[tauri::command]
+pub fn set_book_finished(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    title: String,
+    finished: bool,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    conn.execute(
+        "UPDATE books SET finished_at = CASE WHEN ?1 THEN COALESCE(finished_at, datetime('now'))
+                                           ELSE NULL END
+         WHERE title = ?2",
+        params![finished, title],
+    )
+    .map_err(|e| e.to_string())?;
+    emit_book_updated(&app, &conn, &title)
+}
+
+/// Records a finished reading session. The session ends at the book's saved
+/// position; `start_percentage` is where it began, and without it the
+/// previous session's end is assumed.
+#[tauri::command]
+pub fn log_reading_session(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_title: String,
+    seconds: i64,
+    pages: Option<i64>,
+    start_percentage: Option<f64>,
+) -> Result<ReadingSession, String> {
+    if seconds <= 0 {
+        return Err("Reading session must last at least one second".to_string());
+    }
+    let conn = state.conn()?;
+    let book: Option<(Option<i64>, f64)> = conn
+        .query_row(
+            "SELECT word_count, last_percentage FROM books WHERE title = ?1",
+            params![book_title],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let start_percentage = match start_percentage {
+        Some(start) => Some(start),
+        None => conn
+            .query_row(
+                "SELECT end_percentage FROM reading_sessions WHERE book_title = ?1
+                 ORDER BY ended_at DESC, id DESC LIMIT 1",
+                params![book_title],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .flatten(),
+    };
+    let end_percentage = book.map(|(_, percentage)| percentage);
+    let (words, wpm) = match (book, start_percentage, end_percentage) {
+        (Some((Some(word_count), _)), Some(start), Some(end)) => {
+            reading_time::session_speed(word_count, start, end, seconds)
+        }
+        _ => (None, None),
+    };
+
+    conn.execute(
+        "INSERT INTO reading_sessions
+            (book_title, started_at, seconds, pages, start_percentage, end_percentage, words, wpm)
+         VALUES (?1, datetime('now', ?2), ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            book_title,
+            format!("-{seconds} seconds"),
+            seconds,
+            pages.unwrap_or(0).max(0),
+            start_percentage,
+            end_percentage,
+            words,
+            wpm
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let id = conn.last_insert_rowid();
+    let session = conn
+        .query_row(
+            "SELECT id, book_title, started_at, ended_at, seconds, pages,
+                    start_percentage, end_percentage, wpm
+             FROM reading_sessions WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(ReadingSession {
+                    id: row.get(0)?,
+                    book_title: row.get(1)?,
+                    started_at: row.get(2)?,
+                    ended_at: row.get(3)?,
+                    seconds: row.get(4)?,
+                    pages: row.get(5)?,
+                    start_percentage: row.get(6)?,
+                    end_percentage: row.get(7)?,
+                    wpm: row.get(8)?,
+                })
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::ReadingSessionLogged(session.clone()));
+
+    // A session logged after the fact may have started after the book was
+    // last opened, e.g. when the reader stayed open.
+    let reopened = conn
+        .execute(
+            "UPDATE books SET last_opened_at = ?2
+             WHERE title = ?1 AND (last_opened_at IS NULL OR last_opened_at < ?2)",
+            params![book_title, session.started_at],
+        )
+        .map_err(|e| e.to_string())?;
+    if reopened > 0 {
+        emit_book_updated(&app, &conn, &book_title)?;
+    }
+    Ok(session)
+}
+
+/// Sets a goal's target. A missing or non-positive target removes the goal.
+#[tauri::command]
+pub fn set_goal(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    kind: GoalKind,
+    target: Option<i64>,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let target = target.filter(|t| *t > 0);
+    match target {
+        Some(target) => conn.execute(
+            "INSERT INTO reading_goals (kind, target) VALUES (?1, ?2)
+             ON CONFLICT(kind) DO UPDATE SET target = excluded.target, updated_at = datetime('now')",
+            params![kind.as_str(), target],
+        ),
+        None => conn.execute(
+            "DELETE FROM reading_goals WHERE kind = ?1",
+            params![kind.as_str()],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    events::emit(
+        &app,
+        DataEvent::GoalUpdated(events::GoalUpdate { kind, target }),
+    );
+    Ok(())
+}
+
+/// When the reading and review reminders go out.
+#[tauri::command]
+pub fn get_reminders(state: tauri::State<DbState>) -> Result<Vec<ReminderSchedule>, String> {
+    let conn = state.conn()?;
+    reminders::schedules(&conn)
+}
+
+/// Sends the `kind` reminder daily at `time` (`HH:MM`, local), asking for
+/// permission to show notifications first if needed.
+#[tauri::command]
+pub fn set_reminder(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    kind: ReminderKind,
+    time: String,
+) -> Result<ReminderSchedule, String> {
+    let notification = app.notification();
+    let mut permission = notification.permission_state().map_err(|e| e.to_string())?;
+    if permission != PermissionState::Granted {
+        permission = notification
+            .request_permission()
+            .map_err(|e| e.to_string())?;
+    }
+    if permission != PermissionState::Granted {
+        return Err("Notifications are turned off for the app".to_string());
+    }
+    let conn = state.conn()?;
+    reminders::set(&conn, kind, &time)
+}
+
+#[tauri::command]
+pub fn clear_reminder(
+    state: tauri::State<DbState>,
+    kind: ReminderKind,
+) -> Result<ReminderSchedule, String> {
+    let conn = state.conn()?;
+    reminders::clear(&conn, kind)
+}
+
+#[tauri::command]
+pub fn get_goal_progress(state: tauri::State<DbState>) -> Result<GoalProgress, String> {
+    let conn = state.conn()?;
+    let target = |kind: GoalKind| {
+        conn.query_row(
+            "SELECT target FROM reading_goals WHERE kind = ?1",
+            params![kind.as_str()],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+    };
+
+    // Days are bucketed in local time so a late-night session counts towards
+    // the day the user thinks it belongs to.
+    let books_per_year = match target(GoalKind::BooksPerYear)? {
+        Some(target) => {
+            let (year, finished) = conn
+                .query_row(
+                    "SELECT CAST(strftime('%Y', 'now', 'localtime') AS INTEGER),
+                            (SELECT COUNT(*) FROM books
+                             WHERE strftime('%Y', finished_at, 'localtime')
+                                 = strftime('%Y', 'now', 'localtime'))",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(|e| e.to_string())?;
+            Some(BooksGoalProgress {
+                year,
+                target,
+                finished,
+            })
+        }
+        None => None,
+    };
+
+    let minutes_target = target(GoalKind::MinutesPerDay)?;
+    let minutes_per_day = match minutes_target {
+        Some(target) => {
+            let today_minutes = conn
+                .query_row(
+                    "SELECT COALESCE(SUM(seconds), 0) / 60.0 FROM reading_sessions
+                     WHERE date(ended_at, 'localtime') = date('now', 'localtime')",
+                    [],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            Some(MinutesGoalProgress {
+                target,
+                today_minutes,
+            })
+        }
+        None => None,
+    };
+
+    let today: i64 = conn
+        .query_row(
+            "SELECT CAST(julianday(date('now', 'localtime')) AS INTEGER)",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT CAST(julianday(date(ended_at, 'localtime')) AS INTEGER) AS day
+             FROM reading_sessions
+             GROUP BY day
+             HAVING SUM(seconds) >= ?1
+             ORDER BY day DESC",
+        )
+        .map_err(|e| e.to_string())?;
+    let days = stmt
+        .query_map(params![minutes_target.unwrap_or(0) * 60], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let (current_streak, longest_streak) = streaks(&days, today);
+
+    Ok(GoalProgress {
+        books_per_year,
+        minutes_per_day,
+        current_streak,
+        longest_streak,
+    })
+}
+
+#[tauri::command]
+pub fn get_reading_heatmap(
+    state: tauri::State<DbState>,
+    year: i32,
+) -> Result<ReadingHeatmap, String> {
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = if leap { 366 } else { 365 };
+    let mut heatmap = ReadingHeatmap {
+        year,
+        minutes: vec![0; days],
+        pages: vec![0; days],
+    };
+
+    let conn = state.conn()?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT CAST(strftime('%j', ended_at, 'localtime') AS INTEGER) - 1 AS day,
+                    SUM(seconds), SUM(pages)
+             FROM reading_sessions
+             WHERE strftime('%Y', ended_at, 'localtime') = printf('%04d', ?1)
+             GROUP BY day",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![year], |row| {
+            Ok((
+                row.get::<_, usize>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (day, seconds, pages) = row.map_err(|e| e.to_string())?;
+        if day < days {
+            heatmap.minutes[day] = ((seconds + 30) / 60) as u32;
+            heatmap.pages[day] = pages as u32;
+        }
+    }
+    Ok(heatmap)
+}
+
+/// Estimated time left in a book at the reader's measured speed. Books
+/// imported before word counts existed are counted now.
+#[tauri::command]
+pub async fn get_time_remaining(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<reading_time::TimeRemaining, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let book = conn
+            .query_row(
+                &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+                params![book_id],
+                book_from_row,
+            )
+            .map_err(|e| e.to_string())?;
+        let word_count = match book.word_count {
+            Some(words) => words,
+            None => {
+                if book.archived {
+                    return Err(format!("{} is archived", book.title));
+                }
+                let path = books_dir(&app, &conn)?.join(&book.filename);
+                let words = reading_time::book_words(&path, &book.format, book.page_count)?
+                    .ok_or_else(|| format!("Can't count the words of {}", book.title))?;
+                reading_time::store(&conn, book_id, words).map_err(|e| e.to_string())?;
+                emit_book_updated(&app, &conn, &book.title)?;
+                words
+            }
+        };
+        reading_time::time_remaining(&conn, book_id, word_count, book.last_percentage)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Reading speed measured from sessions, in one book or across the library,
+/// with rolling averages for a chart.
+#[tauri::command]
+pub fn get_reading_speed(
+    state: tauri::State<DbState>,
+    book_id: Option<i64>,
+) -> Result<reading_time::ReadingSpeed, String> {
+    let conn = state.conn()?;
+    reading_time::reading_speed(&conn, book_id).map_err(|e| e.to_string())
+}
+
+/// Exports reading sessions, per-day totals and per-book totals in `range`
+/// as CSV files in the folder `path`.
+#[tauri::command]
+pub async fn export_stats_csv(
+    state: tauri::State<'_, DbState>,
+    path: String,
+    range: Option<stats::StatsRange>,
+) -> Result<stats::StatsExport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        stats::export(
+            &conn,
+            std::path::Path::new(&path),
+            &range.unwrap_or_default(),
+        )
+    })
+    .await
+}
+
+/// Books and reading time per book language, with finished books and
+/// sessions counted within `range`.
+#[tauri::command]
+pub fn get_language_stats(
+    state: tauri::State<DbState>,
+    range: Option<stats::StatsRange>,
+) -> Result<Vec<stats::LanguageStats>, String> {
+    let conn = state.conn()?;
+    stats::languages(&conn, &range.unwrap_or_default())
+}
+
+/// A summary of `year`'s reading for a year-in-review screen, optionally
+/// with a Markdown rendering of it.
+#[tauri::command]
+pub async fn generate_year_review(
+    state: tauri::State<'_, DbState>,
+    year: i32,
+    markdown: Option<bool>,
+) -> Result<year_review::YearReview, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let mut review = year_review::compute(&conn, year).map_err(|e| e.to_string())?;
+        if markdown.unwrap_or(false) {
+            review.markdown = Some(year_review::markdown(&review));
+        }
+        Ok(review)
+    })
+    .await
+}
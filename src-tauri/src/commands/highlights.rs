@@ -0,0 +1,565 @@
+//! Highlights and their notes, merging, bulk actions and highlight colors.
+
+use super::{books_dir, run_blocking};
+use crate::attachments::{self, AttachmentSource};
+use crate::db::{self, DbState};
+use crate::events::DataEvent;
+use crate::models::{
+    Attachment, AttachmentKind, ChapterHighlights, Highlight, HighlightColor, HighlightLink,
+    HighlightStyle, LinkedHighlight, NewRegionAnnotation, NoteRevision,
+};
+use crate::{events, undo};
+use rusqlite::Connection;
+
+// The frontend passes each field as its own argument.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub fn add_highlight(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    book_title: String,
+    cfi: String,
+    text: String,
+    color: String,
+    style: Option<HighlightStyle>,
+    notes: String,
+) -> Result<Highlight, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    // A double tap highlights the same text twice; the second gets the first.
+    let hl = db::highlights::add(
+        &conn,
+        &book_title,
+        &cfi,
+        &text,
+        &color,
+        style.unwrap_or_default(),
+        &notes,
+    )
+    .map_err(|e| e.to_string())?;
+    undo::record(&conn, "Add highlight", since)?;
+    events::emit(&app, DataEvent::HighlightAdded(hl.clone()));
+    Ok(hl)
+}
+
+/// Annotates an image or a region of a fixed-layout page. It's listed with
+/// the book's highlights.
+#[tauri::command]
+pub fn add_region_annotation(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    annotation: NewRegionAnnotation,
+) -> Result<Highlight, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let hl = db::highlights::add_region(&conn, &annotation)?;
+    undo::record(&conn, "Add annotation", since)?;
+    events::emit(&app, DataEvent::HighlightAdded(hl.clone()));
+    Ok(hl)
+}
+
+/// Changes a highlight's color and/or style, leaving out what isn't given.
+#[tauri::command]
+pub fn update_highlight(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    color: Option<String>,
+    style: Option<HighlightStyle>,
+) -> Result<Highlight, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let hl = db::highlights::update(&conn, id, color.as_deref(), style)
+        .map_err(|e| e.to_string())?
+        .ok_or("Highlight not found")?;
+    undo::record(&conn, "Edit highlight", since)?;
+    events::emit(&app, DataEvent::HighlightUpdated(hl.clone()));
+    Ok(hl)
+}
+
+#[tauri::command]
+pub fn get_highlights(
+    state: tauri::State<DbState>,
+    book_title: String,
+) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::highlights::for_book(&conn, &book_title).map_err(|e| e.to_string())
+}
+
+/// Highlights of `book_title` grouped by chapter, both in reading order.
+#[tauri::command]
+pub async fn get_highlights_grouped_by_chapter(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_title: String,
+) -> Result<Vec<ChapterHighlights>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        db::highlights::by_chapter(&conn, &book_title, &books_dir(&app, &conn)?)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_all_highlights(
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<Highlight>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        db::highlights::all(&conn).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Replaces a highlight's note as one undoable step; see
+/// [`db::highlights::set_notes`].
+fn write_highlight_notes(
+    conn: &Connection,
+    id: i64,
+    notes: &str,
+    label: &str,
+) -> Result<Option<Highlight>, String> {
+    let since = undo::mark(conn)?;
+    let hl = db::highlights::set_notes(conn, id, notes).map_err(|e| e.to_string())?;
+    undo::record(conn, label, since)?;
+    Ok(hl)
+}
+
+#[tauri::command]
+pub fn update_highlight_notes(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    notes: String,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    if let Some(hl) = write_highlight_notes(&conn, id, &notes, "Edit note")? {
+        events::emit(&app, DataEvent::HighlightUpdated(hl));
+    }
+    Ok(())
+}
+
+/// Earlier versions of a highlight's note, newest first.
+#[tauri::command]
+pub fn get_note_history(
+    state: tauri::State<DbState>,
+    highlight_id: i64,
+) -> Result<Vec<NoteRevision>, String> {
+    let conn = state.conn()?;
+    db::highlights::note_history(&conn, highlight_id).map_err(|e| e.to_string())
+}
+
+/// Puts an earlier version of a note back. The text it replaces becomes a
+/// revision itself, so restoring can be reversed the same way.
+#[tauri::command]
+pub fn restore_note_revision(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    revision_id: i64,
+) -> Result<Highlight, String> {
+    let conn = state.conn()?;
+    let revision = db::highlights::note_revision(&conn, revision_id)
+        .map_err(|e| e.to_string())?
+        .ok_or("Note revision not found")?;
+    let hl = write_highlight_notes(
+        &conn,
+        revision.highlight_id,
+        &revision.notes,
+        "Restore note",
+    )?
+    .ok_or("Highlight not found")?;
+    events::emit(&app, DataEvent::HighlightUpdated(hl.clone()));
+    Ok(hl)
+}
+
+#[tauri::command]
+pub fn toggle_favorite_highlight(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<Highlight, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let hl = db::highlights::toggle_favorite(&conn, id).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Favorite highlight", since)?;
+    events::emit(&app, DataEvent::HighlightUpdated(hl.clone()));
+    Ok(hl)
+}
+
+#[tauri::command]
+pub fn get_favorite_highlights(state: tauri::State<DbState>) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::highlights::favorites(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_highlight(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    db::highlights::delete(&conn, id).map_err(|e| e.to_string())?;
+    undo::record(&conn, "Delete highlight", since)?;
+    prune_attachments(&state, &conn);
+    events::emit(&app, DataEvent::HighlightDeleted(events::RecordId { id }));
+    Ok(())
+}
+
+/// Deletes the attachments of deleted highlights that undo can no longer
+/// bring back. Runs after the deletion is recorded for undo.
+pub(crate) fn prune_attachments(state: &DbState, conn: &Connection) {
+    if let Err(e) = state.dir().and_then(|dir| attachments::prune(conn, &dir)) {
+        log::warn!("Could not delete attachments of deleted highlights: {e}");
+    }
+}
+
+/// Where the file of `add_attachment` and `attach_audio_note` comes from:
+/// `bytes` made in the app, with their `mime` type, or the file at `path`.
+fn attachment_source(
+    bytes: Option<Vec<u8>>,
+    mime: Option<String>,
+    name: Option<String>,
+    path: Option<String>,
+) -> Result<AttachmentSource, String> {
+    match (bytes, path) {
+        (Some(data), None) => Ok(AttachmentSource::Bytes {
+            data,
+            mime: mime.unwrap_or_default(),
+            name,
+        }),
+        (None, Some(path)) => Ok(AttachmentSource::File(path.into())),
+        _ => Err("Pass either the file's bytes or its path".to_string()),
+    }
+}
+
+/// Attaches a file to a highlight, e.g. a screenshot of the chart it's
+/// about; see [`attachments::attach`].
+#[tauri::command]
+pub async fn add_attachment(
+    state: tauri::State<'_, DbState>,
+    highlight_id: i64,
+    bytes: Option<Vec<u8>>,
+    mime: Option<String>,
+    name: Option<String>,
+    path: Option<String>,
+) -> Result<Attachment, String> {
+    let source = attachment_source(bytes, mime, name, path)?;
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        attachments::attach(&conn, &state.dir()?, highlight_id, source)
+    })
+    .await
+}
+
+/// Attaches a voice note to a highlight: a recording made in the app (its
+/// `mime` type defaulting to WebM, what browsers record) or an audio file.
+#[tauri::command]
+pub async fn attach_audio_note(
+    state: tauri::State<'_, DbState>,
+    highlight_id: i64,
+    bytes: Option<Vec<u8>>,
+    mime: Option<String>,
+    path: Option<String>,
+) -> Result<Attachment, String> {
+    let mime = mime.or_else(|| Some("audio/webm".to_string()));
+    let source = attachment_source(bytes, mime, None, path)?;
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let dir = state.dir()?;
+        let attachment = attachments::attach(&conn, &dir, highlight_id, source)?;
+        if attachment.kind != AttachmentKind::Audio {
+            attachments::delete(&conn, &dir, attachment.id)?;
+            return Err("That isn't a supported audio file".to_string());
+        }
+        Ok(attachment)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_attachments(
+    state: tauri::State<DbState>,
+    highlight_id: i64,
+) -> Result<Vec<Attachment>, String> {
+    let conn = state.conn()?;
+    attachments::list(&conn, highlight_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_attachment(state: tauri::State<DbState>, id: i64) -> Result<(), String> {
+    let conn = state.conn()?;
+    attachments::delete(&conn, &state.dir()?, id)
+}
+
+/// Links highlight `from_id` to `to_id`, in any books, with an optional
+/// `relation` such as "supports". Linking them again changes the relation.
+#[tauri::command]
+pub fn link_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    from_id: i64,
+    to_id: i64,
+    relation: Option<String>,
+) -> Result<HighlightLink, String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    let link = db::highlight_links::link(&conn, from_id, to_id, relation.as_deref())?;
+    undo::record(&conn, "Link highlights", since)?;
+    events::emit(&app, DataEvent::HighlightsLinked(link.clone()));
+    Ok(link)
+}
+
+#[tauri::command]
+pub fn unlink_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    let since = undo::mark(&conn)?;
+    if db::highlight_links::unlink(&conn, id).map_err(|e| e.to_string())? {
+        undo::record(&conn, "Unlink highlights", since)?;
+        events::emit(&app, DataEvent::HighlightsUnlinked(events::RecordId { id }));
+    }
+    Ok(())
+}
+
+/// Highlights linked to or from highlight `id`, newest link first.
+#[tauri::command]
+pub fn get_linked_highlights(
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<Vec<LinkedHighlight>, String> {
+    let conn = state.conn()?;
+    db::highlight_links::linked(&conn, id).map_err(|e| e.to_string())
+}
+
+/// Today's "remember this?" highlights; see [`db::review::daily`].
+#[tauri::command]
+pub fn get_daily_review(
+    state: tauri::State<DbState>,
+    count: usize,
+) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::review::daily(&conn, count).map_err(|e| e.to_string())
+}
+
+/// Groups of highlights in `book_title` whose ranges overlap; see
+/// [`db::highlights::overlapping`].
+#[tauri::command]
+pub fn find_overlapping_highlights(
+    state: tauri::State<DbState>,
+    book_title: String,
+) -> Result<Vec<Vec<Highlight>>, String> {
+    let conn = state.conn()?;
+    db::highlights::overlapping(&conn, &book_title).map_err(|e| e.to_string())
+}
+
+/// Combines highlights of one chapter into one; see
+/// [`db::highlights::merge`].
+#[tauri::command]
+pub fn merge_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    ids: Vec<i64>,
+) -> Result<Highlight, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let since = undo::mark(&tx)?;
+    let merged = db::highlights::merge(&tx, &ids)?;
+    undo::record(&tx, "Merge highlights", since)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    events::emit(
+        &app,
+        DataEvent::HighlightsDeleted(events::RecordIds {
+            ids: merged.removed,
+        }),
+    );
+    events::emit(&app, DataEvent::HighlightUpdated(merged.highlight.clone()));
+    Ok(merged.highlight)
+}
+
+/// Removes duplicate highlights left by double taps; see
+/// [`db::highlights::dedupe`]. Returns how many were removed.
+#[tauri::command]
+pub fn dedupe_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<usize, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let since = undo::mark(&tx)?;
+    let deduped = db::highlights::dedupe(&tx).map_err(|e| e.to_string())?;
+    undo::record(&tx, "Remove duplicate highlights", since)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let count = deduped.removed.len();
+    if count > 0 {
+        events::emit(
+            &app,
+            DataEvent::HighlightsDeleted(events::RecordIds {
+                ids: deduped.removed,
+            }),
+        );
+        events::emit(&app, DataEvent::HighlightsUpdated(deduped.kept));
+    }
+    Ok(count)
+}
+
+// Bulk variants of the highlight commands for multi-select. Each runs in one
+// transaction and emits a single event for the whole batch.
+
+#[tauri::command]
+pub fn bulk_delete_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    ids: Vec<i64>,
+) -> Result<usize, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let since = undo::mark(&tx)?;
+    let deleted = db::highlights::delete_many(&tx, &ids).map_err(|e| e.to_string())?;
+    undo::record(&tx, "Delete highlights", since)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    prune_attachments(&state, &conn);
+
+    let count = deleted.len();
+    events::emit(
+        &app,
+        DataEvent::HighlightsDeleted(events::RecordIds { ids: deleted }),
+    );
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn bulk_recolor_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    ids: Vec<i64>,
+    color: String,
+) -> Result<Vec<Highlight>, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let since = undo::mark(&tx)?;
+    let highlights = db::highlights::recolor_many(&tx, &ids, &color).map_err(|e| e.to_string())?;
+    undo::record(&tx, "Recolor highlights", since)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    events::emit(&app, DataEvent::HighlightsUpdated(highlights.clone()));
+    Ok(highlights)
+}
+
+/// Adds the highlights to the collection, skipping ones already in it.
+/// Returns how many were added.
+#[tauri::command]
+pub fn bulk_add_to_collection(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    ids: Vec<i64>,
+    collection_id: i64,
+) -> Result<usize, String> {
+    let mut conn = state.conn()?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let since = undo::mark(&tx)?;
+    let added =
+        db::collections::add_highlights(&tx, collection_id, &ids).map_err(|e| e.to_string())?;
+    undo::record(&tx, "Add to collection", since)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let count = added.len();
+    events::emit(
+        &app,
+        DataEvent::HighlightsAddedToCollection(events::CollectionLinks {
+            highlight_ids: added,
+            collection_id,
+        }),
+    );
+    Ok(count)
+}
+
+fn emit_colors(app: &tauri::AppHandle, conn: &Connection) -> Result<Vec<HighlightColor>, String> {
+    let colors = db::highlights::colors(conn).map_err(|e| e.to_string())?;
+    events::emit(app, DataEvent::HighlightColorsUpdated(colors.clone()));
+    Ok(colors)
+}
+
+/// The palette, in display order.
+#[tauri::command]
+pub fn list_highlight_colors(state: tauri::State<DbState>) -> Result<Vec<HighlightColor>, String> {
+    let conn = state.conn()?;
+    db::highlights::colors(&conn).map_err(|e| e.to_string())
+}
+
+/// Adds a color at the end of the palette. Existing highlights with this hex
+/// are linked to it.
+#[tauri::command]
+pub fn add_highlight_color(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    name: String,
+    hex: String,
+) -> Result<Vec<HighlightColor>, String> {
+    let conn = state.conn()?;
+    db::highlights::add_color(&conn, &name, &hex)?;
+    emit_colors(&app, &conn)
+}
+
+/// Renames a palette color or changes its hex. Highlights in that color
+/// follow the new hex.
+#[tauri::command]
+pub fn update_highlight_color(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+    name: String,
+    hex: String,
+) -> Result<Vec<HighlightColor>, String> {
+    let conn = state.conn()?;
+    db::highlights::update_color(&conn, id, &name, &hex)?;
+    let colors = emit_colors(&app, &conn)?;
+    events::emit(&app, DataEvent::LibraryReloaded);
+    Ok(colors)
+}
+
+/// Removes a color from the palette. Highlights keep their hex.
+#[tauri::command]
+pub fn delete_highlight_color(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<Vec<HighlightColor>, String> {
+    let conn = state.conn()?;
+    db::highlights::delete_color(&conn, id).map_err(|e| e.to_string())?;
+    emit_colors(&app, &conn)
+}
+
+/// Puts the palette in the order of `ids`.
+#[tauri::command]
+pub fn reorder_highlight_colors(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    ids: Vec<i64>,
+) -> Result<Vec<HighlightColor>, String> {
+    let mut conn = state.conn()?;
+    db::highlights::reorder_colors(&mut conn, &ids).map_err(|e| e.to_string())?;
+    emit_colors(&app, &conn)
+}
+
+/// Highlights in the palette color called `name` (ignoring case), newest
+/// first, optionally only from `book_title`.
+#[tauri::command]
+pub fn get_highlights_by_color(
+    state: tauri::State<DbState>,
+    name: String,
+    book_title: Option<String>,
+) -> Result<Vec<Highlight>, String> {
+    let conn = state.conn()?;
+    db::highlights::by_color(&conn, &name, book_title.as_deref()).map_err(|e| e.to_string())
+}
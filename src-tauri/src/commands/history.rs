@@ -0,0 +1,49 @@
+//! The change journal and undo / redo.
+
+use crate::db::DbState;
+use crate::events::DataEvent;
+use crate::{events, oplog, undo};
+
+/// Journal entries after `cursor`, at most `limit` (see [`oplog`]).
+#[tauri::command]
+pub fn get_changes_since(
+    state: tauri::State<DbState>,
+    cursor: Option<i64>,
+    limit: Option<usize>,
+) -> Result<oplog::ChangePage, String> {
+    let conn = state.conn()?;
+    oplog::changes_since(&conn, cursor, limit.unwrap_or(oplog::MAX_PAGE)).map_err(|e| e.to_string())
+}
+
+/// Reverses the latest highlight, bookmark or collection change (see
+/// [`undo`]). Returns what was undone, or nothing if there is nothing left.
+#[tauri::command]
+pub fn undo_last_operation(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<Option<String>, String> {
+    let mut conn = state.conn()?;
+    let undone = undo::undo(&mut conn)?;
+    if undone.is_some() {
+        events::emit(&app, DataEvent::LibraryReloaded);
+    }
+    Ok(undone)
+}
+
+/// Reapplies the change undone last. Returns what was redone, if anything.
+#[tauri::command]
+pub fn redo(app: tauri::AppHandle, state: tauri::State<DbState>) -> Result<Option<String>, String> {
+    let mut conn = state.conn()?;
+    let redone = undo::redo(&mut conn)?;
+    if redone.is_some() {
+        events::emit(&app, DataEvent::LibraryReloaded);
+    }
+    Ok(redone)
+}
+
+/// What undo and redo would do next, for menu labels.
+#[tauri::command]
+pub fn get_undo_state(state: tauri::State<DbState>) -> Result<undo::UndoState, String> {
+    let conn = state.conn()?;
+    undo::state(&conn).map_err(|e| e.to_string())
+}
@@ -0,0 +1,377 @@
+//! Calibre import, background imports, saved web articles and read-later
+//! exports.
+
+use super::{books_dir, run_blocking};
+use crate::commands::open::import_path;
+use crate::db::{compress_books, store_chapters, DbState};
+use crate::events::DataEvent;
+use crate::import::{read_calibre_books, CalibreBook};
+use crate::models::{
+    BookMetadata, CalibreImportReport, ImportFinished, ImportProgress, ReadLaterImportReport,
+};
+use crate::{article, authors, events, integrity, read_later, storage};
+use base64::Engine;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+
+/// Cancellation flags of running background imports, by task id.
+#[derive(Clone, Default)]
+pub struct ImportTasks {
+    next_id: Arc<AtomicU64>,
+    running: Arc<Mutex<std::collections::HashMap<u64, Arc<AtomicBool>>>>,
+}
+
+#[tauri::command]
+pub async fn import_calibre_library(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    path_to_metadata_db: String,
+) -> Result<CalibreImportReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let metadata_db = std::path::PathBuf::from(&path_to_metadata_db);
+        let library_root = metadata_db
+            .parent()
+            .ok_or_else(|| "Invalid Calibre metadata.db path".to_string())?
+            .to_path_buf();
+
+        let calibre = Connection::open_with_flags(&metadata_db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+        let calibre_books = read_calibre_books(&calibre).map_err(|e| e.to_string())?;
+
+        let mut conn = state.conn()?;
+        let books_dir = books_dir(&app, &conn)?;
+        std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
+        let compress = compress_books(&conn)?;
+
+        let mut report = CalibreImportReport {
+            imported: 0,
+            skipped: 0,
+            failed: Vec::new(),
+        };
+
+        for book in calibre_books {
+            let exists: Option<i64> = conn
+                .query_row(
+                    "SELECT id FROM books WHERE title = ?1 COLLATE fold",
+                    params![book.title],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if exists.is_some() {
+                report.skipped += 1;
+                continue;
+            }
+
+            match import_calibre_book(&mut conn, &books_dir, &library_root, &book, compress) {
+                Ok(()) => report.imported += 1,
+                Err(e) => report.failed.push(format!("{}: {}", book.title, e)),
+            }
+        }
+
+        if report.imported > 0 {
+            events::emit(&app, DataEvent::LibraryReloaded);
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// Copies one Calibre book into `books_dir` under a file name no other book
+/// uses, and adds it in a single transaction. On failure nothing is added and
+/// the copy is removed.
+fn import_calibre_book(
+    conn: &mut Connection,
+    books_dir: &Path,
+    library_root: &Path,
+    book: &CalibreBook,
+    compress: bool,
+) -> Result<(), String> {
+    let book_dir = library_root.join(&book.path);
+    let filename =
+        article::unused_filename(conn, books_dir, &book.epub_name).map_err(|e| e.to_string())?;
+    let target = books_dir.join(&filename);
+    std::fs::copy(book_dir.join(format!("{}.epub", book.epub_name)), &target)
+        .map_err(|e| e.to_string())?;
+
+    let result = add_calibre_book(conn, &target, &filename, &book_dir, book, compress);
+    if result.is_err() {
+        std::fs::remove_file(&target).ok();
+    }
+    result
+}
+
+fn add_calibre_book(
+    conn: &mut Connection,
+    path: &Path,
+    filename: &str,
+    book_dir: &Path,
+    book: &CalibreBook,
+    compress: bool,
+) -> Result<(), String> {
+    // Covers are stored inline as data URLs, matching what the frontend sends to add_book
+    let cover = if book.has_cover {
+        std::fs::read(book_dir.join("cover.jpg")).ok().map(|bytes| {
+            format!(
+                "data:image/jpeg;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            )
+        })
+    } else {
+        None
+    };
+
+    let content_hash = integrity::hash_file(path).map_err(|e| e.to_string())?;
+    storage::convert(path, compress).map_err(|e| e.to_string())?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO books (title, filename, cover, author, series, series_index, content_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![book.title, filename, cover, book.author, book.series, book.series_index, content_hash],
+    )
+    .map_err(|e| e.to_string())?;
+    let book_id = tx.last_insert_rowid();
+    if let Err(e) = store_chapters(&tx, book_id, path) {
+        log::warn!("Could not read chapters of {}: {e}", book.title);
+    }
+    let book_authors: Vec<String> = book.author.iter().cloned().collect();
+    authors::set_authors(&tx, book_id, &book_authors).map_err(|e| e.to_string())?;
+    authors::set_series(&tx, book_id, book.series.as_deref(), book.series_index)
+        .map_err(|e| e.to_string())?;
+
+    // Calibre tags become book collections
+    for tag in &book.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO collections (name) VALUES (?1)",
+            params![tag],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT OR IGNORE INTO book_collections (book_id, collection_id)
+             SELECT ?1, id FROM collections WHERE name = ?2",
+            params![book_id, tag],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+const IMPORT_CANCELLED: &str = "Import cancelled";
+
+/// Imports the book file at `path` on a background task and returns the
+/// task's id right away. Progress arrives as `import://progress` events and
+/// the outcome as an `import://finished` event.
+#[tauri::command]
+pub fn start_import(app: tauri::AppHandle, tasks: tauri::State<ImportTasks>, path: String) -> u64 {
+    let task_id = tasks.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    tasks
+        .running
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(task_id, cancelled.clone());
+    let tasks = tasks.inner().clone();
+
+    tauri::async_runtime::spawn(async move {
+        let path = std::path::PathBuf::from(path);
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let handle = app.clone();
+        let result = run_blocking(move || {
+            import_path(&handle, &path, &mut |stage, progress| {
+                if cancelled.load(Ordering::Relaxed) {
+                    return Err(IMPORT_CANCELLED.to_string());
+                }
+                let event = ImportProgress {
+                    task_id,
+                    filename: filename.clone(),
+                    stage: stage.to_string(),
+                    progress,
+                };
+                if let Err(e) = handle.emit("import://progress", event) {
+                    log::warn!("Failed to emit import progress: {e}");
+                }
+                Ok(())
+            })
+        })
+        .await;
+        tasks
+            .running
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&task_id);
+
+        let finished = match result {
+            Ok(book) => ImportFinished {
+                task_id,
+                book: Some(book),
+                error: None,
+                cancelled: false,
+            },
+            Err(e) => ImportFinished {
+                task_id,
+                book: None,
+                cancelled: e == IMPORT_CANCELLED,
+                error: Some(e),
+            },
+        };
+        if let Err(e) = app.emit("import://finished", finished) {
+            log::warn!("Failed to emit import://finished: {e}");
+        }
+    });
+    task_id
+}
+
+/// Stops a background import at its next stage. Once the book file has been
+/// written the import runs to completion.
+#[tauri::command]
+pub fn cancel_import(tasks: tauri::State<ImportTasks>, task_id: u64) -> Result<(), String> {
+    let running = tasks.running.lock().unwrap_or_else(|e| e.into_inner());
+    let flag = running
+        .get(&task_id)
+        .ok_or_else(|| format!("No import with id {task_id} is running"))?;
+    flag.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Saves the web page at `url` to the library as a readable article. A page
+/// that was saved before is returned as it is.
+#[tauri::command]
+pub async fn save_article(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    url: String,
+) -> Result<BookMetadata, String> {
+    let state = state.inner().clone();
+    let existing = {
+        let (state, url) = (state.clone(), url.trim().to_string());
+        run_blocking(move || {
+            let conn = state.conn()?;
+            article::saved(&conn, &url).map_err(|e| e.to_string())
+        })
+        .await?
+    };
+    if let Some(book) = existing {
+        return Ok(book);
+    }
+
+    let client = article::http_client()?;
+    let (html, final_url) = article::fetch_page(&client, &url).await?;
+    let mut page = run_blocking(move || article::extract(&html, &final_url)).await?;
+    let images = article::fetch_images(&client, &mut page).await;
+
+    let handle = app.clone();
+    let (book, added) = run_blocking(move || {
+        let conn = state.conn()?;
+        // Redirects can lead to a page that was saved under another URL.
+        if let Some(book) = article::saved(&conn, &page.url).map_err(|e| e.to_string())? {
+            return Ok((book, false));
+        }
+        let books_dir = books_dir(&handle, &conn)?;
+        Ok((article::save(&conn, &books_dir, page, images)?, true))
+    })
+    .await?;
+    if added {
+        events::emit(&app, DataEvent::BookAdded(book.clone()));
+    }
+    Ok(book)
+}
+
+/// Saves every article in a Pocket export (zip, HTML or CSV) or Instapaper
+/// CSV at `path`, then adds the export's highlights to them. Articles whose
+/// pages can no longer be fetched are listed in the report.
+#[tauri::command]
+pub async fn import_read_later(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    path: String,
+) -> Result<ReadLaterImportReport, String> {
+    let state = state.inner().clone();
+    let items = run_blocking(move || read_later::read_export(std::path::Path::new(&path))).await?;
+    let client = article::http_client()?;
+    let mut report = ReadLaterImportReport::default();
+    for item in items {
+        let (db, url) = (state.clone(), item.url.clone());
+        let existing = run_blocking(move || {
+            let conn = db.conn()?;
+            article::saved(&conn, &url).map_err(|e| e.to_string())
+        })
+        .await?;
+        let page = match existing {
+            Some(_) => None,
+            None => {
+                let fetched = match article::fetch_page(&client, &item.url).await {
+                    Ok((html, final_url)) => article::extract(&html, &final_url),
+                    Err(e) => Err(e),
+                };
+                match fetched {
+                    Ok(mut page) => {
+                        // The title the reader saw in their list.
+                        if item.title != item.url {
+                            page.title = item.title.clone();
+                        }
+                        let images = article::fetch_images(&client, &mut page).await;
+                        Some((page, images))
+                    }
+                    Err(e) => {
+                        log::warn!("Could not save {}: {e}", item.url);
+                        report.failures.push(format!("{}: {e}", item.url));
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let (db, handle) = (state.clone(), app.clone());
+        let (book, added, highlights) = run_blocking(move || {
+            let conn = db.conn()?;
+            let books_dir = books_dir(&handle, &conn)?;
+            let (book, added) = match (existing, page) {
+                (Some(book), _) => (book, false),
+                (None, Some((page, images))) => {
+                    match article::saved(&conn, &page.url).map_err(|e| e.to_string())? {
+                        Some(book) => (book, false),
+                        None => (article::save(&conn, &books_dir, page, images)?, true),
+                    }
+                }
+                (None, None) => unreachable!(),
+            };
+            if added {
+                if let Some(added_at) = item.added_at {
+                    conn.execute(
+                        "UPDATE books SET created_at = datetime(?1, 'unixepoch') WHERE id = ?2",
+                        params![added_at, book.id],
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+            }
+            let highlights = read_later::add_highlights(
+                &conn,
+                &books_dir,
+                &book.title,
+                &book.filename,
+                &item.highlights,
+            )?;
+            Ok((book, added, highlights))
+        })
+        .await?;
+        if added {
+            report.articles_added += 1;
+            events::emit(&app, DataEvent::BookAdded(book));
+        } else {
+            report.articles_existing += 1;
+        }
+        report.highlights_added += highlights.0;
+        report.highlights_unmatched += highlights.1;
+    }
+    if report.highlights_added > 0 {
+        events::emit(&app, DataEvent::LibraryReloaded);
+    }
+    Ok(report)
+}
@@ -0,0 +1,768 @@
+//! Where and how the library is stored: location, compression, profiles,
+//! encryption, app lock, backups and wiping.
+
+use super::{books_dir, default_books_dir, run_blocking};
+use crate::db::{
+    compress_books, register_functions, DbState, Library, DB_FILE, LIBRARY_PATH_SETTING,
+};
+use crate::events::DataEvent;
+use crate::models::StorageReport;
+use crate::{
+    app_lock, attachments, backup, book_cache, encryption, events, merge, profiles, storage,
+};
+use rusqlite::{params, Connection, OpenFlags};
+use std::sync::Arc;
+use tauri::Manager;
+
+#[tauri::command]
+pub async fn wipe_all_data(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let books_dir = books_dir(&app, &conn)?;
+        let filenames: Vec<String> = conn
+            .prepare("SELECT filename FROM books")
+            .map_err(|e| e.to_string())?
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())?;
+
+        // 1. Clear DB
+        conn.execute_batch(
+            "DELETE FROM highlights;
+             DELETE FROM highlight_links;
+             DELETE FROM books;
+             DELETE FROM bookmarks;
+             DELETE FROM book_collections;
+             DELETE FROM book_tags;
+             DELETE FROM translations;
+             DELETE FROM reading_sessions;
+             DELETE FROM sync_tombstones;
+             DELETE FROM book_text;
+             DELETE FROM chapters;
+             DELETE FROM obsidian_exports;
+             DELETE FROM book_notes;
+             DELETE FROM drawings;
+             DELETE FROM attachments;
+             DELETE FROM progress_history;
+             DELETE FROM book_authors;
+             DELETE FROM authors;
+             DELETE FROM book_series;
+             DELETE FROM series;
+             DELETE FROM note_revisions;
+             DELETE FROM undo_stack;
+             DELETE FROM oplog;
+             VACUUM;",
+        )
+        .map_err(|e| e.to_string())?;
+
+        // 2. Delete all book files. A library folder chosen by the user may
+        //    hold other files too, so only the books' own are removed there.
+        if books_dir == default_books_dir(&app)? {
+            if books_dir.exists() {
+                std::fs::remove_dir_all(&books_dir).map_err(|e| e.to_string())?;
+                std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
+            }
+        } else {
+            for filename in filenames {
+                let path = books_dir.join(filename);
+                if path.exists() {
+                    std::fs::remove_file(path).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+
+        // 3. Delete the attachments' files and the unpacked books.
+        attachments::prune(&conn, &state.dir()?)?;
+        book_cache::clear(&state.dir()?)?;
+
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(())
+    })
+    .await
+}
+
+/// Moves a file, copying it when a plain rename can't cross devices.
+fn move_file(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
+
+#[tauri::command]
+pub fn get_compress_books(state: tauri::State<DbState>) -> Result<bool, String> {
+    let conn = state.conn()?;
+    compress_books(&conn)
+}
+
+/// Turns compression of newly imported books on or off. Books already
+/// stored are left as they are; see `compress_library`.
+#[tauri::command]
+pub fn set_compress_books(state: tauri::State<DbState>, enabled: bool) -> Result<(), String> {
+    let conn = state.conn()?;
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![storage::COMPRESS_SETTING, enabled.to_string()],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Names of the book files present in `books_dir`. Archived books have none.
+fn stored_book_files(
+    conn: &Connection,
+    books_dir: &std::path::Path,
+) -> Result<Vec<String>, String> {
+    let filenames: Vec<String> = conn
+        .prepare("SELECT filename FROM books WHERE NOT archived ORDER BY filename")
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    Ok(filenames
+        .into_iter()
+        .filter(|f| books_dir.join(f).is_file())
+        .collect())
+}
+
+/// Measures (and with `convert`, first compresses or decompresses) every
+/// stored book file.
+fn storage_report(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    convert: Option<bool>,
+) -> Result<StorageReport, String> {
+    let books_dir = books_dir(app, conn)?;
+    let mut report = StorageReport::default();
+    for filename in stored_book_files(conn, &books_dir)? {
+        let path = books_dir.join(&filename);
+        let measured = (|| {
+            if let Some(compress) = convert {
+                storage::convert(&path, compress)?;
+            }
+            let stored = std::fs::metadata(&path)?.len();
+            Ok::<_, std::io::Error>((
+                stored,
+                storage::original_size(&path)?,
+                storage::layout(&path)?,
+            ))
+        })();
+        match measured {
+            Ok((stored, original, layout)) => {
+                report.files += 1;
+                report.compressed_files += usize::from(layout.compressed);
+                report.encrypted_files += usize::from(layout.encrypted);
+                report.stored_bytes += stored;
+                report.original_bytes += original;
+            }
+            Err(e) => report.failed.push(format!("{filename}: {e}")),
+        }
+    }
+    report.saved_bytes = report.original_bytes.saturating_sub(report.stored_bytes);
+    Ok(report)
+}
+
+/// How much space the book files take and how much compression saves.
+#[tauri::command]
+pub async fn get_storage_report(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+) -> Result<StorageReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        storage_report(&app, &conn, None)
+    })
+    .await
+}
+
+/// Compresses every stored book file, or with `compress` false restores them
+/// all to plain files. Returns the storage report afterwards.
+#[tauri::command]
+pub async fn compress_library(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    compress: Option<bool>,
+) -> Result<StorageReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        storage_report(&app, &conn, Some(compress.unwrap_or(true)))
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_library_path(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<String, String> {
+    let conn = state.conn()?;
+    Ok(books_dir(&app, &conn)?.to_string_lossy().into_owned())
+}
+
+/// Moves every book file to `path` and keeps the library there from now on,
+/// e.g. on an external drive or in a synced folder. An empty path moves the
+/// books back into the profile's folder. The database itself stays there,
+/// since SQLite files don't survive being synced while open.
+///
+/// Nothing is moved if any file already exists at the destination, and files
+/// moved before a failure are moved back. Returns the new location.
+#[tauri::command]
+pub async fn set_library_path(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    path: String,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let old_dir = books_dir(&app, &conn)?;
+        let new_dir = if path.trim().is_empty() {
+            default_books_dir(&app)?
+        } else {
+            std::path::PathBuf::from(path.trim())
+        };
+        if !new_dir.is_absolute() {
+            return Err(format!("{} is not an absolute path", new_dir.display()));
+        }
+        std::fs::create_dir_all(&new_dir).map_err(|e| e.to_string())?;
+        let same_dir = match (old_dir.canonicalize(), new_dir.canonicalize()) {
+            (Ok(old), Ok(new)) => old == new,
+            _ => false,
+        };
+
+        if !same_dir {
+            let filenames: Vec<String> = conn
+                .prepare("SELECT filename FROM books")
+                .map_err(|e| e.to_string())?
+                .query_map([], |row| row.get(0))
+                .map_err(|e| e.to_string())?
+                .collect::<rusqlite::Result<_>>()
+                .map_err(|e| e.to_string())?;
+            // Archived books have no file to move.
+            let filenames: Vec<String> = filenames
+                .into_iter()
+                .filter(|f| old_dir.join(f).exists())
+                .collect();
+            if let Some(taken) = filenames.iter().find(|f| new_dir.join(f).exists()) {
+                return Err(format!("{} already exists in {}", taken, new_dir.display()));
+            }
+
+            let mut moved = Vec::new();
+            for filename in &filenames {
+                if let Err(e) = move_file(&old_dir.join(filename), &new_dir.join(filename)) {
+                    for done in moved {
+                        let _ = move_file(&new_dir.join(done), &old_dir.join(done));
+                    }
+                    return Err(format!("Could not move {filename}: {e}"));
+                }
+                moved.push(filename);
+            }
+        }
+
+        if new_dir == default_books_dir(&app)? {
+            conn.execute(
+                "DELETE FROM settings WHERE key = ?1",
+                params![LIBRARY_PATH_SETTING],
+            )
+        } else {
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![LIBRARY_PATH_SETTING, new_dir.to_string_lossy()],
+            )
+        }
+        .map_err(|e| e.to_string())?;
+
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(new_dir.to_string_lossy().into_owned())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn list_profiles(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+) -> Result<Vec<profiles::Profile>, String> {
+    if state.is_app_locked()? {
+        return Err(app_lock::LOCKED.to_string());
+    }
+    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    profiles::list(&app_dir, &state.profile()?)
+}
+
+/// Creates an empty library under `name`. The active profile doesn't change.
+#[tauri::command]
+pub async fn create_profile(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    name: String,
+) -> Result<profiles::Profile, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        if state.is_app_locked()? {
+            return Err(app_lock::LOCKED.to_string());
+        }
+        profiles::validate_name(&name)?;
+        let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        if profiles::exists(&app_dir, &name) {
+            return Err(format!("A profile named {name} already exists"));
+        }
+        Library::open(&app_dir, &name)?;
+        Ok(profiles::Profile {
+            name,
+            active: false,
+        })
+    })
+    .await
+}
+
+/// Makes `name` the active library, now and at the next start.
+#[tauri::command]
+pub async fn switch_profile(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    name: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+        if profiles::validate_name(&name).is_err() || !profiles::exists(&app_dir, &name) {
+            return Err(format!("No profile named {name}"));
+        }
+        if state.is_app_locked()? {
+            return Err(app_lock::LOCKED.to_string());
+        }
+        if state.profile()? == name {
+            return Ok(());
+        }
+        state.switch_to(Library::open(&app_dir, &name)?)?;
+        profiles::set_current(&app_dir, &name)?;
+        events::emit(&app, DataEvent::LibraryReloaded);
+        if let Err(e) = super::watch_folder::restart(&app) {
+            log::info!("Watch folder not started: {e}");
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_encryption_status(
+    state: tauri::State<DbState>,
+) -> Result<encryption::EncryptionStatus, String> {
+    Ok(encryption::EncryptionStatus {
+        enabled: encryption::is_enabled(&state.dir()?),
+        locked: state.is_locked()?,
+    })
+}
+
+/// Opens the active profile's encrypted library with its passphrase. The
+/// frontend asks for it at startup, and after switching to an encrypted
+/// profile, whenever `get_encryption_status` reports the library locked.
+#[tauri::command]
+pub async fn unlock_library(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    passphrase: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        if !state.is_locked()? {
+            return Ok(());
+        }
+        let dir = state.dir()?;
+        let key = Arc::new(encryption::unlock(&dir, &passphrase)?);
+        state.switch_to(Library::open_with_key(&state.profile()?, dir, Some(key))?)?;
+        events::emit(&app, DataEvent::LibraryReloaded);
+        if let Err(e) = super::watch_folder::restart(&app) {
+            log::warn!("Could not start the watch folder: {e}");
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Forgets the key of the active profile until it is unlocked again.
+#[tauri::command]
+pub fn lock_library(app: tauri::AppHandle, state: tauri::State<DbState>) -> Result<(), String> {
+    let dir = state.dir()?;
+    if !encryption::is_enabled(&dir) {
+        return Err("The library is not encrypted".to_string());
+    }
+    state.switch_to(Library::locked(&state.profile()?, dir))?;
+    events::emit(&app, DataEvent::LibraryReloaded);
+    Ok(())
+}
+
+/// Swaps the closed profile's database (see [`DbState::close`]) for
+/// `replacement`, a copy written by [`encryption::export`], and reopens it
+/// with `key`. If the swap fails the old database is reopened as it was.
+fn replace_database(
+    state: &DbState,
+    replacement: &std::path::Path,
+    key: Option<Arc<encryption::LibraryKey>>,
+) -> Result<(), String> {
+    let name = state.profile()?;
+    let dir = state.dir()?;
+    if let Err(e) = std::fs::rename(replacement, dir.join(DB_FILE)) {
+        let _ = std::fs::remove_file(replacement);
+        reopen(state, state.key()?)?;
+        return Err(e.to_string());
+    }
+    // Left over from the old database, and would be replayed onto the new one.
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(dir.join(format!("{DB_FILE}{suffix}")));
+    }
+    state.switch_to(Library::open_with_key(&name, dir, key)?)
+}
+
+/// Reopens the closed profile's database with `key`.
+fn reopen(state: &DbState, key: Option<Arc<encryption::LibraryKey>>) -> Result<(), String> {
+    state.switch_to(Library::open_with_key(
+        &state.profile()?,
+        state.dir()?,
+        key,
+    )?)
+}
+
+/// A connection of its own to the closed profile's database, to snapshot it
+/// from.
+fn dedicated_conn(
+    dir: &std::path::Path,
+    key: Option<&encryption::LibraryKey>,
+) -> Result<Connection, String> {
+    let conn = Connection::open(dir.join(DB_FILE)).map_err(|e| e.to_string())?;
+    if let Some(key) = key {
+        encryption::apply(&conn, key).map_err(|e| e.to_string())?;
+    }
+    register_functions(&conn).map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+/// Copies the closed profile's database to `dest` with the key `to`, and
+/// lists the files to convert along with it.
+fn export_closed(
+    app: &tauri::AppHandle,
+    dir: &std::path::Path,
+    from: Option<&encryption::LibraryKey>,
+    dest: &std::path::Path,
+    to: Option<&encryption::LibraryKey>,
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let conn = dedicated_conn(dir, from)?;
+    let books_dir = books_dir(app, &conn)?;
+    let files = stored_files(&conn, dir, &books_dir)?;
+    let _ = std::fs::remove_file(dest);
+    encryption::export(&conn, dest, to)?;
+    Ok(files)
+}
+
+/// The stored book files and the attachment files, which are encrypted along
+/// with the database.
+fn stored_files(
+    conn: &Connection,
+    library_dir: &std::path::Path,
+    books_dir: &std::path::Path,
+) -> Result<Vec<std::path::PathBuf>, String> {
+    let mut files: Vec<_> = stored_book_files(conn, books_dir)?
+        .into_iter()
+        .map(|filename| books_dir.join(filename))
+        .collect();
+    files.extend(attachments::stored_files(library_dir)?);
+    Ok(files)
+}
+
+/// Encrypts (or decrypts) the listed files with `key`, undoing the files
+/// already done if one fails.
+fn convert_files(
+    files: &[std::path::PathBuf],
+    encrypt: bool,
+    key: &encryption::LibraryKey,
+) -> Result<(), String> {
+    let convert = |path: &std::path::PathBuf, encrypt: bool| {
+        if encrypt {
+            storage::encrypt(path, key)
+        } else {
+            storage::decrypt(path, key)
+        }
+    };
+    for (done, path) in files.iter().enumerate() {
+        if let Err(e) = convert(path, encrypt) {
+            for undo in &files[..done] {
+                let _ = convert(undo, !encrypt);
+            }
+            return Err(format!("Could not convert {}: {e}", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Encrypts the active profile at rest with a key derived from `passphrase`:
+/// the database with SQLCipher, the book and attachment files and the
+/// backups. From then on the library starts locked until `unlock_library` is
+/// given the passphrase. A lost passphrase can't be recovered.
+///
+/// Returns the backups that couldn't be encrypted; they are still readable
+/// without the passphrase until deleted.
+#[tauri::command]
+pub async fn enable_encryption(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    passphrase: String,
+) -> Result<Vec<String>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let dir = state.dir()?;
+        if encryption::is_enabled(&dir) {
+            return Err("The library is already encrypted".to_string());
+        }
+        if state.is_app_locked()? {
+            return Err(app_lock::LOCKED.to_string());
+        }
+        let (config, key) = encryption::create(&passphrase)?;
+        let key = Arc::new(key);
+
+        // Nothing may write to the database between the copy and the swap.
+        state.close()?;
+        let encrypted_db = dir.join(format!("{DB_FILE}.encrypted"));
+        let files = match export_closed(&app, &dir, None, &encrypted_db, Some(&key)) {
+            Ok(files) => files,
+            Err(e) => {
+                let _ = std::fs::remove_file(&encrypted_db);
+                reopen(&state, None)?;
+                return Err(e);
+            }
+        };
+
+        let result = convert_files(&files, true, &key)
+            .and_then(|()| encryption::save(&dir, &config))
+            .and_then(|()| {
+                replace_database(&state, &encrypted_db, Some(key.clone())).inspect_err(|_| {
+                    let _ = encryption::remove(&dir);
+                })
+            });
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&encrypted_db);
+            if !encryption::is_enabled(&dir) {
+                let _ = convert_files(&files, false, &key);
+            }
+            if state.is_locked()? {
+                reopen(&state, None)
+                    .unwrap_or_else(|e| log::warn!("Could not reopen the library: {e}"));
+            }
+            return Err(e);
+        }
+
+        let unconverted = backup::rekey(&backup::backups_dir(&dir), None, Some(&key));
+        // Unpacked books would stay readable without the passphrase.
+        book_cache::clear(&dir)?;
+        log::info!("Encrypted library {}", state.profile()?);
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(unconverted)
+    })
+    .await
+}
+
+/// Turns encryption off for the active profile, which must be unlocked.
+/// `passphrase` is asked again as a confirmation.
+///
+/// Returns the backups that couldn't be decrypted; they still need the
+/// passphrase to restore.
+#[tauri::command]
+pub async fn disable_encryption(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    passphrase: String,
+) -> Result<Vec<String>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let dir = state.dir()?;
+        if !encryption::is_enabled(&dir) {
+            return Err("The library is not encrypted".to_string());
+        }
+        let key = state.key()?.ok_or(encryption::LOCKED)?;
+        if state.is_app_locked()? {
+            return Err(app_lock::LOCKED.to_string());
+        }
+        encryption::unlock(&dir, &passphrase)?;
+        let config = encryption::load(&dir)?;
+
+        // Nothing may write to the database between the copy and the swap.
+        state.close()?;
+        let plain_db = dir.join(format!("{DB_FILE}.decrypted"));
+        let files = match export_closed(&app, &dir, Some(&key), &plain_db, None) {
+            Ok(files) => files,
+            Err(e) => {
+                let _ = std::fs::remove_file(&plain_db);
+                reopen(&state, Some(key))?;
+                return Err(e);
+            }
+        };
+
+        // The config goes before the swap, so the next launch never tries to
+        // key a plaintext database.
+        let result = convert_files(&files, false, &key)
+            .and_then(|()| encryption::remove(&dir))
+            .and_then(|()| {
+                replace_database(&state, &plain_db, None).inspect_err(|_| {
+                    let _ = encryption::save(&dir, &config);
+                })
+            });
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&plain_db);
+            if encryption::is_enabled(&dir) {
+                let _ = convert_files(&files, true, &key);
+            }
+            if state.is_locked()? {
+                reopen(&state, Some(key))
+                    .unwrap_or_else(|e| log::warn!("Could not reopen the library: {e}"));
+            }
+            return Err(e);
+        }
+
+        let unconverted = backup::rekey(&backup::backups_dir(&dir), Some(&key), None);
+        log::info!("Decrypted library {}", state.profile()?);
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(unconverted)
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_app_lock(state: tauri::State<DbState>) -> Result<app_lock::AppLockStatus, String> {
+    // A locked encrypted library can't say whether it has an app lock yet.
+    let enabled = match state.unchecked_conn() {
+        Ok(conn) => app_lock::stored(&conn)
+            .map_err(|e| e.to_string())?
+            .is_some(),
+        Err(_) => false,
+    };
+    Ok(app_lock::AppLockStatus {
+        enabled,
+        locked: state.is_app_locked()?,
+    })
+}
+
+/// Sets the PIN asked for when the app starts, or removes the lock if `pin`
+/// is empty. Only possible while unlocked.
+#[tauri::command]
+pub async fn set_app_lock(state: tauri::State<'_, DbState>, pin: String) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        app_lock::set(&conn, &pin)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn unlock_app(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    pin: String,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        if !state.is_app_locked()? {
+            return Ok(());
+        }
+        let conn = state.unchecked_conn()?;
+        let hash = app_lock::stored(&conn)
+            .map_err(|e| e.to_string())?
+            .ok_or("No app lock is set")?;
+        if !app_lock::verify(&pin, &hash)? {
+            return Err("Wrong PIN".to_string());
+        }
+        state.set_app_locked(false)?;
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(())
+    })
+    .await
+}
+
+/// Locks the app again, e.g. before stepping away from it.
+#[tauri::command]
+pub fn lock_app(app: tauri::AppHandle, state: tauri::State<DbState>) -> Result<(), String> {
+    let conn = state.conn()?;
+    if app_lock::stored(&conn)
+        .map_err(|e| e.to_string())?
+        .is_none()
+    {
+        return Err("No app lock is set".to_string());
+    }
+    state.set_app_locked(true)?;
+    events::emit(&app, DataEvent::LibraryReloaded);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_backups(
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<backup::BackupInfo>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || backup::list(&backup::backups_dir(&state.dir()?))).await
+}
+
+#[tauri::command]
+pub async fn create_backup(state: tauri::State<'_, DbState>) -> Result<backup::BackupInfo, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let dir = backup::backups_dir(&state.dir()?);
+        let conn = state.conn()?;
+        let info = backup::create(&conn, &dir)?;
+        backup::prune(&dir, backup::KEEP)?;
+        Ok(info)
+    })
+    .await
+}
+
+/// Restores a backup. By default the database is replaced wholesale; with
+/// `merge` the backup's highlights are merged into the current library
+/// instead and the merge report is returned.
+#[tauri::command]
+pub async fn restore_backup(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    name: String,
+    merge: Option<bool>,
+) -> Result<Option<merge::MergeReport>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let dir = backup::backups_dir(&state.dir()?);
+        let path = backup::resolve(&dir, &name)?;
+        let mut conn = state.conn()?;
+
+        if merge.unwrap_or(false) {
+            let source = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|e| e.to_string())?;
+            if let Some(key) = state.key()? {
+                encryption::apply(&source, &key).map_err(|e| e.to_string())?;
+            }
+            let items = merge::read_highlights(&source).map_err(|e| e.to_string())?;
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            let report = merge::merge_highlights(&tx, &items).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+            events::emit(&app, DataEvent::HighlightsImported(report.clone()));
+            return Ok(Some(report));
+        }
+
+        // Snapshot the current state first so a mistaken restore can be undone.
+        // Pruning waits until after the restore so it can't remove `path`.
+        backup::create(&conn, &dir)?;
+        backup::restore(&mut conn, &path, state.key()?.as_deref())?;
+        backup::prune(&dir, backup::KEEP)?;
+        events::emit(&app, DataEvent::LibraryReloaded);
+        Ok(None)
+    })
+    .await
+}
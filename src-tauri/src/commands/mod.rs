@@ -0,0 +1,87 @@
+//! Tauri commands, grouped by what they work on. They unpack state, call into
+//! the database layer and other modules, and emit change events.
+
+pub mod bookmarks;
+pub mod books;
+pub mod collections;
+pub mod diagnostics;
+pub mod export;
+pub mod feeds;
+pub mod goals;
+pub mod highlights;
+pub mod history;
+pub mod import;
+pub mod library;
+pub mod open;
+pub mod search;
+pub mod server;
+pub mod sync;
+pub mod tts;
+pub mod vocabulary;
+pub mod watch_folder;
+
+use crate::db::{self, DbState};
+use crate::events::{self, DataEvent};
+use crate::import::NewBook;
+use crate::models::{BookMetadata, ConversionProgress};
+use rusqlite::Connection;
+use tauri::{Emitter, Manager};
+
+/// Folder holding the active profile's book files; see [`db::books_dir`].
+pub fn books_dir(app: &tauri::AppHandle, conn: &Connection) -> Result<std::path::PathBuf, String> {
+    db::books_dir(conn, &app.state::<DbState>().dir()?)
+}
+
+pub fn default_books_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    Ok(db::default_books_dir(&app.state::<DbState>().dir()?))
+}
+
+/// Emits `import://conversion-progress` events for the conversion of
+/// `filename`.
+pub fn conversion_progress<'a>(
+    app: &'a tauri::AppHandle,
+    filename: &'a str,
+) -> impl FnMut(&str, f64) + 'a {
+    move |stage, progress| {
+        let event = ConversionProgress {
+            filename: filename.to_string(),
+            stage: stage.to_string(),
+            progress,
+        };
+        if let Err(e) = app.emit("import://conversion-progress", event) {
+            log::warn!("Failed to emit conversion progress: {e}");
+        }
+    }
+}
+
+/// Adds a book to the active profile's library (see [`crate::import::import_book`])
+/// and tells the windows about it.
+pub fn import_book(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    book: NewBook,
+    progress: &mut dyn FnMut(&str, f64) -> Result<(), String>,
+) -> Result<BookMetadata, String> {
+    let books_dir = books_dir(app, conn)?;
+    let filename = book.filename.clone();
+    let book = crate::import::import_book(
+        conn,
+        &books_dir,
+        book,
+        progress,
+        &mut conversion_progress(app, &filename),
+    )?;
+    events::emit(app, DataEvent::BookAdded(book.clone()));
+    Ok(book)
+}
+
+/// Runs blocking database / filesystem work off the async runtime's worker threads.
+pub async fn run_blocking<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| e.to_string())?
+}
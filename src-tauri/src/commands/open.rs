@@ -0,0 +1,130 @@
+//! Opening files and `tumelog://` links from the OS.
+
+use super::{books_dir, conversion_progress, run_blocking};
+use crate::db::{book_from_row, DbState, BOOK_COLUMNS};
+use crate::deep_link;
+use crate::events::{self, DataEvent};
+use crate::import;
+use crate::models::BookMetadata;
+use rusqlite::params;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+
+/// Books opened from outside the app (file associations, the command line, a
+/// second launch, `tumelog://` links) wait here until the frontend has called
+/// `take_opened_books`; after that they are sent as `library://navigate`
+/// events right away.
+#[derive(Clone, Default)]
+pub struct OpenedBooks(Arc<Mutex<OpenedBooksQueue>>);
+
+#[derive(Default)]
+struct OpenedBooksQueue {
+    frontend_ready: bool,
+    pending: Vec<OpenRequest>,
+}
+
+/// Payload of `library://navigate` events.
+#[derive(Debug, Serialize, Clone)]
+pub struct OpenRequest {
+    pub book: BookMetadata,
+    /// Where to open the book; the saved reading position when `None`.
+    pub cfi: Option<String>,
+}
+
+/// The library entry for the file at `path`; see [`import::import_file`].
+pub fn import_path(
+    app: &tauri::AppHandle,
+    path: &std::path::Path,
+    progress: &mut dyn FnMut(&str, f64) -> Result<(), String>,
+) -> Result<BookMetadata, String> {
+    let conn = app.state::<DbState>().conn()?;
+    let books_dir = books_dir(app, &conn)?;
+    let filename = path.file_name().unwrap_or_default().to_string_lossy();
+    let (book, added) = import::import_file(
+        &conn,
+        &books_dir,
+        path,
+        progress,
+        &mut conversion_progress(app, &filename),
+    )?;
+    if added {
+        events::emit(app, DataEvent::BookAdded(book.clone()));
+    }
+    Ok(book)
+}
+
+/// Imports `paths` in the background and asks the frontend to open each.
+pub fn open_paths(app: &tauri::AppHandle, paths: Vec<std::path::PathBuf>) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        for path in paths {
+            let handle = app.clone();
+            let display = path.display().to_string();
+            match run_blocking(move || import_path(&handle, &path, &mut |_, _| Ok(()))).await {
+                Ok(book) => request_open(&app, OpenRequest { book, cfi: None }),
+                Err(e) => log::error!("Could not open {display}: {e}"),
+            }
+        }
+    });
+}
+
+fn request_open(app: &tauri::AppHandle, request: OpenRequest) {
+    let opened = app.state::<OpenedBooks>();
+    let mut queue = opened.0.lock().unwrap_or_else(|e| e.into_inner());
+    if !queue.frontend_ready {
+        queue.pending.push(request);
+        return;
+    }
+    if let Err(e) = app.emit("library://navigate", request) {
+        log::warn!("Failed to emit library://navigate: {e}");
+    }
+}
+
+/// Books opened from outside the app before the frontend was listening.
+/// Later ones arrive as `library://navigate` events.
+#[tauri::command]
+pub fn take_opened_books(opened: tauri::State<OpenedBooks>) -> Vec<OpenRequest> {
+    let mut queue = opened.0.lock().unwrap_or_else(|e| e.into_inner());
+    queue.frontend_ready = true;
+    std::mem::take(&mut queue.pending)
+}
+
+/// Asks the frontend to show the book and position each link points to.
+pub fn open_deep_links(app: &tauri::AppHandle, urls: Vec<tauri::Url>) {
+    for url in urls {
+        let Some((id, cfi)) = deep_link::parse(&url) else {
+            log::warn!("Ignoring unsupported link {url}");
+            continue;
+        };
+        let book = app.state::<DbState>().conn().and_then(|conn| {
+            conn.query_row(
+                &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+                params![id],
+                book_from_row,
+            )
+            .map_err(|e| e.to_string())
+        });
+        match book {
+            Ok(book) => request_open(app, OpenRequest { book, cfi }),
+            Err(e) => log::error!("Could not open {url}: {e}"),
+        }
+    }
+}
+
+/// Files handed over by macOS Finder arrive as run events rather than
+/// arguments.
+#[cfg_attr(
+    not(any(target_os = "macos", target_os = "ios")),
+    allow(unused_variables)
+)]
+pub fn handle_run_event(app: &tauri::AppHandle, event: tauri::RunEvent) {
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    if let tauri::RunEvent::Opened { urls } = event {
+        let paths = urls
+            .into_iter()
+            .filter_map(|url| url.to_file_path().ok())
+            .collect();
+        open_paths(app, paths);
+    }
+}
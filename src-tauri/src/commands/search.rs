@@ -0,0 +1,227 @@
+//! Full-text and semantic search, and highlight summaries.
+
+use super::{books_dir, run_blocking};
+use crate::commands::books::emit_book_updated;
+use crate::db::{highlight_from_row, DbState, HIGHLIGHT_COLUMNS};
+use crate::import::is_epub;
+use crate::models::{ScoredHighlight, SummaryChunk};
+use crate::{embeddings, llm, search};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+
+/// Most matches returned by a single search.
+const SEARCH_LIMIT: usize = 200;
+
+/// The embedding model, loaded from `<app data>/models` on first use.
+#[derive(Clone, Default)]
+pub struct EmbedderState(pub Arc<Mutex<Option<embeddings::Embedder>>>);
+
+impl EmbedderState {
+    /// Runs `f` with the model, loading it first if it isn't yet.
+    fn with<T>(
+        &self,
+        app: &tauri::AppHandle,
+        f: impl FnOnce(&mut embeddings::Embedder) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut loaded = self.0.lock().map_err(|e| e.to_string())?;
+        let embedder = match loaded.take() {
+            Some(embedder) => embedder,
+            None => embeddings::Embedder::load(&models_dir(app)?)?,
+        };
+        f(loaded.insert(embedder))
+    }
+}
+
+fn models_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("models");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+/// Adds (or refreshes) an EPUB book's text in the full-text index. Books are
+/// only searchable once indexed.
+#[tauri::command]
+pub async fn index_book(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+) -> Result<search::BookIndexReport, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let mut conn = state.conn()?;
+        let (title, filename, format): (String, String, String) = conn
+            .query_row(
+                "SELECT title, filename, format FROM books WHERE id = ?1",
+                params![book_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        if !is_epub(&format) {
+            return Err(format!(
+                "Only EPUB books can be indexed ({title} is {format})"
+            ));
+        }
+
+        let path = books_dir(&app, &conn)?.join(filename);
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let report = search::index_book(&tx, book_id, &path)?;
+        tx.commit().map_err(|e| e.to_string())?;
+
+        emit_book_updated(&app, &conn, &title)?;
+        Ok(report)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn search_in_book(
+    state: tauri::State<'_, DbState>,
+    book_id: i64,
+    query: String,
+) -> Result<Vec<search::SearchHit>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        search::search(&conn, Some(book_id), &query, SEARCH_LIMIT).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn search_library(
+    state: tauri::State<'_, DbState>,
+    query: String,
+) -> Result<Vec<search::SearchHit>, String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        search::search(&conn, None, &query, SEARCH_LIMIT).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Embeds highlights until none are left without a current vector. The model
+/// is only loaded once there is something to embed.
+pub fn embed_all_pending(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    embedder: &EmbedderState,
+) -> Result<usize, String> {
+    let mut total = 0;
+    loop {
+        let pending = embeddings::pending(conn).map_err(|e| e.to_string())?;
+        if pending.is_empty() {
+            return Ok(total);
+        }
+        embedder.with(app, |embedder| {
+            embeddings::embed_pending(conn, embedder, &pending)
+        })?;
+        total += pending.len();
+    }
+}
+
+/// The `k` highlights closest in meaning to `query`, best first. Highlights
+/// the background task hasn't embedded yet aren't found until it has.
+#[tauri::command]
+pub async fn semantic_search_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    embedder: tauri::State<'_, EmbedderState>,
+    query: String,
+    k: usize,
+) -> Result<Vec<ScoredHighlight>, String> {
+    let state = state.inner().clone();
+    let embedder = embedder.inner().clone();
+    run_blocking(move || {
+        let conn = state.conn()?;
+        let matches = embedder.with(&app, |embedder| {
+            embeddings::search(&conn, embedder, &query, k)
+        })?;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE h.id = ?1"
+            ))
+            .map_err(|e| e.to_string())?;
+        let mut results = Vec::new();
+        for (id, score) in matches {
+            if let Some(highlight) = stmt
+                .query_row(params![id], highlight_from_row)
+                .optional()
+                .map_err(|e| e.to_string())?
+            {
+                results.push(ScoredHighlight { highlight, score });
+            }
+        }
+        Ok(results)
+    })
+    .await
+}
+
+/// Summarizes a book's highlights with an OpenAI-compatible `provider` (see
+/// [`llm`] for its keychain settings). The text streams in as
+/// `summary://chunk` events; the finished summary is stored on the book and
+/// returned.
+#[tauri::command]
+pub async fn summarize_highlights(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    book_title: String,
+    provider: String,
+) -> Result<String, String> {
+    let state = state.inner().clone();
+    let (provider, highlights) = run_blocking({
+        let state = state.clone();
+        let book_title = book_title.clone();
+        move || {
+            let provider = llm::Provider::load(&provider)?;
+            let conn = state.conn()?;
+            let mut stmt = conn
+                .prepare(
+                    "SELECT text, notes FROM highlights WHERE book_title = ?1 ORDER BY created_at",
+                )
+                .map_err(|e| e.to_string())?;
+            let highlights = stmt
+                .query_map(params![book_title], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| e.to_string())?
+                .collect::<rusqlite::Result<Vec<(String, String)>>>()
+                .map_err(|e| e.to_string())?;
+            Ok((provider, highlights))
+        }
+    })
+    .await?;
+    if highlights.is_empty() {
+        return Err(format!("{book_title} has no highlights to summarize"));
+    }
+
+    let summary = llm::summarize(&provider, &book_title, &highlights, |delta| {
+        let chunk = SummaryChunk {
+            book_title: book_title.clone(),
+            delta: delta.to_string(),
+        };
+        if let Err(e) = app.emit("summary://chunk", chunk) {
+            log::warn!("Failed to emit summary chunk: {e}");
+        }
+    })
+    .await?;
+
+    run_blocking({
+        let summary = summary.clone();
+        move || {
+            let conn = state.conn()?;
+            conn.execute(
+                "UPDATE books SET summary = ?1, summarized_at = datetime('now') WHERE title = ?2",
+                params![summary, book_title],
+            )
+            .map_err(|e| e.to_string())?;
+            emit_book_updated(&app, &conn, &book_title)
+        }
+    })
+    .await?;
+    Ok(summary)
+}
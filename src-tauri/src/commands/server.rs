@@ -0,0 +1,109 @@
+//! The local HTTP API.
+
+use super::run_blocking;
+use crate::db::DbState;
+use crate::server;
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// Whether the server should run, and on which port.
+pub fn server_settings(conn: &Connection) -> Result<(bool, u16), String> {
+    let setting = |key: &str| {
+        conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+    };
+    let enabled = setting(server::ENABLED_SETTING)?.as_deref() == Some("true");
+    let port = setting(server::PORT_SETTING)?
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(server::DEFAULT_PORT);
+    Ok((enabled, port))
+}
+
+#[tauri::command]
+pub fn get_server_status(
+    state: tauri::State<DbState>,
+    server: tauri::State<server::ServerState>,
+) -> Result<server::ServerStatus, String> {
+    let conn = state.conn()?;
+    let (_, port) = server_settings(&conn)?;
+    server.status(port)
+}
+
+/// Starts or stops the HTTP API for companion apps (see [`server`]) and
+/// remembers the choice for the next start. `port` changes the port it
+/// listens on.
+#[tauri::command]
+pub async fn set_server_enabled(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    server: tauri::State<'_, server::ServerState>,
+    enabled: bool,
+    port: Option<u16>,
+) -> Result<server::ServerStatus, String> {
+    let db = state.inner().clone();
+    let port = run_blocking({
+        let db = db.clone();
+        move || {
+            let conn = db.conn()?;
+            let port = match port {
+                Some(port) => port,
+                None => server_settings(&conn)?.1,
+            };
+            for (key, value) in [
+                (server::ENABLED_SETTING, enabled.to_string()),
+                (server::PORT_SETTING, port.to_string()),
+            ] {
+                conn.execute(
+                    "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                    params![key, value],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(port)
+        }
+    })
+    .await?;
+    if enabled {
+        server.start(&app, db, port).await?;
+    } else {
+        server.stop().await?;
+    }
+    run_blocking({
+        let server = server.inner().clone();
+        move || server.status(port)
+    })
+    .await
+}
+
+/// Replaces the HTTP API's access token; companion apps have to be given the
+/// new one.
+#[tauri::command]
+pub async fn regenerate_server_token(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    server: tauri::State<'_, server::ServerState>,
+) -> Result<server::ServerStatus, String> {
+    let db = state.inner().clone();
+    let (running, port) = run_blocking({
+        let db = db.clone();
+        let server = server.inner().clone();
+        move || {
+            server::new_token()?;
+            let conn = db.conn()?;
+            let (_, port) = server_settings(&conn)?;
+            Ok((server.status(port)?.running, port))
+        }
+    })
+    .await?;
+    // The running server holds the old token.
+    if running {
+        server.start(&app, db, port).await?;
+    }
+    let server = server.inner().clone();
+    run_blocking(move || server.status(port)).await
+}
@@ -0,0 +1,205 @@
+//! Secrets, cloud sync and peer sync.
+
+use super::run_blocking;
+use crate::db::DbState;
+use crate::events::DataEvent;
+use crate::{events, peer, secrets, server, sync};
+use rusqlite::params;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::Emitter;
+
+/// Set while a sync is running so overlapping `sync_now` calls are rejected.
+#[derive(Clone, Default)]
+pub struct SyncState(pub Arc<AtomicBool>);
+
+#[tauri::command]
+pub async fn store_secret(integration: String, key: String, value: String) -> Result<(), String> {
+    run_blocking(move || secrets::store(&integration, &key, &value)).await
+}
+
+#[tauri::command]
+pub async fn get_secret(integration: String, key: String) -> Result<Option<String>, String> {
+    run_blocking(move || secrets::get(&integration, &key)).await
+}
+
+#[tauri::command]
+pub async fn delete_secret(integration: String, key: String) -> Result<(), String> {
+    run_blocking(move || secrets::delete(&integration, &key)).await
+}
+
+/// Configures WebDAV sync. The password goes to the OS keychain; passing
+/// `None` keeps the stored one. An empty URL turns sync off.
+#[tauri::command]
+pub async fn set_sync_config(
+    state: tauri::State<'_, DbState>,
+    url: String,
+    username: String,
+    password: Option<String>,
+) -> Result<(), String> {
+    let state = state.inner().clone();
+    run_blocking(move || {
+        if url.trim().is_empty() {
+            secrets::delete("webdav", "password")?;
+        } else if let Some(password) = password {
+            secrets::store("webdav", "password", &password)?;
+        }
+        let conn = state.conn()?;
+        sync::save_config(&conn, &url, &username).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_sync_config(state: tauri::State<DbState>) -> Result<Option<sync::SyncConfig>, String> {
+    let conn = state.conn()?;
+    sync::load_config(&conn).map_err(|e| e.to_string())
+}
+
+/// Pulls, merges and pushes annotations and progress, emitting `sync://status`
+/// events as it starts and finishes.
+#[tauri::command]
+pub async fn sync_now(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    sync_state: tauri::State<'_, SyncState>,
+) -> Result<sync::SyncReport, String> {
+    let running = sync_state.0.clone();
+    if running.swap(true, Ordering::SeqCst) {
+        return Err("A sync is already in progress".to_string());
+    }
+
+    let emit = |status: sync::SyncStatus| {
+        if let Err(e) = app.emit("sync://status", status) {
+            log::warn!("Failed to emit sync status: {e}");
+        }
+    };
+    emit(sync::SyncStatus {
+        state: "started",
+        report: None,
+        error: None,
+    });
+
+    let db = state.inner().clone();
+    let result = async {
+        let (config, password) = run_blocking({
+            let db = db.clone();
+            move || {
+                let conn = db.conn()?;
+                let config = sync::load_config(&conn)
+                    .map_err(|e| e.to_string())?
+                    .ok_or("Sync is not configured")?;
+                let password = secrets::get("webdav", "password")?.unwrap_or_default();
+                Ok((config, password))
+            }
+        })
+        .await?;
+        sync::sync(db, config, password).await
+    }
+    .await;
+    running.store(false, Ordering::SeqCst);
+
+    match &result {
+        Ok(report) => {
+            if report.pulled > 0 {
+                events::emit(&app, DataEvent::LibraryReloaded);
+            }
+            emit(sync::SyncStatus {
+                state: "completed",
+                report: Some(report.clone()),
+                error: None,
+            });
+        }
+        Err(e) => {
+            log::error!("Sync failed: {e}");
+            emit(sync::SyncStatus {
+                state: "failed",
+                report: None,
+                error: Some(e.clone()),
+            });
+        }
+    }
+    result
+}
+
+/// Devices on the network that can be paired or synced with (see [`peer`]).
+/// Takes a few seconds.
+#[tauri::command]
+pub async fn discover_peers(
+    state: tauri::State<'_, DbState>,
+) -> Result<Vec<peer::DiscoveredPeer>, String> {
+    let db = state.inner().clone();
+    run_blocking(move || {
+        let (own_id, paired) = {
+            let conn = db.conn()?;
+            let paired = peer::list(&conn).map_err(|e| e.to_string())?;
+            (peer::device_id(&conn)?, paired)
+        };
+        Ok(peer::discover(&own_id)?
+            .into_iter()
+            .map(|(device_id, name, address)| peer::DiscoveredPeer {
+                paired: paired.iter().any(|p| p.device_id == device_id),
+                device_id,
+                name,
+                address: address.to_string(),
+            })
+            .collect())
+    })
+    .await
+}
+
+/// A code for another device to pair with this one, valid for a few minutes.
+/// Peers reach this device through the HTTP server, so it has to be running.
+#[tauri::command]
+pub fn start_pairing(
+    server: tauri::State<server::ServerState>,
+    pairing: tauri::State<peer::Pairing>,
+) -> Result<String, String> {
+    if !server.status(server::DEFAULT_PORT)?.running {
+        return Err("Turn on the HTTP server to pair devices".to_string());
+    }
+    pairing.start()
+}
+
+/// Pairs with the device at `address` (`host:port`), using the code it shows.
+#[tauri::command]
+pub async fn pair_peer(
+    state: tauri::State<'_, DbState>,
+    address: String,
+    code: String,
+) -> Result<peer::PairedPeer, String> {
+    peer::pair(state.inner().clone(), address, code).await
+}
+
+/// Exchanges annotation and progress changes with a paired device. `full`
+/// exchanges everything rather than what changed since the last sync.
+#[tauri::command]
+pub async fn sync_with_peer(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    device_id: String,
+    full: Option<bool>,
+) -> Result<sync::SyncReport, String> {
+    peer::sync_with(
+        &app,
+        state.inner().clone(),
+        device_id,
+        full.unwrap_or(false),
+    )
+    .await
+}
+
+#[tauri::command]
+pub fn list_peers(state: tauri::State<DbState>) -> Result<Vec<peer::PairedPeer>, String> {
+    let conn = state.conn()?;
+    peer::list(&conn).map_err(|e| e.to_string())
+}
+
+/// Forgets a paired device; it has to pair again to sync.
+#[tauri::command]
+pub fn unpair_peer(state: tauri::State<DbState>, device_id: String) -> Result<(), String> {
+    let conn = state.conn()?;
+    conn.execute("DELETE FROM peers WHERE device_id = ?1", params![device_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
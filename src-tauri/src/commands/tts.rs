@@ -0,0 +1,67 @@
+//! Reading aloud.
+
+use super::run_blocking;
+use crate::db::DbState;
+use crate::tts;
+use tauri::Emitter;
+
+#[tauri::command]
+pub async fn list_voices(
+    tts_state: tauri::State<'_, tts::TtsState>,
+) -> Result<Vec<tts::Voice>, String> {
+    let tts_state = tts_state.inner().clone();
+    run_blocking(move || tts_state.voices()).await
+}
+
+#[tauri::command]
+pub fn get_tts_settings(state: tauri::State<DbState>) -> Result<tts::TtsSettings, String> {
+    let conn = state.conn()?;
+    tts::load_settings(&conn).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_tts_settings(
+    state: tauri::State<DbState>,
+    settings: tts::TtsSettings,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    tts::save_settings(&conn, &settings).map_err(|e| e.to_string())
+}
+
+/// Speaks `text` with the saved voice settings, interrupting anything being
+/// spoken. Returns the utterance ID, which comes back in a `tts://finished`
+/// event when the chunk ends.
+#[tauri::command]
+pub async fn speak_text(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, DbState>,
+    tts_state: tauri::State<'_, tts::TtsState>,
+    text: String,
+) -> Result<u64, String> {
+    let state = state.inner().clone();
+    let tts_state = tts_state.inner().clone();
+    run_blocking(move || {
+        let settings = tts::load_settings(&*state.conn()?).map_err(|e| e.to_string())?;
+        tts_state.speak(&text, &settings, move |finished| {
+            if let Err(e) = app.emit("tts://finished", finished) {
+                log::warn!("Failed to emit tts://finished: {e}");
+            }
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn pause_speech(tts_state: tauri::State<tts::TtsState>) -> Result<(), String> {
+    tts_state.set_paused(true)
+}
+
+#[tauri::command]
+pub fn resume_speech(tts_state: tauri::State<tts::TtsState>) -> Result<(), String> {
+    tts_state.set_paused(false)
+}
+
+#[tauri::command]
+pub fn stop_speech(tts_state: tauri::State<tts::TtsState>) -> Result<(), String> {
+    tts_state.stop()
+}
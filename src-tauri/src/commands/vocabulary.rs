@@ -0,0 +1,168 @@
+//! Vocabulary words, dictionary lookups and translations.
+
+use super::run_blocking;
+use crate::db::{self, DbState};
+use crate::events::DataEvent;
+use crate::models::VocabWord;
+use crate::translate::{self, Translation, TranslationProvider};
+use crate::{article, dictionary, events};
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+
+/// Dictionaries loaded from `<app data>/dictionaries`, populated on first lookup.
+#[derive(Clone, Default)]
+pub struct DictionaryState(pub Arc<Mutex<Option<Vec<dictionary::Dictionary>>>>);
+
+#[tauri::command]
+pub fn add_vocab_word(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    word: String,
+    context: String,
+    book_title: Option<String>,
+    cfi: Option<String>,
+) -> Result<VocabWord, String> {
+    let conn = state.conn()?;
+    let vocab_word = db::vocabulary::add(
+        &conn,
+        &word,
+        &context,
+        book_title.as_deref(),
+        cfi.as_deref(),
+    )
+    .map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::VocabWordAdded(vocab_word.clone()));
+    Ok(vocab_word)
+}
+
+/// Words saved while reading `book_title`, or every saved word when it's omitted.
+#[tauri::command]
+pub fn get_vocab_words(
+    state: tauri::State<DbState>,
+    book_title: Option<String>,
+) -> Result<Vec<VocabWord>, String> {
+    let conn = state.conn()?;
+    db::vocabulary::list(&conn, book_title.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_vocab_word(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: i64,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    db::vocabulary::delete(&conn, id).map_err(|e| e.to_string())?;
+    events::emit(&app, DataEvent::VocabWordDeleted(events::RecordId { id }));
+    Ok(())
+}
+
+fn dictionaries_dir(app: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("dictionaries");
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+#[tauri::command]
+pub async fn lookup_word(
+    app: tauri::AppHandle,
+    dictionaries: tauri::State<'_, DictionaryState>,
+    word: String,
+    lang: Option<String>,
+) -> Result<Vec<dictionary::Definition>, String> {
+    let dictionaries = dictionaries.inner().clone();
+    run_blocking(move || {
+        let mut loaded = dictionaries.0.lock().map_err(|e| e.to_string())?;
+        if loaded.is_none() {
+            *loaded = Some(dictionary::load_all(&dictionaries_dir(&app)?));
+        }
+        Ok(loaded
+            .iter()
+            .flatten()
+            .filter(|d| lang.as_deref().map_or(true, |l| d.matches_lang(l)))
+            .flat_map(|d| d.lookup(&word))
+            .collect())
+    })
+    .await
+}
+
+/// Rescans the dictionaries folder, picking up files added since the first lookup.
+#[tauri::command]
+pub async fn reload_dictionaries(
+    app: tauri::AppHandle,
+    dictionaries: tauri::State<'_, DictionaryState>,
+) -> Result<Vec<dictionary::DictionaryInfo>, String> {
+    let dictionaries = dictionaries.inner().clone();
+    run_blocking(move || {
+        let fresh = dictionary::load_all(&dictionaries_dir(&app)?);
+        let infos = fresh.iter().map(|d| d.info.clone()).collect();
+        *dictionaries.0.lock().map_err(|e| e.to_string())? = Some(fresh);
+        Ok(infos)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_dictionaries(
+    app: tauri::AppHandle,
+    dictionaries: tauri::State<'_, DictionaryState>,
+) -> Result<Vec<dictionary::DictionaryInfo>, String> {
+    let dictionaries = dictionaries.inner().clone();
+    run_blocking(move || {
+        let mut loaded = dictionaries.0.lock().map_err(|e| e.to_string())?;
+        if loaded.is_none() {
+            *loaded = Some(dictionary::load_all(&dictionaries_dir(&app)?));
+        }
+        Ok(loaded.iter().flatten().map(|d| d.info.clone()).collect())
+    })
+    .await
+}
+
+/// Translates `text` into `target_lang` with LibreTranslate or DeepL (see
+/// [`translate`] for their keychain settings), or answers from the cache if
+/// the text was translated before.
+#[tauri::command]
+pub async fn translate_text(
+    state: tauri::State<'_, DbState>,
+    text: String,
+    target_lang: String,
+    provider: TranslationProvider,
+) -> Result<Translation, String> {
+    let (text, target_lang) = translate::prepare(&text, &target_lang)?;
+    let state = state.inner().clone();
+    let cached = run_blocking({
+        let state = state.clone();
+        let (text, target_lang) = (text.clone(), target_lang.clone());
+        move || {
+            let conn = state.conn()?;
+            translate::cached(&conn, provider, &text, &target_lang).map_err(|e| e.to_string())
+        }
+    })
+    .await?;
+    if let Some(translation) = cached {
+        return Ok(translation);
+    }
+    let endpoint = run_blocking(move || translate::Endpoint::load(provider)).await?;
+    let translation =
+        translate::translate(&article::http_client()?, &endpoint, &text, &target_lang).await?;
+    run_blocking({
+        let translation = translation.clone();
+        move || {
+            let conn = state.conn()?;
+            translate::store(&conn, &text, &translation).map_err(|e| e.to_string())
+        }
+    })
+    .await?;
+    Ok(translation)
+}
+
+/// Forgets every cached translation. Returns how many there were.
+#[tauri::command]
+pub fn clear_translation_cache(state: tauri::State<DbState>) -> Result<usize, String> {
+    let conn = state.conn()?;
+    translate::clear(&conn).map_err(|e| e.to_string())
+}
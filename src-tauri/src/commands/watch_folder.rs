@@ -0,0 +1,185 @@
+//! Importing book files as they appear in the watched folder.
+
+use super::{books_dir, conversion_progress, run_blocking};
+use crate::db::DbState;
+use crate::events::{self, DataEvent};
+use crate::import;
+use crate::watch_folder::{self, WatchFolderConfig, WatchFolderImport};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{Emitter, Manager};
+use tokio::sync::mpsc::{self, error::TryRecvError};
+
+/// How often new files are checked; one is imported once its size stayed
+/// the same between two checks, so files still being written are left alone.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// The running watcher. Dropping it stops the import task too.
+#[derive(Clone, Default)]
+pub struct WatchFolder(Arc<Mutex<Option<notify::RecommendedWatcher>>>);
+
+#[tauri::command]
+pub fn get_watch_folder(state: tauri::State<DbState>) -> Result<Option<WatchFolderConfig>, String> {
+    let conn = state.conn()?;
+    watch_folder::config(&conn)
+}
+
+/// Starts watching `config.path`, importing the book files already in it,
+/// or stops watching with `None`. Each import is reported as a
+/// `watch-folder://imported` event.
+#[tauri::command]
+pub fn set_watch_folder(
+    app: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    config: Option<WatchFolderConfig>,
+) -> Result<(), String> {
+    let conn = state.conn()?;
+    watch_folder::set_config(&conn, config.as_ref())?;
+    restart(&app)
+}
+
+/// (Re)starts the watcher for the active library's folder, if it has one.
+pub fn restart(app: &tauri::AppHandle) -> Result<(), String> {
+    let state = app.state::<WatchFolder>();
+    let mut watcher = state.0.lock().unwrap_or_else(|e| e.into_inner());
+    *watcher = None;
+    let conn = app.state::<DbState>().conn()?;
+    let config = watch_folder::config(&conn)?;
+    if let Some(config) = config {
+        *watcher = Some(start(app, config)?);
+    }
+    Ok(())
+}
+
+fn start(
+    app: &tauri::AppHandle,
+    config: WatchFolderConfig,
+) -> Result<notify::RecommendedWatcher, String> {
+    let folder = PathBuf::from(&config.path);
+    let (tx, rx) = mpsc::unbounded_channel();
+    for path in watch_folder::scan(&folder) {
+        let _ = tx.send(path);
+    }
+    let watched = folder.clone();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths {
+                    if watch_folder::is_book_file(&watched, &path) {
+                        let _ = tx.send(path);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("Watch folder error: {e}"),
+        })
+        .map_err(|e| e.to_string())?;
+    watcher
+        .watch(&folder, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Could not watch {}: {e}", folder.display()))?;
+    log::info!("Watching {} for new books", folder.display());
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(import_settled(app, config, rx));
+    Ok(watcher)
+}
+
+/// Imports each file reported by the watcher once it has settled. Ends when
+/// the watcher is dropped.
+async fn import_settled(
+    app: tauri::AppHandle,
+    config: WatchFolderConfig,
+    mut rx: mpsc::UnboundedReceiver<PathBuf>,
+) {
+    // Size of each file at the last check; `None` until it has been checked.
+    let mut pending: HashMap<PathBuf, Option<u64>> = HashMap::new();
+    let mut interval = tokio::time::interval(SETTLE);
+    loop {
+        interval.tick().await;
+        let stopped = loop {
+            match rx.try_recv() {
+                Ok(path) => {
+                    pending.insert(path, None);
+                }
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        if stopped {
+            return;
+        }
+
+        let mut settled = Vec::new();
+        pending.retain(|path, last_size| {
+            let Ok(size) = std::fs::metadata(path).map(|m| m.len()) else {
+                // Moved or deleted before it settled.
+                return false;
+            };
+            if size > 0 && *last_size == Some(size) {
+                settled.push(path.clone());
+                return false;
+            }
+            *last_size = Some(size);
+            true
+        });
+        for path in settled {
+            import_file(&app, &config, path).await;
+        }
+    }
+}
+
+async fn import_file(app: &tauri::AppHandle, config: &WatchFolderConfig, path: PathBuf) {
+    let filename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (handle, config_copy, name) = (app.clone(), config.clone(), filename.clone());
+    let result = run_blocking(move || {
+        let conn = handle.state::<DbState>().conn()?;
+        let books_dir = books_dir(&handle, &conn)?;
+        let (book, added) = import::import_file(
+            &conn,
+            &books_dir,
+            &path,
+            &mut |_, _| Ok(()),
+            &mut conversion_progress(&handle, &name),
+        )?;
+        if added {
+            events::emit(&handle, DataEvent::BookAdded(book.clone()));
+        }
+        // The book is in the library by now, so failing to tidy up isn't an
+        // import failure.
+        if let Err(e) = watch_folder::after_import(&config_copy, &path) {
+            log::warn!("Could not move or delete {name} after importing it: {e}");
+        }
+        Ok((book, added))
+    })
+    .await;
+
+    let event = match result {
+        // A kept file already in the library shows up again whenever it
+        // changes; there's nothing to report.
+        Ok((_, false)) if config.after_import == watch_folder::AfterImport::Keep => return,
+        Ok((book, added)) => WatchFolderImport {
+            filename,
+            book_id: Some(book.id),
+            added,
+            error: None,
+        },
+        Err(e) => {
+            log::warn!("Could not import {filename} from the watch folder: {e}");
+            WatchFolderImport {
+                filename,
+                book_id: None,
+                added: false,
+                error: Some(e),
+            }
+        }
+    };
+    if let Err(e) = app.emit("watch-folder://imported", event) {
+        log::warn!("Failed to emit watch-folder://imported: {e}");
+    }
+}
@@ -0,0 +1,1414 @@
+//! Converts FB2 and MOBI books to EPUB on import so the reader only ever has to
+//! render one reflowable format.
+//!
+//! FB2 is plain XML and converts losslessly enough: sections become chapters,
+//! embedded `<binary>` images are carried over, and the `notes` body becomes a
+//! linked notes chapter. MOBI files are unpacked from their PalmDB container
+//! (PalmDOC-compressed text only); their HTML is simplified to paragraphs,
+//! headings and basic inline emphasis. HUFF/CDIC-compressed MOBIs can't be
+//! decoded here and are reported as unsupported so the caller can store them
+//! as-is.
+
+use base64::Engine;
+use quick_xml::escape::{escape, resolve_xml_entity};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+/// Reports conversion progress as a stage name and a fraction in `0.0..=1.0`.
+pub type Progress<'a> = &'a mut dyn FnMut(&str, f64);
+
+pub struct Converted {
+    pub epub: Vec<u8>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Cover image as `(mime type, bytes)`.
+    pub cover: Option<(String, Vec<u8>)>,
+}
+
+// ---------------------------------------------------------------------------
+// FB2
+// ---------------------------------------------------------------------------
+
+pub fn fb2_to_epub(data: &[u8], progress: Progress) -> Result<Converted, String> {
+    progress("parsing", 0.0);
+    let xml = decode_xml(data);
+    let mut reader = Reader::from_str(&xml);
+
+    let mut fb2 = Fb2Writer::default();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid FB2 at byte {}: {e}", reader.buffer_position()))?;
+        match event {
+            Event::Start(e) => fb2.start(&e, false),
+            Event::Empty(e) => fb2.start(&e, true),
+            Event::End(e) => fb2.end(&String::from_utf8_lossy(e.local_name().as_ref())),
+            Event::Text(t) => fb2.text(&t.xml_content().map_err(|e| e.to_string())?),
+            Event::CData(t) => fb2.text(&t.decode().map_err(|e| e.to_string())?),
+            Event::GeneralRef(r) => {
+                if let Some(c) = r.resolve_char_ref().map_err(|e| e.to_string())? {
+                    fb2.text(&c.to_string());
+                } else {
+                    let name = r.decode().map_err(|e| e.to_string())?;
+                    fb2.text(resolve_xml_entity(&name).unwrap_or(""));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    progress("converting", 0.5);
+    let Fb2Writer {
+        title,
+        authors,
+        lang,
+        cover_id,
+        chapters,
+        notes,
+        binaries,
+        ..
+    } = fb2;
+
+    let mut book = EpubBuilder::new(
+        title.clone().unwrap_or_else(|| "Untitled".to_string()),
+        lang.unwrap_or_else(|| "en".to_string()),
+    );
+    book.author = (!authors.is_empty()).then(|| authors.join(" & "));
+    for (chapter_title, body) in chapters {
+        book.add_chapter(chapter_title, body);
+    }
+    if let Some(notes) = notes {
+        book.add_named_chapter("notes.xhtml", "Notes".to_string(), notes);
+    }
+
+    let mut cover = None;
+    for (id, (mime, bytes)) in binaries {
+        if cover_id.as_deref() == Some(id.as_str()) {
+            cover = Some((mime.clone(), bytes.clone()));
+            book.cover = Some(id.clone());
+        }
+        book.add_image(id, mime, bytes);
+    }
+
+    progress("packaging", 0.9);
+    let epub = book.finish()?;
+    progress("done", 1.0);
+    Ok(Converted {
+        epub,
+        title,
+        author: book.author,
+        cover,
+    })
+}
+
+/// FB2 files declare their encoding in the XML prolog and windows-1251 is still
+/// common, so transcode to UTF-8 before handing the text to the parser.
+fn decode_xml(data: &[u8]) -> String {
+    let head = String::from_utf8_lossy(&data[..data.len().min(200)]).to_lowercase();
+    let label = head
+        .split_once("encoding=")
+        .and_then(|(_, rest)| {
+            let quote = rest.chars().next()?;
+            rest[1..].split(quote).next()
+        })
+        .unwrap_or("utf-8");
+    let encoding = encoding_rs::Encoding::for_label(label.as_bytes()).unwrap_or(encoding_rs::UTF_8);
+    let (text, _, _) = encoding.decode(data);
+    // The parser would otherwise trust the (now wrong) declared encoding.
+    match text.find("?>") {
+        Some(end) if text.trim_start().starts_with("<?xml") => text[end + 2..].to_string(),
+        _ => text.into_owned(),
+    }
+}
+
+#[derive(Default)]
+struct Fb2Writer {
+    /// Open elements, by local name.
+    path: Vec<String>,
+    title: Option<String>,
+    authors: Vec<String>,
+    author_parts: Vec<String>,
+    lang: Option<String>,
+    cover_id: Option<String>,
+
+    in_notes_body: bool,
+    /// XHTML closing tags for each open element inside a body, or `None` when
+    /// the element produced no markup.
+    closers: Vec<Option<&'static str>>,
+    /// Nesting depth of `<title>` elements and the number of `<p>`s seen in
+    /// the current one; title paragraphs become line breaks in a heading.
+    title_depth: usize,
+    title_paragraphs: usize,
+    current: String,
+    current_title: String,
+    chapters: Vec<(String, String)>,
+    notes: Option<String>,
+
+    binary: Option<(String, String)>,
+    binary_data: String,
+    binaries: Vec<(String, (String, Vec<u8>))>,
+
+    meta_text: String,
+}
+
+impl Fb2Writer {
+    fn in_path(&self, names: &[&str]) -> bool {
+        self.path.len() >= names.len() && self.path[self.path.len() - names.len()..] == *names
+    }
+
+    fn in_body(&self) -> bool {
+        self.path.iter().any(|p| p == "body")
+    }
+
+    /// Depth of the innermost open `<section>` (1 for a top-level section).
+    fn section_depth(&self) -> usize {
+        self.path.iter().filter(|p| *p == "section").count()
+    }
+
+    fn in_chapter_title(&self) -> bool {
+        self.title_depth > 0 && self.section_depth() == 1 && !self.in_notes_body
+    }
+
+    fn start(&mut self, e: &BytesStart, empty: bool) {
+        let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+        let attr = |key: &str| attribute(e, key);
+
+        match name.as_str() {
+            "body" => {
+                self.in_notes_body =
+                    matches!(attr("name").as_deref(), Some("notes") | Some("comments"));
+                if self.in_notes_body {
+                    self.flush_chapter();
+                    self.notes.get_or_insert_with(String::new);
+                }
+            }
+            "binary" => {
+                self.binary = Some((
+                    attr("id").unwrap_or_default(),
+                    attr("content-type").unwrap_or_else(|| "image/jpeg".to_string()),
+                ));
+                self.binary_data.clear();
+            }
+            "image" if self.in_path(&["coverpage"]) => {
+                self.cover_id = attr("href").map(|h| h.trim_start_matches('#').to_string());
+            }
+            _ => {}
+        }
+
+        if self.in_body() && !self.in_notes_body && name == "section" && self.section_depth() == 0 {
+            // Anything before the first section (body title, epigraphs) gets
+            // a chapter of its own.
+            self.flush_chapter();
+        }
+
+        if self.in_body() || name == "body" {
+            let closer = self.open_markup(&name, e, empty);
+            if !empty {
+                self.closers.push(closer);
+            }
+        }
+
+        if empty {
+            return;
+        }
+        self.path.push(name);
+        self.meta_text.clear();
+    }
+
+    fn end(&mut self, name: &str) {
+        if self.in_body() {
+            if let Some(Some(closer)) = self.closers.pop() {
+                self.push_markup(closer);
+            }
+            if name == "title" {
+                self.title_depth -= 1;
+            }
+        }
+
+        let meta = std::mem::take(&mut self.meta_text);
+        let meta = meta.trim();
+        if self.in_path(&["title-info", "book-title"]) {
+            self.title = Some(meta.to_string()).filter(|t| !t.is_empty());
+        } else if self.in_path(&["title-info", "lang"]) {
+            self.lang = Some(meta.to_string()).filter(|l| !l.is_empty());
+        } else if self.in_path(&["title-info", "author", "first-name"])
+            || self.in_path(&["title-info", "author", "middle-name"])
+            || self.in_path(&["title-info", "author", "last-name"])
+            || self.in_path(&["title-info", "author", "nickname"])
+        {
+            if !meta.is_empty() {
+                self.author_parts.push(meta.to_string());
+            }
+        } else if self.in_path(&["title-info", "author"]) {
+            let author = std::mem::take(&mut self.author_parts).join(" ");
+            if !author.is_empty() {
+                self.authors.push(author);
+            }
+        } else if name == "binary" {
+            if let Some((id, mime)) = self.binary.take() {
+                let cleaned: String = self
+                    .binary_data
+                    .chars()
+                    .filter(|c| !c.is_whitespace())
+                    .collect();
+                if let Ok(bytes) = base64::engine::general_purpose::STANDARD.decode(cleaned) {
+                    self.binaries.push((id, (mime, bytes)));
+                }
+            }
+        }
+
+        self.path.pop();
+        if name == "body" {
+            self.flush_chapter();
+            self.in_notes_body = false;
+        } else if name == "section"
+            && self.in_body()
+            && !self.in_notes_body
+            && self.section_depth() == 0
+        {
+            self.flush_chapter();
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.binary.is_some() {
+            self.binary_data.push_str(text);
+        } else if self.in_body() {
+            if self.in_chapter_title() {
+                self.current_title.push_str(text);
+            }
+            self.push_markup(&escape(text));
+        } else {
+            self.meta_text.push_str(text);
+        }
+    }
+
+    fn push_markup(&mut self, markup: &str) {
+        if self.in_notes_body {
+            self.notes.get_or_insert_with(String::new).push_str(markup);
+        } else {
+            self.current.push_str(markup);
+        }
+    }
+
+    /// Writes the opening XHTML for an FB2 element and returns its closing tag.
+    fn open_markup(&mut self, name: &str, e: &BytesStart, empty: bool) -> Option<&'static str> {
+        let attr = |key: &str| attribute(e, key);
+        let id = attr("id")
+            .map(|id| format!(" id=\"{}\"", escape(&id)))
+            .unwrap_or_default();
+
+        let (open, close) = match name {
+            "title" => {
+                if !empty {
+                    self.title_depth += 1;
+                }
+                self.title_paragraphs = 0;
+                let level = (self.section_depth() + 1).min(6);
+                let (open, close) = match level {
+                    1 => ("<h1", "</h1>"),
+                    2 => ("<h2", "</h2>"),
+                    3 => ("<h3", "</h3>"),
+                    4 => ("<h4", "</h4>"),
+                    5 => ("<h5", "</h5>"),
+                    _ => ("<h6", "</h6>"),
+                };
+                (format!("{open}{id}>"), close)
+            }
+            "p" if self.title_depth > 0 => {
+                self.title_paragraphs += 1;
+                if self.in_chapter_title() {
+                    self.current_title.push(' ');
+                }
+                let open = if self.title_paragraphs > 1 {
+                    "<br/>"
+                } else {
+                    ""
+                };
+                (open.to_string(), "")
+            }
+            "p" => (format!("<p{id}>"), "</p>"),
+            "v" => (format!("<p class=\"verse\"{id}>"), "</p>"),
+            "text-author" => (format!("<p class=\"text-author\"{id}>"), "</p>"),
+            "subtitle" => (format!("<p class=\"subtitle\"{id}>"), "</p>"),
+            "emphasis" => ("<em>".to_string(), "</em>"),
+            "strong" => ("<strong>".to_string(), "</strong>"),
+            "strikethrough" => ("<del>".to_string(), "</del>"),
+            "sub" => ("<sub>".to_string(), "</sub>"),
+            "sup" => ("<sup>".to_string(), "</sup>"),
+            "code" => ("<code>".to_string(), "</code>"),
+            "epigraph" => (
+                format!("<blockquote class=\"epigraph\"{id}>"),
+                "</blockquote>",
+            ),
+            "cite" => (format!("<blockquote{id}>"), "</blockquote>"),
+            "poem" => (format!("<div class=\"poem\"{id}>"), "</div>"),
+            "stanza" => ("<div class=\"stanza\">".to_string(), "</div>"),
+            "section" => (format!("<div class=\"section\"{id}>"), "</div>"),
+            "table" => (format!("<table{id}>"), "</table>"),
+            "tr" => ("<tr>".to_string(), "</tr>"),
+            "td" => ("<td>".to_string(), "</td>"),
+            "th" => ("<th>".to_string(), "</th>"),
+            "empty-line" => ("<br/>".to_string(), ""),
+            "image" => {
+                let src = attr("href").unwrap_or_default();
+                let src = src.trim_start_matches('#');
+                let img = format!("<img src=\"images/{}\" alt=\"\"/>", escape(src));
+                if self
+                    .path
+                    .last()
+                    .is_some_and(|p| p == "section" || p == "body")
+                {
+                    (format!("<div class=\"image\">{img}"), "</div>")
+                } else {
+                    (img, "")
+                }
+            }
+            "a" => match attr("href") {
+                Some(href) if href.starts_with('#') => {
+                    (format!("<a href=\"notes.xhtml{}\">", escape(&href)), "</a>")
+                }
+                Some(href) => (format!("<a href=\"{}\">", escape(&href)), "</a>"),
+                None => ("<span>".to_string(), "</span>"),
+            },
+            _ => return None,
+        };
+
+        self.push_markup(&open);
+        if empty {
+            self.push_markup(close);
+            None
+        } else {
+            Some(close).filter(|c| !c.is_empty())
+        }
+    }
+
+    fn flush_chapter(&mut self) {
+        let body = std::mem::take(&mut self.current);
+        let title = collapse_whitespace(std::mem::take(&mut self.current_title).trim());
+        if !body.trim().is_empty() {
+            // Untitled sections fall back to "Chapter N" in the table of
+            // contents; only the front matter is labelled with the book title.
+            let title = if title.is_empty() && self.chapters.is_empty() {
+                self.title.clone().unwrap_or_default()
+            } else {
+                title
+            };
+            self.chapters.push((title, body));
+        }
+    }
+}
+
+/// Looks up an attribute by local name, so `l:href` and `xlink:href` both
+/// match `href`.
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+    e.attributes().flatten().find_map(|a| {
+        (a.key.local_name().as_ref() == key.as_bytes())
+            .then(|| a.unescape_value().ok().map(|v| v.into_owned()))
+            .flatten()
+    })
+}
+
+// ---------------------------------------------------------------------------
+// MOBI
+// ---------------------------------------------------------------------------
+
+const COMPRESSION_NONE: u16 = 1;
+const COMPRESSION_PALMDOC: u16 = 2;
+const COMPRESSION_HUFF_CDIC: u16 = 17480;
+
+/// Converts a MOBI (or plain PalmDOC) file. Returns `Ok(None)` when the file
+/// is valid but uses a compression scheme this module can't decode.
+pub fn mobi_to_epub(data: &[u8], progress: Progress) -> Result<Option<Converted>, String> {
+    progress("parsing", 0.0);
+    let pdb = PalmDb::parse(data)?;
+    let rec0 = pdb.record(0).ok_or("MOBI file has no header record")?;
+    if rec0.len() < 16 {
+        return Err("MOBI header record is truncated".to_string());
+    }
+
+    let compression = be_u16(rec0, 0);
+    let text_length = be_u32(rec0, 4) as usize;
+    let text_records = be_u16(rec0, 8) as usize;
+    let encryption = be_u16(rec0, 12);
+    if encryption != 0 {
+        return Err("DRM-protected MOBI files can't be imported".to_string());
+    }
+    if compression == COMPRESSION_HUFF_CDIC {
+        return Ok(None);
+    }
+    if compression != COMPRESSION_NONE && compression != COMPRESSION_PALMDOC {
+        return Err(format!("Unknown MOBI compression type {compression}"));
+    }
+
+    let mobi = MobiHeader::parse(rec0);
+    // The header's length is only a hint: PalmDOC at most expands two bytes
+    // into ten, so a file can't hold more text than five times its size.
+    let mut raw = Vec::with_capacity(text_length.min(data.len().saturating_mul(5)));
+    for i in 1..=text_records {
+        let record = pdb
+            .record(i)
+            .ok_or_else(|| format!("MOBI text record {i} is missing"))?;
+        let record = &record[..record.len() - trailing_entries_size(record, mobi.extra_flags)];
+        if compression == COMPRESSION_PALMDOC {
+            palmdoc_decompress(record, &mut raw)?;
+        } else {
+            raw.extend_from_slice(record);
+        }
+        progress("decompressing", 0.6 * i as f64 / text_records.max(1) as f64);
+    }
+    raw.truncate(text_length);
+
+    let text = if mobi.text_encoding == 1252 {
+        encoding_rs::WINDOWS_1252.decode(&raw).0.into_owned()
+    } else {
+        String::from_utf8_lossy(&raw).into_owned()
+    };
+
+    progress("converting", 0.7);
+    let exth = mobi.exth.as_ref();
+    let title = exth
+        .and_then(|x| x.string(503))
+        .or_else(|| mobi.full_name.clone())
+        .or_else(|| Some(pdb.name.clone()))
+        .filter(|t| !t.is_empty());
+    let author = exth
+        .map(|x| x.strings(100).join(" & "))
+        .filter(|a| !a.is_empty());
+    let lang = "en".to_string();
+
+    let mut book = EpubBuilder::new(
+        title.clone().unwrap_or_else(|| "Untitled".to_string()),
+        lang,
+    );
+    book.author = author.clone();
+
+    let image_record = |index: usize| -> Option<(String, Vec<u8>)> {
+        let bytes = pdb.record((mobi.first_image? as usize).checked_add(index)?)?;
+        Some((image_mime(bytes)?.to_string(), bytes.to_vec()))
+    };
+
+    let mut images = HashMap::new();
+    if mobi.is_mobi {
+        for (n, chunk) in split_pagebreaks(&text).into_iter().enumerate() {
+            let chapter = simplify_html(chunk);
+            for index in chapter.images {
+                if let Some((mime, bytes)) = image_record(index - 1) {
+                    images.entry(index).or_insert((mime, bytes));
+                }
+            }
+            if !chapter.body.is_empty() {
+                let title = chapter
+                    .title
+                    .unwrap_or_else(|| format!("Chapter {}", n + 1));
+                book.add_chapter(title, chapter.body);
+            }
+        }
+    } else {
+        // Plain PalmDOC: paragraphs are separated by line breaks.
+        let body: String = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| format!("<p>{}</p>\n", escape(l)))
+            .collect();
+        book.add_chapter(title.clone().unwrap_or_default(), body);
+    }
+
+    let mut cover = None;
+    if let Some(offset) = exth.and_then(|x| x.u32(201)) {
+        if let Some((mime, bytes)) = image_record(offset as usize) {
+            let name = format!("cover{}", image_extension(&mime));
+            cover = Some((mime.clone(), bytes.clone()));
+            book.cover = Some(name.clone());
+            book.add_image(name, mime, bytes);
+        }
+    }
+    let mut indexes: Vec<_> = images.into_iter().collect();
+    indexes.sort_by_key(|(index, _)| *index);
+    for (index, (mime, bytes)) in indexes {
+        book.add_image(mobi_image_name(index), mime, bytes);
+    }
+
+    progress("packaging", 0.9);
+    let epub = book.finish()?;
+    progress("done", 1.0);
+    Ok(Some(Converted {
+        epub,
+        title,
+        author,
+        cover,
+    }))
+}
+
+struct PalmDb<'a> {
+    data: &'a [u8],
+    name: String,
+    offsets: Vec<usize>,
+}
+
+impl<'a> PalmDb<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 78 {
+            return Err("File is too small to be a MOBI book".to_string());
+        }
+        let kind = &data[60..68];
+        if kind != b"BOOKMOBI" && kind != b"TEXtREAd" {
+            return Err("Not a MOBI or PalmDOC file".to_string());
+        }
+        let name = String::from_utf8_lossy(&data[..32])
+            .trim_end_matches('\0')
+            .replace('_', " ");
+        let count = be_u16(data, 76) as usize;
+        if data.len() < 78 + count * 8 {
+            return Err("MOBI record list is truncated".to_string());
+        }
+        let offsets = (0..count)
+            .map(|i| be_u32(data, 78 + i * 8) as usize)
+            .collect();
+        Ok(Self {
+            data,
+            name,
+            offsets,
+        })
+    }
+
+    fn record(&self, index: usize) -> Option<&'a [u8]> {
+        let start = *self.offsets.get(index)?;
+        let end = self
+            .offsets
+            .get(index + 1)
+            .copied()
+            .unwrap_or(self.data.len());
+        self.data.get(start..end)
+    }
+}
+
+struct MobiHeader {
+    is_mobi: bool,
+    text_encoding: u32,
+    full_name: Option<String>,
+    first_image: Option<u32>,
+    extra_flags: u16,
+    exth: Option<Exth>,
+}
+
+impl MobiHeader {
+    fn parse(rec0: &[u8]) -> Self {
+        let mut header = MobiHeader {
+            is_mobi: false,
+            text_encoding: 1252,
+            full_name: None,
+            first_image: None,
+            extra_flags: 0,
+            exth: None,
+        };
+        if rec0.len() < 132 || &rec0[16..20] != b"MOBI" {
+            return header;
+        }
+        header.is_mobi = true;
+        let header_len = be_u32(rec0, 20) as usize;
+        header.text_encoding = be_u32(rec0, 28);
+
+        let name_offset = be_u32(rec0, 84) as usize;
+        let name_len = be_u32(rec0, 88) as usize;
+        header.full_name = name_offset
+            .checked_add(name_len)
+            .and_then(|name_end| rec0.get(name_offset..name_end))
+            .map(|bytes| decode_mobi_string(bytes, header.text_encoding));
+
+        let first_image = be_u32(rec0, 108);
+        header.first_image = (first_image != u32::MAX).then_some(first_image);
+        if header_len >= 0xE4 && rec0.len() >= 244 {
+            header.extra_flags = be_u16(rec0, 242);
+        }
+        if be_u32(rec0, 128) & 0x40 != 0 {
+            header.exth = rec0
+                .get(16 + header_len..)
+                .and_then(|bytes| Exth::parse(bytes, header.text_encoding));
+        }
+        header
+    }
+}
+
+/// The EXTH metadata block: a list of typed records (100 = author,
+/// 201 = cover image offset, 503 = updated title, ...).
+struct Exth {
+    records: Vec<(u32, Vec<u8>)>,
+    encoding: u32,
+}
+
+impl Exth {
+    fn parse(data: &[u8], encoding: u32) -> Option<Self> {
+        if data.len() < 12 || &data[..4] != b"EXTH" {
+            return None;
+        }
+        let count = be_u32(data, 8) as usize;
+        // Each record takes at least 8 bytes, whatever the count claims.
+        let mut records = Vec::with_capacity(count.min(data.len() / 8));
+        let mut pos = 12;
+        for _ in 0..count {
+            if pos + 8 > data.len() {
+                break;
+            }
+            let kind = be_u32(data, pos);
+            let len = be_u32(data, pos + 4) as usize;
+            if len < 8 || pos + len > data.len() {
+                break;
+            }
+            records.push((kind, data[pos + 8..pos + len].to_vec()));
+            pos += len;
+        }
+        Some(Self { records, encoding })
+    }
+
+    fn strings(&self, kind: u32) -> Vec<String> {
+        self.records
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, v)| decode_mobi_string(v, self.encoding).trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    fn string(&self, kind: u32) -> Option<String> {
+        self.strings(kind).into_iter().next()
+    }
+
+    fn u32(&self, kind: u32) -> Option<u32> {
+        self.records
+            .iter()
+            .find(|(k, v)| *k == kind && v.len() == 4)
+            .map(|(_, v)| be_u32(v, 0))
+    }
+}
+
+fn decode_mobi_string(bytes: &[u8], encoding: u32) -> String {
+    if encoding == 1252 {
+        encoding_rs::WINDOWS_1252.decode(bytes).0.into_owned()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+fn be_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
+}
+
+fn be_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Size of the trailing entries appended to a text record, as described by the
+/// header's extra-data flags. Each set bit above bit 0 is an entry whose size
+/// is stored as a backwards variable-width integer at the end of the record;
+/// bit 0 marks multibyte-character overlap bytes.
+fn trailing_entries_size(record: &[u8], flags: u16) -> usize {
+    let mut size = 0;
+    let mut bits = flags >> 1;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            let end = record.len().saturating_sub(size);
+            let mut value = 0usize;
+            let mut shift = 0;
+            for &byte in record[..end].iter().rev() {
+                value |= ((byte & 0x7F) as usize) << shift;
+                shift += 7;
+                if byte & 0x80 != 0 || shift >= 28 {
+                    break;
+                }
+            }
+            size += value;
+        }
+        bits >>= 1;
+    }
+    if flags & 1 != 0 {
+        if let Some(&byte) = record
+            .len()
+            .checked_sub(size + 1)
+            .and_then(|i| record.get(i))
+        {
+            size += (byte & 0x3) as usize + 1;
+        }
+    }
+    size.min(record.len())
+}
+
+/// PalmDOC's LZ77 variant.
+fn palmdoc_decompress(input: &[u8], out: &mut Vec<u8>) -> Result<(), String> {
+    let start = out.len();
+    let mut i = 0;
+    while i < input.len() {
+        let c = input[i];
+        i += 1;
+        match c {
+            1..=8 => {
+                let end = (i + c as usize).min(input.len());
+                out.extend_from_slice(&input[i..end]);
+                i = end;
+            }
+            0x00 | 0x09..=0x7F => out.push(c),
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(c ^ 0x80);
+            }
+            0x80..=0xBF => {
+                let next = *input.get(i).ok_or("Truncated PalmDOC record")?;
+                i += 1;
+                let pair = ((c as usize) << 8) | next as usize;
+                let distance = (pair >> 3) & 0x7FF;
+                let length = (pair & 0x7) + 3;
+                if distance == 0 || distance > out.len() - start {
+                    return Err("Corrupt PalmDOC back-reference".to_string());
+                }
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn split_pagebreaks(html: &str) -> Vec<&str> {
+    let lower = html.to_ascii_lowercase();
+    let mut chunks = Vec::new();
+    let mut last = 0;
+    let mut search = 0;
+    while let Some(pos) = lower[search..].find("<mbp:pagebreak") {
+        let pos = search + pos;
+        chunks.push(&html[last..pos]);
+        let end = lower[pos..].find('>').map_or(html.len(), |e| pos + e + 1);
+        last = end;
+        search = end;
+    }
+    chunks.push(&html[last..]);
+    chunks
+}
+
+struct SimplifiedChapter {
+    title: Option<String>,
+    body: String,
+    /// 1-based `recindex` values of referenced images.
+    images: Vec<usize>,
+}
+
+/// Reduces Kindle HTML to well-formed XHTML: paragraphs, headings, images and
+/// a handful of inline tags. MOBI markup is rarely balanced, so inline tags
+/// are tracked on a stack and closed at every block boundary.
+fn simplify_html(html: &str) -> SimplifiedChapter {
+    let mut out = SimplifiedChapter {
+        title: None,
+        body: String::new(),
+        images: Vec::new(),
+    };
+    let mut para = String::new();
+    let mut para_has_text = false;
+    let mut heading: Option<u8> = None;
+    let mut heading_text = String::new();
+    let mut inline: Vec<&'static str> = Vec::new();
+    let mut skip_depth = 0usize;
+
+    let flush = |out: &mut SimplifiedChapter,
+                 para: &mut String,
+                 has_text: &mut bool,
+                 heading: &mut Option<u8>,
+                 heading_text: &mut String,
+                 inline: &[&'static str]| {
+        for tag in inline.iter().rev() {
+            para.push_str(&format!("</{tag}>"));
+        }
+        if *has_text {
+            match heading.take() {
+                Some(level) => {
+                    out.body
+                        .push_str(&format!("<h{level}>{}</h{level}>\n", para.trim()));
+                    if out.title.is_none() {
+                        out.title = Some(heading_text.trim().to_string());
+                    }
+                }
+                None => out.body.push_str(&format!("<p>{}</p>\n", para.trim())),
+            }
+        }
+        *heading = None;
+        heading_text.clear();
+        para.clear();
+        *has_text = false;
+        for tag in inline {
+            para.push_str(&format!("<{tag}>"));
+        }
+    };
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let (text, tag) = match rest.find('<') {
+            Some(0) => {
+                let end = rest.find('>').map_or(rest.len(), |e| e + 1);
+                let tag = &rest[..end];
+                rest = &rest[end..];
+                ("", Some(tag))
+            }
+            Some(pos) => {
+                let text = &rest[..pos];
+                rest = &rest[pos..];
+                (text, None)
+            }
+            None => {
+                let text = rest;
+                rest = "";
+                (text, None)
+            }
+        };
+
+        if !text.is_empty() {
+            if skip_depth > 0 {
+                continue;
+            }
+            let decoded = decode_html_entities(text);
+            let collapsed = collapse_whitespace(&decoded);
+            if collapsed.trim().is_empty() {
+                if para_has_text && !collapsed.is_empty() {
+                    para.push(' ');
+                }
+                continue;
+            }
+            para.push_str(&escape(collapsed.as_str()));
+            if heading.is_some() {
+                heading_text.push_str(&collapsed);
+            }
+            para_has_text = true;
+            continue;
+        }
+
+        let Some(tag) = tag else { continue };
+        let inner = tag.trim_start_matches('<').trim_end_matches('>');
+        let closing = inner.starts_with('/');
+        let inner = inner.trim_start_matches('/');
+        let name: String = inner
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '/')
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match name.as_str() {
+            "head" | "script" | "style" => {
+                if closing {
+                    skip_depth = skip_depth.saturating_sub(1);
+                } else if !inner.ends_with('/') {
+                    skip_depth += 1;
+                }
+            }
+            _ if skip_depth > 0 => {}
+            "p" | "div" | "br" | "li" | "blockquote" | "tr" | "hr" | "table" | "ul" | "ol" => {
+                flush(
+                    &mut out,
+                    &mut para,
+                    &mut para_has_text,
+                    &mut heading,
+                    &mut heading_text,
+                    &inline,
+                );
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                flush(
+                    &mut out,
+                    &mut para,
+                    &mut para_has_text,
+                    &mut heading,
+                    &mut heading_text,
+                    &inline,
+                );
+                if !closing {
+                    heading = Some(name.as_bytes()[1] - b'0');
+                }
+            }
+            "img" => {
+                let index = html_attr(inner, "recindex").and_then(|v| v.parse::<usize>().ok());
+                if let Some(index) = index.filter(|i| *i > 0) {
+                    flush(
+                        &mut out,
+                        &mut para,
+                        &mut para_has_text,
+                        &mut heading,
+                        &mut heading_text,
+                        &inline,
+                    );
+                    out.body.push_str(&format!(
+                        "<div class=\"image\"><img src=\"images/{}\" alt=\"\"/></div>\n",
+                        mobi_image_name(index)
+                    ));
+                    out.images.push(index);
+                }
+            }
+            "b" | "strong" | "i" | "em" | "sup" | "sub" => {
+                let mapped = match name.as_str() {
+                    "b" | "strong" => "strong",
+                    "i" | "em" => "em",
+                    "sup" => "sup",
+                    _ => "sub",
+                };
+                if closing {
+                    if let Some(pos) = inline.iter().rposition(|t| *t == mapped) {
+                        for tag in inline.drain(pos..).rev() {
+                            para.push_str(&format!("</{tag}>"));
+                        }
+                    }
+                } else if !inner.ends_with('/') {
+                    para.push_str(&format!("<{mapped}>"));
+                    inline.push(mapped);
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(
+        &mut out,
+        &mut para,
+        &mut para_has_text,
+        &mut heading,
+        &mut heading_text,
+        &inline,
+    );
+    out.title = out.title.filter(|t| !t.is_empty());
+    out
+}
+
+fn html_attr(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let pos = lower.find(&format!("{name}="))?;
+    let value = &tag[pos + name.len() + 1..];
+    let value = match value.chars().next()? {
+        q @ ('"' | '\'') => value[1..].split(q).next()?,
+        _ => value
+            .split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+            .next()?,
+    };
+    Some(value.to_string())
+}
+
+fn decode_html_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let Some(end) = rest[..rest.len().min(12)].find(';') else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !last_space {
+                out.push(' ');
+            }
+            last_space = true;
+        } else {
+            out.push(c);
+            last_space = false;
+        }
+    }
+    out
+}
+
+fn mobi_image_name(recindex: usize) -> String {
+    format!("image{recindex:05}")
+}
+
+pub(crate) fn image_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'G', b'I', b'F', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+pub(crate) fn image_extension(mime: &str) -> &'static str {
+    match mime {
+        "image/png" => ".png",
+        "image/gif" => ".gif",
+        "image/webp" => ".webp",
+        _ => ".jpg",
+    }
+}
+
+// ---------------------------------------------------------------------------
+// EPUB packaging
+// ---------------------------------------------------------------------------
+
+const STYLESHEET: &str = "body { margin: 0 5%; }
+p { margin: 0; text-indent: 1.5em; }
+h1, h2, h3, h4, h5, h6 { text-align: center; text-indent: 0; }
+p.verse { text-indent: 0; margin-left: 2em; }
+p.subtitle { text-align: center; text-indent: 0; font-weight: bold; margin: 1em 0; }
+p.text-author { text-align: right; font-style: italic; }
+blockquote.epigraph { margin: 1em 0 1em 30%; font-style: italic; }
+div.stanza { margin: 1em 0; }
+div.image { text-align: center; margin: 1em 0; }
+img { max-width: 100%; }
+";
+
+/// A minimal EPUB 2 writer: one XHTML file per chapter, an NCX table of
+/// contents, and images referenced as `images/<name>`.
+pub(crate) struct EpubBuilder {
+    title: String,
+    lang: String,
+    pub(crate) author: Option<String>,
+    /// Replaces the book stylesheet, for documents that aren't books.
+    pub(crate) stylesheet: &'static str,
+    cover: Option<String>,
+    chapters: Vec<(String, String, String)>,
+    images: Vec<(String, String, Vec<u8>)>,
+}
+
+impl EpubBuilder {
+    pub(crate) fn new(title: String, lang: String) -> Self {
+        Self {
+            title,
+            lang,
+            author: None,
+            stylesheet: STYLESHEET,
+            cover: None,
+            chapters: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_chapter(&mut self, title: String, body: String) {
+        let file = format!("chapter{:04}.xhtml", self.chapters.len() + 1);
+        self.chapters.push((file, title, body));
+    }
+
+    fn add_named_chapter(&mut self, file: &str, title: String, body: String) {
+        self.chapters.push((file.to_string(), title, body));
+    }
+
+    pub(crate) fn add_image(&mut self, name: String, mime: String, bytes: Vec<u8>) {
+        if !self.images.iter().any(|(n, _, _)| *n == name) {
+            self.images.push((name, mime, bytes));
+        }
+    }
+
+    pub(crate) fn finish(&self) -> Result<Vec<u8>, String> {
+        use zip::write::SimpleFileOptions;
+        use zip::CompressionMethod;
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        let mut add =
+            |name: &str, bytes: &[u8], options: SimpleFileOptions| -> Result<(), String> {
+                zip.start_file(name, options).map_err(|e| e.to_string())?;
+                zip.write_all(bytes).map_err(|e| e.to_string())
+            };
+
+        // The mimetype entry must come first and be stored uncompressed.
+        add("mimetype", b"application/epub+zip", stored)?;
+        add(
+            "META-INF/container.xml",
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+            deflated,
+        )?;
+        add("OEBPS/content.opf", self.opf().as_bytes(), deflated)?;
+        add("OEBPS/toc.ncx", self.ncx().as_bytes(), deflated)?;
+        add("OEBPS/style.css", self.stylesheet.as_bytes(), deflated)?;
+        for (file, title, body) in &self.chapters {
+            let xhtml = format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                 <!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.1//EN\" \"http://www.w3.org/TR/xhtml11/DTD/xhtml11.dtd\">\n\
+                 <html xmlns=\"http://www.w3.org/1999/xhtml\">\n\
+                 <head><title>{}</title><link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/></head>\n\
+                 <body>\n{}\n</body>\n</html>\n",
+                escape(title.as_str()),
+                body
+            );
+            add(&format!("OEBPS/{file}"), xhtml.as_bytes(), deflated)?;
+        }
+        for (name, _, bytes) in &self.images {
+            add(&format!("OEBPS/images/{name}"), bytes, stored)?;
+        }
+
+        let cursor = zip.finish().map_err(|e| e.to_string())?;
+        Ok(cursor.into_inner())
+    }
+
+    fn identifier(&self) -> String {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.title.hash(&mut hasher);
+        self.author.hash(&mut hasher);
+        for (_, _, body) in &self.chapters {
+            body.hash(&mut hasher);
+        }
+        format!("urn:readme:{:016x}", hasher.finish())
+    }
+
+    fn opf(&self) -> String {
+        let mut manifest = String::from(
+            "    <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n\
+             \x20   <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+        );
+        let mut spine = String::new();
+        for (i, (file, _, _)) in self.chapters.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"chapter{i}\" href=\"{file}\" media-type=\"application/xhtml+xml\"/>\n"
+            ));
+            spine.push_str(&format!("    <itemref idref=\"chapter{i}\"/>\n"));
+        }
+        for (i, (name, mime, _)) in self.images.iter().enumerate() {
+            let id = if self.cover.as_deref() == Some(name.as_str()) {
+                "cover-image".to_string()
+            } else {
+                format!("image{i}")
+            };
+            manifest.push_str(&format!(
+                "    <item id=\"{id}\" href=\"images/{}\" media-type=\"{}\"/>\n",
+                escape(name.as_str()),
+                escape(mime.as_str())
+            ));
+        }
+
+        let creator = self
+            .author
+            .as_deref()
+            .map(|a| {
+                format!(
+                    "    <dc:creator opf:role=\"aut\">{}</dc:creator>\n",
+                    escape(a)
+                )
+            })
+            .unwrap_or_default();
+        let cover = if self.cover.is_some() {
+            "    <meta name=\"cover\" content=\"cover-image\"/>\n"
+        } else {
+            ""
+        };
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"2.0\" unique-identifier=\"bookid\">\n\
+             \x20 <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n\
+             \x20   <dc:identifier id=\"bookid\">{}</dc:identifier>\n\
+             \x20   <dc:title>{}</dc:title>\n\
+             \x20   <dc:language>{}</dc:language>\n\
+             {creator}{cover}\
+             \x20 </metadata>\n\
+             \x20 <manifest>\n{manifest}  </manifest>\n\
+             \x20 <spine toc=\"ncx\">\n{spine}  </spine>\n\
+             </package>\n",
+            self.identifier(),
+            escape(self.title.as_str()),
+            escape(self.lang.as_str()),
+        )
+    }
+
+    fn ncx(&self) -> String {
+        let mut points = String::new();
+        for (i, (file, title, _)) in self.chapters.iter().enumerate() {
+            let label = if title.is_empty() {
+                format!("Chapter {}", i + 1)
+            } else {
+                title.clone()
+            };
+            points.push_str(&format!(
+                "    <navPoint id=\"nav{n}\" playOrder=\"{n}\">\n\
+                 \x20     <navLabel><text>{}</text></navLabel>\n\
+                 \x20     <content src=\"{file}\"/>\n\
+                 \x20   </navPoint>\n",
+                escape(label.as_str()),
+                n = i + 1
+            ));
+        }
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+             \x20 <head><meta name=\"dtb:uid\" content=\"{}\"/></head>\n\
+             \x20 <docTitle><text>{}</text></docTitle>\n\
+             \x20 <navMap>\n{points}  </navMap>\n\
+             </ncx>\n",
+            self.identifier(),
+            escape(self.title.as_str())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PalmDB file of type `kind` holding `records`.
+    fn palm_db(kind: &[u8; 8], records: &[&[u8]]) -> Vec<u8> {
+        let mut data = vec![0; 78];
+        data[..4].copy_from_slice(b"Test");
+        data[60..68].copy_from_slice(kind);
+        data[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = 78 + records.len() * 8;
+        for record in records {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&[0; 4]);
+            offset += record.len();
+        }
+        for record in records {
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    /// A MOBI header record for `text_records` records of uncompressed text
+    /// `text_length` bytes long, with images from record `first_image`.
+    fn mobi_header(text_length: u32, text_records: u16, first_image: u32) -> Vec<u8> {
+        let mut rec0 = vec![0; 248];
+        rec0[..2].copy_from_slice(&COMPRESSION_NONE.to_be_bytes());
+        rec0[4..8].copy_from_slice(&text_length.to_be_bytes());
+        rec0[8..10].copy_from_slice(&text_records.to_be_bytes());
+        rec0[16..20].copy_from_slice(b"MOBI");
+        rec0[20..24].copy_from_slice(&0xE8u32.to_be_bytes());
+        rec0[28..32].copy_from_slice(&65001u32.to_be_bytes());
+        rec0[84..88].copy_from_slice(&u32::MAX.to_be_bytes());
+        rec0[88..92].copy_from_slice(&u32::MAX.to_be_bytes());
+        rec0[108..112].copy_from_slice(&first_image.to_be_bytes());
+        rec0
+    }
+
+    fn decompress(input: &[u8]) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        palmdoc_decompress(input, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn palmdoc_decodes_literals_spaces_and_back_references() {
+        assert_eq!(decompress(b"abc").unwrap(), b"abc");
+        // A run of two bytes copied as they are, even above 0x7F.
+        assert_eq!(
+            decompress(&[2, 0xE9, 0xFF, b'!']).unwrap(),
+            [0xE9, 0xFF, b'!']
+        );
+        // 0xC0 and up is a space followed by the byte with its top bit cleared.
+        assert_eq!(decompress(&[b'a', 0xE2]).unwrap(), b"a b");
+        // Distance 3, length 5: copies overlap the bytes they produce.
+        assert_eq!(
+            decompress(&[b'a', b'b', b'c', 0x80, 0x1A]).unwrap(),
+            b"abcabcab"
+        );
+    }
+
+    #[test]
+    fn palmdoc_rejects_truncated_and_corrupt_input() {
+        assert!(decompress(&[b'a', 0x80]).is_err());
+        // Distance 0, and a distance reaching before the start.
+        assert!(decompress(&[b'a', 0x80, 0x00]).is_err());
+        assert!(decompress(&[b'a', 0x80, 0x10]).is_err());
+        // A literal run cut short stops at the end of the record.
+        assert_eq!(decompress(&[8, b'x', b'y']).unwrap(), b"xy");
+
+        // Back-references stay within this record's output.
+        let mut out = b"earlier record".to_vec();
+        assert!(palmdoc_decompress(&[0x80, 0x18], &mut out).is_err());
+    }
+
+    #[test]
+    fn palm_db_records_stay_within_the_file() {
+        assert!(PalmDb::parse(b"short").is_err());
+        assert!(PalmDb::parse(&palm_db(b"APPLDATA", &[b"x"])).is_err());
+
+        let mut truncated = palm_db(b"BOOKMOBI", &[b"one", b"two"]);
+        truncated.truncate(80);
+        assert!(PalmDb::parse(&truncated).is_err());
+
+        let mut data = palm_db(b"BOOKMOBI", &[b"one", b"two", b"three"]);
+        let pdb = PalmDb::parse(&data).unwrap();
+        assert_eq!(pdb.record(1), Some(&b"two"[..]));
+        assert_eq!(pdb.record(2), Some(&b"three"[..]));
+        assert_eq!(pdb.record(3), None);
+
+        // Offsets past the end of the file, and out of order.
+        data[78 + 8..78 + 12].copy_from_slice(&u32::MAX.to_be_bytes());
+        data[78 + 16..78 + 20].copy_from_slice(&80u32.to_be_bytes());
+        let pdb = PalmDb::parse(&data).unwrap();
+        assert_eq!(pdb.record(0), None);
+        assert_eq!(pdb.record(1), None);
+        assert!(pdb.record(2).is_some());
+    }
+
+    #[test]
+    fn trailing_entries_never_exceed_the_record() {
+        assert_eq!(trailing_entries_size(b"text\x82", 0b10), 2);
+        assert_eq!(trailing_entries_size(&[0x7F, 0x7F, 0x7F, 0x7F], 0xFFFF), 4);
+        assert_eq!(trailing_entries_size(&[], 0xFFFF), 0);
+    }
+
+    #[test]
+    fn hostile_mobi_files_are_rejected_without_panicking() {
+        let no_progress: Progress = &mut |_, _| {};
+        assert!(mobi_to_epub(&palm_db(b"BOOKMOBI", &[]), no_progress).is_err());
+        assert!(mobi_to_epub(&palm_db(b"BOOKMOBI", &[b"short"]), no_progress).is_err());
+
+        // More text records than the file has.
+        let rec0 = mobi_header(10, 5, u32::MAX);
+        let data = palm_db(b"BOOKMOBI", &[&rec0, b"<p>one</p>"]);
+        assert!(mobi_to_epub(&data, no_progress).is_err());
+
+        // An image index so large it can't be a record.
+        let text = format!("<p>Hi</p><img recindex=\"{}\">", usize::MAX);
+        let rec0 = mobi_header(text.len() as u32, 1, 2);
+        let data = palm_db(b"BOOKMOBI", &[&rec0, text.as_bytes()]);
+        let converted = mobi_to_epub(&data, no_progress).unwrap().unwrap();
+        assert!(!converted.epub.is_empty());
+    }
+}
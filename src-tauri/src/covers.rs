@@ -0,0 +1,217 @@
+//! Covers found online for books without one: Open Library and Google Books
+//! are searched by ISBN, or by title and author, and the cover chosen from
+//! their results is downloaded, scaled down and stored on the book as a data
+//! URL like covers taken from the book file.
+
+use crate::convert::image_mime;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const OPEN_LIBRARY_SEARCH: &str = "https://openlibrary.org/search.json";
+const GOOGLE_BOOKS_SEARCH: &str = "https://www.googleapis.com/books/v1/volumes";
+
+/// Results asked of each source.
+const RESULTS: usize = 5;
+
+const MAX_DOWNLOAD_BYTES: usize = 10 * 1024 * 1024;
+
+/// Covers larger than this are scaled down to fit, keeping their shape.
+const MAX_WIDTH: u32 = 600;
+const MAX_HEIGHT: u32 = 900;
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CoverCandidate {
+    /// "Open Library" or "Google Books".
+    pub source: String,
+    pub url: String,
+    /// The edition the cover belongs to, to tell candidates apart.
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+/// What a book is searched by.
+pub struct BookQuery<'a> {
+    pub isbn: Option<&'a str>,
+    pub title: &'a str,
+    pub author: Option<&'a str>,
+}
+
+pub(crate) async fn fetch_json(client: &reqwest::Client, url: Url) -> Result<Value, String> {
+    let text = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// Open Library's search URL for `query`, asking for `fields`.
+pub(crate) fn open_library_url(query: &BookQuery, fields: &str) -> Url {
+    let limit = RESULTS.to_string();
+    let mut params = vec![("fields", fields), ("limit", limit.as_str())];
+    match query.isbn {
+        Some(isbn) => params.push(("isbn", isbn)),
+        None => {
+            params.push(("title", query.title));
+            if let Some(author) = query.author {
+                params.push(("author", author));
+            }
+        }
+    }
+    Url::parse_with_params(OPEN_LIBRARY_SEARCH, params).expect("valid search URL")
+}
+
+/// Google Books' search URL for `query`.
+fn google_books_url(query: &BookQuery) -> Url {
+    let q = match query.isbn {
+        Some(isbn) => format!("isbn:{isbn}"),
+        None => match query.author {
+            Some(author) => format!("intitle:{} inauthor:{author}", query.title),
+            None => format!("intitle:{}", query.title),
+        },
+    };
+    let limit = RESULTS.to_string();
+    Url::parse_with_params(
+        GOOGLE_BOOKS_SEARCH,
+        [("q", q.as_str()), ("maxResults", limit.as_str())],
+    )
+    .expect("valid search URL")
+}
+
+async fn open_library(
+    client: &reqwest::Client,
+    query: &BookQuery<'_>,
+) -> Result<Vec<CoverCandidate>, String> {
+    let url = open_library_url(query, "title,author_name,cover_i");
+    let results = fetch_json(client, url).await?;
+    Ok(results["docs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|doc| {
+            let cover = doc["cover_i"].as_i64()?;
+            Some(CoverCandidate {
+                source: "Open Library".to_string(),
+                url: format!("https://covers.openlibrary.org/b/id/{cover}-L.jpg"),
+                title: doc["title"].as_str().map(str::to_string),
+                author: doc["author_name"][0].as_str().map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+async fn google_books(
+    client: &reqwest::Client,
+    query: &BookQuery<'_>,
+) -> Result<Vec<CoverCandidate>, String> {
+    let results = fetch_json(client, google_books_url(query)).await?;
+    Ok(results["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let info = &item["volumeInfo"];
+            let links = &info["imageLinks"];
+            let url = links["thumbnail"]
+                .as_str()
+                .or_else(|| links["smallThumbnail"].as_str())?;
+            Some(CoverCandidate {
+                source: "Google Books".to_string(),
+                // Links come as http, with a page curl drawn on the image.
+                url: url
+                    .replacen("http://", "https://", 1)
+                    .replace("&edge=curl", ""),
+                title: info["title"].as_str().map(str::to_string),
+                author: info["authors"][0].as_str().map(str::to_string),
+            })
+        })
+        .collect())
+}
+
+/// Covers from both sources; one that can't be reached is left out.
+async fn search(client: &reqwest::Client, query: &BookQuery<'_>) -> Vec<CoverCandidate> {
+    let mut found = Vec::new();
+    for result in [
+        open_library(client, query).await,
+        google_books(client, query).await,
+    ] {
+        match result {
+            Ok(candidates) => found.extend(candidates),
+            Err(e) => log::warn!("Cover search failed: {e}"),
+        }
+    }
+    found
+}
+
+/// Covers for the book described by `query`. With an ISBN that finds
+/// nothing, the title and author are searched instead.
+pub async fn candidates(client: &reqwest::Client, query: &BookQuery<'_>) -> Vec<CoverCandidate> {
+    let mut found = search(client, query).await;
+    if found.is_empty() && query.isbn.is_some() {
+        found = search(
+            client,
+            &BookQuery {
+                isbn: None,
+                ..*query
+            },
+        )
+        .await;
+    }
+    let mut seen = std::collections::HashSet::new();
+    found.retain(|candidate| seen.insert(candidate.url.clone()));
+    found
+}
+
+/// The image at `bytes` scaled down to fit [`MAX_WIDTH`] × [`MAX_HEIGHT`]
+/// and as a JPEG, or unchanged if it is already small enough (or a format
+/// that can't be decoded here).
+fn resize(mime: &'static str, bytes: Vec<u8>) -> Result<(&'static str, Vec<u8>), String> {
+    let Ok(image) = image::load_from_memory(&bytes) else {
+        return Ok((mime, bytes));
+    };
+    if image.width() <= MAX_WIDTH && image.height() <= MAX_HEIGHT {
+        return Ok((mime, bytes));
+    }
+    let scaled = image.thumbnail(MAX_WIDTH, MAX_HEIGHT).to_rgb8();
+    let mut jpeg = Vec::new();
+    scaled
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY))
+        .map_err(|e| e.to_string())?;
+    Ok(("image/jpeg", jpeg))
+}
+
+/// Downloads the cover at `url` and returns it, resized, as a data URL.
+pub async fn download(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    let url = Url::parse(url.trim()).map_err(|e| format!("Invalid URL {url:?}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Covers can't be downloaded from {url}"));
+    }
+    let bytes = client
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())?;
+    if bytes.len() > MAX_DOWNLOAD_BYTES {
+        return Err(format!("The cover at {url} is too large"));
+    }
+    let mime = image_mime(&bytes).ok_or_else(|| format!("{url} is not an image"))?;
+    let (mime, bytes) = resize(mime, bytes.to_vec())?;
+    Ok(format!(
+        "data:{mime};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
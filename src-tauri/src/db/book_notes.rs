@@ -0,0 +1,55 @@
+//! Book notes: a free-form journal kept per book.
+
+use crate::models::BookNote;
+use rusqlite::{params, Connection};
+
+const BOOK_NOTE_COLUMNS: &str = "id, book_id, content, created_at, updated_at";
+
+fn book_note_from_row(row: &rusqlite::Row) -> rusqlite::Result<BookNote> {
+    Ok(BookNote {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        content: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<BookNote> {
+    conn.query_row(
+        &format!("SELECT {BOOK_NOTE_COLUMNS} FROM book_notes WHERE id = ?1"),
+        params![id],
+        book_note_from_row,
+    )
+}
+
+pub fn add(conn: &Connection, book_id: i64, content: &str) -> rusqlite::Result<BookNote> {
+    conn.execute(
+        "INSERT INTO book_notes (book_id, content) VALUES (?1, ?2)",
+        params![book_id, content],
+    )?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// A book's journal, oldest entry first.
+pub fn list(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<BookNote>> {
+    conn.prepare(&format!(
+        "SELECT {BOOK_NOTE_COLUMNS} FROM book_notes WHERE book_id = ?1
+         ORDER BY created_at, id"
+    ))?
+    .query_map(params![book_id], book_note_from_row)?
+    .collect()
+}
+
+pub fn update(conn: &Connection, id: i64, content: &str) -> rusqlite::Result<BookNote> {
+    conn.execute(
+        "UPDATE book_notes SET content = ?1, updated_at = datetime('now') WHERE id = ?2",
+        params![content, id],
+    )?;
+    get(conn, id)
+}
+
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM book_notes WHERE id = ?1", params![id])?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Subject tags (genres) on books. A book has a tag at most once, however it
+//! is capitalized or accented.
+
+use crate::models::Tag;
+use rusqlite::{params, Connection};
+
+/// `book_id`'s tags, alphabetically.
+pub fn for_book(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("SELECT tag FROM book_tags WHERE book_id = ?1 ORDER BY tag COLLATE fold")?
+        .query_map(params![book_id], |row| row.get(0))?
+        .collect()
+}
+
+/// Tags `book_id` with each of `tags` it doesn't have yet. Blank tags are
+/// skipped. Returns how many were added.
+pub fn add(conn: &Connection, book_id: i64, tags: &[String]) -> rusqlite::Result<usize> {
+    let mut insert =
+        conn.prepare("INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES (?1, ?2)")?;
+    let mut added = 0;
+    for tag in tags
+        .iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+    {
+        added += insert.execute(params![book_id, tag])?;
+    }
+    Ok(added)
+}
+
+/// Removes `tag` from `book_id`. Returns whether the book had it.
+pub fn remove(conn: &Connection, book_id: i64, tag: &str) -> rusqlite::Result<bool> {
+    Ok(conn.execute(
+        "DELETE FROM book_tags WHERE book_id = ?1 AND tag = ?2 COLLATE fold",
+        params![book_id, tag.trim()],
+    )? > 0)
+}
+
+/// Every tag in the library with its number of books, alphabetically. Tags
+/// that differ only in case or accents are counted together under the
+/// spelling used first.
+pub fn all(conn: &Connection) -> rusqlite::Result<Vec<Tag>> {
+    conn.prepare(
+        "SELECT (SELECT t.tag FROM book_tags t WHERE t.tag = bt.tag COLLATE fold
+                 ORDER BY t.id LIMIT 1),
+                COUNT(*)
+         FROM book_tags bt
+         GROUP BY bt.tag COLLATE fold
+         ORDER BY bt.tag COLLATE fold",
+    )?
+    .query_map([], |row| {
+        Ok(Tag {
+            name: row.get(0)?,
+            book_count: row.get(1)?,
+        })
+    })?
+    .collect()
+}
+
+/// Renames `from` to `to` on every book, merging it into `to` on books that
+/// already have both. Returns how many books were changed.
+pub fn rename(conn: &Connection, from: &str, to: &str) -> rusqlite::Result<usize> {
+    let (from, to) = (from.trim(), to.trim());
+    if to.is_empty() {
+        return Ok(0);
+    }
+    let renamed = conn.execute(
+        "UPDATE OR IGNORE book_tags SET tag = ?2 WHERE tag = ?1 COLLATE fold",
+        params![from, to],
+    )?;
+    // What's left under the old name is on books that already had `to`.
+    let merged = conn.execute(
+        "DELETE FROM book_tags WHERE tag = ?1 COLLATE fold AND tag <> ?2 COLLATE fold",
+        params![from, to],
+    )?;
+    Ok(renamed + merged)
+}
+
+/// Removes `tag` from every book. Returns how many books had it.
+pub fn delete(conn: &Connection, tag: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM book_tags WHERE tag = ?1 COLLATE fold",
+        params![tag.trim()],
+    )
+}
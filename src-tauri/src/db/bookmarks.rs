@@ -0,0 +1,67 @@
+//! Bookmarks: saved positions in a book, with a label.
+
+use crate::models::{Bookmark, BookmarkSort};
+use rusqlite::{params, Connection};
+
+pub fn bookmark_from_row(row: &rusqlite::Row) -> rusqlite::Result<Bookmark> {
+    Ok(Bookmark {
+        id: row.get(0)?,
+        book_title: row.get(1)?,
+        cfi: row.get(2)?,
+        label: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<Bookmark> {
+    conn.query_row(
+        "SELECT id, book_title, cfi, label, created_at FROM bookmarks WHERE id = ?1",
+        params![id],
+        bookmark_from_row,
+    )
+}
+
+pub fn add(
+    conn: &Connection,
+    book_title: &str,
+    cfi: &str,
+    label: &str,
+) -> rusqlite::Result<Bookmark> {
+    conn.execute(
+        "INSERT INTO bookmarks (book_title, cfi, label) VALUES (?1, ?2, ?3)",
+        params![book_title, cfi, label],
+    )?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// Bookmarks in `book_title`, ordered by `sort`.
+pub fn list(
+    conn: &Connection,
+    book_title: &str,
+    sort: BookmarkSort,
+) -> rusqlite::Result<Vec<Bookmark>> {
+    let order = match sort {
+        BookmarkSort::Position => "cfi COLLATE cfi_order, created_at DESC, id DESC",
+        BookmarkSort::Newest => "created_at DESC, id DESC",
+        BookmarkSort::Oldest => "created_at, id",
+    };
+    conn.prepare(&format!(
+        "SELECT id, book_title, cfi, label, created_at FROM bookmarks WHERE book_title = ?1
+         ORDER BY {order}"
+    ))?
+    .query_map(params![book_title], bookmark_from_row)?
+    .collect()
+}
+
+pub fn rename(conn: &Connection, id: i64, label: &str) -> rusqlite::Result<Bookmark> {
+    conn.execute(
+        "UPDATE bookmarks SET label = ?1 WHERE id = ?2",
+        params![label, id],
+    )?;
+    get(conn, id)
+}
+
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM bookmarks WHERE id = ?1", params![id])?;
+    Ok(())
+}
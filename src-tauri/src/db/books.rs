@@ -0,0 +1,638 @@
+//! Books: their metadata, reading progress and chapters.
+
+use crate::models::{
+    BookMetadata, BookQuery, BookSortBy, BookWithCounts, Chapter, LibrarySort, ProgressEntry,
+    ReadingStatus, SortDirection,
+};
+use crate::{authors, citation, epub, events};
+use rusqlite::{params, Connection, OptionalExtension, Params};
+
+pub const BOOK_COLUMNS: &str = "b.id, b.title, b.filename, b.last_position, b.cover, b.locations_data, b.last_percentage, b.author, b.series, b.series_index, b.format, b.page_count, b.finished_at, b.created_at, b.indexed_at, b.summary, b.summarized_at, b.archived, b.favorite, b.rating, b.review, b.publisher, b.year, b.isbn, b.word_count, b.reading_minutes, b.source_url, b.last_opened_at, b.description, b.language";
+
+pub fn book_from_row(row: &rusqlite::Row) -> rusqlite::Result<BookMetadata> {
+    Ok(BookMetadata {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        filename: row.get(2)?,
+        last_position: row.get(3)?,
+        cover: row.get(4)?,
+        locations_data: row.get(5)?,
+        last_percentage: row.get(6)?,
+        author: row.get(7)?,
+        series: row.get(8)?,
+        series_index: row.get(9)?,
+        format: row.get(10)?,
+        page_count: row.get(11)?,
+        finished_at: row.get(12)?,
+        created_at: row.get(13)?,
+        indexed_at: row.get(14)?,
+        summary: row.get(15)?,
+        summarized_at: row.get(16)?,
+        archived: row.get(17)?,
+        favorite: row.get(18)?,
+        rating: row.get(19)?,
+        review: row.get(20)?,
+        publisher: row.get(21)?,
+        year: row.get(22)?,
+        isbn: row.get(23)?,
+        word_count: row.get(24)?,
+        reading_minutes: row.get(25)?,
+        source_url: row.get(26)?,
+        last_opened_at: row.get(27)?,
+        description: row.get(28)?,
+        language: row.get(29)?,
+    })
+}
+
+/// Progress at which a book counts as finished. Paginated renderers rarely
+/// report exactly 100 on the last page.
+pub const FINISHED_PERCENTAGE: f64 = 99.0;
+
+/// Progress moves smaller than this (in percentage points) update the latest
+/// history entry instead of adding one, so page turns don't each leave a row
+/// but the position before a jump is kept exactly.
+const PROGRESS_HISTORY_STEP: f64 = 1.0;
+
+/// Progress moves of at least this many percentage points are reported as a
+/// jump the reader can undo.
+const PROGRESS_JUMP: f64 = 10.0;
+
+/// Adds `position` to the progress history of the book titled `title`.
+/// Returns the jump when the book moved by [`PROGRESS_JUMP`] or more.
+pub fn record_progress(
+    conn: &Connection,
+    title: &str,
+    position: &str,
+    percentage: f64,
+) -> rusqlite::Result<Option<events::ProgressJump>> {
+    let Some(book_id) = conn
+        .query_row(
+            "SELECT id FROM books WHERE title = ?1",
+            params![title],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let latest: Option<(i64, String, f64)> = conn
+        .query_row(
+            "SELECT id, cfi, percentage FROM progress_history
+             WHERE book_id = ?1 ORDER BY id DESC LIMIT 1",
+            params![book_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    match &latest {
+        Some((_, cfi, _)) if cfi == position => {}
+        Some((id, _, previous)) if (percentage - previous).abs() < PROGRESS_HISTORY_STEP => {
+            conn.execute(
+                "UPDATE progress_history SET cfi = ?1, percentage = ?2, recorded_at = datetime('now')
+                 WHERE id = ?3",
+                params![position, percentage, id],
+            )?;
+        }
+        _ => {
+            conn.execute(
+                "INSERT INTO progress_history (book_id, cfi, percentage) VALUES (?1, ?2, ?3)",
+                params![book_id, position, percentage],
+            )?;
+        }
+    }
+    Ok(latest
+        .filter(|(_, _, previous)| (percentage - previous).abs() >= PROGRESS_JUMP)
+        .map(|(_, _, previous)| events::ProgressJump {
+            book_id,
+            title: title.to_string(),
+            from_percentage: previous,
+            to_percentage: percentage,
+        }))
+}
+
+pub fn chapter_from_row(row: &rusqlite::Row) -> rusqlite::Result<Chapter> {
+    Ok(Chapter {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        position: row.get(2)?,
+        title: row.get(3)?,
+        start_cfi: row.get(4)?,
+        end_cfi: row.get(5)?,
+    })
+}
+
+/// Replaces the stored chapter ranges of `book_id` with those read from the
+/// EPUB at `path`.
+pub fn store_chapters(
+    conn: &Connection,
+    book_id: i64,
+    path: &std::path::Path,
+) -> Result<Vec<Chapter>, String> {
+    let chapters = epub::Epub::open(path)?.chapters();
+    conn.execute("DELETE FROM chapters WHERE book_id = ?1", params![book_id])
+        .map_err(|e| e.to_string())?;
+    let mut stored = Vec::new();
+    for (position, chapter) in chapters.into_iter().enumerate() {
+        conn.execute(
+            "INSERT INTO chapters (book_id, position, title, start_cfi, end_cfi)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                book_id,
+                position as i64,
+                chapter.title,
+                chapter.start_cfi,
+                chapter.end_cfi
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        stored.push(Chapter {
+            id: conn.last_insert_rowid(),
+            book_id,
+            position: position as i64,
+            title: chapter.title,
+            start_cfi: chapter.start_cfi,
+            end_cfi: chapter.end_cfi,
+        });
+    }
+    Ok(stored)
+}
+
+/// What [`update_progress`] changed.
+pub struct ProgressUpdate {
+    /// When the book was first finished, if it has been.
+    pub finished_at: Option<String>,
+    /// Set when the book moved by [`PROGRESS_JUMP`] or more.
+    pub jump: Option<events::ProgressJump>,
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<BookMetadata> {
+    conn.query_row(
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+        params![id],
+        book_from_row,
+    )
+}
+
+pub fn by_title(conn: &Connection, title: &str) -> rusqlite::Result<Option<BookMetadata>> {
+    conn.query_row(
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.title = ?1"),
+        params![title],
+        book_from_row,
+    )
+    .optional()
+}
+
+fn query(conn: &Connection, sql: &str, params: impl Params) -> rusqlite::Result<Vec<BookMetadata>> {
+    conn.prepare(sql)?
+        .query_map(params, book_from_row)?
+        .collect()
+}
+
+/// Every book, by title.
+pub fn all(conn: &Connection) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!("SELECT {BOOK_COLUMNS} FROM books b ORDER BY b.title COLLATE fold"),
+        [],
+    )
+}
+
+/// Every book with its highlight and bookmark counts, favorites first, then
+/// ordered by `sort`. With `min_rating`, only books rated at least that many
+/// stars.
+pub fn list(
+    conn: &Connection,
+    sort: LibrarySort,
+    min_rating: Option<i64>,
+) -> rusqlite::Result<Vec<BookWithCounts>> {
+    let order = match sort {
+        LibrarySort::Added => "b.created_at DESC",
+        LibrarySort::Rating => "b.rating DESC, b.created_at DESC",
+        LibrarySort::Title => "b.title COLLATE fold",
+    };
+    conn.prepare(&format!(
+        "{}
+         WHERE ?1 IS NULL OR b.rating >= ?1
+         ORDER BY b.favorite DESC, {order}",
+        with_counts()
+    ))?
+    .query_map(params![min_rating], book_with_counts)?
+    .collect()
+}
+
+/// Books with their counts, selected from `books b`.
+fn with_counts() -> String {
+    format!(
+        "SELECT {BOOK_COLUMNS}, COALESCE(h.count, 0), COALESCE(bm.count, 0)
+         FROM books b
+         LEFT JOIN (SELECT book_title, COUNT(*) AS count FROM highlights GROUP BY book_title) h
+             ON h.book_title = b.title
+         LEFT JOIN (SELECT book_title, COUNT(*) AS count FROM bookmarks GROUP BY book_title) bm
+             ON bm.book_title = b.title"
+    )
+}
+
+fn book_with_counts(row: &rusqlite::Row) -> rusqlite::Result<BookWithCounts> {
+    Ok(BookWithCounts {
+        book: book_from_row(row)?,
+        highlight_count: row.get(30)?,
+        bookmark_count: row.get(31)?,
+    })
+}
+
+/// Books matching `query`, with their counts. The search matches any part of
+/// the title or author, ignoring case and accents (see [`super::fold`]).
+pub fn query_books(conn: &Connection, query: &BookQuery) -> rusqlite::Result<Vec<BookWithCounts>> {
+    let direction = query.direction.unwrap_or(match query.sort_by {
+        BookSortBy::Title | BookSortBy::Author => SortDirection::Asc,
+        BookSortBy::Progress | BookSortBy::LastOpened | BookSortBy::Added => SortDirection::Desc,
+    });
+    let direction = match direction {
+        SortDirection::Asc => "ASC",
+        SortDirection::Desc => "DESC",
+    };
+    // Books without an author or never opened go last either way.
+    let order = match query.sort_by {
+        BookSortBy::Title => format!("b.title COLLATE fold {direction}"),
+        BookSortBy::Author => {
+            format!("b.author IS NULL, b.author COLLATE fold {direction}, b.title COLLATE fold")
+        }
+        BookSortBy::Progress => format!("b.last_percentage {direction}, b.title COLLATE fold"),
+        BookSortBy::LastOpened => {
+            format!("b.last_opened_at IS NULL, b.last_opened_at {direction}")
+        }
+        BookSortBy::Added => format!("b.created_at {direction}"),
+    };
+    let status = match query.status {
+        None => "1",
+        Some(ReadingStatus::Unread) => "b.finished_at IS NULL AND b.last_percentage = 0",
+        Some(ReadingStatus::Reading) => "b.finished_at IS NULL AND b.last_percentage > 0",
+        Some(ReadingStatus::Finished) => "b.finished_at IS NOT NULL",
+    };
+    let search = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
+    conn.prepare(&format!(
+        "{}
+         WHERE ({status})
+           AND (?1 IS NULL OR instr(fold(b.title), fold(?1)) > 0
+                OR instr(fold(b.author), fold(?1)) > 0)
+           AND (?2 IS NULL OR b.id IN
+                (SELECT book_id FROM book_collections WHERE collection_id = ?2))
+           AND (?3 IS NULL OR b.id IN
+                (SELECT book_id FROM book_tags WHERE tag = ?3 COLLATE fold))
+         ORDER BY {order}, b.id",
+        with_counts()
+    ))?
+    .query_map(
+        params![search, query.collection_id, query.tag],
+        book_with_counts,
+    )?
+    .collect()
+}
+
+/// Notes that the book was just opened.
+pub fn mark_opened(conn: &Connection, filename: &str) -> rusqlite::Result<Option<BookMetadata>> {
+    conn.execute(
+        "UPDATE books SET last_opened_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE filename = ?1",
+        params![filename],
+    )?;
+    conn.query_row(
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.filename = ?1"),
+        params![filename],
+        book_from_row,
+    )
+    .optional()
+}
+
+/// The `limit` books opened most recently, newest first. With
+/// `unfinished_only`, books already finished are left out, for a "Continue
+/// reading" row.
+pub fn recent(
+    conn: &Connection,
+    limit: i64,
+    unfinished_only: bool,
+) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {BOOK_COLUMNS} FROM books b
+             WHERE b.last_opened_at IS NOT NULL AND NOT b.archived
+                 AND NOT (?2 AND b.finished_at IS NOT NULL)
+             ORDER BY b.last_opened_at DESC
+             LIMIT ?1"
+        ),
+        params![limit, unfinished_only],
+    )
+}
+
+pub fn favorites(conn: &Connection) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.favorite ORDER BY b.created_at DESC"),
+        [],
+    )
+}
+
+/// Books by `author_id`, grouped by series and in series order.
+pub fn by_author(conn: &Connection, author_id: i64) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {BOOK_COLUMNS}
+             FROM books b
+             INNER JOIN book_authors ba ON b.id = ba.book_id
+             WHERE ba.author_id = ?1
+             ORDER BY b.series, b.series_index, b.title"
+        ),
+        params![author_id],
+    )
+}
+
+/// Books whose language hasn't been detected, leaving out archived ones.
+pub fn without_language(conn: &Connection) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {BOOK_COLUMNS} FROM books b
+             WHERE b.language IS NULL AND NOT b.archived
+             ORDER BY b.id"
+        ),
+        [],
+    )
+}
+
+/// Books tagged `tag`, ignoring case and accents, by title.
+pub fn by_tag(conn: &Connection, tag: &str) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {BOOK_COLUMNS}
+             FROM books b
+             INNER JOIN book_tags bt ON b.id = bt.book_id
+             WHERE bt.tag = ?1 COLLATE fold
+             ORDER BY b.title COLLATE fold"
+        ),
+        params![tag.trim()],
+    )
+}
+
+/// Books in `series_id` by their position in the series. Books without a
+/// position come last.
+pub fn by_series(conn: &Connection, series_id: i64) -> rusqlite::Result<Vec<BookMetadata>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {BOOK_COLUMNS}
+             FROM books b
+             INNER JOIN book_series bs ON b.id = bs.book_id
+             WHERE bs.series_id = ?1
+             ORDER BY bs.series_index IS NULL, bs.series_index, b.title"
+        ),
+        params![series_id],
+    )
+}
+
+/// Saves the reading position of the book titled `title` and adds it to the
+/// progress history. `None` if there is no such book.
+pub fn update_progress(
+    conn: &Connection,
+    title: &str,
+    position: &str,
+    percentage: f64,
+) -> rusqlite::Result<Option<ProgressUpdate>> {
+    // finished_at records the first time the end was reached and is kept when
+    // the book is re-read from the start.
+    conn.execute(
+        "UPDATE books SET last_position = ?1, last_percentage = ?2,
+            finished_at = CASE WHEN ?2 >= ?4 THEN COALESCE(finished_at, datetime('now'))
+                               ELSE finished_at END
+         WHERE title = ?3",
+        params![position, percentage, title, FINISHED_PERCENTAGE],
+    )?;
+    let jump = record_progress(conn, title, position, percentage)?;
+    let finished_at = conn
+        .query_row(
+            "SELECT finished_at FROM books WHERE title = ?1",
+            params![title],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(finished_at.map(|finished_at| ProgressUpdate { finished_at, jump }))
+}
+
+/// Moves a book back to the position before its latest progress history
+/// entry, which is dropped, so calling it again keeps going back. A finish
+/// recorded since that position is undone too.
+pub fn revert_progress(conn: &mut Connection, book_id: i64) -> Result<BookMetadata, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let entries: Vec<(i64, String, f64, String)> = tx
+        .prepare(
+            "SELECT id, cfi, percentage, recorded_at FROM progress_history
+             WHERE book_id = ?1 ORDER BY id DESC LIMIT 2",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map(params![book_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    let [(latest_id, ..), (_, cfi, percentage, recorded_at)] = entries.as_slice() else {
+        return Err("No earlier position to go back to".to_string());
+    };
+
+    tx.execute(
+        "DELETE FROM progress_history WHERE id = ?1",
+        params![latest_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE books SET last_position = ?1, last_percentage = ?2,
+            finished_at = CASE WHEN ?2 < ?4 AND finished_at >= ?5 THEN NULL
+                               ELSE finished_at END
+         WHERE id = ?3",
+        params![cfi, percentage, book_id, FINISHED_PERCENTAGE, recorded_at],
+    )
+    .map_err(|e| e.to_string())?;
+    let book = get(&tx, book_id).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(book)
+}
+
+/// Reading positions of a book over time, oldest first.
+pub fn progress_history(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<ProgressEntry>> {
+    conn.prepare(
+        "SELECT id, book_id, cfi, percentage, recorded_at FROM progress_history
+         WHERE book_id = ?1 ORDER BY id",
+    )?
+    .query_map(params![book_id], |row| {
+        Ok(ProgressEntry {
+            id: row.get(0)?,
+            book_id: row.get(1)?,
+            cfi: row.get(2)?,
+            percentage: row.get(3)?,
+            recorded_at: row.get(4)?,
+        })
+    })?
+    .collect()
+}
+
+pub fn set_locations(conn: &Connection, title: &str, locations_data: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET locations_data = ?1 WHERE title = ?2",
+        params![locations_data, title],
+    )?;
+    Ok(())
+}
+
+/// Removes the book titled `title` with its highlights, bookmarks, notes and
+/// everything else kept about it. Returns the name of its file, which is
+/// left for the caller to delete once this has succeeded.
+pub fn delete(conn: &mut Connection, title: &str) -> rusqlite::Result<String> {
+    let tx = conn.transaction()?;
+    let (book_id, filename): (i64, String) = tx.query_row(
+        "SELECT id, filename FROM books WHERE title = ?1",
+        params![title],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    // Foreign keys aren't enforced, so everything hanging off the book goes
+    // by hand. Covers are stored inline in the books row, so they go with it.
+    tx.execute(
+        "DELETE FROM highlight_collections
+         WHERE highlight_id IN (SELECT id FROM highlights WHERE book_title = ?1)",
+        params![title],
+    )?;
+    tx.execute(
+        "DELETE FROM highlight_links
+         WHERE from_id IN (SELECT id FROM highlights WHERE book_title = ?1)
+            OR to_id IN (SELECT id FROM highlights WHERE book_title = ?1)",
+        params![title],
+    )?;
+    tx.execute(
+        "DELETE FROM note_revisions
+         WHERE highlight_id IN (SELECT id FROM highlights WHERE book_title = ?1)",
+        params![title],
+    )?;
+    tx.execute(
+        "DELETE FROM highlights WHERE book_title = ?1",
+        params![title],
+    )?;
+    tx.execute(
+        "DELETE FROM bookmarks WHERE book_title = ?1",
+        params![title],
+    )?;
+    tx.execute(
+        "DELETE FROM book_collections WHERE book_id = ?1",
+        params![book_id],
+    )?;
+    tx.execute("DELETE FROM book_tags WHERE book_id = ?1", params![book_id])?;
+    tx.execute("DELETE FROM book_text WHERE book_id = ?1", params![book_id])?;
+    tx.execute("DELETE FROM chapters WHERE book_id = ?1", params![book_id])?;
+    tx.execute(
+        "DELETE FROM book_notes WHERE book_id = ?1",
+        params![book_id],
+    )?;
+    tx.execute("DELETE FROM drawings WHERE book_id = ?1", params![book_id])?;
+    tx.execute(
+        "DELETE FROM progress_history WHERE book_id = ?1",
+        params![book_id],
+    )?;
+    tx.execute("DELETE FROM books WHERE id = ?1", params![book_id])?;
+    authors::remove_orphans(&tx)?;
+    tx.commit()?;
+    Ok(filename)
+}
+
+pub fn toggle_favorite(conn: &Connection, id: i64) -> rusqlite::Result<BookMetadata> {
+    conn.execute(
+        "UPDATE books SET favorite = NOT favorite WHERE id = ?1",
+        params![id],
+    )?;
+    get(conn, id)
+}
+
+/// Rates a book from 1 to 5 stars, or clears the rating with 0.
+pub fn set_rating(conn: &Connection, id: i64, rating: i64) -> Result<BookMetadata, String> {
+    if !(0..=5).contains(&rating) {
+        return Err(format!("Rating must be between 0 and 5, got {rating}"));
+    }
+    conn.execute(
+        "UPDATE books SET rating = ?1 WHERE id = ?2",
+        params![rating, id],
+    )
+    .map_err(|e| e.to_string())?;
+    get(conn, id).map_err(|e| e.to_string())
+}
+
+/// Sets a book's review; a blank review clears it.
+pub fn set_review(conn: &Connection, id: i64, review: &str) -> rusqlite::Result<BookMetadata> {
+    let review = Some(review).filter(|r| !r.trim().is_empty());
+    conn.execute(
+        "UPDATE books SET review = ?1 WHERE id = ?2",
+        params![review, id],
+    )?;
+    get(conn, id)
+}
+
+/// Sets the book's cover, a data URL, or removes it.
+pub fn set_cover(
+    conn: &Connection,
+    id: i64,
+    cover: Option<&str>,
+) -> rusqlite::Result<BookMetadata> {
+    conn.execute(
+        "UPDATE books SET cover = ?1 WHERE id = ?2",
+        params![cover, id],
+    )?;
+    get(conn, id)
+}
+
+/// Sets the publication details used in citations. Blank values clear them;
+/// ISBNs are stored without dashes or spaces.
+pub fn set_publication(
+    conn: &Connection,
+    id: i64,
+    publisher: Option<&str>,
+    year: Option<i64>,
+    isbn: Option<&str>,
+) -> rusqlite::Result<BookMetadata> {
+    let publisher = publisher.map(str::trim).filter(|p| !p.is_empty());
+    let isbn = isbn
+        .map(|i| i.replace(['-', ' '], "").to_uppercase())
+        .filter(|i| !i.is_empty());
+    conn.execute(
+        "UPDATE books SET publisher = ?1, year = ?2, isbn = ?3 WHERE id = ?4",
+        params![publisher, year, isbn, id],
+    )?;
+    get(conn, id)
+}
+
+/// What a citation of `book` is built from. Authors come from the library's
+/// author list, in order, falling back to the book's display author.
+pub fn citation_source(
+    conn: &Connection,
+    book: &BookMetadata,
+) -> rusqlite::Result<citation::Source> {
+    let mut authors: Vec<String> = conn
+        .prepare(
+            "SELECT a.name FROM book_authors ba
+             INNER JOIN authors a ON a.id = ba.author_id
+             WHERE ba.book_id = ?1
+             ORDER BY ba.position",
+        )?
+        .query_map(params![book.id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    if authors.is_empty() {
+        authors.extend(book.author.clone().filter(|a| !a.trim().is_empty()));
+    }
+    Ok(citation::Source {
+        title: book.title.clone(),
+        authors,
+        publisher: book.publisher.clone(),
+        year: book.year,
+        isbn: book.isbn.clone(),
+    })
+}
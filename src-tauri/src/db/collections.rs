@@ -0,0 +1,192 @@
+//! Collections, their highlights and books, and smart collections.
+
+use crate::db::books::{book_from_row, BOOK_COLUMNS};
+use crate::db::highlights::{highlight_from_row, HIGHLIGHT_COLUMNS};
+use crate::models::{BookMetadata, Collection, Highlight, SmartCollection};
+use crate::smart_collections::SmartFilter;
+use rusqlite::{params, Connection};
+
+fn collection_from_row(row: &rusqlite::Row) -> rusqlite::Result<Collection> {
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        emoji: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+pub fn smart_collection_from_row(row: &rusqlite::Row) -> rusqlite::Result<SmartCollection> {
+    let filter: String = row.get(3)?;
+    Ok(SmartCollection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        emoji: row.get(2)?,
+        filter: serde_json::from_str(&filter).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn create(conn: &Connection, name: &str, emoji: &str) -> rusqlite::Result<Collection> {
+    conn.execute(
+        "INSERT INTO collections (name, emoji) VALUES (?1, ?2)",
+        params![name, emoji],
+    )?;
+    conn.query_row(
+        "SELECT id, name, emoji, created_at FROM collections WHERE id = ?1",
+        params![conn.last_insert_rowid()],
+        collection_from_row,
+    )
+}
+
+/// Every collection, by name.
+pub fn all(conn: &Connection) -> rusqlite::Result<Vec<Collection>> {
+    conn.prepare("SELECT id, name, emoji, created_at FROM collections ORDER BY name")?
+        .query_map([], collection_from_row)?
+        .collect()
+}
+
+/// Deletes the collection and its links to highlights and books.
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM highlight_collections WHERE collection_id = ?1",
+        params![id],
+    )?;
+    conn.execute(
+        "DELETE FROM book_collections WHERE collection_id = ?1",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM collections WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+pub fn add_highlight(
+    conn: &Connection,
+    collection_id: i64,
+    highlight_id: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id) VALUES (?1, ?2)",
+        params![highlight_id, collection_id],
+    )?;
+    Ok(())
+}
+
+/// Adds the existing highlights among `ids` that aren't in the collection
+/// yet. Returns the ids that were added.
+pub fn add_highlights(
+    conn: &Connection,
+    collection_id: i64,
+    ids: &[i64],
+) -> rusqlite::Result<Vec<i64>> {
+    let mut insert = conn.prepare(
+        "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id)
+         SELECT id, ?2 FROM highlights WHERE id = ?1",
+    )?;
+    let mut added = Vec::new();
+    for &id in ids {
+        if insert.execute(params![id, collection_id])? > 0 {
+            added.push(id);
+        }
+    }
+    Ok(added)
+}
+
+pub fn remove_highlight(
+    conn: &Connection,
+    collection_id: i64,
+    highlight_id: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM highlight_collections WHERE highlight_id = ?1 AND collection_id = ?2",
+        params![highlight_id, collection_id],
+    )?;
+    Ok(())
+}
+
+/// Highlights in the collection, newest first.
+pub fn highlights(conn: &Connection, collection_id: i64) -> rusqlite::Result<Vec<Highlight>> {
+    conn.prepare(&format!(
+        "SELECT {HIGHLIGHT_COLUMNS}
+         FROM highlights h
+         INNER JOIN highlight_collections hc ON h.id = hc.highlight_id
+         WHERE hc.collection_id = ?1
+         ORDER BY h.created_at DESC"
+    ))?
+    .query_map(params![collection_id], highlight_from_row)?
+    .collect()
+}
+
+/// Collections the highlight is in, by name.
+pub fn for_highlight(conn: &Connection, highlight_id: i64) -> rusqlite::Result<Vec<Collection>> {
+    conn.prepare(
+        "SELECT c.id, c.name, c.emoji, c.created_at
+         FROM collections c
+         INNER JOIN highlight_collections hc ON c.id = hc.collection_id
+         WHERE hc.highlight_id = ?1
+         ORDER BY c.name",
+    )?
+    .query_map(params![highlight_id], collection_from_row)?
+    .collect()
+}
+
+/// Books in the collection, by series, then title.
+pub fn books(conn: &Connection, collection_id: i64) -> rusqlite::Result<Vec<BookMetadata>> {
+    conn.prepare(&format!(
+        "SELECT {BOOK_COLUMNS}
+         FROM books b
+         INNER JOIN book_collections bc ON b.id = bc.book_id
+         WHERE bc.collection_id = ?1
+         ORDER BY b.series, b.series_index, b.title"
+    ))?
+    .query_map(params![collection_id], book_from_row)?
+    .collect()
+}
+
+pub fn create_smart(
+    conn: &Connection,
+    name: &str,
+    emoji: &str,
+    filter: &SmartFilter,
+) -> Result<SmartCollection, String> {
+    let filter_json = serde_json::to_string(filter).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO smart_collections (name, emoji, filter) VALUES (?1, ?2, ?3)",
+        params![name, emoji, filter_json],
+    )
+    .map_err(|e| e.to_string())?;
+    get_smart(conn, conn.last_insert_rowid()).map_err(|e| e.to_string())
+}
+
+pub fn get_smart(conn: &Connection, id: i64) -> rusqlite::Result<SmartCollection> {
+    conn.query_row(
+        "SELECT id, name, emoji, filter, created_at FROM smart_collections WHERE id = ?1",
+        params![id],
+        smart_collection_from_row,
+    )
+}
+
+/// Every smart collection, by name.
+pub fn all_smart(conn: &Connection) -> rusqlite::Result<Vec<SmartCollection>> {
+    conn.prepare("SELECT id, name, emoji, filter, created_at FROM smart_collections ORDER BY name")?
+        .query_map([], smart_collection_from_row)?
+        .collect()
+}
+
+/// Highlights matching the smart collection's filter, newest first.
+pub fn evaluate_smart(conn: &Connection, id: i64) -> rusqlite::Result<Vec<Highlight>> {
+    let collection = get_smart(conn, id)?;
+    let mut values = Vec::new();
+    let condition = collection.filter.to_sql(&mut values);
+    conn.prepare(&format!(
+        "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE {condition} ORDER BY h.created_at DESC"
+    ))?
+    .query_map(rusqlite::params_from_iter(values), highlight_from_row)?
+    .collect()
+}
+
+pub fn delete_smart(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM smart_collections WHERE id = ?1", params![id])?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Drawings: freehand strokes on a page of a book, such as margin notes made
+//! with a stylus.
+
+use crate::models::{Drawing, Stroke};
+use rusqlite::{params, Connection};
+
+const DRAWING_COLUMNS: &str = "id, book_id, cfi, strokes, created_at, updated_at";
+
+fn drawing_from_row(row: &rusqlite::Row) -> rusqlite::Result<Drawing> {
+    let strokes: String = row.get(3)?;
+    Ok(Drawing {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        cfi: row.get(2)?,
+        strokes: serde_json::from_str(&strokes).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+fn to_json(strokes: &[Stroke]) -> rusqlite::Result<String> {
+    serde_json::to_string(strokes).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<Drawing> {
+    conn.query_row(
+        &format!("SELECT {DRAWING_COLUMNS} FROM drawings WHERE id = ?1"),
+        params![id],
+        drawing_from_row,
+    )
+}
+
+pub fn add(
+    conn: &Connection,
+    book_id: i64,
+    cfi: &str,
+    strokes: &[Stroke],
+) -> rusqlite::Result<Drawing> {
+    conn.execute(
+        "INSERT INTO drawings (book_id, cfi, strokes) VALUES (?1, ?2, ?3)",
+        params![book_id, cfi, to_json(strokes)?],
+    )?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// A book's drawings, or with `cfi` only those on that page, oldest first.
+pub fn list(conn: &Connection, book_id: i64, cfi: Option<&str>) -> rusqlite::Result<Vec<Drawing>> {
+    conn.prepare(&format!(
+        "SELECT {DRAWING_COLUMNS} FROM drawings
+         WHERE book_id = ?1 AND (?2 IS NULL OR cfi = ?2)
+         ORDER BY created_at, id"
+    ))?
+    .query_map(params![book_id, cfi], drawing_from_row)?
+    .collect()
+}
+
+/// Replaces a drawing's strokes, e.g. after more were drawn or some erased.
+pub fn update(conn: &Connection, id: i64, strokes: &[Stroke]) -> rusqlite::Result<Drawing> {
+    conn.execute(
+        "UPDATE drawings SET strokes = ?1, updated_at = datetime('now') WHERE id = ?2",
+        params![to_json(strokes)?, id],
+    )?;
+    get(conn, id)
+}
+
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM drawings WHERE id = ?1", params![id])?;
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Feed subscriptions and the inbox of articles saved from them.
+
+use crate::db::books::{book_from_row, BOOK_COLUMNS};
+use crate::feeds::{ParsedEntry, FIRST_FETCH_ENTRIES, MAX_ATTEMPTS};
+use crate::models::{Feed, InboxEntry};
+use rusqlite::{params, Connection, OptionalExtension};
+
+const FEED_COLUMNS: &str =
+    "f.id, f.url, f.title, f.site_url, f.last_fetched_at, f.last_error, f.created_at,
+     (SELECT COUNT(*) FROM feed_entries e
+      WHERE e.feed_id = f.id AND e.book_id IS NOT NULL AND e.read_at IS NULL)";
+
+pub fn feed_from_row(row: &rusqlite::Row) -> rusqlite::Result<Feed> {
+    Ok(Feed {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        site_url: row.get(3)?,
+        last_fetched_at: row.get(4)?,
+        last_error: row.get(5)?,
+        created_at: row.get(6)?,
+        unread_count: row.get(7)?,
+    })
+}
+
+/// Selects the book first so [`book_from_row`] can read it from the start of
+/// the row.
+fn inbox_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<InboxEntry> {
+    Ok(InboxEntry {
+        book: book_from_row(row)?,
+        id: row.get(30)?,
+        feed_id: row.get(31)?,
+        feed_title: row.get(32)?,
+        title: row.get(33)?,
+        url: row.get(34)?,
+        published_at: row.get(35)?,
+        read_at: row.get(36)?,
+    })
+}
+
+fn inbox_query(condition: &str) -> String {
+    format!(
+        "SELECT {BOOK_COLUMNS}, e.id, e.feed_id, f.title, e.title, e.url, e.published_at, e.read_at
+         FROM feed_entries e
+         JOIN feeds f ON f.id = e.feed_id
+         JOIN books b ON b.id = e.book_id
+         WHERE {condition}
+         ORDER BY COALESCE(e.published_at, e.created_at) DESC, e.id DESC"
+    )
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<Feed> {
+    conn.query_row(
+        &format!("SELECT {FEED_COLUMNS} FROM feeds f WHERE f.id = ?1"),
+        params![id],
+        feed_from_row,
+    )
+}
+
+pub fn by_url(conn: &Connection, url: &str) -> rusqlite::Result<Option<Feed>> {
+    conn.query_row(
+        &format!("SELECT {FEED_COLUMNS} FROM feeds f WHERE f.url = ?1"),
+        params![url],
+        feed_from_row,
+    )
+    .optional()
+}
+
+pub fn all(conn: &Connection) -> rusqlite::Result<Vec<Feed>> {
+    conn.prepare(&format!(
+        "SELECT {FEED_COLUMNS} FROM feeds f ORDER BY f.title COLLATE NOCASE"
+    ))?
+    .query_map([], feed_from_row)?
+    .collect()
+}
+
+/// Feeds not checked in the last `minutes`.
+pub fn due(conn: &Connection, minutes: i64) -> rusqlite::Result<Vec<Feed>> {
+    conn.prepare(&format!(
+        "SELECT {FEED_COLUMNS} FROM feeds f
+         WHERE f.last_fetched_at IS NULL
+            OR f.last_fetched_at <= datetime('now', '-' || ?1 || ' minutes')
+         ORDER BY f.id"
+    ))?
+    .query_map(params![minutes], feed_from_row)?
+    .collect()
+}
+
+pub fn subscribe(
+    conn: &Connection,
+    url: &str,
+    title: &str,
+    site_url: Option<&str>,
+) -> rusqlite::Result<Feed> {
+    conn.execute(
+        "INSERT INTO feeds (url, title, site_url) VALUES (?1, ?2, ?3)",
+        params![url, title, site_url],
+    )?;
+    get(conn, conn.last_insert_rowid())
+}
+
+/// Removes the feed and its entries. Articles already saved stay in the
+/// library.
+pub fn delete(conn: &mut Connection, id: i64) -> rusqlite::Result<()> {
+    // Foreign keys aren't enforced, so the entries go by hand.
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM feed_entries WHERE feed_id = ?1", params![id])?;
+    tx.execute("DELETE FROM feeds WHERE id = ?1", params![id])?;
+    tx.commit()
+}
+
+/// Notes a refresh of the feed, and why it failed if it did.
+pub fn record_fetch(
+    conn: &Connection,
+    id: i64,
+    title: Option<&str>,
+    error: Option<&str>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE feeds SET last_fetched_at = datetime('now'), last_error = ?2,
+             title = COALESCE(NULLIF(?3, ''), title)
+         WHERE id = ?1",
+        params![id, error, title],
+    )?;
+    Ok(())
+}
+
+/// Records entries not seen before. On a feed's first fetch only the newest
+/// [`FIRST_FETCH_ENTRIES`] are left to be saved; the rest are marked read.
+pub fn add_entries(
+    conn: &Connection,
+    feed_id: i64,
+    entries: &[ParsedEntry],
+) -> rusqlite::Result<usize> {
+    let first_fetch: bool = conn.query_row(
+        "SELECT NOT EXISTS(SELECT 1 FROM feed_entries WHERE feed_id = ?1)",
+        params![feed_id],
+        |row| row.get(0),
+    )?;
+    let mut added = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let skipped = first_fetch && index >= FIRST_FETCH_ENTRIES;
+        added += conn.execute(
+            "INSERT OR IGNORE INTO feed_entries (feed_id, guid, url, title, published_at, read_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, CASE WHEN ?6 THEN datetime('now') END)",
+            params![
+                feed_id,
+                entry.guid,
+                entry.url,
+                entry.title,
+                entry.published_at,
+                skipped
+            ],
+        )?;
+    }
+    Ok(added)
+}
+
+/// Ids and guids of the feed's entries still to be saved as articles.
+pub fn pending(conn: &Connection, feed_id: i64) -> rusqlite::Result<Vec<(i64, String)>> {
+    conn.prepare(
+        "SELECT id, guid FROM feed_entries
+         WHERE feed_id = ?1 AND saved_at IS NULL AND read_at IS NULL AND attempts < ?2
+         ORDER BY id",
+    )?
+    .query_map(params![feed_id, MAX_ATTEMPTS], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?
+    .collect()
+}
+
+pub fn entry_saved(conn: &Connection, entry_id: i64, book_id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE feed_entries SET book_id = ?2, saved_at = datetime('now'), last_error = NULL
+         WHERE id = ?1",
+        params![entry_id, book_id],
+    )?;
+    Ok(())
+}
+
+pub fn entry_failed(conn: &Connection, entry_id: i64, error: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE feed_entries SET attempts = attempts + 1, last_error = ?2 WHERE id = ?1",
+        params![entry_id, error],
+    )?;
+    Ok(())
+}
+
+/// Saved entries, newest first: only unread ones unless `include_read`.
+pub fn inbox(conn: &Connection, include_read: bool) -> rusqlite::Result<Vec<InboxEntry>> {
+    conn.prepare(&inbox_query("?1 OR e.read_at IS NULL"))?
+        .query_map(params![include_read], inbox_entry_from_row)?
+        .collect()
+}
+
+pub fn inbox_entry(conn: &Connection, entry_id: i64) -> rusqlite::Result<Option<InboxEntry>> {
+    conn.query_row(
+        &inbox_query("e.id = ?1"),
+        params![entry_id],
+        inbox_entry_from_row,
+    )
+    .optional()
+}
+
+/// Marks the entry read, or back to unread. `None` if it isn't in the inbox.
+pub fn set_read(
+    conn: &Connection,
+    entry_id: i64,
+    read: bool,
+) -> rusqlite::Result<Option<InboxEntry>> {
+    conn.execute(
+        "UPDATE feed_entries SET read_at = CASE WHEN ?2 THEN COALESCE(read_at, datetime('now')) END
+         WHERE id = ?1",
+        params![entry_id, read],
+    )?;
+    inbox_entry(conn, entry_id)
+}
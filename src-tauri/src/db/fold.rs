@@ -0,0 +1,37 @@
+//! Case- and accent-insensitive text matching, available in SQL as the
+//! function `fold` (`fold('Cámus')` is `camus`) and the collation `fold`, which
+//! compares folded text: `'Cámus' = 'CAMUS' COLLATE fold`. Book titles and
+//! author and series names are unique and sorted under the collation.
+
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// `text` lowercased, with accents and other combining marks removed.
+pub fn fold(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Adds the `fold` SQL function and collation to the connection. Every
+/// connection to a library needs them, since queries and indexes use them.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "fold",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            Ok(match ctx.get_raw(0) {
+                ValueRef::Text(text) => Some(fold(&String::from_utf8_lossy(text))),
+                ValueRef::Integer(n) => Some(n.to_string()),
+                ValueRef::Real(n) => Some(n.to_string()),
+                ValueRef::Null | ValueRef::Blob(_) => None,
+            })
+        },
+    )?;
+    conn.create_collation("fold", |a, b| fold(a).cmp(&fold(b)))
+}
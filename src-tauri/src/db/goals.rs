@@ -0,0 +1,32 @@
+//! Reading streaks for goals and the year in review.
+
+/// Current and longest runs of consecutive days in `days` (day numbers sorted
+/// newest first). The current run may end yesterday, since today isn't over.
+pub fn streaks(days: &[i64], today: i64) -> (i64, i64) {
+    let mut current = 0;
+    if let Some(&latest) = days.first() {
+        if latest >= today - 1 {
+            current = 1;
+            for pair in days.windows(2) {
+                if pair[0] - pair[1] != 1 {
+                    break;
+                }
+                current += 1;
+            }
+        }
+    }
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for &day in days {
+        run = if previous == Some(day + 1) {
+            run + 1
+        } else {
+            1
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+    (current, longest)
+}
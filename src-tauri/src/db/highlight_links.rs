@@ -0,0 +1,115 @@
+//! Links between highlights, across books, with an optional relation label.
+//! A pair is linked at most once in each direction.
+
+use super::highlights::{self, HIGHLIGHT_COLUMNS};
+use crate::models::{HighlightLink, LinkedHighlight};
+use rusqlite::{params, Connection, OptionalExtension};
+
+const LINK_COLUMNS: &str = "l.id, l.from_id, l.to_id, l.relation, l.created_at";
+
+fn link_from_row(row: &rusqlite::Row) -> rusqlite::Result<HighlightLink> {
+    Ok(HighlightLink {
+        id: row.get(0)?,
+        from_id: row.get(1)?,
+        to_id: row.get(2)?,
+        relation: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<HighlightLink> {
+    conn.query_row(
+        &format!("SELECT {LINK_COLUMNS} FROM highlight_links l WHERE l.id = ?1"),
+        params![id],
+        link_from_row,
+    )
+}
+
+/// Links `from_id` to `to_id`. Linking them again replaces the relation.
+/// Blank relations are stored as `None`.
+pub fn link(
+    conn: &Connection,
+    from_id: i64,
+    to_id: i64,
+    relation: Option<&str>,
+) -> Result<HighlightLink, String> {
+    if from_id == to_id {
+        return Err("A highlight can't be linked to itself".to_string());
+    }
+    for id in [from_id, to_id] {
+        highlights::get(conn, id)
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Highlight {id} not found"))?;
+    }
+    let relation = relation.map(str::trim).filter(|r| !r.is_empty());
+    conn.execute(
+        "INSERT INTO highlight_links (from_id, to_id, relation) VALUES (?1, ?2, ?3)
+         ON CONFLICT (from_id, to_id) DO UPDATE SET relation = excluded.relation",
+        params![from_id, to_id, relation],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.query_row(
+        &format!(
+            "SELECT {LINK_COLUMNS} FROM highlight_links l WHERE l.from_id = ?1 AND l.to_id = ?2"
+        ),
+        params![from_id, to_id],
+        link_from_row,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Removes a link. Returns whether it existed.
+pub fn unlink(conn: &Connection, id: i64) -> rusqlite::Result<bool> {
+    Ok(conn.execute("DELETE FROM highlight_links WHERE id = ?1", params![id])? > 0)
+}
+
+/// Highlights linked to `id` in either direction, newest link first.
+pub fn linked(conn: &Connection, id: i64) -> rusqlite::Result<Vec<LinkedHighlight>> {
+    conn.prepare(&format!(
+        "SELECT {HIGHLIGHT_COLUMNS}, {LINK_COLUMNS} FROM highlight_links l
+         INNER JOIN highlights h
+            ON h.id = CASE WHEN l.from_id = ?1 THEN l.to_id ELSE l.from_id END
+         WHERE l.from_id = ?1 OR l.to_id = ?1
+         ORDER BY l.created_at DESC, l.id DESC"
+    ))?
+    .query_map(params![id], |row| {
+        // The link's columns follow the highlight's 13.
+        let link = HighlightLink {
+            id: row.get(13)?,
+            from_id: row.get(14)?,
+            to_id: row.get(15)?,
+            relation: row.get(16)?,
+            created_at: row.get(17)?,
+        };
+        Ok(LinkedHighlight {
+            outgoing: link.from_id == id,
+            link,
+            highlight: highlights::highlight_from_row(row)?,
+        })
+    })?
+    .collect()
+}
+
+/// Removes the links of a highlight that is being deleted.
+pub fn remove_for(conn: &Connection, highlight_id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM highlight_links WHERE from_id = ?1 OR to_id = ?1",
+        params![highlight_id],
+    )?;
+    Ok(())
+}
+
+/// Moves the links of highlight `from` to `to`, when `from` is merged into
+/// it. Links `to` already has, and links between the two, are dropped.
+pub fn move_links(conn: &Connection, from: i64, to: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE OR IGNORE highlight_links SET from_id = ?2 WHERE from_id = ?1 AND to_id != ?2",
+        params![from, to],
+    )?;
+    conn.execute(
+        "UPDATE OR IGNORE highlight_links SET to_id = ?2 WHERE to_id = ?1 AND from_id != ?2",
+        params![from, to],
+    )?;
+    remove_for(conn, from)
+}
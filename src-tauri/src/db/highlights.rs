@@ -0,0 +1,687 @@
+//! Highlights, their notes and earlier versions of them, and the color palette.
+
+use crate::cfi;
+use crate::db::books::{chapter_from_row, store_chapters};
+use crate::models::{
+    AnnotationGeometry, AnnotationType, ChapterHighlights, Highlight, HighlightColor,
+    HighlightStyle, NewRegionAnnotation, NoteRevision,
+};
+use rusqlite::{params, Connection, OptionalExtension, Params};
+use std::path::Path;
+
+/// Revisions kept per highlight; older ones are dropped.
+const MAX_NOTE_REVISIONS: i64 = 50;
+
+pub const HIGHLIGHT_COLUMNS: &str =
+    "h.id, h.book_title, h.cfi, h.text, h.color, h.notes, h.created_at, h.unanchored, h.favorite, h.color_id, h.style, h.annotation_type, h.geometry";
+
+pub fn highlight_from_row(row: &rusqlite::Row) -> rusqlite::Result<Highlight> {
+    Ok(Highlight {
+        id: row.get(0)?,
+        book_title: row.get(1)?,
+        cfi: row.get(2)?,
+        text: row.get(3)?,
+        color: row.get(4)?,
+        notes: row.get(5)?,
+        created_at: row.get(6)?,
+        unanchored: row.get(7)?,
+        favorite: row.get(8)?,
+        color_id: row.get(9)?,
+        style: HighlightStyle::parse(&row.get::<_, String>(10)?),
+        annotation_type: AnnotationType::parse(&row.get::<_, String>(11)?),
+        geometry: row
+            .get::<_, Option<String>>(12)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+    })
+}
+
+pub fn get(conn: &Connection, id: i64) -> rusqlite::Result<Highlight> {
+    conn.query_row(
+        &format!("SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE h.id = ?1"),
+        params![id],
+        highlight_from_row,
+    )
+}
+
+fn query(conn: &Connection, sql: &str, params: impl Params) -> rusqlite::Result<Vec<Highlight>> {
+    conn.prepare(sql)?
+        .query_map(params, highlight_from_row)?
+        .collect()
+}
+
+/// The highlight of `text` at `cfi` in `book_title`, if there is one.
+pub fn find(
+    conn: &Connection,
+    book_title: &str,
+    cfi: &str,
+    text: &str,
+) -> rusqlite::Result<Option<Highlight>> {
+    conn.query_row(
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             WHERE h.book_title = ?1 AND h.cfi = ?2 AND h.text = ?3
+               AND h.annotation_type = 'text'
+             ORDER BY h.id LIMIT 1"
+        ),
+        params![book_title, cfi, text],
+        highlight_from_row,
+    )
+    .optional()
+}
+
+/// Adds a highlight, or returns the existing one when the same text at the
+/// same position is already highlighted.
+pub fn add(
+    conn: &Connection,
+    book_title: &str,
+    cfi: &str,
+    text: &str,
+    color: &str,
+    style: HighlightStyle,
+    notes: &str,
+) -> rusqlite::Result<Highlight> {
+    if let Some(existing) = find(conn, book_title, cfi, text)? {
+        return Ok(existing);
+    }
+    conn.execute(
+        "INSERT INTO highlights (book_title, cfi, text, color, style, notes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![book_title, cfi, text, color, style.as_str(), notes],
+    )?;
+    get(conn, conn.last_insert_rowid())
+}
+
+fn validate_geometry(annotation: &NewRegionAnnotation) -> Result<(), String> {
+    let AnnotationGeometry {
+        x,
+        y,
+        width,
+        height,
+        ..
+    } = annotation.geometry;
+    let fraction = |v: f64| (0.0..=1.0).contains(&v);
+    if !(fraction(x) && fraction(y) && width > 0.0 && height > 0.0)
+        || x + width > 1.0 + f64::EPSILON
+        || y + height > 1.0 + f64::EPSILON
+    {
+        return Err("The region must lie within the page".to_string());
+    }
+    match annotation.annotation_type {
+        AnnotationType::Text => Err("Text highlights have no region".to_string()),
+        AnnotationType::Image if annotation.geometry.src.is_none() => {
+            Err("Image annotations need the image's src".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Adds an image or region annotation. Unlike text highlights, several may
+/// share a position, e.g. regions of one page.
+pub fn add_region(
+    conn: &Connection,
+    annotation: &NewRegionAnnotation,
+) -> Result<Highlight, String> {
+    validate_geometry(annotation)?;
+    let geometry = serde_json::to_string(&annotation.geometry).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO highlights (book_title, cfi, text, color, notes, annotation_type, geometry)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            annotation.book_title,
+            annotation.cfi,
+            annotation.label.trim(),
+            annotation.color,
+            annotation.notes,
+            annotation.annotation_type.as_str(),
+            geometry
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    get(conn, conn.last_insert_rowid()).map_err(|e| e.to_string())
+}
+
+/// Changes a highlight's color and/or style. `None` if there is no such
+/// highlight.
+pub fn update(
+    conn: &Connection,
+    id: i64,
+    color: Option<&str>,
+    style: Option<HighlightStyle>,
+) -> rusqlite::Result<Option<Highlight>> {
+    let updated = conn.execute(
+        "UPDATE highlights SET color = COALESCE(?2, color), style = COALESCE(?3, style)
+         WHERE id = ?1",
+        params![id, color, style.map(HighlightStyle::as_str)],
+    )?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    get(conn, id).map(Some)
+}
+
+/// Highlights in `book_title`, favorites first, then newest first.
+pub fn for_book(conn: &Connection, book_title: &str) -> rusqlite::Result<Vec<Highlight>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE h.book_title = ?1
+             ORDER BY h.favorite DESC, h.created_at DESC"
+        ),
+        params![book_title],
+    )
+}
+
+/// Highlights in `book_title` in reading order.
+pub fn in_reading_order(conn: &Connection, book_title: &str) -> rusqlite::Result<Vec<Highlight>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE h.book_title = ?1
+             ORDER BY h.cfi COLLATE cfi_order"
+        ),
+        params![book_title],
+    )
+}
+
+/// Every highlight, favorites first, then newest first.
+pub fn all(conn: &Connection) -> rusqlite::Result<Vec<Highlight>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h ORDER BY h.favorite DESC, h.created_at DESC"
+        ),
+        [],
+    )
+}
+
+pub fn favorites(conn: &Connection) -> rusqlite::Result<Vec<Highlight>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h WHERE h.favorite ORDER BY h.created_at DESC"
+        ),
+        [],
+    )
+}
+
+/// Highlights in the palette color called `name` (ignoring case), newest
+/// first, optionally only from `book_title`.
+pub fn by_color(
+    conn: &Connection,
+    name: &str,
+    book_title: Option<&str>,
+) -> rusqlite::Result<Vec<Highlight>> {
+    query(
+        conn,
+        &format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             INNER JOIN highlight_colors c ON c.id = h.color_id
+             WHERE c.name = ?1 AND (?2 IS NULL OR h.book_title = ?2)
+             ORDER BY h.created_at DESC"
+        ),
+        params![name.trim(), book_title],
+    )
+}
+
+/// Highlights of `book_title` grouped by chapter, both in reading order.
+/// Chapters are read from the EPUB in `books_dir` on first use for books
+/// imported before chapter ranges were stored.
+pub fn by_chapter(
+    conn: &Connection,
+    book_title: &str,
+    books_dir: &Path,
+) -> Result<Vec<ChapterHighlights>, String> {
+    let (book_id, filename, format): (i64, String, String) = conn
+        .query_row(
+            "SELECT id, filename, format FROM books WHERE title = ?1",
+            params![book_title],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut chapters = conn
+        .prepare(
+            "SELECT id, book_id, position, title, start_cfi, end_cfi FROM chapters
+             WHERE book_id = ?1 ORDER BY position",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map(params![book_id], chapter_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    if chapters.is_empty() && crate::import::is_epub(&format) {
+        match store_chapters(conn, book_id, &books_dir.join(&filename)) {
+            Ok(stored) => chapters = stored,
+            Err(e) => log::warn!("Could not read chapters of {book_title}: {e}"),
+        }
+    }
+
+    let highlights = in_reading_order(conn, book_title).map_err(|e| e.to_string())?;
+    let mut groups: Vec<ChapterHighlights> = Vec::new();
+    for highlight in highlights {
+        let chapter = chapters
+            .iter()
+            .rev()
+            .find(|c| cfi::compare(&c.start_cfi, &highlight.cfi).is_le())
+            .cloned();
+        match groups.last_mut() {
+            Some(group)
+                if group.chapter.as_ref().map(|c| c.id) == chapter.as_ref().map(|c| c.id) =>
+            {
+                group.highlights.push(highlight)
+            }
+            _ => groups.push(ChapterHighlights {
+                chapter,
+                highlights: vec![highlight],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Replaces a highlight's note, keeping the old text in `note_revisions`
+/// unless it was empty or is unchanged. `None` if there is no such
+/// highlight.
+pub fn set_notes(conn: &Connection, id: i64, notes: &str) -> rusqlite::Result<Option<Highlight>> {
+    let previous: Option<String> = conn
+        .query_row(
+            "SELECT notes FROM highlights WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(previous) = previous.filter(|p| !p.trim().is_empty() && p != notes) {
+        conn.execute(
+            "INSERT INTO note_revisions (highlight_id, notes) VALUES (?1, ?2)",
+            params![id, previous],
+        )?;
+        conn.execute(
+            "DELETE FROM note_revisions WHERE highlight_id = ?1 AND id NOT IN
+                (SELECT id FROM note_revisions WHERE highlight_id = ?1 ORDER BY id DESC LIMIT ?2)",
+            params![id, MAX_NOTE_REVISIONS],
+        )?;
+    }
+    conn.execute(
+        "UPDATE highlights SET notes = ?1 WHERE id = ?2",
+        params![notes, id],
+    )?;
+    get(conn, id).optional()
+}
+
+/// Earlier versions of a highlight's note, newest first.
+pub fn note_history(conn: &Connection, highlight_id: i64) -> rusqlite::Result<Vec<NoteRevision>> {
+    conn.prepare(
+        "SELECT id, highlight_id, notes, replaced_at FROM note_revisions
+         WHERE highlight_id = ?1 ORDER BY id DESC",
+    )?
+    .query_map(params![highlight_id], note_revision_from_row)?
+    .collect()
+}
+
+pub fn note_revision(conn: &Connection, id: i64) -> rusqlite::Result<Option<NoteRevision>> {
+    conn.query_row(
+        "SELECT id, highlight_id, notes, replaced_at FROM note_revisions WHERE id = ?1",
+        params![id],
+        note_revision_from_row,
+    )
+    .optional()
+}
+
+fn note_revision_from_row(row: &rusqlite::Row) -> rusqlite::Result<NoteRevision> {
+    Ok(NoteRevision {
+        id: row.get(0)?,
+        highlight_id: row.get(1)?,
+        notes: row.get(2)?,
+        replaced_at: row.get(3)?,
+    })
+}
+
+pub fn toggle_favorite(conn: &Connection, id: i64) -> rusqlite::Result<Highlight> {
+    conn.execute(
+        "UPDATE highlights SET favorite = NOT favorite WHERE id = ?1",
+        params![id],
+    )?;
+    get(conn, id)
+}
+
+/// Deletes the highlight, its collection links, note revisions and links to
+/// other highlights.
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM highlight_collections WHERE highlight_id = ?1",
+        params![id],
+    )?;
+    super::highlight_links::remove_for(conn, id)?;
+    conn.execute(
+        "DELETE FROM note_revisions WHERE highlight_id = ?1",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM highlights WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Deletes the highlights, their collection links, note revisions and links
+/// to other highlights. Returns the ids that existed.
+pub fn delete_many(conn: &Connection, ids: &[i64]) -> rusqlite::Result<Vec<i64>> {
+    let mut unlink = conn.prepare("DELETE FROM highlight_collections WHERE highlight_id = ?1")?;
+    let mut revisions = conn.prepare("DELETE FROM note_revisions WHERE highlight_id = ?1")?;
+    let mut delete = conn.prepare("DELETE FROM highlights WHERE id = ?1")?;
+    let mut deleted = Vec::new();
+    for &id in ids {
+        unlink.execute(params![id])?;
+        revisions.execute(params![id])?;
+        super::highlight_links::remove_for(conn, id)?;
+        if delete.execute(params![id])? > 0 {
+            deleted.push(id);
+        }
+    }
+    Ok(deleted)
+}
+
+/// Sets the color of the highlights. Returns those that existed, updated.
+pub fn recolor_many(
+    conn: &Connection,
+    ids: &[i64],
+    color: &str,
+) -> rusqlite::Result<Vec<Highlight>> {
+    let mut update = conn.prepare("UPDATE highlights SET color = ?1 WHERE id = ?2")?;
+    let mut highlights = Vec::new();
+    for &id in ids {
+        if update.execute(params![color, id])? > 0 {
+            highlights.push(get(conn, id)?);
+        }
+    }
+    Ok(highlights)
+}
+
+/// Groups of highlights in `book_title` whose ranges overlap, e.g. from
+/// extending a highlight by selecting past its end. Each group is in reading
+/// order; highlights that overlap nothing are left out.
+pub fn overlapping(conn: &Connection, book_title: &str) -> rusqlite::Result<Vec<Vec<Highlight>>> {
+    // Sweep in reading order, growing a group while the next highlight
+    // overlaps any highlight already in it.
+    let mut groups: Vec<Vec<Highlight>> = Vec::new();
+    let mut group: Vec<Highlight> = Vec::new();
+    for highlight in in_reading_order(conn, book_title)? {
+        if !group.is_empty() && !group.iter().any(|h| cfi::overlaps(&h.cfi, &highlight.cfi)) {
+            groups.push(std::mem::take(&mut group));
+        }
+        group.push(highlight);
+    }
+    groups.push(group);
+    groups.retain(|group| group.len() > 1);
+    Ok(groups)
+}
+
+/// Appends `next` to `text`, dropping the words they have in common when one
+/// continues the other.
+fn join_overlapping_text(text: &mut String, next: &str) {
+    let next = next.trim();
+    if text.contains(next) {
+        return;
+    }
+    let overlap = next
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([next.len()])
+        .filter(|&i| i > 0 && text.ends_with(&next[..i]))
+        // Only whole words count, so "the" + "elephant" isn't "thelephant".
+        .filter(|&i| {
+            let before = text[..text.len() - i].chars().next_back();
+            let after = next[i..].chars().next();
+            !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+        })
+        .max()
+        .unwrap_or(0);
+    if overlap == 0 && !text.is_empty() {
+        text.push(' ');
+    }
+    text.push_str(&next[overlap..]);
+}
+
+/// What [`merge`] did.
+pub struct Merged {
+    pub highlight: Highlight,
+    /// The highlights merged into it, which are gone.
+    pub removed: Vec<i64>,
+}
+
+/// Combines highlights of one chapter into the earliest-created of them: its
+/// range grows to cover all of them, texts are joined without repeating the
+/// overlapping parts, notes are concatenated, and collection memberships,
+/// favorites and attachments carry over. The other highlights are deleted.
+/// Meant to run in a transaction.
+pub fn merge(conn: &Connection, ids: &[i64]) -> Result<Merged, String> {
+    let mut highlights = ids
+        .iter()
+        .map(|&id| get(conn, id))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    if highlights.len() < 2 {
+        return Err("Select at least two highlights to merge".to_string());
+    }
+    let book_title = highlights[0].book_title.clone();
+    if highlights.iter().any(|h| h.book_title != book_title) {
+        return Err("Only highlights from the same book can be merged".to_string());
+    }
+
+    highlights.sort_by(|a, b| cfi::compare(&a.cfi, &b.cfi));
+    let mut cfi = highlights[0].cfi.clone();
+    let mut text = String::new();
+    let mut notes: Vec<&str> = Vec::new();
+    for highlight in &highlights {
+        cfi = cfi::span(&cfi, &highlight.cfi)
+            .ok_or("Only highlights from the same chapter can be merged")?;
+        join_overlapping_text(&mut text, &highlight.text);
+        let note = highlight.notes.trim();
+        if !note.is_empty() && !notes.contains(&note) {
+            notes.push(note);
+        }
+    }
+    let keep = highlights
+        .iter()
+        .min_by(|a, b| (&a.created_at, a.id).cmp(&(&b.created_at, b.id)))
+        .expect("at least two highlights");
+    let favorite = highlights.iter().any(|h| h.favorite);
+    let removed: Vec<i64> = highlights
+        .iter()
+        .map(|h| h.id)
+        .filter(|id| *id != keep.id)
+        .collect();
+
+    for id in &removed {
+        conn.execute(
+            "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id)
+             SELECT ?1, collection_id FROM highlight_collections WHERE highlight_id = ?2",
+            params![keep.id, id],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE attachments SET highlight_id = ?1 WHERE highlight_id = ?2",
+            params![keep.id, id],
+        )
+        .map_err(|e| e.to_string())?;
+        super::highlight_links::move_links(conn, *id, keep.id).map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM highlight_collections WHERE highlight_id = ?1",
+            params![id],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            "DELETE FROM note_revisions WHERE highlight_id = ?1",
+            params![id],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM highlights WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+    }
+    // Sync identifies highlights by position and text, so the kept one's old
+    // identity is retired like a deletion.
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_tombstones (kind, book_title, cfi, text, deleted_at)
+         VALUES ('highlight', ?1, ?2, ?3, strftime('%Y-%m-%d %H:%M:%f', 'now'))",
+        params![book_title, keep.cfi, keep.text],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE highlights SET cfi = ?1, text = ?2, notes = ?3, favorite = ?4,
+             updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+         WHERE id = ?5",
+        params![cfi, text, notes.join("\n\n"), favorite, keep.id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(Merged {
+        highlight: get(conn, keep.id).map_err(|e| e.to_string())?,
+        removed,
+    })
+}
+
+/// What [`dedupe`] did.
+pub struct Deduped {
+    /// Highlights that had duplicates, as they are now.
+    pub kept: Vec<Highlight>,
+    /// The duplicates, which are gone.
+    pub removed: Vec<i64>,
+}
+
+/// Removes highlights of the same text at the same position as an older one.
+/// The oldest of each group keeps the others' notes, collection memberships,
+/// attachments and favorite. Meant to run in a transaction.
+pub fn dedupe(conn: &Connection) -> rusqlite::Result<Deduped> {
+    let groups: Vec<(i64, String)> = conn
+        .prepare(
+            "SELECT MIN(id), group_concat(id) FROM highlights
+             GROUP BY book_title, cfi, text, annotation_type, geometry HAVING COUNT(*) > 1",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut deduped = Deduped {
+        kept: Vec::new(),
+        removed: Vec::new(),
+    };
+    for (keep, ids) in groups {
+        let mut highlights = ids
+            .split(',')
+            .filter_map(|id| id.parse().ok())
+            .map(|id| get(conn, id))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        highlights.sort_by_key(|h| h.id);
+        let mut notes: Vec<&str> = Vec::new();
+        for highlight in &highlights {
+            let note = highlight.notes.trim();
+            if !note.is_empty() && !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+        let favorite = highlights.iter().any(|h| h.favorite);
+        let removed: Vec<i64> = highlights
+            .iter()
+            .map(|h| h.id)
+            .filter(|id| *id != keep)
+            .collect();
+        for id in &removed {
+            conn.execute(
+                "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id)
+                 SELECT ?1, collection_id FROM highlight_collections WHERE highlight_id = ?2",
+                params![keep, id],
+            )?;
+            conn.execute(
+                "UPDATE attachments SET highlight_id = ?1 WHERE highlight_id = ?2",
+                params![keep, id],
+            )?;
+            super::highlight_links::move_links(conn, *id, keep)?;
+            conn.execute(
+                "DELETE FROM highlight_collections WHERE highlight_id = ?1",
+                params![id],
+            )?;
+            conn.execute(
+                "DELETE FROM note_revisions WHERE highlight_id = ?1",
+                params![id],
+            )?;
+            conn.execute("DELETE FROM highlights WHERE id = ?1", params![id])?;
+        }
+        // Deleting a duplicate records a tombstone for the position and text
+        // the kept highlight still has.
+        let kept = &highlights[0];
+        conn.execute(
+            "DELETE FROM sync_tombstones WHERE kind = 'highlight'
+                AND book_title = ?1 AND cfi = ?2 AND text = ?3",
+            params![kept.book_title, kept.cfi, kept.text],
+        )?;
+        conn.execute(
+            "UPDATE highlights SET notes = ?1, favorite = ?2 WHERE id = ?3",
+            params![notes.join("\n\n"), favorite, keep],
+        )?;
+        deduped.kept.push(get(conn, keep)?);
+        deduped.removed.extend(removed);
+    }
+    Ok(deduped)
+}
+
+/// `#rgb` or `#rrggbb`, lowercased.
+fn normalize_hex(hex: &str) -> Result<String, String> {
+    let hex = hex.trim().to_lowercase();
+    let digits = hex.strip_prefix('#').unwrap_or("");
+    if !matches!(digits.len(), 3 | 6) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{hex} isn't a hex color like #facc15"));
+    }
+    Ok(hex)
+}
+
+/// The palette, in display order.
+pub fn colors(conn: &Connection) -> rusqlite::Result<Vec<HighlightColor>> {
+    conn.prepare("SELECT id, name, hex, sort_order FROM highlight_colors ORDER BY sort_order, id")?
+        .query_map([], |row| {
+            Ok(HighlightColor {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                hex: row.get(2)?,
+                sort_order: row.get(3)?,
+            })
+        })?
+        .collect()
+}
+
+/// Adds a color at the end of the palette. Existing highlights with this hex
+/// are linked to it.
+pub fn add_color(conn: &Connection, name: &str, hex: &str) -> Result<(), String> {
+    let hex = normalize_hex(hex)?;
+    conn.execute(
+        "INSERT INTO highlight_colors (name, hex, sort_order)
+         VALUES (?1, ?2, (SELECT COALESCE(MAX(sort_order), -1) + 1 FROM highlight_colors))",
+        params![name.trim(), hex],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Renames a palette color or changes its hex. Highlights in that color
+/// follow the new hex.
+pub fn update_color(conn: &Connection, id: i64, name: &str, hex: &str) -> Result<(), String> {
+    let hex = normalize_hex(hex)?;
+    conn.execute(
+        "UPDATE highlight_colors SET name = ?1, hex = ?2 WHERE id = ?3",
+        params![name.trim(), hex, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Removes a color from the palette. Highlights keep their hex.
+pub fn delete_color(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM highlight_colors WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Puts the palette in the order of `ids`.
+pub fn reorder_colors(conn: &mut Connection, ids: &[i64]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    for (position, id) in ids.iter().enumerate() {
+        tx.execute(
+            "UPDATE highlight_colors SET sort_order = ?1 WHERE id = ?2",
+            params![position as i64, id],
+        )?;
+    }
+    tx.commit()
+}
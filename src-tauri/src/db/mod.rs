@@ -0,0 +1,250 @@
+//! The library database: the connection pool of the active profile, and in
+//! the submodules the queries the commands, the HTTP server and the CLI build
+//! on. Nothing here depends on Tauri, so it can be exercised against an
+//! in-memory database.
+
+pub mod book_notes;
+pub mod book_tags;
+pub mod bookmarks;
+pub mod books;
+pub mod collections;
+pub mod drawings;
+pub mod feeds;
+pub mod fold;
+pub mod goals;
+pub mod highlight_links;
+pub mod highlights;
+pub mod review;
+pub mod vocabulary;
+
+pub use books::{book_from_row, store_chapters, BOOK_COLUMNS};
+pub use goals::streaks;
+pub use highlights::{highlight_from_row, HIGHLIGHT_COLUMNS};
+
+use crate::{app_lock, encryption, migrations, oplog, profiles, storage};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+/// Shared connection pool of the active profile. Cheap to clone, so
+/// long-running commands can move a handle onto a blocking thread instead of
+/// holding the whole database hostage. Clones follow profile switches;
+/// connections already taken keep using the library they came from.
+#[derive(Clone)]
+pub struct DbState(Arc<RwLock<Library>>);
+
+pub struct Library {
+    name: String,
+    dir: std::path::PathBuf,
+    /// `None` while an encrypted library is locked.
+    pool: Option<DbPool>,
+    key: Option<Arc<encryption::LibraryKey>>,
+    /// Set while the profile's app lock hasn't been unlocked.
+    app_locked: bool,
+}
+
+pub const DB_FILE: &str = "highlights.db";
+
+/// How long [`DbState::close`] waits for connections in use to be handed back.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Library {
+    /// Opens (creating if needed) and migrates the profile's database. An
+    /// encrypted profile is left locked until `unlock_library`, and one with
+    /// an app lock until `unlock_app`.
+    pub fn open(app_dir: &std::path::Path, name: &str) -> Result<Library, String> {
+        let dir = profiles::dir(app_dir, name);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        if encryption::is_enabled(&dir) {
+            return Ok(Library::locked(name, dir));
+        }
+        Library::open_with_key(name, dir, None)
+    }
+
+    pub fn locked(name: &str, dir: std::path::PathBuf) -> Library {
+        Library {
+            name: name.to_string(),
+            dir,
+            pool: None,
+            key: None,
+            app_locked: false,
+        }
+    }
+
+    pub fn open_with_key(
+        name: &str,
+        dir: std::path::PathBuf,
+        key: Option<Arc<encryption::LibraryKey>>,
+    ) -> Result<Library, String> {
+        let pool = open_pool(&dir.join(DB_FILE), key.clone())?;
+        let mut conn = pool.get().map_err(|e| e.to_string())?;
+        migrations::run(&mut conn)?;
+        oplog::prune(&conn).map_err(|e| e.to_string())?;
+        let app_locked = app_lock::stored(&conn)
+            .map_err(|e| e.to_string())?
+            .is_some();
+        Ok(Library {
+            name: name.to_string(),
+            dir,
+            pool: Some(pool),
+            key,
+            app_locked,
+        })
+    }
+}
+
+impl DbState {
+    pub fn new(library: Library) -> DbState {
+        DbState(Arc::new(RwLock::new(library)))
+    }
+
+    pub fn conn(&self) -> Result<PooledConnection, String> {
+        if self.is_app_locked()? {
+            return Err(app_lock::LOCKED.to_string());
+        }
+        self.unchecked_conn()
+    }
+
+    /// A connection even while the app lock is on, for the lock's own
+    /// commands.
+    pub fn unchecked_conn(&self) -> Result<PooledConnection, String> {
+        let pool = self
+            .0
+            .read()
+            .map_err(|e| e.to_string())?
+            .pool
+            .clone()
+            .ok_or(encryption::LOCKED)?;
+        pool.get().map_err(|e| e.to_string())
+    }
+
+    pub fn is_app_locked(&self) -> Result<bool, String> {
+        Ok(self.0.read().map_err(|e| e.to_string())?.app_locked)
+    }
+
+    pub fn set_app_locked(&self, locked: bool) -> Result<(), String> {
+        self.0.write().map_err(|e| e.to_string())?.app_locked = locked;
+        Ok(())
+    }
+
+    /// Key of the active profile, if it is encrypted and unlocked.
+    pub fn key(&self) -> Result<Option<Arc<encryption::LibraryKey>>, String> {
+        Ok(self.0.read().map_err(|e| e.to_string())?.key.clone())
+    }
+
+    pub fn is_locked(&self) -> Result<bool, String> {
+        Ok(self.0.read().map_err(|e| e.to_string())?.pool.is_none())
+    }
+
+    /// Folder holding the active profile's database, books and backups.
+    pub fn dir(&self) -> Result<std::path::PathBuf, String> {
+        Ok(self.0.read().map_err(|e| e.to_string())?.dir.clone())
+    }
+
+    pub fn profile(&self) -> Result<String, String> {
+        Ok(self.0.read().map_err(|e| e.to_string())?.name.clone())
+    }
+
+    /// Closes the active profile's database so a command can replace the
+    /// file: new connections fail with [`encryption::LOCKED`], and this waits
+    /// until those in use are handed back, so nothing writes to it afterwards.
+    /// The key is kept; [`Library::open_with_key`] reopens the library.
+    pub fn close(&self) -> Result<(), String> {
+        let Some(pool) = self.0.write().map_err(|e| e.to_string())?.pool.take() else {
+            return Ok(());
+        };
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        loop {
+            let state = pool.state();
+            if state.idle_connections == state.connections {
+                // Dropping the pool closes the connections, which checkpoints
+                // the WAL into the file.
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                let mut library = self.0.write().map_err(|e| e.to_string())?;
+                library.pool.get_or_insert(pool);
+                return Err("The library is busy; try again in a moment".to_string());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    pub fn switch_to(&self, library: Library) -> Result<(), String> {
+        storage::set_key(library.key.clone());
+        *self.0.write().map_err(|e| e.to_string())? = library;
+        Ok(())
+    }
+}
+
+/// Settings key of the folder chosen with `set_library_path`.
+pub const LIBRARY_PATH_SETTING: &str = "library.path";
+
+/// Folder holding the book files: the one chosen with `set_library_path`, or
+/// `books` in `library_dir`, the profile's folder.
+pub fn books_dir(conn: &Connection, library_dir: &Path) -> Result<PathBuf, String> {
+    let custom: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![LIBRARY_PATH_SETTING],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(custom.map_or_else(|| default_books_dir(library_dir), PathBuf::from))
+}
+
+pub fn default_books_dir(library_dir: &Path) -> PathBuf {
+    library_dir.join("books")
+}
+
+/// Whether newly stored book files are compressed; see [`storage`].
+pub fn compress_books(conn: &Connection) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![storage::COMPRESS_SETTING],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+    .map(|value| value.as_deref() == Some("true"))
+    .map_err(|e| e.to_string())
+}
+
+/// Adds the SQL functions and collations queries and indexes rely on:
+/// [`fold`] and [`crate::cfi`]'s `cfi_order`.
+pub fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    fold::register(conn)?;
+    crate::cfi::register(conn)
+}
+
+/// A migrated database that lives only as long as the connection.
+pub fn open_in_memory() -> Result<Connection, String> {
+    let mut conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+    register_functions(&conn).map_err(|e| e.to_string())?;
+    migrations::run(&mut conn)?;
+    Ok(conn)
+}
+
+fn open_pool(
+    db_path: &std::path::Path,
+    key: Option<Arc<encryption::LibraryKey>>,
+) -> Result<DbPool, String> {
+    // WAL lets readers proceed while a writer is active; the busy timeout makes
+    // concurrent writers wait for each other instead of failing with SQLITE_BUSY.
+    // SQLCipher needs the key before anything else reads the file.
+    let manager = SqliteConnectionManager::file(db_path).with_init(move |c| {
+        if let Some(key) = &key {
+            encryption::apply(c, key)?;
+        }
+        c.execute_batch("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;")?;
+        register_functions(c)
+    });
+    r2d2::Pool::builder()
+        .build(manager)
+        .map_err(|e| e.to_string())
+}
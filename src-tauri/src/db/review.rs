@@ -0,0 +1,80 @@
+//! The daily review: a few old highlights resurfaced each day, favoring those
+//! not seen for the longest.
+//!
+//! The sample is drawn once a day. Highlights it picks are stamped with
+//! `last_surfaced_at`, and asking again the same day returns them instead of
+//! drawing anew, so the review doesn't change as the app is reopened.
+
+use super::highlights::{highlight_from_row, HIGHLIGHT_COLUMNS};
+use crate::models::Highlight;
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+
+/// Highlights younger than this are too fresh to need resurfacing.
+const MIN_AGE_DAYS: i64 = 7;
+
+/// A number in (0, 1] that is the same for `id` all through `day`.
+fn daily_random(day: &str, id: i64) -> f64 {
+    let digest = Sha256::digest(format!("{day}:{id}"));
+    let bits = u64::from_le_bytes(digest[..8].try_into().expect("8 bytes"));
+    ((bits >> 11) + 1) as f64 / (1u64 << 53) as f64
+}
+
+/// Up to `count` highlights at least a week old for today's review. Each is
+/// drawn with a weight of the days since it was last surfaced (or created),
+/// so long-forgotten highlights come up first without recent ones never
+/// coming up at all.
+pub fn daily(conn: &Connection, count: usize) -> rusqlite::Result<Vec<Highlight>> {
+    let today: String = conn.query_row("SELECT date('now', 'localtime')", [], |row| row.get(0))?;
+    // Shown in an order of the day, the same however they were picked.
+    let shuffle = |highlights: &mut Vec<Highlight>| {
+        highlights.sort_by(|a, b| {
+            daily_random(&today, b.id)
+                .total_cmp(&daily_random(&today, a.id))
+                .then(a.id.cmp(&b.id))
+        });
+    };
+
+    let mut review = conn
+        .prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             WHERE date(h.last_surfaced_at, 'localtime') = ?1"
+        ))?
+        .query_map(params![today], highlight_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if review.len() >= count {
+        shuffle(&mut review);
+        review.truncate(count);
+        return Ok(review);
+    }
+
+    // Weighted sampling without replacement: each candidate's key is
+    // u^(1/weight), compared as ln(u) / weight, and the largest keys win.
+    let mut candidates: Vec<(Highlight, f64)> = conn
+        .prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS},
+                julianday(?1) - julianday(date(coalesce(h.last_surfaced_at, h.created_at), 'localtime'))
+             FROM highlights h
+             WHERE julianday(?1) - julianday(date(h.created_at, 'localtime')) >= ?2
+               AND (h.last_surfaced_at IS NULL
+                    OR date(h.last_surfaced_at, 'localtime') < ?1)"
+        ))?
+        .query_map(params![today, MIN_AGE_DAYS], |row| {
+            let highlight = highlight_from_row(row)?;
+            let days: f64 = row.get(13)?;
+            let key = daily_random(&today, highlight.id).ln() / days.max(1.0);
+            Ok((highlight, key))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    candidates.sort_by(|(a, a_key), (b, b_key)| b_key.total_cmp(a_key).then(a.id.cmp(&b.id)));
+    candidates.truncate(count - review.len());
+
+    let mut stamp =
+        conn.prepare("UPDATE highlights SET last_surfaced_at = datetime('now') WHERE id = ?1")?;
+    for (highlight, _) in candidates {
+        stamp.execute(params![highlight.id])?;
+        review.push(highlight);
+    }
+    shuffle(&mut review);
+    Ok(review)
+}
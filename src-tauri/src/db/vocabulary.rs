@@ -0,0 +1,51 @@
+//! Words saved while reading.
+
+use crate::models::VocabWord;
+use rusqlite::{params, Connection};
+
+pub fn vocab_word_from_row(row: &rusqlite::Row) -> rusqlite::Result<VocabWord> {
+    Ok(VocabWord {
+        id: row.get(0)?,
+        word: row.get(1)?,
+        context: row.get(2)?,
+        book_title: row.get(3)?,
+        cfi: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+/// Saves `word` (trimmed) with the sentence it was found in.
+pub fn add(
+    conn: &Connection,
+    word: &str,
+    context: &str,
+    book_title: Option<&str>,
+    cfi: Option<&str>,
+) -> rusqlite::Result<VocabWord> {
+    conn.execute(
+        "INSERT INTO vocabulary (word, context, book_title, cfi) VALUES (?1, ?2, ?3, ?4)",
+        params![word.trim(), context, book_title, cfi],
+    )?;
+    conn.query_row(
+        "SELECT id, word, context, book_title, cfi, created_at FROM vocabulary WHERE id = ?1",
+        params![conn.last_insert_rowid()],
+        vocab_word_from_row,
+    )
+}
+
+/// Words saved while reading `book_title`, or every saved word when it's
+/// `None`, newest first.
+pub fn list(conn: &Connection, book_title: Option<&str>) -> rusqlite::Result<Vec<VocabWord>> {
+    conn.prepare(
+        "SELECT id, word, context, book_title, cfi, created_at FROM vocabulary
+         WHERE ?1 IS NULL OR book_title = ?1
+         ORDER BY created_at DESC",
+    )?
+    .query_map(params![book_title], vocab_word_from_row)?
+    .collect()
+}
+
+pub fn delete(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM vocabulary WHERE id = ?1", params![id])?;
+    Ok(())
+}
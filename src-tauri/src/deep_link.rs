@@ -0,0 +1,29 @@
+//! `tumelog://book/{id}?cfi=...` links, which reopen a book at a position from
+//! outside the app (e.g. from exported notes).
+
+use tauri::Url;
+
+pub const SCHEME: &str = "tumelog";
+
+/// A link to `book_id`, at `cfi` if given.
+pub fn book_url(book_id: i64, cfi: Option<&str>) -> String {
+    let mut url = Url::parse(&format!("{SCHEME}://book/{book_id}")).expect("valid URL");
+    if let Some(cfi) = cfi {
+        url.query_pairs_mut().append_pair("cfi", cfi);
+    }
+    url.into()
+}
+
+/// Book id and position from a [`book_url`] link.
+pub fn parse(url: &Url) -> Option<(i64, Option<String>)> {
+    if url.scheme() != SCHEME || url.host_str() != Some("book") {
+        return None;
+    }
+    let id = url.path().trim_matches('/').parse().ok()?;
+    let cfi = url
+        .query_pairs()
+        .find(|(key, _)| key == "cfi")
+        .map(|(_, value)| value.into_owned())
+        .filter(|cfi| !cfi.is_empty());
+    Some((id, cfi))
+}
@@ -0,0 +1,258 @@
+//! Log files and the diagnostics bundle users attach to bug reports.
+//!
+//! The app logs to `logs/` in the app data directory, one JSON object per
+//! line, rolling over to a dated file once the current one grows past
+//! [`MAX_LOG_SIZE`]. The bundle is a zip of those files and a summary of the
+//! library: schema version, database pragmas and how many of each kind of
+//! record there are. Nothing in the summary names a book or quotes a
+//! highlight, and paths under the home directory are shortened to `~` in the
+//! logs that go in.
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+
+/// Name of the current log file, without `.log`; rotated ones add a date.
+const LOG_FILE: &str = "tumelog";
+
+/// Size at which the current log file is rotated.
+const MAX_LOG_SIZE: u128 = 2 * 1024 * 1024;
+
+/// Rotated log files kept besides the current one.
+const KEPT_LOGS: usize = 5;
+
+/// Most lines [`recent`] returns.
+pub const MAX_RECENT_LINES: usize = 5000;
+
+/// Tables counted in the bundle's summary.
+const COUNTED_TABLES: &[&str] = &[
+    "books",
+    "highlights",
+    "bookmarks",
+    "collections",
+    "smart_collections",
+    "vocabulary",
+    "reading_sessions",
+    "reading_goals",
+    "book_notes",
+    "drawings",
+    "authors",
+    "series",
+    "note_revisions",
+    "sync_tombstones",
+    "oplog",
+    "undo_stack",
+];
+
+const PRAGMAS: &[&str] = &[
+    "journal_mode",
+    "page_size",
+    "page_count",
+    "freelist_count",
+    "auto_vacuum",
+    "user_version",
+    "encoding",
+    "cipher_version",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LogEntry {
+    pub ts: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+pub fn logs_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join("logs")
+}
+
+/// The log plugin, writing rotating files under [`logs_dir`] and, in debug
+/// builds, plain lines to stdout.
+pub fn log_plugin<R: tauri::Runtime>(app_dir: &Path) -> tauri::plugin::TauriPlugin<R> {
+    let file = Target::new(TargetKind::Folder {
+        path: logs_dir(app_dir),
+        file_name: Some(LOG_FILE.into()),
+    })
+    .format(|out, message, record| {
+        let ts = time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default();
+        out.finish(format_args!(
+            "{}",
+            serde_json::json!({
+                "ts": ts,
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": message.to_string(),
+            })
+        ))
+    });
+    let mut builder = tauri_plugin_log::Builder::default()
+        .clear_targets()
+        .target(file)
+        .rotation_strategy(RotationStrategy::KeepSome(KEPT_LOGS))
+        .max_file_size(MAX_LOG_SIZE)
+        .level(log::LevelFilter::Info);
+    if cfg!(debug_assertions) {
+        builder = builder.target(Target::new(TargetKind::Stdout));
+    }
+    builder.build()
+}
+
+/// Log files oldest first: rotated ones by date, then the current one.
+fn log_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let current = format!("{LOG_FILE}.log");
+    let mut rotated = Vec::new();
+    let mut has_current = false;
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name == current {
+            has_current = true;
+        } else if name.starts_with(&format!("{LOG_FILE}_")) && name.ends_with(".log") {
+            rotated.push(name);
+        }
+    }
+    rotated.sort();
+    if has_current {
+        rotated.push(current);
+    }
+    Ok(rotated.into_iter().map(|name| dir.join(name)).collect())
+}
+
+fn parse_line(line: &str) -> LogEntry {
+    serde_json::from_str(line).unwrap_or_else(|_| LogEntry {
+        ts: String::new(),
+        level: String::new(),
+        target: String::new(),
+        message: line.to_string(),
+    })
+}
+
+/// The last `lines` log lines, oldest first, reaching back into rotated
+/// files when the current one is shorter.
+pub fn recent(app_dir: &Path, lines: usize) -> Result<Vec<LogEntry>, String> {
+    let lines = lines.clamp(1, MAX_RECENT_LINES);
+    let dir = logs_dir(app_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for path in log_files(&dir).map_err(|e| e.to_string())?.iter().rev() {
+        let text = std::fs::read(path).map_err(|e| e.to_string())?;
+        let text = String::from_utf8_lossy(&text);
+        for line in text.lines().rev().filter(|line| !line.trim().is_empty()) {
+            entries.push(parse_line(line));
+            if entries.len() == lines {
+                entries.reverse();
+                return Ok(entries);
+            }
+        }
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+fn pragma(conn: &Connection, name: &str) -> serde_json::Value {
+    let value = conn
+        .query_row(&format!("PRAGMA {name}"), [], |row| row.get::<_, Value>(0))
+        .optional();
+    match value {
+        Ok(Some(Value::Integer(n))) => n.into(),
+        Ok(Some(Value::Real(n))) => n.into(),
+        Ok(Some(Value::Text(s))) => s.into(),
+        Ok(_) => serde_json::Value::Null,
+        Err(e) => format!("error: {e}").into(),
+    }
+}
+
+fn library_summary(conn: &Connection) -> serde_json::Value {
+    let pragmas: serde_json::Map<_, _> = PRAGMAS
+        .iter()
+        .map(|name| (name.to_string(), pragma(conn, name)))
+        .collect();
+    let counts: serde_json::Map<_, _> = COUNTED_TABLES
+        .iter()
+        .map(|table| {
+            let count = conn
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .map_or_else(|e| format!("error: {e}").into(), serde_json::Value::from);
+            (table.to_string(), count)
+        })
+        .collect();
+    serde_json::json!({
+        "schema_version": crate::migrations::schema_version(conn).ok(),
+        "latest_schema_version": crate::migrations::latest_version(),
+        "pragmas": pragmas,
+        "counts": counts,
+    })
+}
+
+/// Replaces the home directory in log text with `~`, so usernames in paths
+/// stay out of the bundle.
+fn redact_home(text: &str) -> String {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .unwrap_or_default();
+    if home.len() < 2 {
+        return text.to_string();
+    }
+    // Log lines are JSON, where Windows paths have their backslashes doubled.
+    let escaped = serde_json::to_string(&home).unwrap_or_default();
+    text.replace(escaped.trim_matches('"'), "~")
+        .replace(&home, "~")
+}
+
+/// Writes the diagnostics bundle to `dest`. Without a connection, e.g. while
+/// the library is locked, the summary says why the library part is missing.
+pub fn export(
+    app_dir: &Path,
+    app_version: &str,
+    conn: Result<&Connection, String>,
+    dest: &Path,
+) -> Result<(), String> {
+    use zip::write::SimpleFileOptions;
+    use zip::CompressionMethod;
+
+    let library = match conn {
+        Ok(conn) => library_summary(conn),
+        Err(e) => serde_json::json!({ "error": e }),
+    };
+    let summary = serde_json::json!({
+        "app_version": app_version,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "library": library,
+    });
+
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("diagnostics.json", options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(
+        serde_json::to_string_pretty(&summary)
+            .map_err(|e| e.to_string())?
+            .as_bytes(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let dir = logs_dir(app_dir);
+    if dir.is_dir() {
+        for path in log_files(&dir).map_err(|e| e.to_string())? {
+            let text = std::fs::read(&path).map_err(|e| e.to_string())?;
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            zip.start_file(format!("logs/{name}"), options)
+                .map_err(|e| e.to_string())?;
+            zip.write_all(redact_home(&String::from_utf8_lossy(&text)).as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let bytes = zip.finish().map_err(|e| e.to_string())?.into_inner();
+    std::fs::write(dest, bytes).map_err(|e| e.to_string())
+}
@@ -0,0 +1,496 @@
+//! Offline dictionary lookup for StarDict and ABBYY Lingvo DSL dictionaries.
+//!
+//! Dictionaries are discovered in a user folder (recursively) and loaded fully
+//! into memory on first use. Supported files:
+//!
+//! * StarDict: `*.ifo` + `*.idx` (or `*.idx.gz`) + `*.dict` (or `*.dict.dz`)
+//! * DSL: `*.dsl` or `*.dsl.dz`, UTF-8 or UTF-16 encoded
+
+use flate2::read::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DictionaryInfo {
+    pub name: String,
+    pub format: String,
+    pub lang: Option<String>,
+    pub word_count: usize,
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Definition {
+    pub dictionary: String,
+    pub word: String,
+    pub definition: String,
+    /// `"text"` or `"html"`
+    pub content_type: String,
+}
+
+enum Entries {
+    /// StarDict: (offset, size) ranges into the decompressed `.dict` data.
+    StarDict {
+        data: Vec<u8>,
+        ranges: Vec<(u64, u32)>,
+        same_type_sequence: Option<String>,
+    },
+    /// DSL: article bodies already stripped of markup.
+    Dsl { articles: Vec<String> },
+}
+
+pub struct Dictionary {
+    pub info: DictionaryInfo,
+    /// Lowercased headword -> (original headword, entry index)
+    index: HashMap<String, Vec<(String, usize)>>,
+    entries: Entries,
+}
+
+impl Dictionary {
+    pub fn lookup(&self, word: &str) -> Vec<Definition> {
+        let Some(hits) = self.index.get(&normalize(word)) else {
+            return Vec::new();
+        };
+        hits.iter()
+            .filter_map(|(headword, idx)| {
+                let (definition, content_type) = self.entry(*idx)?;
+                Some(Definition {
+                    dictionary: self.info.name.clone(),
+                    word: headword.clone(),
+                    definition,
+                    content_type,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether this dictionary should answer lookups for `lang`. Dictionaries
+    /// that don't declare a language are always consulted.
+    pub fn matches_lang(&self, lang: &str) -> bool {
+        match &self.info.lang {
+            None => true,
+            Some(own) => {
+                let own = own.to_lowercase();
+                let lang = lang.to_lowercase();
+                own == lang
+                    || own.starts_with(&format!("{lang}-"))
+                    || own.split('-').any(|l| l == lang)
+            }
+        }
+    }
+
+    fn entry(&self, idx: usize) -> Option<(String, String)> {
+        match &self.entries {
+            Entries::Dsl { articles } => Some((articles.get(idx)?.clone(), "text".to_string())),
+            Entries::StarDict {
+                data,
+                ranges,
+                same_type_sequence,
+            } => {
+                let (offset, size) = *ranges.get(idx)?;
+                let start = offset as usize;
+                let bytes = data.get(start..start + size as usize)?;
+                Some(decode_stardict_entry(bytes, same_type_sequence.as_deref()))
+            }
+        }
+    }
+}
+
+fn normalize(word: &str) -> String {
+    word.trim().to_lowercase()
+}
+
+/// Scans `dir` recursively and loads every dictionary it can parse. Broken
+/// dictionaries are logged and skipped so one bad file doesn't disable lookup.
+pub fn load_all(dir: &Path) -> Vec<Dictionary> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files);
+    files.sort();
+
+    let mut dictionaries = Vec::new();
+    for path in files {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let loaded = if name.ends_with(".ifo") {
+            load_stardict(&path)
+        } else if name.ends_with(".dsl") || name.ends_with(".dsl.dz") {
+            load_dsl(&path)
+        } else {
+            continue;
+        };
+        match loaded {
+            Ok(dict) => dictionaries.push(dict),
+            Err(e) => log::warn!("Skipping dictionary {}: {}", path.display(), e),
+        }
+    }
+    dictionaries
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, out);
+        } else {
+            out.push(path);
+        }
+    }
+}
+
+fn read_maybe_gzipped(path: &Path) -> Result<Vec<u8>, String> {
+    let raw = std::fs::read(path).map_err(|e| e.to_string())?;
+    if raw.starts_with(&[0x1f, 0x8b]) {
+        let mut out = Vec::new();
+        MultiGzDecoder::new(&raw[..])
+            .read_to_end(&mut out)
+            .map_err(|e| e.to_string())?;
+        Ok(out)
+    } else {
+        Ok(raw)
+    }
+}
+
+/// Returns the first of `<stem><ext>` for each candidate extension that exists.
+fn sibling(ifo: &Path, exts: &[&str]) -> Option<PathBuf> {
+    let stem = ifo.file_stem()?.to_str()?;
+    exts.iter()
+        .map(|ext| ifo.with_file_name(format!("{stem}{ext}")))
+        .find(|p| p.exists())
+}
+
+// ---------------------------------------------------------------------------
+// StarDict
+// ---------------------------------------------------------------------------
+
+fn load_stardict(ifo_path: &Path) -> Result<Dictionary, String> {
+    let ifo = std::fs::read_to_string(ifo_path).map_err(|e| e.to_string())?;
+    let mut lines = ifo.lines();
+    if !lines.next().is_some_and(|l| {
+        l.trim_start_matches('\u{feff}')
+            .starts_with("StarDict's dict ifo file")
+    }) {
+        return Err("not a StarDict .ifo file".to_string());
+    }
+    let meta: HashMap<&str, &str> = lines
+        .filter_map(|l| l.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect();
+
+    let idx_path = sibling(ifo_path, &[".idx", ".idx.gz"]).ok_or("missing .idx file")?;
+    let dict_path = sibling(ifo_path, &[".dict", ".dict.dz"]).ok_or("missing .dict file")?;
+    let offset_bits_64 = meta.get("idxoffsetbits") == Some(&"64");
+
+    let idx = read_maybe_gzipped(&idx_path)?;
+    let data = read_maybe_gzipped(&dict_path)?;
+
+    let mut index: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+    let mut ranges = Vec::new();
+    let mut pos = 0;
+    let offset_len = if offset_bits_64 { 8 } else { 4 };
+    while pos < idx.len() {
+        let end = idx[pos..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("truncated .idx file")?
+            + pos;
+        let word = String::from_utf8_lossy(&idx[pos..end]).into_owned();
+        pos = end + 1;
+        let fields = idx
+            .get(pos..pos + offset_len + 4)
+            .ok_or("truncated .idx file")?;
+        let offset = if offset_bits_64 {
+            u64::from_be_bytes(fields[..8].try_into().unwrap())
+        } else {
+            u32::from_be_bytes(fields[..4].try_into().unwrap()) as u64
+        };
+        let size = u32::from_be_bytes(fields[offset_len..].try_into().unwrap());
+        pos += offset_len + 4;
+
+        index
+            .entry(normalize(&word))
+            .or_default()
+            .push((word, ranges.len()));
+        ranges.push((offset, size));
+    }
+
+    Ok(Dictionary {
+        info: DictionaryInfo {
+            name: meta
+                .get("bookname")
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| file_label(ifo_path)),
+            format: "stardict".to_string(),
+            lang: meta.get("lang").map(|s| s.to_string()),
+            word_count: ranges.len(),
+            path: ifo_path.to_string_lossy().into_owned(),
+        },
+        index,
+        entries: Entries::StarDict {
+            data,
+            ranges,
+            same_type_sequence: meta.get("sametypesequence").map(|s| s.to_string()),
+        },
+    })
+}
+
+/// Decodes one `.dict` article into (text, content type). Only the textual
+/// field types are rendered; resources such as images and sounds are skipped.
+fn decode_stardict_entry(bytes: &[u8], same_type_sequence: Option<&str>) -> (String, String) {
+    let mut parts: Vec<(char, String)> = Vec::new();
+    match same_type_sequence {
+        Some(types) if !types.is_empty() => {
+            let types: Vec<char> = types.chars().collect();
+            let mut pos = 0;
+            for (i, t) in types.iter().enumerate() {
+                let last = i == types.len() - 1;
+                let (field, used) = read_field(&bytes[pos.min(bytes.len())..], *t, last);
+                if let Some(field) = field {
+                    parts.push((*t, field));
+                }
+                pos += used;
+            }
+        }
+        _ => {
+            let mut pos = 0;
+            while pos < bytes.len() {
+                let t = bytes[pos] as char;
+                pos += 1;
+                let (field, used) = read_field(&bytes[pos..], t, false);
+                if let Some(field) = field {
+                    parts.push((t, field));
+                }
+                pos += used;
+            }
+        }
+    }
+
+    let is_html = parts.iter().any(|(t, _)| matches!(t, 'h' | 'g' | 'x'));
+    let text = parts
+        .into_iter()
+        .map(|(_, s)| s)
+        .collect::<Vec<_>>()
+        .join(if is_html { "<br>" } else { "\n" });
+    (text, if is_html { "html" } else { "text" }.to_string())
+}
+
+/// Reads one typed field. Lowercase types are NUL-terminated strings (or run to
+/// the end when they are the last field of a `sametypesequence`); uppercase
+/// types are binary blobs prefixed with a big-endian u32 size.
+fn read_field(bytes: &[u8], field_type: char, last: bool) -> (Option<String>, usize) {
+    if field_type.is_ascii_lowercase() {
+        let (end, used) = if last {
+            (bytes.len(), bytes.len())
+        } else {
+            match bytes.iter().position(|b| *b == 0) {
+                Some(i) => (i, i + 1),
+                None => (bytes.len(), bytes.len()),
+            }
+        };
+        let text = String::from_utf8_lossy(&bytes[..end]).into_owned();
+        let textual = matches!(
+            field_type,
+            'm' | 'l' | 'g' | 't' | 'x' | 'y' | 'k' | 'w' | 'h'
+        );
+        (textual.then_some(text), used)
+    } else {
+        let size = if last {
+            bytes.len()
+        } else {
+            bytes
+                .get(..4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize + 4)
+                .unwrap_or(bytes.len())
+        };
+        (None, size.min(bytes.len()))
+    }
+}
+
+fn file_label(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Dictionary")
+        .trim_end_matches(".dsl")
+        .to_string()
+}
+
+// ---------------------------------------------------------------------------
+// DSL
+// ---------------------------------------------------------------------------
+
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xff, 0xfe]) {
+        decode_utf16(rest, u16::from_le_bytes)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        decode_utf16(rest, u16::from_be_bytes)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(rest).into_owned()
+    } else if bytes.len() > 1 && bytes[1] == 0 {
+        // BOM-less UTF-16LE, as written by some older Lingvo tools
+        decode_utf16(bytes, u16::from_le_bytes)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| from_bytes([c[0], c[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn load_dsl(path: &Path) -> Result<Dictionary, String> {
+    let text = decode_text(&read_maybe_gzipped(path)?);
+
+    let mut name = None;
+    let mut index_lang = None;
+    let mut index: HashMap<String, Vec<(String, usize)>> = HashMap::new();
+    let mut articles: Vec<String> = Vec::new();
+    let mut headwords: Vec<String> = Vec::new();
+    let mut body: Vec<String> = Vec::new();
+
+    let mut flush = |headwords: &mut Vec<String>, body: &mut Vec<String>| {
+        if !headwords.is_empty() && !body.is_empty() {
+            let idx = articles.len();
+            articles.push(body.join("\n"));
+            for hw in headwords.iter() {
+                index
+                    .entry(normalize(hw))
+                    .or_default()
+                    .push((hw.clone(), idx));
+            }
+        }
+        headwords.clear();
+        body.clear();
+    };
+
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix('#') {
+            if let Some((key, value)) = header.split_once(char::is_whitespace) {
+                let value = value.trim().trim_matches('"').to_string();
+                match key {
+                    "NAME" => name = Some(value),
+                    "INDEX_LANGUAGE" => index_lang = Some(value),
+                    _ => {}
+                }
+            }
+        } else if line.starts_with([' ', '\t']) {
+            let stripped = strip_dsl_markup(line.trim());
+            if !stripped.is_empty() {
+                body.push(stripped);
+            }
+        } else if !line.trim().is_empty() {
+            // A headword after a body starts a new article; consecutive
+            // headwords share the article that follows them.
+            if !body.is_empty() {
+                flush(&mut headwords, &mut body);
+            }
+            headwords.push(strip_dsl_headword(line.trim()));
+        }
+    }
+    flush(&mut headwords, &mut body);
+
+    Ok(Dictionary {
+        info: DictionaryInfo {
+            name: name.unwrap_or_else(|| file_label(path)),
+            format: "dsl".to_string(),
+            lang: index_lang.map(|l| language_code(&l)),
+            word_count: articles.len(),
+            path: path.to_string_lossy().into_owned(),
+        },
+        index,
+        entries: Entries::Dsl { articles },
+    })
+}
+
+/// Drops `{optional}` parts and escapes from a DSL headword.
+fn strip_dsl_headword(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    if depth == 0 {
+                        out.push(next);
+                    }
+                }
+            }
+            '{' => depth += 1,
+            '}' => depth = (depth - 1).max(0),
+            _ if depth == 0 => out.push(c),
+            _ => {}
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Removes DSL formatting tags (`[b]`, `[m1]`, `[c red]`, ...) and `{{comments}}`.
+fn strip_dsl_markup(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            '{' if chars.peek() == Some(&'{') => {
+                let mut prev = '\0';
+                for c in chars.by_ref() {
+                    if prev == '}' && c == '}' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out.trim().to_string()
+}
+
+/// DSL headers name languages in English ("English", "Russian"); map the common
+/// ones to ISO 639-1 so they can be matched against the reader's `lang` codes.
+fn language_code(name: &str) -> String {
+    let code = match name.to_lowercase().as_str() {
+        "english" => "en",
+        "russian" => "ru",
+        "german" => "de",
+        "french" => "fr",
+        "spanish" => "es",
+        "italian" => "it",
+        "portuguese" => "pt",
+        "dutch" => "nl",
+        "polish" => "pl",
+        "ukrainian" => "uk",
+        "chinese" => "zh",
+        "japanese" => "ja",
+        "korean" => "ko",
+        "latin" => "la",
+        "greek" => "el",
+        "swedish" => "sv",
+        "turkish" => "tr",
+        "czech" => "cs",
+        _ => return name.to_string(),
+    };
+    code.to_string()
+}
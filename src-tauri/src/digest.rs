@@ -0,0 +1,181 @@
+//! A digest of the past day, week or month of reading: the highlights made,
+//! by book, and the time spent reading, as an HTML email body that can be
+//! mailed to yourself (see [`crate::email`]).
+
+use crate::export::{self, ExportItem};
+use crate::stats;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestPeriod {
+    Day,
+    #[default]
+    Week,
+    Month,
+}
+
+impl DigestPeriod {
+    /// SQLite date modifier going back one period from now.
+    fn modifier(self) -> &'static str {
+        match self {
+            DigestPeriod::Day => "-1 day",
+            DigestPeriod::Week => "-7 days",
+            DigestPeriod::Month => "-1 month",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DigestPeriod::Day => "day",
+            DigestPeriod::Week => "week",
+            DigestPeriod::Month => "month",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Digest {
+    pub period: DigestPeriod,
+    /// Start of the period, as a UTC timestamp.
+    pub since: String,
+    pub subject: String,
+    pub html: String,
+    /// The digest as Markdown-like text, for mail clients without HTML.
+    pub plain: String,
+    pub highlights: usize,
+    pub minutes_read: i64,
+    pub sessions: i64,
+    pub books_read: Vec<String>,
+    pub books_finished: Vec<String>,
+    /// Consecutive days read up to today or yesterday.
+    pub current_streak: i64,
+    /// Whether it was mailed.
+    pub sent: bool,
+}
+
+const DIGEST_STYLE: &str = ".stats{font-family:system-ui,sans-serif;list-style:none;padding:0}\
+.stats li{margin:.25rem 0}.empty{color:#6b7280;font-style:italic}";
+
+fn plural(count: i64, one: &str, many: &str) -> String {
+    match count {
+        1 => format!("1 {one}"),
+        n => format!("{n} {many}"),
+    }
+}
+
+/// Text highlights made since `since`, by book title, then in reading order.
+fn items(conn: &Connection, since: &str) -> rusqlite::Result<Vec<ExportItem>> {
+    conn.prepare(
+        "SELECT h.book_title, b.author, h.text, h.notes, h.color, h.created_at
+         FROM highlights h
+         LEFT JOIN books b ON b.title = h.book_title
+         WHERE h.created_at >= ?1 AND h.annotation_type = 'text'
+         ORDER BY h.book_title COLLATE fold, h.book_title, h.cfi COLLATE cfi_order",
+    )?
+    .query_map(params![since], |row| {
+        Ok(ExportItem {
+            book_title: row.get(0)?,
+            author: row.get(1)?,
+            text: row.get(2)?,
+            notes: row.get(3)?,
+            color: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+fn titles(conn: &Connection, sql: &str, since: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare(sql)?
+        .query_map(params![since], |row| row.get(0))?
+        .collect()
+}
+
+/// The digest of the `period` up to now.
+pub fn generate(conn: &Connection, period: DigestPeriod) -> rusqlite::Result<Digest> {
+    let since: String = conn.query_row(
+        "SELECT datetime('now', ?1)",
+        params![period.modifier()],
+        |row| row.get(0),
+    )?;
+    let (sessions, seconds): (i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(seconds), 0) FROM reading_sessions WHERE ended_at >= ?1",
+        params![since],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let books_read = titles(
+        conn,
+        "SELECT book_title FROM reading_sessions WHERE ended_at >= ?1
+         GROUP BY book_title ORDER BY MAX(ended_at) DESC",
+        &since,
+    )?;
+    let books_finished = titles(
+        conn,
+        "SELECT title FROM books WHERE finished_at >= ?1 ORDER BY finished_at",
+        &since,
+    )?;
+    let current_streak = stats::summary(conn)?.current_streak;
+    let items = items(conn, &since)?;
+    let minutes_read = seconds / 60;
+
+    let heading = format!("Your {} in reading", period.name());
+    let summary = format!(
+        "{}, {} read over {}",
+        plural(items.len() as i64, "new highlight", "new highlights"),
+        plural(minutes_read, "minute", "minutes"),
+        plural(sessions, "session", "sessions"),
+    );
+    let mut body = format!(
+        "<h1>{heading}</h1>\n<ul class=\"stats\">\n<li>{}</li>\n<li>{}</li>\n",
+        export::escape(&summary),
+        plural(books_read.len() as i64, "book read", "books read"),
+    );
+    if !books_finished.is_empty() {
+        body.push_str(&format!(
+            "<li>Finished: {}</li>\n",
+            export::escape(&books_finished.join(", "))
+        ));
+    }
+    if current_streak > 1 {
+        body.push_str(&format!("<li>{current_streak}-day reading streak</li>\n"));
+    }
+    body.push_str("</ul>\n");
+    if items.is_empty() {
+        body.push_str("<p class=\"empty\">No new highlights.</p>\n");
+    }
+    for book in export::by_book(&items) {
+        body.push_str(&format!(
+            "<h2>{}</h2>\n",
+            export::escape(&book[0].book_title)
+        ));
+        if let Some(author) = &book[0].author {
+            body.push_str(&format!(
+                "<p class=\"author\">{}</p>\n",
+                export::escape(author)
+            ));
+        }
+        for item in book {
+            body.push_str(&export::figure(item));
+        }
+    }
+
+    Ok(Digest {
+        period,
+        since,
+        subject: format!("{heading}: {summary}"),
+        html: export::document(&heading, &format!("{}{DIGEST_STYLE}", export::STYLE), &body),
+        plain: format!(
+            "{summary}.\n\n{}",
+            export::render(export::ExportFormat::Markdown, &heading, &items)
+        ),
+        highlights: items.len(),
+        minutes_read,
+        sessions,
+        books_read,
+        books_finished,
+        current_streak,
+        sent: false,
+    })
+}
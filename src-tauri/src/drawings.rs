@@ -0,0 +1,74 @@
+//! Drawings exported as SVG files, one per drawing, so margin scribbles can
+//! leave the app along with the highlights.
+
+use crate::db;
+use crate::models::Drawing;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// Side of the square SVG canvas. Stroke coordinates are fractions of the
+/// page, so they're scaled up to it.
+const CANVAS: f64 = 1000.0;
+
+fn attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// The drawing as a standalone SVG document. Pen pressure isn't kept; each
+/// stroke is drawn at its own width.
+pub fn svg(drawing: &Drawing) -> String {
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {CANVAS} {CANVAS}\" \
+         preserveAspectRatio=\"none\">\n"
+    );
+    for stroke in &drawing.strokes {
+        let path: Vec<String> = stroke
+            .points
+            .iter()
+            .enumerate()
+            .map(|(i, [x, y, _])| {
+                let command = if i == 0 { 'M' } else { 'L' };
+                format!("{command}{:.1} {:.1}", x * CANVAS, y * CANVAS)
+            })
+            .collect();
+        if path.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "  <path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.1}\" \
+             stroke-linecap=\"round\" stroke-linejoin=\"round\"/>\n",
+            path.join(" "),
+            attribute(&stroke.color),
+            stroke.width * CANVAS
+        ));
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Writes each drawing of the book into `dir` as `<title> - drawing <id>.svg`.
+/// Returns how many were written.
+pub fn export(conn: &Connection, book_id: i64, dir: &Path) -> Result<usize, String> {
+    let title: String = conn
+        .query_row(
+            "SELECT title FROM books WHERE id = ?1",
+            params![book_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let drawings = db::drawings::list(conn, book_id, None).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let name = crate::obsidian::note_filename(&title);
+    let name = name.trim_end_matches(".md");
+    for drawing in &drawings {
+        std::fs::write(
+            dir.join(format!("{name} - drawing {}.svg", drawing.id)),
+            svg(drawing),
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(drawings.len())
+}
@@ -0,0 +1,219 @@
+//! Mailing book files to a device address, like Amazon's Send to Kindle, and
+//! reading digests to yourself.
+//!
+//! The SMTP settings live in the keychain under the `email` integration:
+//! `host`, `port` (465 for implicit TLS, anything else uses STARTTLS; 587 by
+//! default), `username`, `password`, `from`, `to` (the device address),
+//! optionally `max_mb`, the largest attachment the server accepts, and
+//! `digest_to`, where digests go instead of `from`.
+
+use crate::db;
+use crate::import::{self, book_format};
+use crate::models::BookMetadata;
+use crate::secrets;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Attachment limit when `max_mb` isn't set: Gmail's and many other
+/// providers'. Send to Kindle itself takes up to 50 MB.
+const DEFAULT_MAX_MB: u64 = 25;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    Sending,
+    Sent,
+    Failed,
+}
+
+/// Payload of `email://status` events.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EmailStatus {
+    pub book_id: i64,
+    pub title: String,
+    pub status: Delivery,
+    pub error: Option<String>,
+}
+
+pub struct EmailConfig {
+    host: String,
+    port: u16,
+    username: String,
+    password: String,
+    from: Mailbox,
+    to: Mailbox,
+    digest_to: Option<Mailbox>,
+    max_bytes: u64,
+}
+
+/// The SMTP settings from the keychain.
+pub fn load_config() -> Result<EmailConfig, String> {
+    let get = |key: &str| -> Result<Option<String>, String> {
+        Ok(secrets::get("email", key)?
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty()))
+    };
+    let required = |key: &str| get(key)?.ok_or(format!("No email {key} configured"));
+    let mailbox = |key: &str| -> Result<Mailbox, String> {
+        let address = required(key)?;
+        address
+            .parse()
+            .map_err(|e| format!("Invalid {key} address {address:?}: {e}"))
+    };
+    let port = match get("port")? {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("Invalid SMTP port {port:?}"))?,
+        None => 587,
+    };
+    let max_mb = match get("max_mb")? {
+        Some(mb) => mb.parse().map_err(|_| format!("Invalid max_mb {mb:?}"))?,
+        None => DEFAULT_MAX_MB,
+    };
+    Ok(EmailConfig {
+        host: required("host")?,
+        port,
+        username: required("username")?,
+        password: secrets::get("email", "password")?.ok_or("No email password configured")?,
+        from: mailbox("from")?,
+        to: mailbox("to")?,
+        digest_to: match get("digest_to")? {
+            Some(_) => Some(mailbox("digest_to")?),
+            None => None,
+        },
+        max_bytes: max_mb * 1024 * 1024,
+    })
+}
+
+/// A book file ready to attach.
+pub struct BookAttachment {
+    pub book: BookMetadata,
+    pub filename: String,
+    pub mime: &'static str,
+    pub data: Vec<u8>,
+}
+
+fn mime_type(format: &str) -> &'static str {
+    match format {
+        "pdf" => "application/pdf",
+        "mobi" => "application/x-mobipocket-ebook",
+        _ => "application/epub+zip",
+    }
+}
+
+/// Reads the book's file. With `convert`, MOBI files are converted to EPUB,
+/// which Send to Kindle requires.
+pub fn attachment(
+    conn: &Connection,
+    books_dir: &Path,
+    book_id: i64,
+    convert: bool,
+) -> Result<BookAttachment, String> {
+    let book = db::books::get(conn, book_id).map_err(|e| e.to_string())?;
+    let mut data = crate::storage::read(&books_dir.join(&book.filename))
+        .map_err(|e| format!("Could not read {}: {e}", book.filename))?;
+    let mut format = if import::is_epub(&book.format) {
+        "epub"
+    } else {
+        book_format(&book.filename)
+    };
+    if convert && format == "mobi" {
+        data = import::convert_on_import(&book.filename, &data, &mut |_, _| {})?
+            .ok_or("This MOBI file's compression can't be converted to EPUB")?
+            .epub;
+        format = "epub";
+    }
+    let stem: String = book
+        .title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Ok(BookAttachment {
+        filename: format!("{}.{format}", stem.trim()),
+        mime: mime_type(format),
+        data,
+        book,
+    })
+}
+
+/// Mails the attachment to the configured device address.
+pub async fn send(config: &EmailConfig, attachment: BookAttachment) -> Result<(), String> {
+    // Base64 makes the attachment a third larger on the wire.
+    let encoded = attachment.data.len() as u64 * 4 / 3;
+    if encoded > config.max_bytes {
+        return Err(format!(
+            "{} is {:.1} MB once attached, over the {} MB limit",
+            attachment.filename,
+            encoded as f64 / (1024.0 * 1024.0),
+            config.max_bytes / (1024 * 1024)
+        ));
+    }
+    let content_type = ContentType::parse(attachment.mime).map_err(|e| e.to_string())?;
+    let message = Message::builder()
+        .from(config.from.clone())
+        .to(config.to.clone())
+        .subject(&attachment.book.title)
+        .multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(format!(
+                    "{} is attached.",
+                    attachment.book.title
+                )))
+                .singlepart(
+                    Attachment::new(attachment.filename).body(attachment.data, content_type),
+                ),
+        )
+        .map_err(|e| e.to_string())?;
+    deliver(config, message).await
+}
+
+/// Mails an HTML message, with a plain-text alternative, to the `digest_to`
+/// address, or else back to `from`.
+pub async fn send_html(
+    config: &EmailConfig,
+    subject: &str,
+    plain: String,
+    html: String,
+) -> Result<(), String> {
+    let message = Message::builder()
+        .from(config.from.clone())
+        .to(config
+            .digest_to
+            .clone()
+            .unwrap_or_else(|| config.from.clone()))
+        .subject(subject)
+        .multipart(MultiPart::alternative_plain_html(plain, html))
+        .map_err(|e| e.to_string())?;
+    deliver(config, message).await
+}
+
+async fn deliver(config: &EmailConfig, message: Message) -> Result<(), String> {
+    let builder = if config.port == 465 {
+        AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+    }
+    .map_err(|e| e.to_string())?;
+    let transport = builder
+        .port(config.port)
+        .credentials(Credentials::new(
+            config.username.clone(),
+            config.password.clone(),
+        ))
+        .timeout(Some(Duration::from_secs(120)))
+        .build();
+    transport.send(message).await.map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! Local embeddings for highlights, used to find quotes by meaning rather than
+//! by exact wording.
+//!
+//! Vectors come from a small multilingual sentence-embedding model run
+//! on-device with ONNX Runtime, which is loaded from the system (or the path in
+//! `ORT_DYLIB_PATH`). The model is downloaded into `<app data>/models` the
+//! first time there is something to embed; after that nothing leaves the
+//! machine. Each stored vector records the [`MODEL`] that produced it; changing
+//! the model means bumping that name, after which every highlight is
+//! re-embedded in the background.
+//!
+//! The index is a flat table scanned on every query, which is fast enough for
+//! the tens of thousands of highlights a personal library accumulates.
+
+use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+/// Identifies the embedding model; stored alongside each vector.
+pub const MODEL: &str = "paraphrase-multilingual-minilm-l12-v2-q";
+
+const EMBEDDING_MODEL: EmbeddingModel = EmbeddingModel::ParaphraseMLMiniLML12V2Q;
+
+/// Highlights embedded per background pass, so a large backlog doesn't hold a
+/// connection for long.
+pub const BATCH_SIZE: usize = 256;
+
+/// The loaded embedding model.
+pub struct Embedder(TextEmbedding);
+
+impl Embedder {
+    /// Loads the model from `cache_dir`, downloading it there first if needed.
+    pub fn load(cache_dir: &Path) -> Result<Embedder, String> {
+        let options = InitOptions::new(EMBEDDING_MODEL)
+            .with_cache_dir(cache_dir.to_path_buf())
+            .with_show_download_progress(false);
+        TextEmbedding::try_new(options)
+            .map(Embedder)
+            .map_err(|e| format!("Could not load the embedding model: {e}"))
+    }
+
+    /// Embeds each of `texts` into a unit-length vector.
+    fn embed(&mut self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut vectors = self.0.embed(texts, None).map_err(|e| e.to_string())?;
+        for vector in &mut vectors {
+            let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                vector.iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+        Ok(vectors)
+    }
+}
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+/// Up to [`BATCH_SIZE`] highlights that have no vector from the current
+/// [`MODEL`], as `(highlight id, text to embed)`. Empty means the index is up
+/// to date.
+pub fn pending(conn: &Connection) -> rusqlite::Result<Vec<(i64, String)>> {
+    conn.prepare(
+        "SELECT h.id, h.text, h.notes FROM highlights h
+         LEFT JOIN highlight_embeddings e ON e.highlight_id = h.id AND e.model = ?1
+         WHERE e.highlight_id IS NULL
+         LIMIT ?2",
+    )?
+    .query_map(params![MODEL, BATCH_SIZE as i64], |row| {
+        let text: String = row.get(1)?;
+        let notes: String = row.get(2)?;
+        Ok((row.get(0)?, format!("{text}\n{notes}")))
+    })?
+    .collect()
+}
+
+/// Embeds and stores the `pending` highlights.
+pub fn embed_pending(
+    conn: &Connection,
+    embedder: &mut Embedder,
+    pending: &[(i64, String)],
+) -> Result<(), String> {
+    let texts: Vec<String> = pending.iter().map(|(_, text)| text.clone()).collect();
+    let vectors = embedder.embed(&texts)?;
+    let mut stmt = conn
+        .prepare(
+            "INSERT OR REPLACE INTO highlight_embeddings (highlight_id, model, vector)
+             VALUES (?1, ?2, ?3)",
+        )
+        .map_err(|e| e.to_string())?;
+    for ((id, _), vector) in pending.iter().zip(&vectors) {
+        stmt.execute(params![id, MODEL, to_blob(vector)])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The `k` highlights most similar to `query`, as `(highlight id, cosine
+/// similarity)`, best first. Only highlights embedded with the current
+/// [`MODEL`] are considered.
+pub fn search(
+    conn: &Connection,
+    embedder: &mut Embedder,
+    query: &str,
+    k: usize,
+) -> Result<Vec<(i64, f32)>, String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query = embedder
+        .embed(&[query.to_string()])?
+        .pop()
+        .unwrap_or_default();
+
+    let mut stmt = conn
+        .prepare("SELECT highlight_id, vector FROM highlight_embeddings WHERE model = ?1")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![MODEL], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut scored = Vec::new();
+    for row in rows {
+        let (id, blob) = row.map_err(|e| e.to_string())?;
+        // Both sides are unit length, so the dot product is the cosine.
+        let score: f32 = from_blob(&blob)
+            .iter()
+            .zip(&query)
+            .map(|(a, b)| a * b)
+            .sum();
+        if score > 0.0 {
+            scored.push((id, score));
+        }
+    }
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(k);
+    Ok(scored)
+}
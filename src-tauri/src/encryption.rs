@@ -0,0 +1,198 @@
+//! Opt-in encryption of a profile's library at rest.
+//!
+//! An encrypted profile has an `encryption.json` next to its database holding
+//! the salt and Argon2id parameters its key is derived from; the passphrase
+//! itself is never stored. The key opens the database with SQLCipher and
+//! seals book files with XChaCha20-Poly1305. Until the passphrase is given
+//! with `unlock_library`, the profile stays locked and every command that
+//! needs the database fails with [`LOCKED`].
+//!
+//! Snapshots taken with `VACUUM INTO` keep the database's key, so backups of
+//! an encrypted library are encrypted as well.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Error returned while the active profile is encrypted and not unlocked.
+pub const LOCKED: &str = "Library is locked";
+
+const CONFIG_FILE: &str = "encryption.json";
+
+/// Start of every sealed book file, followed by the nonce.
+const MAGIC: &[u8; 8] = b"TMLGENC1";
+const NONCE_LEN: usize = 24;
+
+/// Sealed with the key when encryption is turned on, so a wrong passphrase
+/// can be told apart from a damaged database.
+const CHECK: &[u8] = b"tumelog library key";
+
+/// Shortest passphrase accepted when turning encryption on.
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// A profile's 256-bit key. Wiped from memory when dropped.
+pub struct LibraryKey([u8; 32]);
+
+impl Drop for LibraryKey {
+    fn drop(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+impl LibraryKey {
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+
+    /// The key as SQLCipher's raw key literal, which skips its own key
+    /// derivation.
+    fn sqlcipher(&self) -> zeroize::Zeroizing<String> {
+        let hex: String = self.0.iter().map(|b| format!("{b:02x}")).collect();
+        zeroize::Zeroizing::new(format!("x'{hex}'"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub locked: bool,
+}
+
+/// Key derivation settings stored in `encryption.json`.
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    salt: Vec<u8>,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    /// [`CHECK`] sealed with the key.
+    check: Vec<u8>,
+}
+
+fn config_path(dir: &Path) -> PathBuf {
+    dir.join(CONFIG_FILE)
+}
+
+/// Whether the profile in `dir` is encrypted.
+pub fn is_enabled(dir: &Path) -> bool {
+    config_path(dir).is_file()
+}
+
+fn derive(passphrase: &str, config: &Config) -> Result<LibraryKey, String> {
+    let params = Params::new(config.m_cost, config.t_cost, config.p_cost, Some(32))
+        .map_err(|e| e.to_string())?;
+    let mut key = [0; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &config.salt, &mut key)
+        .map_err(|e| e.to_string())?;
+    Ok(LibraryKey(key))
+}
+
+/// A new random salt and the key `passphrase` derives from it. Nothing is
+/// written until [`save`], so an aborted setup leaves the profile as it was.
+pub fn create(passphrase: &str) -> Result<(Config, LibraryKey), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!(
+            "The passphrase must be at least {MIN_PASSPHRASE_LEN} characters"
+        ));
+    }
+    let mut salt = vec![0; 16];
+    OsRng.fill_bytes(&mut salt);
+    let defaults = Params::default();
+    let mut config = Config {
+        salt,
+        m_cost: defaults.m_cost(),
+        t_cost: defaults.t_cost(),
+        p_cost: defaults.p_cost(),
+        check: Vec::new(),
+    };
+    let key = derive(passphrase, &config)?;
+    config.check = seal(&key, CHECK)?;
+    Ok((config, key))
+}
+
+pub fn save(dir: &Path, config: &Config) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    std::fs::write(config_path(dir), json).map_err(|e| e.to_string())
+}
+
+pub fn remove(dir: &Path) -> Result<(), String> {
+    std::fs::remove_file(config_path(dir)).map_err(|e| e.to_string())
+}
+
+/// The key derivation settings of the encrypted profile in `dir`.
+pub fn load(dir: &Path) -> Result<Config, String> {
+    let json = std::fs::read_to_string(config_path(dir)).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Derives the key of the encrypted profile in `dir` from `passphrase`.
+pub fn unlock(dir: &Path, passphrase: &str) -> Result<LibraryKey, String> {
+    let config = load(dir)?;
+    let key = derive(passphrase, &config)?;
+    match unseal(&key, &config.check) {
+        Ok(check) if check == CHECK => Ok(key),
+        _ => Err("Wrong passphrase".to_string()),
+    }
+}
+
+/// Gives SQLCipher the key of a freshly opened connection. Must come before
+/// anything else touches the database.
+pub fn apply(conn: &Connection, key: &LibraryKey) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "key", key.sqlcipher().as_str())
+}
+
+/// Writes a copy of `conn`'s database to `dest`, encrypted with `key`, or
+/// decrypted if there is none.
+pub fn export(conn: &Connection, dest: &Path, key: Option<&LibraryKey>) -> Result<(), String> {
+    let literal = key.map(LibraryKey::sqlcipher);
+    conn.execute(
+        "ATTACH DATABASE ?1 AS export KEY ?2",
+        params![
+            dest.to_string_lossy(),
+            literal.as_ref().map_or("", |k| k.as_str())
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let result = conn
+        .query_row("SELECT sqlcipher_export('export')", [], |_| Ok(()))
+        .map_err(|e| e.to_string());
+    conn.execute_batch("DETACH DATABASE export")
+        .map_err(|e| e.to_string())?;
+    result
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// `data` encrypted with `key`, behind [`MAGIC`] and a random nonce.
+pub fn seal(key: &LibraryKey, data: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce, data)
+        .map_err(|_| "Encryption failed".to_string())?;
+    let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+/// Reverses [`seal`]. Fails if `data` was sealed with another key or has been
+/// tampered with.
+pub fn unseal(key: &LibraryKey, data: &[u8]) -> Result<Vec<u8>, String> {
+    let body = data
+        .strip_prefix(MAGIC.as_slice())
+        .filter(|body| body.len() >= NONCE_LEN)
+        .ok_or("Not an encrypted file")?;
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+    key.cipher()
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Could not decrypt: wrong key or damaged file".to_string())
+}
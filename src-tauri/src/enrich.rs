@@ -0,0 +1,203 @@
+//! Missing book details filled in from Open Library: author, year of first
+//! publication, description, subjects and page count, looked up by ISBN or
+//! else by title and author.
+//!
+//! Looking a book up only proposes details; they are written with [`apply`]
+//! once the user has reviewed them, and then only into fields that are still
+//! empty. Subjects become the book's tags.
+
+use crate::authors;
+use crate::covers::{fetch_json, open_library_url, BookQuery};
+use crate::db::{self, fold::fold};
+use crate::models::BookMetadata;
+use reqwest::Url;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+const OPEN_LIBRARY: &str = "https://openlibrary.org";
+
+const FIELDS: &str = "key,title,author_name,first_publish_year,number_of_pages_median,subject";
+
+const MAX_SUBJECTS: usize = 10;
+
+/// Share of the words in the book's title a search result must have for a
+/// title search to count it as the same book.
+const MIN_TITLE_OVERLAP: f64 = 0.5;
+
+/// Subjects Open Library uses for its own bookkeeping rather than genres.
+const IGNORED_SUBJECTS: &[&str] = &[
+    "accessible book",
+    "protected daisy",
+    "in library",
+    "lending library",
+    "large type books",
+    "open library staff picks",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MetadataProposal {
+    pub book_id: i64,
+    /// Open Library page of the work the details come from.
+    pub source_url: Option<String>,
+    /// The work's title, to check it's the right book.
+    pub matched_title: Option<String>,
+    pub author: Option<String>,
+    pub year: Option<i64>,
+    pub description: Option<String>,
+    pub page_count: Option<i64>,
+    /// Subjects the book isn't tagged with yet.
+    pub subjects: Vec<String>,
+}
+
+fn words(text: &str) -> HashSet<String> {
+    fold(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Share of the words of `title` that `candidate` also has.
+fn title_overlap(title: &str, candidate: &str) -> f64 {
+    let title = words(title);
+    if title.is_empty() {
+        return 0.0;
+    }
+    let candidate = words(candidate);
+    title.intersection(&candidate).count() as f64 / title.len() as f64
+}
+
+/// The search result for `query` that best matches the book. An ISBN search
+/// trusts its first result; a title search wants the titles to agree.
+async fn best_match(
+    client: &reqwest::Client,
+    query: &BookQuery<'_>,
+) -> Result<Option<Value>, String> {
+    let results = fetch_json(client, open_library_url(query, FIELDS)).await?;
+    let docs = results["docs"].as_array().cloned().unwrap_or_default();
+    if query.isbn.is_some() {
+        return Ok(docs.into_iter().next());
+    }
+    Ok(docs
+        .into_iter()
+        .map(|doc| {
+            let overlap = title_overlap(query.title, doc["title"].as_str().unwrap_or_default());
+            (doc, overlap)
+        })
+        .filter(|(_, overlap)| *overlap >= MIN_TITLE_OVERLAP)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(doc, _)| doc))
+}
+
+/// The work's description, which Open Library gives as a string or as
+/// `{ "type": "/type/text", "value": ... }`.
+async fn description(client: &reqwest::Client, work_key: &str) -> Result<Option<String>, String> {
+    let url = Url::parse(&format!("{OPEN_LIBRARY}{work_key}.json")).map_err(|e| e.to_string())?;
+    let work = fetch_json(client, url).await?;
+    let text = work["description"]
+        .as_str()
+        .or_else(|| work["description"]["value"].as_str());
+    Ok(text
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string))
+}
+
+/// Details Open Library has for `book` that the book is missing, or `None`
+/// if it can't find the book. `tags` are the book's current tags.
+pub async fn lookup(
+    client: &reqwest::Client,
+    book: &BookMetadata,
+    tags: &[String],
+) -> Result<Option<MetadataProposal>, String> {
+    let mut query = BookQuery {
+        isbn: book.isbn.as_deref(),
+        title: &book.title,
+        author: book.author.as_deref(),
+    };
+    let mut doc = best_match(client, &query).await?;
+    if doc.is_none() && query.isbn.is_some() {
+        query.isbn = None;
+        doc = best_match(client, &query).await?;
+    }
+    let Some(doc) = doc else {
+        return Ok(None);
+    };
+
+    let key = doc["key"].as_str();
+    let description = match key {
+        Some(key) if book.description.is_none() => description(client, key).await?,
+        _ => None,
+    };
+    let tagged: HashSet<String> = tags.iter().map(|tag| fold(tag)).collect();
+    let mut seen = HashSet::new();
+    let subjects = doc["subject"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|subject| subject.as_str())
+        .map(str::trim)
+        .filter(|subject| {
+            let folded = fold(subject);
+            !subject.is_empty()
+                && !subject.contains(':')
+                && !IGNORED_SUBJECTS.contains(&folded.as_str())
+                && !tagged.contains(&folded)
+                && seen.insert(folded)
+        })
+        .take(MAX_SUBJECTS)
+        .map(str::to_string)
+        .collect();
+
+    Ok(Some(MetadataProposal {
+        book_id: book.id,
+        source_url: key.map(|key| format!("{OPEN_LIBRARY}{key}")),
+        matched_title: doc["title"].as_str().map(str::to_string),
+        author: doc["author_name"][0]
+            .as_str()
+            .filter(|_| book.author.is_none())
+            .map(str::to_string),
+        year: doc["first_publish_year"]
+            .as_i64()
+            .filter(|_| book.year.is_none()),
+        description,
+        page_count: doc["number_of_pages_median"]
+            .as_i64()
+            .filter(|_| book.page_count.is_none()),
+        subjects,
+    }))
+}
+
+/// Writes the reviewed `proposal` into the book's empty fields and adds its
+/// subjects as tags.
+pub fn apply(conn: &Connection, proposal: &MetadataProposal) -> Result<BookMetadata, String> {
+    let book = db::books::get(conn, proposal.book_id).map_err(|e| e.to_string())?;
+    let trimmed = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    let author = trimmed(&proposal.author);
+    conn.execute(
+        "UPDATE books SET author = COALESCE(author, ?2), year = COALESCE(year, ?3),
+             description = COALESCE(description, ?4), page_count = COALESCE(page_count, ?5)
+         WHERE id = ?1",
+        params![
+            book.id,
+            author,
+            proposal.year,
+            trimmed(&proposal.description),
+            proposal.page_count
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    if let (None, Some(author)) = (&book.author, author) {
+        authors::set_authors(conn, book.id, &[author]).map_err(|e| e.to_string())?;
+    }
+    db::book_tags::add(conn, book.id, &proposal.subjects).map_err(|e| e.to_string())?;
+    db::books::get(conn, book.id).map_err(|e| e.to_string())
+}
@@ -0,0 +1,886 @@
+//! Reads stored EPUB files: the package document's spine, chapter titles from
+//! the table of contents, and chapter text split into passages, each anchored
+//! by an EPUB CFI that epub.js can `display()`.
+//!
+//! Chapters are parsed as XML. Sloppy markup (mismatched end tags, HTML-only
+//! entities) is tolerated; a chapter that stops parsing part-way keeps the
+//! passages read up to that point.
+
+use crate::db::fold::fold;
+use quick_xml::escape::resolve_xml_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Elements whose text forms one passage. Text outside any of them (e.g.
+/// directly in `<body>`) belongs to the nearest enclosing block that is.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "dt",
+    "dd",
+    "blockquote",
+    "pre",
+    "td",
+    "th",
+    "caption",
+    "figcaption",
+    "div",
+    "section",
+    "article",
+    "aside",
+    "body",
+];
+
+/// Elements whose content is never shown as text.
+const SKIPPED_ELEMENTS: &[&str] = &["head", "script", "style", "svg", "math"];
+
+#[derive(Debug, Clone)]
+pub struct SpineItem {
+    pub idref: String,
+    /// Path of the chapter inside the archive.
+    pub path: String,
+    /// Label of the table-of-contents entry for this item, or for the nearest
+    /// preceding item that has one.
+    pub title: Option<String>,
+    /// Whether the table of contents links to this item, i.e. a chapter starts
+    /// here rather than continuing from the previous item.
+    pub in_toc: bool,
+}
+
+/// A run of spine items belonging to one table-of-contents entry.
+#[derive(Debug, Clone)]
+pub struct ChapterRange {
+    pub title: Option<String>,
+    /// CFI of the first spine item's start.
+    pub start_cfi: String,
+    /// CFI of the next chapter's start; `None` for the last chapter.
+    pub end_cfi: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub cfi: String,
+    pub text: String,
+}
+
+/// What the package document says about the book itself.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    /// Creators in package order, leaving out those with a role other than
+    /// author (editors, illustrators, ...).
+    pub authors: Vec<String>,
+    /// From Calibre's `calibre:series` or an EPUB 3 `belongs-to-collection`.
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub publisher: Option<String>,
+    /// Year of the first `<dc:date>`.
+    pub year: Option<i64>,
+    /// From an identifier marked as an ISBN (`urn:isbn:` or
+    /// `opf:scheme="ISBN"`), digits only.
+    pub isbn: Option<String>,
+    /// The first `<dc:language>`, as written.
+    pub language: Option<String>,
+    /// `<dc:subject>` entries, with entries listing several subjects split
+    /// at semicolons and repeats left out.
+    pub subjects: Vec<String>,
+}
+
+pub struct Epub<R> {
+    archive: ZipArchive<R>,
+    pub spine: Vec<SpineItem>,
+    pub metadata: Metadata,
+    /// Archive path and media type of the cover image.
+    cover: Option<(String, String)>,
+}
+
+impl Epub<crate::storage::BookFile> {
+    /// Opens a stored book file, compressed or not.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        Self::from_reader(crate::storage::open(path).map_err(|e| e.to_string())?)
+    }
+}
+
+impl<R: Read + Seek> Epub<R> {
+    pub fn from_reader(reader: R) -> Result<Self, String> {
+        let mut archive = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+
+        let container = read_string(&mut archive, "META-INF/container.xml")?;
+        let opf_path = find_rootfile(&container).ok_or("EPUB has no package document")?;
+        let opf = read_string(&mut archive, &opf_path)?;
+        let package = parse_package(&opf, parent_dir(&opf_path))?;
+
+        let toc = match &package.toc {
+            Some((path, TocKind::Ncx)) => read_string(&mut archive, path)
+                .map(|ncx| parse_ncx(&ncx, parent_dir(path)))
+                .unwrap_or_default(),
+            Some((path, TocKind::Nav)) => read_string(&mut archive, path)
+                .map(|nav| parse_nav(&nav, parent_dir(path)))
+                .unwrap_or_default(),
+            None => HashMap::new(),
+        };
+
+        let mut title = None;
+        let spine = package
+            .spine
+            .into_iter()
+            .map(|(idref, path)| {
+                let label = toc.get(&path);
+                if let Some(label) = label {
+                    title = Some(label.clone());
+                }
+                SpineItem {
+                    idref,
+                    path,
+                    title: title.clone(),
+                    in_toc: label.is_some(),
+                }
+            })
+            .collect();
+
+        Ok(Epub {
+            archive,
+            spine,
+            metadata: package.metadata,
+            cover: package.cover,
+        })
+    }
+
+    /// Chapters in reading order. Spine items the table of contents doesn't
+    /// link to (split files, front matter) belong to the chapter before them;
+    /// anything before the first linked item forms an untitled chapter.
+    pub fn chapters(&self) -> Vec<ChapterRange> {
+        let start_cfi =
+            |index: usize| format!("epubcfi({}!)", spine_cfi(index, &self.spine[index].idref));
+        self.chapter_spans()
+            .into_iter()
+            .map(|span| ChapterRange {
+                title: self.spine[span.start].title.clone(),
+                start_cfi: start_cfi(span.start),
+                end_cfi: (span.end < self.spine.len()).then(|| start_cfi(span.end)),
+            })
+            .collect()
+    }
+
+    /// The spine items of each of [`Self::chapters`].
+    pub fn chapter_spans(&self) -> Vec<Range<usize>> {
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for (index, item) in self.spine.iter().enumerate() {
+            match spans.last_mut() {
+                Some(span) if !item.in_toc => span.end = index + 1,
+                _ => spans.push(index..index + 1),
+            }
+        }
+        spans
+    }
+
+    /// The spine item's markup, decoded as UTF-8.
+    /// The cover image as `(media type, bytes)`, if the package names one.
+    pub fn cover(&mut self) -> Option<(String, Vec<u8>)> {
+        let (path, media_type) = self.cover.clone()?;
+        let mut file = self.archive.by_name(&path).ok()?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).ok()?;
+        Some((media_type, bytes))
+    }
+
+    pub fn chapter_html(&mut self, index: usize) -> Result<String, String> {
+        let item = self
+            .spine
+            .get(index)
+            .ok_or_else(|| format!("No chapter {index}"))?;
+        let path = item.path.clone();
+        read_string(&mut self.archive, &path)
+    }
+
+    /// The text of spine item `index`, one passage per block element.
+    pub fn passages(&mut self, index: usize) -> Result<Vec<Passage>, String> {
+        let html = self.chapter_html(index)?;
+        let base = spine_cfi(index, &self.spine[index].idref);
+        Ok(extract_passages(&html, &base))
+    }
+
+    /// The DOM text of spine item `index`, for locating quotes.
+    pub fn chapter_text(&mut self, index: usize) -> Result<ChapterText, String> {
+        let html = self.chapter_html(index)?;
+        let base = spine_cfi(index, &self.spine[index].idref);
+        Ok(parse_chapter(&html, &base).1)
+    }
+}
+
+/// The spine-level part of a CFI, e.g. `/6/4[chap01]`. The package document's
+/// `<spine>` is its third child element, hence `/6`.
+pub fn spine_cfi(index: usize, idref: &str) -> String {
+    format!("/6/{}[{}]", (index + 1) * 2, idref)
+}
+
+fn read_string<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<String, String> {
+    let mut file = archive.by_name(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn parent_dir(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..=i])
+}
+
+/// Resolves an href relative to `base` (a directory ending in `/`, or empty)
+/// into an archive path, dropping any fragment.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<String> = base
+        .split('/')
+        .filter(|p| !p.is_empty())
+        .map(str::to_string)
+        .collect();
+    for part in percent_decode(href).split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part.to_string()),
+        }
+    }
+    parts.join("/")
+}
+
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Looks up an attribute by local name, so `epub:type` matches `type`.
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+    e.attributes().flatten().find_map(|a| {
+        (a.key.local_name().as_ref() == key.as_bytes())
+            .then(|| a.unescape_value().ok().map(|v| v.into_owned()))
+            .flatten()
+    })
+}
+
+fn local_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase()
+}
+
+fn find_rootfile(container: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) if local_name(&e) == "rootfile" => {
+                return attribute(&e, "full-path");
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+enum TocKind {
+    Ncx,
+    Nav,
+}
+
+struct Package {
+    /// `(idref, archive path)` in reading order.
+    spine: Vec<(String, String)>,
+    toc: Option<(String, TocKind)>,
+    metadata: Metadata,
+    cover: Option<(String, String)>,
+}
+
+/// A `<dc:creator>` or `<meta>` element whose text is being read.
+struct MetadataText {
+    element: String,
+    id: Option<String>,
+    /// `opf:role` on creators, `opf:scheme` on identifiers, `property` on
+    /// metas.
+    property: Option<String>,
+    refines: Option<String>,
+    text: String,
+}
+
+fn parse_package(opf: &str, base: &str) -> Result<Package, String> {
+    let mut manifest: HashMap<String, String> = HashMap::new();
+    let mut media_types: HashMap<String, String> = HashMap::new();
+    let mut cover_id = None;
+    let mut nav = None;
+    let mut ncx_id = None;
+    let mut ncx_by_type = None;
+    let mut idrefs = Vec::new();
+    let mut current: Option<MetadataText> = None;
+    let mut texts: Vec<MetadataText> = Vec::new();
+    let mut metadata = Metadata::default();
+
+    let mut reader = Reader::from_str(opf);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e))
+                if matches!(
+                    local_name(&e).as_str(),
+                    "title"
+                        | "creator"
+                        | "meta"
+                        | "publisher"
+                        | "date"
+                        | "identifier"
+                        | "subject"
+                        | "language"
+                ) =>
+            {
+                let element = local_name(&e);
+                read_named_meta(&e, &mut metadata, &mut cover_id);
+                let property = match element.as_str() {
+                    "creator" => attribute(&e, "role"),
+                    "identifier" => attribute(&e, "scheme"),
+                    _ => attribute(&e, "property"),
+                };
+                current = Some(MetadataText {
+                    element,
+                    id: attribute(&e, "id"),
+                    property,
+                    refines: attribute(&e, "refines"),
+                    text: String::new(),
+                });
+            }
+            Ok(Event::Text(t)) if current.is_some() => {
+                if let (Some(current), Ok(text)) = (&mut current, t.xml_content()) {
+                    current.text.push_str(&text);
+                }
+            }
+            Ok(Event::GeneralRef(r)) if current.is_some() => {
+                let c = match r.resolve_char_ref() {
+                    Ok(Some(c)) => Some(c),
+                    _ => r
+                        .decode()
+                        .ok()
+                        .and_then(|name| resolve_xml_entity(&name)?.chars().next()),
+                };
+                if let (Some(current), Some(c)) = (&mut current, c) {
+                    current.text.push(c);
+                }
+            }
+            Ok(Event::End(_)) if current.is_some() => texts.extend(current.take()),
+            Ok(Event::Start(e) | Event::Empty(e)) => match local_name(&e).as_str() {
+                "meta" => read_named_meta(&e, &mut metadata, &mut cover_id),
+                "item" => {
+                    let (Some(id), Some(href)) = (attribute(&e, "id"), attribute(&e, "href"))
+                    else {
+                        continue;
+                    };
+                    let path = resolve(base, &href);
+                    let properties = attribute(&e, "properties").unwrap_or_default();
+                    if properties.split_whitespace().any(|p| p == "nav") {
+                        nav = Some(path.clone());
+                    }
+                    if properties.split_whitespace().any(|p| p == "cover-image") {
+                        cover_id = Some(id.clone());
+                    }
+                    if let Some(media_type) = attribute(&e, "media-type") {
+                        media_types.insert(id.clone(), media_type);
+                    }
+                    if attribute(&e, "media-type").as_deref() == Some("application/x-dtbncx+xml") {
+                        ncx_by_type = Some(path.clone());
+                    }
+                    manifest.insert(id, path);
+                }
+                "spine" => ncx_id = attribute(&e, "toc"),
+                "itemref" => {
+                    if let Some(idref) = attribute(&e, "idref") {
+                        idrefs.push(idref);
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(format!("Invalid package document: {e}")),
+            _ => {}
+        }
+    }
+
+    // Items missing from the manifest still occupy a spine position, so they
+    // are kept (with an empty path) to keep later CFIs correct.
+    let spine = idrefs
+        .into_iter()
+        .map(|idref| {
+            let path = manifest.get(&idref).cloned().unwrap_or_default();
+            (idref, path)
+        })
+        .collect();
+    let ncx = ncx_id
+        .and_then(|id| manifest.get(&id).cloned())
+        .or(ncx_by_type);
+    let toc = match (ncx, nav) {
+        (Some(ncx), _) => Some((ncx, TocKind::Ncx)),
+        (None, Some(nav)) => Some((nav, TocKind::Nav)),
+        (None, None) => None,
+    };
+    let cover = cover_id.and_then(|id| {
+        let media_type = media_types.get(&id).filter(|t| t.starts_with("image/"))?;
+        Some((manifest.get(&id)?.clone(), media_type.clone()))
+    });
+    resolve_metadata(&mut metadata, &texts);
+    Ok(Package {
+        spine,
+        toc,
+        metadata,
+        cover,
+    })
+}
+
+/// EPUB 2 `<meta name=".." content=".."/>`: Calibre's series and the manifest
+/// id of the cover image.
+fn read_named_meta(e: &BytesStart, metadata: &mut Metadata, cover_id: &mut Option<String>) {
+    match (attribute(e, "name"), attribute(e, "content")) {
+        (Some(name), Some(content)) if name == "cover" => {
+            cover_id.get_or_insert(content);
+        }
+        (Some(name), Some(content)) if name == "calibre:series" => {
+            metadata.series = Some(content.trim().to_string()).filter(|s| !s.is_empty());
+        }
+        (Some(name), Some(content)) if name == "calibre:series_index" => {
+            metadata.series_index = content.trim().parse().ok();
+        }
+        _ => {}
+    }
+}
+
+/// Fills in the title, authors, language, subjects and, if Calibre didn't
+/// provide one, the series from the text elements of `<metadata>`, applying
+/// EPUB 3 `refines` metas.
+fn resolve_metadata(metadata: &mut Metadata, texts: &[MetadataText]) {
+    metadata.title = texts
+        .iter()
+        .filter(|t| t.element == "title")
+        .map(|t| collapse_whitespace(&t.text))
+        .find(|t| !t.is_empty());
+    let first = |element: &str| {
+        texts
+            .iter()
+            .filter(|t| t.element == element)
+            .map(|t| collapse_whitespace(&t.text))
+            .find(|t| !t.is_empty())
+    };
+    metadata.publisher = first("publisher");
+    metadata.language = first("language");
+    metadata.year = first("date").and_then(|date| date.get(..4).and_then(|year| year.parse().ok()));
+    metadata.isbn = texts
+        .iter()
+        .filter(|t| t.element == "identifier")
+        .find_map(|t| {
+            let text = t.text.trim();
+            let lower = text.to_lowercase();
+            let value = if let Some(rest) = lower.strip_prefix("urn:isbn:") {
+                rest
+            } else if t
+                .property
+                .as_deref()
+                .is_some_and(|s| s.eq_ignore_ascii_case("isbn"))
+            {
+                lower.as_str()
+            } else {
+                return None;
+            };
+            let digits: String = value
+                .chars()
+                .filter(|c| c.is_ascii_digit() || *c == 'x')
+                .collect();
+            matches!(digits.len(), 10 | 13).then(|| digits.to_uppercase())
+        });
+
+    let mut seen = HashSet::new();
+    metadata.subjects = texts
+        .iter()
+        .filter(|t| t.element == "subject")
+        .flat_map(|t| t.text.split(';'))
+        .map(collapse_whitespace)
+        .filter(|subject| !subject.is_empty() && seen.insert(fold(subject)))
+        .collect();
+
+    let refinement = |id: &Option<String>, property: &str| {
+        let target = format!("#{}", id.as_deref()?);
+        texts
+            .iter()
+            .find(|t| {
+                t.element == "meta"
+                    && t.refines.as_deref() == Some(target.as_str())
+                    && t.property.as_deref() == Some(property)
+            })
+            .map(|t| t.text.trim().to_string())
+    };
+
+    for creator in texts.iter().filter(|t| t.element == "creator") {
+        let name = collapse_whitespace(&creator.text);
+        let role = creator
+            .property
+            .clone()
+            .or_else(|| refinement(&creator.id, "role"));
+        if !name.is_empty() && role.map_or(true, |r| r.eq_ignore_ascii_case("aut")) {
+            metadata.authors.push(name);
+        }
+    }
+
+    if metadata.series.is_none() {
+        let collection = texts.iter().find(|t| {
+            t.element == "meta"
+                && t.refines.is_none()
+                && t.property.as_deref() == Some("belongs-to-collection")
+                && refinement(&t.id, "collection-type").map_or(true, |kind| kind == "series")
+        });
+        if let Some(collection) = collection {
+            metadata.series = Some(collapse_whitespace(&collection.text)).filter(|s| !s.is_empty());
+            metadata.series_index = refinement(&collection.id, "group-position")
+                .and_then(|position| position.parse().ok());
+        }
+    }
+}
+
+/// Maps chapter paths to the label of their first NCX `navPoint`.
+fn parse_ncx(ncx: &str, base: &str) -> HashMap<String, String> {
+    let mut labels = HashMap::new();
+    let mut reader = Reader::from_str(ncx);
+    let mut in_label = false;
+    let mut label = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if local_name(&e) == "navlabel" => {
+                in_label = true;
+                label.clear();
+            }
+            Ok(Event::End(e)) if e.local_name().as_ref().eq_ignore_ascii_case(b"navLabel") => {
+                in_label = false;
+            }
+            Ok(Event::Text(t)) if in_label => {
+                if let Ok(text) = t.xml_content() {
+                    label.push_str(&text);
+                }
+            }
+            Ok(Event::Start(e) | Event::Empty(e)) if local_name(&e) == "content" => {
+                if let Some(src) = attribute(&e, "src") {
+                    let title = collapse_whitespace(&label);
+                    if !title.is_empty() {
+                        labels.entry(resolve(base, &src)).or_insert(title);
+                    }
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    labels
+}
+
+/// Maps chapter paths to the text of their first link in the EPUB 3
+/// `<nav epub:type="toc">`.
+fn parse_nav(nav: &str, base: &str) -> HashMap<String, String> {
+    let mut labels = HashMap::new();
+    let mut reader = Reader::from_str(nav);
+    reader.config_mut().check_end_names = false;
+    let mut nav_depth = 0usize;
+    let mut link: Option<(String, String)> = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => match local_name(&e).as_str() {
+                "nav" if nav_depth > 0 || attribute(&e, "type").as_deref() == Some("toc") => {
+                    nav_depth += 1;
+                }
+                "a" if nav_depth > 0 => {
+                    link = attribute(&e, "href").map(|href| (href, String::new()));
+                }
+                _ => {}
+            },
+            Ok(Event::End(e)) => match e.local_name().as_ref() {
+                b"nav" if nav_depth > 0 => nav_depth -= 1,
+                b"a" => {
+                    if let Some((href, text)) = link.take() {
+                        let title = collapse_whitespace(&text);
+                        if !title.is_empty() {
+                            labels.entry(resolve(base, &href)).or_insert(title);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Ok(Event::Text(t)) => {
+                if let (Some((_, text)), Ok(content)) = (link.as_mut(), t.xml_content()) {
+                    text.push_str(&content);
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    labels
+}
+
+struct OpenElement {
+    name: String,
+    /// CFI step of this element within its parent.
+    step: String,
+    /// Element children seen so far.
+    children: usize,
+    /// Text collected for this element, if it is a block.
+    text: Option<String>,
+}
+
+/// A chapter's text as the reader's DOM holds it, for locating quotes and
+/// turning their position back into a CFI.
+pub struct ChapterText {
+    /// Content of every visible text node, concatenated in document order.
+    pub text: String,
+    base: String,
+    runs: Vec<TextRun>,
+}
+
+/// One CFI text position: the text between two elements (CFI merges adjacent
+/// text and entity nodes into a single odd-numbered step).
+struct TextRun {
+    /// Steps from the root element, ending in the odd text step.
+    path: String,
+    /// Byte offset of the run in [`ChapterText::text`].
+    start: usize,
+}
+
+impl ChapterText {
+    /// Range CFI covering `self.text[start..end]`.
+    pub fn range_cfi(&self, start: usize, end: usize) -> Option<String> {
+        if start >= end {
+            return None;
+        }
+        let point = |pos: usize, inclusive_end: bool| {
+            let run = self.runs.iter().rev().find(|r| {
+                if inclusive_end {
+                    r.start < pos
+                } else {
+                    r.start <= pos
+                }
+            })?;
+            // CFI offsets count UTF-16 code units, like the DOM.
+            let offset = self.text.get(run.start..pos)?.encode_utf16().count();
+            let steps: Vec<&str> = run.path.split('/').skip(1).collect();
+            Some((steps, offset))
+        };
+        let (start_steps, start_offset) = point(start, false)?;
+        let (end_steps, end_offset) = point(end, true)?;
+
+        let shared = start_steps
+            .iter()
+            .zip(&end_steps)
+            .take_while(|(a, b)| a == b)
+            .count()
+            .min(start_steps.len().min(end_steps.len()) - 1);
+        let join = |steps: &[&str]| steps.iter().map(|s| format!("/{s}")).collect::<String>();
+        Some(format!(
+            "epubcfi({}!{},{}:{start_offset},{}:{end_offset})",
+            self.base,
+            join(&start_steps[..shared]),
+            join(&start_steps[shared..]),
+            join(&end_steps[shared..])
+        ))
+    }
+}
+
+/// Splits chapter markup into passages. `base` is the spine-level CFI from
+/// [`spine_cfi`]; each passage's CFI points at its block element.
+pub fn extract_passages(html: &str, base: &str) -> Vec<Passage> {
+    parse_chapter(html, base).0
+}
+
+/// Reads chapter markup into its passages and its DOM text.
+pub fn parse_chapter(html: &str, base: &str) -> (Vec<Passage>, ChapterText) {
+    let mut reader = Reader::from_str(html);
+    reader.config_mut().check_end_names = false;
+
+    // The root (`<html>`) is the CFI's starting point, so it has no step.
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut roots = 0usize;
+    let mut skipping = 0usize;
+    let mut passages = Vec::new();
+    let mut chapter = ChapterText {
+        text: String::new(),
+        base: base.to_string(),
+        runs: Vec::new(),
+    };
+    // Whether the current text position already has a run; any element
+    // boundary starts a new one.
+    let mut in_run = false;
+
+    let step_for = |e: &BytesStart, stack: &mut Vec<OpenElement>, roots: &mut usize| {
+        let index = match stack.last_mut() {
+            Some(parent) => {
+                parent.children += 1;
+                parent.children
+            }
+            None => {
+                *roots += 1;
+                *roots
+            }
+        };
+        match attribute(e, "id") {
+            Some(id) => format!("/{}[{}]", index * 2, id),
+            None => format!("/{}", index * 2),
+        }
+    };
+
+    let push_block_text = |stack: &mut Vec<OpenElement>, text: &str| {
+        if let Some(block) = stack.iter_mut().rev().find_map(|el| el.text.as_mut()) {
+            block.push_str(text);
+        }
+    };
+
+    let mut push_text = |stack: &mut Vec<OpenElement>, in_run: &mut bool, text: &str| {
+        let Some(parent) = stack.last() else {
+            return;
+        };
+        if !*in_run {
+            let path: String = stack[1..]
+                .iter()
+                .map(|el| el.step.as_str())
+                .chain([format!("/{}", parent.children * 2 + 1).as_str()])
+                .collect();
+            chapter.runs.push(TextRun {
+                path,
+                start: chapter.text.len(),
+            });
+            *in_run = true;
+        }
+        chapter.text.push_str(text);
+        push_block_text(stack, text);
+    };
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => {
+                log::warn!("Stopped reading chapter {base} early: {e}");
+                break;
+            }
+        };
+        match event {
+            Event::Start(e) => {
+                in_run = false;
+                let name = local_name(&e);
+                let step = step_for(&e, &mut stack, &mut roots);
+                if skipping > 0 || SKIPPED_ELEMENTS.contains(&name.as_str()) {
+                    skipping += 1;
+                }
+                let text = BLOCK_ELEMENTS.contains(&name.as_str()).then(String::new);
+                stack.push(OpenElement {
+                    name,
+                    step,
+                    children: 0,
+                    text,
+                });
+            }
+            Event::Empty(e) => {
+                in_run = false;
+                step_for(&e, &mut stack, &mut roots);
+                if skipping == 0 && local_name(&e) == "br" {
+                    push_block_text(&mut stack, " ");
+                }
+            }
+            Event::End(e) => {
+                in_run = false;
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase();
+                // Unbalanced markup: close up to the matching element, if any.
+                let Some(depth) = stack.iter().rposition(|el| el.name == name) else {
+                    continue;
+                };
+                while stack.len() > depth {
+                    let cfi_steps: String = stack[1..].iter().map(|el| el.step.as_str()).collect();
+                    let element = stack.pop().expect("stack is non-empty");
+                    if skipping > 0 {
+                        skipping -= 1;
+                        continue;
+                    }
+                    if let Some(text) = element.text {
+                        let text = collapse_whitespace(&text);
+                        if !text.is_empty() {
+                            passages.push(Passage {
+                                cfi: format!("epubcfi({base}!{cfi_steps})"),
+                                text,
+                            });
+                        }
+                    }
+                }
+            }
+            Event::Text(t) if skipping == 0 => {
+                if let Ok(text) = t.xml_content() {
+                    push_text(&mut stack, &mut in_run, &text);
+                }
+            }
+            Event::CData(t) if skipping == 0 => {
+                if let Ok(text) = t.decode() {
+                    push_text(&mut stack, &mut in_run, &text);
+                }
+            }
+            Event::GeneralRef(r) if skipping == 0 => {
+                let c = match r.resolve_char_ref() {
+                    Ok(Some(c)) => Some(c.to_string()),
+                    _ => r.decode().ok().and_then(|name| html_entity(&name)),
+                };
+                if let Some(c) = c {
+                    push_text(&mut stack, &mut in_run, &c);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    (passages, chapter)
+}
+
+fn html_entity(name: &str) -> Option<String> {
+    if let Some(c) = resolve_xml_entity(name) {
+        return Some(c.to_string());
+    }
+    let c = match name {
+        "nbsp" => ' ',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "shy" => return Some(String::new()),
+        _ => return None,
+    };
+    Some(c.to_string())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
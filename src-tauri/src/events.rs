@@ -0,0 +1,164 @@
+//! Events emitted after every write so all open windows can update the
+//! affected record in place instead of refetching everything.
+//!
+//! Names follow `<area>://<what happened>`; the payload is the affected record,
+//! or just its key for deletions. [`DataEvent::LibraryReloaded`] covers bulk
+//! changes (restores, imports, sync, wipes) where listeners should refetch.
+
+use crate::merge::MergeReport;
+use crate::models::{
+    BookMetadata, BookNote, Bookmark, Collection, Drawing, Feed, GoalKind, Highlight,
+    HighlightColor, HighlightLink, InboxEntry, ReadingSession, SmartCollection, VocabWord,
+};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RecordId {
+    pub id: i64,
+}
+
+/// Keys of the records affected by a bulk operation.
+#[derive(Debug, Serialize, Clone)]
+pub struct RecordIds {
+    pub ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BookRef {
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BookProgress {
+    pub title: String,
+    pub last_position: String,
+    pub last_percentage: f64,
+    pub finished_at: Option<String>,
+}
+
+/// A large move in a book's progress, which the reader may want to undo with
+/// `revert_progress`.
+#[derive(Debug, Serialize, Clone)]
+pub struct ProgressJump {
+    pub book_id: i64,
+    pub title: String,
+    pub from_percentage: f64,
+    pub to_percentage: f64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CollectionLink {
+    pub highlight_id: i64,
+    pub collection_id: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CollectionLinks {
+    pub highlight_ids: Vec<i64>,
+    pub collection_id: i64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GoalUpdate {
+    pub kind: GoalKind,
+    /// `None` when the goal was removed.
+    pub target: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum DataEvent {
+    BookAdded(BookMetadata),
+    BookUpdated(BookMetadata),
+    BookProgress(BookProgress),
+    ProgressJumped(ProgressJump),
+    BookDeleted(BookRef),
+    LibraryReloaded,
+    HighlightAdded(Highlight),
+    HighlightUpdated(Highlight),
+    HighlightDeleted(RecordId),
+    HighlightsUpdated(Vec<Highlight>),
+    HighlightsDeleted(RecordIds),
+    HighlightsImported(MergeReport),
+    BookmarkAdded(Bookmark),
+    BookmarkUpdated(Bookmark),
+    BookmarkDeleted(RecordId),
+    BookNoteAdded(BookNote),
+    BookNoteUpdated(BookNote),
+    BookNoteDeleted(RecordId),
+    DrawingAdded(Drawing),
+    DrawingUpdated(Drawing),
+    DrawingDeleted(RecordId),
+    HighlightColorsUpdated(Vec<HighlightColor>),
+    HighlightsLinked(HighlightLink),
+    HighlightsUnlinked(RecordId),
+    CollectionCreated(Collection),
+    CollectionDeleted(RecordId),
+    HighlightAddedToCollection(CollectionLink),
+    HighlightRemovedFromCollection(CollectionLink),
+    HighlightsAddedToCollection(CollectionLinks),
+    SmartCollectionCreated(SmartCollection),
+    SmartCollectionDeleted(RecordId),
+    VocabWordAdded(VocabWord),
+    VocabWordDeleted(RecordId),
+    ReadingSessionLogged(ReadingSession),
+    GoalUpdated(GoalUpdate),
+    FeedUpdated(Feed),
+    FeedDeleted(RecordId),
+    InboxEntryUpdated(InboxEntry),
+}
+
+impl DataEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataEvent::BookAdded(_) => "library://book-added",
+            DataEvent::BookUpdated(_) => "library://book-updated",
+            DataEvent::BookProgress(_) => "library://book-progress",
+            DataEvent::ProgressJumped(_) => "library://progress-jumped",
+            DataEvent::BookDeleted(_) => "library://book-deleted",
+            DataEvent::LibraryReloaded => "library://reloaded",
+            DataEvent::HighlightAdded(_) => "annotations://highlight-added",
+            DataEvent::HighlightUpdated(_) => "annotations://highlight-updated",
+            DataEvent::HighlightDeleted(_) => "annotations://highlight-deleted",
+            DataEvent::HighlightsUpdated(_) => "annotations://highlights-updated",
+            DataEvent::HighlightsDeleted(_) => "annotations://highlights-deleted",
+            DataEvent::HighlightsImported(_) => "annotations://highlights-imported",
+            DataEvent::BookmarkAdded(_) => "annotations://bookmark-added",
+            DataEvent::BookmarkUpdated(_) => "annotations://bookmark-updated",
+            DataEvent::BookmarkDeleted(_) => "annotations://bookmark-deleted",
+            DataEvent::BookNoteAdded(_) => "annotations://book-note-added",
+            DataEvent::BookNoteUpdated(_) => "annotations://book-note-updated",
+            DataEvent::BookNoteDeleted(_) => "annotations://book-note-deleted",
+            DataEvent::DrawingAdded(_) => "annotations://drawing-added",
+            DataEvent::DrawingUpdated(_) => "annotations://drawing-updated",
+            DataEvent::DrawingDeleted(_) => "annotations://drawing-deleted",
+            DataEvent::HighlightColorsUpdated(_) => "annotations://colors-updated",
+            DataEvent::HighlightsLinked(_) => "annotations://highlights-linked",
+            DataEvent::HighlightsUnlinked(_) => "annotations://highlights-unlinked",
+            DataEvent::CollectionCreated(_) => "collections://collection-created",
+            DataEvent::CollectionDeleted(_) => "collections://collection-deleted",
+            DataEvent::HighlightAddedToCollection(_) => "collections://highlight-added",
+            DataEvent::HighlightRemovedFromCollection(_) => "collections://highlight-removed",
+            DataEvent::HighlightsAddedToCollection(_) => "collections://highlights-added",
+            DataEvent::SmartCollectionCreated(_) => "collections://smart-collection-created",
+            DataEvent::SmartCollectionDeleted(_) => "collections://smart-collection-deleted",
+            DataEvent::VocabWordAdded(_) => "vocabulary://word-added",
+            DataEvent::VocabWordDeleted(_) => "vocabulary://word-deleted",
+            DataEvent::ReadingSessionLogged(_) => "goals://session-logged",
+            DataEvent::GoalUpdated(_) => "goals://goal-updated",
+            DataEvent::FeedUpdated(_) => "feeds://feed-updated",
+            DataEvent::FeedDeleted(_) => "feeds://feed-deleted",
+            DataEvent::InboxEntryUpdated(_) => "feeds://inbox-entry-updated",
+        }
+    }
+}
+
+/// Broadcasts `event` to every window. The write has already happened, so a
+/// failure to emit is logged rather than returned.
+pub fn emit(app: &AppHandle, event: DataEvent) {
+    let name = event.name();
+    if let Err(e) = app.emit(name, event) {
+        log::warn!("Failed to emit {name}: {e}");
+    }
+}
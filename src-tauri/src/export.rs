@@ -0,0 +1,285 @@
+//! Highlights leaving the app: the JSON document that round-trips through
+//! [`import_document`], and shareable renderings as Markdown or a standalone
+//! HTML page with its styles inlined. Rendered highlights are grouped by
+//! book, in the order given.
+
+use crate::db::{highlight_from_row, HIGHLIGHT_COLUMNS};
+use crate::merge::{self, MergeReport};
+use crate::models::{
+    ExportScope, ExportedHighlight, HighlightsExport, HIGHLIGHTS_EXPORT_SCHEMA_VERSION,
+};
+use rusqlite::{params, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+pub struct ExportItem {
+    pub book_title: String,
+    pub author: Option<String>,
+    pub text: String,
+    pub notes: String,
+    pub color: String,
+    pub created_at: String,
+}
+
+/// SQL condition on `highlights h` selecting `scope`, with its parameter.
+pub(crate) fn scope_condition(scope: &ExportScope) -> (&'static str, Option<Value>) {
+    match scope {
+        ExportScope::All => ("1", None),
+        ExportScope::Book { title } => ("h.book_title = ?1", Some(title.clone().into())),
+        ExportScope::Collection { collection_id } => (
+            "h.id IN (SELECT highlight_id FROM highlight_collections WHERE collection_id = ?1)",
+            Some((*collection_id).into()),
+        ),
+    }
+}
+
+/// The highlights in `scope` with their collections, as written by
+/// `export_highlights_json`.
+pub fn highlights_document(
+    conn: &Connection,
+    scope: &ExportScope,
+) -> Result<HighlightsExport, String> {
+    let (condition, param) = scope_condition(scope);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS}, h.updated_at FROM highlights h WHERE {condition} ORDER BY h.book_title, h.created_at"
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(param), |row| {
+            Ok((highlight_from_row(row)?, row.get(13)?))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut collections_stmt = conn
+        .prepare(
+            "SELECT c.name FROM collections c
+             INNER JOIN highlight_collections hc ON c.id = hc.collection_id
+             WHERE hc.highlight_id = ?1 ORDER BY c.name",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        let (hl, updated_at) = row.map_err(|e| e.to_string())?;
+        let collections = collections_stmt
+            .query_map(params![hl.id], |r| r.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .map_err(|e| e.to_string())?;
+        items.push(ExportedHighlight {
+            book_title: hl.book_title,
+            cfi: hl.cfi,
+            text: hl.text,
+            color: hl.color,
+            notes: hl.notes,
+            created_at: hl.created_at,
+            updated_at,
+            collections,
+        });
+    }
+
+    let exported_at: String = conn
+        .query_row("SELECT datetime('now')", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    Ok(HighlightsExport {
+        schema_version: HIGHLIGHTS_EXPORT_SCHEMA_VERSION,
+        exported_at,
+        items,
+    })
+}
+
+/// Merges a document written by [`highlights_document`] into the library in
+/// one transaction; see [`merge::merge_highlights`].
+pub fn import_document(conn: &mut Connection, json: &str) -> Result<MergeReport, String> {
+    let document: HighlightsExport = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    if document.schema_version > HIGHLIGHTS_EXPORT_SCHEMA_VERSION {
+        return Err(format!(
+            "Unsupported highlights export version {} (this app reads up to {})",
+            document.schema_version, HIGHLIGHTS_EXPORT_SCHEMA_VERSION
+        ));
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let report = merge::merge_highlights(&tx, &document.items).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// The highlights in `scope` for [`render`], by book title, then oldest
+/// first.
+pub fn items(conn: &Connection, scope: &ExportScope) -> rusqlite::Result<Vec<ExportItem>> {
+    let (condition, param) = scope_condition(scope);
+    conn.prepare(&format!(
+        "SELECT h.book_title, b.author, h.text, h.notes, h.color, h.created_at
+         FROM highlights h
+         LEFT JOIN books b ON b.title = h.book_title
+         WHERE {condition}
+         ORDER BY h.book_title COLLATE fold, h.book_title, h.created_at"
+    ))?
+    .query_map(rusqlite::params_from_iter(param), |row| {
+        Ok(ExportItem {
+            book_title: row.get(0)?,
+            author: row.get(1)?,
+            text: row.get(2)?,
+            notes: row.get(3)?,
+            color: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+/// Heading of a rendering of `scope`: the book's title, or the collection's
+/// emoji and name.
+pub fn scope_title(conn: &Connection, scope: &ExportScope) -> rusqlite::Result<String> {
+    match scope {
+        ExportScope::All => Ok("Highlights".to_string()),
+        ExportScope::Book { title } => Ok(title.clone()),
+        ExportScope::Collection { collection_id } => {
+            let (name, emoji): (String, String) = conn.query_row(
+                "SELECT name, emoji FROM collections WHERE id = ?1",
+                params![collection_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            Ok(format!("{emoji} {name}").trim().to_string())
+        }
+    }
+}
+
+/// Renders `items` under the heading `title`.
+pub fn render(format: ExportFormat, title: &str, items: &[ExportItem]) -> String {
+    match format {
+        ExportFormat::Markdown => markdown(title, items),
+        ExportFormat::Html => html(title, items),
+    }
+}
+
+/// Consecutive items of the same book.
+pub(crate) fn by_book(items: &[ExportItem]) -> Vec<&[ExportItem]> {
+    items
+        .chunk_by(|a, b| a.book_title == b.book_title)
+        .collect()
+}
+
+/// `2024-03-01 18:22:10` → `2024-03-01`.
+pub(crate) fn date(timestamp: &str) -> &str {
+    timestamp.get(..10).unwrap_or(timestamp)
+}
+
+fn markdown(title: &str, items: &[ExportItem]) -> String {
+    let mut out = format!("# {title}\n");
+    for book in by_book(items) {
+        out.push_str(&format!("\n## {}\n", book[0].book_title));
+        if let Some(author) = &book[0].author {
+            out.push_str(&format!("\n*{author}*\n"));
+        }
+        for item in book {
+            out.push('\n');
+            for line in item.text.trim().lines() {
+                out.push_str(&format!("> {line}\n"));
+            }
+            if !item.notes.trim().is_empty() {
+                out.push('\n');
+                out.push_str(item.notes.trim());
+                out.push('\n');
+            }
+            out.push_str(&format!("\n*{}*\n", date(&item.created_at)));
+        }
+    }
+    out
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Paragraphs separated by blank lines, with single line breaks kept.
+fn paragraphs(text: &str) -> String {
+    text.trim()
+        .split("\n\n")
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| format!("<p>{}</p>", escape(p.trim()).replace('\n', "<br>")))
+        .collect()
+}
+
+/// Highlight colors come from the user's palette; anything that isn't a
+/// plain color value falls back to the default rather than reaching the CSS.
+fn css_color(color: &str) -> &str {
+    let plain = !color.is_empty() && color.chars().all(|c| c.is_ascii_alphanumeric() || c == '#');
+    if plain {
+        color
+    } else {
+        "#facc15"
+    }
+}
+
+pub(crate) const STYLE: &str = "body{font-family:Georgia,'Times New Roman',serif;max-width:42rem;\
+margin:3rem auto;padding:0 1.25rem;color:#1f2937;line-height:1.6;background:#fdfcf9}\
+h1{font-size:2rem;margin-bottom:2rem}h2{font-size:1.35rem;margin:2.5rem 0 .25rem}\
+.author{color:#6b7280;font-style:italic;margin:0 0 1rem}\
+figure{margin:1.5rem 0}blockquote{margin:0;padding:.25rem 0 .25rem 1rem;border-left:4px solid}\
+blockquote p{margin:.25rem 0}.note{font-family:system-ui,sans-serif;font-size:.95rem;\
+background:#f3f4f6;border-radius:6px;padding:.5rem .75rem;margin-top:.5rem}.note p{margin:.25rem 0}\
+figcaption{font-family:system-ui,sans-serif;font-size:.8rem;color:#9ca3af;margin-top:.35rem}";
+
+/// A highlight with its note and date.
+pub(crate) fn figure(item: &ExportItem) -> String {
+    let mut out = format!(
+        "<figure>\n<blockquote style=\"border-color:{}\">{}</blockquote>\n",
+        css_color(&item.color),
+        paragraphs(&item.text)
+    );
+    if !item.notes.trim().is_empty() {
+        out.push_str(&format!(
+            "<div class=\"note\">{}</div>\n",
+            paragraphs(&item.notes)
+        ));
+    }
+    out.push_str(&format!(
+        "<figcaption>{}</figcaption>\n</figure>\n",
+        date(&item.created_at)
+    ));
+    out
+}
+
+/// A standalone page with `style` inlined.
+pub(crate) fn document(title: &str, style: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+<title>{}</title>\n<style>{style}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+fn html(title: &str, items: &[ExportItem]) -> String {
+    let mut body = format!("<h1>{}</h1>\n", escape(title));
+    for book in by_book(items) {
+        body.push_str(&format!("<h2>{}</h2>\n", escape(&book[0].book_title)));
+        if let Some(author) = &book[0].author {
+            body.push_str(&format!("<p class=\"author\">{}</p>\n", escape(author)));
+        }
+        for item in book {
+            body.push_str(&figure(item));
+        }
+    }
+    document(title, STYLE, &body)
+}
@@ -0,0 +1,99 @@
+//! A book's content as plain text, for word counts, read-aloud, indexing and
+//! the assistant, so the frontend needn't take books apart itself.
+//!
+//! EPUBs (and saved articles) give one paragraph per block element, split
+//! into the chapters of their table of contents; PDFs give a paragraph per
+//! page, each page counting as a chapter. Whitespace is collapsed to single
+//! spaces within paragraphs, which are separated by blank lines.
+
+use crate::epub::Epub;
+use crate::reading_time::count_words;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookText {
+    /// The chapter extracted, or `None` for the whole book.
+    pub chapter: Option<usize>,
+    pub chapter_title: Option<String>,
+    /// Chapters (pages for PDFs) in the book.
+    pub chapters: usize,
+    pub text: String,
+    pub word_count: usize,
+}
+
+/// `text` with runs of whitespace made single spaces, trimmed.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn paragraphs(paragraphs: impl IntoIterator<Item = String>) -> String {
+    paragraphs
+        .into_iter()
+        .map(|paragraph| normalize(&paragraph))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn epub_text(path: &Path, chapter: Option<usize>) -> Result<BookText, String> {
+    let mut epub = Epub::open(path)?;
+    let spans = epub.chapter_spans();
+    let (items, chapter_title) = match chapter {
+        Some(index) => {
+            let span = spans
+                .get(index)
+                .ok_or_else(|| format!("No chapter {index}; the book has {}", spans.len()))?;
+            (span.clone(), epub.spine[span.start].title.clone())
+        }
+        None => (0..epub.spine.len(), None),
+    };
+    let mut passages = Vec::new();
+    for index in items {
+        passages.extend(epub.passages(index)?.into_iter().map(|p| p.text));
+    }
+    let text = paragraphs(passages);
+    Ok(BookText {
+        chapter,
+        chapter_title,
+        chapters: spans.len(),
+        word_count: count_words(&text),
+        text,
+    })
+}
+
+fn pdf_text(path: &Path, page: Option<usize>) -> Result<BookText, String> {
+    let data = crate::storage::read(path).map_err(|e| e.to_string())?;
+    let document = lopdf::Document::load_mem(&data).map_err(|e| e.to_string())?;
+    let pages: Vec<u32> = document.get_pages().into_keys().collect();
+    let selected = match page {
+        Some(index) => vec![*pages
+            .get(index)
+            .ok_or_else(|| format!("No page {index}; the book has {}", pages.len()))?],
+        None => pages.clone(),
+    };
+    let mut texts = Vec::new();
+    for number in selected {
+        match document.extract_text(&[number]) {
+            Ok(text) => texts.push(text),
+            Err(e) => log::warn!("Could not read page {number}: {e}"),
+        }
+    }
+    let text = paragraphs(texts);
+    Ok(BookText {
+        chapter: page,
+        chapter_title: page.map(|index| format!("Page {}", index + 1)),
+        chapters: pages.len(),
+        word_count: count_words(&text),
+        text,
+    })
+}
+
+/// The text of the book at `path`, or of one of its chapters (0-based).
+pub fn book_text(path: &Path, format: &str, chapter: Option<usize>) -> Result<BookText, String> {
+    match format {
+        format if crate::import::is_epub(format) => epub_text(path, chapter),
+        "pdf" => pdf_text(path, chapter),
+        _ => Err(format!("Can't extract text from {format} books")),
+    }
+}
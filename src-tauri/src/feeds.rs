@@ -0,0 +1,394 @@
+//! RSS and Atom feeds whose new entries are saved as articles into the inbox.
+//!
+//! Parsing is lenient: only the handful of elements needed to list entries
+//! are read, by local name, so RSS 0.9x–2.0, RDF and Atom all work and
+//! namespaced extensions like `content:encoded` are picked up too.
+
+use crate::article::{self, Article};
+use kuchikiki::traits::TendrilSink;
+use quick_xml::escape::resolve_xml_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use reqwest::Url;
+use time::format_description::well_known::{Rfc2822, Rfc3339};
+use time::{OffsetDateTime, UtcOffset};
+
+/// How often each feed is checked for new entries.
+pub const REFRESH_MINUTES: i64 = 60;
+
+/// Entries saved from a newly subscribed feed; older ones are left out so
+/// subscribing doesn't fill the inbox with a feed's whole archive.
+pub const FIRST_FETCH_ENTRIES: usize = 5;
+
+/// Times an entry is tried before it's given up on.
+pub const MAX_ATTEMPTS: i64 = 3;
+
+#[derive(Debug, Default)]
+pub struct ParsedFeed {
+    pub title: String,
+    pub site_url: Option<String>,
+    /// Newest first, as feeds list them.
+    pub entries: Vec<ParsedEntry>,
+}
+
+impl ParsedFeed {
+    /// Makes relative links absolute, against the address the feed was
+    /// fetched from.
+    fn resolve_links(&mut self, base: &Url) {
+        let resolve = |link: &mut Option<String>| {
+            if let Some(url) = link.as_deref().and_then(|link| base.join(link).ok()) {
+                *link = Some(url.into());
+            }
+        };
+        resolve(&mut self.site_url);
+        for entry in &mut self.entries {
+            resolve(&mut entry.url);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ParsedEntry {
+    /// The entry's `guid` or `id`, else its link or title.
+    pub guid: String,
+    pub url: Option<String>,
+    pub title: String,
+    /// UTC, formatted like SQLite's `datetime('now')`.
+    pub published_at: Option<String>,
+    /// The entry's full content, or else its summary, as HTML.
+    pub content: Option<String>,
+}
+
+fn local_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.local_name().as_ref()).to_lowercase()
+}
+
+fn attribute(e: &BytesStart, key: &str) -> Option<String> {
+    e.attributes().flatten().find_map(|a| {
+        (a.key.local_name().as_ref() == key.as_bytes())
+            .then(|| a.unescape_value().ok().map(|v| v.into_owned()))
+            .flatten()
+    })
+}
+
+/// Whether an Atom `<link>` points at the entry's web page.
+fn is_page_link(e: &BytesStart) -> bool {
+    matches!(attribute(e, "rel").as_deref(), None | Some("alternate"))
+}
+
+pub fn parse(xml: &str) -> Result<ParsedFeed, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut feed = ParsedFeed::default();
+    let mut entry: Option<ParsedEntry> = None;
+    let mut summary: Option<String> = None;
+    let mut is_feed = false;
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Not a valid feed: {e}"))?;
+        match event {
+            Event::Start(e) => {
+                let name = local_name(&e);
+                match name.as_str() {
+                    "rss" | "feed" | "rdf" | "channel" => is_feed = true,
+                    "item" | "entry" => {
+                        entry = Some(ParsedEntry::default());
+                        summary = None;
+                    }
+                    _ => {}
+                }
+                if name == "link" && is_page_link(&e) {
+                    if let Some(href) = attribute(&e, "href") {
+                        set_link(&mut feed, &mut entry, href);
+                    }
+                }
+                // Atom `type="xhtml"` content is markup rather than text.
+                if matches!(name.as_str(), "content" | "summary")
+                    && attribute(&e, "type").as_deref() == Some("xhtml")
+                {
+                    let inner = reader
+                        .read_text(e.name())
+                        .map_err(|e| format!("Not a valid feed: {e}"))?;
+                    text = inner.into_owned();
+                    end_element(&name, &path, &text, &mut feed, &mut entry, &mut summary);
+                    text.clear();
+                    continue;
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Empty(e) if local_name(&e) == "link" && is_page_link(&e) => {
+                if let Some(href) = attribute(&e, "href") {
+                    set_link(&mut feed, &mut entry, href);
+                }
+            }
+            Event::Text(t) => {
+                if let Ok(t) = t.xml_content() {
+                    text.push_str(&t);
+                }
+            }
+            Event::CData(t) => text.push_str(&String::from_utf8_lossy(&t)),
+            Event::GeneralRef(r) => match r.resolve_char_ref() {
+                Ok(Some(c)) => text.push(c),
+                _ => {
+                    let name = r.decode().map_err(|e| e.to_string())?;
+                    text.push_str(resolve_xml_entity(&name).unwrap_or_default());
+                }
+            },
+            Event::End(_) => {
+                let Some(name) = path.pop() else { continue };
+                end_element(&name, &path, &text, &mut feed, &mut entry, &mut summary);
+                if matches!(name.as_str(), "item" | "entry") {
+                    if let Some(mut done) = entry.take() {
+                        if done.content.is_none() {
+                            done.content = summary.take();
+                        }
+                        if done.guid.is_empty() {
+                            done.guid = done.url.clone().unwrap_or_else(|| done.title.clone());
+                        }
+                        // Some feeds repeat an entry; the first copy is kept.
+                        let seen = feed.entries.iter().any(|e| e.guid == done.guid);
+                        if !done.guid.is_empty() && !seen {
+                            feed.entries.push(done);
+                        }
+                    }
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if !is_feed {
+        return Err("Not an RSS or Atom feed".to_string());
+    }
+    Ok(feed)
+}
+
+fn set_link(feed: &mut ParsedFeed, entry: &mut Option<ParsedEntry>, href: String) {
+    match entry {
+        Some(entry) => {
+            entry.url.get_or_insert(href);
+        }
+        None => {
+            feed.site_url.get_or_insert(href);
+        }
+    }
+}
+
+/// Stores the text of a finished element on the entry being read, or on the
+/// feed when outside of one.
+fn end_element(
+    name: &str,
+    path: &[String],
+    text: &str,
+    feed: &mut ParsedFeed,
+    entry: &mut Option<ParsedEntry>,
+    summary: &mut Option<String>,
+) {
+    let value = text.trim();
+    if value.is_empty() {
+        return;
+    }
+    let parent = path.last().map(String::as_str);
+    match entry {
+        Some(entry) if matches!(parent, Some("item" | "entry")) => match name {
+            "title" => entry.title = clean(value),
+            "link" => {
+                entry.url.get_or_insert_with(|| value.to_string());
+            }
+            "guid" | "id" => entry.guid = value.to_string(),
+            "pubdate" | "published" | "date" | "updated" | "issued"
+                if entry.published_at.is_none() =>
+            {
+                entry.published_at = normalize_date(value);
+            }
+            "encoded" | "content" => entry.content = Some(value.to_string()),
+            "description" | "summary" => *summary = Some(value.to_string()),
+            _ => {}
+        },
+        Some(_) => {}
+        None if matches!(parent, Some("channel" | "feed")) => match name {
+            "title" => feed.title = clean(value),
+            "link" => {
+                feed.site_url.get_or_insert_with(|| value.to_string());
+            }
+            _ => {}
+        },
+        None => {}
+    }
+}
+
+/// Titles as plain text; feeds often escape markup and entities in them.
+fn clean(text: &str) -> String {
+    let text = if text.contains(['<', '&']) {
+        kuchikiki::parse_html()
+            .one(text)
+            .document_node
+            .text_contents()
+    } else {
+        text.to_string()
+    };
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// RFC 2822 (RSS) and RFC 3339 (Atom) dates as UTC `YYYY-MM-DD HH:MM:SS`.
+fn normalize_date(date: &str) -> Option<String> {
+    let parsed = OffsetDateTime::parse(date, &Rfc2822)
+        .or_else(|_| OffsetDateTime::parse(date, &Rfc3339))
+        .ok()?;
+    let utc = parsed.to_offset(UtcOffset::UTC);
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        utc.year(),
+        u8::from(utc.month()),
+        utc.day(),
+        utc.hour(),
+        utc.minute(),
+        utc.second()
+    ))
+}
+
+/// The feed a web page advertises with `<link rel="alternate">`, so a site's
+/// address can be subscribed to directly.
+pub fn discover(html: &str, base: &Url) -> Option<Url> {
+    let document = kuchikiki::parse_html().one(html).document_node;
+    document
+        .select("link[rel~='alternate']")
+        .ok()?
+        .find_map(|link| {
+            let attributes = link.attributes.borrow();
+            let kind = attributes.get("type")?.to_lowercase();
+            if !(kind.contains("rss") || kind.contains("atom")) {
+                return None;
+            }
+            base.join(attributes.get("href")?).ok()
+        })
+}
+
+/// Downloads and parses the feed at `url`, following a web page to the feed
+/// it links to. Returns the feed with the address it was found at.
+pub async fn fetch(client: &reqwest::Client, url: Url) -> Result<(ParsedFeed, Url), String> {
+    let (body, content_type, final_url) = article::fetch_text(client, url).await?;
+    if content_type.contains("html") {
+        let feed_url =
+            discover(&body, &final_url).ok_or(format!("No feed found at {final_url}"))?;
+        let (body, _, final_url) = article::fetch_text(client, feed_url).await?;
+        let mut feed = parse(&body)?;
+        feed.resolve_links(&final_url);
+        return Ok((feed, final_url));
+    }
+    let mut feed = parse(&body)?;
+    feed.resolve_links(&final_url);
+    Ok((feed, final_url))
+}
+
+/// The article for `entry`: its web page, or when that can't be fetched or
+/// read, the content the feed carries.
+pub async fn entry_article(
+    client: &reqwest::Client,
+    entry: &ParsedEntry,
+    feed_url: &Url,
+) -> Result<(Article, Vec<article::ImageData>), String> {
+    let base = entry
+        .url
+        .as_deref()
+        .and_then(|url| feed_url.join(url).ok())
+        .unwrap_or_else(|| feed_url.clone());
+    let from_page = if entry.url.is_some() {
+        match article::fetch_page(client, base.as_str()).await {
+            Ok((html, page_url)) => article::extract(&html, &page_url),
+            Err(e) => Err(e),
+        }
+    } else {
+        Err("The entry has no link".to_string())
+    };
+    let mut page = match (from_page, &entry.content) {
+        (Ok(page), _) => page,
+        (Err(e), Some(content)) => {
+            log::info!("Using the feed's copy of {:?}: {e}", entry.title);
+            let mut page =
+                article::extract(&format!("<html><body>{content}</body></html>"), &base)?;
+            page.url = base.to_string();
+            page
+        }
+        (Err(e), None) => return Err(e),
+    };
+    if !entry.title.is_empty() {
+        page.title = entry.title.clone();
+    }
+    let images = article::fetch_images(client, &mut page).await;
+    Ok((page, images))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rss_dates_are_optional() {
+        let feed = parse(
+            r#"<rss version="2.0"><channel><title>Blog</title>
+                <item><title>Dated</title><guid>a</guid>
+                    <pubDate>Tue, 10 Jun 2025 09:30:00 +0200</pubDate></item>
+                <item><title>Undated</title><guid>b</guid></item>
+                <item><title>Garbled</title><guid>c</guid><pubDate>last week</pubDate></item>
+            </channel></rss>"#,
+        )
+        .unwrap();
+        let dates: Vec<Option<&str>> = feed
+            .entries
+            .iter()
+            .map(|e| e.published_at.as_deref())
+            .collect();
+        assert_eq!(dates, [Some("2025-06-10 07:30:00"), None, None]);
+    }
+
+    #[test]
+    fn relative_links_resolve_against_the_feed() {
+        let mut feed = parse(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Notes</title>
+                <link href="/"/>
+                <entry><id>urn:1</id><title>One</title><link href="posts/one"/></entry>
+                <entry><id>urn:2</id><title>Two</title>
+                    <link href="https://elsewhere.example/two"/></entry>
+            </feed>"#,
+        )
+        .unwrap();
+        feed.resolve_links(&Url::parse("https://example.com/blog/atom.xml").unwrap());
+        assert_eq!(feed.site_url.as_deref(), Some("https://example.com/"));
+        let urls: Vec<Option<&str>> = feed.entries.iter().map(|e| e.url.as_deref()).collect();
+        assert_eq!(
+            urls,
+            [
+                Some("https://example.com/blog/posts/one"),
+                Some("https://elsewhere.example/two")
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_entries_are_listed_once() {
+        let feed = parse(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Notes</title>
+                <entry><id>urn:1</id><title>First copy</title>
+                    <updated>2025-06-10T09:30:00Z</updated></entry>
+                <entry><id>urn:1</id><title>Second copy</title></entry>
+                <entry><title>No id</title><link href="/three"/></entry>
+            </feed>"#,
+        )
+        .unwrap();
+        let entries: Vec<(&str, &str)> = feed
+            .entries
+            .iter()
+            .map(|e| (e.guid.as_str(), e.title.as_str()))
+            .collect();
+        assert_eq!(entries, [("urn:1", "First copy"), ("/three", "No id")]);
+        assert_eq!(
+            feed.entries[0].published_at.as_deref(),
+            Some("2025-06-10 09:30:00")
+        );
+    }
+}
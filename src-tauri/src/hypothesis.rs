@@ -0,0 +1,314 @@
+//! One-way sync of highlights to Hypothes.is as annotations.
+//!
+//! The API token lives in the keychain under the `hypothesis` integration
+//! (`token`), with an optional `group` ID to post into; without one,
+//! annotations are private to the token's user. The annotation ID of each
+//! highlight is remembered, so edits update the same annotation and deleted
+//! highlights delete theirs.
+//!
+//! Books are identified by `urn:isbn:` when they have an ISBN and by a hash
+//! of title and author otherwise; saved articles use the page they came from,
+//! so their annotations also show up on the web. Each highlight targets its
+//! text (a `TextQuoteSelector`) and, in books, its EPUB CFI (a
+//! `FragmentSelector`).
+
+use crate::embeddings::fnv1a;
+use crate::{run_blocking, DbState};
+use reqwest::{Method, StatusCode};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const API: &str = "https://api.hypothes.is/api";
+
+const CFI_SPEC: &str = "http://www.idpf.org/epub/linking/cfi/epub-cfi.html";
+
+/// Tag added to every annotation, to find them again on Hypothes.is.
+const TAG: &str = "tumelog";
+
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HypothesisSyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+/// A highlight created or edited since it was last pushed.
+struct Pending {
+    highlight_id: i64,
+    annotation_id: Option<String>,
+    updated_at: String,
+    uri: String,
+    title: String,
+    text: String,
+    notes: String,
+    /// `None` for articles, whose CFIs mean nothing on the web page.
+    cfi: Option<String>,
+}
+
+struct Client {
+    http: reqwest::Client,
+    token: String,
+}
+
+impl Client {
+    /// Sends a request, waiting out rate limits. `Ok(None)` means the
+    /// annotation wasn't found (deleted on Hypothes.is).
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Option<Value>, String> {
+        for _ in 0..MAX_ATTEMPTS {
+            let mut request = self
+                .http
+                .request(method.clone(), format!("{API}{path}"))
+                .bearer_auth(&self.token)
+                .header(reqwest::header::ACCEPT, "application/json");
+            if let Some(body) = body {
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(body).map_err(|e| e.to_string())?);
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let wait = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(5);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if status == StatusCode::UNAUTHORIZED {
+                return Err("Hypothes.is rejected the API token".to_string());
+            }
+            let text = response.text().await.map_err(|e| e.to_string())?;
+            if !status.is_success() {
+                let reason = serde_json::from_str::<Value>(&text)
+                    .ok()
+                    .and_then(|v| v["reason"].as_str().map(str::to_string))
+                    .unwrap_or(text);
+                return Err(format!("Hypothes.is request failed ({status}): {reason}"));
+            }
+            return serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| e.to_string());
+        }
+        Err("Hypothes.is kept rate-limiting requests; try again later".to_string())
+    }
+
+    /// The `acct:` ID of the token's user.
+    async fn user_id(&self) -> Result<String, String> {
+        let profile = self
+            .send(Method::GET, "/profile", None)
+            .await?
+            .ok_or("Hypothes.is profile not found")?;
+        profile["userid"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| "Hypothes.is rejected the API token".to_string())
+    }
+}
+
+/// The URI annotations on a book are attached to.
+fn book_uri(
+    title: &str,
+    author: Option<&str>,
+    isbn: Option<&str>,
+    source_url: Option<&str>,
+) -> String {
+    if let Some(url) = source_url {
+        return url.to_string();
+    }
+    if let Some(isbn) = isbn
+        .map(|i| {
+            i.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+        })
+        .filter(|i| !i.is_empty())
+    {
+        return format!("urn:isbn:{isbn}");
+    }
+    let key = format!("{title}\u{1f}{}", author.unwrap_or_default());
+    format!("urn:x-tumelog:book:{:016x}", fnv1a(key.as_bytes()))
+}
+
+fn annotation(pending: &Pending, user_id: &str, group: Option<&str>) -> Value {
+    let mut selectors = vec![json!({ "type": "TextQuoteSelector", "exact": pending.text })];
+    if let Some(cfi) = &pending.cfi {
+        selectors.push(json!({ "type": "FragmentSelector", "conformsTo": CFI_SPEC, "value": cfi }));
+    }
+    let read = match group {
+        Some(group) => format!("group:{group}"),
+        None => user_id.to_string(),
+    };
+    json!({
+        "uri": pending.uri,
+        "document": { "title": [pending.title] },
+        "text": pending.notes,
+        "tags": [TAG],
+        "group": group.unwrap_or("__world__"),
+        "permissions": {
+            "read": [read],
+            "update": [user_id],
+            "delete": [user_id],
+            "admin": [user_id],
+        },
+        "target": [{ "source": pending.uri, "selector": selectors }],
+    })
+}
+
+/// What a sync has to do: highlights to push, and the annotations of
+/// highlights since deleted.
+struct Changes {
+    pending: Vec<Pending>,
+    deleted: Vec<(i64, String)>,
+}
+
+fn load_changes(db: &DbState) -> Result<Changes, String> {
+    let conn = db.conn()?;
+    let pending = conn
+        .prepare(
+            "SELECT h.id, a.annotation_id, h.book_title, b.author, b.isbn, b.source_url,
+                    h.text, h.notes, h.cfi, h.updated_at
+             FROM highlights h
+             LEFT JOIN books b ON b.title = h.book_title
+             LEFT JOIN hypothesis_annotations a ON a.highlight_id = h.id
+             WHERE a.highlight_id IS NULL OR h.updated_at > a.updated_at
+             ORDER BY h.id",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| {
+            let title: String = row.get(2)?;
+            let author: Option<String> = row.get(3)?;
+            let isbn: Option<String> = row.get(4)?;
+            let source_url: Option<String> = row.get(5)?;
+            Ok(Pending {
+                highlight_id: row.get(0)?,
+                annotation_id: row.get(1)?,
+                updated_at: row.get(9)?,
+                uri: book_uri(
+                    &title,
+                    author.as_deref(),
+                    isbn.as_deref(),
+                    source_url.as_deref(),
+                ),
+                cfi: source_url.is_none().then(|| row.get(8)).transpose()?,
+                title,
+                text: row.get(6)?,
+                notes: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    let deleted = conn
+        .prepare(
+            "SELECT highlight_id, annotation_id FROM hypothesis_annotations
+             WHERE highlight_id NOT IN (SELECT id FROM highlights)",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    Ok(Changes { pending, deleted })
+}
+
+/// Creates or updates an annotation for every highlight changed since the
+/// last sync, and deletes those of deleted highlights.
+pub async fn sync(
+    db: DbState,
+    token: String,
+    group: Option<String>,
+) -> Result<HypothesisSyncReport, String> {
+    let client = Client {
+        http: reqwest::Client::new(),
+        token,
+    };
+    let user_id = client.user_id().await?;
+    let Changes { pending, deleted } = run_blocking({
+        let db = db.clone();
+        move || load_changes(&db)
+    })
+    .await?;
+
+    let mut report = HypothesisSyncReport::default();
+    for highlight in pending {
+        let body = annotation(&highlight, &user_id, group.as_deref());
+        let updated = match &highlight.annotation_id {
+            Some(id) => client
+                .send(Method::PATCH, &format!("/annotations/{id}"), Some(&body))
+                .await?
+                .is_some(),
+            None => false,
+        };
+        let annotation_id = if updated {
+            report.updated += 1;
+            highlight.annotation_id.clone().unwrap_or_default()
+        } else {
+            let created = client
+                .send(Method::POST, "/annotations", Some(&body))
+                .await?
+                .ok_or("Hypothes.is API not found")?;
+            report.created += 1;
+            created["id"]
+                .as_str()
+                .ok_or("Hypothes.is returned an annotation without an ID")?
+                .to_string()
+        };
+
+        // Record each one as it's pushed, so an interrupted sync doesn't
+        // post duplicates when run again.
+        let db = db.clone();
+        let (highlight_id, updated_at) = (highlight.highlight_id, highlight.updated_at);
+        run_blocking(move || {
+            let conn = db.conn()?;
+            conn.execute(
+                "INSERT INTO hypothesis_annotations (highlight_id, annotation_id, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(highlight_id) DO UPDATE SET
+                     annotation_id = excluded.annotation_id, updated_at = excluded.updated_at",
+                params![highlight_id, annotation_id, updated_at],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await?;
+    }
+
+    for (highlight_id, annotation_id) in deleted {
+        // Already gone on Hypothes.is counts as deleted too.
+        client
+            .send(
+                Method::DELETE,
+                &format!("/annotations/{annotation_id}"),
+                None,
+            )
+            .await?;
+        report.deleted += 1;
+        let db = db.clone();
+        run_blocking(move || {
+            let conn = db.conn()?;
+            conn.execute(
+                "DELETE FROM hypothesis_annotations WHERE highlight_id = ?1",
+                params![highlight_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await?;
+    }
+    Ok(report)
+}
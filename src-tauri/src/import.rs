@@ -0,0 +1,382 @@
+//! Adding book files to the library: format detection, conversion to EPUB,
+//! metadata extraction and storage. Also reads Calibre libraries.
+
+use crate::db::{book_from_row, book_tags, compress_books, store_chapters, BOOK_COLUMNS};
+use crate::models::BookMetadata;
+use crate::{authors, convert, epub, integrity, language, reading_time, storage};
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+
+/// Storage format inferred from the file extension. Anything that isn't a PDF
+/// or a MOBI is treated as an EPUB, which is all the library accepted before.
+pub fn book_format(filename: &str) -> &'static str {
+    let lower = filename.to_lowercase();
+    if lower.ends_with(".pdf") {
+        "pdf"
+    } else if [".mobi", ".azw", ".prc"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+    {
+        "mobi"
+    } else {
+        "epub"
+    }
+}
+
+/// Whether books of `format` are stored as EPUB files. Saved web articles
+/// are.
+pub fn is_epub(format: &str) -> bool {
+    matches!(format, "epub" | crate::article::FORMAT)
+}
+
+/// Converts FB2 and MOBI imports to EPUB, telling `progress` about each
+/// conversion stage. Returns `None` for files that are stored as-is.
+pub fn convert_on_import(
+    filename: &str,
+    data: &[u8],
+    progress: &mut dyn FnMut(&str, f64),
+) -> Result<Option<convert::Converted>, String> {
+    let lower = filename.to_lowercase();
+    if lower.ends_with(".fb2") {
+        convert::fb2_to_epub(data, progress).map(Some)
+    } else if book_format(filename) == "mobi" {
+        let converted = convert::mobi_to_epub(data, progress)?;
+        if converted.is_none() {
+            log::warn!("{filename} uses HUFF/CDIC compression; storing it unconverted");
+        }
+        Ok(converted)
+    } else {
+        Ok(None)
+    }
+}
+
+/// A book file to add to the library.
+pub struct NewBook {
+    pub title: String,
+    pub filename: String,
+    /// A `data:` URL; read from the file when `None`.
+    pub cover: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Stores a book file in `books_dir` and adds it to the database. The book
+/// is returned as it is if it's already in the library; a different book
+/// with the same title is an error.
+///
+/// `progress` is told about each stage as `(stage, fraction done)`; an error
+/// from it aborts the import, which is how background imports are cancelled.
+/// Nothing is written before the last stage that can be aborted.
+/// `conversion` is told about the stages of converting FB2 and MOBI files.
+pub fn import_book(
+    conn: &Connection,
+    books_dir: &Path,
+    book: NewBook,
+    progress: &mut dyn FnMut(&str, f64) -> Result<(), String>,
+    conversion: &mut dyn FnMut(&str, f64),
+) -> Result<BookMetadata, String> {
+    let NewBook {
+        mut title,
+        mut filename,
+        mut cover,
+        mut data,
+    } = book;
+    std::fs::create_dir_all(books_dir).map_err(|e| e.to_string())?;
+
+    let mut author = None;
+    let mut page_count = None;
+
+    progress("converting", 0.1)?;
+    if let Some(converted) = convert_on_import(&filename, &data, conversion)? {
+        filename = std::path::Path::new(&filename)
+            .with_extension("epub")
+            .to_string_lossy()
+            .into_owned();
+        if let Some(converted_title) = converted.title.filter(|t| !t.trim().is_empty()) {
+            title = converted_title.trim().to_string();
+        }
+        author = converted.author;
+        if cover.is_none() {
+            cover = converted.cover.map(|(mime, bytes)| {
+                format!(
+                    "data:{mime};base64,{}",
+                    base64::engine::general_purpose::STANDARD.encode(bytes)
+                )
+            });
+        }
+        data = converted.epub;
+    }
+
+    progress("metadata", 0.5)?;
+    let format = book_format(&filename);
+    let mut authors: Vec<String> = Vec::new();
+    let mut metadata = epub::Metadata::default();
+    if format == "pdf" {
+        match lopdf::Document::load_metadata_mem(&data) {
+            Ok(meta) => {
+                // Many PDFs carry titles like "untitled" or "Microsoft Word -
+                // Document1", so theirs only stands in for a missing one.
+                if title.trim().is_empty() {
+                    if let Some(pdf_title) = meta.title.filter(|t| !t.trim().is_empty()) {
+                        title = pdf_title.trim().to_string();
+                    }
+                }
+                author = meta.author.filter(|a| !a.trim().is_empty());
+                page_count = Some(meta.page_count as i64);
+            }
+            Err(e) => log::warn!("Could not read metadata of {filename}: {e}"),
+        }
+    } else if format == "epub" {
+        match epub::Epub::from_reader(std::io::Cursor::new(&data)) {
+            Ok(mut epub) => {
+                if cover.is_none() {
+                    progress("cover", 0.6)?;
+                    cover = epub.cover().map(|(mime, bytes)| {
+                        format!(
+                            "data:{mime};base64,{}",
+                            base64::engine::general_purpose::STANDARD.encode(bytes)
+                        )
+                    });
+                }
+                metadata = epub.metadata;
+            }
+            Err(e) => log::warn!("Could not read metadata of {title}: {e}"),
+        }
+        if !metadata.authors.is_empty() {
+            authors = std::mem::take(&mut metadata.authors);
+            author = Some(authors.join(", "));
+        }
+    }
+    if authors.is_empty() {
+        authors.extend(author.clone());
+    }
+    if title.trim().is_empty() {
+        title = Path::new(&filename).file_stem().map_or_else(
+            || filename.clone(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+    }
+
+    let content_hash = integrity::hash_bytes(&data);
+    // A book whose title differs only in case or accents has the same title.
+    let existing: Option<(i64, String, Option<String>)> = conn
+        .query_row(
+            "SELECT id, filename, content_hash FROM books WHERE title = ?1 COLLATE fold",
+            params![title],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some((id, existing_filename, existing_hash)) = existing {
+        let same_book = match existing_hash {
+            Some(hash) => hash == content_hash,
+            None => existing_filename == filename,
+        };
+        if !same_book {
+            return Err(format!(
+                "Another book titled \"{title}\" is already in the library"
+            ));
+        }
+        return conn
+            .query_row(
+                &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+                params![id],
+                book_from_row,
+            )
+            .map_err(|e| e.to_string());
+    }
+
+    progress("saving", 0.7)?;
+    let file_path = books_dir.join(&filename);
+    storage::write(&file_path, &data, compress_books(conn)?).map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "INSERT INTO books (title, filename, cover, format, author, page_count, series, series_index, publisher, year, isbn, content_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            title,
+            filename,
+            cover,
+            format,
+            author,
+            page_count,
+            metadata.series,
+            metadata.series_index,
+            metadata.publisher,
+            metadata.year,
+            metadata.isbn,
+            content_hash
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut book = conn
+        .query_row(
+            &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+            params![conn.last_insert_rowid()],
+            book_from_row,
+        )
+        .map_err(|e| e.to_string())?;
+
+    if format == "epub" {
+        if let Err(e) = store_chapters(conn, book.id, &file_path) {
+            log::warn!("Could not read chapters of {}: {e}", book.title);
+        }
+    }
+    match reading_time::book_words(&file_path, format, page_count) {
+        Ok(Some(words)) => {
+            reading_time::store(conn, book.id, words).map_err(|e| e.to_string())?;
+            book.word_count = Some(words);
+            book.reading_minutes = Some(reading_time::minutes(words, reading_time::DEFAULT_WPM));
+        }
+        Ok(None) => {}
+        Err(e) => log::warn!("Could not count the words of {}: {e}", book.title),
+    }
+    match language::book_language(&file_path, format) {
+        Ok(detected) => {
+            language::store(conn, book.id, detected.as_deref()).map_err(|e| e.to_string())?;
+            book.language = detected;
+        }
+        Err(e) => log::warn!("Could not detect the language of {}: {e}", book.title),
+    }
+    authors::set_authors(conn, book.id, &authors).map_err(|e| e.to_string())?;
+    book_tags::add(conn, book.id, &metadata.subjects).map_err(|e| e.to_string())?;
+    authors::set_series(
+        conn,
+        book.id,
+        metadata.series.as_deref(),
+        metadata.series_index,
+    )
+    .map_err(|e| e.to_string())?;
+
+    Ok(book)
+}
+
+pub struct CalibreBook {
+    pub title: String,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub path: String,
+    pub epub_name: String,
+    pub has_cover: bool,
+    pub tags: Vec<String>,
+}
+
+pub fn read_calibre_books(calibre: &Connection) -> rusqlite::Result<Vec<CalibreBook>> {
+    let mut stmt = calibre.prepare(
+        "SELECT b.id, b.title, b.path, b.has_cover, b.series_index, d.name,
+                (SELECT group_concat(a.name, ' & ')
+                   FROM books_authors_link bal JOIN authors a ON a.id = bal.author
+                  WHERE bal.book = b.id),
+                (SELECT s.name
+                   FROM books_series_link bsl JOIN series s ON s.id = bsl.series
+                  WHERE bsl.book = b.id)
+         FROM books b
+         INNER JOIN data d ON d.book = b.id AND upper(d.format) = 'EPUB'
+         ORDER BY b.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let id: i64 = row.get(0)?;
+        let series: Option<String> = row.get(7)?;
+        Ok((
+            id,
+            CalibreBook {
+                title: row.get(1)?,
+                path: row.get(2)?,
+                has_cover: row.get(3)?,
+                // Calibre stores series_index = 1.0 even for books without a series
+                series_index: if series.is_some() { row.get(4)? } else { None },
+                epub_name: row.get(5)?,
+                author: row.get(6)?,
+                series,
+                tags: Vec::new(),
+            },
+        ))
+    })?;
+
+    let mut tag_stmt = calibre.prepare(
+        "SELECT t.name FROM books_tags_link btl JOIN tags t ON t.id = btl.tag
+         WHERE btl.book = ?1 ORDER BY t.name",
+    )?;
+    let mut books = Vec::new();
+    for row in rows {
+        let (id, mut book) = row?;
+        book.tags = tag_stmt
+            .query_map(params![id], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        books.push(book);
+    }
+    Ok(books)
+}
+
+/// The library entry for the file at `path`, importing it first if no book
+/// was imported from a file of that name; `true` along with it if it was
+/// imported. `progress` and `conversion` are as for [`import_book`].
+pub fn import_file(
+    conn: &Connection,
+    books_dir: &Path,
+    path: &Path,
+    progress: &mut dyn FnMut(&str, f64) -> Result<(), String>,
+    conversion: &mut dyn FnMut(&str, f64),
+) -> Result<(BookMetadata, bool), String> {
+    let filename = path
+        .file_name()
+        .ok_or("Not a file")?
+        .to_string_lossy()
+        .into_owned();
+    let converted_filename = Path::new(&filename)
+        .with_extension("epub")
+        .to_string_lossy()
+        .into_owned();
+    let existing = conn
+        .query_row(
+            &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.filename IN (?1, ?2)"),
+            params![filename, converted_filename],
+            book_from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(book) = existing {
+        return Ok((book, false));
+    }
+
+    progress("reading", 0.0)?;
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let mut title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| filename.clone());
+    if filename.to_lowercase().ends_with(".epub") {
+        if let Ok(epub) = epub::Epub::from_reader(std::io::Cursor::new(&data)) {
+            if let Some(epub_title) = epub.metadata.title {
+                title = epub_title;
+            }
+        }
+    }
+    let book = NewBook {
+        title,
+        filename,
+        cover: None,
+        data,
+    };
+    let book = import_book(conn, books_dir, book, progress, conversion)?;
+    Ok((book, true))
+}
+
+/// Extensions the app registers for and accepts as command-line arguments.
+pub const OPENABLE_EXTENSIONS: &[&str] = &["epub", "pdf", "fb2", "mobi", "azw", "prc"];
+
+/// Book files among command-line arguments, resolved against `cwd`.
+pub fn openable_paths(args: &[String], cwd: &std::path::Path) -> Vec<std::path::PathBuf> {
+    args.iter()
+        .map(|arg| cwd.join(arg))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| OPENABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect()
+}
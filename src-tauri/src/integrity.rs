@@ -0,0 +1,35 @@
+//! Content hashes of book files, to notice when a stored file changes behind
+//! the library's back: disk corruption, a cloud sync conflict copy written
+//! over it, or another app editing it in place.
+//!
+//! Hashes are SHA-256 of the file's contents as imported, in lowercase hex;
+//! compressing the stored file doesn't change them. Replacing a book's file
+//! through the app records the new file's hash.
+
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+pub fn hash_bytes(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+/// Hashes the contents of the book file at `path`, reading plain files in
+/// chunks rather than all at once.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = crate::storage::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,213 @@
+//! The language a book is written in, as an ISO 639-1 code ("en", "fr"),
+//! for filtering and per-language reading stats.
+//!
+//! A sample of the book's text is run through language detection. The
+//! language its metadata declares is only used when detection isn't sure:
+//! converters and authoring tools often declare a default ("en") whatever
+//! the book is written in.
+
+use crate::epub::Epub;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use whatlang::Lang;
+
+/// Bytes of text detection looks at. More only slows it down.
+const SAMPLE_BYTES: usize = 20_000;
+
+/// Detectable languages with their ISO 639-1 codes.
+const CODES: &[(Lang, &str)] = &[
+    (Lang::Afr, "af"),
+    (Lang::Aka, "ak"),
+    (Lang::Amh, "am"),
+    (Lang::Ara, "ar"),
+    (Lang::Aze, "az"),
+    (Lang::Bel, "be"),
+    (Lang::Ben, "bn"),
+    (Lang::Bul, "bg"),
+    (Lang::Cat, "ca"),
+    (Lang::Ces, "cs"),
+    (Lang::Cmn, "zh"),
+    (Lang::Dan, "da"),
+    (Lang::Deu, "de"),
+    (Lang::Ell, "el"),
+    (Lang::Eng, "en"),
+    (Lang::Epo, "eo"),
+    (Lang::Est, "et"),
+    (Lang::Fin, "fi"),
+    (Lang::Fra, "fr"),
+    (Lang::Guj, "gu"),
+    (Lang::Heb, "he"),
+    (Lang::Hin, "hi"),
+    (Lang::Hrv, "hr"),
+    (Lang::Hun, "hu"),
+    (Lang::Hye, "hy"),
+    (Lang::Ind, "id"),
+    (Lang::Ita, "it"),
+    (Lang::Jav, "jv"),
+    (Lang::Jpn, "ja"),
+    (Lang::Kan, "kn"),
+    (Lang::Kat, "ka"),
+    (Lang::Khm, "km"),
+    (Lang::Kor, "ko"),
+    (Lang::Lat, "la"),
+    (Lang::Lav, "lv"),
+    (Lang::Lit, "lt"),
+    (Lang::Mal, "ml"),
+    (Lang::Mar, "mr"),
+    (Lang::Mkd, "mk"),
+    (Lang::Mya, "my"),
+    (Lang::Nep, "ne"),
+    (Lang::Nld, "nl"),
+    (Lang::Nob, "nb"),
+    (Lang::Ori, "or"),
+    (Lang::Pan, "pa"),
+    (Lang::Pes, "fa"),
+    (Lang::Pol, "pl"),
+    (Lang::Por, "pt"),
+    (Lang::Ron, "ro"),
+    (Lang::Rus, "ru"),
+    (Lang::Sin, "si"),
+    (Lang::Slk, "sk"),
+    (Lang::Slv, "sl"),
+    (Lang::Sna, "sn"),
+    (Lang::Spa, "es"),
+    (Lang::Srp, "sr"),
+    (Lang::Swe, "sv"),
+    (Lang::Tam, "ta"),
+    (Lang::Tel, "te"),
+    (Lang::Tgl, "tl"),
+    (Lang::Tha, "th"),
+    (Lang::Tuk, "tk"),
+    (Lang::Tur, "tr"),
+    (Lang::Ukr, "uk"),
+    (Lang::Urd, "ur"),
+    (Lang::Uzb, "uz"),
+    (Lang::Vie, "vi"),
+    (Lang::Yid, "yi"),
+    (Lang::Zul, "zu"),
+];
+
+/// Three-letter codes whatlang doesn't use: the ISO 639-2 bibliographic
+/// codes older metadata has, and macrolanguages.
+const OTHER_CODES: &[(&str, &str)] = &[
+    ("arm", "hy"),
+    ("bur", "my"),
+    ("chi", "zh"),
+    ("cze", "cs"),
+    ("dut", "nl"),
+    ("fre", "fr"),
+    ("geo", "ka"),
+    ("ger", "de"),
+    ("gre", "el"),
+    ("mac", "mk"),
+    ("per", "fa"),
+    ("rum", "ro"),
+    ("slo", "sk"),
+    ("fas", "fa"),
+    ("nor", "no"),
+    ("zho", "zh"),
+];
+
+/// A declared language ("en-US", "fre", "eng") as an ISO 639-1 code, or
+/// `None` if it isn't a language code ("und", "English").
+pub fn normalize(code: &str) -> Option<String> {
+    let primary = code
+        .trim()
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !primary.chars().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+    match primary.len() {
+        2 => Some(primary),
+        3 => CODES
+            .iter()
+            .find(|(lang, _)| lang.code() == primary)
+            .map(|(_, code)| *code)
+            .or_else(|| {
+                OTHER_CODES
+                    .iter()
+                    .find(|(other, _)| *other == primary)
+                    .map(|(_, code)| *code)
+            })
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+/// The language `text` is written in, if detection is confident of it.
+pub fn detect(text: &str) -> Option<String> {
+    let info = whatlang::detect(text).filter(|info| info.is_reliable())?;
+    CODES
+        .iter()
+        .find(|(lang, _)| *lang == info.lang())
+        .map(|(_, code)| code.to_string())
+}
+
+/// Indexes `0..len` starting a quarter of the way in, so front matter (often
+/// a copyright page in another language) is looked at last.
+fn sample_order(len: usize) -> impl Iterator<Item = usize> {
+    let start = len / 4;
+    (start..len).chain(0..start)
+}
+
+/// Appends `text` to `sample`. Returns whether the sample is long enough.
+fn push(sample: &mut String, text: &str) -> bool {
+    sample.push_str(text);
+    sample.push('\n');
+    sample.len() >= SAMPLE_BYTES
+}
+
+fn epub_sample(path: &Path) -> Result<(Option<String>, String), String> {
+    let mut epub = Epub::open(path)?;
+    let mut sample = String::new();
+    for index in sample_order(epub.spine.len()) {
+        match epub.chapter_text(index) {
+            Ok(chapter) if push(&mut sample, &chapter.text) => break,
+            Ok(_) => {}
+            Err(e) => log::warn!("Could not read chapter {index}: {e}"),
+        }
+    }
+    Ok((epub.metadata.language.clone(), sample))
+}
+
+fn pdf_sample(path: &Path) -> Result<(Option<String>, String), String> {
+    let data = crate::storage::read(path).map_err(|e| e.to_string())?;
+    let document = lopdf::Document::load_mem(&data).map_err(|e| e.to_string())?;
+    let declared = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Lang"))
+        .and_then(|lang| lang.as_str())
+        .ok()
+        .map(|lang| String::from_utf8_lossy(lang).into_owned());
+    let pages: Vec<u32> = document.get_pages().into_keys().collect();
+    let mut sample = String::new();
+    for index in sample_order(pages.len()) {
+        if let Ok(text) = document.extract_text(&[pages[index]]) {
+            if push(&mut sample, &text) {
+                break;
+            }
+        }
+    }
+    Ok((declared, sample))
+}
+
+/// The language of the book at `path`, from its text or else its metadata.
+pub fn book_language(path: &Path, format: &str) -> Result<Option<String>, String> {
+    let (declared, sample) = match format {
+        format if crate::import::is_epub(format) => epub_sample(path)?,
+        "pdf" => pdf_sample(path)?,
+        _ => return Ok(None),
+    };
+    Ok(detect(&sample).or_else(|| declared.as_deref().and_then(normalize)))
+}
+
+pub fn store(conn: &Connection, book_id: i64, language: Option<&str>) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET language = ?1 WHERE id = ?2",
+        params![language, book_id],
+    )?;
+    Ok(())
+}
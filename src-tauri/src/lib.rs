@@ -1,718 +1,533 @@
-use rusqlite::{params, Connection};
-use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
-use tauri::Manager;
-
-// ---------------------------------------------------------------------------
-// Data types
-// ---------------------------------------------------------------------------
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Highlight {
-    pub id: i64,
-    pub book_title: String,
-    pub cfi: String,
-    pub text: String,
-    pub color: String,
-    pub notes: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Bookmark {
-    pub id: i64,
-    pub book_title: String,
-    pub cfi: String,
-    pub label: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Collection {
-    pub id: i64,
-    pub name: String,
-    pub emoji: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct BookMetadata {
-    pub id: i64,
-    pub title: String,
-    pub filename: String,
-    pub last_cfi: String,
-    pub cover: Option<String>,
-    pub locations_data: Option<String>,
-    pub last_percentage: f64,
-    pub created_at: String,
-}
-
-pub struct DbState(pub Mutex<Connection>);
-
-// ---------------------------------------------------------------------------
-// Database helpers
-// ---------------------------------------------------------------------------
-
-fn init_db(conn: &Connection) {
-    conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS highlights (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            book_title  TEXT    NOT NULL,
-            cfi         TEXT    NOT NULL,
-            text        TEXT    NOT NULL,
-            color       TEXT    NOT NULL DEFAULT '#facc15',
-            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
-        );
-        CREATE TABLE IF NOT EXISTS books (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            title       TEXT    NOT NULL UNIQUE,
-            filename    TEXT    NOT NULL,
-            last_cfi    TEXT    NOT NULL DEFAULT '',
-            cover       TEXT,
-            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
-        );
-        CREATE TABLE IF NOT EXISTS bookmarks (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            book_title  TEXT    NOT NULL,
-            cfi         TEXT    NOT NULL,
-            label       TEXT    NOT NULL,
-            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
-        );",
-    )
-    .expect("Failed to initialize database");
-
-    // Simple migration: ensure columns exist in highlights
-    let _ = conn.execute(
-        "ALTER TABLE highlights ADD COLUMN color TEXT NOT NULL DEFAULT '#facc15'",
-        [],
-    );
-    let _ = conn.execute(
-        "ALTER TABLE highlights ADD COLUMN created_at TEXT NOT NULL DEFAULT (datetime('now'))",
-        [],
-    );
-    let _ = conn.execute(
-        "ALTER TABLE highlights ADD COLUMN notes TEXT NOT NULL DEFAULT ''",
-        [],
-    );
-
-    // Migration: add locations_data column to books table
-    let _ = conn.execute("ALTER TABLE books ADD COLUMN locations_data TEXT", []);
-
-    // Migration: add last_percentage column to books table
-    let _ = conn.execute(
-        "ALTER TABLE books ADD COLUMN last_percentage REAL NOT NULL DEFAULT 0.0",
-        [],
-    );
-
-    // Collections tables
-    conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS collections (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            name        TEXT    NOT NULL UNIQUE,
-            emoji       TEXT    NOT NULL DEFAULT '📌',
-            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
-        );
-        CREATE TABLE IF NOT EXISTS highlight_collections (
-            highlight_id   INTEGER NOT NULL,
-            collection_id  INTEGER NOT NULL,
-            PRIMARY KEY (highlight_id, collection_id),
-            FOREIGN KEY (highlight_id) REFERENCES highlights(id) ON DELETE CASCADE,
-            FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
-        );",
-    )
-    .expect("Failed to create collections tables");
-}
-
-// ---------------------------------------------------------------------------
-// Tauri commands
-// ---------------------------------------------------------------------------
-
-#[tauri::command]
-fn add_book(
-    app: tauri::AppHandle,
-    state: tauri::State<DbState>,
-    title: String,
-    filename: String,
-    cover: Option<String>,
-    data: Vec<u8>,
-) -> Result<BookMetadata, String> {
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let books_dir = app_dir.join("books");
-    std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
-
-    let file_path = books_dir.join(&filename);
-    std::fs::write(&file_path, data).map_err(|e| e.to_string())?;
-
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT OR IGNORE INTO books (title, filename, cover) VALUES (?1, ?2, ?3)",
-        params![title, filename, cover],
-    )
-    .map_err(|e| e.to_string())?;
-
-    let book = conn
-        .query_row(
-            "SELECT id, title, filename, last_cfi, cover, locations_data, last_percentage, created_at FROM books WHERE title = ?1",
-            params![title],
-            |row| {
-                Ok(BookMetadata {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    filename: row.get(2)?,
-                    last_cfi: row.get(3)?,
-                    cover: row.get(4)?,
-                    locations_data: row.get(5)?,
-                    last_percentage: row.get(6)?,
-                    created_at: row.get(7)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?;
-
-    Ok(book)
-}
-
-#[tauri::command]
-fn get_all_books(state: tauri::State<DbState>) -> Result<Vec<BookMetadata>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, title, filename, last_cfi, cover, locations_data, last_percentage, created_at FROM books ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(BookMetadata {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                filename: row.get(2)?,
-                last_cfi: row.get(3)?,
-                cover: row.get(4)?,
-                locations_data: row.get(5)?,
-                last_percentage: row.get(6)?,
-                created_at: row.get(7)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut books = Vec::new();
-    for row in rows {
-        books.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(books)
-}
-
-#[tauri::command]
-fn update_book_progress(
-    state: tauri::State<DbState>,
-    title: String,
-    cfi: String,
-    percentage: f64,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE books SET last_cfi = ?1, last_percentage = ?2 WHERE title = ?3",
-        params![cfi, percentage, title],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn update_book_locations(
-    state: tauri::State<DbState>,
-    title: String,
-    locations_data: String,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE books SET locations_data = ?1 WHERE title = ?2",
-        params![locations_data, title],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn get_book_content(app: tauri::AppHandle, filename: String) -> Result<Vec<u8>, String> {
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let file_path = app_dir.join("books").join(filename);
-    std::fs::read(file_path).map_err(|e| e.to_string())
-}
-
-#[tauri::command]
-fn add_highlight(
-    state: tauri::State<DbState>,
-    book_title: String,
-    cfi: String,
-    text: String,
-    color: String,
-    notes: String,
-) -> Result<Highlight, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO highlights (book_title, cfi, text, color, notes) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![book_title, cfi, text, color, notes],
-    )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    let hl = conn
-        .query_row(
-            "SELECT id, book_title, cfi, text, color, notes, created_at FROM highlights WHERE id = ?1",
-            params![id],
-            |row| {
-                Ok(Highlight {
-                    id: row.get(0)?,
-                    book_title: row.get(1)?,
-                    cfi: row.get(2)?,
-                    text: row.get(3)?,
-                    color: row.get(4)?,
-                    notes: row.get(5)?,
-                    created_at: row.get(6)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?;
-
-    Ok(hl)
-}
-
-#[tauri::command]
-fn get_highlights(
-    state: tauri::State<DbState>,
-    book_title: String,
-) -> Result<Vec<Highlight>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, book_title, cfi, text, color, notes, created_at FROM highlights WHERE book_title = ?1 ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map(params![book_title], |row| {
-            Ok(Highlight {
-                id: row.get(0)?,
-                book_title: row.get(1)?,
-                cfi: row.get(2)?,
-                text: row.get(3)?,
-                color: row.get(4)?,
-                notes: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut highlights = Vec::new();
-    for row in rows {
-        highlights.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(highlights)
-}
-
-#[tauri::command]
-fn delete_book(
-    app: tauri::AppHandle,
-    state: tauri::State<DbState>,
-    title: String,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    // 1. Get filename to delete the file later
-    let filename: String = conn
-        .query_row(
-            "SELECT filename FROM books WHERE title = ?1",
-            params![title],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
-
-    // 2. Delete from DB (Cascade-like manual cleanup)
-    conn.execute(
-        "DELETE FROM highlights WHERE book_title = ?1",
-        params![title],
-    )
-    .map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM books WHERE title = ?1", params![title])
-        .map_err(|e| e.to_string())?;
-
-    // 3. Delete the file
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let file_path = app_dir.join("books").join(filename);
-    if file_path.exists() {
-        std::fs::remove_file(file_path).map_err(|e| e.to_string())?;
-    }
-
-    Ok(())
-}
-
-#[tauri::command]
-fn get_all_highlights(state: tauri::State<DbState>) -> Result<Vec<Highlight>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, book_title, cfi, text, color, notes, created_at FROM highlights ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(Highlight {
-                id: row.get(0)?,
-                book_title: row.get(1)?,
-                cfi: row.get(2)?,
-                text: row.get(3)?,
-                color: row.get(4)?,
-                notes: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut highlights = Vec::new();
-    for row in rows {
-        highlights.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(highlights)
-}
-
-#[tauri::command]
-fn update_highlight_notes(
-    state: tauri::State<DbState>,
-    id: i64,
-    notes: String,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE highlights SET notes = ?1 WHERE id = ?2",
-        params![notes, id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn delete_highlight(state: tauri::State<DbState>, id: i64) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM highlights WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn add_bookmark(
-    state: tauri::State<DbState>,
-    book_title: String,
-    cfi: String,
-    label: String,
-) -> Result<Bookmark, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO bookmarks (book_title, cfi, label) VALUES (?1, ?2, ?3)",
-        params![book_title, cfi, label],
-    )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    let bookmark = conn
-        .query_row(
-            "SELECT id, book_title, cfi, label, created_at FROM bookmarks WHERE id = ?1",
-            params![id],
-            |row| {
-                Ok(Bookmark {
-                    id: row.get(0)?,
-                    book_title: row.get(1)?,
-                    cfi: row.get(2)?,
-                    label: row.get(3)?,
-                    created_at: row.get(4)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?;
-
-    Ok(bookmark)
-}
-
-#[tauri::command]
-fn get_bookmarks(
-    state: tauri::State<DbState>,
-    book_title: String,
-) -> Result<Vec<Bookmark>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, book_title, cfi, label, created_at FROM bookmarks WHERE book_title = ?1 ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let rows = stmt
-        .query_map(params![book_title], |row| {
-            Ok(Bookmark {
-                id: row.get(0)?,
-                book_title: row.get(1)?,
-                cfi: row.get(2)?,
-                label: row.get(3)?,
-                created_at: row.get(4)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut bookmarks = Vec::new();
-    for row in rows {
-        bookmarks.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(bookmarks)
-}
-
-#[tauri::command]
-fn delete_bookmark(state: tauri::State<DbState>, id: i64) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM bookmarks WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn wipe_all_data(app: tauri::AppHandle, state: tauri::State<DbState>) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-
-    // 1. Clear DB
-    conn.execute_batch(
-        "DELETE FROM highlights;
-         DELETE FROM books;
-         DELETE FROM bookmarks;
-         VACUUM;",
-    )
-    .map_err(|e| e.to_string())?;
-
-    // 2. Delete all book files
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let books_dir = app_dir.join("books");
-    if books_dir.exists() {
-        std::fs::remove_dir_all(&books_dir).map_err(|e| e.to_string())?;
-        std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
-    }
-
-    Ok(())
-}
-
-// ---------------------------------------------------------------------------
-// Collection commands
-// ---------------------------------------------------------------------------
-
-#[tauri::command]
-fn create_collection(
-    state: tauri::State<DbState>,
-    name: String,
-    emoji: String,
-) -> Result<Collection, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT INTO collections (name, emoji) VALUES (?1, ?2)",
-        params![name, emoji],
-    )
-    .map_err(|e| e.to_string())?;
-    let id = conn.last_insert_rowid();
-    let collection = conn
-        .query_row(
-            "SELECT id, name, emoji, created_at FROM collections WHERE id = ?1",
-            params![id],
-            |row| {
-                Ok(Collection {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    emoji: row.get(2)?,
-                    created_at: row.get(3)?,
-                })
-            },
-        )
-        .map_err(|e| e.to_string())?;
-    Ok(collection)
-}
-
-#[tauri::command]
-fn get_all_collections(state: tauri::State<DbState>) -> Result<Vec<Collection>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare("SELECT id, name, emoji, created_at FROM collections ORDER BY name")
-        .map_err(|e| e.to_string())?;
-    let rows = stmt
-        .query_map([], |row| {
-            Ok(Collection {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                emoji: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-    let mut collections = Vec::new();
-    for r in rows {
-        collections.push(r.map_err(|e| e.to_string())?);
-    }
-    Ok(collections)
-}
-
-#[tauri::command]
-fn delete_collection(state: tauri::State<DbState>, id: i64) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "DELETE FROM highlight_collections WHERE collection_id = ?1",
-        params![id],
-    )
-    .map_err(|e| e.to_string())?;
-    conn.execute("DELETE FROM collections WHERE id = ?1", params![id])
-        .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn add_highlight_to_collection(
-    state: tauri::State<DbState>,
-    highlight_id: i64,
-    collection_id: i64,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id) VALUES (?1, ?2)",
-        params![highlight_id, collection_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn remove_highlight_from_collection(
-    state: tauri::State<DbState>,
-    highlight_id: i64,
-    collection_id: i64,
-) -> Result<(), String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    conn.execute(
-        "DELETE FROM highlight_collections WHERE highlight_id = ?1 AND collection_id = ?2",
-        params![highlight_id, collection_id],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[tauri::command]
-fn get_highlights_by_collection(
-    state: tauri::State<DbState>,
-    collection_id: i64,
-) -> Result<Vec<Highlight>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare(
-            "SELECT h.id, h.book_title, h.cfi, h.text, h.color, h.notes, h.created_at
-             FROM highlights h
-             INNER JOIN highlight_collections hc ON h.id = hc.highlight_id
-             WHERE hc.collection_id = ?1
-             ORDER BY h.created_at DESC",
-        )
-        .map_err(|e| e.to_string())?;
-    let rows = stmt
-        .query_map(params![collection_id], |row| {
-            Ok(Highlight {
-                id: row.get(0)?,
-                book_title: row.get(1)?,
-                cfi: row.get(2)?,
-                text: row.get(3)?,
-                color: row.get(4)?,
-                notes: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-    let mut highlights = Vec::new();
-    for r in rows {
-        highlights.push(r.map_err(|e| e.to_string())?);
-    }
-    Ok(highlights)
-}
-
-#[tauri::command]
-fn get_highlight_collections(
-    state: tauri::State<DbState>,
-    highlight_id: i64,
-) -> Result<Vec<Collection>, String> {
-    let conn = state.0.lock().map_err(|e| e.to_string())?;
-    let mut stmt = conn
-        .prepare(
-            "SELECT c.id, c.name, c.emoji, c.created_at
-             FROM collections c
-             INNER JOIN highlight_collections hc ON c.id = hc.collection_id
-             WHERE hc.highlight_id = ?1
-             ORDER BY c.name",
-        )
-        .map_err(|e| e.to_string())?;
-    let rows = stmt
-        .query_map(params![highlight_id], |row| {
-            Ok(Collection {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                emoji: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-    let mut collections = Vec::new();
-    for r in rows {
-        collections.push(r.map_err(|e| e.to_string())?);
-    }
-    Ok(collections)
-}
-
-// ---------------------------------------------------------------------------
-// App entry
-// ---------------------------------------------------------------------------
+mod app_lock;
+mod article;
+mod attachments;
+mod authors;
+mod automation;
+mod backup;
+mod blog;
+mod book_cache;
+mod cfi;
+mod citation;
+pub mod cli;
+mod commands;
+mod convert;
+mod covers;
+pub mod db;
+mod deep_link;
+mod diagnostics;
+mod dictionary;
+mod digest;
+mod drawings;
+mod email;
+mod embeddings;
+mod encryption;
+mod enrich;
+mod epub;
+mod events;
+mod export;
+mod extract;
+mod feeds;
+mod hypothesis;
+mod import;
+mod integrity;
+mod language;
+mod llm;
+mod markdown;
+mod merge;
+mod migrations;
+pub mod models;
+mod notion;
+mod obsidian;
+mod oplog;
+mod peer;
+mod profiles;
+mod quote_image;
+mod read_later;
+mod reading_time;
+mod readwise;
+mod reanchor;
+mod reminders;
+mod search;
+mod secrets;
+mod server;
+mod site;
+mod smart_collections;
+mod stats;
+mod storage;
+mod streaming;
+mod sync;
+mod templates;
+mod translate;
+mod tts;
+mod undo;
+mod watch_folder;
+mod year_review;
+
+use crate::commands::feeds::FeedRefresh;
+use crate::commands::import::ImportTasks;
+use crate::commands::open::{handle_run_event, open_deep_links, open_paths, OpenedBooks};
+use crate::commands::run_blocking;
+use crate::commands::search::{embed_all_pending, EmbedderState};
+use crate::commands::server::server_settings;
+use crate::commands::sync::SyncState;
+use crate::commands::vocabulary::DictionaryState;
+use crate::commands::watch_folder::WatchFolder;
+use crate::db::{DbState, Library};
+use crate::import::openable_paths;
+use tauri::{Emitter, Manager};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+/// Must match `identifier` in `tauri.conf.json`, which names the app data
+/// folder on desktop. Secrets are kept in the keychain under it too. Android
+/// keeps the old identifier (see `tauri.android.conf.json`), since a new
+/// package would be a different app that can't reach the old one's data.
+pub const APP_IDENTIFIER: &str = "app.tumelog";
+
+/// Identifier of versions before the app had its own, whose data folder and
+/// secrets are moved over.
+const LEGACY_IDENTIFIER: &str = "com.tauri.dev";
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::Builder::default()
+    let builder = tauri::Builder::default();
+    // Must be the first plugin: a second launch forwards its arguments here
+    // and exits before anything else starts.
+    #[cfg(desktop)]
+    let builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.unminimize();
+            let _ = window.set_focus();
+        }
+        open_paths(
+            app,
+            openable_paths(
+                args.get(1..).unwrap_or_default(),
+                &std::path::PathBuf::from(cwd),
+            ),
+        );
+    }));
+    builder
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .plugin(tauri_plugin_notification::init())
+        .register_uri_scheme_protocol(attachments::SCHEME, |ctx, request| {
+            let state = ctx.app_handle().state::<DbState>();
+            let dir = match state.is_app_locked() {
+                Ok(false) => state.dir().ok(),
+                _ => None,
+            };
+            attachments::respond(dir.as_deref(), request.uri().path())
+        })
+        .register_uri_scheme_protocol(book_cache::SCHEME, |ctx, request| {
+            let range = request
+                .headers()
+                .get(tauri::http::header::RANGE)
+                .and_then(|value| value.to_str().ok());
+            book_cache::respond(
+                &ctx.app_handle().state::<DbState>(),
+                request.uri().path(),
+                range,
+            )
+        })
         .setup(|app| {
-            // Open / create the SQLite database in the app data dir
+            // Open / create the active profile's SQLite database
             let app_dir = app
                 .path()
                 .app_data_dir()
                 .expect("failed to resolve app data dir");
+            #[cfg(desktop)]
+            debug_assert_eq!(app.config().identifier, APP_IDENTIFIER);
+            let moved = profiles::move_legacy_app_dir(&app_dir, LEGACY_IDENTIFIER);
             std::fs::create_dir_all(&app_dir).ok();
-            let db_path = app_dir.join("highlights.db");
-            let conn = Connection::open(&db_path).expect("failed to open SQLite database");
-            init_db(&conn);
-            app.manage(DbState(Mutex::new(conn)));
+            app.handle().plugin(diagnostics::log_plugin(&app_dir))?;
+            if let Err(e) = moved {
+                log::warn!("Could not move the library of an earlier version: {e}");
+            }
+            let library = Library::open(&app_dir, &profiles::current(&app_dir))?;
+            let db = DbState::new(library);
+            app.manage(db.clone());
+            app.manage(DictionaryState::default());
+            app.manage(EmbedderState::default());
+            app.manage(SyncState::default());
+            app.manage(tts::TtsState::default());
+            app.manage(OpenedBooks::default());
+            app.manage(ImportTasks::default());
+            app.manage(FeedRefresh::default());
+            app.manage(WatchFolder::default());
+            app.manage(server::ServerState::default());
+            app.manage(peer::Pairing::default());
+
+            match db.conn().and_then(|conn| server_settings(&conn)) {
+                Ok((true, port)) => {
+                    let server = app.state::<server::ServerState>().inner().clone();
+                    let db = db.clone();
+                    let app = app.handle().clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(e) = server.start(&app, db, port).await {
+                            log::warn!("Could not start the HTTP API server: {e}");
+                        }
+                    });
+                }
+                Ok((false, _)) => {}
+                // A locked library starts without the server.
+                Err(e) => log::info!("HTTP API server not started: {e}"),
+            }
 
-            if cfg!(debug_assertions) {
-                app.handle().plugin(
-                    tauri_plugin_log::Builder::default()
-                        .level(log::LevelFilter::Info)
-                        .build(),
-                )?;
+            // Highlights deleted by a sync leave their attachments behind.
+            if let Ok(conn) = db.conn() {
+                commands::highlights::prune_attachments(&db, &conn);
             }
+
+            // A locked library starts watching once it's unlocked.
+            if let Err(e) = commands::watch_folder::restart(app.handle()) {
+                log::info!("Watch folder not started: {e}");
+            }
+
+            let args: Vec<String> = std::env::args().skip(1).collect();
+            let cwd = std::env::current_dir().unwrap_or_default();
+            open_paths(app.handle(), openable_paths(&args, &cwd));
+
+            // Links that launched the app, then any that arrive while it runs
+            // (forwarded by the single-instance plugin on Windows and Linux).
+            #[cfg(any(windows, target_os = "linux"))]
+            if let Err(e) = app.deep_link().register_all() {
+                log::warn!("Could not register the tumelog:// scheme: {e}");
+            }
+            if let Ok(Some(urls)) = app.deep_link().get_current() {
+                open_deep_links(app.handle(), urls);
+            }
+            let handle = app.handle().clone();
+            app.deep_link()
+                .on_open_url(move |event| open_deep_links(&handle, event.urls()));
+
+            // Check hourly whether the daily backup is due; the first tick
+            // fires immediately, so a backup is also taken at startup if needed.
+            let embeddings_db = db.clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+                loop {
+                    interval.tick().await;
+                    let db = db.clone();
+                    let result = run_blocking(move || {
+                        let conn = db.conn()?;
+                        backup::run_scheduled(&conn, &backup::backups_dir(&db.dir()?))
+                    })
+                    .await;
+                    if let Err(e) = result {
+                        log::error!("Scheduled backup failed: {e}");
+                    }
+                }
+            });
+
+            // Check feeds for new entries; each is refreshed at most every
+            // `feeds::REFRESH_MINUTES`.
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(15 * 60));
+                loop {
+                    interval.tick().await;
+                    match commands::feeds::refresh_due(&handle).await {
+                        Ok(report) if report.saved > 0 => {
+                            log::info!("Saved {} articles from feeds", report.saved)
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::error!("Scheduled feed refresh failed: {e}"),
+                    }
+                }
+            });
+
+            // Run scheduled exports: all of them at startup, then daily ones
+            // whenever a day has passed since their last run.
+            let automation_db = embeddings_db.clone();
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+                let mut startup = true;
+                loop {
+                    interval.tick().await;
+                    match automation::run_due(&automation_db, startup).await {
+                        Ok(statuses) => {
+                            for status in statuses {
+                                if let Err(e) = handle.emit("automation://ran", status) {
+                                    log::warn!("Failed to emit automation status: {e}");
+                                }
+                            }
+                        }
+                        Err(e) => log::error!("Scheduled exports failed: {e}"),
+                    }
+                    startup = false;
+                }
+            });
+
+            // Show reading and review reminders when their time of day comes.
+            let reminders_db = embeddings_db.clone();
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    let db = reminders_db.clone();
+                    let handle = handle.clone();
+                    let result = run_blocking(move || {
+                        let conn = db.conn()?;
+                        reminders::send_due(&handle, &conn)
+                    })
+                    .await;
+                    if let Err(e) = result {
+                        log::error!("Sending reminders failed: {e}");
+                    }
+                }
+            });
+
+            // Keep highlight embeddings current for semantic search.
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    let db = embeddings_db.clone();
+                    let app = handle.clone();
+                    let result = run_blocking(move || {
+                        let conn = db.conn()?;
+                        let embedder = app.state::<EmbedderState>();
+                        embed_all_pending(&app, &conn, &embedder)
+                    })
+                    .await;
+                    match result {
+                        Ok(0) => {}
+                        Ok(count) => log::info!("Embedded {count} highlights"),
+                        Err(e) => log::error!("Embedding highlights failed: {e}"),
+                    }
+                }
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
-            add_book,
-            get_all_books,
-            update_book_progress,
-            update_book_locations,
-            get_book_content,
-            add_highlight,
-            get_highlights,
-            get_all_highlights,
-            delete_highlight,
-            delete_book,
-            update_highlight_notes,
-            add_bookmark,
-            get_bookmarks,
-            delete_bookmark,
-            wipe_all_data,
-            create_collection,
-            get_all_collections,
-            delete_collection,
-            add_highlight_to_collection,
-            remove_highlight_from_collection,
-            get_highlights_by_collection,
-            get_highlight_collections
+            commands::books::add_book,
+            commands::books::get_all_books,
+            commands::books::query_books,
+            commands::books::update_book_progress,
+            commands::books::update_book_locations,
+            commands::books::get_book_content,
+            commands::books::extract_book_text,
+            commands::books::open_cached_book,
+            commands::books::get_book_file_url,
+            commands::books::prefetch_resources,
+            commands::books::get_book_cache,
+            commands::books::set_book_cache_limit,
+            commands::books::clear_book_cache,
+            commands::books::get_recent_books,
+            commands::highlights::add_highlight,
+            commands::highlights::add_region_annotation,
+            commands::highlights::update_highlight,
+            commands::highlights::attach_audio_note,
+            commands::highlights::add_attachment,
+            commands::highlights::get_attachments,
+            commands::highlights::delete_attachment,
+            commands::highlights::link_highlights,
+            commands::highlights::unlink_highlights,
+            commands::highlights::get_linked_highlights,
+            commands::highlights::get_daily_review,
+            commands::highlights::get_highlights,
+            commands::highlights::get_highlights_grouped_by_chapter,
+            commands::highlights::get_all_highlights,
+            commands::highlights::delete_highlight,
+            commands::books::delete_book,
+            commands::books::replace_book_file,
+            commands::books::archive_book,
+            commands::books::unarchive_book,
+            commands::books::relink_book_file,
+            commands::highlights::update_highlight_notes,
+            commands::highlights::get_note_history,
+            commands::highlights::restore_note_revision,
+            commands::highlights::toggle_favorite_highlight,
+            commands::books::toggle_favorite_book,
+            commands::highlights::get_favorite_highlights,
+            commands::books::get_favorite_books,
+            commands::books::set_book_rating,
+            commands::books::set_book_review,
+            commands::books::set_book_publication,
+            commands::books::fetch_cover_online,
+            commands::books::set_book_cover_from_url,
+            commands::books::enrich_metadata,
+            commands::books::apply_metadata,
+            commands::books::generate_citation,
+            commands::books::export_bibtex,
+            commands::highlights::find_overlapping_highlights,
+            commands::highlights::merge_highlights,
+            commands::highlights::dedupe_highlights,
+            commands::highlights::bulk_delete_highlights,
+            commands::highlights::bulk_recolor_highlights,
+            commands::highlights::bulk_add_to_collection,
+            commands::bookmarks::add_bookmark,
+            commands::bookmarks::get_bookmarks,
+            commands::bookmarks::update_bookmark,
+            commands::bookmarks::delete_bookmark,
+            commands::library::wipe_all_data,
+            commands::collections::create_collection,
+            commands::collections::get_all_collections,
+            commands::collections::delete_collection,
+            commands::collections::add_highlight_to_collection,
+            commands::collections::remove_highlight_from_collection,
+            commands::collections::get_highlights_by_collection,
+            commands::collections::get_highlight_collections,
+            commands::collections::get_books_by_collection,
+            commands::books::list_authors,
+            commands::books::list_series,
+            commands::books::get_books_by_author,
+            commands::books::get_books_by_series,
+            commands::books::get_books_by_tag,
+            commands::books::detect_book_language,
+            commands::books::detect_missing_languages,
+            commands::books::set_book_language,
+            commands::books::list_book_tags,
+            commands::books::get_book_tags,
+            commands::books::add_book_tag,
+            commands::books::remove_book_tag,
+            commands::books::rename_book_tag,
+            commands::books::delete_book_tag,
+            commands::import::import_calibre_library,
+            commands::diagnostics::get_schema_version,
+            commands::collections::create_smart_collection,
+            commands::collections::get_all_smart_collections,
+            commands::collections::evaluate_smart_collection,
+            commands::collections::delete_smart_collection,
+            commands::export::export_highlights_json,
+            commands::export::import_highlights_json,
+            commands::export::export_collection,
+            commands::export::publish_highlights_site,
+            commands::export::get_obsidian_template,
+            commands::export::set_obsidian_template,
+            commands::export::export_to_obsidian,
+            commands::export::export_markdown,
+            commands::export::export_blog_posts,
+            commands::export::export_drawings,
+            commands::export::get_export_templates,
+            commands::export::save_export_template,
+            commands::export::delete_export_template,
+            commands::export::preview_export_template,
+            commands::export::export_with_template,
+            commands::export::render_quote_image,
+            commands::vocabulary::add_vocab_word,
+            commands::vocabulary::get_vocab_words,
+            commands::vocabulary::delete_vocab_word,
+            commands::books::add_book_note,
+            commands::books::get_book_notes,
+            commands::books::get_progress_history,
+            commands::books::revert_progress,
+            commands::books::update_book_note,
+            commands::books::delete_book_note,
+            commands::books::add_drawing,
+            commands::books::get_drawings,
+            commands::books::update_drawing,
+            commands::books::delete_drawing,
+            commands::highlights::list_highlight_colors,
+            commands::highlights::add_highlight_color,
+            commands::highlights::update_highlight_color,
+            commands::highlights::delete_highlight_color,
+            commands::highlights::reorder_highlight_colors,
+            commands::highlights::get_highlights_by_color,
+            commands::vocabulary::lookup_word,
+            commands::vocabulary::reload_dictionaries,
+            commands::vocabulary::list_dictionaries,
+            commands::vocabulary::translate_text,
+            commands::vocabulary::clear_translation_cache,
+            commands::goals::set_book_finished,
+            commands::goals::log_reading_session,
+            commands::goals::set_goal,
+            commands::goals::get_reminders,
+            commands::goals::set_reminder,
+            commands::goals::clear_reminder,
+            commands::goals::get_goal_progress,
+            commands::goals::get_reading_heatmap,
+            commands::goals::export_stats_csv,
+            commands::goals::get_language_stats,
+            commands::goals::get_time_remaining,
+            commands::goals::get_reading_speed,
+            commands::goals::generate_year_review,
+            commands::library::get_encryption_status,
+            commands::library::unlock_library,
+            commands::library::lock_library,
+            commands::library::enable_encryption,
+            commands::library::disable_encryption,
+            commands::library::get_app_lock,
+            commands::library::set_app_lock,
+            commands::library::unlock_app,
+            commands::library::lock_app,
+            commands::server::get_server_status,
+            commands::server::set_server_enabled,
+            commands::server::regenerate_server_token,
+            commands::history::get_changes_since,
+            commands::diagnostics::get_recent_logs,
+            commands::diagnostics::export_diagnostics,
+            commands::history::undo_last_operation,
+            commands::history::redo,
+            commands::history::get_undo_state,
+            commands::sync::discover_peers,
+            commands::sync::start_pairing,
+            commands::sync::pair_peer,
+            commands::sync::sync_with_peer,
+            commands::sync::list_peers,
+            commands::sync::unpair_peer,
+            commands::library::list_backups,
+            commands::library::create_backup,
+            commands::library::restore_backup,
+            commands::diagnostics::check_database,
+            commands::diagnostics::clean_library,
+            commands::diagnostics::verify_library,
+            commands::diagnostics::verify_book_files,
+            commands::sync::store_secret,
+            commands::sync::get_secret,
+            commands::sync::delete_secret,
+            commands::sync::set_sync_config,
+            commands::sync::get_sync_config,
+            commands::sync::sync_now,
+            commands::search::index_book,
+            commands::search::search_in_book,
+            commands::search::search_library,
+            commands::search::semantic_search_highlights,
+            commands::search::summarize_highlights,
+            commands::library::get_library_path,
+            commands::library::get_compress_books,
+            commands::library::set_compress_books,
+            commands::library::get_storage_report,
+            commands::library::compress_library,
+            commands::library::set_library_path,
+            commands::library::list_profiles,
+            commands::library::create_profile,
+            commands::library::switch_profile,
+            commands::export::sync_to_notion,
+            commands::export::sync_readwise,
+            commands::export::sync_to_hypothesis,
+            commands::export::get_automation_status,
+            commands::export::set_automation,
+            commands::export::run_automation,
+            commands::tts::list_voices,
+            commands::tts::get_tts_settings,
+            commands::tts::set_tts_settings,
+            commands::tts::speak_text,
+            commands::tts::pause_speech,
+            commands::tts::resume_speech,
+            commands::tts::stop_speech,
+            commands::open::take_opened_books,
+            commands::import::start_import,
+            commands::import::cancel_import,
+            commands::import::save_article,
+            commands::export::send_book_via_email,
+            commands::export::generate_digest,
+            commands::import::import_read_later,
+            commands::feeds::subscribe_feed,
+            commands::feeds::unsubscribe_feed,
+            commands::feeds::get_feeds,
+            commands::feeds::refresh_feeds,
+            commands::feeds::get_inbox,
+            commands::feeds::mark_inbox_entry_read,
+            commands::watch_folder::get_watch_folder,
+            commands::watch_folder::set_watch_folder,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(handle_run_event);
 }
@@ -0,0 +1,153 @@
+//! Summaries of a book's highlights from an OpenAI-compatible chat completions
+//! endpoint (OpenAI, OpenRouter, a local Ollama or llama.cpp server, ...).
+//!
+//! Providers are configured entirely through the keychain, namespaced by the
+//! provider name: `url` (the API base, e.g. `https://api.openai.com/v1`),
+//! `api_key` (optional for local servers) and `model` (optional).
+
+use crate::secrets;
+use serde_json::{json, Value};
+
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Upper bound on the highlight text sent in one request, to stay well inside
+/// common context windows. Later highlights are left out past this point.
+const MAX_PROMPT_CHARS: usize = 60_000;
+
+const SYSTEM_PROMPT: &str = "You summarize a reader's highlights and notes from a book. \
+Write a concise summary of the key ideas they captured, grouped by theme, in the \
+language of the highlights. Use Markdown. Do not invent content that isn't supported \
+by the highlights.";
+
+pub struct Provider {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+impl Provider {
+    /// Reads the provider's settings from the keychain.
+    pub fn load(name: &str) -> Result<Provider, String> {
+        let url = secrets::get(name, "url")?
+            .filter(|u| !u.trim().is_empty())
+            .ok_or_else(|| format!("No URL configured for provider {name}"))?;
+        Ok(Provider {
+            url,
+            api_key: secrets::get(name, "api_key")?.filter(|k| !k.is_empty()),
+            model: secrets::get(name, "model")?
+                .filter(|m| !m.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        })
+    }
+
+    fn endpoint(&self) -> String {
+        let base = self.url.trim_end_matches('/');
+        if base.ends_with("/chat/completions") {
+            base.to_string()
+        } else {
+            format!("{base}/chat/completions")
+        }
+    }
+}
+
+/// Builds the user message from `(text, notes)` pairs.
+fn prompt(book_title: &str, highlights: &[(String, String)]) -> String {
+    let mut out = format!("Highlights from \"{book_title}\":\n");
+    for (index, (text, notes)) in highlights.iter().enumerate() {
+        let mut entry = format!("\n{}. {}\n", index + 1, text.trim());
+        if !notes.trim().is_empty() {
+            entry.push_str(&format!("   Note: {}\n", notes.trim()));
+        }
+        if out.len() + entry.len() > MAX_PROMPT_CHARS {
+            out.push_str(&format!(
+                "\n({} more highlights omitted for length.)\n",
+                highlights.len() - index
+            ));
+            break;
+        }
+        out.push_str(&entry);
+    }
+    out
+}
+
+/// Requests a summary and streams it back, calling `on_delta` with each piece
+/// of text as it arrives. Returns the complete summary.
+pub async fn summarize(
+    provider: &Provider,
+    book_title: &str,
+    highlights: &[(String, String)],
+    mut on_delta: impl FnMut(&str),
+) -> Result<String, String> {
+    let body = json!({
+        "model": provider.model,
+        "stream": true,
+        "messages": [
+            { "role": "system", "content": SYSTEM_PROMPT },
+            { "role": "user", "content": prompt(book_title, highlights) },
+        ],
+    });
+
+    let mut request = reqwest::Client::new()
+        .post(provider.endpoint())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&body).map_err(|e| e.to_string())?);
+    if let Some(key) = &provider.api_key {
+        request = request.bearer_auth(key);
+    }
+    let mut response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!(
+            "Summary request failed ({status}): {}",
+            text.trim()
+        ));
+    }
+
+    // Servers that ignore `stream` answer with a single JSON document.
+    let is_event_stream = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_event_stream {
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        let value: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        let summary = value["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or("Summary response had no content")?
+            .to_string();
+        on_delta(&summary);
+        return Ok(summary);
+    }
+
+    let mut summary = String::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    'stream: while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(newline) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                break 'stream;
+            }
+            let value: Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+            if let Some(message) = value["error"]["message"].as_str() {
+                return Err(format!("Summary failed: {message}"));
+            }
+            if let Some(delta) = value["choices"][0]["delta"]["content"].as_str() {
+                summary.push_str(delta);
+                on_delta(delta);
+            }
+        }
+    }
+
+    if summary.trim().is_empty() {
+        return Err("Summary response was empty".to_string());
+    }
+    Ok(summary)
+}
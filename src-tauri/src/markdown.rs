@@ -0,0 +1,211 @@
+//! Export to plain Markdown files with YAML frontmatter, one per book, in the
+//! layout Joplin's "Markdown + Front Matter" importer and Logseq read:
+//! `title`, `author`, `source`, `tags` (the book's collections) and
+//! `created`/`updated` dates in ISO 8601.
+//!
+//! Each highlight is followed by an HTML comment naming its ID, invisible
+//! once rendered, so merging into a file that was edited since can tell which
+//! highlights it already has.
+
+use crate::db;
+use crate::models::BookMetadata;
+use crate::obsidian::note_filename;
+use crate::readwise::iso8601;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// What to do with a book's file when the output folder already has one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExistingFiles {
+    /// Replace the whole file.
+    #[default]
+    Overwrite,
+    /// Rewrite the frontmatter and append highlights the file doesn't have,
+    /// keeping the rest of it as edited.
+    Merge,
+    /// Leave the file alone.
+    Skip,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MarkdownExportReport {
+    /// Files created or overwritten.
+    pub written: usize,
+    pub merged: usize,
+    pub skipped: usize,
+}
+
+struct Highlight {
+    id: i64,
+    text: String,
+    notes: String,
+}
+
+/// A YAML scalar; JSON strings are valid YAML.
+pub(crate) fn yaml(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn frontmatter(
+    title: &str,
+    book: Option<&BookMetadata>,
+    tags: &[String],
+    created: &str,
+    updated: &str,
+) -> String {
+    let mut lines = vec!["---".to_string(), format!("title: {}", yaml(title))];
+    if let Some(author) = book.and_then(|b| b.author.as_deref()) {
+        lines.push(format!("author: {}", yaml(author)));
+    }
+    if let Some(url) = book.and_then(|b| b.source_url.as_deref()) {
+        lines.push(format!("source: {}", yaml(url)));
+    }
+    if !tags.is_empty() {
+        lines.push("tags:".to_string());
+        lines.extend(tags.iter().map(|tag| format!("  - {}", yaml(tag))));
+    }
+    lines.push(format!("created: {}", yaml(&iso8601(created))));
+    lines.push(format!("updated: {}", yaml(&iso8601(updated))));
+    if let Some(finished) = book.and_then(|b| b.finished_at.as_deref()) {
+        lines.push(format!("finished: {}", yaml(&iso8601(finished))));
+    }
+    lines.push("---".to_string());
+    lines.join("\n") + "\n"
+}
+
+fn marker(id: i64) -> String {
+    format!("<!-- tumelog:highlight:{id} -->")
+}
+
+fn highlight_block(highlight: &Highlight) -> String {
+    let quoted: Vec<String> = highlight
+        .text
+        .trim()
+        .lines()
+        .map(|line| format!("> {line}").trim_end().to_string())
+        .collect();
+    let mut block = format!("{}\n{}\n", quoted.join("\n"), marker(highlight.id));
+    if !highlight.notes.trim().is_empty() {
+        block.push_str(&format!("\n{}\n", highlight.notes.trim()));
+    }
+    block
+}
+
+/// Splits off a leading frontmatter block, returning the rest of the file.
+fn body(file: &str) -> &str {
+    let Some(rest) = file.strip_prefix("---\n") else {
+        return file;
+    };
+    match rest.find("\n---\n") {
+        Some(end) => &rest[end + 5..],
+        None => match rest.strip_suffix("\n---") {
+            Some(_) => "",
+            None => file,
+        },
+    }
+}
+
+fn merge(existing: &str, frontmatter: &str, highlights: &[Highlight]) -> String {
+    let mut body = body(existing).to_string();
+    for highlight in highlights {
+        if body.contains(&marker(highlight.id)) {
+            continue;
+        }
+        if !body.is_empty() && !body.ends_with("\n\n") {
+            body.push_str(if body.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        body.push_str(&highlight_block(highlight));
+    }
+    format!("{frontmatter}{body}")
+}
+
+fn highlights(conn: &Connection, title: &str) -> rusqlite::Result<Vec<Highlight>> {
+    conn.prepare(
+        "SELECT id, text, notes FROM highlights WHERE book_title = ?1
+         ORDER BY cfi COLLATE cfi_order",
+    )?
+    .query_map(params![title], |row| {
+        Ok(Highlight {
+            id: row.get(0)?,
+            text: row.get(1)?,
+            notes: row.get(2)?,
+        })
+    })?
+    .collect()
+}
+
+pub(crate) fn book_tags(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<String>> {
+    conn.prepare(
+        "SELECT c.name FROM collections c
+         INNER JOIN book_collections bc ON c.id = bc.collection_id
+         WHERE bc.book_id = ?1 ORDER BY c.name",
+    )?
+    .query_map(params![book_id], |row| row.get(0))?
+    .collect()
+}
+
+/// Writes a file for every book with highlights into `dir`, handling files
+/// already there as `existing` says.
+pub fn export(
+    conn: &Connection,
+    dir: &Path,
+    existing: ExistingFiles,
+) -> Result<MarkdownExportReport, String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a folder", dir.display()));
+    }
+    let titles: Vec<(String, String, String)> = conn
+        .prepare(
+            "SELECT book_title, MIN(created_at), MAX(COALESCE(updated_at, created_at))
+             FROM highlights GROUP BY book_title ORDER BY book_title",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut report = MarkdownExportReport::default();
+    for (title, first_highlight, last_change) in titles {
+        let path = dir.join(note_filename(&title));
+        let exists = path.exists();
+        if exists && existing == ExistingFiles::Skip {
+            report.skipped += 1;
+            continue;
+        }
+
+        // Highlights can outlive their book row (e.g. after a sync), so fall
+        // back to just the title.
+        let book = conn
+            .query_row(
+                "SELECT id FROM books WHERE title = ?1",
+                params![title],
+                |row| row.get(0),
+            )
+            .optional()
+            .and_then(|id| id.map(|id| db::books::get(conn, id)).transpose())
+            .map_err(|e| e.to_string())?;
+        let tags = match &book {
+            Some(book) => book_tags(conn, book.id).map_err(|e| e.to_string())?,
+            None => Vec::new(),
+        };
+        let created = book.as_ref().map_or(&first_highlight, |b| &b.created_at);
+        let frontmatter = frontmatter(&title, book.as_ref(), &tags, created, &last_change);
+        let highlights = highlights(conn, &title).map_err(|e| e.to_string())?;
+
+        if exists && existing == ExistingFiles::Merge {
+            let current = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            std::fs::write(&path, merge(&current, &frontmatter, &highlights))
+                .map_err(|e| e.to_string())?;
+            report.merged += 1;
+            continue;
+        }
+        let blocks: Vec<String> = highlights.iter().map(highlight_block).collect();
+        let file = format!("{frontmatter}\n# {title}\n\n{}", blocks.join("\n"));
+        std::fs::write(&path, file).map_err(|e| e.to_string())?;
+        report.written += 1;
+    }
+    Ok(report)
+}
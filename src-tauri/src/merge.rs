@@ -0,0 +1,181 @@
+//! Merges incoming highlights (JSON imports, merge-restores from backups) into
+//! the library without creating duplicates.
+//!
+//! Records match on `(book_title, cfi, text)`. For a match, the newer record's
+//! colour wins and notes are reconciled: an empty note or one contained in the
+//! other gives way, and two genuinely different notes are both kept, newer
+//! first. A highlight at the same location with different text is a different
+//! highlight and is added alongside the existing one.
+
+use crate::models::ExportedHighlight;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// Separates the two notes when both sides of a conflict are kept.
+const NOTE_SEPARATOR: &str = "\n\n";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MergeReport {
+    /// New highlights.
+    pub added: usize,
+    /// Matches whose colour or notes were replaced by the newer version.
+    pub updated: usize,
+    /// Matches that were already identical.
+    pub unchanged: usize,
+    /// Matches with two different notes, both of which were kept.
+    pub conflicts: usize,
+    /// Added highlights that share a location with an existing highlight but
+    /// have different text.
+    pub kept_both: usize,
+}
+
+pub fn merge_highlights(
+    tx: &Transaction,
+    incoming: &[ExportedHighlight],
+) -> rusqlite::Result<MergeReport> {
+    let mut report = MergeReport::default();
+
+    for item in incoming {
+        let existing: Option<(i64, String, String, String)> = tx
+            .query_row(
+                "SELECT id, color, notes, COALESCE(updated_at, created_at) FROM highlights
+                 WHERE book_title = ?1 AND cfi = ?2 AND text = ?3
+                 ORDER BY id LIMIT 1",
+                params![item.book_title, item.cfi, item.text],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+
+        let highlight_id = match existing {
+            None => {
+                let same_location: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM highlights WHERE book_title = ?1 AND cfi = ?2)",
+                    params![item.book_title, item.cfi],
+                    |row| row.get(0),
+                )?;
+                tx.execute(
+                    "INSERT INTO highlights (book_title, cfi, text, color, notes, created_at, updated_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        item.book_title,
+                        item.cfi,
+                        item.text,
+                        item.color,
+                        item.notes,
+                        item.created_at,
+                        item.updated_at
+                    ],
+                )?;
+                report.added += 1;
+                if same_location {
+                    report.kept_both += 1;
+                }
+                tx.last_insert_rowid()
+            }
+            Some((id, color, notes, updated_at)) => {
+                let incoming_updated = item.updated_at.as_deref().unwrap_or(&item.created_at);
+                let incoming_newer = incoming_updated > updated_at.as_str();
+                let (newer, older) = if incoming_newer {
+                    (item.notes.as_str(), notes.as_str())
+                } else {
+                    (notes.as_str(), item.notes.as_str())
+                };
+                let (merged_notes, conflict) = reconcile_notes(newer, older);
+                let merged_color = if incoming_newer { &item.color } else { &color };
+
+                if merged_notes == notes && *merged_color == color {
+                    report.unchanged += 1;
+                } else {
+                    tx.execute(
+                        "UPDATE highlights SET color = ?2, notes = ?3 WHERE id = ?1",
+                        params![id, merged_color, merged_notes],
+                    )?;
+                    if conflict {
+                        report.conflicts += 1;
+                    } else {
+                        report.updated += 1;
+                    }
+                }
+                id
+            }
+        };
+
+        for name in &item.collections {
+            tx.execute(
+                "INSERT OR IGNORE INTO collections (name) VALUES (?1)",
+                params![name],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO highlight_collections (highlight_id, collection_id)
+                 SELECT ?1, id FROM collections WHERE name = ?2",
+                params![highlight_id, name],
+            )?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns the note to keep and whether both sides had to be kept.
+fn reconcile_notes(newer: &str, older: &str) -> (String, bool) {
+    let (newer_trimmed, older_trimmed) = (newer.trim(), older.trim());
+    if older_trimmed.is_empty() || newer_trimmed.contains(older_trimmed) {
+        (newer.to_string(), false)
+    } else if newer_trimmed.is_empty() || older_trimmed.contains(newer_trimmed) {
+        (older.to_string(), false)
+    } else {
+        (format!("{newer}{NOTE_SEPARATOR}{older}"), true)
+    }
+}
+
+/// Reads every highlight from another database (e.g. a backup) in the export
+/// format, so it can be fed to [`merge_highlights`]. Databases from before
+/// per-record timestamps fall back to `created_at`.
+pub fn read_highlights(conn: &Connection) -> rusqlite::Result<Vec<ExportedHighlight>> {
+    let has_updated_at: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('highlights') WHERE name = 'updated_at')",
+        [],
+        |row| row.get(0),
+    )?;
+    let updated_at = if has_updated_at {
+        "h.updated_at"
+    } else {
+        "NULL"
+    };
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT h.id, h.book_title, h.cfi, h.text, h.color, h.notes, h.created_at, {updated_at}
+         FROM highlights h ORDER BY h.id"
+    ))?;
+    let mut collections_stmt = conn.prepare(
+        "SELECT c.name FROM collections c
+         INNER JOIN highlight_collections hc ON c.id = hc.collection_id
+         WHERE hc.highlight_id = ?1 ORDER BY c.name",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            ExportedHighlight {
+                book_title: row.get(1)?,
+                cfi: row.get(2)?,
+                text: row.get(3)?,
+                color: row.get(4)?,
+                notes: row.get(5)?,
+                created_at: row.get(6)?,
+                updated_at: row.get(7)?,
+                collections: Vec::new(),
+            },
+        ))
+    })?;
+
+    let mut items = Vec::new();
+    for row in rows {
+        let (id, mut item) = row?;
+        item.collections = collections_stmt
+            .query_map(params![id], |r| r.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        items.push(item);
+    }
+    Ok(items)
+}
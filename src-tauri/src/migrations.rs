@@ -0,0 +1,1195 @@
+//! Schema migrations keyed on `PRAGMA user_version`.
+//!
+//! Each entry in [`MIGRATIONS`] moves the schema forward by exactly one
+//! version and runs inside its own transaction, so a failing step leaves the
+//! database at the previous version instead of half-migrated. New schema
+//! changes are appended to the list; existing steps must never be edited once
+//! released.
+
+use rusqlite::{Connection, Transaction};
+
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+const MIGRATIONS: &[Migration] = &[
+    v1_baseline,
+    v2_smart_collections,
+    v3_vocabulary,
+    v4_book_formats,
+    v5_reading_goals,
+    v6_sync,
+    v7_book_text,
+    v8_highlight_embeddings,
+    v9_book_summaries,
+    v10_chapters,
+    v11_unanchored_highlights,
+    v12_archived_books,
+    v13_favorites,
+    v14_obsidian_exports,
+    v15_notion_pages,
+    v16_book_notes,
+    v17_book_ratings,
+    v18_authors_and_series,
+    v19_highlight_colors,
+    v20_book_publication,
+    v21_word_counts,
+    v22_session_speed,
+    v23_progress_history,
+    v24_content_hashes,
+    v25_peers,
+    v26_sync_clocks,
+    v27_oplog,
+    v28_undo_stack,
+    v29_note_revisions,
+    v30_article_sources,
+    v31_feeds,
+    v32_hypothesis_annotations,
+    v33_last_opened,
+    v34_fold_collation,
+    v35_highlight_styles,
+    v36_region_annotations,
+    v37_drawings,
+    v38_audio_notes,
+    v39_attachments,
+    v40_highlight_links,
+    v41_last_surfaced,
+    v42_book_tags,
+    v43_book_language,
+    v44_translations,
+    v45_sync_sequence,
+    v46_signed_peers,
+    v47_attachment_oplog,
+    v48_note_revision_oplog,
+    v49_orphaned_feed_entries,
+];
+
+/// Version the database will be at once all migrations have been applied.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Applies every migration newer than the database's current `user_version`.
+pub fn run(conn: &mut Connection) -> Result<(), String> {
+    let current = schema_version(conn).map_err(|e| e.to_string())?;
+    if current > latest_version() {
+        return Err(format!(
+            "Database schema version {current} is newer than this app supports ({})",
+            latest_version()
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as i64 + 1;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        migration(&tx).map_err(|e| format!("Migration to v{version} failed: {e}"))?;
+        tx.pragma_update(None, "user_version", version)
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        log::info!("Migrated database to schema v{version}");
+    }
+    Ok(())
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?;
+    stmt.exists([table, column])
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !has_column(conn, table, column)? {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))?;
+    }
+    Ok(())
+}
+
+/// Columns left out of the oplog: bookkeeping that triggers maintain, and
+/// book fields too large or too frequently written to journal (reading
+/// position has `progress_history`). A change to nothing but these isn't
+/// logged.
+const OPLOG_IGNORED: &[&str] = &[
+    "updated_at",
+    "color_id",
+    "color_clock",
+    "notes_clock",
+    "notes_versions",
+    "label_clock",
+    "progress_clock",
+    "progress_updated_at",
+    "sync_seq",
+    "last_opened_at",
+    "last_surfaced_at",
+    "cover",
+    "locations_data",
+    "last_cfi",
+    "last_position",
+    "last_percentage",
+];
+
+/// (Re)creates the triggers journaling `table` into `oplog`. The payload
+/// lists the table's columns as they are now, so a later migration adding
+/// columns to a journaled table should call this again.
+fn create_oplog_triggers(tx: &Transaction, table: &str) -> rusqlite::Result<()> {
+    let columns: Vec<String> = tx
+        .prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?
+        .query_map([table], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?
+        .into_iter()
+        .filter(|column| !OPLOG_IGNORED.contains(&column.as_str()))
+        .collect();
+    let payload = |row: &str| {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| format!("'{column}', {row}.\"{column}\""))
+            .collect();
+        format!("json_object({})", fields.join(", "))
+    };
+    let changed = columns
+        .iter()
+        .map(|column| format!("NEW.\"{column}\" IS NOT OLD.\"{column}\""))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    let (new, old) = (payload("NEW"), payload("OLD"));
+    tx.execute_batch(&format!(
+        "DROP TRIGGER IF EXISTS oplog_{table}_insert;
+        DROP TRIGGER IF EXISTS oplog_{table}_update;
+        DROP TRIGGER IF EXISTS oplog_{table}_delete;
+        CREATE TRIGGER oplog_{table}_insert AFTER INSERT ON {table} BEGIN
+            INSERT INTO oplog (table_name, op, row_id, new)
+            VALUES ('{table}', 'insert', NEW.rowid, {new});
+        END;
+        CREATE TRIGGER oplog_{table}_update AFTER UPDATE ON {table}
+        WHEN {changed} BEGIN
+            INSERT INTO oplog (table_name, op, row_id, old, new)
+            VALUES ('{table}', 'update', NEW.rowid, {old}, {new});
+        END;
+        CREATE TRIGGER oplog_{table}_delete AFTER DELETE ON {table} BEGIN
+            INSERT INTO oplog (table_name, op, row_id, old)
+            VALUES ('{table}', 'delete', OLD.rowid, {old});
+        END;"
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Migration steps
+// ---------------------------------------------------------------------------
+
+/// The schema as it stood before versioning. Databases created by older builds
+/// already have some or all of it, so every statement here is idempotent.
+fn v1_baseline(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS highlights (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_title  TEXT    NOT NULL,
+            cfi         TEXT    NOT NULL,
+            text        TEXT    NOT NULL,
+            color       TEXT    NOT NULL DEFAULT '#facc15',
+            notes       TEXT    NOT NULL DEFAULT '',
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE IF NOT EXISTS books (
+            id              INTEGER PRIMARY KEY AUTOINCREMENT,
+            title           TEXT    NOT NULL UNIQUE,
+            filename        TEXT    NOT NULL,
+            last_cfi        TEXT    NOT NULL DEFAULT '',
+            cover           TEXT,
+            locations_data  TEXT,
+            last_percentage REAL    NOT NULL DEFAULT 0.0,
+            author          TEXT,
+            series          TEXT,
+            series_index    REAL,
+            created_at      TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE IF NOT EXISTS bookmarks (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_title  TEXT    NOT NULL,
+            cfi         TEXT    NOT NULL,
+            label       TEXT    NOT NULL,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE IF NOT EXISTS collections (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            name        TEXT    NOT NULL UNIQUE,
+            emoji       TEXT    NOT NULL DEFAULT '📌',
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE IF NOT EXISTS highlight_collections (
+            highlight_id   INTEGER NOT NULL,
+            collection_id  INTEGER NOT NULL,
+            PRIMARY KEY (highlight_id, collection_id),
+            FOREIGN KEY (highlight_id) REFERENCES highlights(id) ON DELETE CASCADE,
+            FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+        );
+        CREATE TABLE IF NOT EXISTS book_collections (
+            book_id        INTEGER NOT NULL,
+            collection_id  INTEGER NOT NULL,
+            PRIMARY KEY (book_id, collection_id),
+            FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE CASCADE,
+            FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+        );",
+    )?;
+
+    // Columns that were bolted on with ALTER TABLE before versioning existed.
+    // highlights.created_at is not listed: it has been in the CREATE statement
+    // from the start, and SQLite can't ADD COLUMN with a datetime() default.
+    add_column_if_missing(tx, "highlights", "color", "TEXT NOT NULL DEFAULT '#facc15'")?;
+    add_column_if_missing(tx, "highlights", "notes", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(tx, "books", "locations_data", "TEXT")?;
+    add_column_if_missing(tx, "books", "last_percentage", "REAL NOT NULL DEFAULT 0.0")?;
+    add_column_if_missing(tx, "books", "author", "TEXT")?;
+    add_column_if_missing(tx, "books", "series", "TEXT")?;
+    add_column_if_missing(tx, "books", "series_index", "REAL")?;
+    Ok(())
+}
+
+fn v2_smart_collections(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE smart_collections (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            name        TEXT    NOT NULL UNIQUE,
+            emoji       TEXT    NOT NULL DEFAULT '✨',
+            filter      TEXT    NOT NULL,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );",
+    )
+}
+
+fn v3_vocabulary(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE vocabulary (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            word        TEXT    NOT NULL,
+            context     TEXT    NOT NULL DEFAULT '',
+            book_title  TEXT,
+            cfi         TEXT,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_vocabulary_book_title ON vocabulary(book_title);",
+    )
+}
+
+/// Adds PDF support: a per-book format, the page count for paginated formats,
+/// and `last_cfi` becomes a format-specific `last_position` (a CFI for EPUBs,
+/// a page reference for PDFs).
+fn v4_book_formats(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN format TEXT NOT NULL DEFAULT 'epub';
+        ALTER TABLE books ADD COLUMN page_count INTEGER;
+        ALTER TABLE books RENAME COLUMN last_cfi TO last_position;",
+    )
+}
+
+/// Reading sessions and goals. Books gain `finished_at`; books finished before
+/// this version have no known finish date and are not backfilled.
+fn v5_reading_goals(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN finished_at TEXT;
+        CREATE TABLE reading_sessions (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_title  TEXT    NOT NULL,
+            started_at  TEXT    NOT NULL,
+            ended_at    TEXT    NOT NULL DEFAULT (datetime('now')),
+            seconds     INTEGER NOT NULL,
+            pages       INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX idx_reading_sessions_ended_at ON reading_sessions(ended_at);
+        CREATE TABLE reading_goals (
+            kind        TEXT    PRIMARY KEY,
+            target      INTEGER NOT NULL,
+            updated_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );",
+    )
+}
+
+/// Bookkeeping for last-write-wins sync: per-record modification times kept
+/// current by triggers, tombstones for deleted records, and a key/value
+/// `settings` table for non-secret configuration. Timestamps carry
+/// milliseconds so edits made in quick succession on two devices still order.
+fn v6_sync(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE settings (
+            key         TEXT    PRIMARY KEY,
+            value       TEXT    NOT NULL
+        );
+        CREATE TABLE sync_tombstones (
+            kind        TEXT    NOT NULL,
+            book_title  TEXT    NOT NULL,
+            cfi         TEXT    NOT NULL,
+            text        TEXT    NOT NULL DEFAULT '',
+            deleted_at  TEXT    NOT NULL,
+            PRIMARY KEY (kind, book_title, cfi, text)
+        );
+
+        ALTER TABLE highlights ADD COLUMN updated_at TEXT;
+        UPDATE highlights SET updated_at = created_at;
+        ALTER TABLE bookmarks ADD COLUMN updated_at TEXT;
+        UPDATE bookmarks SET updated_at = created_at;
+        ALTER TABLE books ADD COLUMN progress_updated_at TEXT;
+
+        CREATE TRIGGER highlights_sync_insert AFTER INSERT ON highlights BEGIN
+            UPDATE highlights SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = NEW.id AND NEW.updated_at IS NULL;
+            DELETE FROM sync_tombstones WHERE kind = 'highlight'
+                AND book_title = NEW.book_title AND cfi = NEW.cfi AND text = NEW.text;
+        END;
+        CREATE TRIGGER highlights_sync_update AFTER UPDATE OF color, notes ON highlights
+        WHEN NEW.updated_at IS OLD.updated_at BEGIN
+            UPDATE highlights SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER highlights_sync_delete AFTER DELETE ON highlights BEGIN
+            INSERT OR REPLACE INTO sync_tombstones (kind, book_title, cfi, text, deleted_at)
+            VALUES ('highlight', OLD.book_title, OLD.cfi, OLD.text,
+                    strftime('%Y-%m-%d %H:%M:%f', 'now'));
+        END;
+
+        CREATE TRIGGER bookmarks_sync_insert AFTER INSERT ON bookmarks BEGIN
+            UPDATE bookmarks SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = NEW.id AND NEW.updated_at IS NULL;
+            DELETE FROM sync_tombstones WHERE kind = 'bookmark'
+                AND book_title = NEW.book_title AND cfi = NEW.cfi AND text = '';
+        END;
+        CREATE TRIGGER bookmarks_sync_update AFTER UPDATE OF label ON bookmarks
+        WHEN NEW.updated_at IS OLD.updated_at BEGIN
+            UPDATE bookmarks SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER bookmarks_sync_delete AFTER DELETE ON bookmarks BEGIN
+            INSERT OR REPLACE INTO sync_tombstones (kind, book_title, cfi, text, deleted_at)
+            VALUES ('bookmark', OLD.book_title, OLD.cfi, '',
+                    strftime('%Y-%m-%d %H:%M:%f', 'now'));
+        END;
+
+        CREATE TRIGGER books_sync_progress AFTER UPDATE OF last_position, last_percentage ON books
+        WHEN NEW.progress_updated_at IS OLD.progress_updated_at BEGIN
+            UPDATE books SET progress_updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+            WHERE id = NEW.id;
+        END;",
+    )
+}
+
+/// Full-text index of book content, filled per book on request. Diacritics
+/// are folded so "cafe" finds "café".
+fn v7_book_text(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE book_text USING fts5(
+            text,
+            chapter UNINDEXED,
+            cfi UNINDEXED,
+            book_id UNINDEXED,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+        ALTER TABLE books ADD COLUMN indexed_at TEXT;",
+    )
+}
+
+/// Vectors for semantic highlight search. Editing a highlight's text or notes
+/// drops its vector so the background task re-embeds it.
+fn v8_highlight_embeddings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE highlight_embeddings (
+            highlight_id    INTEGER PRIMARY KEY,
+            model           TEXT    NOT NULL,
+            vector          BLOB    NOT NULL
+        );
+        CREATE TRIGGER highlights_embedding_stale AFTER UPDATE OF text, notes ON highlights BEGIN
+            DELETE FROM highlight_embeddings WHERE highlight_id = NEW.id;
+        END;
+        CREATE TRIGGER highlights_embedding_delete AFTER DELETE ON highlights BEGIN
+            DELETE FROM highlight_embeddings WHERE highlight_id = OLD.id;
+        END;",
+    )
+}
+
+/// Generated summaries of a book's highlights.
+fn v9_book_summaries(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN summary TEXT;
+        ALTER TABLE books ADD COLUMN summarized_at TEXT;",
+    )
+}
+
+/// Chapter boundaries from each EPUB's table of contents, as CFI ranges.
+/// `end_cfi` is the next chapter's start and `NULL` for the last chapter.
+fn v10_chapters(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE chapters (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id     INTEGER NOT NULL,
+            position    INTEGER NOT NULL,
+            title       TEXT,
+            start_cfi   TEXT    NOT NULL,
+            end_cfi     TEXT,
+            UNIQUE (book_id, position)
+        );",
+    )
+}
+
+/// Flags highlights that couldn't be found again after their book's file was
+/// replaced with a different edition.
+fn v11_unanchored_highlights(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE highlights ADD COLUMN unanchored INTEGER NOT NULL DEFAULT 0;")
+}
+
+/// Marks books whose file was deleted to save space, keeping their metadata
+/// and annotations.
+fn v12_archived_books(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE books ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;")
+}
+
+/// Favorite highlights and books, listed first.
+fn v13_favorites(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE highlights ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE books ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+/// Content hash of the last note written for each book into each Obsidian
+/// vault, for incremental exports.
+fn v14_obsidian_exports(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE obsidian_exports (
+            vault       TEXT    NOT NULL,
+            book_title  TEXT    NOT NULL,
+            hash        TEXT    NOT NULL,
+            exported_at TEXT    NOT NULL,
+            PRIMARY KEY (vault, book_title)
+        );",
+    )
+}
+
+/// The Notion page written for each book in each Notion database, with a
+/// hash of its content so unchanged books can be skipped.
+fn v15_notion_pages(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE notion_pages (
+            database_id TEXT    NOT NULL,
+            book_title  TEXT    NOT NULL,
+            page_id     TEXT    NOT NULL,
+            hash        TEXT    NOT NULL,
+            synced_at   TEXT    NOT NULL,
+            PRIMARY KEY (database_id, book_title)
+        );",
+    )
+}
+
+/// A running journal per book, separate from highlight notes.
+fn v16_book_notes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE book_notes (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id     INTEGER NOT NULL,
+            content     TEXT    NOT NULL DEFAULT '',
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now')),
+            updated_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_book_notes_book_id ON book_notes(book_id);",
+    )
+}
+
+/// Star ratings (0 = unrated) and free-form reviews on books.
+fn v17_book_ratings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN rating INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE books ADD COLUMN review TEXT;",
+    )
+}
+
+/// Authors and series as their own entities, filled from the display values
+/// already on books.
+fn v18_authors_and_series(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE authors (
+            id    INTEGER PRIMARY KEY AUTOINCREMENT,
+            name  TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+        CREATE TABLE book_authors (
+            book_id    INTEGER NOT NULL,
+            author_id  INTEGER NOT NULL,
+            position   INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (book_id, author_id)
+        );
+        CREATE INDEX idx_book_authors_author_id ON book_authors(author_id);
+
+        CREATE TABLE series (
+            id    INTEGER PRIMARY KEY AUTOINCREMENT,
+            name  TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+        CREATE TABLE book_series (
+            book_id       INTEGER PRIMARY KEY,
+            series_id     INTEGER NOT NULL,
+            series_index  REAL
+        );
+        CREATE INDEX idx_book_series_series_id ON book_series(series_id);
+
+        INSERT OR IGNORE INTO authors (name)
+            SELECT DISTINCT trim(author) FROM books WHERE trim(COALESCE(author, '')) <> '';
+        INSERT INTO book_authors (book_id, author_id)
+            SELECT b.id, a.id FROM books b INNER JOIN authors a ON a.name = trim(b.author);
+        INSERT OR IGNORE INTO series (name)
+            SELECT DISTINCT trim(series) FROM books WHERE trim(COALESCE(series, '')) <> '';
+        INSERT INTO book_series (book_id, series_id, series_index)
+            SELECT b.id, s.id, b.series_index FROM books b INNER JOIN series s ON s.name = trim(b.series);",
+    )
+}
+
+/// A named highlight palette. Highlights keep their hex in `color` and point
+/// at the matching palette entry through `color_id`, which triggers keep in
+/// step: with new and recolored highlights, and with palette entries whose
+/// hex changes. Colors already in use that aren't in the default palette are
+/// added under their hex.
+fn v19_highlight_colors(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE highlight_colors (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            name        TEXT    NOT NULL UNIQUE COLLATE NOCASE,
+            hex         TEXT    NOT NULL UNIQUE COLLATE NOCASE,
+            sort_order  INTEGER NOT NULL DEFAULT 0
+        );
+        INSERT INTO highlight_colors (name, hex, sort_order) VALUES
+            ('Yellow', '#facc15', 0),
+            ('Green', '#4ade80', 1),
+            ('Blue', '#60a5fa', 2),
+            ('Pink', '#f472b6', 3),
+            ('Purple', '#c084fc', 4);
+        INSERT OR IGNORE INTO highlight_colors (name, hex, sort_order)
+            SELECT lower(color), lower(color), 5 FROM highlights
+            WHERE color <> '' GROUP BY lower(color) ORDER BY MIN(created_at);
+
+        ALTER TABLE highlights ADD COLUMN color_id INTEGER;
+        UPDATE highlights SET color_id =
+            (SELECT c.id FROM highlight_colors c WHERE c.hex = highlights.color);
+        CREATE INDEX idx_highlights_color_id ON highlights(color_id);
+
+        CREATE TRIGGER highlights_color_insert AFTER INSERT ON highlights BEGIN
+            UPDATE highlights SET color_id =
+                (SELECT c.id FROM highlight_colors c WHERE c.hex = NEW.color)
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER highlights_color_update AFTER UPDATE OF color ON highlights BEGIN
+            UPDATE highlights SET color_id =
+                (SELECT c.id FROM highlight_colors c WHERE c.hex = NEW.color)
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER highlight_colors_insert AFTER INSERT ON highlight_colors BEGIN
+            UPDATE highlights SET color_id = NEW.id WHERE color = NEW.hex COLLATE NOCASE;
+        END;
+        CREATE TRIGGER highlight_colors_update AFTER UPDATE OF hex ON highlight_colors BEGIN
+            UPDATE highlights SET color = NEW.hex WHERE color_id = NEW.id;
+        END;
+        CREATE TRIGGER highlight_colors_delete AFTER DELETE ON highlight_colors BEGIN
+            UPDATE highlights SET color_id = NULL WHERE color_id = OLD.id;
+        END;",
+    )
+}
+
+/// Publisher, year and ISBN of each book, for citations.
+fn v20_book_publication(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN publisher TEXT;
+        ALTER TABLE books ADD COLUMN year INTEGER;
+        ALTER TABLE books ADD COLUMN isbn TEXT;",
+    )
+}
+
+/// Length of each book in words and the reading time that works out to.
+/// Existing books are counted the first time an estimate is asked for.
+fn v21_word_counts(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN word_count INTEGER;
+        ALTER TABLE books ADD COLUMN reading_minutes INTEGER;",
+    )
+}
+
+/// Where in the book each reading session started and ended, and the reading
+/// speed that works out to when the book's length is known.
+fn v22_session_speed(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE reading_sessions ADD COLUMN start_percentage REAL;
+        ALTER TABLE reading_sessions ADD COLUMN end_percentage REAL;
+        ALTER TABLE reading_sessions ADD COLUMN words INTEGER;
+        ALTER TABLE reading_sessions ADD COLUMN wpm REAL;",
+    )
+}
+
+/// Past reading positions of each book, so progress can be charted over time
+/// and an accidental jump undone.
+fn v23_progress_history(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE progress_history (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id      INTEGER NOT NULL,
+            cfi          TEXT    NOT NULL,
+            percentage   REAL    NOT NULL,
+            recorded_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_progress_history_book_id ON progress_history(book_id, id);",
+    )
+}
+
+/// SHA-256 of each book's file as imported. Existing books get theirs the
+/// first time the library is verified.
+fn v24_content_hashes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE books ADD COLUMN content_hash TEXT;")
+}
+
+/// Devices paired for LAN sync. `sent_until` is when this device last sent
+/// the peer its changes (by this device's clock), `received_until` the
+/// peer's timestamp for the last changes it sent back.
+fn v25_peers(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE peers (
+            device_id       TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            secret          TEXT NOT NULL,
+            address         TEXT,
+            sent_until      TEXT,
+            received_until  TEXT,
+            paired_at       TEXT NOT NULL DEFAULT (datetime('now')),
+            last_synced_at  TEXT
+        );",
+    )
+}
+
+/// Logical clocks for sync merges (see [`crate::sync`]). Each field that
+/// syncs gets the Lamport clock of its last edit, `<counter>-<device id>`
+/// with the counter zero-padded so clocks compare as text; notes also get a
+/// version vector of the edits they include. Triggers stamp local edits, and
+/// leave alone writes that set the clock themselves, i.e. merged records.
+///
+/// Existing records get counter 0 followed by their timestamp, which keeps
+/// the old last-write-wins order among them until they're edited.
+fn v26_sync_clocks(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "INSERT OR IGNORE INTO settings (key, value)
+            VALUES ('peer.device_id', lower(hex(randomblob(12))));
+        CREATE TABLE sync_clock (
+            id          INTEGER PRIMARY KEY CHECK (id = 1),
+            counter     INTEGER NOT NULL
+        );
+        INSERT INTO sync_clock (id, counter) VALUES (1, 0);
+        CREATE VIEW sync_clock_now AS
+            SELECT counter, device, printf('%016d-%s', counter, device) AS clock
+            FROM sync_clock, (SELECT COALESCE(
+                (SELECT value FROM settings WHERE key = 'peer.device_id'), '') AS device);
+
+        ALTER TABLE highlights ADD COLUMN color_clock TEXT;
+        ALTER TABLE highlights ADD COLUMN notes_clock TEXT;
+        ALTER TABLE highlights ADD COLUMN notes_versions TEXT NOT NULL DEFAULT '{}';
+        UPDATE highlights SET
+            color_clock = printf('%016d-%s', 0, COALESCE(updated_at, created_at)),
+            notes_clock = printf('%016d-%s', 0, COALESCE(updated_at, created_at));
+        ALTER TABLE bookmarks ADD COLUMN label_clock TEXT;
+        UPDATE bookmarks SET label_clock = printf('%016d-%s', 0, COALESCE(updated_at, created_at));
+        ALTER TABLE books ADD COLUMN progress_clock TEXT;
+        UPDATE books SET progress_clock = printf('%016d-%s', 0, progress_updated_at)
+        WHERE progress_updated_at IS NOT NULL;
+        ALTER TABLE sync_tombstones ADD COLUMN clock TEXT;
+        UPDATE sync_tombstones SET clock = printf('%016d-%s', 0, deleted_at);
+
+        CREATE TRIGGER highlights_clock_insert AFTER INSERT ON highlights
+        WHEN NEW.color_clock IS NULL BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE highlights SET
+                color_clock = (SELECT clock FROM sync_clock_now),
+                notes_clock = (SELECT clock FROM sync_clock_now),
+                notes_versions = (SELECT json_object(device, counter) FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER highlights_clock_color AFTER UPDATE OF color ON highlights
+        WHEN NEW.color IS NOT OLD.color AND NEW.color_clock IS OLD.color_clock BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE highlights SET color_clock = (SELECT clock FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER highlights_clock_notes AFTER UPDATE OF notes ON highlights
+        WHEN NEW.notes IS NOT OLD.notes AND NEW.notes_versions IS OLD.notes_versions BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE highlights SET
+                notes_clock = (SELECT clock FROM sync_clock_now),
+                notes_versions = (SELECT json_set(NEW.notes_versions,
+                    '$.\"' || device || '\"', counter) FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+
+        CREATE TRIGGER bookmarks_clock_insert AFTER INSERT ON bookmarks
+        WHEN NEW.label_clock IS NULL BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE bookmarks SET label_clock = (SELECT clock FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+        CREATE TRIGGER bookmarks_clock_label AFTER UPDATE OF label ON bookmarks
+        WHEN NEW.label IS NOT OLD.label AND NEW.label_clock IS OLD.label_clock BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE bookmarks SET label_clock = (SELECT clock FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+
+        CREATE TRIGGER books_clock_progress
+        AFTER UPDATE OF last_position, last_percentage ON books
+        WHEN (NEW.last_position IS NOT OLD.last_position
+              OR NEW.last_percentage IS NOT OLD.last_percentage)
+            AND NEW.progress_clock IS OLD.progress_clock BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE books SET progress_clock = (SELECT clock FROM sync_clock_now)
+            WHERE id = NEW.id;
+        END;
+
+        CREATE TRIGGER sync_tombstones_clock AFTER INSERT ON sync_tombstones
+        WHEN NEW.clock IS NULL BEGIN
+            UPDATE sync_clock SET counter = counter + 1;
+            UPDATE sync_tombstones SET clock = (SELECT clock FROM sync_clock_now)
+            WHERE rowid = NEW.rowid;
+        END;",
+    )
+}
+
+/// Append-only journal of changes to the user's data (see [`crate::oplog`]).
+fn v27_oplog(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE oplog (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            table_name  TEXT    NOT NULL,
+            op          TEXT    NOT NULL,
+            row_id      INTEGER NOT NULL,
+            old         TEXT,
+            new         TEXT,
+            at          TEXT    NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+        );
+        CREATE INDEX idx_oplog_table_row ON oplog(table_name, row_id);
+        CREATE INDEX idx_oplog_at ON oplog(at);",
+    )?;
+    for table in [
+        "highlights",
+        "books",
+        "bookmarks",
+        "collections",
+        "highlight_collections",
+        "book_collections",
+        "smart_collections",
+        "vocabulary",
+        "reading_goals",
+        "book_notes",
+        "authors",
+        "book_authors",
+        "series",
+        "book_series",
+        "highlight_colors",
+    ] {
+        create_oplog_triggers(tx, table)?;
+    }
+    Ok(())
+}
+
+/// Operations that can be undone, each a range of `oplog` entries (see
+/// [`crate::undo`]).
+fn v28_undo_stack(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE undo_stack (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            label         TEXT    NOT NULL,
+            first_change  INTEGER NOT NULL,
+            last_change   INTEGER NOT NULL,
+            undone        INTEGER NOT NULL DEFAULT 0,
+            created_at    TEXT    NOT NULL DEFAULT (datetime('now'))
+        );",
+    )
+}
+
+/// Earlier texts of highlight notes, saved whenever a note is overwritten.
+fn v29_note_revisions(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE note_revisions (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            highlight_id  INTEGER NOT NULL,
+            notes         TEXT    NOT NULL,
+            replaced_at   TEXT    NOT NULL DEFAULT (datetime('now')),
+            FOREIGN KEY (highlight_id) REFERENCES highlights(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_note_revisions_highlight_id ON note_revisions(highlight_id, id);",
+    )
+}
+
+/// The page a saved web article came from; one book per page.
+fn v30_article_sources(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN source_url TEXT;
+        CREATE UNIQUE INDEX idx_books_source_url ON books(source_url) WHERE source_url IS NOT NULL;",
+    )
+}
+
+/// Subscribed RSS and Atom feeds and the entries seen in them. An entry is
+/// in the inbox once saved as a book, until it's marked as read.
+fn v31_feeds(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE feeds (
+            id               INTEGER PRIMARY KEY AUTOINCREMENT,
+            url              TEXT    NOT NULL UNIQUE,
+            title            TEXT    NOT NULL,
+            site_url         TEXT,
+            last_fetched_at  TEXT,
+            last_error       TEXT,
+            created_at       TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE TABLE feed_entries (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            feed_id       INTEGER NOT NULL,
+            guid          TEXT    NOT NULL,
+            url           TEXT,
+            title         TEXT    NOT NULL,
+            published_at  TEXT,
+            book_id       INTEGER,
+            saved_at      TEXT,
+            read_at       TEXT,
+            attempts      INTEGER NOT NULL DEFAULT 0,
+            last_error    TEXT,
+            created_at    TEXT    NOT NULL DEFAULT (datetime('now')),
+            UNIQUE (feed_id, guid),
+            FOREIGN KEY (feed_id) REFERENCES feeds(id) ON DELETE CASCADE,
+            FOREIGN KEY (book_id) REFERENCES books(id) ON DELETE SET NULL
+        );
+        CREATE INDEX idx_feed_entries_book_id ON feed_entries(book_id);",
+    )
+}
+
+/// The Hypothes.is annotation each highlight was pushed as, and the highlight's
+/// `updated_at` when it was. No foreign key: rows outliving their highlight
+/// are how a sync knows which annotations to delete.
+fn v32_hypothesis_annotations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE hypothesis_annotations (
+            highlight_id   INTEGER PRIMARY KEY,
+            annotation_id  TEXT    NOT NULL,
+            updated_at     TEXT    NOT NULL
+        );",
+    )
+}
+
+/// When each book was last opened, for the recently read list. Backfilled
+/// from the latest reading session or progress update. The oplog triggers
+/// on `books` are recreated so they also cover `source_url` from v30.
+fn v33_last_opened(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN last_opened_at TEXT;
+        UPDATE books SET last_opened_at = (
+            SELECT MAX(at) FROM (
+                SELECT MAX(s.started_at) AS at FROM reading_sessions s
+                WHERE s.book_title = books.title
+                UNION ALL
+                SELECT books.progress_updated_at WHERE books.last_percentage > 0
+            )
+        );
+        CREATE INDEX idx_books_last_opened_at ON books(last_opened_at);",
+    )?;
+    create_oplog_triggers(tx, "books")
+}
+
+/// Book titles and author and series names unique regardless of case and
+/// accents, under the `fold` collation (see [`crate::db::fold`]). Books
+/// already sharing a folded title are numbered, and authors and series
+/// merged into the first.
+fn v34_fold_collation(tx: &Transaction) -> rusqlite::Result<()> {
+    let duplicates: Vec<(i64, String)> = tx
+        .prepare(
+            "SELECT b.id, b.title FROM books b
+             WHERE EXISTS (SELECT 1 FROM books o WHERE o.title = b.title COLLATE fold AND o.id < b.id)
+             ORDER BY b.id",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let title_tables: Vec<String> = tx
+        .prepare(
+            "SELECT m.name FROM sqlite_master m, pragma_table_info(m.name) p
+             WHERE m.type = 'table' AND p.name = 'book_title'",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, title) in duplicates {
+        let mut n = 2;
+        let renamed = loop {
+            let candidate = format!("{title} ({n})");
+            let taken: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM books WHERE title = ?1 COLLATE fold)",
+                [&candidate],
+                |row| row.get(0),
+            )?;
+            if !taken {
+                break candidate;
+            }
+            n += 1;
+        };
+        tx.execute(
+            "UPDATE books SET title = ?2 WHERE id = ?1",
+            rusqlite::params![id, renamed],
+        )?;
+        for table in &title_tables {
+            tx.execute(
+                &format!("UPDATE {table} SET book_title = ?2 WHERE book_title = ?1"),
+                [&title, &renamed],
+            )?;
+        }
+    }
+
+    tx.execute_batch(
+        "UPDATE OR IGNORE book_authors SET author_id =
+            (SELECT MIN(o.id) FROM authors a, authors o
+             WHERE a.id = book_authors.author_id AND o.name = a.name COLLATE fold);
+        DELETE FROM book_authors WHERE author_id NOT IN
+            (SELECT MIN(o.id) FROM authors a, authors o
+             WHERE o.name = a.name COLLATE fold GROUP BY a.id);
+        DELETE FROM authors WHERE id NOT IN (SELECT author_id FROM book_authors);
+        UPDATE book_series SET series_id =
+            (SELECT MIN(o.id) FROM series s, series o
+             WHERE s.id = book_series.series_id AND o.name = s.name COLLATE fold);
+        DELETE FROM series WHERE id NOT IN (SELECT series_id FROM book_series);
+
+        CREATE UNIQUE INDEX idx_books_title_fold ON books(title COLLATE fold);
+        CREATE UNIQUE INDEX idx_authors_name_fold ON authors(name COLLATE fold);
+        CREATE UNIQUE INDEX idx_series_name_fold ON series(name COLLATE fold);",
+    )
+}
+
+/// How each highlight is drawn: `highlight`, `underline`, `squiggly` or
+/// `strikethrough`.
+fn v35_highlight_styles(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE highlights ADD COLUMN style TEXT NOT NULL DEFAULT 'highlight';")?;
+    create_oplog_triggers(tx, "highlights")
+}
+
+/// Highlights of an image or a rectangle of a page instead of text, with the
+/// rectangle as JSON in `geometry`.
+fn v36_region_annotations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE highlights ADD COLUMN annotation_type TEXT NOT NULL DEFAULT 'text';
+         ALTER TABLE highlights ADD COLUMN geometry TEXT;",
+    )?;
+    create_oplog_triggers(tx, "highlights")
+}
+
+/// Freehand drawings on pages, their strokes as JSON.
+fn v37_drawings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE drawings (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id     INTEGER NOT NULL,
+            cfi         TEXT    NOT NULL,
+            strokes     TEXT    NOT NULL DEFAULT '[]',
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now')),
+            updated_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_drawings_book_id ON drawings(book_id, cfi);",
+    )?;
+    create_oplog_triggers(tx, "drawings")
+}
+
+/// Voice notes on highlights, stored as files in the library's `audio`
+/// folder. Replaced by attachments in v39.
+fn v38_audio_notes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE audio_notes (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            highlight_id  INTEGER NOT NULL,
+            file          TEXT    NOT NULL,
+            mime          TEXT    NOT NULL,
+            created_at    TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_audio_notes_highlight_id ON audio_notes(highlight_id);",
+    )
+}
+
+/// Files of any kind attached to highlights, taking over the voice notes;
+/// see [`crate::attachments`]. Their files stay in the `audio` folder.
+fn v39_attachments(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE attachments (
+            id            INTEGER PRIMARY KEY AUTOINCREMENT,
+            highlight_id  INTEGER NOT NULL,
+            kind          TEXT    NOT NULL,
+            path          TEXT    NOT NULL,
+            mime          TEXT    NOT NULL,
+            name          TEXT,
+            created_at    TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE INDEX idx_attachments_highlight_id ON attachments(highlight_id);
+        INSERT INTO attachments (highlight_id, kind, path, mime, created_at)
+            SELECT highlight_id, 'audio', 'audio/' || file, mime, created_at FROM audio_notes;
+        DROP TABLE audio_notes;",
+    )
+}
+
+/// Links between highlights, journaled like collection links so they can be
+/// undone.
+fn v40_highlight_links(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE highlight_links (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            from_id     INTEGER NOT NULL,
+            to_id       INTEGER NOT NULL,
+            relation    TEXT,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now')),
+            UNIQUE (from_id, to_id)
+        );
+        CREATE INDEX idx_highlight_links_to_id ON highlight_links(to_id);",
+    )?;
+    create_oplog_triggers(tx, "highlight_links")
+}
+
+/// When the daily review last showed each highlight. Left out of the oplog,
+/// so showing the review isn't journaled.
+fn v41_last_surfaced(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE highlights ADD COLUMN last_surfaced_at TEXT;")?;
+    create_oplog_triggers(tx, "highlights")
+}
+
+/// A description of each book, and subject tags (genres) on books, told
+/// apart regardless of case and accents.
+fn v42_book_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN description TEXT;
+        CREATE TABLE book_tags (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            book_id     INTEGER NOT NULL,
+            tag         TEXT    NOT NULL,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE UNIQUE INDEX idx_book_tags_book_tag ON book_tags(book_id, tag COLLATE fold);
+        CREATE INDEX idx_book_tags_tag ON book_tags(tag COLLATE fold);",
+    )?;
+    create_oplog_triggers(tx, "books")?;
+    create_oplog_triggers(tx, "book_tags")
+}
+
+/// The language each book is written in, as an ISO 639-1 code.
+fn v43_book_language(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE books ADD COLUMN language TEXT;
+        CREATE INDEX idx_books_language ON books(language);",
+    )?;
+    create_oplog_triggers(tx, "books")
+}
+
+/// A local cache, so unlike user data it isn't logged for sync.
+fn v44_translations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE translations (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            provider    TEXT    NOT NULL,
+            target_lang TEXT    NOT NULL,
+            text        TEXT    NOT NULL,
+            translation TEXT    NOT NULL,
+            source_lang TEXT,
+            created_at  TEXT    NOT NULL DEFAULT (datetime('now'))
+        );
+        CREATE UNIQUE INDEX idx_translations_text ON translations(provider, target_lang, text);",
+    )
+}
+
+/// When each synced record last changed here, as a tick of the sync clock, so
+/// LAN sync can send a peer everything changed since its last exchange (see
+/// [`crate::peer`]). Merged records from other devices tick it too: they keep
+/// their own, possibly older clocks, but still have to travel on. Peers
+/// remember the clock they last exchanged at instead of a timestamp, so the
+/// first sync with each after this migration is a full one.
+fn v45_sync_sequence(tx: &Transaction) -> rusqlite::Result<()> {
+    let synced: [(&str, &str, &[&str]); 4] = [
+        (
+            "highlights",
+            "id",
+            &[
+                "book_title",
+                "cfi",
+                "text",
+                "color",
+                "notes",
+                "color_clock",
+                "notes_clock",
+                "notes_versions",
+            ],
+        ),
+        (
+            "bookmarks",
+            "id",
+            &["book_title", "cfi", "label", "label_clock"],
+        ),
+        (
+            "books",
+            "id",
+            &[
+                "title",
+                "last_position",
+                "last_percentage",
+                "progress_clock",
+            ],
+        ),
+        ("sync_tombstones", "rowid", &["clock"]),
+    ];
+    for (table, key, columns) in synced {
+        let changed = columns
+            .iter()
+            .map(|column| format!("NEW.{column} IS NOT OLD.{column}"))
+            .collect::<Vec<_>>()
+            .join(" OR ");
+        let stamp = format!(
+            "UPDATE sync_clock SET counter = counter + 1;
+            UPDATE {table} SET sync_seq = (SELECT counter FROM sync_clock)
+            WHERE {key} = NEW.{key};"
+        );
+        tx.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN sync_seq INTEGER NOT NULL DEFAULT 0;
+            CREATE TRIGGER {table}_sync_seq_insert AFTER INSERT ON {table} BEGIN
+                {stamp}
+            END;
+            CREATE TRIGGER {table}_sync_seq_update AFTER UPDATE OF {} ON {table}
+            WHEN {changed} BEGIN
+                {stamp}
+            END;",
+            columns.join(", ")
+        ))?;
+    }
+    tx.execute_batch(
+        "ALTER TABLE peers DROP COLUMN sent_until;
+        ALTER TABLE peers DROP COLUMN received_until;
+        ALTER TABLE peers ADD COLUMN sent_until INTEGER;
+        ALTER TABLE peers ADD COLUMN received_until INTEGER;",
+    )
+}
+
+/// Pairings made before LAN sync signed its requests sent their secret over
+/// the network in the clear, so they're forgotten and devices pair again (see
+/// [`crate::peer`]).
+fn v46_signed_peers(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("DELETE FROM peers;")
+}
+
+/// Journals attachments like the rest of the user's data. They stay out of
+/// undo: a deleted highlight's attachments are kept until its deletion can't
+/// be undone any more (see [`crate::attachments::prune`]).
+fn v47_attachment_oplog(tx: &Transaction) -> rusqlite::Result<()> {
+    create_oplog_triggers(tx, "attachments")
+}
+
+/// Note revisions are deleted with their highlight by hand, since foreign
+/// keys aren't enforced, and journaled so undoing the deletion brings them
+/// back. Revisions of highlights deleted before then are dropped.
+fn v48_note_revision_oplog(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DELETE FROM note_revisions WHERE highlight_id NOT IN (SELECT id FROM highlights);",
+    )?;
+    create_oplog_triggers(tx, "note_revisions")
+}
+
+/// Entries of feeds unsubscribed from before their entries were deleted with
+/// them.
+fn v49_orphaned_feed_entries(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("DELETE FROM feed_entries WHERE feed_id NOT IN (SELECT id FROM feeds);")
+}
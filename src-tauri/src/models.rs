@@ -0,0 +1,750 @@
+//! Data types shared by the database layer, the commands and the frontend.
+
+use crate::smart_collections::SmartFilter;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Highlight {
+    pub id: i64,
+    pub book_title: String,
+    pub cfi: String,
+    pub text: String,
+    pub color: String,
+    pub notes: String,
+    pub created_at: String,
+    /// Set when the book's file was replaced and this highlight's text
+    /// couldn't be found in the new one.
+    pub unanchored: bool,
+    pub favorite: bool,
+    /// Palette entry matching `color`, if any.
+    pub color_id: Option<i64>,
+    pub style: HighlightStyle,
+    pub annotation_type: AnnotationType,
+    /// Where an image or region annotation sits; `None` for text.
+    pub geometry: Option<AnnotationGeometry>,
+}
+
+/// A connection between two highlights, possibly in different books, such
+/// as one idea supporting or contradicting another.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighlightLink {
+    pub id: i64,
+    pub from_id: i64,
+    pub to_id: i64,
+    /// How `from_id` relates to `to_id`, e.g. "contradicts".
+    pub relation: Option<String>,
+    pub created_at: String,
+}
+
+/// A highlight linked to the one asked about, with the link between them.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LinkedHighlight {
+    pub link: HighlightLink,
+    /// Whether the link was made from the highlight asked about.
+    pub outgoing: bool,
+    pub highlight: Highlight,
+}
+
+/// A file attached to a highlight.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: i64,
+    pub highlight_id: i64,
+    pub kind: AttachmentKind,
+    /// Path within the library folder.
+    pub path: String,
+    pub mime: String,
+    /// Name of the file it was attached from, if known.
+    pub name: Option<String>,
+    pub created_at: String,
+    /// Where the webview can load it from.
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    /// A voice note or other recording.
+    Audio,
+    Image,
+    File,
+}
+
+impl AttachmentKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AttachmentKind::Audio => "audio",
+            AttachmentKind::Image => "image",
+            AttachmentKind::File => "file",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "audio" => AttachmentKind::Audio,
+            "image" => AttachmentKind::Image,
+            _ => AttachmentKind::File,
+        }
+    }
+
+    pub fn for_mime(mime: &str) -> Self {
+        if mime.starts_with("audio/") {
+            AttachmentKind::Audio
+        } else if mime.starts_with("image/") {
+            AttachmentKind::Image
+        } else {
+            AttachmentKind::File
+        }
+    }
+}
+
+/// What a highlight marks: a text selection, an image, or a rectangle of a
+/// fixed-layout page.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationType {
+    #[default]
+    Text,
+    Image,
+    Region,
+}
+
+impl AnnotationType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnnotationType::Text => "text",
+            AnnotationType::Image => "image",
+            AnnotationType::Region => "region",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "image" => AnnotationType::Image,
+            "region" => AnnotationType::Region,
+            _ => AnnotationType::Text,
+        }
+    }
+}
+
+/// A rectangle in fractions (0 to 1) of the page or image it's on, so it
+/// stays in place at any zoom. Stored as JSON in `highlights.geometry`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AnnotationGeometry {
+    /// Page of a PDF or fixed-layout book, counting from 1.
+    pub page: Option<i64>,
+    /// The annotated image's `src` within the book.
+    pub src: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// An image or region annotation to add.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NewRegionAnnotation {
+    pub book_title: String,
+    /// Position of the page or image in the book.
+    pub cfi: String,
+    pub annotation_type: AnnotationType,
+    pub geometry: AnnotationGeometry,
+    /// Caption shown in place of highlighted text, e.g. "Figure 3".
+    #[serde(default)]
+    pub label: String,
+    pub color: String,
+    #[serde(default)]
+    pub notes: String,
+}
+
+/// How a highlight is drawn over its text.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightStyle {
+    #[default]
+    Highlight,
+    Underline,
+    Squiggly,
+    Strikethrough,
+}
+
+impl HighlightStyle {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HighlightStyle::Highlight => "highlight",
+            HighlightStyle::Underline => "underline",
+            HighlightStyle::Squiggly => "squiggly",
+            HighlightStyle::Strikethrough => "strikethrough",
+        }
+    }
+
+    /// The style stored as `value`; unknown values are drawn as highlights.
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "underline" => HighlightStyle::Underline,
+            "squiggly" => HighlightStyle::Squiggly,
+            "strikethrough" => HighlightStyle::Strikethrough,
+            _ => HighlightStyle::Highlight,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Chapter {
+    pub id: i64,
+    pub book_id: i64,
+    pub position: i64,
+    pub title: Option<String>,
+    pub start_cfi: String,
+    /// Start of the next chapter; `None` for the last one.
+    pub end_cfi: Option<String>,
+}
+
+/// Highlights falling inside one chapter, in reading order. `chapter` is
+/// `None` for highlights that couldn't be placed (e.g. a PDF, or an EPUB
+/// whose table of contents couldn't be read).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChapterHighlights {
+    pub chapter: Option<Chapter>,
+    pub highlights: Vec<Highlight>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScoredHighlight {
+    pub highlight: Highlight,
+    /// Cosine similarity to the query, in `(0, 1]`.
+    pub score: f32,
+}
+
+/// A highlight's note as it was before being overwritten.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteRevision {
+    pub id: i64,
+    pub highlight_id: i64,
+    pub notes: String,
+    pub replaced_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bookmark {
+    pub id: i64,
+    pub book_title: String,
+    pub cfi: String,
+    pub label: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookmarkSort {
+    /// Reading order, as in a table of contents.
+    #[default]
+    Position,
+    Newest,
+    Oldest,
+}
+
+/// Order of `get_all_books`. Favorites always come first.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LibrarySort {
+    /// Most recently added first.
+    #[default]
+    Added,
+    /// Highest rated first, unrated last.
+    Rating,
+    Title,
+}
+
+/// Field `query_books` sorts by.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSortBy {
+    #[default]
+    Title,
+    Author,
+    Progress,
+    LastOpened,
+    Added,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+/// How far the reader has got with a book.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadingStatus {
+    Unread,
+    Reading,
+    Finished,
+}
+
+/// Filters and order of `query_books`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BookQuery {
+    pub sort_by: BookSortBy,
+    /// Titles and authors A to Z, progress and dates newest or highest first
+    /// when not given.
+    pub direction: Option<SortDirection>,
+    /// Matched against titles and authors, ignoring case and accents.
+    pub search: Option<String>,
+    pub status: Option<ReadingStatus>,
+    pub collection_id: Option<i64>,
+    /// Only books with this tag, ignoring case and accents.
+    pub tag: Option<String>,
+}
+
+/// A named entry in the highlight palette.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighlightColor {
+    pub id: i64,
+    pub name: String,
+    pub hex: String,
+    pub sort_order: i64,
+}
+
+/// A journal entry about a whole book, separate from highlight notes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookNote {
+    pub id: i64,
+    pub book_id: i64,
+    pub content: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// One pen stroke. `points` are `[x, y, pressure]`, with x and y in
+/// fractions (0 to 1) of the page so the stroke stays in place at any zoom;
+/// `width` is a fraction of the page width too.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Stroke {
+    pub color: String,
+    pub width: f64,
+    pub points: Vec<[f64; 3]>,
+}
+
+/// Freehand strokes drawn on a page of a book.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Drawing {
+    pub id: i64,
+    pub book_id: i64,
+    /// Position of the page the drawing is on.
+    pub cfi: String,
+    pub strokes: Vec<Stroke>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A past reading position of a book.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProgressEntry {
+    pub id: i64,
+    pub book_id: i64,
+    pub cfi: String,
+    pub percentage: f64,
+    pub recorded_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VocabWord {
+    pub id: i64,
+    pub word: String,
+    pub context: String,
+    pub book_title: Option<String>,
+    pub cfi: Option<String>,
+    pub created_at: String,
+}
+
+/// Outcome of importing a Pocket or Instapaper export.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReadLaterImportReport {
+    pub articles_added: usize,
+    /// Articles that were already in the library.
+    pub articles_existing: usize,
+    pub highlights_added: usize,
+    /// Highlights whose text wasn't found in the saved article.
+    pub highlights_unmatched: usize,
+    /// `"<url>: <error>"` for each article that couldn't be fetched.
+    pub failures: Vec<String>,
+}
+
+/// A subscribed RSS or Atom feed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Feed {
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    pub site_url: Option<String>,
+    pub last_fetched_at: Option<String>,
+    /// Why the last refresh failed, if it did.
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub unread_count: i64,
+}
+
+/// A feed entry saved as an article, as listed in the inbox.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InboxEntry {
+    pub id: i64,
+    pub feed_id: i64,
+    pub feed_title: String,
+    pub title: String,
+    pub url: Option<String>,
+    pub published_at: Option<String>,
+    pub read_at: Option<String>,
+    pub book: BookMetadata,
+}
+
+/// What a feed refresh brought in.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FeedRefreshReport {
+    pub feeds: usize,
+    pub saved: usize,
+    pub failed: usize,
+}
+
+/// A subject tag and how many books have it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub book_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub emoji: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmartCollection {
+    pub id: i64,
+    pub name: String,
+    pub emoji: String,
+    pub filter: SmartFilter,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookMetadata {
+    pub id: i64,
+    pub title: String,
+    pub filename: String,
+    pub last_position: String,
+    pub cover: Option<String>,
+    pub locations_data: Option<String>,
+    pub last_percentage: f64,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub format: String,
+    pub page_count: Option<i64>,
+    /// When the reader first reached the end of the book.
+    pub finished_at: Option<String>,
+    pub created_at: String,
+    /// When the book's text was last added to the full-text index.
+    pub indexed_at: Option<String>,
+    /// Generated summary of the book's highlights, in Markdown.
+    pub summary: Option<String>,
+    pub summarized_at: Option<String>,
+    /// The book's file was deleted with `archive_book`; everything else is
+    /// kept.
+    pub archived: bool,
+    /// Pinned to the top of the library.
+    pub favorite: bool,
+    /// Stars from 1 to 5; 0 means unrated.
+    pub rating: i64,
+    pub review: Option<String>,
+    pub publisher: Option<String>,
+    pub year: Option<i64>,
+    pub isbn: Option<String>,
+    pub word_count: Option<i64>,
+    /// Estimated minutes to read the whole book at an average speed.
+    pub reading_minutes: Option<i64>,
+    /// The web page a saved article was fetched from.
+    pub source_url: Option<String>,
+    /// When the book's content was last loaded for reading.
+    pub last_opened_at: Option<String>,
+    /// The publisher's blurb, as plain text.
+    pub description: Option<String>,
+    /// ISO 639-1 code of the language the book is written in.
+    pub language: Option<String>,
+}
+
+/// A library entry with its annotation counts, for list badges.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookWithCounts {
+    #[serde(flatten)]
+    pub book: BookMetadata,
+    pub highlight_count: i64,
+    pub bookmark_count: i64,
+}
+
+/// Payload of `import://conversion-progress` events.
+#[derive(Debug, Serialize, Clone)]
+pub struct ConversionProgress {
+    pub filename: String,
+    pub stage: String,
+    pub progress: f64,
+}
+
+/// Payload of `import://progress` events.
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportProgress {
+    pub task_id: u64,
+    pub filename: String,
+    pub stage: String,
+    pub progress: f64,
+}
+
+/// Payload of `import://finished` events: the imported book, or why there is
+/// none.
+#[derive(Debug, Serialize, Clone)]
+pub struct ImportFinished {
+    pub task_id: u64,
+    pub book: Option<BookMetadata>,
+    pub error: Option<String>,
+    /// The import stopped because of `cancel_import`.
+    pub cancelled: bool,
+}
+
+/// Payload of `summary://chunk` events.
+#[derive(Debug, Serialize, Clone)]
+pub struct SummaryChunk {
+    pub book_title: String,
+    pub delta: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CalibreImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingSession {
+    pub id: i64,
+    pub book_title: String,
+    pub started_at: String,
+    pub ended_at: String,
+    pub seconds: i64,
+    pub pages: i64,
+    /// Progress through the book when the session started and ended.
+    pub start_percentage: Option<f64>,
+    pub end_percentage: Option<f64>,
+    /// Words per minute, when the session could be measured.
+    pub wpm: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalKind {
+    BooksPerYear,
+    MinutesPerDay,
+}
+
+impl GoalKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GoalKind::BooksPerYear => "books_per_year",
+            GoalKind::MinutesPerDay => "minutes_per_day",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BooksGoalProgress {
+    pub year: i64,
+    pub target: i64,
+    pub finished: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MinutesGoalProgress {
+    pub target: i64,
+    pub today_minutes: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GoalProgress {
+    pub books_per_year: Option<BooksGoalProgress>,
+    pub minutes_per_day: Option<MinutesGoalProgress>,
+    /// Consecutive days, ending today or yesterday, that met the daily minutes
+    /// goal (or had any reading at all when no daily goal is set).
+    pub current_streak: i64,
+    pub longest_streak: i64,
+}
+
+/// Per-day reading totals for one calendar year. Index 0 is January 1st; the
+/// arrays have one entry per day of the year.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingHeatmap {
+    pub year: i32,
+    pub minutes: Vec<u32>,
+    pub pages: Vec<u32>,
+}
+
+/// Rows that point at something that no longer exists.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OrphanCounts {
+    pub highlights: usize,
+    pub bookmarks: usize,
+    pub vocabulary: usize,
+    pub highlight_collection_links: usize,
+    pub book_collection_links: usize,
+    pub highlight_links: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatabaseCheckReport {
+    /// Messages from `PRAGMA integrity_check`; `["ok"]` for a healthy file.
+    pub integrity: Vec<String>,
+    pub orphans: OrphanCounts,
+    pub repaired: bool,
+    /// Highlights and bookmarks moved to a book whose title differs only in
+    /// case or surrounding whitespace.
+    pub relinked: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FileStatus {
+    Ok,
+    /// Deliberately has no file; see `archive_book`.
+    Archived,
+    Missing,
+    /// The file exists but can't be opened as its format.
+    Unreadable {
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookFileStatus {
+    pub book_id: i64,
+    pub title: String,
+    pub filename: String,
+    #[serde(flatten)]
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    /// The file matches the hash recorded at import.
+    Ok,
+    /// No hash was recorded for the file (it predates hashing); the current
+    /// one has been stored.
+    Recorded,
+    /// The file's contents differ from what was imported.
+    Modified {
+        expected: String,
+        actual: String,
+    },
+    Missing,
+    Archived,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookIntegrity {
+    pub book_id: i64,
+    pub title: String,
+    pub filename: String,
+    #[serde(flatten)]
+    pub status: IntegrityStatus,
+}
+
+/// Disk use of the stored book files.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StorageReport {
+    pub files: usize,
+    pub compressed_files: usize,
+    pub encrypted_files: usize,
+    /// Bytes the files take on disk.
+    pub stored_bytes: u64,
+    /// Bytes they would take uncompressed.
+    pub original_bytes: u64,
+    pub saved_bytes: u64,
+    /// Files that couldn't be read or converted.
+    pub failed: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LibraryCleanReport {
+    /// Book files (and leftover `.tmp` files) in the books folder that no
+    /// book refers to.
+    pub orphaned_files: Vec<String>,
+    pub orphaned_bytes: u64,
+    /// The orphaned files were deleted.
+    pub removed: bool,
+    /// Books whose file is gone. Archived books have no file by design and
+    /// aren't listed.
+    pub missing_files: Vec<BookMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SchemaVersion {
+    pub current: i64,
+    pub latest: i64,
+}
+
+/// Bumped whenever the layout of [`HighlightsExport`] changes incompatibly.
+pub const HIGHLIGHTS_EXPORT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportScope {
+    #[default]
+    All,
+    Book {
+        title: String,
+    },
+    Collection {
+        collection_id: i64,
+    },
+}
+
+/// What `publish_highlights_site` puts on the site.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SiteOptions {
+    /// Heading of the index page; "Highlights" when not given.
+    pub title: Option<String>,
+    pub scope: ExportScope,
+    /// Only these highlights within `scope`, when given.
+    pub highlight_ids: Option<Vec<i64>>,
+    pub favorites_only: bool,
+    /// Leave notes out, e.g. when they're private.
+    pub hide_notes: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportedHighlight {
+    pub book_title: String,
+    pub cfi: String,
+    pub text: String,
+    pub color: String,
+    pub notes: String,
+    pub created_at: String,
+    /// Last modification time; absent in exports from older versions.
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub collections: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighlightsExport {
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub items: Vec<ExportedHighlight>,
+}
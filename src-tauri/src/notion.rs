@@ -0,0 +1,349 @@
+//! One-way sync of highlights into a Notion database: one page per book, with
+//! each highlight as a quote block followed by its note.
+//!
+//! The integration token and database ID live in the keychain under the
+//! `notion` integration (`token`, `database_id`); the database must be shared
+//! with the integration. Page IDs are remembered per book, so later syncs
+//! rewrite the same page instead of adding another, and a content hash skips
+//! books whose highlights haven't changed.
+
+use crate::embeddings::fnv1a;
+use crate::{run_blocking, DbState};
+use reqwest::{Method, StatusCode};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const API: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Most blocks Notion accepts in one request.
+const BLOCKS_PER_REQUEST: usize = 100;
+
+/// Longest text Notion accepts in one rich text object.
+const MAX_TEXT_CHARS: usize = 2000;
+
+/// Attempts per request while Notion answers 429 Too Many Requests.
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct NotionSyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+struct BookPage {
+    title: String,
+    blocks: Vec<Value>,
+    hash: String,
+    page_id: Option<String>,
+    synced_hash: Option<String>,
+}
+
+fn rich_text(text: &str, italic: bool) -> Vec<Value> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(MAX_TEXT_CHARS)
+        .map(|chunk| {
+            json!({
+                "type": "text",
+                "text": { "content": chunk.iter().collect::<String>() },
+                "annotations": { "italic": italic },
+            })
+        })
+        .collect()
+}
+
+fn blocks(highlights: &[(String, String)]) -> Vec<Value> {
+    let mut blocks = Vec::new();
+    for (text, notes) in highlights {
+        blocks.push(json!({
+            "object": "block",
+            "type": "quote",
+            "quote": { "rich_text": rich_text(text.trim(), false) },
+        }));
+        if !notes.trim().is_empty() {
+            blocks.push(json!({
+                "object": "block",
+                "type": "paragraph",
+                "paragraph": { "rich_text": rich_text(notes.trim(), true) },
+            }));
+        }
+    }
+    blocks
+}
+
+struct Client {
+    http: reqwest::Client,
+    token: String,
+}
+
+impl Client {
+    /// Sends a request, waiting out rate limits. `Ok(None)` means the object
+    /// wasn't found (deleted, or no longer shared with the integration).
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Option<Value>, String> {
+        for _ in 0..MAX_ATTEMPTS {
+            let mut request = self
+                .http
+                .request(method.clone(), format!("{API}{path}"))
+                .bearer_auth(&self.token)
+                .header("Notion-Version", NOTION_VERSION);
+            if let Some(body) = body {
+                request = request
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(serde_json::to_vec(body).map_err(|e| e.to_string())?);
+            }
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                let wait = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(1);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+                continue;
+            }
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let text = response.text().await.map_err(|e| e.to_string())?;
+            if !status.is_success() {
+                let message = serde_json::from_str::<Value>(&text)
+                    .ok()
+                    .and_then(|v| v["message"].as_str().map(str::to_string))
+                    .unwrap_or(text);
+                return Err(format!("Notion request failed ({status}): {message}"));
+            }
+            return serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| e.to_string());
+        }
+        Err("Notion kept rate-limiting requests; try again later".to_string())
+    }
+
+    /// Name of the database's title property, which differs per database.
+    async fn title_property(&self, database_id: &str) -> Result<String, String> {
+        let database = self
+            .send(Method::GET, &format!("/databases/{database_id}"), None)
+            .await?
+            .ok_or("Notion database not found; is it shared with the integration?")?;
+        database["properties"]
+            .as_object()
+            .and_then(|properties| {
+                properties
+                    .iter()
+                    .find(|(_, p)| p["type"] == "title")
+                    .map(|(name, _)| name.clone())
+            })
+            .ok_or_else(|| "Notion database has no title property".to_string())
+    }
+
+    async fn append(&self, page_id: &str, blocks: &[Value]) -> Result<(), String> {
+        for chunk in blocks.chunks(BLOCKS_PER_REQUEST) {
+            self.send(
+                Method::PATCH,
+                &format!("/blocks/{page_id}/children"),
+                Some(&json!({ "children": chunk })),
+            )
+            .await?
+            .ok_or("Notion page disappeared during sync")?;
+        }
+        Ok(())
+    }
+
+    async fn create_page(
+        &self,
+        database_id: &str,
+        title_property: &str,
+        title: &str,
+        blocks: &[Value],
+    ) -> Result<String, String> {
+        let (first, rest) = blocks.split_at(blocks.len().min(BLOCKS_PER_REQUEST));
+        let page = self
+            .send(
+                Method::POST,
+                "/pages",
+                Some(&json!({
+                    "parent": { "database_id": database_id },
+                    "properties": {
+                        title_property: { "title": rich_text(title, false) },
+                    },
+                    "children": first,
+                })),
+            )
+            .await?
+            .ok_or("Notion database not found; is it shared with the integration?")?;
+        let page_id = page["id"]
+            .as_str()
+            .ok_or("Notion returned a page without an ID")?
+            .to_string();
+        self.append(&page_id, rest).await?;
+        Ok(page_id)
+    }
+
+    /// Replaces the page's content. Returns `false` if the page is gone.
+    async fn replace_content(&self, page_id: &str, blocks: &[Value]) -> Result<bool, String> {
+        let Some(page) = self
+            .send(Method::GET, &format!("/pages/{page_id}"), None)
+            .await?
+        else {
+            return Ok(false);
+        };
+        if page["archived"].as_bool().unwrap_or(false) {
+            return Ok(false);
+        }
+
+        let mut old_blocks = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut path = format!("/blocks/{page_id}/children?page_size={BLOCKS_PER_REQUEST}");
+            if let Some(cursor) = &cursor {
+                path.push_str(&format!("&start_cursor={cursor}"));
+            }
+            let Some(list) = self.send(Method::GET, &path, None).await? else {
+                return Ok(false);
+            };
+            old_blocks.extend(
+                list["results"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|b| b["id"].as_str().map(str::to_string)),
+            );
+            match list["next_cursor"].as_str() {
+                Some(next) if list["has_more"].as_bool().unwrap_or(false) => {
+                    cursor = Some(next.to_string())
+                }
+                _ => break,
+            }
+        }
+        for block_id in old_blocks {
+            self.send(Method::DELETE, &format!("/blocks/{block_id}"), None)
+                .await?;
+        }
+        self.append(page_id, blocks).await?;
+        Ok(true)
+    }
+}
+
+fn load_pages(db: &DbState, database_id: &str) -> Result<Vec<BookPage>, String> {
+    let conn = db.conn()?;
+    let titles: Vec<String> = conn
+        .prepare("SELECT DISTINCT book_title FROM highlights ORDER BY book_title")
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut highlights_stmt = conn
+        .prepare(
+            "SELECT text, notes FROM highlights WHERE book_title = ?1
+             ORDER BY cfi COLLATE cfi_order",
+        )
+        .map_err(|e| e.to_string())?;
+    let mut page_stmt = conn
+        .prepare(
+            "SELECT page_id, hash FROM notion_pages WHERE database_id = ?1 AND book_title = ?2",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut pages = Vec::new();
+    for title in titles {
+        let highlights: Vec<(String, String)> = highlights_stmt
+            .query_map(params![title], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())?;
+
+        let blocks = blocks(&highlights);
+        let hash = format!(
+            "{:016x}",
+            fnv1a(&serde_json::to_vec(&blocks).map_err(|e| e.to_string())?)
+        );
+        let synced: Option<(String, String)> = page_stmt
+            .query_row(params![database_id, title], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let (page_id, synced_hash) = synced.unzip();
+        pages.push(BookPage {
+            title,
+            blocks,
+            hash,
+            page_id,
+            synced_hash,
+        });
+    }
+    Ok(pages)
+}
+
+/// Creates or updates a page for every book with highlights.
+pub async fn sync(
+    db: DbState,
+    token: String,
+    database_id: String,
+) -> Result<NotionSyncReport, String> {
+    let client = Client {
+        http: reqwest::Client::new(),
+        token,
+    };
+    let title_property = client.title_property(&database_id).await?;
+    let pages = run_blocking({
+        let db = db.clone();
+        let database_id = database_id.clone();
+        move || load_pages(&db, &database_id)
+    })
+    .await?;
+
+    let mut report = NotionSyncReport::default();
+    for page in pages {
+        if page.page_id.is_some() && page.synced_hash.as_deref() == Some(page.hash.as_str()) {
+            report.unchanged += 1;
+            continue;
+        }
+        let updated = match &page.page_id {
+            Some(page_id) => client.replace_content(page_id, &page.blocks).await?,
+            None => false,
+        };
+        let page_id = if updated {
+            report.updated += 1;
+            page.page_id.clone().unwrap_or_default()
+        } else {
+            report.created += 1;
+            client
+                .create_page(&database_id, &title_property, &page.title, &page.blocks)
+                .await?
+        };
+
+        // Record each page as soon as it's written, so a failure later in
+        // the run doesn't lead to duplicates next time.
+        let db = db.clone();
+        let database_id = database_id.clone();
+        run_blocking(move || {
+            let conn = db.conn()?;
+            conn.execute(
+                "INSERT INTO notion_pages (database_id, book_title, page_id, hash, synced_at)
+                 VALUES (?1, ?2, ?3, ?4, datetime('now'))
+                 ON CONFLICT(database_id, book_title) DO UPDATE
+                 SET page_id = excluded.page_id, hash = excluded.hash,
+                     synced_at = excluded.synced_at",
+                params![database_id, page.title, page_id, page.hash],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await?;
+    }
+    Ok(report)
+}
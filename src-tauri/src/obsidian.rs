@@ -0,0 +1,156 @@
+//! Export into an Obsidian vault: one Markdown note per book, rendered from a
+//! user-editable template (see [`templates`] for what templates can use).
+//!
+//! The `quote` filter keeps multi-paragraph highlights inside their quote
+//! block. Values going into YAML frontmatter should pass through
+//! `json_encode()`, which yields valid YAML scalars.
+//!
+//! Each note's content hash is remembered per vault, so incremental exports
+//! only rewrite notes whose book changed (or whose template did).
+
+use crate::embeddings::fnv1a;
+use crate::templates;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tera::Tera;
+
+const TEMPLATE_NAME: &str = "book.md";
+
+/// Settings key of the user's template; the default is used when unset.
+pub const TEMPLATE_SETTING: &str = "obsidian.template";
+
+pub const DEFAULT_TEMPLATE: &str = r#"---
+title: {{ book.title | json_encode() }}
+{%- if book.author %}
+author: {{ book.author | json_encode() }}
+{%- endif %}
+{%- if book.series %}
+series: {{ book.series | json_encode() }}
+{%- endif %}
+highlights: {{ highlights | length }}
+{%- if book.finished_at %}
+finished: {{ book.finished_at | json_encode() }}
+{%- endif %}
+tags: [books]
+---
+
+# {{ book.title }}
+{% if book.author %}
+Author: [[{{ book.author }}]]
+{% endif %}
+{%- if book.summary %}
+## Summary
+
+{{ book.summary }}
+{% endif %}
+## Highlights
+{% for h in highlights %}
+{{ h.text | quote }} ^hl-{{ h.id }}
+{% if h.notes %}
+{{ h.notes }}
+{% endif %}
+*{{ h.date }}* · [Open]({{ h.link }}){% for c in h.collections %} [[{{ c }}]]{% endfor %}
+{% endfor %}"#;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ObsidianExportReport {
+    /// Notes created or rewritten.
+    pub written: usize,
+    /// Notes left alone because nothing changed since the last export.
+    pub unchanged: usize,
+}
+
+/// Compiles `template`, reporting syntax errors with their cause.
+pub fn compile(template: &str) -> Result<Tera, String> {
+    templates::compile(TEMPLATE_NAME, template)
+}
+
+/// The user's template, or [`DEFAULT_TEMPLATE`].
+pub fn template(conn: &Connection) -> rusqlite::Result<String> {
+    Ok(conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![TEMPLATE_SETTING],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string()))
+}
+
+/// A note file name for `title`, without characters Obsidian or common
+/// filesystems reject.
+pub(crate) fn note_filename(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let cleaned = cleaned.trim_start_matches('.');
+    if cleaned.is_empty() {
+        "Untitled.md".to_string()
+    } else {
+        format!("{cleaned}.md")
+    }
+}
+
+/// Writes a note for every book with highlights into `vault`. With
+/// `incremental`, notes whose content is unchanged since the last export to
+/// this vault (and that still exist) are skipped.
+pub fn export(
+    conn: &Connection,
+    vault: &Path,
+    incremental: bool,
+) -> Result<ObsidianExportReport, String> {
+    if !vault.is_dir() {
+        return Err(format!("{} is not a folder", vault.display()));
+    }
+    let tera = compile(&template(conn).map_err(|e| e.to_string())?)?;
+    let vault_key = vault.to_string_lossy().into_owned();
+
+    let titles: Vec<String> = conn
+        .prepare("SELECT DISTINCT book_title FROM highlights ORDER BY book_title")
+        .map_err(|e| e.to_string())?
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut report = ObsidianExportReport::default();
+    for title in titles {
+        let note = templates::render_book(conn, &tera, TEMPLATE_NAME, &title)?;
+        let hash = format!("{:016x}", fnv1a(note.as_bytes()));
+
+        let path = vault.join(note_filename(&title));
+        if incremental && path.exists() {
+            let previous: Option<String> = conn
+                .query_row(
+                    "SELECT hash FROM obsidian_exports WHERE vault = ?1 AND book_title = ?2",
+                    params![vault_key, title],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if previous.as_deref() == Some(hash.as_str()) {
+                report.unchanged += 1;
+                continue;
+            }
+        }
+
+        std::fs::write(&path, note).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO obsidian_exports (vault, book_title, hash, exported_at)
+             VALUES (?1, ?2, ?3, datetime('now'))
+             ON CONFLICT(vault, book_title) DO UPDATE
+             SET hash = excluded.hash, exported_at = excluded.exported_at",
+            params![vault_key, title, hash],
+        )
+        .map_err(|e| e.to_string())?;
+        report.written += 1;
+    }
+    Ok(report)
+}
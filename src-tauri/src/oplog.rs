@@ -0,0 +1,89 @@
+//! Journal of every change to the user's data: highlights, books, bookmarks,
+//! collections and the rest, filled by triggers (see the `v27_oplog`
+//! migration) so no write path can bypass it.
+//!
+//! Each entry holds the row as it was (`old`, for updates and deletes) and
+//! as it became (`new`, for inserts and updates), as JSON. Entries are
+//! numbered in order and never rewritten, so a reader keeps the last number
+//! it saw as a cursor and asks for what came after. Reading positions,
+//! covers and sync bookkeeping are left out; see `OPLOG_IGNORED` in
+//! [`crate::migrations`].
+
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+/// Entries older than this are dropped when the library is opened.
+const RETENTION_DAYS: i64 = 90;
+
+/// Most entries returned by one [`changes_since`] call.
+pub const MAX_PAGE: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Change {
+    pub id: i64,
+    pub table: String,
+    /// `insert`, `update` or `delete`.
+    pub op: String,
+    pub row_id: i64,
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+    pub at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChangePage {
+    pub changes: Vec<Change>,
+    /// Pass back to get the entries after these.
+    pub cursor: i64,
+    pub has_more: bool,
+}
+
+fn json(text: Option<String>) -> Option<serde_json::Value> {
+    text.and_then(|text| serde_json::from_str(&text).ok())
+}
+
+/// Reads `id, table_name, op, row_id, old, new, at`.
+pub fn change_from_row(row: &rusqlite::Row) -> rusqlite::Result<Change> {
+    Ok(Change {
+        id: row.get(0)?,
+        table: row.get(1)?,
+        op: row.get(2)?,
+        row_id: row.get(3)?,
+        old: json(row.get(4)?),
+        new: json(row.get(5)?),
+        at: row.get(6)?,
+    })
+}
+
+/// Up to `limit` entries after `cursor` (from the start without one), oldest
+/// first.
+pub fn changes_since(
+    conn: &Connection,
+    cursor: Option<i64>,
+    limit: usize,
+) -> rusqlite::Result<ChangePage> {
+    let cursor = cursor.unwrap_or(0);
+    let limit = limit.clamp(1, MAX_PAGE);
+    let mut changes = conn
+        .prepare(
+            "SELECT id, table_name, op, row_id, old, new, at FROM oplog
+             WHERE id > ?1 ORDER BY id LIMIT ?2",
+        )?
+        .query_map(params![cursor, limit as i64 + 1], change_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let has_more = changes.len() > limit;
+    changes.truncate(limit);
+    Ok(ChangePage {
+        cursor: changes.last().map_or(cursor, |change| change.id),
+        changes,
+        has_more,
+    })
+}
+
+/// Drops entries past [`RETENTION_DAYS`].
+pub fn prune(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM oplog WHERE at < strftime('%Y-%m-%d %H:%M:%f', 'now', ?1)",
+        params![format!("-{RETENTION_DAYS} days")],
+    )
+}
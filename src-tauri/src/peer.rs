@@ -0,0 +1,996 @@
+//! Sync directly between devices on the same network, with no server or
+//! cloud account in between.
+//!
+//! While the local server (see [`crate::server`]) runs, the device advertises
+//! itself over mDNS as `_tumelog._tcp`. Pairing happens once: one device
+//! shows a short-lived code from `start_pairing`, the other enters it in
+//! `pair_peer`. The two run SPAKE2 over Ristretto255 with the code as the
+//! password, so they end up with the same key only if the code was right,
+//! and a wrong guess teaches an eavesdropper or impostor nothing about the
+//! code. The initiator proves it got the key first, and only then does the
+//! responder prove the same; the key itself never crosses the network. A code
+//! allows [`MAX_ATTEMPTS`] tries before it's used up.
+//!
+//! Every sync request afterwards is signed with an HMAC of the key over a
+//! nonce, a timestamp and the body, and the reply is signed over the same
+//! nonce, so neither side acts on a message from anyone but its peer. A
+//! device's id is public in its mDNS record, so an address found by mDNS is
+//! only used once whoever answers there proves it holds the key.
+//!
+//! A sync sends the changes made since the last sync with that peer and gets
+//! the peer's changes back; both sides merge them the way WebDAV sync does
+//! (see [`crate::sync`]). Each side remembers how far its sync clock had got
+//! when it last sent the other its changes, and sends the records changed
+//! after that. Records merged in from a third device count as changes too, so
+//! they travel on whatever clocks they carry.
+
+use crate::events::{self, DataEvent};
+use crate::sync::{self, Snapshot, SyncReport};
+use crate::{run_blocking, DbState};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderName, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use hmac::{Hmac, Mac};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SERVICE_TYPE: &str = "_tumelog._tcp.local.";
+
+/// Settings key holding this device's id, created on first use.
+const DEVICE_ID_SETTING: &str = "peer.device_id";
+
+const CODE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Pairing attempts a code allows; each gives an impostor one guess at it.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// How long discovery listens for answers.
+const DISCOVERY_TIME: Duration = Duration::from_secs(3);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How far a signed request's timestamp may be from the receiver's clock.
+const CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+const PAIR_PATH: &str = "/peer/pair";
+const CONFIRM_PATH: &str = "/peer/pair/confirm";
+const HELLO_PATH: &str = "/peer/hello";
+const SYNC_PATH: &str = "/peer/sync";
+
+const DEVICE_HEADER: &str = "x-peer-device";
+const NONCE_HEADER: &str = "x-peer-nonce";
+const TIMESTAMP_HEADER: &str = "x-peer-timestamp";
+const SIGNATURE_HEADER: &str = "x-peer-signature";
+
+const INITIATOR: &str = "initiator";
+const RESPONDER: &str = "responder";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiscoveredPeer {
+    pub device_id: String,
+    pub name: String,
+    /// `ip:port` to pair with.
+    pub address: String,
+    pub paired: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PairedPeer {
+    pub device_id: String,
+    pub name: String,
+    pub address: Option<String>,
+    pub paired_at: String,
+    pub last_synced_at: Option<String>,
+}
+
+/// The pairing code this device is showing, if any, and the pairing waiting
+/// for the other device to prove it used that code.
+#[derive(Clone, Default)]
+pub struct Pairing(Arc<Mutex<PairingState>>);
+
+#[derive(Default)]
+struct PairingState {
+    code: Option<ShownCode>,
+    pending: Option<PendingPair>,
+}
+
+struct ShownCode {
+    code: String,
+    shown: Instant,
+    attempts: u32,
+}
+
+struct PendingPair {
+    device_id: String,
+    name: String,
+    key: Vec<u8>,
+    started: Instant,
+}
+
+impl Pairing {
+    /// A new six-digit code, replacing any earlier one.
+    pub fn start(&self) -> Result<String, String> {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        *self.0.lock().map_err(|e| e.to_string())? = PairingState {
+            code: Some(ShownCode {
+                code: code.clone(),
+                shown: Instant::now(),
+                attempts: 0,
+            }),
+            pending: None,
+        };
+        Ok(code)
+    }
+
+    /// The code being shown for another attempt, unless it has expired or
+    /// run out of attempts. A new attempt replaces any pairing in progress.
+    fn attempt(&self) -> Result<Option<String>, String> {
+        let mut state = self.0.lock().map_err(|e| e.to_string())?;
+        state.pending = None;
+        if state
+            .code
+            .as_ref()
+            .is_some_and(|shown| shown.shown.elapsed() >= CODE_LIFETIME)
+        {
+            state.code = None;
+        }
+        let Some(shown) = state.code.as_mut() else {
+            return Ok(None);
+        };
+        shown.attempts += 1;
+        let code = shown.code.clone();
+        if shown.attempts >= MAX_ATTEMPTS {
+            state.code = None;
+        }
+        Ok(Some(code))
+    }
+
+    /// Forgets the code once it has paired a device.
+    fn used(&self) -> Result<(), String> {
+        self.0.lock().map_err(|e| e.to_string())?.code = None;
+        Ok(())
+    }
+
+    fn set_pending(&self, pending: PendingPair) -> Result<(), String> {
+        self.0.lock().map_err(|e| e.to_string())?.pending = Some(pending);
+        Ok(())
+    }
+
+    /// The pairing `device_id` started, if it's still waiting. Any attempt
+    /// uses it up.
+    fn take_pending(&self, device_id: &str) -> Result<Option<PendingPair>, String> {
+        Ok(self
+            .0
+            .lock()
+            .map_err(|e| e.to_string())?
+            .pending
+            .take()
+            .filter(|pending| {
+                pending.device_id == device_id && pending.started.elapsed() < CODE_LIFETIME
+            }))
+    }
+}
+
+/// This device's id, which peers know it by.
+pub fn device_id(conn: &Connection) -> Result<String, String> {
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![DEVICE_ID_SETTING],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let id = random_token(12);
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)",
+        params![DEVICE_ID_SETTING, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+/// Name shown to other devices.
+pub fn device_name() -> String {
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "Tumelog".to_string())
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buffer = vec![0; bytes];
+    OsRng.fill_bytes(&mut buffer);
+    encode(&buffer)
+}
+
+fn encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn decode(encoded: &str) -> Option<Vec<u8>> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(encoded)
+        .ok()
+}
+
+fn decode_point(encoded: &str) -> Option<RistrettoPoint> {
+    let bytes: [u8; 32] = decode(encoded)?.try_into().ok()?;
+    CompressedRistretto(bytes).decompress()
+}
+
+fn encode_point(point: &RistrettoPoint) -> String {
+    encode(point.compress().as_bytes())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// ---------------------------------------------------------------------------
+// Keys and signatures
+// ---------------------------------------------------------------------------
+
+/// HMAC-SHA256 of `key` over `parts`, each prefixed with its length so that
+/// different lists of parts never sign alike.
+fn mac(key: &[u8], parts: &[&[u8]]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in parts {
+        mac.update(&(part.len() as u64).to_be_bytes());
+        mac.update(part);
+    }
+    mac
+}
+
+fn signature(mac: HmacSha256) -> String {
+    encode(&mac.finalize().into_bytes())
+}
+
+fn verify(mac: HmacSha256, signature: &str) -> bool {
+    decode(signature).is_some_and(|signature| mac.verify_slice(&signature).is_ok())
+}
+
+/// A SPAKE2 secret scalar, uniformly random.
+fn random_scalar() -> Scalar {
+    let mut bytes = [0; 64];
+    OsRng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+/// The code as a SPAKE2 password.
+fn code_scalar(code: &str) -> Scalar {
+    Scalar::hash_from_bytes::<Sha512>(code.trim().as_bytes())
+}
+
+/// The point `role`'s message is blinded with (M and N in SPAKE2), hashed
+/// so that nobody knows its discrete logarithm.
+fn blinding_point(role: &str) -> RistrettoPoint {
+    RistrettoPoint::hash_from_bytes::<Sha512>(format!("tumelog pairing {role}").as_bytes())
+}
+
+/// What `role` sends the other device: its public point blinded by the code.
+fn pake_message(secret: &Scalar, code: &str, role: &str) -> RistrettoPoint {
+    secret * RISTRETTO_BASEPOINT_POINT + code_scalar(code) * blinding_point(role)
+}
+
+/// The point both devices arrive at if they used the same code, from the
+/// message the device in `their_role` sent.
+fn pake_shared(
+    secret: &Scalar,
+    code: &str,
+    their_role: &str,
+    their_message: &RistrettoPoint,
+) -> Option<RistrettoPoint> {
+    let shared = secret * (their_message - code_scalar(code) * blinding_point(their_role));
+    (shared != RistrettoPoint::identity()).then_some(shared)
+}
+
+/// The key a pairing leaves both devices with: the SPAKE2 shared point mixed
+/// with the code and with both devices' ids and messages.
+fn pairing_key(
+    shared: &RistrettoPoint,
+    code: &str,
+    initiator: (&str, &RistrettoPoint),
+    responder: (&str, &RistrettoPoint),
+) -> Vec<u8> {
+    mac(
+        shared.compress().as_bytes(),
+        &[
+            b"pair",
+            code.trim().as_bytes(),
+            initiator.0.as_bytes(),
+            initiator.1.compress().as_bytes(),
+            responder.0.as_bytes(),
+            responder.1.compress().as_bytes(),
+        ],
+    )
+    .finalize()
+    .into_bytes()
+    .to_vec()
+}
+
+/// What a device sends to prove it derived `key`, as `role` in the pairing.
+fn key_proof(key: &[u8], role: &str) -> HmacSha256 {
+    mac(key, &[b"proof", role.as_bytes()])
+}
+
+fn request_mac(
+    key: &[u8],
+    device_id: &str,
+    path: &str,
+    nonce: &str,
+    timestamp: &str,
+    body: &[u8],
+) -> HmacSha256 {
+    mac(
+        key,
+        &[
+            b"request",
+            device_id.as_bytes(),
+            path.as_bytes(),
+            nonce.as_bytes(),
+            timestamp.as_bytes(),
+            &Sha256::digest(body),
+        ],
+    )
+}
+
+/// A reply is signed over its request's nonce, so it can't be replayed as the
+/// answer to another request.
+fn response_mac(key: &[u8], nonce: &str, body: &[u8]) -> HmacSha256 {
+    mac(key, &[b"response", nonce.as_bytes(), &Sha256::digest(body)])
+}
+
+fn stored_key(conn: &Connection, device_id: &str) -> Result<Option<Vec<u8>>, String> {
+    let key: Option<String> = conn
+        .query_row(
+            "SELECT secret FROM peers WHERE device_id = ?1",
+            params![device_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(key.as_deref().and_then(decode))
+}
+
+// ---------------------------------------------------------------------------
+// Discovery
+// ---------------------------------------------------------------------------
+
+/// This device's mDNS advertisement; withdrawn when dropped.
+pub struct Advertisement(ServiceDaemon);
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown();
+    }
+}
+
+/// Advertises this device as reachable on `port`.
+pub fn advertise(device_id: &str, port: u16) -> Result<Advertisement, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    let name = device_name();
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        device_id,
+        &format!("{device_id}.local."),
+        "",
+        port,
+        &[("id", device_id), ("name", name.as_str())][..],
+    )
+    .map_err(|e| e.to_string())?
+    .enable_addr_auto();
+    daemon.register(service).map_err(|e| e.to_string())?;
+    Ok(Advertisement(daemon))
+}
+
+/// Devices advertising themselves on the network, other than this one.
+/// Blocks for [`DISCOVERY_TIME`]. Anyone can advertise any id, so the
+/// results are only claims.
+pub fn discover(own_id: &str) -> Result<Vec<(String, String, SocketAddr)>, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| e.to_string())?;
+    let events = daemon.browse(SERVICE_TYPE).map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + DISCOVERY_TIME;
+    let mut found: Vec<(String, String, SocketAddr)> = Vec::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = events.recv_timeout(left) else {
+            break;
+        };
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        let Some(id) = info.get_property_val_str("id").map(str::to_string) else {
+            continue;
+        };
+        if id == own_id || found.iter().any(|(known, _, _)| *known == id) {
+            continue;
+        }
+        // Link-local IPv6 addresses need a scope to connect to; IPv4 is simpler.
+        let ip = info
+            .get_addresses()
+            .iter()
+            .copied()
+            .min_by_key(|ip| !matches!(ip, IpAddr::V4(_)));
+        if let Some(ip) = ip {
+            let name = info.get_property_val_str("name").unwrap_or(&id).to_string();
+            found.push((id, name, SocketAddr::new(ip, info.get_port())));
+        }
+    }
+    let _ = daemon.shutdown();
+    Ok(found)
+}
+
+// ---------------------------------------------------------------------------
+// Server side
+// ---------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize)]
+struct PairRequest {
+    device_id: String,
+    name: String,
+    /// The initiator's SPAKE2 message.
+    message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PairResponse {
+    device_id: String,
+    name: String,
+    /// The responder's SPAKE2 message.
+    message: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PairConfirm {
+    device_id: String,
+    /// Proves the initiator derived the key, and so used the right code.
+    proof: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PairConfirmed {
+    /// Proves the responder derived the key too.
+    proof: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncRequest {
+    /// The responder's `synced_until` from the last sync; its changes since
+    /// then are sent back. `None` asks for everything.
+    since: Option<u64>,
+    changes: Snapshot,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncResponse {
+    /// The responder's sync clock counter when it collected `changes`.
+    synced_until: u64,
+    changes: Snapshot,
+}
+
+/// Nonces of recently accepted requests, so none is accepted twice.
+#[derive(Clone, Default)]
+struct SeenNonces(Arc<Mutex<HashMap<String, Instant>>>);
+
+impl SeenNonces {
+    /// Records `nonce`; false if it had been used already.
+    fn first_use(&self, nonce: String) -> Result<bool, String> {
+        let mut seen = self.0.lock().map_err(|e| e.to_string())?;
+        // Anything older fails the timestamp check anyway.
+        seen.retain(|_, accepted| accepted.elapsed() < 2 * CLOCK_SKEW);
+        Ok(seen.insert(nonce, Instant::now()).is_none())
+    }
+}
+
+#[derive(Clone)]
+struct PeerApi {
+    app: tauri::AppHandle,
+    db: DbState,
+    pairing: Pairing,
+    seen: SeenNonces,
+}
+
+/// A request that a paired device signed, with what's needed to sign the
+/// reply.
+struct Signed {
+    key: Vec<u8>,
+    nonce: String,
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> &'a str {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// Checks that a request to `path` was signed by a paired device, recently,
+/// and hasn't been seen before.
+fn authenticate(
+    conn: &Connection,
+    seen: &SeenNonces,
+    path: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Option<Signed>, String> {
+    let device_id = header_str(headers, DEVICE_HEADER);
+    let nonce = header_str(headers, NONCE_HEADER);
+    let timestamp = header_str(headers, TIMESTAMP_HEADER);
+    let recent = timestamp
+        .parse::<u64>()
+        .is_ok_and(|sent| sent.abs_diff(unix_time()) <= CLOCK_SKEW.as_secs());
+    if nonce.is_empty() || !recent {
+        return Ok(None);
+    }
+    let Some(key) = stored_key(conn, device_id)? else {
+        return Ok(None);
+    };
+    let mac = request_mac(&key, device_id, path, nonce, timestamp, body);
+    if !verify(mac, header_str(headers, SIGNATURE_HEADER))
+        || !seen.first_use(format!("{device_id}:{nonce}"))?
+    {
+        return Ok(None);
+    }
+    Ok(Some(Signed {
+        key,
+        nonce: nonce.to_string(),
+    }))
+}
+
+/// `body` as JSON, signed as the reply to `request`.
+fn signed_json(request: &Signed, body: &impl Serialize) -> Response {
+    let body = match serde_json::to_vec(body) {
+        Ok(body) => body,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+    let signature = signature(response_mac(&request.key, &request.nonce, &body));
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (HeaderName::from_static(SIGNATURE_HEADER), signature),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// First half of pairing: answers the initiator's SPAKE2 message with this
+/// device's. Nothing in the answer depends on the code in a way that could be
+/// checked without another attempt.
+async fn handle_pair(State(api): State<PeerApi>, Json(request): Json<PairRequest>) -> Response {
+    let code = match api.pairing.attempt() {
+        Ok(Some(code)) => code,
+        Ok(None) => return error(StatusCode::FORBIDDEN, "Wrong or expired pairing code"),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let Some(their_message) = decode_point(&request.message) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid pairing message");
+    };
+    let db = api.db.clone();
+    let own_id = match run_blocking(move || device_id(&*db.conn()?)).await {
+        Ok(id) => id,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let secret = random_scalar();
+    let message = pake_message(&secret, &code, RESPONDER);
+    let Some(shared) = pake_shared(&secret, &code, INITIATOR, &their_message) else {
+        return error(StatusCode::BAD_REQUEST, "Invalid pairing message");
+    };
+    let key = pairing_key(
+        &shared,
+        &code,
+        (&request.device_id, &their_message),
+        (&own_id, &message),
+    );
+    let pending = PendingPair {
+        device_id: request.device_id,
+        name: request.name,
+        key,
+        started: Instant::now(),
+    };
+    if let Err(e) = api.pairing.set_pending(pending) {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    Json(PairResponse {
+        device_id: own_id,
+        name: device_name(),
+        message: encode_point(&message),
+    })
+    .into_response()
+}
+
+/// Second half of pairing: the initiator proves it derived the same key, and
+/// only then is it stored and this device proves the same in return.
+async fn handle_pair_confirm(
+    State(api): State<PeerApi>,
+    Json(request): Json<PairConfirm>,
+) -> Response {
+    let pending = match api.pairing.take_pending(&request.device_id) {
+        Ok(pending) => pending,
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    };
+    let Some(pending) =
+        pending.filter(|pending| verify(key_proof(&pending.key, INITIATOR), &request.proof))
+    else {
+        return error(StatusCode::FORBIDDEN, "Wrong or expired pairing code");
+    };
+    if let Err(e) = api.pairing.used() {
+        return error(StatusCode::INTERNAL_SERVER_ERROR, e);
+    }
+    let proof = signature(key_proof(&pending.key, RESPONDER));
+    let db = api.db.clone();
+    let stored = run_blocking(move || {
+        db.conn()?
+            .execute(
+                "INSERT INTO peers (device_id, name, secret) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(device_id) DO UPDATE SET
+                     name = excluded.name, secret = excluded.secret,
+                     sent_until = NULL, received_until = NULL, paired_at = datetime('now')",
+                params![pending.device_id, pending.name, encode(&pending.key)],
+            )
+            .map_err(|e| e.to_string())?;
+        log::info!("Paired with {} ({})", pending.name, pending.device_id);
+        Ok(())
+    })
+    .await;
+    match stored {
+        Ok(()) => Json(PairConfirmed { proof }).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// Lets a device check that whoever answers at an address holds the key it
+/// shares with the peer it's looking for.
+async fn handle_hello(State(api): State<PeerApi>, headers: HeaderMap, body: Bytes) -> Response {
+    let db = api.db.clone();
+    let seen = api.seen.clone();
+    let result =
+        run_blocking(move || authenticate(&*db.conn()?, &seen, HELLO_PATH, &headers, &body)).await;
+    match result {
+        Ok(Some(signed)) => signed_json(&signed, &serde_json::json!({})),
+        Ok(None) => error(StatusCode::UNAUTHORIZED, "Unknown device or bad signature"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn handle_sync(State(api): State<PeerApi>, headers: HeaderMap, body: Bytes) -> Response {
+    let db = api.db.clone();
+    let seen = api.seen.clone();
+    let result = run_blocking(move || {
+        let mut conn = db.conn()?;
+        let Some(signed) = authenticate(&conn, &seen, SYNC_PATH, &headers, &body)? else {
+            return Ok(None);
+        };
+        let request: SyncRequest =
+            serde_json::from_slice(&body).map_err(|e| format!("Invalid sync request: {e}"))?;
+        // Collected before applying the peer's changes, so they aren't sent
+        // straight back.
+        let synced_until = sync::current_counter(&conn).map_err(|e| e.to_string())?;
+        let changes = sync::local_changes(&conn, request.since).map_err(|e| e.to_string())?;
+        let pulled = sync::apply_changes(&mut conn, request.changes)?;
+        Ok(Some((
+            signed,
+            SyncResponse {
+                synced_until,
+                changes,
+            },
+            pulled,
+        )))
+    })
+    .await;
+    match result {
+        Ok(Some((signed, response, pulled))) => {
+            if pulled > 0 {
+                events::emit(&api.app, DataEvent::LibraryReloaded);
+            }
+            signed_json(&signed, &response)
+        }
+        Ok(None) => error(StatusCode::UNAUTHORIZED, "Unknown device or bad signature"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+/// Routes other devices pair and sync through. They authenticate themselves,
+/// so they sit outside the API token check.
+pub fn routes(app: tauri::AppHandle, db: DbState, pairing: Pairing) -> Router {
+    Router::new()
+        .route(PAIR_PATH, post(handle_pair))
+        .route(CONFIRM_PATH, post(handle_pair_confirm))
+        .route(HELLO_PATH, post(handle_hello))
+        .route(SYNC_PATH, post(handle_sync))
+        .with_state(PeerApi {
+            app,
+            db,
+            pairing,
+            seen: SeenNonces::default(),
+        })
+}
+
+// ---------------------------------------------------------------------------
+// Client side
+// ---------------------------------------------------------------------------
+
+/// Sends `request` and returns the reply's headers and body, or the error
+/// the peer gave.
+async fn receive(
+    request: reqwest::RequestBuilder,
+) -> Result<(reqwest::header::HeaderMap, Vec<u8>), String> {
+    let response = request
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        let message = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        return Err(message);
+    }
+    Ok((headers, bytes.to_vec()))
+}
+
+async fn send_json<Req: Serialize, Res: for<'de> Deserialize<'de>>(
+    url: String,
+    body: &Req,
+) -> Result<Res, String> {
+    let request = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(body).map_err(|e| e.to_string())?);
+    let (_, bytes) = receive(request).await?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid response from peer: {e}"))
+}
+
+/// Posts `body` to `path` at `address`, signed with `key` as coming from
+/// `own_id`, and checks that the reply is signed with `key` too.
+async fn send_signed<Req: Serialize, Res: for<'de> Deserialize<'de>>(
+    address: &str,
+    path: &str,
+    own_id: &str,
+    key: &[u8],
+    body: &Req,
+) -> Result<Res, String> {
+    let body = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+    let nonce = random_token(16);
+    let timestamp = unix_time().to_string();
+    let request = reqwest::Client::new()
+        .post(format!("http://{address}{path}"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(DEVICE_HEADER, own_id)
+        .header(NONCE_HEADER, &nonce)
+        .header(TIMESTAMP_HEADER, &timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            signature(request_mac(key, own_id, path, &nonce, &timestamp, &body)),
+        )
+        .body(body);
+    let (headers, bytes) = receive(request).await?;
+    let signed = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|signature| verify(response_mac(key, &nonce, &bytes), signature));
+    if !signed {
+        return Err(format!(
+            "The device at {address} couldn't prove it is the paired device"
+        ));
+    }
+    serde_json::from_slice(&bytes).map_err(|e| format!("Invalid response from peer: {e}"))
+}
+
+fn peer_from_row(row: &rusqlite::Row) -> rusqlite::Result<PairedPeer> {
+    Ok(PairedPeer {
+        device_id: row.get(0)?,
+        name: row.get(1)?,
+        address: row.get(2)?,
+        paired_at: row.get(3)?,
+        last_synced_at: row.get(4)?,
+    })
+}
+
+const PEER_COLUMNS: &str = "device_id, name, address, paired_at, last_synced_at";
+
+pub fn list(conn: &Connection) -> rusqlite::Result<Vec<PairedPeer>> {
+    conn.prepare(&format!("SELECT {PEER_COLUMNS} FROM peers ORDER BY name"))?
+        .query_map([], peer_from_row)?
+        .collect()
+}
+
+/// Pairs with the device at `address` using the code it shows.
+pub async fn pair(db: DbState, address: String, code: String) -> Result<PairedPeer, String> {
+    let own_id = run_blocking({
+        let db = db.clone();
+        move || device_id(&*db.conn()?)
+    })
+    .await?;
+    let secret = random_scalar();
+    let message = pake_message(&secret, &code, INITIATOR);
+    let request = PairRequest {
+        device_id: own_id.clone(),
+        name: device_name(),
+        message: encode_point(&message),
+    };
+    let response: PairResponse =
+        send_json(format!("http://{address}{PAIR_PATH}"), &request).await?;
+    let bad_message = || "Invalid response from peer: bad pairing message".to_string();
+    let their_message = decode_point(&response.message).ok_or_else(bad_message)?;
+    let shared = pake_shared(&secret, &code, RESPONDER, &their_message).ok_or_else(bad_message)?;
+    let key = pairing_key(
+        &shared,
+        &code,
+        (&own_id, &message),
+        (&response.device_id, &their_message),
+    );
+    let confirm = PairConfirm {
+        device_id: own_id,
+        proof: signature(key_proof(&key, INITIATOR)),
+    };
+    let confirmed: PairConfirmed =
+        send_json(format!("http://{address}{CONFIRM_PATH}"), &confirm).await?;
+    if !verify(key_proof(&key, RESPONDER), &confirmed.proof) {
+        return Err(format!(
+            "The device at {address} couldn't prove it used the same code"
+        ));
+    }
+    run_blocking(move || {
+        let conn = db.conn()?;
+        conn.execute(
+            "INSERT INTO peers (device_id, name, secret, address) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(device_id) DO UPDATE SET
+                 name = excluded.name, secret = excluded.secret, address = excluded.address,
+                 sent_until = NULL, received_until = NULL, paired_at = datetime('now')",
+            params![response.device_id, response.name, encode(&key), address],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.query_row(
+            &format!("SELECT {PEER_COLUMNS} FROM peers WHERE device_id = ?1"),
+            params![response.device_id],
+            peer_from_row,
+        )
+        .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Where to reach `peer_id`. The address it last answered at is kept, unless
+/// mDNS finds the device somewhere else and whoever answers there proves it
+/// holds `key`; anyone on the network can claim a device's id.
+async fn locate(
+    own_id: &str,
+    peer_id: &str,
+    key: &[u8],
+    stored: Option<String>,
+) -> Result<String, String> {
+    let discovered = run_blocking({
+        let own_id = own_id.to_string();
+        let peer_id = peer_id.to_string();
+        move || {
+            Ok(discover(&own_id)?
+                .into_iter()
+                .find(|(id, _, _)| *id == peer_id)
+                .map(|(_, _, address)| address.to_string()))
+        }
+    })
+    .await?;
+    match discovered {
+        Some(found) if stored.as_ref() != Some(&found) => {
+            let hello = serde_json::json!({});
+            match send_signed::<_, serde_json::Value>(&found, HELLO_PATH, own_id, key, &hello).await
+            {
+                Ok(_) => Ok(found),
+                Err(e) => {
+                    log::warn!("Not syncing with {peer_id} at {found}: {e}");
+                    stored.ok_or(e)
+                }
+            }
+        }
+        found => stored
+            .or(found)
+            .ok_or_else(|| "The device isn't on the network".to_string()),
+    }
+}
+
+/// Exchanges changes with the paired device `peer_id`. With `full`, every
+/// record is exchanged rather than just recent changes.
+pub async fn sync_with(
+    app: &tauri::AppHandle,
+    db: DbState,
+    peer_id: String,
+    full: bool,
+) -> Result<SyncReport, String> {
+    let (request, own_id, key, stored_address, sent_until) = run_blocking({
+        let db = db.clone();
+        let peer_id = peer_id.clone();
+        move || {
+            let conn = db.conn()?;
+            let (secret, stored_address, sent_until, received_until): (
+                String,
+                Option<String>,
+                Option<u64>,
+                Option<u64>,
+            ) = conn
+                .query_row(
+                    "SELECT secret, address, sent_until, received_until FROM peers
+                     WHERE device_id = ?1",
+                    params![peer_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Not paired with {peer_id}"))?;
+            let key = decode(&secret).ok_or("Pair with the device again to sync")?;
+            // Read before collecting changes, so edits made meanwhile go
+            // with the next sync rather than being skipped.
+            let counter = sync::current_counter(&conn).map_err(|e| e.to_string())?;
+            let (sent_until, received_until) = if full {
+                (None, None)
+            } else {
+                (sent_until, received_until)
+            };
+            let changes = sync::local_changes(&conn, sent_until).map_err(|e| e.to_string())?;
+            let request = SyncRequest {
+                since: received_until,
+                changes,
+            };
+            Ok((request, device_id(&conn)?, key, stored_address, counter))
+        }
+    })
+    .await?;
+
+    let address = locate(&own_id, &peer_id, &key, stored_address).await?;
+    let pushed = request.changes.record_count();
+    let response: SyncResponse = send_signed(&address, SYNC_PATH, &own_id, &key, &request).await?;
+
+    let pulled = run_blocking(move || {
+        let mut conn = db.conn()?;
+        let pulled = sync::apply_changes(&mut conn, response.changes)?;
+        conn.execute(
+            "UPDATE peers SET address = ?2, sent_until = ?3, received_until = ?4,
+                 last_synced_at = datetime('now')
+             WHERE device_id = ?1",
+            params![
+                peer_id,
+                address,
+                sent_until as i64,
+                response.synced_until as i64
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(pulled)
+    })
+    .await?;
+    if pulled > 0 {
+        events::emit(app, DataEvent::LibraryReloaded);
+    }
+    Ok(SyncReport { pulled, pushed })
+}
@@ -0,0 +1,99 @@
+//! Separate libraries for separate purposes (work research, personal
+//! fiction, ...), each with its own database, book folder and backups.
+//!
+//! The default profile lives directly in the app data dir, where the library
+//! always was; others live in `<app data>/profiles/<name>`. The active
+//! profile's name is kept in `<app data>/profile` so it survives restarts.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT: &str = "default";
+
+const CURRENT_FILE: &str = "profile";
+
+const MAX_NAME_CHARS: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub active: bool,
+}
+
+/// Folder holding the profile's database, books and backups.
+pub fn dir(app_dir: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT {
+        app_dir.to_path_buf()
+    } else {
+        app_dir.join("profiles").join(name)
+    }
+}
+
+/// Moves the app data folder of versions that had the identifier `legacy`
+/// to `app_dir`, unless there's already one there.
+pub fn move_legacy_app_dir(app_dir: &Path, legacy: &str) -> std::io::Result<()> {
+    let legacy_dir = app_dir.with_file_name(legacy);
+    if app_dir.exists() || !legacy_dir.is_dir() {
+        return Ok(());
+    }
+    std::fs::rename(legacy_dir, app_dir)
+}
+
+/// Profile names become folder names, so only letters, digits, spaces, `-`
+/// and `_` are allowed.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.trim().is_empty()
+        && name == name.trim()
+        && name.chars().count() <= MAX_NAME_CHARS
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Profile names must be 1-{MAX_NAME_CHARS} letters, digits, spaces, '-' or '_'"
+        ))
+    }
+}
+
+pub fn exists(app_dir: &Path, name: &str) -> bool {
+    name == DEFAULT || dir(app_dir, name).is_dir()
+}
+
+/// The profile to open at startup, falling back to the default if the saved
+/// one has gone missing.
+pub fn current(app_dir: &Path) -> String {
+    std::fs::read_to_string(app_dir.join(CURRENT_FILE))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| validate_name(name).is_ok() && exists(app_dir, name))
+        .unwrap_or_else(|| DEFAULT.to_string())
+}
+
+pub fn set_current(app_dir: &Path, name: &str) -> Result<(), String> {
+    std::fs::write(app_dir.join(CURRENT_FILE), name).map_err(|e| e.to_string())
+}
+
+/// Every profile, the default first and the rest by name.
+pub fn list(app_dir: &Path, active: &str) -> Result<Vec<Profile>, String> {
+    let mut names = Vec::new();
+    let profiles_dir = app_dir.join("profiles");
+    if profiles_dir.is_dir() {
+        for entry in std::fs::read_dir(&profiles_dir).map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() && validate_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+    }
+    names.sort_by_key(|name| name.to_lowercase());
+    Ok(std::iter::once(DEFAULT.to_string())
+        .chain(names)
+        .map(|name| Profile {
+            active: name == active,
+            name,
+        })
+        .collect())
+}
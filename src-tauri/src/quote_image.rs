@@ -0,0 +1,283 @@
+//! Shareable PNG cards of a highlight: the quote, wrapped and sized to fit,
+//! above the book title and author.
+//!
+//! Text is drawn with a font from the system (Georgia, DejaVu Serif and
+//! similar serif faces are tried in turn) unless the style names one.
+
+use image::{Rgb, RgbImage};
+use rusttype::{point, Font, Scale};
+use serde::{Deserialize, Serialize};
+
+/// Square, the size most social networks display without cropping.
+const SIZE: u32 = 1080;
+const PADDING: f32 = 110.0;
+const ACCENT_WIDTH: u32 = 10;
+
+const MAX_FONT_SIZE: f32 = 64.0;
+const MIN_FONT_SIZE: f32 = 26.0;
+const LINE_HEIGHT: f32 = 1.4;
+
+const FONT_CANDIDATES: &[&str] = &[
+    // macOS
+    "/System/Library/Fonts/Supplemental/Georgia.ttf",
+    "/Library/Fonts/Georgia.ttf",
+    // Windows
+    "C:\\Windows\\Fonts\\georgia.ttf",
+    "C:\\Windows\\Fonts\\times.ttf",
+    // Linux
+    "/usr/share/fonts/truetype/dejavu/DejaVuSerif.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSerif.ttf",
+    "/usr/share/fonts/TTF/DejaVuSerif.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSerif-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationSerif-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSerif-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSerif-Regular.ttf",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteTheme {
+    #[default]
+    Light,
+    Dark,
+    Sepia,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct QuoteStyle {
+    #[serde(default)]
+    pub theme: QuoteTheme,
+    /// A TrueType/OpenType font file to use instead of the system serif.
+    #[serde(default)]
+    pub font_path: Option<String>,
+}
+
+struct Palette {
+    background: [u8; 3],
+    text: [u8; 3],
+    muted: [u8; 3],
+}
+
+impl QuoteTheme {
+    fn palette(self) -> Palette {
+        match self {
+            QuoteTheme::Light => Palette {
+                background: [253, 252, 249],
+                text: [31, 41, 55],
+                muted: [107, 114, 128],
+            },
+            QuoteTheme::Dark => Palette {
+                background: [24, 24, 27],
+                text: [244, 244, 245],
+                muted: [161, 161, 170],
+            },
+            QuoteTheme::Sepia => Palette {
+                background: [244, 236, 216],
+                text: [67, 52, 34],
+                muted: [130, 108, 82],
+            },
+        }
+    }
+}
+
+fn load_font(path: Option<&str>) -> Result<Font<'static>, String> {
+    let data = match path {
+        Some(path) => std::fs::read(path).map_err(|e| format!("{path}: {e}"))?,
+        None => FONT_CANDIDATES
+            .iter()
+            .find_map(|p| std::fs::read(p).ok())
+            .ok_or("No serif font found on this system; choose a font file")?,
+    };
+    Font::try_from_vec(data).ok_or_else(|| "Not a usable font file".to_string())
+}
+
+/// `#rgb` or `#rrggbb`.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let mut rgb = [0; 3];
+            for (i, c) in hex.chars().enumerate() {
+                rgb[i] = channel(&c.to_string())? * 17;
+            }
+            Some(rgb)
+        }
+        6 => Some([
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ]),
+        _ => None,
+    }
+}
+
+fn text_width(font: &Font, scale: Scale, text: &str) -> f32 {
+    font.layout(text, scale, point(0.0, 0.0))
+        .last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0)
+}
+
+/// Greedy word wrap; words wider than a line are split between characters.
+fn wrap(font: &Font, scale: Scale, text: &str, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{line} {word}")
+            };
+            if text_width(font, scale, &candidate) <= width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                line.push(c);
+                if text_width(font, scale, &line) > width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::replace(&mut line, c.to_string()));
+                }
+            }
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn draw_text(
+    image: &mut RgbImage,
+    font: &Font,
+    scale: Scale,
+    x: f32,
+    baseline: f32,
+    text: &str,
+    color: [u8; 3],
+) {
+    for glyph in font.layout(text, scale, point(x, baseline)) {
+        let Some(bounds) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        glyph.draw(|gx, gy, coverage| {
+            let (px, py) = (bounds.min.x + gx as i32, bounds.min.y + gy as i32);
+            if px < 0 || py < 0 || px >= image.width() as i32 || py >= image.height() as i32 {
+                return;
+            }
+            let pixel = image.get_pixel_mut(px as u32, py as u32);
+            for (channel, target) in pixel.0.iter_mut().zip(color) {
+                *channel =
+                    (*channel as f32 * (1.0 - coverage) + target as f32 * coverage).round() as u8;
+            }
+        });
+    }
+}
+
+/// Renders the card as PNG bytes.
+pub fn render(
+    text: &str,
+    book_title: &str,
+    author: Option<&str>,
+    color: &str,
+    style: &QuoteStyle,
+) -> Result<Vec<u8>, String> {
+    let font = load_font(style.font_path.as_deref())?;
+    let palette = style.theme.palette();
+    let accent = parse_color(color).unwrap_or(palette.muted);
+    let mut image = RgbImage::from_pixel(SIZE, SIZE, Rgb(palette.background));
+
+    let width = SIZE as f32 - 2.0 * PADDING;
+    let available = SIZE as f32 - 2.0 * PADDING;
+    let attribution = match author.filter(|a| !a.trim().is_empty()) {
+        Some(author) => format!("— {book_title}, {}", author.trim()),
+        None => format!("— {book_title}"),
+    };
+    let text = format!(
+        "“{}”",
+        text.trim()
+            .trim_matches(|c| c == '"' || c == '“' || c == '”')
+    );
+
+    // The largest font size at which everything fits; at the smallest size,
+    // overlong quotes are cut off with an ellipsis.
+    let mut size = MAX_FONT_SIZE;
+    let (lines, attribution_lines, size) = loop {
+        let scale = Scale::uniform(size);
+        let small = Scale::uniform((size * 0.6).max(MIN_FONT_SIZE * 0.8));
+        let mut lines = wrap(&font, scale, &text, width);
+        let attribution_lines = wrap(&font, small, &attribution, width);
+        let height = lines.len() as f32 * size * LINE_HEIGHT
+            + size
+            + attribution_lines.len() as f32 * small.y * LINE_HEIGHT;
+        if height <= available {
+            break (lines, attribution_lines, size);
+        }
+        if size <= MIN_FONT_SIZE {
+            let room = available - size - attribution_lines.len() as f32 * small.y * LINE_HEIGHT;
+            let keep = ((room / (size * LINE_HEIGHT)) as usize).max(1);
+            lines.truncate(keep);
+            if let Some(last) = lines.last_mut() {
+                last.push('…');
+            }
+            break (lines, attribution_lines, size);
+        }
+        size = (size - 2.0).max(MIN_FONT_SIZE);
+    };
+
+    let scale = Scale::uniform(size);
+    let small = Scale::uniform((size * 0.6).max(MIN_FONT_SIZE * 0.8));
+    let ascent = font.v_metrics(scale).ascent;
+    let small_ascent = font.v_metrics(small).ascent;
+    let height = lines.len() as f32 * size * LINE_HEIGHT
+        + size
+        + attribution_lines.len() as f32 * small.y * LINE_HEIGHT;
+    let top = ((SIZE as f32 - height) / 2.0).max(PADDING);
+
+    let quote_height = lines.len() as f32 * size * LINE_HEIGHT;
+    let bar_x = (PADDING - 40.0) as u32;
+    for y in top as u32..(top + quote_height) as u32 {
+        for x in bar_x..bar_x + ACCENT_WIDTH {
+            image.put_pixel(x, y, Rgb(accent));
+        }
+    }
+
+    let mut baseline = top + ascent;
+    for line in &lines {
+        draw_text(
+            &mut image,
+            &font,
+            scale,
+            PADDING,
+            baseline,
+            line,
+            palette.text,
+        );
+        baseline += size * LINE_HEIGHT;
+    }
+    baseline += size - size * LINE_HEIGHT + small_ascent;
+    for line in &attribution_lines {
+        draw_text(
+            &mut image,
+            &font,
+            small,
+            PADDING,
+            baseline,
+            line,
+            palette.muted,
+        );
+        baseline += small.y * LINE_HEIGHT;
+    }
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
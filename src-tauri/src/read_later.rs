@@ -0,0 +1,247 @@
+//! Reading lists exported from Pocket and Instapaper, to be saved again as
+//! articles along with their highlights.
+//!
+//! Pocket exports come as a zip of `part_*.csv` files (`title,url,time_added,
+//! tags,status`) with highlights in `annotations/part_*.json`, or from older
+//! accounts as `ril_export.html`, a list of links. Instapaper exports a CSV
+//! (`URL,Title,Selection,Folder,Timestamp`) whose selection is the text the
+//! reader highlighted when saving.
+
+use crate::db;
+use crate::epub::Epub;
+use crate::models::HighlightStyle;
+use crate::reanchor;
+use kuchikiki::traits::TendrilSink;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// An article in a read-later export.
+#[derive(Debug, Clone, Default)]
+pub struct SavedItem {
+    pub url: String,
+    pub title: String,
+    /// When it was saved, as seconds since the Unix epoch.
+    pub added_at: Option<i64>,
+    pub highlights: Vec<String>,
+}
+
+/// Reads a Pocket export (zip, HTML or CSV) or an Instapaper CSV.
+pub fn read_export(path: &Path) -> Result<Vec<SavedItem>, String> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "zip" => pocket_zip(std::fs::File::open(path).map_err(|e| e.to_string())?),
+        "html" | "htm" => Ok(pocket_html(
+            &std::fs::read_to_string(path).map_err(|e| e.to_string())?,
+        )),
+        "csv" => csv_export(&std::fs::read_to_string(path).map_err(|e| e.to_string())?),
+        _ => Err(format!(
+            "{} isn't a Pocket or Instapaper export",
+            path.display()
+        )),
+    }
+}
+
+fn pocket_zip<R: Read + std::io::Seek>(reader: R) -> Result<Vec<SavedItem>, String> {
+    let mut zip = zip::ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    let mut items = Vec::new();
+    let mut highlights: HashMap<String, Vec<String>> = HashMap::new();
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index).map_err(|e| e.to_string())?;
+        let name = file.name().to_lowercase();
+        if !(name.ends_with(".csv") || name.ends_with(".json") || name.ends_with(".html")) {
+            continue;
+        }
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(|e| e.to_string())?;
+        if name.ends_with(".csv") {
+            items.extend(csv_export(&text)?);
+        } else if name.ends_with(".html") {
+            items.extend(pocket_html(&text));
+        } else {
+            for (url, quotes) in pocket_annotations(&text)? {
+                highlights.entry(url).or_default().extend(quotes);
+            }
+        }
+    }
+    if items.is_empty() {
+        return Err("The zip has no Pocket list in it".to_string());
+    }
+    for item in &mut items {
+        if let Some(quotes) = highlights.remove(&item.url) {
+            item.highlights.extend(quotes);
+        }
+    }
+    Ok(items)
+}
+
+/// Highlighted quotes by article URL, from `annotations/part_*.json`.
+fn pocket_annotations(json: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Ok(value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|article| {
+            let url = article.get("url")?.as_str()?.to_string();
+            let quotes = article
+                .get("highlights")?
+                .as_array()?
+                .iter()
+                .filter_map(|h| h.get("quote")?.as_str().map(str::to_string))
+                .collect();
+            Some((url, quotes))
+        })
+        .collect())
+}
+
+/// The links of `ril_export.html`.
+fn pocket_html(html: &str) -> Vec<SavedItem> {
+    let document = kuchikiki::parse_html().one(html).document_node;
+    let Ok(links) = document.select("a[href]") else {
+        return Vec::new();
+    };
+    links
+        .map(|link| {
+            let attributes = link.attributes.borrow();
+            SavedItem {
+                url: attributes.get("href").unwrap_or_default().to_string(),
+                title: link.text_contents().trim().to_string(),
+                added_at: attributes.get("time_added").and_then(|t| t.parse().ok()),
+                highlights: Vec::new(),
+            }
+        })
+        .filter(|item| item.url.starts_with("http"))
+        .collect()
+}
+
+/// A Pocket or Instapaper CSV, told apart by their header rows.
+fn csv_export(text: &str) -> Result<Vec<SavedItem>, String> {
+    let mut rows = parse_csv(text).into_iter();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or("The CSV file is empty")?
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let url = column("url").ok_or("The CSV file has no URL column")?;
+    let title = column("title");
+    // Pocket's `time_added`, Instapaper's `Timestamp`.
+    let added = column("time_added").or_else(|| column("timestamp"));
+    let selection = column("selection");
+
+    let mut items: Vec<SavedItem> = Vec::new();
+    for row in rows {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|i| row.get(i))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let Some(url) = field(Some(url)).filter(|u| u.starts_with("http")) else {
+            continue;
+        };
+        let highlight = field(selection);
+        // Instapaper repeats an article for each selection.
+        if let Some(item) = items.iter_mut().find(|item| item.url == url) {
+            item.highlights.extend(highlight);
+            continue;
+        }
+        items.push(SavedItem {
+            title: field(title).unwrap_or_else(|| url.clone()),
+            added_at: field(added).and_then(|t| t.parse().ok()),
+            highlights: highlight.into_iter().collect(),
+            url,
+        });
+    }
+    Ok(items)
+}
+
+/// Rows of RFC 4180 CSV: quoted fields may hold commas, newlines and doubled
+/// quotes.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// Adds `quotes` to the book as highlights in the first palette color,
+/// skipping ones it already has. Returns how many were added and how many
+/// couldn't be found in the text.
+pub fn add_highlights(
+    conn: &Connection,
+    books_dir: &Path,
+    book_title: &str,
+    filename: &str,
+    quotes: &[String],
+) -> Result<(usize, usize), String> {
+    if quotes.is_empty() {
+        return Ok((0, 0));
+    }
+    let mut epub = Epub::open(&books_dir.join(filename))?;
+    let refs: Vec<&str> = quotes.iter().map(String::as_str).collect();
+    let cfis = reanchor::find_quotes(&mut epub, &refs);
+    let color = db::highlights::colors(conn)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .next()
+        .map_or_else(|| "#facc15".to_string(), |c| c.hex);
+    let (mut added, mut missing) = (0, 0);
+    for (quote, cfi) in quotes.iter().zip(cfis) {
+        let Some(cfi) = cfi else {
+            missing += 1;
+            continue;
+        };
+        let exists: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM highlights WHERE book_title = ?1 AND (cfi = ?2 OR text = ?3))",
+                params![book_title, cfi, quote],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            db::highlights::add(
+                conn,
+                book_title,
+                &cfi,
+                quote.trim(),
+                &color,
+                HighlightStyle::default(),
+                "",
+            )
+            .map_err(|e| e.to_string())?;
+            added += 1;
+        }
+    }
+    Ok((added, missing))
+}
@@ -0,0 +1,222 @@
+//! Word counts and reading-time estimates.
+//!
+//! EPUB books are counted from their text when imported (or the first time
+//! an estimate is asked for); PDFs only have a page count, so their length is
+//! approximated from it. `books.reading_minutes` is the estimate at
+//! [`DEFAULT_WPM`]; time remaining uses the reader's own speed once there are
+//! reading sessions to measure it from.
+//!
+//! A session's speed is the share of the book's words between where it
+//! started and ended, over its duration. Sessions that went backwards, jumped
+//! around or sat idle give implausible speeds and aren't counted.
+
+use crate::epub::Epub;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Typical adult reading speed for prose, used until one has been measured.
+pub const DEFAULT_WPM: f64 = 250.0;
+
+/// Rough word count of a printed page, for PDFs.
+const PDF_WORDS_PER_PAGE: i64 = 300;
+
+/// Measurements outside this range come from skimming or an idle reader and
+/// are ignored.
+const PLAUSIBLE_WPM: std::ops::RangeInclusive<f64> = 50.0..=1500.0;
+
+/// Sessions shorter than this are too noisy to measure.
+const MIN_SESSION_SECONDS: i64 = 60;
+
+/// Sessions averaged for the reader's current speed.
+const RECENT_SESSIONS: i64 = 20;
+
+/// Sessions in each point of the rolling average returned for charts.
+const ROLLING_WINDOW: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimeRemaining {
+    pub word_count: i64,
+    pub words_remaining: i64,
+    pub wpm: f64,
+    /// `wpm` comes from the reader's sessions rather than [`DEFAULT_WPM`].
+    pub measured: bool,
+    pub minutes_remaining: i64,
+}
+
+/// Words in `text`: runs of characters containing a letter or digit.
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Word count of the book at `path`: counted for EPUBs, estimated from
+/// `page_count` for PDFs. `None` when neither is possible.
+pub fn book_words(
+    path: &Path,
+    format: &str,
+    page_count: Option<i64>,
+) -> Result<Option<i64>, String> {
+    match format {
+        format if crate::import::is_epub(format) => {
+            let mut epub = Epub::open(path)?;
+            let mut words = 0;
+            for index in 0..epub.spine.len() {
+                match epub.chapter_text(index) {
+                    Ok(chapter) => words += count_words(&chapter.text),
+                    Err(e) => log::warn!("Could not read chapter {index}: {e}"),
+                }
+            }
+            Ok(Some(words as i64))
+        }
+        "pdf" => Ok(page_count.map(|pages| pages * PDF_WORDS_PER_PAGE)),
+        _ => Ok(None),
+    }
+}
+
+/// Stores `words` on the book along with the estimate at [`DEFAULT_WPM`].
+pub fn store(conn: &Connection, book_id: i64, words: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET word_count = ?1, reading_minutes = ?2 WHERE id = ?3",
+        params![words, minutes(words, DEFAULT_WPM), book_id],
+    )?;
+    Ok(())
+}
+
+/// Minutes to read `words` at `wpm`, rounded up.
+pub fn minutes(words: i64, wpm: f64) -> i64 {
+    (words as f64 / wpm).ceil() as i64
+}
+
+/// Words read and the speed in a session of `seconds` that moved from
+/// `start` to `end` percent through a book of `word_count` words. The speed
+/// is `None` when it can't be trusted.
+pub fn session_speed(
+    word_count: i64,
+    start: f64,
+    end: f64,
+    seconds: i64,
+) -> (Option<i64>, Option<f64>) {
+    if end <= start || word_count <= 0 {
+        return (None, None);
+    }
+    let words = (word_count as f64 * (end.min(100.0) - start.max(0.0)) / 100.0).round() as i64;
+    let wpm = words as f64 / (seconds as f64 / 60.0);
+    let trusted = seconds >= MIN_SESSION_SECONDS && PLAUSIBLE_WPM.contains(&wpm);
+    (Some(words), trusted.then_some(wpm))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionSpeed {
+    pub session_id: i64,
+    pub book_title: String,
+    pub ended_at: String,
+    pub wpm: f64,
+    /// Average over this session and the ones before it in the window,
+    /// weighted by time.
+    pub rolling_wpm: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReadingSpeed {
+    /// Over every measured session.
+    pub average_wpm: Option<f64>,
+    /// Over the most recent measured sessions; what estimates use.
+    pub recent_wpm: Option<f64>,
+    /// Measured sessions, oldest first.
+    pub sessions: Vec<SessionSpeed>,
+}
+
+/// Words over minutes of `(words, seconds)` pairs.
+fn weighted(samples: &[(i64, i64)]) -> Option<f64> {
+    let words: i64 = samples.iter().map(|(w, _)| w).sum();
+    let seconds: i64 = samples.iter().map(|(_, s)| s).sum();
+    (seconds > 0).then(|| words as f64 / (seconds as f64 / 60.0))
+}
+
+/// Speed from measured sessions, in `book_id` or across the library.
+pub fn reading_speed(conn: &Connection, book_id: Option<i64>) -> rusqlite::Result<ReadingSpeed> {
+    let rows: Vec<(i64, String, String, i64, i64, f64)> = conn
+        .prepare(
+            "SELECT s.id, s.book_title, s.ended_at, s.words, s.seconds, s.wpm
+             FROM reading_sessions s
+             WHERE s.wpm IS NOT NULL
+               AND (?1 IS NULL OR s.book_title = (SELECT title FROM books WHERE id = ?1))
+             ORDER BY s.ended_at, s.id",
+        )?
+        .query_map(params![book_id], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let samples: Vec<(i64, i64)> = rows.iter().map(|r| (r.3, r.4)).collect();
+    let recent_start = samples.len().saturating_sub(RECENT_SESSIONS as usize);
+    let sessions = rows
+        .into_iter()
+        .enumerate()
+        .map(
+            |(i, (session_id, book_title, ended_at, _, _, wpm))| SessionSpeed {
+                session_id,
+                book_title,
+                ended_at,
+                wpm,
+                rolling_wpm: weighted(&samples[(i + 1).saturating_sub(ROLLING_WINDOW)..=i])
+                    .unwrap_or(wpm),
+            },
+        )
+        .collect();
+    Ok(ReadingSpeed {
+        average_wpm: weighted(&samples),
+        recent_wpm: weighted(&samples[recent_start..]),
+        sessions,
+    })
+}
+
+/// The reader's current speed in words per minute, from their most recent
+/// measured sessions. With `book_id`, only that book's sessions count.
+pub fn measured_wpm(conn: &Connection, book_id: Option<i64>) -> rusqlite::Result<Option<f64>> {
+    let (words, seconds): (i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(words), 0), COALESCE(SUM(seconds), 0) FROM (
+             SELECT s.words, s.seconds FROM reading_sessions s
+             WHERE s.wpm IS NOT NULL
+               AND (?1 IS NULL OR s.book_title = (SELECT title FROM books WHERE id = ?1))
+             ORDER BY s.ended_at DESC, s.id DESC
+             LIMIT ?2
+         )",
+        params![book_id, RECENT_SESSIONS],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(weighted(&[(words, seconds)]))
+}
+
+/// Time left in a book of `word_count` words read up to `percentage`, at the
+/// speed measured in that book, else across the library, else the default.
+pub fn time_remaining(
+    conn: &Connection,
+    book_id: i64,
+    word_count: i64,
+    percentage: f64,
+) -> rusqlite::Result<TimeRemaining> {
+    let measured = match measured_wpm(conn, Some(book_id))? {
+        Some(wpm) => Some(wpm),
+        None => measured_wpm(conn, None)?,
+    };
+    let wpm = measured.unwrap_or(DEFAULT_WPM);
+    let words_remaining =
+        (word_count as f64 * (1.0 - percentage.clamp(0.0, 100.0) / 100.0)).round() as i64;
+    Ok(TimeRemaining {
+        word_count,
+        words_remaining,
+        wpm,
+        measured: measured.is_some(),
+        minutes_remaining: minutes(words_remaining, wpm),
+    })
+}
@@ -0,0 +1,175 @@
+//! Incremental push of highlights to Readwise.
+//!
+//! The access token lives in the keychain as `readwise`/`token`. Each sync
+//! sends the highlights created or edited since the last one, in batches;
+//! Readwise de-duplicates on text, title and author, so an edited highlight
+//! updates the one already there.
+
+use crate::{run_blocking, DbState};
+use reqwest::StatusCode;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const ENDPOINT: &str = "https://readwise.io/api/v2/highlights/";
+
+/// Settings key holding the `updated_at` of the newest highlight pushed.
+const CURSOR_SETTING: &str = "readwise.synced_until";
+
+const BATCH_SIZE: usize = 100;
+
+/// Readwise rejects longer highlight texts and notes.
+const MAX_CHARS: usize = 8191;
+
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReadwiseSyncReport {
+    pub pushed: usize,
+    /// `updated_at` of the newest highlight Readwise now has.
+    pub synced_until: Option<String>,
+}
+
+struct Pending {
+    json: Value,
+    updated_at: String,
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_CHARS).collect()
+}
+
+/// SQLite's `2024-03-01 18:22:10` (UTC) as ISO 8601.
+pub(crate) fn iso8601(timestamp: &str) -> String {
+    let mut iso = timestamp.replacen(' ', "T", 1);
+    if !iso.ends_with('Z') {
+        iso.push('Z');
+    }
+    iso
+}
+
+fn load_pending(db: &DbState) -> Result<(Vec<Pending>, Option<String>), String> {
+    let conn = db.conn()?;
+    let since: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![CURSOR_SETTING],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let pending = conn
+        .prepare(
+            "SELECT h.text, h.notes, h.book_title, b.author, h.created_at, h.updated_at
+             FROM highlights h
+             LEFT JOIN books b ON b.title = h.book_title
+             WHERE ?1 IS NULL OR h.updated_at > ?1
+             ORDER BY h.updated_at",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map(params![since], |row| {
+            let notes: String = row.get(1)?;
+            let author: Option<String> = row.get(3)?;
+            let created_at: String = row.get(4)?;
+            let mut highlight = json!({
+                "text": truncate(&row.get::<_, String>(0)?),
+                "title": row.get::<_, String>(2)?,
+                "source_type": "readme",
+                "category": "books",
+                "highlighted_at": iso8601(&created_at),
+            });
+            if !notes.trim().is_empty() {
+                highlight["note"] = json!(truncate(&notes));
+            }
+            if let Some(author) = author.filter(|a| !a.trim().is_empty()) {
+                highlight["author"] = json!(author);
+            }
+            Ok(Pending {
+                json: highlight,
+                updated_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+    Ok((pending, since))
+}
+
+async fn post(client: &reqwest::Client, token: &str, highlights: &[Value]) -> Result<(), String> {
+    let body =
+        serde_json::to_vec(&json!({ "highlights": highlights })).map_err(|e| e.to_string())?;
+    for _ in 0..MAX_ATTEMPTS {
+        let response = client
+            .post(ENDPOINT)
+            .header(reqwest::header::AUTHORIZATION, format!("Token {token}"))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60);
+            log::info!("Readwise rate limit reached; waiting {wait}s");
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            continue;
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return Err("Readwise rejected the access token".to_string());
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!(
+                "Readwise request failed ({status}): {}",
+                text.trim()
+            ));
+        }
+        return Ok(());
+    }
+    Err("Readwise kept rate-limiting requests; try again later".to_string())
+}
+
+/// Pushes every highlight created or edited since the last sync.
+pub async fn sync(db: DbState, token: String) -> Result<ReadwiseSyncReport, String> {
+    let (pending, since) = run_blocking({
+        let db = db.clone();
+        move || load_pending(&db)
+    })
+    .await?;
+
+    let client = reqwest::Client::new();
+    let mut report = ReadwiseSyncReport {
+        pushed: 0,
+        synced_until: since,
+    };
+    for batch in pending.chunks(BATCH_SIZE) {
+        let highlights: Vec<Value> = batch.iter().map(|p| p.json.clone()).collect();
+        post(&client, &token, &highlights).await?;
+
+        // Advance the cursor after every batch, so an interrupted sync
+        // resumes where it stopped.
+        let synced_until = batch.last().map(|p| p.updated_at.clone());
+        let db = db.clone();
+        let cursor = synced_until.clone();
+        run_blocking(move || {
+            let conn = db.conn()?;
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![CURSOR_SETTING, cursor],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await?;
+        report.pushed += batch.len();
+        report.synced_until = synced_until;
+    }
+    Ok(report)
+}
@@ -0,0 +1,216 @@
+//! Moves highlights onto a new edition of their book, whose CFIs no longer
+//! match the old file.
+//!
+//! Each highlight's text is looked up in the new file, starting with the
+//! chapter at the same spine position and working outwards. Matching ignores
+//! case, whitespace and typographic quote/dash differences; if the exact text
+//! isn't there (a typo was fixed, a hyphen removed), a match on its opening
+//! and closing words with a similar length in between is accepted. Highlights
+//! that can't be found keep their old CFI and are flagged `unanchored`.
+
+use crate::cfi;
+use crate::epub::{ChapterText, Epub};
+use rusqlite::{params, Transaction};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek};
+
+/// Characters matched at each end of a quote in fuzzy matching.
+const ANCHOR_CHARS: usize = 32;
+
+/// Quotes shorter than this (after normalization) must match exactly.
+const MIN_FUZZY_CHARS: usize = 24;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReanchorReport {
+    /// Highlights found at a new position.
+    pub relocated: usize,
+    /// Highlights found where they already were.
+    pub unchanged: usize,
+    /// Highlights that couldn't be found and were flagged.
+    pub unanchored: usize,
+}
+
+/// Chapter text folded for matching, with the byte range in the original text
+/// of every folded byte.
+struct Folded {
+    text: String,
+    spans: Vec<(usize, usize)>,
+}
+
+fn fold_char(c: char, out: &mut String) {
+    match c {
+        '‘' | '’' | '‚' | '′' => out.push('\''),
+        '“' | '”' | '„' | '″' | '«' | '»' => out.push('"'),
+        '‐' | '‑' | '‒' | '–' | '—' | '―' => out.push('-'),
+        '\u{ad}' => {}
+        _ => out.extend(c.to_lowercase()),
+    }
+}
+
+fn fold(text: &str) -> Folded {
+    let mut folded = Folded {
+        text: String::with_capacity(text.len()),
+        spans: Vec::with_capacity(text.len()),
+    };
+    for (start, c) in text.char_indices() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let before = folded.text.len();
+        fold_char(c, &mut folded.text);
+        let span = (start, start + c.len_utf8());
+        folded
+            .spans
+            .extend(std::iter::repeat(span).take(folded.text.len() - before));
+    }
+    folded
+}
+
+fn fold_quote(quote: &str) -> String {
+    let mut out = String::new();
+    quote
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .for_each(|c| fold_char(c, &mut out));
+    out
+}
+
+/// Byte range of `quote` in the chapter's original text.
+fn locate(chapter: &Folded, quote: &str) -> Option<(usize, usize)> {
+    let to_original = |start: usize, end: usize| {
+        Some((chapter.spans.get(start)?.0, chapter.spans.get(end - 1)?.1))
+    };
+
+    if let Some(start) = chapter.text.find(quote) {
+        return to_original(start, start + quote.len());
+    }
+
+    let chars: Vec<(usize, char)> = quote.char_indices().collect();
+    if chars.len() < MIN_FUZZY_CHARS {
+        return None;
+    }
+    let anchor = ANCHOR_CHARS.min(chars.len() / 3);
+    let head = &quote[..chars[anchor].0];
+    let tail = &quote[chars[chars.len() - anchor].0..];
+    let (min_len, max_len) = (quote.len() * 7 / 10, quote.len() * 13 / 10);
+    for (start, _) in chapter.text.match_indices(head) {
+        let window_end = (start + max_len).min(chapter.text.len());
+        let Some(window) = chapter.text.get(start..window_end) else {
+            continue;
+        };
+        if let Some(tail_at) = window.rfind(tail) {
+            let end = start + tail_at + tail.len();
+            if end - start >= min_len {
+                return to_original(start, end);
+            }
+        }
+    }
+    None
+}
+
+/// Re-anchors every highlight of `book_title` onto `epub`.
+pub fn reanchor<R: Read + Seek>(
+    tx: &Transaction,
+    book_title: &str,
+    epub: &mut Epub<R>,
+) -> Result<ReanchorReport, String> {
+    let chapters: Vec<Option<(ChapterText, Folded)>> = (0..epub.spine.len())
+        .map(|index| {
+            let chapter = epub.chapter_text(index).ok()?;
+            let folded = fold(&chapter.text);
+            Some((chapter, folded))
+        })
+        .collect();
+
+    let highlights: Vec<(i64, String, String)> = tx
+        .prepare("SELECT id, cfi, text FROM highlights WHERE book_title = ?1")
+        .map_err(|e| e.to_string())?
+        .query_map(params![book_title], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut report = ReanchorReport::default();
+    for (id, old_cfi, text) in highlights {
+        let quote = fold_quote(&text);
+        let near = cfi::spine_index(&old_cfi).unwrap_or(0);
+        let mut order: Vec<usize> = (0..chapters.len()).collect();
+        order.sort_by_key(|index| index.abs_diff(near));
+
+        let new_cfi = (!quote.is_empty())
+            .then(|| {
+                order.iter().find_map(|index| {
+                    let (chapter, folded) = chapters[*index].as_ref()?;
+                    let (start, end) = locate(folded, &quote)?;
+                    chapter.range_cfi(start, end)
+                })
+            })
+            .flatten();
+
+        match new_cfi {
+            Some(new_cfi) if new_cfi == old_cfi => {
+                tx.execute(
+                    "UPDATE highlights SET unanchored = 0 WHERE id = ?1",
+                    params![id],
+                )
+                .map_err(|e| e.to_string())?;
+                report.unchanged += 1;
+            }
+            Some(new_cfi) => {
+                // Sync identifies highlights by CFI, so the old position is
+                // retired like a deletion and the moved highlight counts as
+                // an edit.
+                tx.execute(
+                    "INSERT OR REPLACE INTO sync_tombstones (kind, book_title, cfi, text, deleted_at)
+                     VALUES ('highlight', ?1, ?2, ?3, strftime('%Y-%m-%d %H:%M:%f', 'now'))",
+                    params![book_title, old_cfi, text],
+                )
+                .map_err(|e| e.to_string())?;
+                tx.execute(
+                    "UPDATE highlights SET cfi = ?1, unanchored = 0,
+                         updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now')
+                     WHERE id = ?2",
+                    params![new_cfi, id],
+                )
+                .map_err(|e| e.to_string())?;
+                report.relocated += 1;
+            }
+            None => {
+                tx.execute(
+                    "UPDATE highlights SET unanchored = 1 WHERE id = ?1",
+                    params![id],
+                )
+                .map_err(|e| e.to_string())?;
+                report.unanchored += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Range CFIs of `quotes` in `epub`, for highlights that come with their
+/// text but no position, searching the chapters in reading order.
+pub fn find_quotes<R: Read + Seek>(epub: &mut Epub<R>, quotes: &[&str]) -> Vec<Option<String>> {
+    let chapters: Vec<(ChapterText, Folded)> = (0..epub.spine.len())
+        .filter_map(|index| {
+            let chapter = epub.chapter_text(index).ok()?;
+            let folded = fold(&chapter.text);
+            Some((chapter, folded))
+        })
+        .collect();
+    quotes
+        .iter()
+        .map(|quote| {
+            let quote = fold_quote(quote);
+            if quote.is_empty() {
+                return None;
+            }
+            chapters.iter().find_map(|(chapter, folded)| {
+                let (start, end) = locate(folded, &quote)?;
+                chapter.range_cfi(start, end)
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+//! Daily OS notifications: a reminder to read, and one when highlights are
+//! waiting in the daily review. Each is off or set to a local time of day.
+//!
+//! The time is stored in the settings table as `reminders.<kind>`, and the
+//! local date it last went out as `reminders.<kind>.sent`. A reminder goes
+//! out once a day, late if the app wasn't running at its time.
+
+use crate::db;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_notification::NotificationExt;
+
+/// Highlights the review notification offers.
+const REVIEW_COUNT: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReminderKind {
+    Reading,
+    Review,
+}
+
+impl ReminderKind {
+    pub const ALL: [ReminderKind; 2] = [ReminderKind::Reading, ReminderKind::Review];
+
+    fn key(self) -> &'static str {
+        match self {
+            ReminderKind::Reading => "reminders.reading",
+            ReminderKind::Review => "reminders.review",
+        }
+    }
+
+    fn sent_key(self) -> String {
+        format!("{}.sent", self.key())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReminderSchedule {
+    pub kind: ReminderKind,
+    /// Local time of day as `HH:MM`; `None` when off.
+    pub time: Option<String>,
+    /// Local date it last went out.
+    pub last_sent_on: Option<String>,
+}
+
+fn setting(conn: &Connection, key: &str) -> Result<Option<String>, String> {
+    conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The local date and time of day, as `YYYY-MM-DD` and `HH:MM`.
+fn local_now(conn: &Connection) -> Result<(String, String), String> {
+    conn.query_row(
+        "SELECT date('now', 'localtime'), strftime('%H:%M', 'now', 'localtime')",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// `time` as `HH:MM`, accepting a single-digit hour.
+fn parse_time(time: &str) -> Result<String, String> {
+    let invalid = || format!("{time} is not a time of day like 08:30");
+    let (hours, minutes) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u8 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u8 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok(format!("{hours:02}:{minutes:02}"))
+}
+
+pub fn schedule(conn: &Connection, kind: ReminderKind) -> Result<ReminderSchedule, String> {
+    Ok(ReminderSchedule {
+        kind,
+        time: setting(conn, kind.key())?,
+        last_sent_on: setting(conn, &kind.sent_key())?,
+    })
+}
+
+pub fn schedules(conn: &Connection) -> Result<Vec<ReminderSchedule>, String> {
+    ReminderKind::ALL
+        .into_iter()
+        .map(|kind| schedule(conn, kind))
+        .collect()
+}
+
+/// Sends the `kind` reminder daily at `time`. A time that has already passed
+/// today starts tomorrow rather than firing right away.
+pub fn set(conn: &Connection, kind: ReminderKind, time: &str) -> Result<ReminderSchedule, String> {
+    let time = parse_time(time)?;
+    set_setting(conn, kind.key(), &time)?;
+    let (today, now) = local_now(conn)?;
+    if time <= now {
+        set_setting(conn, &kind.sent_key(), &today)?;
+    }
+    schedule(conn, kind)
+}
+
+pub fn clear(conn: &Connection, kind: ReminderKind) -> Result<ReminderSchedule, String> {
+    conn.execute("DELETE FROM settings WHERE key = ?1", params![kind.key()])
+        .map_err(|e| e.to_string())?;
+    schedule(conn, kind)
+}
+
+/// Title and body of the `kind` notification, or `None` if there is nothing
+/// to say.
+fn message(conn: &Connection, kind: ReminderKind) -> Result<Option<(String, String)>, String> {
+    match kind {
+        ReminderKind::Reading => {
+            let current: Option<String> = conn
+                .query_row(
+                    "SELECT title FROM books
+                     WHERE finished_at IS NULL AND last_opened_at IS NOT NULL
+                     ORDER BY last_opened_at DESC LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let body = match current {
+                Some(title) => format!("Pick up where you left off in {title}"),
+                None => "Open a book and read for a while".to_string(),
+            };
+            Ok(Some(("Time to read".to_string(), body)))
+        }
+        ReminderKind::Review => {
+            let count = db::review::daily(conn, REVIEW_COUNT)
+                .map_err(|e| e.to_string())?
+                .len();
+            Ok(match count {
+                0 => None,
+                1 => Some((
+                    "Remember this?".to_string(),
+                    "1 highlight to review".to_string(),
+                )),
+                n => Some((
+                    "Remember these?".to_string(),
+                    format!("{n} highlights to review"),
+                )),
+            })
+        }
+    }
+}
+
+/// Shows the reminders whose time has come today and that haven't gone out
+/// yet, for the scheduler.
+pub fn send_due(app: &AppHandle, conn: &Connection) -> Result<(), String> {
+    let (today, now) = local_now(conn)?;
+    for kind in ReminderKind::ALL {
+        let schedule = schedule(conn, kind)?;
+        let due = schedule.time.is_some_and(|time| time <= now)
+            && schedule.last_sent_on.as_deref() != Some(today.as_str());
+        if !due {
+            continue;
+        }
+        // Marked first, so a failing notification isn't retried every minute.
+        set_setting(conn, &kind.sent_key(), &today)?;
+        if let Some((title, body)) = message(conn, kind)? {
+            app.notification()
+                .builder()
+                .title(title)
+                .body(body)
+                .show()
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! Full-text search over book content.
+//!
+//! Indexing is opt-in per book: [`index_book`] reads every chapter of the
+//! stored EPUB and writes one row per passage into the `book_text` FTS5 table,
+//! together with the chapter title and a CFI the reader can jump to.
+
+use crate::epub::Epub;
+use rusqlite::{params, Connection, Transaction};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Markers around matched terms in [`SearchHit::snippet`].
+const MATCH_START: &str = "<mark>";
+const MATCH_END: &str = "</mark>";
+
+/// Tokens of context on each side of a match in snippets.
+const SNIPPET_TOKENS: i64 = 12;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BookIndexReport {
+    pub book_id: i64,
+    pub chapters: usize,
+    pub passages: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub book_id: i64,
+    pub book_title: String,
+    pub chapter: Option<String>,
+    pub cfi: String,
+    /// The passage around the match, with matched terms wrapped in
+    /// `<mark>`…`</mark>`. Everything else is plain text and must be escaped
+    /// before rendering as HTML.
+    pub snippet: String,
+}
+
+/// Replaces the indexed text of `book_id` with the contents of the EPUB at
+/// `path`.
+pub fn index_book(tx: &Transaction, book_id: i64, path: &Path) -> Result<BookIndexReport, String> {
+    let mut epub = Epub::open(path)?;
+    tx.execute("DELETE FROM book_text WHERE book_id = ?1", params![book_id])
+        .map_err(|e| e.to_string())?;
+
+    let mut stmt = tx
+        .prepare("INSERT INTO book_text (text, chapter, cfi, book_id) VALUES (?1, ?2, ?3, ?4)")
+        .map_err(|e| e.to_string())?;
+    let mut report = BookIndexReport {
+        book_id,
+        chapters: 0,
+        passages: 0,
+    };
+    for index in 0..epub.spine.len() {
+        let passages = match epub.passages(index) {
+            Ok(passages) => passages,
+            Err(e) => {
+                log::warn!("Skipping chapter {index} of book {book_id}: {e}");
+                continue;
+            }
+        };
+        let chapter = epub.spine[index].title.clone();
+        for passage in &passages {
+            stmt.execute(params![passage.text, chapter, passage.cfi, book_id])
+                .map_err(|e| e.to_string())?;
+        }
+        report.chapters += 1;
+        report.passages += passages.len();
+    }
+
+    tx.execute(
+        "UPDATE books SET indexed_at = datetime('now') WHERE id = ?1",
+        params![book_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Turns free-form user input into an FTS5 query: every word must appear, and
+/// the last one may be a prefix (so results show up while typing). Quoting
+/// each word keeps FTS5 syntax characters from causing errors.
+fn match_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", words.join(" ")))
+}
+
+/// Matches in one book, in reading order, or across every indexed book, best
+/// first.
+pub fn search(
+    conn: &Connection,
+    book_id: Option<i64>,
+    query: &str,
+    limit: usize,
+) -> rusqlite::Result<Vec<SearchHit>> {
+    let Some(fts_query) = match_query(query) else {
+        return Ok(Vec::new());
+    };
+    let order = if book_id.is_some() {
+        "book_text.rowid"
+    } else {
+        "rank"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT book_text.book_id, b.title, book_text.chapter, book_text.cfi,
+                snippet(book_text, 0, ?3, ?4, '…', ?5)
+         FROM book_text
+         INNER JOIN books b ON b.id = book_text.book_id
+         WHERE book_text MATCH ?1 AND (?2 IS NULL OR book_text.book_id = ?2)
+         ORDER BY {order}
+         LIMIT ?6"
+    ))?;
+    let rows = stmt.query_map(
+        params![
+            fts_query,
+            book_id,
+            MATCH_START,
+            MATCH_END,
+            SNIPPET_TOKENS,
+            limit as i64
+        ],
+        |row| {
+            Ok(SearchHit {
+                book_id: row.get(0)?,
+                book_title: row.get(1)?,
+                chapter: row.get(2)?,
+                cfi: row.get(3)?,
+                snippet: row.get(4)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
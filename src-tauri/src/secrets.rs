@@ -0,0 +1,65 @@
+//! Credentials for sync and API integrations, kept in the OS keychain
+//! (Keychain on macOS, Credential Manager on Windows, Secret Service on Linux)
+//! rather than in the SQLite database.
+//!
+//! Secrets are namespaced per integration, so `("webdav", "password")` and
+//! `("readwise", "token")` are separate keychain entries, all under the
+//! app's identifier.
+
+use crate::{APP_IDENTIFIER, LEGACY_IDENTIFIER};
+use keyring::{Entry, Error};
+
+fn entry(integration: &str, key: &str) -> Result<Entry, String> {
+    entry_in(APP_IDENTIFIER, integration, key)
+}
+
+fn entry_in(service: &str, integration: &str, key: &str) -> Result<Entry, String> {
+    let valid = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    };
+    if !valid(integration) || !valid(key) {
+        return Err(format!("Invalid secret name {integration}/{key}"));
+    }
+    Entry::new(service, &format!("{integration}/{key}")).map_err(|e| e.to_string())
+}
+
+pub fn store(integration: &str, key: &str, value: &str) -> Result<(), String> {
+    entry(integration, key)?
+        .set_password(value)
+        .map_err(|e| e.to_string())
+}
+
+pub fn get(integration: &str, key: &str) -> Result<Option<String>, String> {
+    match entry(integration, key)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::NoEntry) => take_legacy(integration, key),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Moves a secret stored by a version that had the legacy identifier.
+fn take_legacy(integration: &str, key: &str) -> Result<Option<String>, String> {
+    let legacy = entry_in(LEGACY_IDENTIFIER, integration, key)?;
+    let value = match legacy.get_password() {
+        Ok(value) => value,
+        Err(Error::NoEntry) => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    };
+    store(integration, key, &value)?;
+    if let Err(e) = legacy.delete_credential() {
+        log::warn!("Could not delete the old {integration}/{key} secret: {e}");
+    }
+    Ok(Some(value))
+}
+
+pub fn delete(integration: &str, key: &str) -> Result<(), String> {
+    for service in [APP_IDENTIFIER, LEGACY_IDENTIFIER] {
+        match entry_in(service, integration, key)?.delete_credential() {
+            Ok(()) | Err(Error::NoEntry) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
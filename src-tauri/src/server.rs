@@ -0,0 +1,390 @@
+//! Optional read-only HTTP API for companion apps on the local network, e.g.
+//! a phone browsing the library and pulling annotations.
+//!
+//! The server listens on all interfaces on `server.port` while `server.enabled`
+//! is set. Every request needs the access token, kept in the keychain as
+//! `server`/`token`, either as `Authorization: Bearer <token>` or as a
+//! `token` query parameter. Responses are JSON:
+//!
+//! - `GET /api/books`: every book, without covers or locations
+//! - `GET /api/books/{id}`
+//! - `GET /api/books/{id}/cover`: the cover image
+//! - `GET /api/books/{id}/progress`
+//! - `GET /api/books/{id}/highlights`
+//! - `GET /api/highlights?since=<datetime>`: highlights created or edited
+//!   after `since` (all of them without it), oldest change first
+//!
+//! Traffic is plain HTTP, so the token is only as private as the network.
+//!
+//! The server also carries device-to-device sync under `/peer/`, which has
+//! its own authentication (see [`crate::peer`]), and advertises itself over
+//! mDNS while it runs.
+
+use crate::commands::run_blocking;
+use crate::db::{book_from_row, highlight_from_row, DbState, BOOK_COLUMNS, HIGHLIGHT_COLUMNS};
+use crate::models::{BookMetadata, Highlight};
+use crate::{app_lock, encryption, peer, secrets};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::Manager;
+use tokio::sync::oneshot;
+
+/// Settings key: start the server with the app.
+pub const ENABLED_SETTING: &str = "server.enabled";
+/// Settings key: port to listen on.
+pub const PORT_SETTING: &str = "server.port";
+
+pub const DEFAULT_PORT: u16 = 8787;
+
+/// How long stopping the server waits for requests in flight.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerStatus {
+    pub running: bool,
+    pub port: u16,
+    pub token: Option<String>,
+    /// URLs other devices can reach the server at, as far as can be told.
+    pub addresses: Vec<String>,
+}
+
+struct Running {
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+    /// The serve task, which lets go of the port when it finishes.
+    serving: tauri::async_runtime::JoinHandle<()>,
+    /// Kept for as long as the server runs.
+    _advertisement: Option<peer::Advertisement>,
+}
+
+impl Running {
+    /// Shuts the server down and waits until its port is free. Requests in
+    /// flight get [`SHUTDOWN_TIMEOUT`] to finish before they're cut off.
+    async fn stop(self) {
+        let _ = self.shutdown.send(());
+        let mut serving = self.serving;
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, &mut serving)
+            .await
+            .is_err()
+        {
+            serving.abort();
+            let _ = serving.await;
+        }
+        log::info!("HTTP API server on port {} stopped", self.port);
+    }
+}
+
+/// The running server, if any.
+#[derive(Clone, Default)]
+pub struct ServerState(Arc<Mutex<Option<Running>>>);
+
+#[derive(Clone)]
+struct Api {
+    db: DbState,
+    token: Arc<str>,
+}
+
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        let status = if message == app_lock::LOCKED || message == encryption::LOCKED {
+            StatusCode::LOCKED
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        ApiError(status, message)
+    }
+}
+
+fn not_found(what: &str) -> ApiError {
+    ApiError(StatusCode::NOT_FOUND, format!("{what} not found"))
+}
+
+/// Runs `f` with a connection on a blocking thread.
+async fn with_conn<T, F>(db: &DbState, f: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Connection) -> Result<T, String> + Send + 'static,
+{
+    let db = db.clone();
+    Ok(run_blocking(move || f(&*db.conn()?)).await?)
+}
+
+/// Compares in constant time, so a secret can't be guessed byte by byte.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn authorize(State(api): State<Api>, request: Request, next: Next) -> Response {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let query = request.uri().query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="))
+            .map(str::to_string)
+    });
+    match bearer.or(query) {
+        Some(token) if constant_time_eq(token.as_bytes(), api.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => ApiError(StatusCode::UNAUTHORIZED, "Missing or wrong token".into()).into_response(),
+    }
+}
+
+fn book(conn: &Connection, id: i64) -> Result<Option<BookMetadata>, String> {
+    conn.query_row(
+        &format!("SELECT {BOOK_COLUMNS} FROM books b WHERE b.id = ?1"),
+        params![id],
+        book_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+async fn books(State(api): State<Api>) -> Result<Json<Vec<BookMetadata>>, ApiError> {
+    let books = with_conn(&api.db, |conn| {
+        conn.prepare(&format!(
+            "SELECT {BOOK_COLUMNS} FROM books b ORDER BY b.title COLLATE fold"
+        ))
+        .map_err(|e| e.to_string())?
+        .query_map([], book_from_row)
+        .map_err(|e| e.to_string())?
+        .map(|book| {
+            // Too large to send for a whole library; covers have their own route.
+            book.map(|book| BookMetadata {
+                cover: None,
+                locations_data: None,
+                ..book
+            })
+        })
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())
+    })
+    .await?;
+    Ok(Json(books))
+}
+
+async fn book_by_id(
+    State(api): State<Api>,
+    Path(id): Path<i64>,
+) -> Result<Json<BookMetadata>, ApiError> {
+    let book = with_conn(&api.db, move |conn| book(conn, id)).await?;
+    book.map(Json).ok_or_else(|| not_found("Book"))
+}
+
+async fn cover(State(api): State<Api>, Path(id): Path<i64>) -> Result<Response, ApiError> {
+    let cover = with_conn(&api.db, move |conn| {
+        Ok(book(conn, id)?.and_then(|book| book.cover))
+    })
+    .await?
+    .ok_or_else(|| not_found("Cover"))?;
+    // Covers are stored as data URLs.
+    let (mime, data) = cover
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or_else(|| not_found("Cover"))?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|e| e.to_string())?;
+    Ok(([(header::CONTENT_TYPE, mime.to_string())], bytes).into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct Progress {
+    book_id: i64,
+    cfi: String,
+    percentage: f64,
+    finished_at: Option<String>,
+}
+
+async fn progress(State(api): State<Api>, Path(id): Path<i64>) -> Result<Json<Progress>, ApiError> {
+    let book = with_conn(&api.db, move |conn| book(conn, id))
+        .await?
+        .ok_or_else(|| not_found("Book"))?;
+    Ok(Json(Progress {
+        book_id: book.id,
+        cfi: book.last_position,
+        percentage: book.last_percentage,
+        finished_at: book.finished_at,
+    }))
+}
+
+async fn book_highlights(
+    State(api): State<Api>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<Highlight>>, ApiError> {
+    let highlights = with_conn(&api.db, move |conn| {
+        let Some(book) = book(conn, id)? else {
+            return Ok(None);
+        };
+        conn.prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             WHERE h.book_title = ?1 ORDER BY h.created_at"
+        ))
+        .map_err(|e| e.to_string())?
+        .query_map(params![book.title], highlight_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map(Some)
+        .map_err(|e| e.to_string())
+    })
+    .await?;
+    highlights.map(Json).ok_or_else(|| not_found("Book"))
+}
+
+#[derive(Deserialize)]
+struct Since {
+    since: Option<String>,
+}
+
+async fn highlights(
+    State(api): State<Api>,
+    Query(Since { since }): Query<Since>,
+) -> Result<Json<Vec<Highlight>>, ApiError> {
+    let highlights = with_conn(&api.db, move |conn| {
+        conn.prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             WHERE ?1 IS NULL OR h.updated_at > ?1
+             ORDER BY h.updated_at, h.id"
+        ))
+        .map_err(|e| e.to_string())?
+        .query_map(params![since], highlight_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())
+    })
+    .await?;
+    Ok(Json(highlights))
+}
+
+/// The access token, created on first use.
+pub fn token() -> Result<String, String> {
+    if let Some(token) = secrets::get("server", "token")?.filter(|t| !t.is_empty()) {
+        return Ok(token);
+    }
+    new_token()
+}
+
+/// Replaces the access token, locking out every client that has the old one.
+pub fn new_token() -> Result<String, String> {
+    let mut bytes = [0; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let token = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    secrets::store("server", "token", &token)?;
+    Ok(token)
+}
+
+/// This machine's address on the network the default route goes through.
+/// Connecting a UDP socket sends nothing; it only picks the interface.
+fn lan_address() -> Option<std::net::IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+impl ServerState {
+    pub fn status(&self, port: u16) -> Result<ServerStatus, String> {
+        let running = self.0.lock().map_err(|e| e.to_string())?;
+        let port = running.as_ref().map_or(port, |r| r.port);
+        Ok(ServerStatus {
+            running: running.is_some(),
+            port,
+            token: secrets::get("server", "token")?,
+            addresses: lan_address()
+                .map(|ip| format!("http://{ip}:{port}"))
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// Starts serving `db` on `port`, replacing a server already running.
+    pub async fn start(
+        &self,
+        app: &tauri::AppHandle,
+        db: DbState,
+        port: u16,
+    ) -> Result<(), String> {
+        self.stop().await?;
+        let (token, device_id) = run_blocking({
+            let db = db.clone();
+            move || Ok((token()?, peer::device_id(&*db.conn()?)?))
+        })
+        .await?;
+        let peers = peer::routes(
+            app.clone(),
+            db.clone(),
+            app.state::<peer::Pairing>().inner().clone(),
+        );
+        let api = Api {
+            db,
+            token: token.into(),
+        };
+        let router = Router::new()
+            .route("/api/books", get(books))
+            .route("/api/books/{id}", get(book_by_id))
+            .route("/api/books/{id}/cover", get(cover))
+            .route("/api/books/{id}/progress", get(progress))
+            .route("/api/books/{id}/highlights", get(book_highlights))
+            .route("/api/highlights", get(highlights))
+            .layer(middleware::from_fn_with_state(api.clone(), authorize))
+            .with_state(api)
+            .merge(peers);
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+            .await
+            .map_err(|e| format!("Could not listen on port {port}: {e}"))?;
+        let (shutdown, stopped) = oneshot::channel();
+        let serving = tauri::async_runtime::spawn(async move {
+            let served = axum::serve(listener, router)
+                .with_graceful_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await;
+            if let Err(e) = served {
+                log::warn!("HTTP API server stopped: {e}");
+            }
+        });
+        log::info!("HTTP API server listening on port {port}");
+        // Without it, peers can still pair and sync by address.
+        let advertisement = peer::advertise(&device_id, port)
+            .inspect_err(|e| log::warn!("Could not advertise over mDNS: {e}"))
+            .ok();
+        *self.0.lock().map_err(|e| e.to_string())? = Some(Running {
+            port,
+            shutdown,
+            serving,
+            _advertisement: advertisement,
+        });
+        Ok(())
+    }
+
+    pub async fn stop(&self) -> Result<(), String> {
+        let running = self.0.lock().map_err(|e| e.to_string())?.take();
+        if let Some(running) = running {
+            running.stop().await;
+        }
+        Ok(())
+    }
+}
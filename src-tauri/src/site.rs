@@ -0,0 +1,184 @@
+//! A static website of highlights to host anywhere: `index.html` listing the
+//! books, and a page per book in `books/` with its highlights in reading
+//! order. Styles are inlined, so the folder has no other files.
+
+use crate::export::{self, ExportItem};
+use crate::models::SiteOptions;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+/// Folder of the book pages.
+const BOOKS_DIR: &str = "books";
+
+const SITE_STYLE: &str = "a{color:inherit}.back{font-family:system-ui,sans-serif;font-size:.9rem}\
+.books{list-style:none;padding:0}.books li{margin:0 0 1.25rem}.books a{font-size:1.15rem}\
+.books .author{margin:0}.count{font-family:system-ui,sans-serif;font-size:.8rem;color:#9ca3af}";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SiteReport {
+    pub books: usize,
+    pub highlights: usize,
+}
+
+/// A file name for `title` that's safe in URLs: accents dropped, lowercase
+/// ASCII letters and digits joined by hyphens.
+pub(crate) fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.nfkd().filter(char::is_ascii) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "book".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Text highlights chosen by `options`, by book title, then in reading order.
+fn items(conn: &Connection, options: &SiteOptions) -> rusqlite::Result<Vec<ExportItem>> {
+    let (condition, param) = export::scope_condition(&options.scope);
+    let favorites = if options.favorites_only {
+        "AND h.favorite = 1"
+    } else {
+        ""
+    };
+    let selected: Option<HashSet<i64>> = options
+        .highlight_ids
+        .as_ref()
+        .map(|ids| ids.iter().copied().collect());
+    let rows = conn
+        .prepare(&format!(
+            "SELECT h.id, h.book_title, b.author, h.text, h.notes, h.color, h.created_at
+             FROM highlights h
+             LEFT JOIN books b ON b.title = h.book_title
+             WHERE ({condition}) AND h.annotation_type = 'text' {favorites}
+             ORDER BY h.book_title COLLATE fold, h.book_title, h.cfi COLLATE cfi_order"
+        ))?
+        .query_map(rusqlite::params_from_iter(param), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                ExportItem {
+                    book_title: row.get(1)?,
+                    author: row.get(2)?,
+                    text: row.get(3)?,
+                    notes: row.get(4)?,
+                    color: row.get(5)?,
+                    created_at: row.get(6)?,
+                },
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows
+        .into_iter()
+        .filter(|(id, _)| selected.as_ref().map_or(true, |ids| ids.contains(id)))
+        .map(|(_, mut item)| {
+            if options.hide_notes {
+                item.notes.clear();
+            }
+            item
+        })
+        .collect())
+}
+
+fn book_page(site_title: &str, book: &[ExportItem]) -> String {
+    let title = &book[0].book_title;
+    let mut body = format!(
+        "<p class=\"back\"><a href=\"../index.html\">&larr; {}</a></p>\n<h1>{}</h1>\n",
+        export::escape(site_title),
+        export::escape(title)
+    );
+    if let Some(author) = &book[0].author {
+        body.push_str(&format!(
+            "<p class=\"author\">{}</p>\n",
+            export::escape(author)
+        ));
+    }
+    for item in book {
+        body.push_str(&export::figure(item));
+    }
+    export::document(title, &format!("{}{SITE_STYLE}", export::STYLE), &body)
+}
+
+/// Writes the site for the highlights chosen by `options` into `dir`. Book
+/// pages left from an earlier run into the same folder are removed.
+pub fn publish(conn: &Connection, options: &SiteOptions, dir: &Path) -> Result<SiteReport, String> {
+    let items = items(conn, options).map_err(|e| e.to_string())?;
+    let site_title = options
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or("Highlights");
+    let books_dir = dir.join(BOOKS_DIR);
+    std::fs::create_dir_all(&books_dir).map_err(|e| e.to_string())?;
+
+    let books = export::by_book(&items);
+    let mut written = HashSet::new();
+    let mut index = format!(
+        "<h1>{}</h1>\n<ul class=\"books\">\n",
+        export::escape(site_title)
+    );
+    for book in &books {
+        let base = slug(&book[0].book_title);
+        let mut file = format!("{base}.html");
+        let mut n = 2;
+        while written.contains(&file) {
+            file = format!("{base}-{n}.html");
+            n += 1;
+        }
+        std::fs::write(books_dir.join(&file), book_page(site_title, book))
+            .map_err(|e| e.to_string())?;
+
+        index.push_str(&format!(
+            "<li><a href=\"{BOOKS_DIR}/{file}\">{}</a>",
+            export::escape(&book[0].book_title)
+        ));
+        if let Some(author) = &book[0].author {
+            index.push_str(&format!(
+                "<p class=\"author\">{}</p>",
+                export::escape(author)
+            ));
+        }
+        let count = match book.len() {
+            1 => "1 highlight".to_string(),
+            n => format!("{n} highlights"),
+        };
+        index.push_str(&format!("<span class=\"count\">{count}</span></li>\n"));
+        written.insert(file);
+    }
+    index.push_str("</ul>\n");
+    std::fs::write(
+        dir.join("index.html"),
+        export::document(
+            site_title,
+            &format!("{}{SITE_STYLE}", export::STYLE),
+            &index,
+        ),
+    )
+    .map_err(|e| e.to_string())?;
+
+    for entry in std::fs::read_dir(&books_dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let stale = path.extension().is_some_and(|ext| ext == "html")
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| !written.contains(name));
+        if stale {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(SiteReport {
+        books: books.len(),
+        highlights: items.len(),
+    })
+}
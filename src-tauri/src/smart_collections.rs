@@ -0,0 +1,114 @@
+//! Smart collections: highlight collections defined by a stored filter rather
+//! than by explicit membership.
+//!
+//! Filters are stored as JSON and compiled into a parameterised `WHERE` clause
+//! over `highlights h`, e.g.
+//!
+//! ```json
+//! { "type": "all", "filters": [
+//!     { "type": "color", "value": "#ef4444" },
+//!     { "type": "created_within_days", "days": 30 },
+//!     { "type": "shelf", "collection_id": 3 }
+//! ] }
+//! ```
+
+use rusqlite::types::Value;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartFilter {
+    /// Every nested filter must match. An empty list matches everything.
+    All {
+        filters: Vec<SmartFilter>,
+    },
+    /// At least one nested filter must match. An empty list matches nothing.
+    Any {
+        filters: Vec<SmartFilter>,
+    },
+    Not {
+        filter: Box<SmartFilter>,
+    },
+    Color {
+        value: String,
+    },
+    /// The highlight's color is the palette entry with this name.
+    ColorName {
+        name: String,
+    },
+    Book {
+        title: String,
+    },
+    /// The highlight's book belongs to the given book collection.
+    Shelf {
+        collection_id: i64,
+    },
+    /// The highlight itself belongs to the given collection.
+    Collection {
+        collection_id: i64,
+    },
+    CreatedWithinDays {
+        days: i64,
+    },
+    TextContains {
+        value: String,
+    },
+    HasNotes,
+    Favorite,
+}
+
+impl SmartFilter {
+    /// Compiles the filter into SQL, pushing bound values onto `params` in order.
+    pub fn to_sql(&self, params: &mut Vec<Value>) -> String {
+        match self {
+            SmartFilter::All { filters } => join(filters, " AND ", "1", params),
+            SmartFilter::Any { filters } => join(filters, " OR ", "0", params),
+            SmartFilter::Not { filter } => format!("NOT ({})", filter.to_sql(params)),
+            SmartFilter::Color { value } => {
+                params.push(Value::Text(value.clone()));
+                "lower(h.color) = lower(?)".to_string()
+            }
+            SmartFilter::ColorName { name } => {
+                params.push(Value::Text(name.clone()));
+                "h.color_id IN (SELECT id FROM highlight_colors WHERE name = ?)".to_string()
+            }
+            SmartFilter::Book { title } => {
+                params.push(Value::Text(title.clone()));
+                "h.book_title = ? COLLATE fold".to_string()
+            }
+            SmartFilter::Shelf { collection_id } => {
+                params.push(Value::Integer(*collection_id));
+                "h.book_title IN (SELECT b.title FROM books b
+                    INNER JOIN book_collections bc ON b.id = bc.book_id
+                    WHERE bc.collection_id = ?)"
+                    .to_string()
+            }
+            SmartFilter::Collection { collection_id } => {
+                params.push(Value::Integer(*collection_id));
+                "h.id IN (SELECT highlight_id FROM highlight_collections WHERE collection_id = ?)"
+                    .to_string()
+            }
+            SmartFilter::CreatedWithinDays { days } => {
+                params.push(Value::Text(format!("-{} days", days.max(&0))));
+                "h.created_at >= datetime('now', ?)".to_string()
+            }
+            SmartFilter::TextContains { value } => {
+                params.push(Value::Text(value.clone()));
+                "instr(lower(h.text), lower(?)) > 0".to_string()
+            }
+            SmartFilter::HasNotes => "h.notes <> ''".to_string(),
+            SmartFilter::Favorite => "h.favorite".to_string(),
+        }
+    }
+}
+
+fn join(filters: &[SmartFilter], op: &str, empty: &str, params: &mut Vec<Value>) -> String {
+    if filters.is_empty() {
+        return empty.to_string();
+    }
+    let parts: Vec<String> = filters
+        .iter()
+        .map(|f| format!("({})", f.to_sql(params)))
+        .collect();
+    parts.join(op)
+}
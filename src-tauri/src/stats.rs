@@ -0,0 +1,276 @@
+//! Reading statistics: a summary of the whole library, totals per language,
+//! and CSV files for analysis in a spreadsheet.
+//!
+//! An export is a folder of three files: every reading session, totals per
+//! day and totals per book. Days are local calendar days, like the heatmap
+//! and goals.
+
+use crate::db::streaks;
+use rusqlite::{params, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const SESSIONS_FILE: &str = "reading-sessions.csv";
+pub const DAYS_FILE: &str = "reading-days.csv";
+pub const BOOKS_FILE: &str = "reading-books.csv";
+
+/// Days to include, both ends inclusive, as `YYYY-MM-DD`. A missing end
+/// leaves that side open.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StatsRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl StatsRange {
+    fn validate(&self) -> Result<(), String> {
+        for date in self.from.iter().chain(&self.to) {
+            let bytes = date.as_bytes();
+            let valid = bytes.len() == 10
+                && bytes.iter().enumerate().all(|(i, b)| match i {
+                    4 | 7 => *b == b'-',
+                    _ => b.is_ascii_digit(),
+                });
+            if !valid {
+                return Err(format!("Invalid date {date:?}, expected YYYY-MM-DD"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatsExport {
+    pub sessions: usize,
+    pub days: usize,
+    pub books: usize,
+}
+
+/// Totals over the whole library.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatsSummary {
+    pub books: i64,
+    pub finished_books: i64,
+    pub highlights: i64,
+    pub bookmarks: i64,
+    pub reading_sessions: i64,
+    pub minutes_read: i64,
+    /// Consecutive days read up to today or yesterday.
+    pub current_streak: i64,
+    pub longest_streak: i64,
+    pub languages: Vec<LanguageStats>,
+}
+
+/// Books and reading in one language.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LanguageStats {
+    /// ISO 639-1 code, or `None` for books whose language isn't known.
+    pub language: Option<String>,
+    pub books: i64,
+    /// Books finished in the range.
+    pub finished_books: i64,
+    /// Sessions in the range.
+    pub reading_sessions: i64,
+    pub minutes_read: i64,
+    pub pages: i64,
+}
+
+pub fn summary(conn: &Connection) -> rusqlite::Result<StatsSummary> {
+    let (books, finished_books, highlights, bookmarks, reading_sessions, seconds): (
+        i64,
+        i64,
+        i64,
+        i64,
+        i64,
+        i64,
+    ) = conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM books),
+                (SELECT COUNT(*) FROM books WHERE finished_at IS NOT NULL),
+                (SELECT COUNT(*) FROM highlights),
+                (SELECT COUNT(*) FROM bookmarks),
+                (SELECT COUNT(*) FROM reading_sessions),
+                (SELECT COALESCE(SUM(seconds), 0) FROM reading_sessions)",
+        [],
+        |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        },
+    )?;
+    let today: i64 = conn.query_row(
+        "SELECT CAST(julianday(date('now', 'localtime')) AS INTEGER)",
+        [],
+        |row| row.get(0),
+    )?;
+    let days = conn
+        .prepare(
+            "SELECT DISTINCT CAST(julianday(date(ended_at, 'localtime')) AS INTEGER) AS day
+             FROM reading_sessions ORDER BY day DESC",
+        )?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let (current_streak, longest_streak) = streaks(&days, today);
+    let languages = language_totals(conn, &StatsRange::default())?;
+    Ok(StatsSummary {
+        books,
+        finished_books,
+        highlights,
+        bookmarks,
+        reading_sessions,
+        minutes_read: seconds / 60,
+        current_streak,
+        longest_streak,
+        languages,
+    })
+}
+
+/// Totals per book language, most read first. Books count whenever they
+/// were added; finished books and sessions only within `range`.
+pub fn languages(conn: &Connection, range: &StatsRange) -> Result<Vec<LanguageStats>, String> {
+    range.validate()?;
+    language_totals(conn, range).map_err(|e| e.to_string())
+}
+
+fn language_totals(conn: &Connection, range: &StatsRange) -> rusqlite::Result<Vec<LanguageStats>> {
+    conn.prepare(&format!(
+        "WITH read AS (
+             SELECT b.language, COUNT(*) AS sessions, SUM(s.seconds) AS seconds,
+                    SUM(s.pages) AS pages
+             FROM ({SESSIONS}) s
+             INNER JOIN books b ON b.title = s.book_title
+             GROUP BY b.language
+         ),
+         library AS (
+             SELECT language, COUNT(*) AS books,
+                    SUM(finished_at IS NOT NULL
+                        AND (?1 IS NULL OR date(finished_at, 'localtime') >= ?1)
+                        AND (?2 IS NULL OR date(finished_at, 'localtime') <= ?2)) AS finished
+             FROM books
+             GROUP BY language
+         )
+         SELECT l.language, l.books, l.finished, COALESCE(r.sessions, 0),
+                COALESCE(r.seconds, 0), COALESCE(r.pages, 0)
+         FROM library l
+         LEFT JOIN read r ON r.language IS l.language
+         ORDER BY COALESCE(r.seconds, 0) DESC, l.books DESC, l.language IS NULL, l.language"
+    ))?
+    .query_map(params![range.from, range.to], |row| {
+        Ok(LanguageStats {
+            language: row.get(0)?,
+            books: row.get(1)?,
+            finished_books: row.get(2)?,
+            reading_sessions: row.get(3)?,
+            minutes_read: row.get::<_, i64>(4)? / 60,
+            pages: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+/// Quotes a field if it contains a separator, quote or line break.
+fn field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::Integer(i) => return i.to_string(),
+        Value::Real(r) => return r.to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Blob(b) => String::from_utf8_lossy(b).into_owned(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// Runs `sql` with the range's bounds as `?1` and `?2` and writes the result,
+/// with a header row of the column names, to `path`. Returns the row count.
+fn write_query(
+    conn: &Connection,
+    path: &Path,
+    sql: &str,
+    range: &StatsRange,
+) -> Result<usize, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let columns = stmt.column_count();
+    let mut out = stmt.column_names().join(",");
+    out.push_str("\r\n");
+    let mut rows = stmt
+        .query(params![range.from, range.to])
+        .map_err(|e| e.to_string())?;
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let fields = (0..columns)
+            .map(|i| row.get::<_, Value>(i).map(|v| field(&v)))
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+        count += 1;
+    }
+    std::fs::write(path, out).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Sessions in the range, as a subquery with local start and end times.
+const SESSIONS: &str = "SELECT id, book_title,
+        datetime(started_at, 'localtime') AS started_at,
+        datetime(ended_at, 'localtime') AS ended_at,
+        date(ended_at, 'localtime') AS day,
+        seconds, pages
+    FROM reading_sessions
+    WHERE (?1 IS NULL OR date(ended_at, 'localtime') >= ?1)
+      AND (?2 IS NULL OR date(ended_at, 'localtime') <= ?2)";
+
+/// Writes the three CSV files for `range` into the folder `dir`, creating it
+/// if needed.
+pub fn export(conn: &Connection, dir: &Path, range: &StatsRange) -> Result<StatsExport, String> {
+    range.validate()?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let sessions = write_query(
+        conn,
+        &dir.join(SESSIONS_FILE),
+        &format!(
+            "SELECT id, book_title, started_at, ended_at, seconds,
+                    ROUND(seconds / 60.0, 1) AS minutes, pages
+             FROM ({SESSIONS}) ORDER BY started_at"
+        ),
+        range,
+    )?;
+    let days = write_query(
+        conn,
+        &dir.join(DAYS_FILE),
+        &format!(
+            "SELECT day AS date, COUNT(*) AS sessions, SUM(seconds) AS seconds,
+                    ROUND(SUM(seconds) / 60.0, 1) AS minutes, SUM(pages) AS pages,
+                    COUNT(DISTINCT book_title) AS books
+             FROM ({SESSIONS}) GROUP BY day ORDER BY day"
+        ),
+        range,
+    )?;
+    let books = write_query(
+        conn,
+        &dir.join(BOOKS_FILE),
+        &format!(
+            "SELECT s.book_title, b.author, b.language, COUNT(*) AS sessions,
+                    SUM(s.seconds) AS seconds, ROUND(SUM(s.seconds) / 60.0, 1) AS minutes,
+                    SUM(s.pages) AS pages, MIN(s.day) AS first_read, MAX(s.day) AS last_read,
+                    date(b.finished_at, 'localtime') AS finished
+             FROM ({SESSIONS}) s
+             LEFT JOIN books b ON b.title = s.book_title
+             GROUP BY s.book_title
+             ORDER BY SUM(s.seconds) DESC"
+        ),
+        range,
+    )?;
+    Ok(StatsExport {
+        sessions,
+        days,
+        books,
+    })
+}
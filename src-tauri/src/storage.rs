@@ -0,0 +1,251 @@
+//! Optional zstd compression and encryption of stored book files.
+//!
+//! A compressed or encrypted book keeps its file name; files are told apart
+//! by the magic number at the start, so every path into the books folder
+//! stays the same and plain, compressed and encrypted files can sit side by
+//! side. Everything that reads a book file goes through [`read`] or [`open`],
+//! which decrypt and decompress transparently.
+//!
+//! Compression applies to files imported while the `library.compress`
+//! setting is on; `compress_library` converts the files already stored. In an
+//! encrypted library (see [`crate::encryption`]) the file, compressed or not,
+//! is then sealed with the library's key. Highlight attachments are stored
+//! the same way, uncompressed.
+
+use crate::encryption::{self, LibraryKey};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Settings key: compress newly imported books.
+pub const COMPRESS_SETTING: &str = "library.compress";
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// EPUBs and PDFs are already deflated internally, so higher levels cost a
+/// lot of time for little gain.
+const LEVEL: i32 = 9;
+
+/// Key of the active library, if it is encrypted and unlocked. `DbState`
+/// keeps it in step with the library it holds, so readers deep in the EPUB
+/// and PDF code don't need the key passed down to them.
+static KEY: RwLock<Option<Arc<LibraryKey>>> = RwLock::new(None);
+
+pub fn set_key(key: Option<Arc<LibraryKey>>) {
+    *KEY.write().unwrap_or_else(|e| e.into_inner()) = key;
+}
+
+fn key() -> Option<Arc<LibraryKey>> {
+    KEY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// A stored book file opened for reading: the file itself, or its decrypted
+/// and decompressed contents.
+pub enum BookFile {
+    Plain(File),
+    Decoded(Cursor<Vec<u8>>),
+}
+
+impl Read for BookFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            BookFile::Plain(file) => file.read(buf),
+            BookFile::Decoded(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for BookFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            BookFile::Plain(file) => file.seek(pos),
+            BookFile::Decoded(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+/// How a stored file is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub encrypted: bool,
+    pub compressed: bool,
+}
+
+/// The first bytes of `file`, which is then rewound.
+fn peek(file: &mut File) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(8);
+    (&mut *file).take(8).read_to_end(&mut head)?;
+    file.rewind()?;
+    Ok(head)
+}
+
+/// Contents of a sealed file, still compressed if they were.
+fn read_sealed(mut file: File, key: Option<&LibraryKey>) -> std::io::Result<Vec<u8>> {
+    let key = key.ok_or_else(|| std::io::Error::other(encryption::LOCKED))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    encryption::unseal(key, &data).map_err(std::io::Error::other)
+}
+
+fn layout_with(path: &Path, key: Option<&LibraryKey>) -> std::io::Result<Layout> {
+    let mut file = File::open(path)?;
+    let head = peek(&mut file)?;
+    if encryption::is_sealed(&head) {
+        Ok(Layout {
+            encrypted: true,
+            compressed: read_sealed(file, key)?.starts_with(&ZSTD_MAGIC),
+        })
+    } else {
+        Ok(Layout {
+            encrypted: false,
+            compressed: head.starts_with(&ZSTD_MAGIC),
+        })
+    }
+}
+
+/// How the file at `path` is stored. Looking inside an encrypted file needs
+/// the library unlocked.
+pub fn layout(path: &Path) -> std::io::Result<Layout> {
+    layout_with(path, key().as_deref())
+}
+
+fn open_with(path: &Path, key: Option<&LibraryKey>) -> std::io::Result<BookFile> {
+    let mut file = File::open(path)?;
+    let head = peek(&mut file)?;
+    if encryption::is_sealed(&head) {
+        let data = read_sealed(file, key)?;
+        let data = if data.starts_with(&ZSTD_MAGIC) {
+            zstd::decode_all(data.as_slice())?
+        } else {
+            data
+        };
+        Ok(BookFile::Decoded(Cursor::new(data)))
+    } else if head.starts_with(&ZSTD_MAGIC) {
+        Ok(BookFile::Decoded(Cursor::new(zstd::decode_all(file)?)))
+    } else {
+        Ok(BookFile::Plain(file))
+    }
+}
+
+/// Opens the book file at `path` for reading its original contents.
+pub fn open(path: &Path) -> std::io::Result<BookFile> {
+    open_with(path, key().as_deref())
+}
+
+fn read_with(path: &Path, key: Option<&LibraryKey>) -> std::io::Result<Vec<u8>> {
+    match open_with(path, key)? {
+        BookFile::Plain(mut file) => {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        }
+        BookFile::Decoded(cursor) => Ok(cursor.into_inner()),
+    }
+}
+
+/// The original contents of the book file at `path`.
+pub fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    read_with(path, key().as_deref())
+}
+
+/// `data` as stored: compressed into a zstd frame if `compress` is set (the
+/// frame records the original size, which the storage report reads back),
+/// then sealed if there is a key.
+fn encode(data: &[u8], compress: bool, key: Option<&LibraryKey>) -> std::io::Result<Vec<u8>> {
+    let data = if compress {
+        Cow::Owned(zstd::bulk::compress(data, LEVEL)?)
+    } else {
+        Cow::Borrowed(data)
+    };
+    match key {
+        Some(key) => encryption::seal(key, &data).map_err(std::io::Error::other),
+        None => Ok(data.into_owned()),
+    }
+}
+
+/// Writes a book file, compressed if `compress` is set and encrypted if the
+/// library is. An existing file is replaced whole; see [`replace`].
+pub fn write(path: &Path, data: &[u8], compress: bool) -> std::io::Result<()> {
+    replace(path, &encode(data, compress, key().as_deref())?)
+}
+
+/// Writes `data` next to `path` and moves it over the file there, so the
+/// book is never left half-written and a memory map of the old file (see
+/// [`crate::streaming`]) keeps reading the old contents.
+fn replace(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp_path = Path::new(&temp_name);
+    let result = (|| {
+        let mut file = File::create(temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(temp_path);
+    }
+    result
+}
+
+/// Size of the file at `path` once decrypted and decompressed.
+pub fn original_size(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let head = peek(&mut file)?;
+    if encryption::is_sealed(&head) {
+        return Ok(read(path)?.len() as u64);
+    }
+    if !head.starts_with(&ZSTD_MAGIC) {
+        return Ok(file.metadata()?.len());
+    }
+    // The largest frame header is 18 bytes.
+    let mut header = Vec::with_capacity(18);
+    (&mut file).take(18).read_to_end(&mut header)?;
+    if let Ok(Some(size)) = zstd::zstd_safe::get_frame_content_size(&header) {
+        return Ok(size);
+    }
+    file.rewind()?;
+    std::io::copy(&mut zstd::Decoder::new(file)?, &mut std::io::sink())
+}
+
+/// Rewrites the file at `path`, read with the key `from`, compressed as
+/// `compress` says (or as it was, if `None`) and sealed with `to`, unless it
+/// already is stored that way. Returns the file's size before and after.
+fn recode(
+    path: &Path,
+    compress: Option<bool>,
+    from: Option<&LibraryKey>,
+    to: Option<&LibraryKey>,
+) -> std::io::Result<(u64, u64)> {
+    let before = std::fs::metadata(path)?.len();
+    let current = layout_with(path, from)?;
+    let target = Layout {
+        encrypted: to.is_some(),
+        compressed: compress.unwrap_or(current.compressed),
+    };
+    if current == target {
+        return Ok((before, before));
+    }
+    let data = encode(&read_with(path, from)?, target.compressed, to)?;
+    replace(path, &data)?;
+    Ok((before, std::fs::metadata(path)?.len()))
+}
+
+/// Compresses (or decompresses, if `compress` is false) the file at `path`,
+/// keeping it encrypted if the library is. See [`recode`].
+pub fn convert(path: &Path, compress: bool) -> std::io::Result<(u64, u64)> {
+    let key = key();
+    recode(path, Some(compress), key.as_deref(), key.as_deref())
+}
+
+/// Encrypts the plain file at `path` with `key`.
+pub fn encrypt(path: &Path, key: &LibraryKey) -> std::io::Result<(u64, u64)> {
+    recode(path, None, None, Some(key))
+}
+
+/// Decrypts the file at `path`, sealed with `key`.
+pub fn decrypt(path: &Path, key: &LibraryKey) -> std::io::Result<(u64, u64)> {
+    recode(path, None, Some(key), None)
+}
@@ -0,0 +1,205 @@
+//! Files served to the webview with HTTP range requests, so a viewer can seek
+//! through a very large book (a fixed-layout EPUB's media, a 500 MB PDF)
+//! without the whole file being read. Plain files are memory-mapped and only
+//! the requested bytes are copied out, at most [`MAX_PART`] per response.
+
+use memmap2::Mmap;
+use std::fs::File;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use tauri::http::{header, Response, StatusCode};
+
+/// A file's contents, mapped or, when it had to be decoded, in memory.
+pub enum Contents {
+    Mapped(Mmap),
+    Memory(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Contents::Mapped(map) => map,
+            Contents::Memory(data) => data,
+        }
+    }
+}
+
+/// Maps the file at `path` into memory.
+pub fn map(path: &Path) -> std::io::Result<Contents> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        // Empty files can't be mapped on every platform.
+        return Ok(Contents::Memory(Vec::new()));
+    }
+    // SAFETY: the mapping is only read, and `storage` replaces book files by
+    // renaming a new file over them rather than changing them in place, so
+    // the mapped file keeps its contents. A file truncated by another program
+    // while mapped is the one case this doesn't cover.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(Contents::Mapped(map))
+}
+
+/// Most bytes sent in one response. Viewers ask for the rest with further
+/// range requests, as they must when a server sends less than they asked for.
+const MAX_PART: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+enum Requested {
+    Whole,
+    Part(Range<usize>),
+    Unsatisfiable,
+}
+
+/// All of a file small enough to send at once, else its first part.
+fn whole(len: usize) -> Requested {
+    if len > MAX_PART {
+        Requested::Part(0..MAX_PART)
+    } else {
+        Requested::Whole
+    }
+}
+
+/// What a `Range` header asks of `len` bytes, capped at [`MAX_PART`]. Only a
+/// single byte range is honored; anything else is answered as if there were
+/// no header, which the header allows.
+fn requested(range: Option<&str>, len: usize) -> Requested {
+    let Some(spec) = range.and_then(|r| r.trim().strip_prefix("bytes=")) else {
+        return whole(len);
+    };
+    if spec.contains(',') {
+        return whole(len);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return whole(len);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let part = if start.is_empty() {
+        // The last `end` bytes.
+        match end.parse::<usize>() {
+            Ok(0) => None,
+            Ok(suffix) => Some(len.saturating_sub(suffix)..len),
+            Err(_) => return whole(len),
+        }
+    } else {
+        let Ok(start) = start.parse::<usize>() else {
+            return whole(len);
+        };
+        match end.parse::<usize>() {
+            _ if end.is_empty() => Some(start..len),
+            Ok(end) if start <= end => Some(start..len.min(end.saturating_add(1))),
+            _ => return whole(len),
+        }
+    };
+    match part {
+        Some(part) if part.start < len => {
+            Requested::Part(part.start..part.end.min(part.start + MAX_PART))
+        }
+        _ => Requested::Unsatisfiable,
+    }
+}
+
+fn builder() -> tauri::http::response::Builder {
+    // Viewers fetch the file rather than loading it as an element, and need
+    // to see the range headers across origins.
+    Response::builder()
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "Accept-Ranges, Content-Range, Content-Length",
+        )
+}
+
+pub fn not_found() -> Response<Vec<u8>> {
+    builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(Vec::new())
+        .expect("static response parts")
+}
+
+/// Response with the part of `data` asked for by the `range` header: 206
+/// with that part, or the first part of a large file asked for whole, 416 if
+/// it lies outside `data`, or 200 with all of it.
+pub fn respond(data: &[u8], range: Option<&str>, mime: &str) -> Response<Vec<u8>> {
+    let len = data.len();
+    let builder = builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, mime);
+    match requested(range, len) {
+        Requested::Whole => builder.body(data.to_vec()),
+        Requested::Part(part) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{len}", part.start, part.end - 1),
+            )
+            .body(data[part].to_vec()),
+        Requested::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+            .body(Vec::new()),
+    }
+    .expect("static response parts")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LARGE: usize = 10 * MAX_PART;
+
+    #[test]
+    fn suffix_ranges_count_from_the_end() {
+        assert_eq!(requested(Some("bytes=-10"), 100), Requested::Part(90..100));
+        assert_eq!(requested(Some("bytes=-500"), 100), Requested::Part(0..100));
+        assert_eq!(requested(Some("bytes=-0"), 100), Requested::Unsatisfiable);
+        assert_eq!(
+            requested(Some("bytes=-10"), LARGE),
+            Requested::Part(LARGE - 10..LARGE)
+        );
+    }
+
+    #[test]
+    fn open_ended_ranges_are_capped() {
+        assert_eq!(requested(Some("bytes=10-"), 100), Requested::Part(10..100));
+        assert_eq!(
+            requested(Some("bytes=0-"), LARGE),
+            Requested::Part(0..MAX_PART)
+        );
+        assert_eq!(
+            requested(Some("bytes=10-"), LARGE),
+            Requested::Part(10..10 + MAX_PART)
+        );
+        assert_eq!(
+            requested(Some("bytes=90-200"), 100),
+            Requested::Part(90..100)
+        );
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert_eq!(requested(Some("bytes=100-"), 100), Requested::Unsatisfiable);
+        assert_eq!(
+            requested(Some("bytes=200-300"), 100),
+            Requested::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn unusable_headers_are_ignored() {
+        for range in [
+            None,
+            Some("bytes=50-10"),
+            Some("bytes=0-1,5-6"),
+            Some("items=0-1"),
+        ] {
+            assert_eq!(requested(range, 100), Requested::Whole, "{range:?}");
+            assert_eq!(
+                requested(range, LARGE),
+                Requested::Part(0..MAX_PART),
+                "{range:?}"
+            );
+        }
+    }
+}
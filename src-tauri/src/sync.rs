@@ -0,0 +1,1126 @@
+//! Syncs annotations and reading progress between devices through a single
+//! JSON snapshot on a WebDAV server (e.g. Nextcloud).
+//!
+//! A sync pulls the remote snapshot, merges it with the local one record by
+//! record, applies the result locally and pushes it back. The upload is conditional on
+//! the ETag of the snapshot that was pulled, so two devices syncing at the same
+//! time retry instead of overwriting each other.
+//!
+//! Records are keyed the same way as JSON imports: highlights by
+//! `(book_title, cfi, text)`, bookmarks by `(book_title, cfi)` and progress by
+//! book title. Book files themselves are not synced; progress for books that
+//! aren't in the local library is carried along untouched.
+//!
+//! Merges don't trust wall clocks. Every synced field carries the Lamport
+//! clock of its last edit (see the `v26_sync_clocks` migration), and the
+//! later clock wins field by field, so changing a highlight's color on one
+//! device and its note on another keeps both. Notes also carry a version
+//! vector: when two devices edited a note without seeing each other's edit,
+//! both texts are kept, combined line by line. Deletions travel as
+//! tombstones and win over edits they have seen. Merging is commutative, so
+//! devices converge whatever order they sync in.
+//!
+//! The same snapshots, cut down to the records changed since the last
+//! exchange, are what devices send each other directly in LAN peer sync (see
+//! [`crate::peer`]).
+
+use crate::{run_blocking, DbState};
+use reqwest::StatusCode;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Bumped whenever the snapshot layout changes incompatibly. Version 1
+/// snapshots, from before clocks, are still read.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+const REMOTE_FILE: &str = "readme-sync.json";
+
+/// Tombstones older than this are dropped; a device that has been offline for
+/// longer may resurrect records deleted elsewhere.
+const TOMBSTONE_DAYS: i64 = 90;
+
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncConfig {
+    pub url: String,
+    pub username: String,
+    pub last_synced_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncReport {
+    /// Local records created, updated or deleted from the remote snapshot.
+    pub pulled: usize,
+    /// Records the remote snapshot was missing or had an older version of.
+    pub pushed: usize,
+}
+
+/// Payload of `sync://status` events.
+#[derive(Debug, Serialize, Clone)]
+pub struct SyncStatus {
+    /// `started`, `completed` or `failed`.
+    pub state: &'static str,
+    pub report: Option<SyncReport>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Snapshot {
+    schema_version: u32,
+    highlights: Vec<SyncHighlight>,
+    bookmarks: Vec<SyncBookmark>,
+    progress: Vec<SyncProgress>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct SyncHighlight {
+    book_title: String,
+    cfi: String,
+    text: String,
+    color: String,
+    notes: String,
+    created_at: String,
+    updated_at: String,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    color_clock: String,
+    #[serde(default)]
+    notes_clock: String,
+    /// Latest counter of each device's edits included in `notes`.
+    #[serde(default)]
+    notes_versions: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct SyncBookmark {
+    book_title: String,
+    cfi: String,
+    label: String,
+    created_at: String,
+    updated_at: String,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    label_clock: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct SyncProgress {
+    book_title: String,
+    position: String,
+    percentage: f64,
+    updated_at: String,
+    #[serde(default)]
+    clock: String,
+}
+
+/// Clock given to records from before clocks: counter 0 sorts them before
+/// any real edit, and the timestamp keeps their old order among themselves.
+fn legacy_clock(updated_at: &str) -> String {
+    format!("{:016}-{updated_at}", 0)
+}
+
+/// The counter part of a clock.
+fn counter(clock: &str) -> u64 {
+    clock.get(..16).and_then(|c| c.parse().ok()).unwrap_or(0)
+}
+
+trait Record: Clone + PartialEq {
+    type Key: Ord;
+    fn key(&self) -> Self::Key;
+    /// Combines two versions of the same record. Must not depend on which is
+    /// local, or devices would disagree.
+    fn merge(a: &Self, b: &Self) -> Self;
+}
+
+/// Whether `a` includes every edit `b` does.
+fn covers(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> bool {
+    b.iter()
+        .all(|(device, n)| a.get(device).is_some_and(|m| m >= n))
+}
+
+/// Combines two notes edited independently from a common text: lines both
+/// start and end with are kept once, the differing middles follow each
+/// other, `first`'s before `second`'s.
+fn join_notes(first: &str, second: &str) -> String {
+    if first.is_empty() || first == second {
+        return second.to_string();
+    }
+    if second.is_empty() {
+        return first.to_string();
+    }
+    let a: Vec<&str> = first.lines().collect();
+    let b: Vec<&str> = second.lines().collect();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let mut lines = a[..a.len() - suffix].to_vec();
+    lines.extend_from_slice(&b[prefix..b.len() - suffix]);
+    lines.extend_from_slice(&a[a.len() - suffix..]);
+    lines.join("\n")
+}
+
+/// Picks between a tombstone and a live record: the deletion wins if it
+/// came after every edit of the record.
+fn resolve_deletion<'a, T>(
+    a: &'a T,
+    b: &'a T,
+    deleted: fn(&T) -> bool,
+    clock: fn(&T) -> &str,
+) -> &'a T {
+    let (dead, alive) = if deleted(a) { (a, b) } else { (b, a) };
+    if clock(dead) > clock(alive) {
+        dead
+    } else {
+        alive
+    }
+}
+
+impl SyncHighlight {
+    fn clock(&self) -> &str {
+        self.color_clock.as_str().max(self.notes_clock.as_str())
+    }
+}
+
+impl Record for SyncHighlight {
+    type Key = (String, String, String);
+    fn key(&self) -> Self::Key {
+        (self.book_title.clone(), self.cfi.clone(), self.text.clone())
+    }
+
+    fn merge(a: &Self, b: &Self) -> Self {
+        if a.deleted && b.deleted {
+            return (if (b.clock(), &b.updated_at) > (a.clock(), &a.updated_at) {
+                b
+            } else {
+                a
+            })
+            .clone();
+        }
+        if a.deleted || b.deleted {
+            return resolve_deletion(a, b, |h| h.deleted, Self::clock).clone();
+        }
+
+        let color = if (&b.color_clock, &b.color) > (&a.color_clock, &a.color) {
+            b
+        } else {
+            a
+        };
+        let (first, second) = if (&b.notes_clock, &b.notes) > (&a.notes_clock, &a.notes) {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        let (notes, notes_versions) = match (
+            covers(&a.notes_versions, &b.notes_versions),
+            covers(&b.notes_versions, &a.notes_versions),
+        ) {
+            (true, false) => (a.notes.clone(), a.notes_versions.clone()),
+            (false, true) => (b.notes.clone(), b.notes_versions.clone()),
+            // The same edits, or records from before version vectors.
+            (true, true) => (second.notes.clone(), second.notes_versions.clone()),
+            (false, false) => {
+                let mut versions = first.notes_versions.clone();
+                for (device, &n) in &second.notes_versions {
+                    let entry = versions.entry(device.clone()).or_default();
+                    *entry = (*entry).max(n);
+                }
+                (join_notes(&first.notes, &second.notes), versions)
+            }
+        };
+        SyncHighlight {
+            color: color.color.clone(),
+            color_clock: color.color_clock.clone(),
+            notes,
+            notes_clock: second.notes_clock.clone(),
+            notes_versions,
+            created_at: a.created_at.clone().min(b.created_at.clone()),
+            updated_at: a.updated_at.clone().max(b.updated_at.clone()),
+            ..a.clone()
+        }
+    }
+}
+
+impl Record for SyncBookmark {
+    type Key = (String, String);
+    fn key(&self) -> Self::Key {
+        (self.book_title.clone(), self.cfi.clone())
+    }
+
+    fn merge(a: &Self, b: &Self) -> Self {
+        if a.deleted != b.deleted {
+            return resolve_deletion(a, b, |b| b.deleted, |b| &b.label_clock).clone();
+        }
+        let later =
+            (&b.label_clock, &b.label, &b.updated_at) > (&a.label_clock, &a.label, &a.updated_at);
+        (if later { b } else { a }).clone()
+    }
+}
+
+impl Record for SyncProgress {
+    type Key = String;
+    fn key(&self) -> Self::Key {
+        self.book_title.clone()
+    }
+
+    fn merge(a: &Self, b: &Self) -> Self {
+        let later = (&b.clock, &b.position, &b.updated_at) > (&a.clock, &a.position, &a.updated_at);
+        (if later { b } else { a }).clone()
+    }
+}
+
+/// Result of merging one record type: the merged set, plus the records that
+/// have to be written locally because the remote version won.
+struct Merged<T> {
+    all: Vec<T>,
+    to_apply: Vec<T>,
+    pushed: usize,
+}
+
+/// Merges the local and remote versions of each record.
+fn merge<T: Record>(local: Vec<T>, remote: Vec<T>) -> Merged<T> {
+    let mut by_key: BTreeMap<T::Key, (Option<T>, Option<T>)> = BTreeMap::new();
+    for record in local {
+        let key = record.key();
+        by_key.entry(key).or_default().0 = Some(record);
+    }
+    for record in remote {
+        let key = record.key();
+        by_key.entry(key).or_default().1 = Some(record);
+    }
+
+    let mut merged = Merged {
+        all: Vec::with_capacity(by_key.len()),
+        to_apply: Vec::new(),
+        pushed: 0,
+    };
+    for (_, pair) in by_key {
+        let winner = match pair {
+            (Some(local), Some(remote)) => {
+                let combined = T::merge(&local, &remote);
+                if combined != local {
+                    merged.to_apply.push(combined.clone());
+                }
+                if combined != remote {
+                    merged.pushed += 1;
+                }
+                combined
+            }
+            (Some(local), None) => {
+                merged.pushed += 1;
+                local
+            }
+            (None, Some(remote)) => {
+                merged.to_apply.push(remote.clone());
+                remote
+            }
+            (None, None) => continue,
+        };
+        merged.all.push(winner);
+    }
+    merged
+}
+
+// ---------------------------------------------------------------------------
+// Local database
+// ---------------------------------------------------------------------------
+
+/// The local clock's counter. Records changed after it is read get a higher
+/// `sync_seq`, so it marks how far a peer has been sent changes.
+pub fn current_counter(conn: &Connection) -> rusqlite::Result<u64> {
+    conn.query_row("SELECT counter FROM sync_clock", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|counter| counter as u64)
+}
+
+/// Local records changed after the clock read `since` (all of them without
+/// it), tombstones included.
+pub fn local_changes(conn: &Connection, since: Option<u64>) -> rusqlite::Result<Snapshot> {
+    local_snapshot(conn, since)
+}
+
+/// Merges `remote` records into the database. Returns how many local records
+/// changed.
+pub fn apply_changes(conn: &mut Connection, mut remote: Snapshot) -> Result<usize, String> {
+    if remote.schema_version > SNAPSHOT_VERSION {
+        return Err(format!(
+            "The other device's sync data (version {}) is from a newer version of the app",
+            remote.schema_version
+        ));
+    }
+    remote.fill_legacy_clocks();
+    let local = local_snapshot(conn, None).map_err(|e| e.to_string())?;
+    let latest = remote.latest_counter();
+    let highlights = merge(local.highlights, remote.highlights);
+    let bookmarks = merge(local.bookmarks, remote.bookmarks);
+    let progress = merge(local.progress, remote.progress);
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let changed = apply(
+        &tx,
+        &highlights.to_apply,
+        &bookmarks.to_apply,
+        &progress.to_apply,
+    )
+    .map_err(|e| e.to_string())?;
+    observe(&tx, latest).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(changed)
+}
+
+impl Snapshot {
+    pub fn record_count(&self) -> usize {
+        self.highlights.len() + self.bookmarks.len() + self.progress.len()
+    }
+
+    /// Gives records from a version 1 snapshot the clocks the
+    /// `v26_sync_clocks` migration gave local ones.
+    fn fill_legacy_clocks(&mut self) {
+        for h in &mut self.highlights {
+            if h.color_clock.is_empty() {
+                h.color_clock = legacy_clock(&h.updated_at);
+            }
+            if h.notes_clock.is_empty() {
+                h.notes_clock = legacy_clock(&h.updated_at);
+            }
+        }
+        for b in &mut self.bookmarks {
+            if b.label_clock.is_empty() {
+                b.label_clock = legacy_clock(&b.updated_at);
+            }
+        }
+        for p in &mut self.progress {
+            if p.clock.is_empty() {
+                p.clock = legacy_clock(&p.updated_at);
+            }
+        }
+    }
+
+    /// The highest clock counter in the snapshot.
+    fn latest_counter(&self) -> u64 {
+        let highlights = self.highlights.iter().flat_map(|h| {
+            [counter(&h.color_clock), counter(&h.notes_clock)]
+                .into_iter()
+                .chain(h.notes_versions.values().copied())
+        });
+        let bookmarks = self.bookmarks.iter().map(|b| counter(&b.label_clock));
+        let progress = self.progress.iter().map(|p| counter(&p.clock));
+        highlights
+            .chain(bookmarks)
+            .chain(progress)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Moves the local clock past `counter`, so edits made after a sync come
+/// after everything it brought in.
+fn observe(tx: &Transaction, counter: u64) -> rusqlite::Result<()> {
+    tx.execute(
+        "UPDATE sync_clock SET counter = max(counter, ?1)",
+        params![counter as i64],
+    )?;
+    Ok(())
+}
+
+fn versions(json: String) -> BTreeMap<String, u64> {
+    serde_json::from_str(&json).unwrap_or_default()
+}
+
+/// Every local record, or with `since` only those changed after the clock
+/// read it.
+fn local_snapshot(conn: &Connection, since: Option<u64>) -> rusqlite::Result<Snapshot> {
+    let since = since.map(|counter| counter as i64);
+    conn.execute(
+        "DELETE FROM sync_tombstones WHERE deleted_at < datetime('now', ?1)",
+        params![format!("-{TOMBSTONE_DAYS} days")],
+    )?;
+
+    let mut highlights = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT book_title, cfi, text, color, notes, created_at, COALESCE(updated_at, created_at),
+                color_clock, notes_clock, notes_versions
+         FROM highlights WHERE ?1 IS NULL OR sync_seq > ?1",
+    )?;
+    for row in stmt.query_map(params![since], |row| {
+        Ok(SyncHighlight {
+            book_title: row.get(0)?,
+            cfi: row.get(1)?,
+            text: row.get(2)?,
+            color: row.get(3)?,
+            notes: row.get(4)?,
+            created_at: row.get(5)?,
+            updated_at: row.get(6)?,
+            deleted: false,
+            color_clock: row.get(7)?,
+            notes_clock: row.get(8)?,
+            notes_versions: versions(row.get(9)?),
+        })
+    })? {
+        highlights.push(row?);
+    }
+
+    let mut bookmarks = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT book_title, cfi, label, created_at, COALESCE(updated_at, created_at), label_clock
+         FROM bookmarks WHERE ?1 IS NULL OR sync_seq > ?1",
+    )?;
+    for row in stmt.query_map(params![since], |row| {
+        Ok(SyncBookmark {
+            book_title: row.get(0)?,
+            cfi: row.get(1)?,
+            label: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+            deleted: false,
+            label_clock: row.get(5)?,
+        })
+    })? {
+        bookmarks.push(row?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT kind, book_title, cfi, text, deleted_at, clock FROM sync_tombstones
+         WHERE kind IN ('highlight', 'bookmark') AND (?1 IS NULL OR sync_seq > ?1)",
+    )?;
+    let tombstones = stmt.query_map(params![since], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+        ))
+    })?;
+    for tombstone in tombstones {
+        let (kind, book_title, cfi, text, deleted_at, clock) = tombstone?;
+        if kind == "highlight" {
+            highlights.push(SyncHighlight {
+                book_title,
+                cfi,
+                text,
+                color: String::new(),
+                notes: String::new(),
+                created_at: deleted_at.clone(),
+                updated_at: deleted_at,
+                deleted: true,
+                color_clock: clock.clone(),
+                notes_clock: clock,
+                notes_versions: BTreeMap::new(),
+            });
+        } else {
+            bookmarks.push(SyncBookmark {
+                book_title,
+                cfi,
+                label: String::new(),
+                created_at: deleted_at.clone(),
+                updated_at: deleted_at,
+                deleted: true,
+                label_clock: clock,
+            });
+        }
+    }
+
+    let mut progress = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT title, last_position, last_percentage, COALESCE(progress_updated_at, created_at),
+                progress_clock
+         FROM books WHERE last_position <> '' AND (?1 IS NULL OR sync_seq > ?1)",
+    )?;
+    for row in stmt.query_map(params![since], |row| {
+        let updated_at: String = row.get(3)?;
+        Ok(SyncProgress {
+            book_title: row.get(0)?,
+            position: row.get(1)?,
+            percentage: row.get(2)?,
+            clock: row
+                .get::<_, Option<String>>(4)?
+                .unwrap_or_else(|| legacy_clock(&updated_at)),
+            updated_at,
+        })
+    })? {
+        progress.push(row?);
+    }
+
+    Ok(Snapshot {
+        schema_version: SNAPSHOT_VERSION,
+        highlights,
+        bookmarks,
+        progress,
+    })
+}
+
+fn set_tombstone(
+    tx: &Transaction,
+    kind: &str,
+    book_title: &str,
+    cfi: &str,
+    text: &str,
+    deleted_at: &str,
+    clock: &str,
+) -> rusqlite::Result<()> {
+    tx.execute(
+        "INSERT OR REPLACE INTO sync_tombstones (kind, book_title, cfi, text, deleted_at, clock)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![kind, book_title, cfi, text, deleted_at, clock],
+    )?;
+    Ok(())
+}
+
+/// Writes merged records into the database, preserving their timestamps and
+/// clocks so the next sync doesn't see them as local edits.
+fn apply(
+    tx: &Transaction,
+    highlights: &[SyncHighlight],
+    bookmarks: &[SyncBookmark],
+    progress: &[SyncProgress],
+) -> rusqlite::Result<usize> {
+    let mut changed = 0;
+
+    for h in highlights {
+        if h.deleted {
+            tx.execute(
+                "DELETE FROM highlight_collections WHERE highlight_id IN
+                    (SELECT id FROM highlights WHERE book_title = ?1 AND cfi = ?2 AND text = ?3)",
+                params![h.book_title, h.cfi, h.text],
+            )?;
+            tx.execute(
+                "DELETE FROM highlight_links
+                 WHERE from_id IN
+                    (SELECT id FROM highlights WHERE book_title = ?1 AND cfi = ?2 AND text = ?3)
+                    OR to_id IN
+                    (SELECT id FROM highlights WHERE book_title = ?1 AND cfi = ?2 AND text = ?3)",
+                params![h.book_title, h.cfi, h.text],
+            )?;
+            tx.execute(
+                "DELETE FROM note_revisions WHERE highlight_id IN
+                    (SELECT id FROM highlights WHERE book_title = ?1 AND cfi = ?2 AND text = ?3)",
+                params![h.book_title, h.cfi, h.text],
+            )?;
+            changed += tx.execute(
+                "DELETE FROM highlights WHERE book_title = ?1 AND cfi = ?2 AND text = ?3",
+                params![h.book_title, h.cfi, h.text],
+            )?;
+            set_tombstone(
+                tx,
+                "highlight",
+                &h.book_title,
+                &h.cfi,
+                &h.text,
+                &h.updated_at,
+                h.clock(),
+            )?;
+            continue;
+        }
+        let versions = serde_json::to_string(&h.notes_versions)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let updated = tx.execute(
+            "UPDATE highlights SET color = ?4, notes = ?5, updated_at = ?6,
+                 color_clock = ?7, notes_clock = ?8, notes_versions = ?9
+             WHERE book_title = ?1 AND cfi = ?2 AND text = ?3",
+            params![
+                h.book_title,
+                h.cfi,
+                h.text,
+                h.color,
+                h.notes,
+                h.updated_at,
+                h.color_clock,
+                h.notes_clock,
+                versions
+            ],
+        )?;
+        if updated == 0 {
+            tx.execute(
+                "INSERT INTO highlights (book_title, cfi, text, color, notes, created_at, updated_at,
+                     color_clock, notes_clock, notes_versions)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    h.book_title,
+                    h.cfi,
+                    h.text,
+                    h.color,
+                    h.notes,
+                    h.created_at,
+                    h.updated_at,
+                    h.color_clock,
+                    h.notes_clock,
+                    versions
+                ],
+            )?;
+        }
+        changed += 1;
+    }
+
+    for b in bookmarks {
+        if b.deleted {
+            changed += tx.execute(
+                "DELETE FROM bookmarks WHERE book_title = ?1 AND cfi = ?2",
+                params![b.book_title, b.cfi],
+            )?;
+            set_tombstone(
+                tx,
+                "bookmark",
+                &b.book_title,
+                &b.cfi,
+                "",
+                &b.updated_at,
+                &b.label_clock,
+            )?;
+            continue;
+        }
+        let updated = tx.execute(
+            "UPDATE bookmarks SET label = ?3, updated_at = ?4, label_clock = ?5
+             WHERE book_title = ?1 AND cfi = ?2",
+            params![b.book_title, b.cfi, b.label, b.updated_at, b.label_clock],
+        )?;
+        if updated == 0 {
+            tx.execute(
+                "INSERT INTO bookmarks (book_title, cfi, label, created_at, updated_at, label_clock)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    b.book_title,
+                    b.cfi,
+                    b.label,
+                    b.created_at,
+                    b.updated_at,
+                    b.label_clock
+                ],
+            )?;
+        }
+        changed += 1;
+    }
+
+    for p in progress {
+        changed += tx.execute(
+            "UPDATE books SET last_position = ?2, last_percentage = ?3, progress_updated_at = ?4,
+                 progress_clock = ?5
+             WHERE title = ?1",
+            params![
+                p.book_title,
+                p.position,
+                p.percentage,
+                p.updated_at,
+                p.clock
+            ],
+        )?;
+    }
+
+    Ok(changed)
+}
+
+pub fn load_config(conn: &Connection) -> rusqlite::Result<Option<SyncConfig>> {
+    let setting = |key: &str| {
+        conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    };
+    let (Some(url), Some(username)) = (
+        setting("sync.webdav_url")?,
+        setting("sync.webdav_username")?,
+    ) else {
+        return Ok(None);
+    };
+    Ok(Some(SyncConfig {
+        url,
+        username,
+        last_synced_at: setting("sync.last_synced_at")?,
+    }))
+}
+
+/// Stores the server location. An empty URL turns sync off.
+pub fn save_config(conn: &Connection, url: &str, username: &str) -> rusqlite::Result<()> {
+    if url.trim().is_empty() {
+        conn.execute("DELETE FROM settings WHERE key LIKE 'sync.%'", [])?;
+        return Ok(());
+    }
+    for (key, value) in [
+        ("sync.webdav_url", url.trim()),
+        ("sync.webdav_username", username),
+    ] {
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// WebDAV
+// ---------------------------------------------------------------------------
+
+struct Remote {
+    client: reqwest::Client,
+    url: String,
+    username: String,
+    password: String,
+}
+
+impl Remote {
+    fn file_url(&self) -> String {
+        format!("{}/{REMOTE_FILE}", self.url.trim_end_matches('/'))
+    }
+
+    /// Fetches the remote snapshot and its ETag; `None` if nothing has been
+    /// pushed yet.
+    async fn pull(&self) -> Result<(Option<Snapshot>, Option<String>), String> {
+        let response = self
+            .client
+            .get(self.file_url())
+            .basic_auth(&self.username, Some(&self.password))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok((None, None));
+        }
+        let response = response.error_for_status().map_err(|e| e.to_string())?;
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+        let snapshot: Snapshot =
+            serde_json::from_slice(&bytes).map_err(|e| format!("Invalid sync snapshot: {e}"))?;
+        if snapshot.schema_version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "The server's sync data (version {}) is from a newer version of the app",
+                snapshot.schema_version
+            ));
+        }
+        Ok((Some(snapshot), etag))
+    }
+
+    /// Uploads the snapshot if the remote copy is still the one that was
+    /// pulled. Returns `false` when another device got there first.
+    async fn push(&self, snapshot: &Snapshot, etag: Option<&str>) -> Result<bool, String> {
+        let body = serde_json::to_vec(snapshot).map_err(|e| e.to_string())?;
+        for create_collection in [false, true] {
+            if create_collection {
+                let mkcol = reqwest::Method::from_bytes(b"MKCOL").map_err(|e| e.to_string())?;
+                self.client
+                    .request(mkcol, &self.url)
+                    .basic_auth(&self.username, Some(&self.password))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            let request = self
+                .client
+                .put(self.file_url())
+                .basic_auth(&self.username, Some(&self.password))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            let request = match etag {
+                Some(etag) => request.header(reqwest::header::IF_MATCH, etag),
+                None => request.header(reqwest::header::IF_NONE_MATCH, "*"),
+            };
+            let response = request.send().await.map_err(|e| e.to_string())?;
+            match response.status() {
+                StatusCode::PRECONDITION_FAILED => return Ok(false),
+                // The target folder doesn't exist yet.
+                StatusCode::CONFLICT | StatusCode::NOT_FOUND if !create_collection => continue,
+                _ => {
+                    response.error_for_status().map_err(|e| e.to_string())?;
+                    return Ok(true);
+                }
+            }
+        }
+        Err("Could not create the sync folder on the server".to_string())
+    }
+}
+
+/// Runs one full pull-merge-push cycle.
+pub async fn sync(db: DbState, config: SyncConfig, password: String) -> Result<SyncReport, String> {
+    let remote = Remote {
+        client: reqwest::Client::new(),
+        url: config.url,
+        username: config.username,
+        password,
+    };
+
+    for _ in 0..MAX_ATTEMPTS {
+        let (remote_snapshot, etag) = remote.pull().await?;
+        let had_remote = remote_snapshot.is_some();
+        let mut remote_snapshot = remote_snapshot.unwrap_or_default();
+        remote_snapshot.fill_legacy_clocks();
+
+        let task_db = db.clone();
+        let (report, merged) = run_blocking(move || {
+            let mut conn = task_db.conn()?;
+            let local = local_snapshot(&conn, None).map_err(|e| e.to_string())?;
+            let latest = remote_snapshot.latest_counter();
+
+            let highlights = merge(local.highlights, remote_snapshot.highlights);
+            let bookmarks = merge(local.bookmarks, remote_snapshot.bookmarks);
+            let progress = merge(local.progress, remote_snapshot.progress);
+
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            let pulled = apply(
+                &tx,
+                &highlights.to_apply,
+                &bookmarks.to_apply,
+                &progress.to_apply,
+            )
+            .map_err(|e| e.to_string())?;
+            observe(&tx, latest).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())?;
+
+            let report = SyncReport {
+                pulled,
+                pushed: highlights.pushed + bookmarks.pushed + progress.pushed,
+            };
+            let cutoff: String = conn
+                .query_row(
+                    "SELECT datetime('now', ?1)",
+                    params![format!("-{TOMBSTONE_DAYS} days")],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            let merged = Snapshot {
+                schema_version: SNAPSHOT_VERSION,
+                highlights: highlights
+                    .all
+                    .into_iter()
+                    .filter(|h| !h.deleted || h.updated_at >= cutoff)
+                    .collect(),
+                bookmarks: bookmarks
+                    .all
+                    .into_iter()
+                    .filter(|b| !b.deleted || b.updated_at >= cutoff)
+                    .collect(),
+                progress: progress.all,
+            };
+            Ok((report, merged))
+        })
+        .await?;
+
+        let needs_push = report.pushed > 0 || !had_remote;
+        if needs_push && !remote.push(&merged, etag.as_deref()).await? {
+            log::info!("Sync snapshot changed on the server during sync; retrying");
+            continue;
+        }
+
+        let db = db.clone();
+        run_blocking(move || {
+            let conn = db.conn()?;
+            conn.execute(
+                "INSERT INTO settings (key, value) VALUES ('sync.last_synced_at', datetime('now'))
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                [],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+        .await?;
+        return Ok(report);
+    }
+    Err("The server's sync data kept changing during sync; try again".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(counter: u64, device: &str) -> String {
+        format!("{counter:016}-{device}")
+    }
+
+    fn highlight(notes: &str, notes_clock: String, versions: &[(&str, u64)]) -> SyncHighlight {
+        SyncHighlight {
+            book_title: "Moby-Dick".to_string(),
+            cfi: "epubcfi(/6/4!/4/2/1:0)".to_string(),
+            text: "Call me Ishmael.".to_string(),
+            color: "yellow".to_string(),
+            notes: notes.to_string(),
+            created_at: "2024-01-01 10:00:00".to_string(),
+            updated_at: "2024-01-01 10:00:00".to_string(),
+            deleted: false,
+            color_clock: clock(1, "a"),
+            notes_clock,
+            notes_versions: versions
+                .iter()
+                .map(|(device, n)| (device.to_string(), *n))
+                .collect(),
+        }
+    }
+
+    fn tombstone(deleted_clock: String) -> SyncHighlight {
+        SyncHighlight {
+            deleted: true,
+            color_clock: deleted_clock.clone(),
+            notes_clock: deleted_clock,
+            ..highlight("", clock(0, "a"), &[])
+        }
+    }
+
+    #[test]
+    fn covers_compares_every_device() {
+        let both: BTreeMap<String, u64> = [("a".to_string(), 2), ("b".to_string(), 1)].into();
+        let a_only: BTreeMap<String, u64> = [("a".to_string(), 1)].into();
+        assert!(covers(&both, &a_only));
+        assert!(!covers(&a_only, &both));
+        assert!(covers(&a_only, &BTreeMap::new()));
+        assert!(covers(&BTreeMap::new(), &BTreeMap::new()));
+    }
+
+    #[test]
+    fn join_notes_keeps_shared_lines_once() {
+        assert_eq!(
+            join_notes("Intro\nfrom A\nOutro", "Intro\nfrom B\nOutro"),
+            "Intro\nfrom A\nfrom B\nOutro"
+        );
+        assert_eq!(join_notes("", "only"), "only");
+        assert_eq!(join_notes("only", ""), "only");
+        assert_eq!(join_notes("same", "same"), "same");
+    }
+
+    #[test]
+    fn merge_is_commutative() {
+        let base = highlight("Base", clock(1, "a"), &[("a", 1)]);
+        let edited_a = highlight("Base\nfrom A", clock(2, "a"), &[("a", 2)]);
+        let edited_b = highlight("Base\nfrom B", clock(3, "b"), &[("a", 1), ("b", 3)]);
+        let mut recolored = base.clone();
+        recolored.color = "blue".to_string();
+        recolored.color_clock = clock(4, "b");
+        let legacy = highlight("Old", legacy_clock("2023-05-01 08:00:00"), &[]);
+        let records = [
+            base,
+            edited_a,
+            edited_b,
+            recolored,
+            legacy,
+            tombstone(clock(2, "b")),
+            tombstone(clock(5, "a")),
+        ];
+        for a in &records {
+            for b in &records {
+                assert_eq!(SyncHighlight::merge(a, b), SyncHighlight::merge(b, a));
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_note_edits_are_joined() {
+        let edited_a = highlight("Base\nfrom A", clock(2, "a"), &[("a", 2)]);
+        let edited_b = highlight("Base\nfrom B", clock(3, "b"), &[("a", 1), ("b", 3)]);
+        let merged = SyncHighlight::merge(&edited_a, &edited_b);
+        assert_eq!(merged.notes, "Base\nfrom A\nfrom B");
+        assert_eq!(
+            merged.notes_versions,
+            [("a".to_string(), 2), ("b".to_string(), 3)].into()
+        );
+        assert_eq!(merged.notes_clock, clock(3, "b"));
+    }
+
+    #[test]
+    fn a_note_edit_that_saw_the_other_replaces_it() {
+        let edited_a = highlight("Base\nfrom A", clock(2, "a"), &[("a", 2)]);
+        let rewritten = highlight("Rewritten by B", clock(3, "b"), &[("a", 2), ("b", 3)]);
+        let merged = SyncHighlight::merge(&edited_a, &rewritten);
+        assert_eq!(merged.notes, "Rewritten by B");
+        assert_eq!(merged.notes_versions, rewritten.notes_versions);
+    }
+
+    #[test]
+    fn color_and_notes_merge_separately() {
+        let mut recolored = highlight("Base", clock(1, "a"), &[("a", 1)]);
+        recolored.color = "blue".to_string();
+        recolored.color_clock = clock(4, "a");
+        let annotated = highlight("Base\nmore", clock(3, "b"), &[("a", 1), ("b", 3)]);
+        let merged = SyncHighlight::merge(&recolored, &annotated);
+        assert_eq!(merged.color, "blue");
+        assert_eq!(merged.notes, "Base\nmore");
+    }
+
+    #[test]
+    fn tombstones_lose_to_later_edits() {
+        let edited = highlight("Edited", clock(5, "b"), &[("b", 5)]);
+        let earlier_deletion = tombstone(clock(3, "a"));
+        assert!(!SyncHighlight::merge(&earlier_deletion, &edited).deleted);
+        assert!(!SyncHighlight::merge(&edited, &earlier_deletion).deleted);
+
+        let later_deletion = tombstone(clock(6, "a"));
+        assert!(SyncHighlight::merge(&later_deletion, &edited).deleted);
+        assert!(SyncHighlight::merge(&edited, &later_deletion).deleted);
+    }
+
+    #[test]
+    fn legacy_records_merge_by_timestamp() {
+        let mut older = highlight("Older", legacy_clock("2023-01-01 08:00:00"), &[]);
+        older.updated_at = "2023-01-01 08:00:00".to_string();
+        let mut newer = highlight("Newer", legacy_clock("2023-02-01 08:00:00"), &[]);
+        newer.updated_at = "2023-02-01 08:00:00".to_string();
+        assert_eq!(SyncHighlight::merge(&older, &newer).notes, "Newer");
+        assert_eq!(SyncHighlight::merge(&newer, &older).notes, "Newer");
+
+        // Any edit made with clocks comes after every legacy one.
+        let clocked = highlight("Clocked", clock(1, "a"), &[("a", 1)]);
+        let merged = SyncHighlight::merge(&newer, &clocked);
+        assert_eq!(merged.notes, "Clocked");
+        assert_eq!(merged, SyncHighlight::merge(&clocked, &newer));
+    }
+
+    #[test]
+    fn changes_are_picked_by_when_they_reached_this_device() {
+        let mut conn = crate::db::open_in_memory().unwrap();
+        conn.execute(
+            "INSERT INTO highlights (book_title, cfi, text, color, notes)
+             VALUES ('Moby-Dick', 'epubcfi(/6/2!/4/2/1:0)', 'Local', 'yellow', '')",
+            [],
+        )
+        .unwrap();
+        let watermark = current_counter(&conn).unwrap();
+        assert_eq!(
+            local_changes(&conn, Some(watermark))
+                .unwrap()
+                .record_count(),
+            0
+        );
+        assert_eq!(local_changes(&conn, None).unwrap().record_count(), 1);
+
+        // Relayed from a third device whose clock is far behind this one's.
+        let relayed = highlight("From C", clock(1, "c"), &[("c", 1)]);
+        let remote = Snapshot {
+            schema_version: SNAPSHOT_VERSION,
+            highlights: vec![relayed],
+            ..Snapshot::default()
+        };
+        assert_eq!(apply_changes(&mut conn, remote).unwrap(), 1);
+        conn.execute(
+            "UPDATE highlights SET notes = 'Edited' WHERE text = 'Local'",
+            [],
+        )
+        .unwrap();
+
+        let changes = local_changes(&conn, Some(watermark)).unwrap();
+        let mut notes: Vec<&str> = changes
+            .highlights
+            .iter()
+            .map(|h| h.notes.as_str())
+            .collect();
+        notes.sort();
+        assert_eq!(notes, ["Edited", "From C"]);
+    }
+
+    #[test]
+    fn counter_reads_the_clock_prefix() {
+        assert_eq!(counter(&clock(42, "device")), 42);
+        assert_eq!(counter(&legacy_clock("2024-01-01 10:00:00")), 0);
+        assert_eq!(counter(""), 0);
+    }
+}
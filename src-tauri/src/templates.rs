@@ -0,0 +1,381 @@
+//! [Tera](https://keats.github.io/tera/docs/) templates for exports, shared by
+//! the Obsidian exporter and the user's own Markdown, HTML and CSV templates.
+//!
+//! User templates are files in the profile's `templates` folder, named
+//! `<name>.md`, `<name>.html`, `<name>.csv` or `<name>.txt`; the extension
+//! picks the format, and HTML templates escape their values. A file named like
+//! one of the [`BUILT_IN`] templates replaces it, and deleting it brings the
+//! built-in one back.
+//!
+//! Templates render one book at a time, with `book` and `highlights` (ordered
+//! by position) in their context. Besides Tera's built-ins they get a `quote`
+//! filter that prefixes every line with `> `, and a `csv` filter that quotes a
+//! value as a CSV field.
+
+use crate::deep_link;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tera::{Context, Tera, Value};
+
+const MARKDOWN_TEMPLATE: &str = r#"# {{ book.title }}
+{% if book.author %}
+*{{ book.author }}*
+{% endif %}
+{%- for h in highlights %}
+{{ h.text | quote }}
+{% if h.notes %}
+{{ h.notes }}
+{% endif %}
+{%- endfor %}"#;
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{ book.title }}</title>
+</head>
+<body>
+<h1>{{ book.title }}</h1>
+{%- if book.author %}
+<p><em>{{ book.author }}</em></p>
+{%- endif %}
+{%- for h in highlights %}
+<blockquote style="border-left: 4px solid {{ h.color }}; padding-left: 1em">{{ h.text }}</blockquote>
+{%- if h.notes %}
+<p>{{ h.notes }}</p>
+{%- endif %}
+{%- endfor %}
+</body>
+</html>
+"#;
+
+const CSV_TEMPLATE: &str = r#"Title,Author,Highlight,Note,Color,Date
+{% for h in highlights -%}
+{{ book.title | csv }},{{ book.author | csv }},{{ h.text | csv }},{{ h.notes | csv }},{{ h.color | csv }},{{ h.date | csv }}
+{% endfor %}"#;
+
+/// Templates available without any files, by file name.
+pub const BUILT_IN: [(&str, &str); 3] = [
+    ("book.md", MARKDOWN_TEMPLATE),
+    ("book.html", HTML_TEMPLATE),
+    ("highlights.csv", CSV_TEMPLATE),
+];
+
+const EXTENSIONS: [&str; 4] = ["md", "html", "csv", "txt"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportTemplate {
+    /// File name, with the extension that sets the format.
+    pub name: String,
+    pub content: String,
+    pub built_in: bool,
+    /// A built-in template replaced by the user's file.
+    pub modified: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct BookContext {
+    pub id: Option<i64>,
+    pub title: String,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub publisher: Option<String>,
+    pub year: Option<i64>,
+    pub isbn: Option<String>,
+    pub source_url: Option<String>,
+    pub rating: i64,
+    pub review: Option<String>,
+    pub created_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub summary: Option<String>,
+    /// Names of the collections the book is in.
+    pub collections: Vec<String>,
+    /// `tumelog://` link that opens the book.
+    pub link: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct HighlightContext {
+    pub id: i64,
+    pub text: String,
+    pub notes: String,
+    pub color: String,
+    pub cfi: String,
+    pub created_at: String,
+    /// `created_at` without the time.
+    pub date: String,
+    pub collections: Vec<String>,
+    /// `tumelog://` link that opens the book at the highlight.
+    pub link: String,
+}
+
+fn quote(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("quote", "value", String, value);
+    let quoted: Vec<String> = text
+        .trim()
+        .lines()
+        .map(|line| format!("> {line}").trim_end().to_string())
+        .collect();
+    Ok(Value::String(quoted.join("\n")))
+}
+
+/// Quotes the value when it holds a comma, quote or line break; `null`
+/// becomes an empty field.
+fn csv(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        Ok(Value::String(format!("\"{}\"", text.replace('"', "\"\""))))
+    } else {
+        Ok(Value::String(text))
+    }
+}
+
+/// Compiles `template` under `name`, whose extension decides whether values
+/// are HTML-escaped. Syntax errors are reported with their cause.
+pub fn compile(name: &str, template: &str) -> Result<Tera, String> {
+    let mut tera = Tera::default();
+    tera.register_filter("quote", quote);
+    tera.register_filter("csv", csv);
+    tera.add_raw_template(name, template)
+        .map_err(|e| error_chain(&e))?;
+    Ok(tera)
+}
+
+/// Tera's errors keep the useful detail in their sources.
+pub fn error_chain(e: &tera::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+/// Where the profile in `library_dir` keeps its templates.
+pub fn templates_dir(library_dir: &Path) -> PathBuf {
+    library_dir.join("templates")
+}
+
+/// Checks that `name` is a plain file name with a template extension.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = name.rsplit_once('.').is_some_and(|(stem, extension)| {
+        !stem.is_empty()
+            && stem
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+            && EXTENSIONS.contains(&extension)
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name:?} isn't a valid template name; use letters, digits, spaces, - and _ followed by .md, .html, .csv or .txt"
+        ))
+    }
+}
+
+/// The built-in templates, as replaced by the user's files, then the user's
+/// other templates by name.
+pub fn list(dir: &Path) -> Result<Vec<ExportTemplate>, String> {
+    let mut templates = Vec::new();
+    for (name, content) in BUILT_IN {
+        templates.push(load(dir, name).unwrap_or_else(|_| ExportTemplate {
+            name: name.to_string(),
+            content: content.to_string(),
+            built_in: true,
+            modified: false,
+        }));
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(templates);
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| validate_name(name).is_ok() && !BUILT_IN.iter().any(|(b, _)| b == name))
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    for name in names {
+        templates.push(load(dir, &name)?);
+    }
+    Ok(templates)
+}
+
+/// The template called `name`: the user's file, or the built-in one.
+pub fn load(dir: &Path, name: &str) -> Result<ExportTemplate, String> {
+    validate_name(name)?;
+    let built_in = BUILT_IN.iter().find(|(b, _)| *b == name);
+    match std::fs::read_to_string(dir.join(name)) {
+        Ok(content) => Ok(ExportTemplate {
+            name: name.to_string(),
+            content,
+            built_in: built_in.is_some(),
+            modified: built_in.is_some(),
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => match built_in {
+            Some((_, content)) => Ok(ExportTemplate {
+                name: name.to_string(),
+                content: content.to_string(),
+                built_in: true,
+                modified: false,
+            }),
+            None => Err(format!("No template named {name}")),
+        },
+        Err(e) => Err(format!("Could not read the template {name}: {e}")),
+    }
+}
+
+/// Stores the template after checking that it compiles.
+pub fn save(dir: &Path, name: &str, content: &str) -> Result<ExportTemplate, String> {
+    validate_name(name)?;
+    compile(name, content)?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    std::fs::write(dir.join(name), content).map_err(|e| e.to_string())?;
+    load(dir, name)
+}
+
+/// Deletes the user's template; a built-in one goes back to its default.
+pub fn delete(dir: &Path, name: &str) -> Result<(), String> {
+    validate_name(name)?;
+    match std::fs::remove_file(dir.join(name)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// The book called `title`. Highlights can outlive their book row (e.g. after
+/// a sync), so it may be just the title.
+pub fn book_context(conn: &Connection, title: &str) -> rusqlite::Result<BookContext> {
+    let book = conn
+        .query_row(
+            "SELECT id, title, author, series, series_index, publisher, year, isbn, source_url,
+                    rating, review, created_at, finished_at, summary
+             FROM books WHERE title = ?1",
+            params![title],
+            |row| {
+                Ok(BookContext {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    author: row.get(2)?,
+                    series: row.get(3)?,
+                    series_index: row.get(4)?,
+                    publisher: row.get(5)?,
+                    year: row.get(6)?,
+                    isbn: row.get(7)?,
+                    source_url: row.get(8)?,
+                    rating: row.get(9)?,
+                    review: row.get(10)?,
+                    created_at: row.get(11)?,
+                    finished_at: row.get(12)?,
+                    summary: row.get(13)?,
+                    collections: Vec::new(),
+                    link: None,
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut book) = book else {
+        return Ok(BookContext {
+            title: title.to_string(),
+            ..BookContext::default()
+        });
+    };
+    if let Some(id) = book.id {
+        book.link = Some(deep_link::book_url(id, None));
+        book.collections = conn
+            .prepare(
+                "SELECT c.name FROM collections c
+                 INNER JOIN book_collections bc ON c.id = bc.collection_id
+                 WHERE bc.book_id = ?1 ORDER BY c.name",
+            )?
+            .query_map(params![id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+    }
+    Ok(book)
+}
+
+/// The book's highlights in reading order.
+pub fn highlight_contexts(
+    conn: &Connection,
+    title: &str,
+) -> rusqlite::Result<Vec<HighlightContext>> {
+    let mut collections = conn.prepare(
+        "SELECT c.name FROM collections c
+         INNER JOIN highlight_collections hc ON c.id = hc.collection_id
+         WHERE hc.highlight_id = ?1 ORDER BY c.name",
+    )?;
+    let mut highlights: Vec<HighlightContext> = conn
+        .prepare(
+            "SELECT h.id, h.text, h.notes, h.color, h.cfi, h.created_at, b.id
+             FROM highlights h INNER JOIN books b ON b.title = h.book_title
+             WHERE h.book_title = ?1
+             ORDER BY h.cfi COLLATE cfi_order",
+        )?
+        .query_map(params![title], |row| {
+            let created_at: String = row.get(5)?;
+            let cfi: String = row.get(4)?;
+            Ok(HighlightContext {
+                link: deep_link::book_url(row.get(6)?, Some(&cfi)),
+                id: row.get(0)?,
+                text: row.get(1)?,
+                notes: row.get(2)?,
+                color: row.get(3)?,
+                cfi,
+                date: created_at.get(..10).unwrap_or(&created_at).to_string(),
+                created_at,
+                collections: Vec::new(),
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    for highlight in &mut highlights {
+        highlight.collections = collections
+            .query_map(params![highlight.id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+    }
+    Ok(highlights)
+}
+
+/// Renders the compiled template `name` for the book called `title`.
+pub fn render_book(
+    conn: &Connection,
+    tera: &Tera,
+    name: &str,
+    title: &str,
+) -> Result<String, String> {
+    let book = book_context(conn, title).map_err(|e| e.to_string())?;
+    let highlights = highlight_contexts(conn, title).map_err(|e| e.to_string())?;
+    let mut context = Context::new();
+    context.insert("book", &book);
+    context.insert("highlights", &highlights);
+    tera.render(name, &context)
+        .map_err(|e| format!("{title}: {}", error_chain(&e)))
+}
+
+/// Renders `template` for the book with `book_id`; `name` sets the format,
+/// as for stored templates.
+pub fn render(
+    conn: &Connection,
+    name: &str,
+    template: &str,
+    book_id: i64,
+) -> Result<String, String> {
+    let title: String = conn
+        .query_row(
+            "SELECT title FROM books WHERE id = ?1",
+            params![book_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No book with id {book_id}"))?;
+    let tera = compile(name, template)?;
+    render_book(conn, &tera, name, &title)
+}
@@ -0,0 +1,234 @@
+//! Translations of selected text from a LibreTranslate server or DeepL.
+//!
+//! Providers are configured through the keychain like [`crate::llm`]'s, under
+//! their own names:
+//!
+//! - `libretranslate`: `url` (the server, defaulting to libretranslate.com)
+//!   and `api_key` (optional for self-hosted servers).
+//! - `deepl`: `api_key`, and optionally `url`. Without one, free-plan keys
+//!   (ending in `:fx`) go to the free API and others to the pro API.
+//!
+//! Every translation is kept in the `translations` table, so looking the same
+//! text up again is instant and works offline.
+
+use crate::secrets;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const LIBRETRANSLATE_URL: &str = "https://libretranslate.com";
+const DEEPL_FREE_URL: &str = "https://api-free.deepl.com";
+const DEEPL_PRO_URL: &str = "https://api.deepl.com";
+
+/// Longest selection translated in one request.
+const MAX_TEXT_CHARS: usize = 5_000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslationProvider {
+    LibreTranslate,
+    DeepL,
+}
+
+impl TranslationProvider {
+    /// Name of the provider's keychain namespace and in the cache.
+    fn name(self) -> &'static str {
+        match self {
+            TranslationProvider::LibreTranslate => "libretranslate",
+            TranslationProvider::DeepL => "deepl",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Translation {
+    pub text: String,
+    /// Language the provider detected in the original, if it says.
+    pub source_lang: Option<String>,
+    pub target_lang: String,
+    pub provider: TranslationProvider,
+    /// Whether the translation came from the cache.
+    pub cached: bool,
+}
+
+/// A provider's endpoint and key, read from the keychain.
+pub struct Endpoint {
+    provider: TranslationProvider,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl Endpoint {
+    pub fn load(provider: TranslationProvider) -> Result<Endpoint, String> {
+        let name = provider.name();
+        let api_key = secrets::get(name, "api_key")?.filter(|k| !k.trim().is_empty());
+        let url = secrets::get(name, "url")?.filter(|u| !u.trim().is_empty());
+        let url = match (provider, url, &api_key) {
+            (_, Some(url), _) => url,
+            (TranslationProvider::LibreTranslate, None, _) => LIBRETRANSLATE_URL.to_string(),
+            (TranslationProvider::DeepL, None, Some(key)) if key.trim().ends_with(":fx") => {
+                DEEPL_FREE_URL.to_string()
+            }
+            (TranslationProvider::DeepL, None, Some(_)) => DEEPL_PRO_URL.to_string(),
+            (TranslationProvider::DeepL, None, None) => {
+                return Err("No DeepL API key configured".to_string())
+            }
+        };
+        Ok(Endpoint {
+            provider,
+            url: url.trim().trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+}
+
+/// `text` trimmed and `target_lang` checked, ready to translate or look up.
+pub fn prepare(text: &str, target_lang: &str) -> Result<(String, String), String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Nothing to translate".to_string());
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!(
+            "Select at most {MAX_TEXT_CHARS} characters to translate"
+        ));
+    }
+    let target = target_lang.trim();
+    if target.is_empty()
+        || !target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(format!("Invalid target language {target_lang:?}"));
+    }
+    Ok((text.to_string(), target.to_string()))
+}
+
+/// The cached translation of `text` into `target_lang`, if there is one.
+/// Target languages are cached lowercase, "EN-GB" and "en-gb" being one.
+pub fn cached(
+    conn: &Connection,
+    provider: TranslationProvider,
+    text: &str,
+    target_lang: &str,
+) -> rusqlite::Result<Option<Translation>> {
+    conn.query_row(
+        "SELECT translation, source_lang FROM translations
+         WHERE provider = ?1 AND target_lang = ?2 AND text = ?3",
+        params![provider.name(), target_lang.to_ascii_lowercase(), text],
+        |row| {
+            Ok(Translation {
+                text: row.get(0)?,
+                source_lang: row.get(1)?,
+                target_lang: target_lang.to_string(),
+                provider,
+                cached: true,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Caches `translation` of `text`.
+pub fn store(conn: &Connection, text: &str, translation: &Translation) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO translations (provider, target_lang, text, translation, source_lang)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(provider, target_lang, text) DO UPDATE SET
+             translation = excluded.translation,
+             source_lang = excluded.source_lang,
+             created_at = datetime('now')",
+        params![
+            translation.provider.name(),
+            translation.target_lang.to_ascii_lowercase(),
+            text,
+            translation.text,
+            translation.source_lang
+        ],
+    )?;
+    Ok(())
+}
+
+/// Removes every cached translation. Returns how many there were.
+pub fn clear(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute("DELETE FROM translations", [])
+}
+
+async fn post(request: reqwest::RequestBuilder, body: &Value) -> Result<Value, String> {
+    let response = request
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(body).map_err(|e| e.to_string())?)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    let value: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+    if !status.is_success() {
+        // LibreTranslate reports `error`, DeepL `message`.
+        let message = value["error"]
+            .as_str()
+            .or_else(|| value["message"].as_str())
+            .unwrap_or(text.trim());
+        return Err(format!("Translation failed ({status}): {message}"));
+    }
+    Ok(value)
+}
+
+/// Translates `text` into `target_lang` with the provider at `endpoint`.
+pub async fn translate(
+    client: &reqwest::Client,
+    endpoint: &Endpoint,
+    text: &str,
+    target_lang: &str,
+) -> Result<Translation, String> {
+    let (text, source_lang) = match endpoint.provider {
+        TranslationProvider::LibreTranslate => {
+            let mut body = json!({
+                "q": text,
+                "source": "auto",
+                "target": target_lang,
+                "format": "text",
+            });
+            if let Some(key) = &endpoint.api_key {
+                body["api_key"] = json!(key);
+            }
+            let request = client.post(format!("{}/translate", endpoint.url));
+            let value = post(request, &body).await?;
+            (
+                value["translatedText"].as_str().map(str::to_string),
+                value["detectedLanguage"]["language"]
+                    .as_str()
+                    .map(str::to_string),
+            )
+        }
+        TranslationProvider::DeepL => {
+            let body = json!({
+                "text": [text],
+                "target_lang": target_lang.to_ascii_uppercase(),
+            });
+            let key = endpoint.api_key.as_deref().unwrap_or_default();
+            let request = client
+                .post(format!("{}/v2/translate", endpoint.url))
+                .header(
+                    reqwest::header::AUTHORIZATION,
+                    format!("DeepL-Auth-Key {key}"),
+                );
+            let value = post(request, &body).await?;
+            let translation = &value["translations"][0];
+            (
+                translation["text"].as_str().map(str::to_string),
+                translation["detected_source_language"]
+                    .as_str()
+                    .map(str::to_ascii_lowercase),
+            )
+        }
+    };
+    Ok(Translation {
+        text: text.ok_or("The translation response had no text")?,
+        source_lang,
+        target_lang: target_lang.to_string(),
+        provider: endpoint.provider,
+        cached: false,
+    })
+}
@@ -0,0 +1,382 @@
+//! Read-aloud through the operating system's speech engine (AVFoundation on
+//! macOS, WinRT on Windows, Speech Dispatcher on Linux). The reader speaks one
+//! chunk (typically a passage) at a time and moves on when the engine reports
+//! that the chunk finished, which doubles as the position callback.
+//!
+//! Engines aren't thread-safe, so the engine lives on a thread of its own and
+//! is driven through a channel. None of them can pause mid-utterance: pausing
+//! stops the chunk, and resuming speaks it again from its start.
+
+use ::tts::{Tts, UtteranceId};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+
+const RATE_SETTING: &str = "tts.rate";
+const PITCH_SETTING: &str = "tts.pitch";
+const VOICE_SETTING: &str = "tts.voice";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Voice {
+    /// Passed back as [`TtsSettings::voice`].
+    pub id: String,
+    pub name: String,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TtsSettings {
+    /// Speed relative to the voice's normal pace, 0.5–2.0.
+    pub rate: f64,
+    /// Relative pitch, 0.5–2.0.
+    pub pitch: f64,
+    /// `None` uses the system's default voice.
+    pub voice: Option<String>,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        TtsSettings {
+            rate: 1.0,
+            pitch: 1.0,
+            voice: None,
+        }
+    }
+}
+
+pub fn load_settings(conn: &Connection) -> rusqlite::Result<TtsSettings> {
+    let setting = |key: &str| {
+        conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    };
+    let defaults = TtsSettings::default();
+    Ok(TtsSettings {
+        rate: setting(RATE_SETTING)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.rate),
+        pitch: setting(PITCH_SETTING)?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.pitch),
+        voice: setting(VOICE_SETTING)?.filter(|v| !v.is_empty()),
+    })
+}
+
+pub fn save_settings(conn: &Connection, settings: &TtsSettings) -> rusqlite::Result<()> {
+    for (key, value) in [
+        (RATE_SETTING, settings.rate.clamp(0.5, 2.0).to_string()),
+        (PITCH_SETTING, settings.pitch.clamp(0.5, 2.0).to_string()),
+        (VOICE_SETTING, settings.voice.clone().unwrap_or_default()),
+    ] {
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+    }
+    Ok(())
+}
+
+/// Maps `relative` (0.5–2.0, 1.0 being normal) onto an engine's range.
+fn scale(relative: f64, min: f32, normal: f32, max: f32) -> f32 {
+    let relative = relative.clamp(0.5, 2.0) as f32;
+    if normal > 0.0 {
+        return (normal * relative).clamp(min, max);
+    }
+    // Ranges centred on zero (Speech Dispatcher's -100 to 100): halving or
+    // doubling goes halfway to either end.
+    let step = relative.log2();
+    let span = if step >= 0.0 {
+        max - normal
+    } else {
+        normal - min
+    };
+    normal + step * span / 2.0
+}
+
+fn apply_settings(engine: &mut Tts, settings: &TtsSettings) -> Result<(), String> {
+    let features = engine.supported_features();
+    if features.rate {
+        let rate = scale(
+            settings.rate,
+            engine.min_rate(),
+            engine.normal_rate(),
+            engine.max_rate(),
+        );
+        engine.set_rate(rate).map_err(|e| e.to_string())?;
+    }
+    if features.pitch {
+        let pitch = scale(
+            settings.pitch,
+            engine.min_pitch(),
+            engine.normal_pitch(),
+            engine.max_pitch(),
+        );
+        engine.set_pitch(pitch).map_err(|e| e.to_string())?;
+    }
+    if let (true, Some(id)) = (features.voice, &settings.voice) {
+        let voices = engine.voices().map_err(|e| e.to_string())?;
+        if let Some(voice) = voices.iter().find(|v| v.id() == *id) {
+            engine.set_voice(voice).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+type Job = Box<dyn FnOnce(&mut Tts) + Send>;
+
+struct Utterance {
+    id: u64,
+    text: String,
+    settings: TtsSettings,
+    /// The engine's id for the chunk while it's being spoken; `None` while
+    /// paused.
+    engine_id: Option<UtteranceId>,
+    paused: bool,
+    on_finished: Box<dyn FnOnce(UtteranceFinished) + Send>,
+}
+
+/// The chunk being spoken, if any, and the engine's thread.
+#[derive(Clone, Default)]
+pub struct TtsState(Arc<Mutex<TtsInner>>);
+
+#[derive(Default)]
+struct TtsInner {
+    engine: Option<mpsc::Sender<Job>>,
+    next_id: u64,
+    current: Option<Utterance>,
+    /// Chunks the engine reported finished before it had told us their id.
+    ended_early: Vec<UtteranceId>,
+}
+
+/// Payload of `tts://finished`.
+#[derive(Debug, Serialize, Clone)]
+pub struct UtteranceFinished {
+    pub id: u64,
+    /// Set when the chunk was stopped or replaced rather than spoken to the end.
+    pub interrupted: bool,
+}
+
+impl TtsState {
+    /// Runs `job` on the engine's thread, starting the engine first if it
+    /// isn't running.
+    fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut Tts) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let engine = {
+            let mut inner = self.0.lock().map_err(|e| e.to_string())?;
+            match &inner.engine {
+                Some(engine) => engine.clone(),
+                None => {
+                    let engine = self.start_engine()?;
+                    inner.engine = Some(engine.clone());
+                    engine
+                }
+            }
+        };
+        let (reply, result) = mpsc::channel();
+        engine
+            .send(Box::new(move |tts: &mut Tts| {
+                let _ = reply.send(job(tts));
+            }))
+            .map_err(|_| "Text-to-speech stopped working".to_string())?;
+        result
+            .recv()
+            .map_err(|_| "Text-to-speech stopped working".to_string())?
+    }
+
+    fn start_engine(&self) -> Result<mpsc::Sender<Job>, String> {
+        let (sender, jobs) = mpsc::channel::<Job>();
+        let (started, startup) = mpsc::channel();
+        let state = self.clone();
+        std::thread::spawn(move || {
+            let engine = Tts::default()
+                .map_err(|e| format!("Text-to-speech is unavailable: {e}"))
+                .and_then(|engine| {
+                    if !engine.supported_features().utterance_callbacks {
+                        return Err(
+                            "The speech engine can't report when it finishes speaking".to_string()
+                        );
+                    }
+                    let ended = state.clone();
+                    let stopped = state.clone();
+                    engine
+                        .on_utterance_end(Some(Box::new(move |id| {
+                            ended.engine_finished(id, false)
+                        })))
+                        .and_then(|()| {
+                            engine.on_utterance_stop(Some(Box::new(move |id| {
+                                stopped.engine_finished(id, true)
+                            })))
+                        })
+                        .map_err(|e| e.to_string())?;
+                    Ok(engine)
+                });
+            let mut engine = match engine {
+                Ok(engine) => {
+                    let _ = started.send(Ok(()));
+                    engine
+                }
+                Err(e) => {
+                    let _ = started.send(Err(e));
+                    return;
+                }
+            };
+            for job in jobs {
+                job(&mut engine);
+            }
+        });
+        startup
+            .recv()
+            .map_err(|_| "Text-to-speech is unavailable".to_string())??;
+        Ok(sender)
+    }
+
+    /// Called by the engine when the chunk `engine_id` ended.
+    fn engine_finished(&self, engine_id: UtteranceId, interrupted: bool) {
+        let Ok(mut inner) = self.0.lock() else {
+            return;
+        };
+        if inner
+            .current
+            .as_ref()
+            .is_some_and(|u| u.engine_id.as_ref() == Some(&engine_id))
+        {
+            if let Some(utterance) = inner.current.take() {
+                drop(inner);
+                utterance.finish(interrupted);
+            }
+        } else {
+            inner.ended_early.push(engine_id);
+        }
+    }
+
+    /// Speaks the current chunk `id` and records the engine's id for it.
+    fn start(&self, id: u64) -> Result<(), String> {
+        let (text, settings) = {
+            let inner = self.0.lock().map_err(|e| e.to_string())?;
+            match inner.current.as_ref().filter(|u| u.id == id) {
+                Some(u) => (u.text.clone(), u.settings.clone()),
+                None => return Ok(()),
+            }
+        };
+        let engine_id = self.run(move |engine| {
+            apply_settings(engine, &settings)?;
+            engine.speak(text, true).map_err(|e| e.to_string())
+        })?;
+        let Some(engine_id) = engine_id else {
+            return Err("The speech engine didn't accept the text".to_string());
+        };
+
+        let mut inner = self.0.lock().map_err(|e| e.to_string())?;
+        let ended_early = inner.ended_early.contains(&engine_id);
+        inner.ended_early.clear();
+        match inner.current.as_mut().filter(|u| u.id == id) {
+            Some(_) if ended_early => {
+                if let Some(utterance) = inner.current.take() {
+                    drop(inner);
+                    utterance.finish(false);
+                }
+            }
+            Some(utterance) => utterance.engine_id = Some(engine_id),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Starts speaking `text`, interrupting whatever was being spoken.
+    /// `on_finished` runs once the chunk ends, from a background thread.
+    pub fn speak(
+        &self,
+        text: &str,
+        settings: &TtsSettings,
+        on_finished: impl FnOnce(UtteranceFinished) + Send + 'static,
+    ) -> Result<u64, String> {
+        let (id, replaced) = {
+            let mut inner = self.0.lock().map_err(|e| e.to_string())?;
+            inner.next_id += 1;
+            let id = inner.next_id;
+            let replaced = inner.current.replace(Utterance {
+                id,
+                text: text.to_string(),
+                settings: settings.clone(),
+                engine_id: None,
+                paused: false,
+                on_finished: Box::new(on_finished),
+            });
+            (id, replaced)
+        };
+        if let Some(replaced) = replaced {
+            replaced.finish(true);
+        }
+        if let Err(e) = self.start(id) {
+            let mut inner = self.0.lock().map_err(|e| e.to_string())?;
+            if inner.current.as_ref().is_some_and(|u| u.id == id) {
+                inner.current = None;
+            }
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    pub fn stop(&self) -> Result<(), String> {
+        let current = self.0.lock().map_err(|e| e.to_string())?.current.take();
+        if let Some(utterance) = current {
+            if !utterance.paused {
+                self.run(|engine| engine.stop().map(|_| ()).map_err(|e| e.to_string()))?;
+            }
+            utterance.finish(true);
+        }
+        Ok(())
+    }
+
+    pub fn set_paused(&self, paused: bool) -> Result<(), String> {
+        let id = {
+            let mut inner = self.0.lock().map_err(|e| e.to_string())?;
+            let Some(utterance) = inner.current.as_mut() else {
+                return Ok(());
+            };
+            if utterance.paused == paused {
+                return Ok(());
+            }
+            utterance.paused = paused;
+            // The engine reports the stop, which mustn't end the chunk.
+            utterance.engine_id = None;
+            utterance.id
+        };
+        if paused {
+            self.run(|engine| engine.stop().map(|_| ()).map_err(|e| e.to_string()))
+        } else {
+            self.start(id)
+        }
+    }
+
+    /// Voices installed on the system.
+    pub fn voices(&self) -> Result<Vec<Voice>, String> {
+        self.run(|engine| {
+            let voices = engine.voices().map_err(|e| e.to_string())?;
+            Ok(voices
+                .into_iter()
+                .map(|voice| Voice {
+                    id: voice.id(),
+                    name: voice.name(),
+                    language: Some(voice.language().to_string()),
+                })
+                .collect())
+        })
+    }
+}
+
+impl Utterance {
+    fn finish(self, interrupted: bool) {
+        (self.on_finished)(UtteranceFinished {
+            id: self.id,
+            interrupted,
+        });
+    }
+}
@@ -0,0 +1,288 @@
+//! Undo and redo of highlight, bookmark and collection changes, replayed from
+//! the oplog (see [`crate::oplog`]).
+//!
+//! Commands that change those call [`mark`] before writing and [`record`]
+//! after, which puts the oplog entries in between on the undo stack as one
+//! operation. Undoing applies their inverses newest first; redoing applies
+//! them again in order. Recording a new operation drops whatever had been
+//! undone. Undo and redo are journaled like any other change, and sync sees
+//! them as fresh edits.
+
+use crate::oplog::{self, Change};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// Tables whose changes are undoable.
+const TABLES: &str = "'highlights', 'bookmarks', 'collections', 'highlight_collections',
+     'book_collections', 'highlight_links', 'note_revisions'";
+
+/// Operations kept on the stack.
+const MAX_OPERATIONS: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UndoState {
+    /// Label of the operation undo would reverse.
+    pub undo: Option<String>,
+    /// Label of the operation redo would reapply.
+    pub redo: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Undo,
+    Redo,
+}
+
+/// Where the oplog stands before an undoable command writes anything.
+pub fn mark(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT COALESCE(MAX(id), 0) FROM oplog", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Puts the undoable changes made since `since` on the stack as one
+/// operation called `label`. Changes made meanwhile by other commands end up
+/// in it too.
+pub fn record(conn: &Connection, label: &str, since: i64) -> Result<(), String> {
+    let (first, last): (Option<i64>, Option<i64>) = conn
+        .query_row(
+            &format!(
+                "SELECT MIN(id), MAX(id) FROM oplog WHERE id > ?1 AND table_name IN ({TABLES})"
+            ),
+            params![since],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok(());
+    };
+    conn.execute_batch("DELETE FROM undo_stack WHERE undone = 1")
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO undo_stack (label, first_change, last_change) VALUES (?1, ?2, ?3)",
+        params![label, first, last],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM undo_stack WHERE id <= (SELECT MAX(id) FROM undo_stack) - ?1",
+        params![MAX_OPERATIONS],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn state(conn: &Connection) -> rusqlite::Result<UndoState> {
+    let label = |sql: &str| conn.query_row(sql, [], |row| row.get(0)).optional();
+    Ok(UndoState {
+        undo: label("SELECT label FROM undo_stack WHERE undone = 0 ORDER BY id DESC LIMIT 1")?,
+        redo: label("SELECT label FROM undo_stack WHERE undone = 1 ORDER BY id LIMIT 1")?,
+    })
+}
+
+/// Reverses the latest operation not yet undone. Returns its label, or
+/// `None` if there is nothing to undo.
+pub fn undo(conn: &mut Connection) -> Result<Option<String>, String> {
+    step(conn, Direction::Undo)
+}
+
+/// Reapplies the operation undone last. Returns its label, or `None` if
+/// there is nothing to redo.
+pub fn redo(conn: &mut Connection) -> Result<Option<String>, String> {
+    step(conn, Direction::Redo)
+}
+
+fn step(conn: &mut Connection, direction: Direction) -> Result<Option<String>, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let next = match direction {
+        Direction::Undo => "WHERE undone = 0 ORDER BY id DESC",
+        Direction::Redo => "WHERE undone = 1 ORDER BY id",
+    };
+    loop {
+        let operation: Option<(i64, String, i64, i64)> = tx
+            .query_row(
+                &format!(
+                    "SELECT id, label, first_change, last_change FROM undo_stack {next} LIMIT 1"
+                ),
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((id, label, first, last)) = operation else {
+            return Ok(None);
+        };
+        let changes = tx
+            .prepare(&format!(
+                "SELECT id, table_name, op, row_id, old, new, at FROM oplog
+                 WHERE id BETWEEN ?1 AND ?2 AND table_name IN ({TABLES}) ORDER BY id"
+            ))
+            .map_err(|e| e.to_string())?
+            .query_map(params![first, last], oplog::change_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+        // The journal entries have been pruned; the operation is gone.
+        if changes.is_empty() {
+            tx.execute("DELETE FROM undo_stack WHERE id = ?1", params![id])
+                .map_err(|e| e.to_string())?;
+            continue;
+        }
+        match direction {
+            Direction::Undo => {
+                for change in changes.iter().rev() {
+                    revert(&tx, change)?;
+                }
+            }
+            Direction::Redo => {
+                for change in &changes {
+                    reapply(&tx, change)?;
+                }
+            }
+        }
+        // Sync would otherwise delete records brought back under an identity
+        // it saw retired, e.g. by undoing a merge.
+        tx.execute_batch(
+            "DELETE FROM sync_tombstones WHERE kind = 'highlight' AND EXISTS (
+                 SELECT 1 FROM highlights h WHERE h.book_title = sync_tombstones.book_title
+                     AND h.cfi = sync_tombstones.cfi AND h.text = sync_tombstones.text);
+             DELETE FROM sync_tombstones WHERE kind = 'bookmark' AND EXISTS (
+                 SELECT 1 FROM bookmarks b WHERE b.book_title = sync_tombstones.book_title
+                     AND b.cfi = sync_tombstones.cfi);",
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE undo_stack SET undone = ?2 WHERE id = ?1",
+            params![id, direction == Direction::Undo],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        return Ok(Some(label));
+    }
+}
+
+fn revert(tx: &Transaction, change: &Change) -> Result<(), String> {
+    match (change.op.as_str(), &change.old, &change.new) {
+        ("insert", _, Some(row)) => delete(tx, &change.table, row),
+        ("delete", Some(row), _) => insert(tx, &change.table, row),
+        ("update", Some(old), _) => update(tx, &change.table, old),
+        _ => Err(format!("Can't undo oplog entry {}", change.id)),
+    }
+}
+
+fn reapply(tx: &Transaction, change: &Change) -> Result<(), String> {
+    match (change.op.as_str(), &change.old, &change.new) {
+        ("insert", _, Some(row)) => insert(tx, &change.table, row),
+        ("delete", Some(row), _) => delete(tx, &change.table, row),
+        ("update", _, Some(new)) => update(tx, &change.table, new),
+        _ => Err(format!("Can't redo oplog entry {}", change.id)),
+    }
+}
+
+fn sql_value(value: &serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+        serde_json::Value::Number(n) => n
+            .as_i64()
+            .map(Value::Integer)
+            .unwrap_or_else(|| Value::Real(n.as_f64().unwrap_or_default())),
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+/// The row's fields that are still columns of `table`, so journal entries
+/// never put anything but column names into SQL.
+fn fields(
+    tx: &Transaction,
+    table: &str,
+    row: &serde_json::Value,
+) -> Result<Vec<(String, Value)>, String> {
+    let columns = tx
+        .prepare("SELECT name FROM pragma_table_info(?1)")
+        .map_err(|e| e.to_string())?
+        .query_map([table], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(|e| e.to_string())?;
+    let row = row.as_object().ok_or("Malformed oplog entry")?;
+    Ok(columns
+        .into_iter()
+        .filter_map(|column| {
+            let value = sql_value(row.get(&column)?);
+            Some((column, value))
+        })
+        .collect())
+}
+
+/// A `WHERE` clause finding the row: by `id` where the table has one, by
+/// every field otherwise (link tables).
+fn locate(fields: &[(String, Value)]) -> (String, Vec<Value>) {
+    let key: Vec<&(String, Value)> = match fields.iter().find(|(column, _)| column == "id") {
+        Some(id) => vec![id],
+        None => fields.iter().collect(),
+    };
+    let clause = key
+        .iter()
+        .map(|(column, _)| format!("\"{column}\" IS ?"))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    (clause, key.into_iter().map(|(_, v)| v.clone()).collect())
+}
+
+fn table_name(table: &str) -> Result<&str, String> {
+    // Only ever one of TABLES, which are plain identifiers.
+    if TABLES.contains(&format!("'{table}'")) {
+        Ok(table)
+    } else {
+        Err(format!("{table} changes can't be undone"))
+    }
+}
+
+fn insert(tx: &Transaction, table: &str, row: &serde_json::Value) -> Result<(), String> {
+    let table = table_name(table)?;
+    let fields = fields(tx, table, row)?;
+    let columns: Vec<String> = fields.iter().map(|(c, _)| format!("\"{c}\"")).collect();
+    let placeholders = vec!["?"; fields.len()].join(", ");
+    // A link that is back already is fine; anything else in the way is not.
+    let or_ignore = if fields.iter().any(|(c, _)| c == "id") {
+        ""
+    } else {
+        " OR IGNORE"
+    };
+    tx.execute(
+        &format!(
+            "INSERT{or_ignore} INTO {table} ({}) VALUES ({placeholders})",
+            columns.join(", ")
+        ),
+        params_from_iter(fields.into_iter().map(|(_, v)| v)),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn delete(tx: &Transaction, table: &str, row: &serde_json::Value) -> Result<(), String> {
+    let table = table_name(table)?;
+    let (clause, key) = locate(&fields(tx, table, row)?);
+    tx.execute(
+        &format!("DELETE FROM {table} WHERE {clause}"),
+        params_from_iter(key),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn update(tx: &Transaction, table: &str, row: &serde_json::Value) -> Result<(), String> {
+    let table = table_name(table)?;
+    let fields = fields(tx, table, row)?;
+    let (clause, key) = locate(&fields);
+    let set: Vec<String> = fields.iter().map(|(c, _)| format!("\"{c}\" = ?")).collect();
+    tx.execute(
+        &format!("UPDATE {table} SET {} WHERE {clause}", set.join(", ")),
+        params_from_iter(fields.into_iter().map(|(_, v)| v).chain(key)),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,138 @@
+//! A folder whose book files are imported as they appear, such as a browser's
+//! downloads folder. Only the folder itself is watched, not its subfolders.
+//!
+//! The folder and what to do with a file once it's in the library are stored
+//! in the settings table as `watch_folder` (JSON).
+
+use crate::import::OPENABLE_EXTENSIONS;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const SETTING: &str = "watch_folder";
+
+/// Subfolder imported files are moved to when no other is chosen.
+const DEFAULT_MOVE_TO: &str = "Imported";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AfterImport {
+    /// Leave the file where it is.
+    #[default]
+    Keep,
+    /// Move it to `move_to`, or an `Imported` subfolder.
+    Move,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WatchFolderConfig {
+    pub path: String,
+    #[serde(default)]
+    pub after_import: AfterImport,
+    pub move_to: Option<String>,
+}
+
+/// Payload of `watch-folder://imported` events.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WatchFolderImport {
+    pub filename: String,
+    pub book_id: Option<i64>,
+    /// `false` when the file was already in the library.
+    pub added: bool,
+    pub error: Option<String>,
+}
+
+pub fn config(conn: &Connection) -> Result<Option<WatchFolderConfig>, String> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![SETTING],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+}
+
+/// Stores the watch folder, or stops watching with `None`.
+pub fn set_config(conn: &Connection, config: Option<&WatchFolderConfig>) -> Result<(), String> {
+    let Some(config) = config else {
+        conn.execute("DELETE FROM settings WHERE key = ?1", params![SETTING])
+            .map_err(|e| e.to_string())?;
+        return Ok(());
+    };
+    if !Path::new(&config.path).is_dir() {
+        return Err(format!("{} is not a folder", config.path));
+    }
+    let json = serde_json::to_string(config).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![SETTING, json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Whether `path` is a book file directly inside `folder`. Partial downloads
+/// (`.crdownload`, `.part`) don't count until they're renamed.
+pub fn is_book_file(folder: &Path, path: &Path) -> bool {
+    path.parent() == Some(folder)
+        && !path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        && path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| OPENABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// The book files already in the folder.
+pub fn scan(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_book_file(folder, path))
+        .collect()
+}
+
+/// Moves or deletes the imported file as configured.
+pub fn after_import(config: &WatchFolderConfig, path: &Path) -> Result<(), String> {
+    match config.after_import {
+        AfterImport::Keep => Ok(()),
+        AfterImport::Delete => std::fs::remove_file(path).map_err(|e| e.to_string()),
+        AfterImport::Move => {
+            let dir = config
+                .move_to
+                .as_deref()
+                .filter(|dir| !dir.trim().is_empty())
+                .map_or_else(
+                    || Path::new(&config.path).join(DEFAULT_MOVE_TO),
+                    PathBuf::from,
+                );
+            std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+            let name = path.file_name().ok_or("Not a file")?;
+            let mut target = dir.join(name);
+            let stem = path.file_stem().unwrap_or(name).to_string_lossy();
+            let extension = path
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            let mut n = 2;
+            while target.exists() {
+                target = dir.join(format!("{stem} ({n}){extension}"));
+                n += 1;
+            }
+            // A rename fails across filesystems; copy and delete instead.
+            if std::fs::rename(path, &target).is_err() {
+                std::fs::copy(path, &target).map_err(|e| e.to_string())?;
+                std::fs::remove_file(path).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! A look back at one calendar year of reading: what was finished, how much
+//! time went into it, and what stood out.
+//!
+//! Everything is counted in local time, like the heatmap and goals. Genres
+//! are the book collections a book is in (Calibre tags are imported as
+//! collections), and authors come from the library's author list.
+
+use crate::db::{highlight_from_row, streaks, HIGHLIGHT_COLUMNS};
+use crate::models::Highlight;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// How many entries the top-N lists hold.
+const TOP: i64 = 5;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FinishedBook {
+    pub id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub finished_at: String,
+    pub rating: i64,
+}
+
+/// An author or genre with the reading time that went into it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RankedName {
+    pub name: String,
+    pub books: i64,
+    pub minutes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HighlightedBook {
+    pub title: String,
+    pub highlights: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct YearReview {
+    pub year: i32,
+    pub books_finished: Vec<FinishedBook>,
+    pub minutes_read: i64,
+    pub pages_read: i64,
+    pub sessions: i64,
+    pub days_read: i64,
+    /// Longest run of consecutive days with any reading within the year.
+    pub longest_streak: i64,
+    pub top_authors: Vec<RankedName>,
+    pub top_genres: Vec<RankedName>,
+    pub highlights_made: i64,
+    pub most_highlighted_book: Option<HighlightedBook>,
+    /// Favorite highlights made during the year, topped up with annotated
+    /// ones when there are few favorites.
+    pub favorite_quotes: Vec<Highlight>,
+    /// The review rendered as Markdown, when asked for.
+    pub markdown: Option<String>,
+}
+
+/// Reading time per name for books read during the year, most first.
+/// `names` must select `(book_id, name)` pairs.
+fn ranked(conn: &Connection, year: i32, names: &str) -> rusqlite::Result<Vec<RankedName>> {
+    let sql = format!(
+        "SELECT n.name, COUNT(DISTINCT b.id), SUM(s.seconds) / 60
+         FROM reading_sessions s
+         INNER JOIN books b ON b.title = s.book_title
+         INNER JOIN ({names}) n ON n.book_id = b.id
+         WHERE strftime('%Y', s.ended_at, 'localtime') = printf('%04d', ?1)
+         GROUP BY n.name
+         ORDER BY SUM(s.seconds) DESC, n.name
+         LIMIT ?2"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params![year, TOP], |row| {
+        Ok(RankedName {
+            name: row.get(0)?,
+            books: row.get(1)?,
+            minutes: row.get(2)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Computes the review of `year`.
+pub fn compute(conn: &Connection, year: i32) -> rusqlite::Result<YearReview> {
+    let books_finished = conn
+        .prepare(
+            "SELECT id, title, author, finished_at, rating FROM books
+             WHERE strftime('%Y', finished_at, 'localtime') = printf('%04d', ?1)
+             ORDER BY finished_at",
+        )?
+        .query_map(params![year], |row| {
+            Ok(FinishedBook {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                author: row.get(2)?,
+                finished_at: row.get(3)?,
+                rating: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let (seconds, pages_read, sessions): (i64, i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(seconds), 0), COALESCE(SUM(pages), 0), COUNT(*)
+         FROM reading_sessions
+         WHERE strftime('%Y', ended_at, 'localtime') = printf('%04d', ?1)",
+        params![year],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    let days: Vec<i64> = conn
+        .prepare(
+            "SELECT DISTINCT CAST(julianday(date(ended_at, 'localtime')) AS INTEGER) AS day
+             FROM reading_sessions
+             WHERE strftime('%Y', ended_at, 'localtime') = printf('%04d', ?1)
+             ORDER BY day DESC",
+        )?
+        .query_map(params![year], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    // Only the longest run is wanted, so any "today" will do.
+    let (_, longest_streak) = streaks(&days, 0);
+
+    let top_authors = ranked(
+        conn,
+        year,
+        "SELECT ba.book_id, a.name FROM book_authors ba
+         INNER JOIN authors a ON a.id = ba.author_id",
+    )?;
+    let top_genres = ranked(
+        conn,
+        year,
+        "SELECT bc.book_id, c.name FROM book_collections bc
+         INNER JOIN collections c ON c.id = bc.collection_id",
+    )?;
+
+    let highlights_made = conn.query_row(
+        "SELECT COUNT(*) FROM highlights
+         WHERE strftime('%Y', created_at, 'localtime') = printf('%04d', ?1)",
+        params![year],
+        |row| row.get(0),
+    )?;
+    let most_highlighted_book = conn
+        .query_row(
+            "SELECT book_title, COUNT(*) FROM highlights
+             WHERE strftime('%Y', created_at, 'localtime') = printf('%04d', ?1)
+             GROUP BY book_title
+             ORDER BY COUNT(*) DESC, MIN(created_at)
+             LIMIT 1",
+            params![year],
+            |row| {
+                Ok(HighlightedBook {
+                    title: row.get(0)?,
+                    highlights: row.get(1)?,
+                })
+            },
+        )
+        .optional()?;
+    let favorite_quotes = conn
+        .prepare(&format!(
+            "SELECT {HIGHLIGHT_COLUMNS} FROM highlights h
+             WHERE strftime('%Y', h.created_at, 'localtime') = printf('%04d', ?1)
+               AND (h.favorite OR h.notes <> '')
+             ORDER BY h.favorite DESC, h.created_at
+             LIMIT ?2"
+        ))?
+        .query_map(params![year, TOP], highlight_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(YearReview {
+        year,
+        books_finished,
+        minutes_read: seconds / 60,
+        pages_read,
+        sessions,
+        days_read: days.len() as i64,
+        longest_streak,
+        top_authors,
+        top_genres,
+        highlights_made,
+        most_highlighted_book,
+        favorite_quotes,
+        markdown: None,
+    })
+}
+
+/// Renders `review` as a Markdown document.
+pub fn markdown(review: &YearReview) -> String {
+    let mut out = format!("# {} in reading\n\n", review.year);
+    out.push_str(&format!(
+        "- **{}** books finished\n- **{}** hours read over {} days ({} sessions)\n- **{}** pages\n- Longest streak: **{}** days\n- **{}** highlights\n",
+        review.books_finished.len(),
+        review.minutes_read / 60,
+        review.days_read,
+        review.sessions,
+        review.pages_read,
+        review.longest_streak,
+        review.highlights_made,
+    ));
+
+    if !review.books_finished.is_empty() {
+        out.push_str("\n## Finished\n\n");
+        for book in &review.books_finished {
+            out.push_str(&format!("- {}", book.title));
+            if let Some(author) = &book.author {
+                out.push_str(&format!(" by {author}"));
+            }
+            if book.rating > 0 {
+                out.push_str(&format!(" {}", "★".repeat(book.rating as usize)));
+            }
+            out.push('\n');
+        }
+    }
+
+    for (heading, names) in [
+        ("Top authors", &review.top_authors),
+        ("Top genres", &review.top_genres),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n## {heading}\n\n"));
+        for (rank, entry) in names.iter().enumerate() {
+            out.push_str(&format!(
+                "{}. {} ({} min)\n",
+                rank + 1,
+                entry.name,
+                entry.minutes
+            ));
+        }
+    }
+
+    if let Some(book) = &review.most_highlighted_book {
+        out.push_str(&format!(
+            "\n## Most highlighted\n\n{} ({} highlights)\n",
+            book.title, book.highlights
+        ));
+    }
+
+    if !review.favorite_quotes.is_empty() {
+        out.push_str("\n## Favorite quotes\n");
+        for quote in &review.favorite_quotes {
+            out.push('\n');
+            for line in quote.text.trim().lines() {
+                out.push_str(&format!("> {line}\n"));
+            }
+            out.push_str(&format!(">\n> — *{}*\n", quote.book_title));
+        }
+    }
+    out
+}
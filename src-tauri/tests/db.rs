@@ -0,0 +1,700 @@
+//! The database layer against an in-memory SQLite database.
+
+use app_lib::db::{
+    self, book_notes, book_tags, bookmarks, books, collections, drawings, feeds, highlight_links,
+    highlights, review,
+};
+use app_lib::models::{
+    AnnotationGeometry, AnnotationType, BookQuery, BookSortBy, BookmarkSort, Highlight,
+    HighlightStyle, LibrarySort, NewRegionAnnotation, ReadingStatus, Stroke, Tag,
+};
+use rusqlite::{params, Connection};
+
+fn library() -> Connection {
+    db::open_in_memory().expect("migrated in-memory database")
+}
+
+fn add_book(conn: &Connection, title: &str) -> i64 {
+    conn.execute(
+        "INSERT INTO books (title, filename, format) VALUES (?1, ?2, 'epub')",
+        params![title, format!("{title}.epub")],
+    )
+    .unwrap();
+    conn.last_insert_rowid()
+}
+
+fn add_highlight(conn: &Connection, book: &str, cfi: &str, text: &str) -> i64 {
+    highlights::add(
+        conn,
+        book,
+        cfi,
+        text,
+        "#facc15",
+        HighlightStyle::Highlight,
+        "",
+    )
+    .unwrap()
+    .id
+}
+
+#[test]
+fn books_are_listed_with_their_counts() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    add_book(&conn, "Emma");
+    add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+    bookmarks::add(&conn, "Dune", "epubcfi(/6/4!/4/2/1:0)", "Start").unwrap();
+    books::toggle_favorite(&conn, dune).unwrap();
+
+    let listed = books::list(&conn, LibrarySort::Title, None).unwrap();
+    let titles: Vec<&str> = listed.iter().map(|b| b.book.title.as_str()).collect();
+    assert_eq!(titles, ["Dune", "Emma"]);
+    assert_eq!(listed[0].highlight_count, 1);
+    assert_eq!(listed[0].bookmark_count, 1);
+    assert_eq!(listed[1].highlight_count, 0);
+
+    assert_eq!(books::favorites(&conn).unwrap().len(), 1);
+    assert!(books::by_title(&conn, "Missing").unwrap().is_none());
+}
+
+#[test]
+fn ratings_are_validated_and_filter_the_list() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    add_book(&conn, "Emma");
+
+    assert!(books::set_rating(&conn, dune, 6).is_err());
+    assert_eq!(books::set_rating(&conn, dune, 4).unwrap().rating, 4);
+    let rated = books::list(&conn, LibrarySort::Rating, Some(3)).unwrap();
+    assert_eq!(rated.len(), 1);
+    assert_eq!(rated[0].book.id, dune);
+}
+
+#[test]
+fn books_are_searched_ignoring_case_and_accents() {
+    let conn = library();
+    add_book(&conn, "Les Misérables");
+    let emma = add_book(&conn, "Émile");
+    add_book(&conn, "Dune");
+    conn.execute(
+        "UPDATE books SET last_percentage = 0.4 WHERE id = ?1",
+        params![emma],
+    )
+    .unwrap();
+
+    let titles = |query: BookQuery| -> Vec<String> {
+        books::query_books(&conn, &query)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.book.title)
+            .collect()
+    };
+    let search = |text: &str| BookQuery {
+        search: Some(text.to_string()),
+        ..Default::default()
+    };
+    assert_eq!(titles(search("MISERABLES")), ["Les Misérables"]);
+    assert_eq!(titles(search("emile")), ["Émile"]);
+    assert_eq!(
+        titles(BookQuery::default()),
+        ["Dune", "Émile", "Les Misérables"]
+    );
+    assert_eq!(
+        titles(BookQuery {
+            sort_by: BookSortBy::Progress,
+            status: Some(ReadingStatus::Unread),
+            ..Default::default()
+        }),
+        ["Dune", "Les Misérables"]
+    );
+}
+
+#[test]
+fn tags_ignore_case_and_accents_and_merge_on_rename() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    let emile = add_book(&conn, "Émile");
+    let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        book_tags::add(
+            &conn,
+            dune,
+            &tags(&["Science Fiction", "science fiction", " "])
+        )
+        .unwrap(),
+        1
+    );
+    book_tags::add(&conn, emile, &tags(&["Éducation", "Sci-Fi"])).unwrap();
+    book_tags::add(&conn, dune, &tags(&["Sci-Fi"])).unwrap();
+
+    let titles = |tag: &str| {
+        books::by_tag(&conn, tag)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.title)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(titles("EDUCATION"), ["Émile"]);
+    assert_eq!(titles("sci-fi"), ["Dune", "Émile"]);
+
+    assert_eq!(
+        book_tags::rename(&conn, "sci-fi", "Science Fiction").unwrap(),
+        2
+    );
+    assert_eq!(
+        book_tags::for_book(&conn, dune).unwrap(),
+        ["Science Fiction"]
+    );
+    assert_eq!(
+        book_tags::all(&conn).unwrap(),
+        [
+            Tag {
+                name: "Éducation".to_string(),
+                book_count: 1
+            },
+            Tag {
+                name: "Science Fiction".to_string(),
+                book_count: 2
+            },
+        ]
+    );
+    assert!(book_tags::remove(&conn, emile, "education").unwrap());
+    assert_eq!(book_tags::delete(&conn, "SCIENCE FICTION").unwrap(), 2);
+    assert!(book_tags::all(&conn).unwrap().is_empty());
+}
+
+#[test]
+fn titles_are_unique_and_sorted_ignoring_case_and_accents() {
+    let conn = library();
+    add_book(&conn, "Zola");
+    add_book(&conn, "Étranger");
+    add_book(&conn, "camus");
+
+    assert!(conn
+        .execute(
+            "INSERT INTO books (title, filename, format) VALUES ('CÁMUS', 'x.epub', 'epub')",
+            [],
+        )
+        .is_err());
+    let listed = books::list(&conn, LibrarySort::Title, None).unwrap();
+    let titles: Vec<&str> = listed.iter().map(|b| b.book.title.as_str()).collect();
+    assert_eq!(titles, ["camus", "Étranger", "Zola"]);
+}
+
+#[test]
+fn progress_is_saved_and_can_be_reverted() {
+    let mut conn = library();
+    let dune = add_book(&conn, "Dune");
+
+    books::update_progress(&conn, "Dune", "epubcfi(/6/2!/4/2/1:0)", 5.0).unwrap();
+    let update = books::update_progress(&conn, "Dune", "epubcfi(/6/20!/4/2/1:0)", 99.5)
+        .unwrap()
+        .expect("book exists");
+    assert!(update.finished_at.is_some());
+    let jump = update.jump.expect("moved more than ten points");
+    assert_eq!(jump.from_percentage, 5.0);
+    assert_eq!(books::progress_history(&conn, dune).unwrap().len(), 2);
+
+    let reverted = books::revert_progress(&mut conn, dune).unwrap();
+    assert_eq!(reverted.last_percentage, 5.0);
+    assert_eq!(reverted.last_position, "epubcfi(/6/2!/4/2/1:0)");
+
+    assert!(
+        books::update_progress(&conn, "Missing", "epubcfi(/6/2)", 1.0)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn deleting_a_book_removes_its_annotations() {
+    let mut conn = library();
+    add_book(&conn, "Dune");
+    add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+    bookmarks::add(&conn, "Dune", "epubcfi(/6/4!/4/2/1:0)", "Start").unwrap();
+
+    assert_eq!(books::delete(&mut conn, "Dune").unwrap(), "Dune.epub");
+    assert!(highlights::all(&conn).unwrap().is_empty());
+    assert!(bookmarks::list(&conn, "Dune", BookmarkSort::Newest)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn highlight_notes_keep_their_earlier_versions() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let id = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+
+    highlights::set_notes(&conn, id, "first").unwrap();
+    highlights::set_notes(&conn, id, "second").unwrap();
+    // Unchanged text doesn't add a revision.
+    highlights::set_notes(&conn, id, "second").unwrap();
+
+    let history = highlights::note_history(&conn, id).unwrap();
+    let notes: Vec<&str> = history.iter().map(|r| r.notes.as_str()).collect();
+    assert_eq!(notes, ["first"]);
+    let revision = highlights::note_revision(&conn, history[0].id)
+        .unwrap()
+        .unwrap();
+    let restored = highlights::set_notes(&conn, revision.highlight_id, &revision.notes)
+        .unwrap()
+        .unwrap();
+    assert_eq!(restored.notes, "first");
+
+    assert!(highlights::set_notes(&conn, id + 1, "none")
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn deleting_a_highlight_removes_its_note_history() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let a = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "One");
+    let b = add_highlight(&conn, "Dune", "epubcfi(/6/6!/4/2,/1:0,/1:5)", "Two");
+    for id in [a, b] {
+        highlights::set_notes(&conn, id, "first").unwrap();
+        highlights::set_notes(&conn, id, "second").unwrap();
+    }
+
+    highlights::delete(&conn, a).unwrap();
+    highlights::delete_many(&conn, &[b]).unwrap();
+    let revisions: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note_revisions", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(revisions, 0);
+}
+
+#[test]
+fn deleting_a_highlight_takes_it_out_of_collections() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let id = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+    let collection = collections::create(&conn, "Picks", "").unwrap();
+    collections::add_highlight(&conn, collection.id, id).unwrap();
+
+    highlights::delete(&conn, id).unwrap();
+    let links: i64 = conn
+        .query_row("SELECT COUNT(*) FROM highlight_collections", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(links, 0);
+}
+
+#[test]
+fn highlights_are_ordered_favorites_first_and_by_reading_position() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let late = add_highlight(&conn, "Dune", "epubcfi(/6/8!/4/2,/1:0,/1:5)", "Later");
+    let early = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Earlier");
+    highlights::toggle_favorite(&conn, late).unwrap();
+
+    let ids: Vec<i64> = highlights::for_book(&conn, "Dune")
+        .unwrap()
+        .iter()
+        .map(|h| h.id)
+        .collect();
+    assert_eq!(ids, [late, early]);
+    let ids: Vec<i64> = highlights::in_reading_order(&conn, "Dune")
+        .unwrap()
+        .iter()
+        .map(|h| h.id)
+        .collect();
+    assert_eq!(ids, [early, late]);
+    assert_eq!(highlights::favorites(&conn).unwrap().len(), 1);
+}
+
+#[test]
+fn overlapping_highlights_merge_into_the_oldest() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let first = add_highlight(
+        &conn,
+        "Dune",
+        "epubcfi(/6/4!/4/2,/1:0,/1:20)",
+        "Fear is the mind-killer",
+    );
+    let second = add_highlight(
+        &conn,
+        "Dune",
+        "epubcfi(/6/4!/4/2,/1:12,/1:40)",
+        "mind-killer. Fear is the little-death",
+    );
+    add_highlight(&conn, "Dune", "epubcfi(/6/8!/4/2,/1:0,/1:5)", "Elsewhere");
+    let collection = collections::create(&conn, "Litany", "🛡").unwrap();
+    collections::add_highlight(&conn, collection.id, second).unwrap();
+
+    let groups = highlights::overlapping(&conn, "Dune").unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].len(), 2);
+
+    let merged = highlights::merge(&conn, &[second, first]).unwrap();
+    assert_eq!(merged.highlight.id, first);
+    assert_eq!(merged.removed, [second]);
+    assert_eq!(
+        merged.highlight.text,
+        "Fear is the mind-killer. Fear is the little-death"
+    );
+    assert_eq!(merged.highlight.cfi, "epubcfi(/6/4!/4/2,/1:0,/1:40)");
+    let in_collection = collections::highlights(&conn, collection.id).unwrap();
+    assert_eq!(in_collection.len(), 1);
+    assert_eq!(in_collection[0].id, first);
+
+    assert!(highlights::merge(&conn, &[first]).is_err());
+}
+
+#[test]
+fn duplicate_highlights_are_not_added_and_can_be_removed() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let cfi = "epubcfi(/6/4!/4/2,/1:0,/1:5)";
+    let first = add_highlight(&conn, "Dune", cfi, "Spice");
+    assert_eq!(add_highlight(&conn, "Dune", cfi, "Spice"), first);
+
+    // Rows written before the guard, e.g. by an older version.
+    conn.execute(
+        "INSERT INTO highlights (book_title, cfi, text, notes) VALUES ('Dune', ?1, 'Spice', 'Later')",
+        params![cfi],
+    )
+    .unwrap();
+    let deduped = highlights::dedupe(&conn).unwrap();
+    assert_eq!(deduped.removed.len(), 1);
+    assert_eq!(deduped.kept[0].id, first);
+    assert_eq!(deduped.kept[0].notes, "Later");
+    assert_eq!(highlights::for_book(&conn, "Dune").unwrap().len(), 1);
+    let tombstones: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sync_tombstones", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tombstones, 0);
+}
+
+#[test]
+fn deduping_moves_attachments_to_the_kept_highlight() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let cfi = "epubcfi(/6/4!/4/2,/1:0,/1:5)";
+    let first = add_highlight(&conn, "Dune", cfi, "Spice");
+    conn.execute(
+        "INSERT INTO highlights (book_title, cfi, text) VALUES ('Dune', ?1, 'Spice')",
+        params![cfi],
+    )
+    .unwrap();
+    let duplicate = conn.last_insert_rowid();
+    conn.execute(
+        "INSERT INTO attachments (highlight_id, kind, path, mime)
+         VALUES (?1, 'audio', 'attachments/1.webm', 'audio/webm')",
+        params![duplicate],
+    )
+    .unwrap();
+
+    assert_eq!(highlights::dedupe(&conn).unwrap().removed, vec![duplicate]);
+    let owner: i64 = conn
+        .query_row("SELECT highlight_id FROM attachments", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(owner, first);
+}
+
+#[test]
+fn deduping_moves_links_to_the_kept_highlight() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let cfi = "epubcfi(/6/4!/4/2,/1:0,/1:5)";
+    let first = add_highlight(&conn, "Dune", cfi, "Spice");
+    let other = add_highlight(&conn, "Dune", "epubcfi(/6/6!/4/2,/1:0,/1:5)", "Sand");
+    conn.execute(
+        "INSERT INTO highlights (book_title, cfi, text) VALUES ('Dune', ?1, 'Spice')",
+        params![cfi],
+    )
+    .unwrap();
+    let duplicate = conn.last_insert_rowid();
+    highlight_links::link(&conn, duplicate, other, Some("supports")).unwrap();
+    highlight_links::link(&conn, duplicate, first, None).unwrap();
+
+    highlights::dedupe(&conn).unwrap();
+    let linked = highlight_links::linked(&conn, first).unwrap();
+    assert_eq!(linked.len(), 1);
+    assert_eq!(linked[0].highlight.id, other);
+    let dangling: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM highlight_links WHERE from_id = ?1 OR to_id = ?1",
+            params![duplicate],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(dangling, 0);
+}
+
+#[test]
+fn highlight_styles_are_stored_and_changed() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let id = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+    assert_eq!(
+        highlights::get(&conn, id).unwrap().style,
+        HighlightStyle::Highlight
+    );
+
+    let updated = highlights::update(&conn, id, None, Some(HighlightStyle::Squiggly))
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.style, HighlightStyle::Squiggly);
+    assert_eq!(updated.color, "#facc15");
+    assert!(highlights::update(&conn, id + 1, Some("#000"), None)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn regions_of_a_page_are_annotated_separately() {
+    let conn = library();
+    add_book(&conn, "Atlas");
+    let region = |x: f64| NewRegionAnnotation {
+        book_title: "Atlas".to_string(),
+        cfi: "epubcfi(/6/8!/4)".to_string(),
+        annotation_type: AnnotationType::Region,
+        geometry: AnnotationGeometry {
+            page: Some(4),
+            src: None,
+            x,
+            y: 0.1,
+            width: 0.5,
+            height: 0.2,
+        },
+        label: String::new(),
+        color: "#facc15".to_string(),
+        notes: String::new(),
+    };
+    let first = highlights::add_region(&conn, &region(0.0)).unwrap();
+    highlights::add_region(&conn, &region(0.5)).unwrap();
+    assert!(highlights::add_region(&conn, &region(0.75)).is_err());
+    assert_eq!(first.annotation_type, AnnotationType::Region);
+    assert_eq!(first.geometry.unwrap().page, Some(4));
+
+    assert!(highlights::dedupe(&conn).unwrap().removed.is_empty());
+    assert_eq!(highlights::for_book(&conn, "Atlas").unwrap().len(), 2);
+}
+
+#[test]
+fn bulk_actions_skip_missing_highlights() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let a = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "One");
+    let b = add_highlight(&conn, "Dune", "epubcfi(/6/6!/4/2,/1:0,/1:5)", "Two");
+    let collection = collections::create(&conn, "Picks", "").unwrap();
+
+    let recolored = highlights::recolor_many(&conn, &[a, b, 999], "#60a5fa").unwrap();
+    assert_eq!(recolored.len(), 2);
+    assert_eq!(highlights::by_color(&conn, "blue", None).unwrap().len(), 2);
+
+    assert_eq!(
+        collections::add_highlights(&conn, collection.id, &[a, 999]).unwrap(),
+        [a]
+    );
+    // Already in the collection.
+    assert!(collections::add_highlights(&conn, collection.id, &[a])
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(highlights::delete_many(&conn, &[a, 999]).unwrap(), [a]);
+    assert!(collections::highlights(&conn, collection.id)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn palette_colors_are_validated_and_reordered() {
+    let mut conn = library();
+    assert!(highlights::add_color(&conn, "Orange", "orange").is_err());
+    highlights::add_color(&conn, "Orange", "#FB923C").unwrap();
+
+    let colors = highlights::colors(&conn).unwrap();
+    let orange = colors.last().unwrap();
+    assert_eq!(orange.hex, "#fb923c");
+
+    let mut ids: Vec<i64> = colors.iter().map(|c| c.id).collect();
+    ids.reverse();
+    highlights::reorder_colors(&mut conn, &ids).unwrap();
+    assert_eq!(highlights::colors(&conn).unwrap()[0].name, "Orange");
+}
+
+#[test]
+fn bookmarks_sort_by_position_or_age() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    let late = bookmarks::add(&conn, "Dune", "epubcfi(/6/8!/4/2/1:0)", "Late").unwrap();
+    let early = bookmarks::add(&conn, "Dune", "epubcfi(/6/4!/4/2/1:0)", "Early").unwrap();
+
+    let ids = |sort| -> Vec<i64> {
+        bookmarks::list(&conn, "Dune", sort)
+            .unwrap()
+            .iter()
+            .map(|b| b.id)
+            .collect()
+    };
+    assert_eq!(ids(BookmarkSort::Position), [early.id, late.id]);
+    assert_eq!(ids(BookmarkSort::Newest), [early.id, late.id]);
+    assert_eq!(ids(BookmarkSort::Oldest), [late.id, early.id]);
+
+    assert_eq!(
+        bookmarks::rename(&conn, late.id, "Renamed").unwrap().label,
+        "Renamed"
+    );
+    bookmarks::delete(&conn, late.id).unwrap();
+    assert_eq!(ids(BookmarkSort::Position), [early.id]);
+}
+
+#[test]
+fn collections_hold_highlights_and_books() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    let id = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Spice");
+    let collection = collections::create(&conn, "Favorites", "⭐").unwrap();
+    collections::add_highlight(&conn, collection.id, id).unwrap();
+    conn.execute(
+        "INSERT INTO book_collections (book_id, collection_id) VALUES (?1, ?2)",
+        params![dune, collection.id],
+    )
+    .unwrap();
+
+    assert_eq!(collections::for_highlight(&conn, id).unwrap().len(), 1);
+    assert_eq!(
+        collections::books(&conn, collection.id).unwrap()[0].id,
+        dune
+    );
+
+    collections::remove_highlight(&conn, collection.id, id).unwrap();
+    assert!(collections::highlights(&conn, collection.id)
+        .unwrap()
+        .is_empty());
+
+    collections::delete(&conn, collection.id).unwrap();
+    assert!(collections::all(&conn).unwrap().is_empty());
+    assert!(collections::books(&conn, collection.id).unwrap().is_empty());
+}
+
+#[test]
+fn book_notes_can_be_edited_and_deleted() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    let note = book_notes::add(&conn, dune, "Reread the appendix").unwrap();
+    book_notes::add(&conn, dune, "Compare with Children of Dune").unwrap();
+
+    let edited = book_notes::update(&conn, note.id, "Reread the appendices").unwrap();
+    assert_eq!(edited.content, "Reread the appendices");
+    assert_eq!(book_notes::list(&conn, dune).unwrap().len(), 2);
+
+    book_notes::delete(&conn, note.id).unwrap();
+    assert_eq!(book_notes::list(&conn, dune).unwrap().len(), 1);
+}
+
+#[test]
+fn unsubscribing_removes_the_feeds_entries() {
+    let mut conn = library();
+    let feed = feeds::subscribe(&conn, "https://example.com/feed.xml", "Blog", None).unwrap();
+    conn.execute(
+        "INSERT INTO feed_entries (feed_id, guid, title) VALUES (?1, 'a', 'A')",
+        params![feed.id],
+    )
+    .unwrap();
+
+    feeds::delete(&mut conn, feed.id).unwrap();
+    let entries: i64 = conn
+        .query_row("SELECT COUNT(*) FROM feed_entries", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(entries, 0);
+    assert!(feeds::all(&conn).unwrap().is_empty());
+}
+
+#[test]
+fn drawings_keep_their_strokes_per_page() {
+    let conn = library();
+    let dune = add_book(&conn, "Dune");
+    let stroke = |x: f64| Stroke {
+        color: "#1d4ed8".to_string(),
+        width: 0.004,
+        points: vec![[x, 0.1, 0.5], [x, 0.3, 0.7]],
+    };
+    let page = "epubcfi(/6/4!/4)";
+    let drawing = drawings::add(&conn, dune, page, &[stroke(0.05)]).unwrap();
+    drawings::add(&conn, dune, "epubcfi(/6/6!/4)", &[stroke(0.9)]).unwrap();
+
+    let updated = drawings::update(&conn, drawing.id, &[stroke(0.05), stroke(0.95)]).unwrap();
+    assert_eq!(updated.strokes.len(), 2);
+    assert_eq!(updated.strokes[1].points[0], [0.95, 0.1, 0.5]);
+    assert_eq!(drawings::list(&conn, dune, Some(page)).unwrap().len(), 1);
+    assert_eq!(drawings::list(&conn, dune, None).unwrap().len(), 2);
+
+    drawings::delete(&conn, drawing.id).unwrap();
+    assert_eq!(drawings::list(&conn, dune, Some(page)).unwrap().len(), 0);
+}
+
+#[test]
+fn highlights_link_across_books() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    add_book(&conn, "Walden");
+    let a = add_highlight(&conn, "Dune", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Fear");
+    let b = add_highlight(&conn, "Walden", "epubcfi(/6/4!/4/2,/1:0,/1:5)", "Simplify");
+    let c = add_highlight(&conn, "Walden", "epubcfi(/6/6!/4/2,/1:0,/1:5)", "Woods");
+
+    assert!(highlight_links::link(&conn, a, a, None).is_err());
+    assert!(highlight_links::link(&conn, a, 999, None).is_err());
+    highlight_links::link(&conn, a, b, Some("supports")).unwrap();
+    let relinked = highlight_links::link(&conn, a, b, Some(" contradicts ")).unwrap();
+    assert_eq!(relinked.relation.as_deref(), Some("contradicts"));
+    highlight_links::link(&conn, c, a, None).unwrap();
+
+    let linked = highlight_links::linked(&conn, a).unwrap();
+    assert_eq!(linked.len(), 2);
+    let to_b = linked.iter().find(|l| l.highlight.id == b).unwrap();
+    assert!(to_b.outgoing);
+    assert!(
+        !linked
+            .iter()
+            .find(|l| l.highlight.id == c)
+            .unwrap()
+            .outgoing
+    );
+    assert_eq!(
+        highlight_links::linked(&conn, b).unwrap()[0].highlight.id,
+        a
+    );
+
+    highlights::delete(&conn, c).unwrap();
+    assert_eq!(highlight_links::linked(&conn, a).unwrap().len(), 1);
+    assert!(highlight_links::unlink(&conn, to_b.link.id).unwrap());
+    assert!(highlight_links::linked(&conn, b).unwrap().is_empty());
+}
+
+#[test]
+fn daily_review_is_stable_through_the_day() {
+    let conn = library();
+    add_book(&conn, "Dune");
+    for i in 0..6 {
+        add_highlight(
+            &conn,
+            "Dune",
+            &format!("epubcfi(/6/{}!/4/2,/1:0,/1:5)", i * 2 + 4),
+            "Old",
+        );
+    }
+    let fresh = add_highlight(&conn, "Dune", "epubcfi(/6/20!/4/2,/1:0,/1:5)", "New");
+    conn.execute(
+        "UPDATE highlights SET created_at = datetime('now', '-30 days') WHERE id != ?1",
+        params![fresh],
+    )
+    .unwrap();
+
+    let ids = |review: Vec<Highlight>| -> Vec<i64> { review.into_iter().map(|h| h.id).collect() };
+    let first = ids(review::daily(&conn, 3).unwrap());
+    assert_eq!(first.len(), 3);
+    assert!(!first.contains(&fresh));
+    assert_eq!(ids(review::daily(&conn, 3).unwrap()), first);
+    assert_eq!(ids(review::daily(&conn, 2).unwrap()).len(), 2);
+
+    let more = ids(review::daily(&conn, 10).unwrap());
+    assert_eq!(more.len(), 6);
+    assert!(first.iter().all(|id| more.contains(id)));
+}